@@ -0,0 +1,81 @@
+use std::ffi::CString;
+use std::io::Cursor;
+use std::path::Path;
+use std::time::Instant;
+
+use anyhow::{bail, Error};
+
+extern crate proxmox_backup;
+
+use proxmox_backup::backup::*;
+use proxmox_backup::pxar::catalog::BackupCatalogWriter;
+
+// Compare linear directory scans with lookup table (binary search tree)
+// lookups in a catalog with one large directory.
+//
+// # cargo run --release --example catalog_lookup_bench [entry-count]
+
+const LOOKUPS: usize = 100;
+
+fn main() {
+    if let Err(err) = run() {
+        eprintln!("ERROR: {}", err);
+        std::process::exit(1);
+    }
+}
+
+fn run() -> Result<(), Error> {
+
+    let count: usize = match std::env::args().nth(1) {
+        Some(count) => count.parse()?,
+        None => 1_000_000,
+    };
+
+    let mut data = Vec::new();
+
+    let start_time = Instant::now();
+    let mut writer = CatalogWriter::with_lookup_table(&mut data)?;
+    writer.start_directory(&CString::new("data")?)?;
+    for i in 0..count {
+        writer.add_file(&CString::new(format!("file-{:08}.dat", i))?, i as u64, 0)?;
+    }
+    writer.end_directory()?;
+    writer.finish()?;
+    drop(writer);
+    println!("created catalog with {} entries in {} ms", count, start_time.elapsed().as_millis());
+
+    let mut reader = CatalogReader::new(Cursor::new(data));
+    let names: Vec<String> = (0..LOOKUPS)
+        .map(|i| format!("file-{:08}.dat", (i * 7919) % count))
+        .collect();
+
+    let dir = reader.lookup_path(Path::new("/data"))?.unwrap();
+
+    let start_time = Instant::now();
+    for name in names.iter() {
+        let found = reader.read_dir(&dir)?
+            .into_iter()
+            .any(|entry| entry.name == name.as_bytes());
+        if !found {
+            bail!("linear lookup failed for {}", name);
+        }
+    }
+    let linear_time = start_time.elapsed();
+
+    let start_time = Instant::now();
+    for name in names.iter() {
+        if reader.lookup_path(&Path::new("/data").join(name))?.is_none() {
+            bail!("lookup table lookup failed for {}", name);
+        }
+    }
+    let lookup_time = start_time.elapsed();
+
+    println!(
+        "linear: {} us/lookup, lookup table: {} us/lookup, speedup {:.0}x",
+        linear_time.as_micros() / LOOKUPS as u128,
+        lookup_time.as_micros() / LOOKUPS as u128,
+        linear_time.as_secs_f64() / lookup_time.as_secs_f64(),
+    );
+
+    Ok(())
+}
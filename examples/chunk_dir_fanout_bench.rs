@@ -0,0 +1,91 @@
+use std::path::PathBuf;
+use std::time::Instant;
+
+use anyhow::{bail, Error};
+
+extern crate proxmox_backup;
+
+use proxmox_backup::backup::*;
+
+// Compare chunk lookup latency for different chunk directory fan-outs.
+//
+// Creates one chunk store per fan-out below the given (absolute) directory,
+// so run it on the file system you want to test:
+//
+// # cargo run --release --example chunk_dir_fanout_bench /mnt/test 200000
+//
+// Drop the page cache between runs (echo 3 > /proc/sys/vm/drop_caches) to
+// measure cold lookups.
+
+const FAN_OUTS: [&str; 4] = ["16", "12", "8:8", "4:4:8"];
+
+fn main() {
+    if let Err(err) = run() {
+        eprintln!("ERROR: {}", err);
+        std::process::exit(1);
+    }
+}
+
+fn run() -> Result<(), Error> {
+
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() < 2 {
+        bail!("usage: {} <directory> [chunk-count]", args[0]);
+    }
+
+    let base = PathBuf::from(&args[1]);
+    let count: usize = match args.get(2) {
+        Some(count) => count.parse()?,
+        None => 100_000,
+    };
+
+    let user = nix::unistd::User::from_uid(nix::unistd::Uid::current())?.unwrap();
+
+    for fan_out in FAN_OUTS.iter() {
+        let fan_out: ChunkDirFanOut = fan_out.parse()?;
+
+        let mut path = base.clone();
+        path.push(format!("fanout-{}", fan_out.to_string().replace(':', "-")));
+        let _ = std::fs::remove_dir_all(&path);
+
+        let start_time = Instant::now();
+        let chunk_store = ChunkStore::create("bench", &path, user.uid, user.gid, fan_out, None)?;
+        let create_time = start_time.elapsed();
+
+        let mut digests = Vec::with_capacity(count);
+        let start_time = Instant::now();
+        for i in 0..count {
+            let data = (i as u64).to_le_bytes();
+            let (chunk, digest) = DataChunkBuilder::new(&data).compress(false).build()?;
+            chunk_store.insert_chunk(&chunk, &digest)?;
+            digests.push(digest);
+        }
+        let insert_time = start_time.elapsed();
+
+        let start_time = Instant::now();
+        for digest in digests.iter() {
+            chunk_store.cond_touch_chunk(digest, true)?;
+        }
+        let lookup_time = start_time.elapsed();
+
+        let start_time = Instant::now();
+        for i in 0..count {
+            let digest = openssl::sha::sha256(&(i as u64).to_be_bytes());
+            chunk_store.cond_touch_chunk(&digest, false)?;
+        }
+        let missing_time = start_time.elapsed();
+
+        println!(
+            "fan-out {:8} create {:6} ms, insert {:6} us/chunk, lookup {:6} us/chunk, missing {:6} us/chunk",
+            fan_out.to_string(),
+            create_time.as_millis(),
+            insert_time.as_micros() / count as u128,
+            lookup_time.as_micros() / count as u128,
+            missing_time.as_micros() / count as u128,
+        );
+
+        std::fs::remove_dir_all(&path)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,97 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Error};
+
+use proxmox_backup::backup::{AsyncReadChunk, DataBlob};
+use proxmox_backup::client::PrefetchingChunkReader;
+
+// Compare sequential chunk reads with and without prefetching, using a
+// simulated download latency (default 50ms per chunk):
+//
+// # cargo run --release --example prefetch_chunk_bench [chunk-count] [latency-ms]
+
+#[derive(Clone)]
+struct DelayedChunkReader {
+    latency: Duration,
+    data: Vec<u8>,
+}
+
+impl AsyncReadChunk for DelayedChunkReader {
+    fn read_raw_chunk<'a>(
+        &'a self,
+        _digest: &'a [u8; 32],
+    ) -> Pin<Box<dyn Future<Output = Result<DataBlob, Error>> + Send + 'a>> {
+        Box::pin(async move {
+            tokio::time::sleep(self.latency).await;
+            DataBlob::encode(&self.data, None, false)
+        })
+    }
+
+    fn read_chunk<'a>(
+        &'a self,
+        digest: &'a [u8; 32],
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<u8>, Error>> + Send + 'a>> {
+        Box::pin(async move { self.read_raw_chunk(digest).await?.decode(None, None) })
+    }
+}
+
+async fn run() -> Result<(), Error> {
+    let args: Vec<String> = std::env::args().collect();
+
+    let count: usize = match args.get(1) {
+        Some(count) => count.parse()?,
+        None => 200,
+    };
+    let latency: u64 = match args.get(2) {
+        Some(latency) => latency.parse()?,
+        None => 50,
+    };
+    if count == 0 {
+        bail!("chunk count must be greater than 0");
+    }
+
+    let reader = DelayedChunkReader {
+        latency: Duration::from_millis(latency),
+        data: vec![0u8; 64 * 1024],
+    };
+
+    let digests: Vec<[u8; 32]> = (0..count)
+        .map(|i| {
+            let mut digest = [0u8; 32];
+            digest[..8].copy_from_slice(&(i as u64).to_le_bytes());
+            digest
+        })
+        .collect();
+
+    for depth in [1, 2, 4, 8, 16].iter() {
+        let mut prefetcher = PrefetchingChunkReader::new(reader.clone(), digests.clone())
+            .prefetch_depth(*depth);
+
+        let start_time = Instant::now();
+        let mut bytes = 0;
+        for digest in digests.iter() {
+            bytes += prefetcher.read_raw_chunk(digest).await?.raw_size();
+        }
+        let elapsed = start_time.elapsed().as_secs_f64();
+
+        println!(
+            "prefetch depth {:2}: {} chunks in {:.2}s ({:.1} chunks/s, {:.2} MB/s)",
+            depth,
+            count,
+            elapsed,
+            count as f64 / elapsed,
+            (bytes as f64) / (elapsed * 1024.0 * 1024.0),
+        );
+    }
+
+    Ok(())
+}
+
+fn main() {
+    if let Err(err) = proxmox_backup::tools::runtime::main(run()) {
+        eprintln!("ERROR: {}", err);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,99 @@
+use std::path::PathBuf;
+use std::time::Instant;
+
+use anyhow::{bail, Error};
+
+extern crate proxmox_backup;
+
+use proxmox_backup::tools::fs::{scan_subdir, scan_subdir_typed};
+
+// Compare filtering directory entries by type via fstatat() with using
+// the d_type provided by the file system (scan_subdir_typed).
+//
+// Creates a test directory with a mix of sub directories and files below
+// the given (absolute) directory, so run it on the file system you want
+// to test (e.g. ext4):
+//
+// # cargo run --release --example scan_subdir_bench /mnt/test 10000
+//
+// Use 'strace -c' to compare the number of system calls.
+
+const ROUNDS: usize = 10;
+
+fn main() {
+    if let Err(err) = run() {
+        eprintln!("ERROR: {}", err);
+        std::process::exit(1);
+    }
+}
+
+fn run() -> Result<(), Error> {
+
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() < 2 {
+        bail!("usage: {} <directory> [entry-count]", args[0]);
+    }
+
+    let mut path = PathBuf::from(&args[1]);
+    path.push("scan-subdir-bench");
+    let count: usize = match args.get(2) {
+        Some(count) => count.parse()?,
+        None => 10_000,
+    };
+
+    let _ = std::fs::remove_dir_all(&path);
+    std::fs::create_dir(&path)?;
+    for i in 0..count {
+        let name = path.join(format!("{:08}", i));
+        if i % 2 == 0 {
+            std::fs::create_dir(name)?;
+        } else {
+            std::fs::write(name, b"")?;
+        }
+    }
+
+    let regex = regex::Regex::new(r"^[0-9]{8}$")?;
+
+    let start_time = Instant::now();
+    let mut found = 0;
+    for _ in 0..ROUNDS {
+        for entry in scan_subdir(libc::AT_FDCWD, &path, &regex)? {
+            let entry = entry?;
+            let stat = nix::sys::stat::fstatat(
+                entry.parent_fd(),
+                entry.file_name(),
+                nix::fcntl::AtFlags::AT_SYMLINK_NOFOLLOW,
+            )?;
+            if (stat.st_mode & libc::S_IFMT) == libc::S_IFDIR {
+                found += 1;
+            }
+        }
+    }
+    let stat_time = start_time.elapsed();
+
+    let start_time = Instant::now();
+    let mut found_typed = 0;
+    for _ in 0..ROUNDS {
+        for entry in scan_subdir_typed(libc::AT_FDCWD, &path, &regex, nix::dir::Type::Directory)? {
+            entry?;
+            found_typed += 1;
+        }
+    }
+    let typed_time = start_time.elapsed();
+
+    if found != found_typed {
+        bail!("result mismatch ({} != {})", found, found_typed);
+    }
+
+    println!(
+        "{} entries: fstatat {} us/scan, d_type {} us/scan, speedup {:.1}x",
+        count,
+        stat_time.as_micros() / ROUNDS as u128,
+        typed_time.as_micros() / ROUNDS as u128,
+        stat_time.as_secs_f64() / typed_time.as_secs_f64(),
+    );
+
+    std::fs::remove_dir_all(&path)?;
+
+    Ok(())
+}
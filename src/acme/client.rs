@@ -20,7 +20,7 @@ use proxmox_http::client::SimpleHttp;
 
 use crate::api2::types::AcmeAccountName;
 use crate::config::acme::account_path;
-use crate::tools::pbs_simple_http;
+use crate::tools::{pbs_simple_http, proxy_config_from_system};
 
 /// Our on-disk format inherited from PVE's proxmox-acme code.
 #[derive(Deserialize, Serialize)]
@@ -65,6 +65,16 @@ pub struct AcmeClient {
 impl AcmeClient {
     /// Create a new ACME client for a given ACME directory URL.
     pub fn new(directory_url: String) -> Self {
+        let host = directory_url
+            .parse::<hyper::Uri>()
+            .ok()
+            .and_then(|uri| uri.host().map(str::to_string))
+            .unwrap_or_default();
+        let proxy_config = proxy_config_from_system(&host).unwrap_or_else(|err| {
+            log::warn!("ignoring proxy environment - {}", err);
+            None
+        });
+
         Self {
             directory_url,
             debug: false,
@@ -73,7 +83,7 @@ impl AcmeClient {
             account: None,
             directory: None,
             nonce: None,
-            http_client: pbs_simple_http(None),
+            http_client: pbs_simple_http(proxy_config),
         }
     }
 
@@ -3,9 +3,11 @@
 pub mod access;
 pub mod admin;
 pub mod backup;
+pub mod batch;
 pub mod config;
 pub mod node;
 pub mod reader;
+pub mod schema;
 pub mod status;
 pub mod types;
 pub mod version;
@@ -24,11 +26,13 @@ const SUBDIRS: SubdirMap = &[
     ("access", &access::ROUTER),
     ("admin", &admin::ROUTER),
     ("backup", &backup::ROUTER),
+    ("batch", &batch::ROUTER),
     ("config", &config::ROUTER),
     ("nodes", &NODES_ROUTER),
     ("ping", &ping::ROUTER),
     ("pull", &pull::ROUTER),
     ("reader", &reader::ROUTER),
+    ("schema", &schema::ROUTER),
     ("status", &status::ROUTER),
     ("tape", &tape::ROUTER),
     ("version", &version::ROUTER),
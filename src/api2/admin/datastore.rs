@@ -3,6 +3,7 @@
 use std::collections::HashSet;
 use std::ffi::OsStr;
 use std::os::unix::ffi::OsStrExt;
+use std::sync::Arc;
 
 use anyhow::{bail, format_err, Error};
 use futures::*;
@@ -29,12 +30,15 @@ use crate::api2::helpers;
 use crate::backup::*;
 use crate::config::datastore;
 use crate::config::cached_user_info::CachedUserInfo;
+use crate::client::pull::repair_from_remote;
 use crate::pxar::create_zip;
+use crate::{task_log, task_warn};
 
 use crate::server::{jobstate::Job, WorkerTask};
 use crate::tools::{
     self,
     AsyncChannelWriter, AsyncReaderStream, WrappedReaderStream,
+    format::HumanByte,
 };
 
 use crate::config::acl::{
@@ -44,6 +48,7 @@ use crate::config::acl::{
     PRIV_DATASTORE_PRUNE,
     PRIV_DATASTORE_BACKUP,
     PRIV_DATASTORE_VERIFY,
+    PRIV_REMOTE_READ,
 };
 
 fn check_priv_or_backup_owner(
@@ -362,6 +367,89 @@ pub fn delete_snapshot(
     Ok(Value::Null)
 }
 
+#[api(
+    input: {
+        properties: {
+            store: {
+                schema: DATASTORE_SCHEMA,
+            },
+            "backup-type": {
+                schema: BACKUP_TYPE_SCHEMA,
+            },
+            "backup-id": {
+                schema: BACKUP_ID_SCHEMA,
+            },
+            "backup-time": {
+                schema: BACKUP_TIME_SCHEMA,
+            },
+            "target-backup-type": {
+                description: "Backup type of the clone (default: source backup type).",
+                schema: BACKUP_TYPE_SCHEMA,
+                optional: true,
+            },
+            "target-backup-id": {
+                description: "Backup ID of the clone (default: source backup ID).",
+                schema: BACKUP_ID_SCHEMA,
+                optional: true,
+            },
+            "target-backup-time": {
+                description: "Backup time of the clone (default: now).",
+                schema: BACKUP_TIME_SCHEMA,
+                optional: true,
+            },
+        },
+    },
+    returns: {
+        description: "The cloned snapshot (type/id/time).",
+        type: String,
+    },
+    access: {
+        permission: &Permission::Privilege(
+            &["datastore", "{store}"],
+            PRIV_DATASTORE_MODIFY | PRIV_DATASTORE_BACKUP,
+            true),
+        description: "Requires Datastore.Modify, or Datastore.Backup and ownership of the source and target group.",
+    },
+)]
+/// Clone a backup snapshot.
+///
+/// Index and blob files are hard-linked, so this does not copy any data.
+pub fn clone_snapshot(
+    store: String,
+    backup_type: String,
+    backup_id: String,
+    backup_time: i64,
+    target_backup_type: Option<String>,
+    target_backup_id: Option<String>,
+    target_backup_time: Option<i64>,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<String, Error> {
+
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+
+    let datastore = DataStore::lookup_datastore(&store)?;
+
+    let target_group = BackupGroup::new(
+        target_backup_type.unwrap_or_else(|| backup_type.clone()),
+        target_backup_id.unwrap_or_else(|| backup_id.clone()),
+    );
+    let target_time = match target_backup_time {
+        Some(time) => time,
+        None => proxmox::tools::time::epoch_i64(),
+    };
+
+    let snapshot = BackupDir::new(backup_type, backup_id, backup_time)?;
+
+    check_priv_or_backup_owner(&datastore, snapshot.group(), &auth_id, PRIV_DATASTORE_MODIFY)?;
+    if datastore.group_path(&target_group).exists() {
+        check_priv_or_backup_owner(&datastore, &target_group, &auth_id, PRIV_DATASTORE_MODIFY)?;
+    }
+
+    let clone = datastore.clone_snapshot(&snapshot, &target_group, target_time)?;
+
+    Ok(clone.to_string())
+}
+
 #[api(
     input: {
         properties: {
@@ -629,6 +717,7 @@ pub fn status(
         avail: storage.avail,
         gc_status,
         counts,
+        insert_verify_mismatches: datastore.insert_verify_mismatches(),
     })
 }
 
@@ -650,6 +739,15 @@ pub fn status(
                 schema: BACKUP_TIME_SCHEMA,
                 optional: true,
             },
+            "repair-remote": {
+                schema: REMOTE_ID_SCHEMA,
+                optional: true,
+            },
+            "repair-remote-store": {
+                description: "Datastore on the repair remote. Defaults to the local datastore name.",
+                schema: DATASTORE_SCHEMA,
+                optional: true,
+            },
         },
     },
     returns: {
@@ -657,22 +755,43 @@ pub fn status(
     },
     access: {
         permission: &Permission::Privilege(&["datastore", "{store}"], PRIV_DATASTORE_VERIFY | PRIV_DATASTORE_BACKUP, true),
+        description: "Repairing from a remote additionally requires Datastore.Modify on the datastore and Remote.Read on the remote datastore.",
     },
 )]
 /// Verify backups.
 ///
 /// This function can verify a single backup snapshot, all backup from a backup group,
-/// or all backups in the datastore.
+/// or all backups in the datastore. If a repair remote is given, corrupt chunks of
+/// failed snapshots are re-downloaded from the same snapshot on that remote.
 pub fn verify(
     store: String,
     backup_type: Option<String>,
     backup_id: Option<String>,
     backup_time: Option<i64>,
+    repair_remote: Option<String>,
+    repair_remote_store: Option<String>,
     rpcenv: &mut dyn RpcEnvironment,
 ) -> Result<Value, Error> {
     let datastore = DataStore::lookup_datastore(&store)?;
 
     let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+
+    let repair_source = match repair_remote {
+        Some(remote) => {
+            let remote_store = repair_remote_store.unwrap_or_else(|| store.clone());
+            let user_info = CachedUserInfo::new()?;
+            // replaces chunks referenced by any snapshot, not only owned ones
+            user_info.check_privs(&auth_id, &["datastore", &store], PRIV_DATASTORE_MODIFY, false)?;
+            user_info.check_privs(&auth_id, &["remote", &remote, &remote_store], PRIV_REMOTE_READ, false)?;
+            Some((remote, remote_store))
+        }
+        None => {
+            if repair_remote_store.is_some() {
+                bail!("parameter 'repair-remote-store' requires 'repair-remote'");
+            }
+            None
+        }
+    };
     let worker_id;
 
     let mut backup_dir = None;
@@ -712,22 +831,22 @@ pub fn verify(
         auth_id.clone(),
         to_stdout,
         move |worker| {
-            let verify_worker = crate::backup::VerifyWorker::new(worker.clone(), datastore);
-            let failed_dirs = if let Some(backup_dir) = backup_dir {
+            let verify_worker = crate::backup::VerifyWorker::new(worker.clone(), datastore.clone());
+            let mut failed_dirs = if let Some(backup_dir) = &backup_dir {
                 let mut res = Vec::new();
                 if !verify_backup_dir(
                     &verify_worker,
-                    &backup_dir,
+                    backup_dir,
                     worker.upid().clone(),
                     None,
                 )? {
                     res.push(backup_dir.to_string());
                 }
                 res
-            } else if let Some(backup_group) = backup_group {
+            } else if let Some(backup_group) = &backup_group {
                 let failed_dirs = verify_backup_group(
                     &verify_worker,
-                    &backup_group,
+                    backup_group,
                     &mut StoreProgress::new(1),
                     worker.upid(),
                     None,
@@ -745,6 +864,19 @@ pub fn verify(
 
                 verify_all_backups(&verify_worker, worker.upid(), owner, None)?
             };
+
+            if let (Some((remote, remote_store)), false) = (repair_source, failed_dirs.is_empty()) {
+                let corrupt_chunks = verify_worker.corrupt_chunks();
+                failed_dirs = repair_and_reverify(
+                    &worker,
+                    datastore,
+                    &remote,
+                    &remote_store,
+                    failed_dirs,
+                    &corrupt_chunks,
+                )?;
+            }
+
             if !failed_dirs.is_empty() {
                 worker.log("Failed to verify the following snapshots/groups:");
                 for dir in failed_dirs {
@@ -759,6 +891,77 @@ pub fn verify(
     Ok(json!(upid_str))
 }
 
+// Re-download corrupt chunks of the failed snapshots from a remote, then
+// verify those snapshots again. Returns the snapshots still failing.
+fn repair_and_reverify(
+    worker: &Arc<WorkerTask>,
+    datastore: Arc<DataStore>,
+    remote: &str,
+    remote_store: &str,
+    failed_dirs: Vec<String>,
+    corrupt_chunks: &HashSet<[u8; 32]>,
+) -> Result<Vec<String>, Error> {
+    task_log!(worker, "try to repair {} snapshots from remote '{}' ({})", failed_dirs.len(), remote, remote_store);
+
+    let (client, src_repo, _) = tools::runtime::block_on(
+        crate::api2::pull::get_pull_parameters(datastore.name(), remote, remote_store),
+    )?;
+
+    let mut report = RepairReport::default();
+    let mut repaired_dirs = Vec::new();
+    let mut still_failed = Vec::new();
+
+    for dir in failed_dirs {
+        let snapshot: BackupDir = match dir.parse() {
+            Ok(snapshot) => snapshot,
+            Err(_) => {
+                // whole group failed, nothing to repair
+                still_failed.push(dir);
+                continue;
+            }
+        };
+
+        let result = tools::runtime::block_on(repair_from_remote(
+            worker,
+            &client,
+            &src_repo,
+            datastore.clone(),
+            &snapshot,
+            corrupt_chunks,
+        ));
+
+        match result {
+            Ok(snapshot_report) => {
+                task_log!(worker, "repair {}: {}", snapshot, snapshot_report);
+                report.repaired += snapshot_report.repaired;
+                report.still_missing += snapshot_report.still_missing;
+                report.remote_also_missing += snapshot_report.remote_also_missing;
+                if snapshot_report.repaired > 0 {
+                    repaired_dirs.push(snapshot);
+                } else {
+                    still_failed.push(dir);
+                }
+            }
+            Err(err) => {
+                task_warn!(worker, "repair {} failed - {}", snapshot, err);
+                still_failed.push(dir);
+            }
+        }
+    }
+
+    task_log!(worker, "repair finished: {}", report);
+
+    // fresh worker, so chunks marked corrupt before are checked again
+    let verify_worker = crate::backup::VerifyWorker::new(worker.clone(), datastore);
+    for snapshot in repaired_dirs {
+        if !verify_backup_dir(&verify_worker, &snapshot, worker.upid().clone(), None)? {
+            still_failed.push(snapshot.to_string());
+        }
+    }
+
+    Ok(still_failed)
+}
+
 #[macro_export]
 macro_rules! add_common_prune_prameters {
     ( [ $( $list1:tt )* ] ) => {
@@ -818,6 +1021,10 @@ pub const API_METHOD_PRUNE: ApiMethod = ApiMethod::new(
                 "Just show what prune would do, but do not delete anything.")
              .schema()
             ),
+            ("gc", true, &BooleanSchema::new(
+                "Also remove the chunks only used by the removed snapshots (without a full garbage collection).")
+             .schema()
+            ),
         ],[
             ("store", false, &DATASTORE_SCHEMA),
         ])
@@ -843,6 +1050,8 @@ pub fn prune(
 
     let dry_run = param["dry-run"].as_bool().unwrap_or(false);
 
+    let gc = param["gc"].as_bool().unwrap_or(false);
+
     let group = BackupGroup::new(backup_type, backup_id);
 
     let datastore = DataStore::lookup_datastore(&store)?;
@@ -899,6 +1108,41 @@ pub fn prune(
                             store, backup_type, backup_id));
     }
 
+    if gc {
+        let result = datastore.prune_and_collect(&group, &prune_options, &worker);
+        let result = match result {
+            Ok(result) => result,
+            Err(err) => {
+                worker.log_result(&Err(format_err!("{}", err)));
+                return Err(err);
+            }
+        };
+
+        for (backup_dir, keep) in result.prune_info {
+            worker.log(format!(
+                "{} {}",
+                backup_dir.relative_path().display(),
+                if keep { "keep" } else { "remove" },
+            ));
+            prune_result.push(json!({
+                "backup-type": backup_dir.group().backup_type(),
+                "backup-id": backup_dir.group().backup_id(),
+                "backup-time": backup_dir.backup_time(),
+                "keep": keep,
+            }));
+        }
+
+        worker.log(format!(
+            "removed {} snapshots, {} chunks ({})",
+            result.snapshots_deleted,
+            result.chunks_freed,
+            HumanByte::from(result.bytes_freed),
+        ));
+        worker.log_result(&Ok(()));
+
+        return Ok(json!(prune_result));
+    }
+
     for (info, mut keep) in prune_info {
         if keep_all { keep = true; }
 
@@ -977,6 +1221,44 @@ pub fn start_garbage_collection(
     Ok(json!(upid_str))
 }
 
+#[api(
+    input: {
+        properties: {
+            store: {
+                schema: DATASTORE_SCHEMA,
+            },
+        },
+    },
+    returns: {
+        schema: UPID_SCHEMA,
+    },
+    access: {
+        permission: &Permission::Privilege(&["datastore", "{store}"], PRIV_DATASTORE_MODIFY, false),
+    },
+)]
+/// Move all chunks to the configured chunk directory fan-out.
+pub fn migrate_chunk_fanout(
+    store: String,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<Value, Error> {
+
+    // also allows resuming an interrupted migration
+    let datastore = DataStore::lookup_datastore_for_migration(&store)?;
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+
+    let to_stdout = rpcenv.env_type() == RpcEnvironmentType::CLI;
+
+    let upid_str = WorkerTask::new_thread(
+        "migrate-chunk-fanout",
+        Some(store),
+        auth_id,
+        to_stdout,
+        move |worker| datastore.migrate_chunk_fanout(&*worker),
+    )?;
+
+    Ok(json!(upid_str))
+}
+
 #[api(
     input: {
         properties: {
@@ -992,7 +1274,7 @@ pub fn start_garbage_collection(
         permission: &Permission::Privilege(&["datastore", "{store}"], PRIV_DATASTORE_AUDIT, false),
     },
 )]
-/// Garbage collection status.
+/// Garbage collection status (of the running garbage collection, if any).
 pub fn garbage_collection_status(
     store: String,
     _info: &ApiMethod,
@@ -1001,7 +1283,8 @@ pub fn garbage_collection_status(
 
     let datastore = DataStore::lookup_datastore(&store)?;
 
-    let status = datastore.last_gc_status();
+    let status = datastore.gc_progress()
+        .unwrap_or_else(|| datastore.last_gc_status());
 
     Ok(status)
 }
@@ -1536,6 +1819,50 @@ pub fn get_rrd_stats(
     )
 }
 
+#[api(
+    input: {
+        properties: {
+            store: {
+                schema: DATASTORE_SCHEMA,
+            },
+        },
+    },
+    returns: {
+        description: "True if the datastore is in read-only mode.",
+        type: bool,
+    },
+    access: {
+        permission: &Permission::Privilege(&["datastore", "{store}"], PRIV_DATASTORE_MODIFY, false),
+    },
+)]
+/// Get the read-only mode of a datastore
+pub fn get_readonly(store: String) -> Result<bool, Error> {
+    let datastore = DataStore::lookup_datastore(&store)?;
+    Ok(datastore.is_readonly())
+}
+
+#[api(
+    input: {
+        properties: {
+            store: {
+                schema: DATASTORE_SCHEMA,
+            },
+            readonly: {
+                description: "Enable read-only mode (no new backups, prune or garbage collection).",
+                type: bool,
+            },
+        },
+    },
+    access: {
+        permission: &Permission::Privilege(&["datastore", "{store}"], PRIV_DATASTORE_MODIFY, false),
+    },
+)]
+/// Set the read-only mode of a datastore
+pub fn set_readonly(store: String, readonly: bool) -> Result<(), Error> {
+    let datastore = DataStore::lookup_datastore(&store)?;
+    datastore.set_readonly(readonly)
+}
+
 #[api(
     input: {
         properties: {
@@ -1727,6 +2054,55 @@ pub fn set_backup_owner(
     Ok(())
 }
 
+#[api(
+    input: {
+        properties: {
+            store: {
+                schema: DATASTORE_SCHEMA,
+            },
+            recipient: {
+                description: "The wrapped key entry of the key configuration (JSON).",
+            },
+            cert: {
+                description: "Recipient certificate (PEM), must contain a RSA public key.",
+            },
+        },
+    },
+    access: {
+        permission: &Permission::Privilege(&["datastore", "{store}"], PRIV_DATASTORE_MODIFY, false),
+    },
+)]
+/// Check a master key wrapped for an additional recipient.
+///
+/// Keys are only wrapped on the client (see 'proxmox-backup-client key
+/// add-recipient'), so this never gets the key or its password. It checks
+/// that the entry matches the recipient certificate.
+pub fn add_key_recipient(
+    store: String,
+    recipient: String,
+    cert: String,
+) -> Result<(), Error> {
+
+    DataStore::lookup_datastore(&store)?;
+
+    let recipient: EncryptedKeyRecipient = serde_json::from_str(&recipient)
+        .map_err(|err| format_err!("unable to parse wrapped key entry - {}", err))?;
+
+    let cert = openssl::x509::X509::from_pem(cert.as_bytes())
+        .map_err(|err| format_err!("unable to parse recipient certificate - {}", err))?;
+
+    recipient.check_cert(&cert)
+}
+
+#[sortable]
+const DATASTORE_KEY_SUBDIRS: SubdirMap = &[
+    (
+        "add-recipient",
+        &Router::new()
+            .post(&API_METHOD_ADD_KEY_RECIPIENT)
+    ),
+];
+
 #[sortable]
 const DATASTORE_INFO_SUBDIRS: SubdirMap = &[
     (
@@ -1766,6 +2142,17 @@ const DATASTORE_INFO_SUBDIRS: SubdirMap = &[
             .get(&API_METHOD_LIST_GROUPS)
             .delete(&API_METHOD_DELETE_GROUP)
     ),
+    (
+        "key",
+        &Router::new()
+            .get(&list_subdirs_api_method!(DATASTORE_KEY_SUBDIRS))
+            .subdirs(DATASTORE_KEY_SUBDIRS)
+    ),
+    (
+        "migrate-chunk-fanout",
+        &Router::new()
+            .post(&API_METHOD_MIGRATE_CHUNK_FANOUT)
+    ),
     (
         "notes",
         &Router::new()
@@ -1782,6 +2169,12 @@ const DATASTORE_INFO_SUBDIRS: SubdirMap = &[
         &Router::new()
             .download(&API_METHOD_PXAR_FILE_DOWNLOAD)
     ),
+    (
+        "readonly",
+        &Router::new()
+            .get(&API_METHOD_GET_READONLY)
+            .put(&API_METHOD_SET_READONLY)
+    ),
     (
         "rrd",
         &Router::new()
@@ -1792,6 +2185,13 @@ const DATASTORE_INFO_SUBDIRS: SubdirMap = &[
         &Router::new()
             .get(&API_METHOD_LIST_SNAPSHOTS)
             .delete(&API_METHOD_DELETE_SNAPSHOT)
+            .subdirs(&[
+                (
+                    "clone",
+                    &Router::new()
+                        .post(&API_METHOD_CLONE_SNAPSHOT)
+                ),
+            ])
     ),
     (
         "status",
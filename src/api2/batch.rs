@@ -0,0 +1,317 @@
+//! Execute multiple read-only API calls with a single request.
+
+use std::collections::HashMap;
+
+use anyhow::{bail, format_err, Error};
+use futures::FutureExt;
+use lazy_static::lazy_static;
+use serde_json::{json, Value};
+
+use proxmox::api::{
+    check_api_permission, ApiFuture, ApiHandler, ApiMethod, HttpError, Permission, Router,
+    RpcEnvironment, RpcEnvironmentType, UserInformation,
+};
+use proxmox::api::router::ReturnType;
+use proxmox::api::schema::*;
+use proxmox::{http_err, sortable};
+
+use crate::config::cached_user_info::CachedUserInfo;
+use crate::server::RestEnvironment;
+
+/// Maximum number of sub-requests in a single batch request.
+pub const MAX_BATCH_REQUESTS: usize = 50;
+
+/// Maximum number of batch requests executed at the same time.
+const MAX_CONCURRENT_BATCHES: usize = 4;
+
+lazy_static! {
+    static ref BATCH_LIMIT: tokio::sync::Semaphore =
+        tokio::sync::Semaphore::new(MAX_CONCURRENT_BATCHES);
+}
+
+#[sortable]
+const BATCH_REQUEST_SCHEMA: Schema = ObjectSchema::new(
+    "A single read-only API request.",
+    &sorted!([
+        ("method", true, &StringSchema::new("HTTP method, only 'GET' is allowed.")
+            .default("GET")
+            .schema()
+        ),
+        ("params", true, &ObjectSchema::new("Request parameters.", &[])
+            .additional_properties(true)
+            .schema()
+        ),
+        ("path", false, &StringSchema::new("API path, relative to '/api2/json'.")
+            .max_length(1024)
+            .schema()
+        ),
+    ]),
+).schema();
+
+#[sortable]
+const BATCH_RESULT_SCHEMA: Schema = ObjectSchema::new(
+    "Result of a single request. Successful calls contain the returned value as 'data'.",
+    &sorted!([
+        ("error", true, &StringSchema::new("Error message.").schema()),
+        ("status", false, &IntegerSchema::new("HTTP status code.").schema()),
+    ]),
+)
+.additional_properties(true)
+.schema();
+
+#[sortable]
+pub const API_METHOD_BATCH: ApiMethod = ApiMethod::new(
+    &ApiHandler::Async(&batch),
+    &ObjectSchema::new(
+        "Execute multiple read-only API calls. The results are returned in request order.",
+        &sorted!([
+            ("requests", false, &ArraySchema::new("List of requests.", &BATCH_REQUEST_SCHEMA)
+                .min_length(1)
+                .max_length(MAX_BATCH_REQUESTS)
+                .schema()
+            ),
+        ]),
+    ),
+)
+.returns(ReturnType::new(
+    false,
+    &ArraySchema::new("List of results.", &BATCH_RESULT_SCHEMA).schema(),
+))
+.access(
+    Some("Any authenticated user. Permissions are checked for each request separately."),
+    &Permission::Anybody,
+);
+
+fn batch<'a>(
+    param: Value,
+    _info: &ApiMethod,
+    rpcenv: &'a mut dyn RpcEnvironment,
+) -> ApiFuture<'a> {
+    async move {
+        let _permit = BATCH_LIMIT.try_acquire().map_err(|_| {
+            http_err!(TOO_MANY_REQUESTS, "too many concurrent batch requests, try again later")
+        })?;
+
+        let requests = match param["requests"].as_array() {
+            Some(requests) => requests.clone(),
+            None => bail!("missing parameter 'requests'"),
+        };
+
+        if requests.len() > MAX_BATCH_REQUESTS {
+            bail!("too many requests in batch (> {})", MAX_BATCH_REQUESTS);
+        }
+
+        let user_info = CachedUserInfo::new()?;
+
+        let env = BatchEnv {
+            auth_id: rpcenv.get_auth_id(),
+            client_ip: rpcenv.get_client_ip(),
+            env_type: rpcenv.env_type(),
+        };
+
+        let results = run_batch(&super::ROUTER, user_info.as_ref(), &env, requests).await;
+
+        Ok(Value::from(results))
+    }
+    .boxed()
+}
+
+// environment of the batch request, shared by all sub-requests
+struct BatchEnv {
+    auth_id: Option<String>,
+    client_ip: Option<std::net::SocketAddr>,
+    env_type: RpcEnvironmentType,
+}
+
+async fn run_batch(
+    router: &Router,
+    user_info: &dyn UserInformation,
+    env: &BatchEnv,
+    requests: Vec<Value>,
+) -> Vec<Value> {
+    let list = requests
+        .into_iter()
+        .map(|request| run_request(router, user_info, env, request));
+
+    futures::future::join_all(list).await
+}
+
+async fn run_request(
+    router: &Router,
+    user_info: &dyn UserInformation,
+    env: &BatchEnv,
+    request: Value,
+) -> Value {
+    match run_request_do(router, user_info, env, request).await {
+        Ok(data) => json!({ "status": 200, "data": data }),
+        Err(err) => {
+            let status = match err.downcast_ref::<HttpError>() {
+                Some(http_err) => http_err.code.as_u16(),
+                None => 400,
+            };
+            json!({ "status": status, "error": err.to_string() })
+        }
+    }
+}
+
+async fn run_request_do(
+    router: &Router,
+    user_info: &dyn UserInformation,
+    env: &BatchEnv,
+    request: Value,
+) -> Result<Value, Error> {
+    let method = request["method"].as_str().unwrap_or("GET");
+    if !method.eq_ignore_ascii_case("GET") {
+        return Err(http_err!(METHOD_NOT_ALLOWED, "only GET requests are allowed in batch requests"));
+    }
+
+    let path = request["path"]
+        .as_str()
+        .ok_or_else(|| format_err!("missing request path"))?;
+    let path = path.strip_prefix("/api2/json").unwrap_or(path);
+
+    let (_path, components) = crate::tools::normalize_uri_path(path)?;
+
+    let mut uri_param = HashMap::new();
+    let info = match router.find_method(&components, hyper::Method::GET, &mut uri_param) {
+        Some(info) => info,
+        None => return Err(http_err!(NOT_FOUND, "Path '{}' not found.", path)),
+    };
+
+    // protected calls need to be proxied to the privileged daemon
+    if info.protected && env.env_type == RpcEnvironmentType::PUBLIC {
+        bail!("protected API calls are not allowed in batch requests");
+    }
+
+    if !check_api_permission(
+        info.access.permission,
+        env.auth_id.as_deref(),
+        &uri_param,
+        user_info,
+    ) {
+        return Err(http_err!(FORBIDDEN, "permission check failed"));
+    }
+
+    let mut params = match &request["params"] {
+        Value::Null => json!({}),
+        Value::Object(_) => request["params"].clone(),
+        _ => bail!("request parameters must be an object"),
+    };
+
+    for (k, v) in uri_param {
+        if let Some((_optional, prop_schema)) = info.parameters.lookup(&k) {
+            params[&k] = parse_simple_value(&v, prop_schema)?;
+        }
+    }
+    verify_json_object(&params, &info.parameters)?;
+
+    let mut rpcenv = RestEnvironment::new(env.env_type);
+    rpcenv.set_auth_id(env.auth_id.clone());
+    rpcenv.set_client_ip(env.client_ip);
+
+    match info.handler {
+        ApiHandler::Sync(handler) => (handler)(params, info, &mut rpcenv),
+        ApiHandler::Async(handler) => (handler)(params, info, &mut rpcenv).await,
+        ApiHandler::AsyncHttp(_) => bail!("API call not supported in batch requests"),
+    }
+}
+
+pub const ROUTER: Router = Router::new()
+    .post(&API_METHOD_BATCH);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::config::acl::PRIV_SYS_AUDIT;
+
+    fn public_data(
+        _param: Value,
+        _info: &ApiMethod,
+        _rpcenv: &mut dyn RpcEnvironment,
+    ) -> Result<Value, Error> {
+        Ok(json!("public data"))
+    }
+
+    fn secret_data(
+        _param: Value,
+        _info: &ApiMethod,
+        _rpcenv: &mut dyn RpcEnvironment,
+    ) -> Result<Value, Error> {
+        Ok(json!("secret data"))
+    }
+
+    const API_METHOD_PUBLIC: ApiMethod = ApiMethod::new(
+        &ApiHandler::Sync(&public_data),
+        &ObjectSchema::new("Public data.", &[]),
+    )
+    .access(None, &Permission::Anybody);
+
+    const API_METHOD_SECRET: ApiMethod = ApiMethod::new(
+        &ApiHandler::Sync(&secret_data),
+        &ObjectSchema::new("Secret data.", &[]),
+    )
+    .access(None, &Permission::Privilege(&["system"], PRIV_SYS_AUDIT, false));
+
+    const TEST_ROUTER: Router = Router::new().subdirs(&[
+        ("public", &Router::new().get(&API_METHOD_PUBLIC)),
+        ("secret", &Router::new().get(&API_METHOD_SECRET).post(&API_METHOD_SECRET)),
+    ]);
+
+    struct TestUserInfo;
+
+    impl UserInformation for TestUserInfo {
+        fn is_superuser(&self, _userid: &str) -> bool {
+            false
+        }
+
+        fn is_group_member(&self, _userid: &str, _group: &str) -> bool {
+            false
+        }
+
+        fn lookup_privs(&self, userid: &str, path: &[&str]) -> u64 {
+            if userid == "audit@pbs" && path.join("/") == "system" {
+                PRIV_SYS_AUDIT
+            } else {
+                0
+            }
+        }
+    }
+
+    fn run_test_batch(auth_id: &str) -> Vec<Value> {
+        let env = BatchEnv {
+            auth_id: Some(auth_id.to_string()),
+            client_ip: None,
+            env_type: RpcEnvironmentType::PRIVILEGED,
+        };
+
+        let requests = vec![
+            json!({ "path": "/public" }),
+            json!({ "path": "/api2/json/secret" }),
+            json!({ "method": "POST", "path": "/secret" }),
+            json!({ "path": "/nonexistent" }),
+        ];
+
+        futures::executor::block_on(run_batch(&TEST_ROUTER, &TestUserInfo, &env, requests))
+    }
+
+    #[test]
+    fn test_batch_permissions() {
+        let results = run_test_batch("user@pbs");
+        let status: Vec<u64> = results.iter().map(|res| res["status"].as_u64().unwrap()).collect();
+        assert_eq!(status, vec![200, 403, 405, 404]);
+        assert_eq!(results[0]["data"], "public data");
+
+        // failed requests must not leak any data
+        for result in &results[1..] {
+            assert!(result["data"].is_null());
+            assert!(result["error"].is_string());
+        }
+        assert!(!Value::from(results).to_string().contains("secret data"));
+
+        let results = run_test_batch("audit@pbs");
+        let status: Vec<u64> = results.iter().map(|res| res["status"].as_u64().unwrap()).collect();
+        assert_eq!(status, vec![200, 200, 405, 404]);
+        assert_eq!(results[1]["data"], "secret data");
+    }
+}
@@ -12,7 +12,10 @@ use crate::api2::types::*;
 use crate::backup::*;
 use crate::config::cached_user_info::CachedUserInfo;
 use crate::config::datastore::{self, DataStoreConfig, DIR_NAME_SCHEMA};
-use crate::config::acl::{PRIV_DATASTORE_ALLOCATE, PRIV_DATASTORE_AUDIT, PRIV_DATASTORE_MODIFY};
+use crate::config::acl::{
+    PRIV_DATASTORE_ALLOCATE, PRIV_DATASTORE_AUDIT, PRIV_DATASTORE_MODIFY, PRIV_SYS_MODIFY,
+};
+use crate::api2::node::disks::zfs::ZPOOL_NAME_SCHEMA;
 use crate::server::{jobstate, WorkerTask};
 
 #[api(
@@ -58,8 +61,13 @@ pub(crate) fn do_create_datastore(
 ) -> Result<(), Error> {
     let path: PathBuf = datastore.path.clone().into();
 
+    let fan_out = match datastore.chunk_dir_fan_out {
+        Some(ref fan_out) => fan_out.parse()?,
+        None => ChunkDirFanOut::default(),
+    };
+
     let backup_user = crate::backup::backup_user()?;
-    let _store = ChunkStore::create(&datastore.name, path, backup_user.uid, backup_user.gid, worker)?;
+    let _store = ChunkStore::create(&datastore.name, path, backup_user.uid, backup_user.gid, fan_out, worker)?;
 
     config.set_data(&datastore.name, "datastore", &datastore)?;
 
@@ -129,18 +137,40 @@ pub(crate) fn do_create_datastore(
                 optional: true,
                 schema: PRUNE_SCHEMA_KEEP_YEARLY,
             },
+            "chunk-dir-fan-out": {
+                optional: true,
+                schema: CHUNK_DIR_FAN_OUT_SCHEMA,
+            },
+            "zfs-pool": {
+                description: "Create a new ZFS dataset (named like the datastore) on this pool, mounted at 'path'.",
+                optional: true,
+                schema: ZPOOL_NAME_SCHEMA,
+            },
         },
     },
     access: {
+        description: "Creating a ZFS dataset additionally requires Sys.Modify on /system/disks.",
         permission: &Permission::Privilege(&["datastore"], PRIV_DATASTORE_ALLOCATE, false),
     },
 )]
 /// Create new datastore config.
 pub fn create_datastore(
-    param: Value,
+    mut param: Value,
     rpcenv: &mut dyn RpcEnvironment,
 ) -> Result<String, Error> {
 
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+
+    let zfs_pool = param
+        .as_object_mut()
+        .and_then(|param| param.remove("zfs-pool"))
+        .and_then(|pool| pool.as_str().map(String::from));
+
+    if zfs_pool.is_some() {
+        let user_info = CachedUserInfo::new()?;
+        user_info.check_privs(&auth_id, &["system", "disks"], PRIV_SYS_MODIFY, false)?;
+    }
+
     let lock = datastore::lock_config()?;
 
     let datastore: datastore::DataStoreConfig = serde_json::from_value(param)?;
@@ -151,14 +181,34 @@ pub fn create_datastore(
         bail!("datastore '{}' already exists.", datastore.name);
     }
 
-    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
-
     WorkerTask::new_thread(
         "create-datastore",
         Some(datastore.name.to_string()),
         auth_id,
         false,
-        move |worker| do_create_datastore(lock, config, datastore, Some(&worker)),
+        move |worker| {
+            let pool = match zfs_pool {
+                Some(pool) => pool,
+                None => return do_create_datastore(lock, config, datastore, Some(&worker)),
+            };
+
+            let options = ZfsDatasetOptions {
+                mountpoint: Some(datastore.path.clone()),
+                ..Default::default()
+            };
+            let name = datastore.name.clone();
+            worker.log(format!("create ZFS dataset '{}/{}'", pool, name));
+            DataStore::create_on_zfs_pool(&pool, &name, options)?;
+
+            let result = do_create_datastore(lock, config, datastore, Some(&worker));
+            if result.is_err() {
+                worker.log(format!("destroy ZFS dataset '{}/{}'", pool, name));
+                if let Err(err) = DataStore::destroy_zfs_dataset(&pool, &name) {
+                    worker.warn(format!("unable to destroy ZFS dataset '{}/{}' - {}", pool, name, err));
+                }
+            }
+            result
+        },
     )
 }
 
@@ -214,10 +264,14 @@ pub enum DeletableProperty {
     keep_yearly,
     /// Delete the verify-new property
     verify_new,
+    /// Delete the verify-on-insert property
+    verify_on_insert,
     /// Delete the notify-user property
     notify_user,
     /// Delete the notify property
     notify,
+    /// Delete the chunk-dir-fan-out property
+    chunk_dir_fan_out,
 }
 
 #[api(
@@ -277,6 +331,16 @@ pub enum DeletableProperty {
                 optional: true,
                 default: false,
             },
+            "verify-on-insert": {
+                description: "If enabled, newly written chunks are read back and checked.",
+                type: bool,
+                optional: true,
+                default: false,
+            },
+            "chunk-dir-fan-out": {
+                optional: true,
+                schema: CHUNK_DIR_FAN_OUT_SCHEMA,
+            },
             delete: {
                 description: "List of properties to delete.",
                 type: Array,
@@ -309,8 +373,10 @@ pub fn update_datastore(
     keep_monthly: Option<u64>,
     keep_yearly: Option<u64>,
     verify_new: Option<bool>,
+    verify_on_insert: Option<bool>,
     notify: Option<String>,
     notify_user: Option<Userid>,
+    chunk_dir_fan_out: Option<String>,
     delete: Option<Vec<DeletableProperty>>,
     digest: Option<String>,
 ) -> Result<(), Error> {
@@ -340,8 +406,10 @@ pub fn update_datastore(
                 DeletableProperty::keep_monthly => { data.keep_monthly = None; },
                 DeletableProperty::keep_yearly => { data.keep_yearly = None; },
                 DeletableProperty::verify_new => { data.verify_new = None; },
+                DeletableProperty::verify_on_insert => { data.verify_on_insert = None; },
                 DeletableProperty::notify => { data.notify = None; },
                 DeletableProperty::notify_user => { data.notify_user = None; },
+                DeletableProperty::chunk_dir_fan_out => { data.chunk_dir_fan_out = None; },
             }
         }
     }
@@ -384,9 +452,13 @@ pub fn update_datastore(
         }
     }
     if verify_new.is_some() { data.verify_new = verify_new; }
+    if verify_on_insert.is_some() { data.verify_on_insert = verify_on_insert; }
 
     if notify_user.is_some() { data.notify_user = notify_user; }
 
+    // takes effect with the next fan-out migration
+    if chunk_dir_fan_out.is_some() { data.chunk_dir_fan_out = chunk_dir_fan_out; }
+
     config.set_data(&name, "datastore", &data)?;
 
     datastore::save_config(&config)?;
@@ -49,7 +49,7 @@ pub fn list_dir_content<R: Read + Seek>(
         components.push(b'/');
         components.extend(&direntry.name);
         let mut entry = ArchiveEntry::new(&components, Some(&direntry.attr));
-        if let DirEntryAttribute::File { size, mtime } = direntry.attr {
+        if let DirEntryAttribute::File { size, mtime, .. } = direntry.attr {
             entry.size = size.into();
             entry.mtime = mtime.into();
         }
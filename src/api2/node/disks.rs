@@ -15,6 +15,7 @@ use crate::server::WorkerTask;
 
 use crate::api2::types::{Authid, UPID_SCHEMA, NODE_SCHEMA, BLOCKDEVICE_NAME_SCHEMA};
 
+pub mod btrfs;
 pub mod directory;
 pub mod zfs;
 
@@ -169,6 +170,7 @@ pub fn initialize_disk(
 #[sortable]
 const SUBDIRS: SubdirMap = &sorted!([
     //    ("lvm", &lvm::ROUTER),
+    ("btrfs", &btrfs::ROUTER),
     ("directory", &directory::ROUTER),
     ("zfs", &zfs::ROUTER),
     (
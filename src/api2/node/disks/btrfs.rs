@@ -0,0 +1,161 @@
+use anyhow::{bail, Error};
+use serde_json::json;
+
+use proxmox::api::{
+    api, Permission, RpcEnvironment, RpcEnvironmentType,
+    schema::parse_property_string,
+};
+use proxmox::api::router::Router;
+
+use crate::config::acl::PRIV_SYS_MODIFY;
+use crate::tools::disks::{
+    BtrfsFormatOptions, BtrfsRaid, DiskManage, DiskUsageType, FileSystemType,
+    create_btrfs_file_system, get_fs_uuid,
+};
+use crate::config::datastore::{self, DataStoreConfig};
+
+use crate::server::WorkerTask;
+
+use crate::api2::types::*;
+
+use crate::tools::systemd;
+
+use super::directory::create_datastore_mount_unit;
+use super::zfs::{DISK_ARRAY_SCHEMA, DISK_LIST_SCHEMA};
+
+#[api(
+    protected: true,
+    input: {
+        properties: {
+            node: {
+                schema: NODE_SCHEMA,
+            },
+            name: {
+                schema: DATASTORE_SCHEMA,
+            },
+            devices: {
+                schema: DISK_LIST_SCHEMA,
+            },
+            "data-profile": {
+                type: BtrfsRaid,
+            },
+            "metadata-profile": {
+                type: BtrfsRaid,
+            },
+            "add-datastore": {
+                description: "Configure a datastore using the btrfs file system.",
+                type: bool,
+                optional: true,
+            },
+        },
+    },
+    returns: {
+        schema: UPID_SCHEMA,
+    },
+    access: {
+        permission: &Permission::Privilege(&["system", "disks"], PRIV_SYS_MODIFY, false),
+    },
+)]
+/// Create a new btrfs file system on one or more disks. Will be mounted under '/mnt/datastore/<name>'.
+pub fn create_btrfs(
+    name: String,
+    devices: String,
+    data_profile: BtrfsRaid,
+    metadata_profile: BtrfsRaid,
+    add_datastore: Option<bool>,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<String, Error> {
+
+    let to_stdout = rpcenv.env_type() == RpcEnvironmentType::CLI;
+
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+
+    let add_datastore = add_datastore.unwrap_or(false);
+
+    let devices_text = devices.clone();
+    let devices = parse_property_string(&devices, &DISK_ARRAY_SCHEMA)?;
+    let devices: Vec<String> = devices.as_array().unwrap().iter()
+        .map(|v| v.as_str().unwrap().to_string()).collect();
+
+    let disk_map = crate::tools::disks::get_disks(None, true)?;
+    for disk in devices.iter() {
+        match disk_map.get(disk) {
+            Some(info) => {
+                if info.used != DiskUsageType::Unused {
+                    bail!("disk '{}' is already in use.", disk);
+                }
+            }
+            None => {
+                bail!("no such disk '{}'", disk);
+            }
+        }
+    }
+
+    let mount_point = format!("/mnt/datastore/{}", &name);
+
+    // check if the default path does exist already and bail if it does
+    let default_path = std::path::PathBuf::from(&mount_point);
+
+    match std::fs::metadata(&default_path) {
+        Err(_) => {}, // path does not exist
+        Ok(_) => {
+            bail!("path {:?} already exists", default_path);
+        }
+    }
+
+    let upid_str = WorkerTask::new_thread(
+        "btrfscreate", Some(name.clone()), auth_id, to_stdout, move |worker|
+        {
+            worker.log(format!(
+                "create btrfs (data {}, metadata {}) '{}' on devices '{}'",
+                data_profile, metadata_profile, name, devices_text,
+            ));
+
+            let manager = DiskManage::new();
+
+            let mut disks = Vec::with_capacity(devices.len());
+            for device in devices.iter() {
+                disks.push(manager.clone().disk_by_name(device)?);
+            }
+            let disk_refs: Vec<_> = disks.iter().collect();
+
+            let opts = BtrfsFormatOptions {
+                data_profile,
+                metadata_profile,
+                label: Some(name.clone()),
+            };
+
+            create_btrfs_file_system(&disk_refs, opts)?;
+
+            // all devices share the same file system uuid
+            let uuid = get_fs_uuid(&disks[0])?;
+            let uuid_path = format!("/dev/disk/by-uuid/{}", uuid);
+
+            let mount_unit_name = create_datastore_mount_unit(&name, &mount_point, FileSystemType::Btrfs, &uuid_path)?;
+
+            systemd::reload_daemon()?;
+            systemd::enable_unit(&mount_unit_name)?;
+            systemd::start_unit(&mount_unit_name)?;
+
+            if add_datastore {
+                let lock = datastore::lock_config()?;
+                let datastore: DataStoreConfig =
+                    serde_json::from_value(json!({ "name": name, "path": mount_point }))?;
+
+                let (config, _digest) = datastore::config()?;
+
+                if config.sections.get(&datastore.name).is_some() {
+                    bail!("datastore '{}' already exists.", datastore.name);
+                }
+
+                crate::api2::config::datastore::do_create_datastore(lock, config, datastore, Some(&worker))?;
+            }
+
+            Ok(())
+        })?;
+
+    Ok(upid_str)
+}
+
+pub const ROUTER: Router = Router::new()
+    .post(&API_METHOD_CREATE_BTRFS);
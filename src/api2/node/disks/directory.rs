@@ -167,7 +167,7 @@ pub fn create_datastore_disk(
 
             let disk = manager.disk_by_name(&disk)?;
 
-            let partition = create_single_linux_partition(&disk)?;
+            let partition = create_single_linux_partition(&disk, Some(&name), None)?;
             create_file_system(&partition, filesystem)?;
 
             let uuid = get_fs_uuid(&partition)?;
@@ -263,7 +263,7 @@ pub const ROUTER: Router = Router::new()
     .match_all("name", &ITEM_ROUTER);
 
 
-fn create_datastore_mount_unit(
+pub(crate) fn create_datastore_mount_unit(
     datastore_name: &str,
     mount_point: &str,
     fs_type: FileSystemType,
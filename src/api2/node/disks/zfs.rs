@@ -48,27 +48,6 @@ pub const ZPOOL_NAME_SCHEMA: Schema =StringSchema::new("ZFS Pool Name")
     .format(&ApiStringFormat::Pattern(&ZPOOL_NAME_REGEX))
     .schema();
 
-#[api(
-    default: "On",
-)]
-#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
-#[serde(rename_all = "lowercase")]
-/// The ZFS compression algorithm to use.
-pub enum ZfsCompressionType {
-    /// Gnu Zip
-    Gzip,
-    /// LZ4
-    Lz4,
-    /// LZJB
-    Lzjb,
-    /// ZLE
-    Zle,
-    /// Enable compression using the default algorithm.
-    On,
-    /// Disable compression.
-    Off,
-}
-
 #[api()]
 #[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
@@ -74,10 +74,18 @@ fn get_status(
         uname.version()
     );
 
+    let root = crate::tools::disks::disk_usage_full(Path::new("/"))?;
+    let root_inodes = NodeInodeCounters {
+        total: root.inodes_total,
+        used: root.inodes_used,
+        free: root.inodes_free,
+    };
+
     Ok(NodeStatus {
         memory,
         swap,
-        root: crate::tools::disks::disk_usage(Path::new("/"))?,
+        root: root.into(),
+        root_inodes,
         uptime: procfs::read_proc_uptime()?.0 as u64,
         loadavg,
         kversion,
@@ -6,6 +6,7 @@ use serde_json::{json, Value};
 
 use proxmox::api::{api, Router, RpcEnvironment, Permission};
 use proxmox::api::router::SubdirMap;
+use proxmox::api::schema::{Schema, StringSchema};
 use proxmox::{identity, list_subdirs_api_method, sortable};
 
 use crate::tools;
@@ -13,7 +14,7 @@ use crate::tools;
 use crate::api2::types::*;
 use crate::api2::pull::check_pull_privs;
 
-use crate::server::{self, UPID, TaskState, TaskListInfoIterator};
+use crate::server::{self, UPID, TaskState, TaskListInfoIterator, TaskLogEntry, TaskLogTimezone};
 use crate::config::acl::{
     PRIV_DATASTORE_MODIFY,
     PRIV_DATASTORE_VERIFY,
@@ -220,6 +221,57 @@ async fn get_task_status(
     Ok(result)
 }
 
+#[api(
+    input: {
+        properties: {
+            node: {
+                schema: NODE_SCHEMA,
+            },
+            upid: {
+                schema: UPID_SCHEMA,
+            },
+        },
+    },
+    returns: {
+        description: "Task exit status.",
+        properties: {
+            active: {
+                type: bool,
+                description: "Whether the task is still running.",
+            },
+            exitstatus: {
+                type: String,
+                optional: true,
+                description: "'OK', 'Error: <msg>', or 'unkwown' (only set for stopped tasks).",
+            },
+        },
+    },
+    access: {
+        description: "Users can access their own tasks, or need Sys.Audit on /system/tasks.",
+        permission: &Permission::Anybody,
+    },
+)]
+/// Get the task exit status only.
+///
+/// This is a lightweight alternative to the full task status, for polling.
+async fn get_task_exit_status(
+    param: Value,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<Value, Error> {
+
+    let upid = extract_upid(&param)?;
+
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+    check_task_access(&auth_id, &upid)?;
+
+    if crate::server::worker_is_active(&upid).await? {
+        Ok(json!({ "active": true }))
+    } else {
+        let exitstatus = crate::server::upid_read_status(&upid).unwrap_or(TaskState::Unknown { endtime: 0 });
+        Ok(json!({ "active": false, "exitstatus": exitstatus.to_string() }))
+    }
+}
+
 fn extract_upid(param: &Value) -> Result<UPID, Error> {
 
     let upid_str = tools::required_string_param(&param, "upid")?;
@@ -227,6 +279,17 @@ fn extract_upid(param: &Value) -> Result<UPID, Error> {
     upid_str.parse::<UPID>()
 }
 
+const TASK_LOG_TIMEZONE_SCHEMA: Schema = StringSchema::new(
+    "Format timestamps using this timezone ('UTC', '+HH:MM' or a zone name like 'Europe/Vienna'). Default is the server timezone.")
+    .schema();
+
+fn extract_timezone(param: &Value) -> Result<Option<TaskLogTimezone>, Error> {
+    match param["timezone"].as_str() {
+        Some(timezone) => Ok(Some(timezone.parse()?)),
+        None => Ok(None),
+    }
+}
+
 #[api(
     input: {
         properties: {
@@ -253,6 +316,10 @@ fn extract_upid(param: &Value) -> Result<UPID, Error> {
                 description: "Only list this amount of lines.",
                 default: 50,
             },
+            timezone: {
+                schema: TASK_LOG_TIMEZONE_SCHEMA,
+                optional: true,
+            },
         },
     },
     access: {
@@ -277,6 +344,8 @@ async fn read_task_log(
     let start = param["start"].as_u64().unwrap_or(0);
     let mut limit = param["limit"].as_u64().unwrap_or(50);
 
+    let timezone = extract_timezone(&param)?;
+
     let mut count: u64 = 0;
 
     let path = upid.log_path();
@@ -292,6 +361,11 @@ async fn read_task_log(
                 if count < start { continue };
 	        if limit == 0 { continue };
 
+                let line = match timezone {
+                    Some(ref tz) => TaskLogEntry::parse(&line).format(tz),
+                    None => line,
+                };
+
                 lines.push(json!({ "n": count, "t": line }));
 
                 limit -= 1;
@@ -313,6 +387,64 @@ async fn read_task_log(
     Ok(json!(lines))
 }
 
+#[api(
+    input: {
+        properties: {
+            node: {
+                schema: NODE_SCHEMA,
+            },
+            upid: {
+                schema: UPID_SCHEMA,
+            },
+            timezone: {
+                schema: TASK_LOG_TIMEZONE_SCHEMA,
+                optional: true,
+            },
+        },
+    },
+    returns: {
+        description: "The whole task log.",
+        type: String,
+    },
+    access: {
+        description: "Users can access their own tasks, or need Sys.Audit on /system/tasks.",
+        permission: &Permission::Anybody,
+    },
+)]
+/// Read the whole task log as plain text.
+fn read_task_log_text(
+    param: Value,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<String, Error> {
+
+    let upid = extract_upid(&param)?;
+
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+
+    check_task_access(&auth_id, &upid)?;
+
+    let timezone = extract_timezone(&param)?;
+
+    let file = File::open(upid.log_path())?;
+
+    let mut text = String::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        match timezone {
+            Some(ref tz) => text.push_str(&TaskLogEntry::parse(&line).format(tz)),
+            None => text.push_str(&line),
+        }
+        text.push('\n');
+    }
+
+    Ok(text)
+}
+
+// worker types which check WorkerTask::cleanup_requested
+fn task_supports_cleanup(worker_type: &str) -> bool {
+    worker_type == "sync" || worker_type == "syncjob"
+}
+
 #[api(
     protected: true,
     input: {
@@ -323,6 +455,12 @@ async fn read_task_log(
             upid: {
                 schema: UPID_SCHEMA,
             },
+            cleanup: {
+                type: bool,
+                description: "Also remove data written by the interrupted operation (sync tasks only).",
+                default: false,
+                optional: true,
+            },
         },
     },
     access: {
@@ -345,7 +483,12 @@ fn stop_task(
         user_info.check_privs(&auth_id, &["system", "tasks"], PRIV_SYS_MODIFY, false)?;
     }
 
-    server::abort_worker_async(upid);
+    let cleanup = param["cleanup"].as_bool().unwrap_or(false);
+    if cleanup && !task_supports_cleanup(&upid.worker_type) {
+        bail!("task type '{}' does not support cleanup on stop", upid.worker_type);
+    }
+
+    server::abort_worker_async(upid, cleanup);
 
     Ok(Value::Null)
 }
@@ -412,6 +555,11 @@ fn stop_task(
                     type: TaskStateType,
                 },
             },
+            cursor: {
+                optional: true,
+                schema: UPID_SCHEMA,
+                description: "Continue listing after this task (use 'next-cursor' of the previous call).",
+            },
         },
     },
     returns: {
@@ -425,6 +573,9 @@ fn stop_task(
     },
 )]
 /// List tasks.
+///
+/// The returned 'total' is the exact number of tasks matching all filters
+/// (after the cursor), so this always reads the list to the end.
 #[allow(clippy::too_many_arguments)]
 pub fn list_tasks(
     start: u64,
@@ -436,6 +587,7 @@ pub fn list_tasks(
     until: Option<i64>,
     typefilter: Option<String>,
     statusfilter: Option<Vec<TaskStateType>>,
+    cursor: Option<String>,
     param: Value,
     mut rpcenv: &mut dyn RpcEnvironment,
 ) -> Result<Vec<TaskListItem>, Error> {
@@ -448,10 +600,28 @@ pub fn list_tasks(
 
     let store = param["store"].as_str();
 
+    let cursor: Option<(String, UPID)> = match cursor {
+        Some(cursor) => {
+            let upid = cursor.parse()?;
+            Some((cursor, upid))
+        }
+        None => None,
+    };
+
+    let filter = TaskListFilter {
+        running,
+        errors,
+        userfilter: userfilter.as_deref(),
+        store,
+        typefilter: typefilter.as_deref(),
+        statusfilter: statusfilter.as_deref(),
+    };
+
     let list = TaskListInfoIterator::new(running)?;
     let limit = if limit > 0 { limit as usize } else { usize::MAX };
 
-    let result: Vec<TaskListItem> = list
+    // the list is sorted by start time (newest first), so the time range is applied first
+    let list = list
         .skip_while(|info| {
             match (info, until) {
                 (Ok(info), Some(until)) => info.upid.starttime > until,
@@ -466,57 +636,183 @@ pub fn list_tasks(
                 (Err(_), _) => false,
             }
         })
-        .filter_map(|info| {
-        let info = match info {
-            Ok(info) => info,
-            Err(_) => return None,
-        };
-
-        if !list_all && check_task_access(&auth_id, &info.upid).is_err() {
-            return None;
+        .filter_map(|info| info.ok())
+        .filter(|info| list_all || check_task_access(&auth_id, &info.upid).is_ok())
+        .filter(|info| filter.matches(info))
+        .map(TaskListItem::from);
+
+    // pagination is only applied to tasks matching all filters
+    let (result, total) = paginate_task_list(list, cursor.as_ref(), start as usize, limit);
+
+    if total > start as usize + result.len() {
+        if let Some(last) = result.last() {
+            rpcenv["next-cursor"] = Value::from(last.upid.clone());
         }
+    }
+
+    rpcenv["total"] = Value::from(total);
 
-        if let Some(needle) = &userfilter {
-            if !info.upid.auth_id.to_string().contains(needle) { return None; }
+    Ok(result)
+}
+
+// Filters for 'list_tasks' (besides the time range and access checks)
+struct TaskListFilter<'a> {
+    running: bool,
+    errors: bool,
+    userfilter: Option<&'a str>,
+    store: Option<&'a str>,
+    typefilter: Option<&'a str>,
+    statusfilter: Option<&'a [TaskStateType]>,
+}
+
+impl <'a> TaskListFilter<'a> {
+
+    fn matches(&self, info: &server::TaskListInfo) -> bool {
+        if let Some(needle) = self.userfilter {
+            if !info.upid.auth_id.to_string().contains(needle) { return false; }
         }
 
-        if let Some(store) = store {
+        if let Some(store) = self.store {
             if !check_job_store(&info.upid, store) {
-                return None;
+                return false;
             }
         }
 
-        if let Some(typefilter) = &typefilter {
+        if let Some(typefilter) = self.typefilter {
             if !info.upid.worker_type.contains(typefilter) {
-                return None;
+                return false;
             }
         }
 
-        match (&info.state, &statusfilter) {
-            (Some(_), _) if running => return None,
-            (Some(crate::server::TaskState::OK { .. }), _) if errors => return None,
-            (Some(state), Some(filters)) => {
-                if !filters.contains(&state.tasktype()) {
-                    return None;
+        match (&info.state, self.statusfilter) {
+            (Some(_), _) if self.running => false,
+            (Some(TaskState::OK { .. }), _) if self.errors => false,
+            (Some(state), Some(filters)) => filters.contains(&state.tasktype()),
+            (None, Some(_)) => false, // running tasks have no status yet
+            _ => true,
+        }
+    }
+}
+
+// Apply the cursor and 'start'/'limit' to the filtered task list.
+//
+// Returns the requested page and the total number of tasks after the cursor,
+// which means this needs to consume the whole list.
+fn paginate_task_list<I: Iterator<Item = TaskListItem>>(
+    list: I,
+    cursor: Option<&(String, UPID)>,
+    start: usize,
+    limit: usize,
+) -> (Vec<TaskListItem>, usize) {
+    // set once we passed the cursor task
+    let mut cursor_found = false;
+
+    let mut result = Vec::new();
+    let mut total = 0;
+
+    for item in list.skip_while(|item| {
+        // new tasks are prepended, so resume after the cursor task instead of
+        // relying on the offset - skip everything up to (and including) the cursor,
+        // or everything not older than the cursor if that task is gone
+        match cursor {
+            Some((cursor_str, cursor_upid)) => {
+                if &item.upid == cursor_str {
+                    cursor_found = true;
+                    return true;
                 }
-            },
-            (None, Some(_)) => return None,
-            _ => {},
+                !cursor_found && item.starttime >= cursor_upid.starttime
+            }
+            None => false,
         }
+    }) {
+        if total >= start && result.len() < limit {
+            result.push(item);
+        }
+        total += 1;
+    }
 
-        Some(info.into())
-    }).skip(start as usize)
-        .take(limit)
-        .collect();
+    (result, total)
+}
 
-    let mut count = result.len() + start as usize;
-    if !result.is_empty() && result.len() >= limit { // we have a 'virtual' entry as long as we have any new
-        count += 1;
-    }
+#[cfg(test)]
+fn test_task_info(starttime: i64, worker_type: &str, state: Option<TaskState>) -> server::TaskListInfo {
+    let upid_str = format!(
+        "UPID:node:00000001:00000001:00000000:{:08X}:{}::root@pam:",
+        starttime,
+        worker_type,
+    );
+    server::TaskListInfo { upid: upid_str.parse().unwrap(), upid_str, state }
+}
 
-    rpcenv["total"] = Value::from(count);
+#[test]
+fn test_task_list_filter_and_pagination() {
+    // newest first, like TaskListInfoIterator
+    let task_list = || {
+        let mut list = Vec::new();
+        for i in 0..20i64 {
+            let worker_type = if i % 2 == 0 { "backup" } else { "prune" };
+            let state = match i % 4 {
+                0 => Some(TaskState::OK { endtime: 1000 - i }),
+                1 => Some(TaskState::Error { message: "failed".to_string(), endtime: 1000 - i }),
+                2 => Some(TaskState::Warning { count: 1, endtime: 1000 - i }),
+                _ => None,
+            };
+            list.push(test_task_info(1000 - i, worker_type, state));
+        }
+        list
+    };
 
-    Ok(result)
+    let statusfilter = vec![TaskStateType::OK, TaskStateType::Warning];
+    let filter = TaskListFilter {
+        running: false,
+        errors: false,
+        userfilter: None,
+        store: None,
+        typefilter: Some("backup"),
+        statusfilter: Some(&statusfilter),
+    };
+
+    let filtered = || task_list()
+        .into_iter()
+        .filter(|info| filter.matches(info))
+        .map(TaskListItem::from);
+
+    // every even task is a backup with status OK or warning
+    let (page, total) = paginate_task_list(filtered(), None, 0, 3);
+    assert_eq!(total, 10);
+    assert_eq!(page.iter().map(|item| item.starttime).collect::<Vec<_>>(), vec![1000, 998, 996]);
+
+    let (page, total) = paginate_task_list(filtered(), None, 0, 0);
+    assert_eq!(total, 10);
+    assert!(page.is_empty());
+
+    // the total does not depend on the page
+    let (page, total) = paginate_task_list(filtered(), None, 8, 3);
+    assert_eq!(total, 10);
+    assert_eq!(page.iter().map(|item| item.starttime).collect::<Vec<_>>(), vec![984, 982]);
+
+    let (page, total) = paginate_task_list(filtered(), None, 10, 3);
+    assert_eq!(total, 10);
+    assert!(page.is_empty());
+
+    // the cursor task and everything before it are skipped
+    let cursor_upid = task_list()[4].upid_str.clone();
+    let cursor = (cursor_upid.clone(), cursor_upid.parse().unwrap());
+    let (page, total) = paginate_task_list(filtered(), Some(&cursor), 1, 2);
+    assert_eq!(total, 7);
+    assert_eq!(page.iter().map(|item| item.starttime).collect::<Vec<_>>(), vec![992, 990]);
+
+    // running tasks have no status, so they never match a status filter
+    let statusfilter = vec![TaskStateType::Error];
+    let filter = TaskListFilter {
+        running: false,
+        errors: false,
+        userfilter: None,
+        store: None,
+        typefilter: Some("prune"),
+        statusfilter: Some(&statusfilter),
+    };
+    assert_eq!(task_list().iter().filter(|info| filter.matches(info)).count(), 5);
 }
 
 #[sortable]
@@ -524,10 +820,22 @@ const UPID_API_SUBDIRS: SubdirMap = &sorted!([
     (
         "log", &Router::new()
             .get(&API_METHOD_READ_TASK_LOG)
+            .subdirs(&[
+                (
+                    "text", &Router::new()
+                        .get(&API_METHOD_READ_TASK_LOG_TEXT)
+                ),
+            ])
     ),
     (
         "status", &Router::new()
             .get(&API_METHOD_GET_TASK_STATUS)
+            .subdirs(&[
+                (
+                    "exit", &Router::new()
+                        .get(&API_METHOD_GET_TASK_EXIT_STATUS)
+                ),
+            ])
     )
 ]);
 
@@ -9,7 +9,7 @@ use proxmox::api::{ApiMethod, Router, RpcEnvironment, Permission};
 
 use crate::server::{WorkerTask, jobstate::Job};
 use crate::backup::DataStore;
-use crate::client::{HttpClient, BackupRepository, pull::pull_store};
+use crate::client::{HttpClient, BackupRepository, pull::{pull_store, PullChunkConcurrency, PullParameters}};
 use crate::api2::types::*;
 use crate::config::{
     remote,
@@ -98,7 +98,10 @@ pub fn do_sync_job(
                 worker.log(format!("Sync datastore '{}' from '{}/{}'",
                         sync_job.store, sync_job.remote, sync_job.remote_store));
 
-                crate::client::pull::pull_store(&worker, &client, &src_repo, tgt_store.clone(), delete, sync_owner).await?;
+                let mut params = PullParameters::new(src_repo, tgt_store, sync_owner);
+                params.delete = delete;
+
+                pull_store(&worker, &client, &params).await?;
 
                 worker.log(format!("sync job '{}' end", &job_id));
 
@@ -149,6 +152,36 @@ pub fn do_sync_job(
                 schema: REMOVE_VANISHED_BACKUPS_SCHEMA,
                 optional: true,
             },
+            "verify-synced": {
+                description: "Verify newly synced snapshots.",
+                type: bool,
+                optional: true,
+                default: false,
+            },
+            "omit-client-log": {
+                description: "Do not download client logs of synced snapshots.",
+                type: bool,
+                optional: true,
+                default: false,
+            },
+            "check-concurrency": {
+                schema: PULL_CHECK_CONCURRENCY_SCHEMA,
+                optional: true,
+            },
+            "download-concurrency": {
+                schema: PULL_DOWNLOAD_CONCURRENCY_SCHEMA,
+                optional: true,
+            },
+            "group-concurrency": {
+                schema: PULL_GROUP_CONCURRENCY_SCHEMA,
+                optional: true,
+            },
+            resume: {
+                description: "Skip the groups already synced by a previous failed sync.",
+                type: bool,
+                optional: true,
+                default: false,
+            },
         },
     },
     access: {
@@ -166,12 +199,20 @@ async fn pull (
     remote: String,
     remote_store: String,
     remove_vanished: Option<bool>,
+    verify_synced: bool,
+    omit_client_log: bool,
+    check_concurrency: Option<usize>,
+    download_concurrency: Option<usize>,
+    group_concurrency: Option<usize>,
+    resume: bool,
     _info: &ApiMethod,
     rpcenv: &mut dyn RpcEnvironment,
 ) -> Result<String, Error> {
 
     let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
     let delete = remove_vanished.unwrap_or(true);
+    let concurrency = PullChunkConcurrency::new(check_concurrency, download_concurrency);
+    let group_concurrency = group_concurrency.unwrap_or(1);
 
     check_pull_privs(&auth_id, &store, &remote, &remote_store, delete)?;
 
@@ -182,7 +223,17 @@ async fn pull (
 
         worker.log(format!("sync datastore '{}' start", store));
 
-        let pull_future = pull_store(&worker, &client, &src_repo, tgt_store.clone(), delete, auth_id);
+        let params = PullParameters {
+            delete,
+            verify_synced,
+            concurrency,
+            omit_client_log,
+            resume,
+            group_concurrency,
+            ..PullParameters::new(src_repo, tgt_store, auth_id)
+        };
+
+        let pull_future = pull_store(&worker, &client, &params);
         let future = select!{
             success = pull_future.fuse() => success,
             abort = worker.abort_future().map(|_| Err(format_err!("pull aborted"))) => abort,
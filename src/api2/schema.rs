@@ -0,0 +1,495 @@
+//! OpenAPI schema export
+//!
+//! Generates an OpenAPI 3.0 document from the `#[api]` method definitions of the router tree.
+
+use std::collections::{BTreeSet, HashMap};
+
+use anyhow::Error;
+use serde_json::{json, Map, Value};
+
+use proxmox::api::schema::{ApiStringFormat, ObjectSchema, ObjectSchemaType, Schema};
+use proxmox::api::{ApiHandler, ApiMethod, Permission, Router, RpcEnvironment, SubRoute};
+
+use super::version::PROXMOX_PKG_VERSION;
+
+/// Generate an OpenAPI specification from a router tree
+pub trait OpenApiSchema {
+    fn to_openapi_schema(&self) -> Value;
+}
+
+impl OpenApiSchema for Router {
+    fn to_openapi_schema(&self) -> Value {
+        let mut generator = OpenApiGenerator::default();
+        generator.count_router(self);
+        generator.add_router(self, "", &[]);
+        generator.document()
+    }
+}
+
+// schemas are static, so the address identifies them
+fn schema_key(schema: &Schema) -> usize {
+    schema as *const Schema as usize
+}
+
+fn is_object(schema: &Schema) -> bool {
+    matches!(schema, Schema::Object(_) | Schema::AllOf(_))
+}
+
+fn object_properties(schema: &Schema) -> Option<&dyn ObjectSchemaType> {
+    match schema {
+        Schema::Object(object) => Some(object),
+        Schema::AllOf(all_of) => Some(all_of),
+        _ => None,
+    }
+}
+
+// "backup-type" => "BackupType"
+fn camel_case(text: &str) -> String {
+    text.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            match chars.next() {
+                Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+#[derive(Default)]
+struct OpenApiGenerator {
+    // number of references to each object schema
+    uses: HashMap<usize, usize>,
+    // object schemas referenced more than once are moved to `components.schemas`
+    names: HashMap<usize, String>,
+    components: Map<String, Value>,
+    paths: Map<String, Value>,
+    tags: BTreeSet<String>,
+}
+
+impl OpenApiGenerator {
+    fn count_schema(&mut self, schema: &Schema) {
+        match schema {
+            Schema::Object(_) | Schema::AllOf(_) => {
+                let uses = self.uses.entry(schema_key(schema)).or_insert(0);
+                *uses += 1;
+                if *uses > 1 {
+                    return;
+                }
+                if let Some(object) = object_properties(schema) {
+                    for (_name, _optional, property) in object.properties() {
+                        self.count_schema(property);
+                    }
+                }
+            }
+            Schema::Array(array) => self.count_schema(array.items),
+            _ => (),
+        }
+    }
+
+    fn count_router(&mut self, router: &Router) {
+        for api_method in [router.get, router.post, router.put, router.delete].iter().flatten() {
+            for (_name, _optional, property) in api_method.parameters.properties() {
+                self.count_schema(property);
+            }
+            self.count_schema(api_method.returns.schema);
+        }
+
+        match &router.subroute {
+            None => (),
+            Some(SubRoute::MatchAll { router, .. }) => self.count_router(router),
+            Some(SubRoute::Map(dirmap)) => {
+                for (_key, sub_router) in dirmap.iter() {
+                    self.count_router(sub_router);
+                }
+            }
+        }
+    }
+
+    // returns a reference for shared object schemas, the inlined schema otherwise
+    fn schema(&mut self, schema: &Schema, context: &str) -> Value {
+        let key = schema_key(schema);
+
+        if !is_object(schema) || self.uses.get(&key).copied().unwrap_or(0) < 2 {
+            return self.inline_schema(schema, context);
+        }
+
+        if let Some(name) = self.names.get(&key) {
+            return json!({ "$ref": format!("#/components/schemas/{}", name) });
+        }
+
+        let base = match camel_case(context) {
+            name if name.is_empty() => "Object".to_string(),
+            name => name,
+        };
+        let mut name = base.clone();
+        let mut count = 1;
+        while self.components.contains_key(&name) {
+            count += 1;
+            name = format!("{}{}", base, count);
+        }
+
+        // register first, the schema may reference itself
+        self.names.insert(key, name.clone());
+        self.components.insert(name.clone(), Value::Null);
+        let data = self.inline_schema(schema, context);
+        self.components.insert(name.clone(), data);
+
+        json!({ "$ref": format!("#/components/schemas/{}", name) })
+    }
+
+    fn object_schema(&mut self, object: &dyn ObjectSchemaType, description: &str) -> Value {
+        let mut properties = Map::new();
+        let mut required = Vec::new();
+
+        for (name, optional, property) in object.properties() {
+            properties.insert(name.to_string(), self.schema(property, name));
+            if !*optional {
+                required.push(Value::from(*name));
+            }
+        }
+
+        let mut data = json!({
+            "type": "object",
+            "description": description,
+            "properties": properties,
+            "additionalProperties": object.additional_properties(),
+        });
+        if !required.is_empty() {
+            data["required"] = required.into();
+        }
+
+        data
+    }
+
+    fn inline_schema(&mut self, schema: &Schema, context: &str) -> Value {
+        match schema {
+            Schema::Null => json!({ "nullable": true }),
+            Schema::Boolean(boolean_schema) => {
+                let mut data = json!({
+                    "type": "boolean",
+                    "description": boolean_schema.description,
+                });
+                if let Some(default) = boolean_schema.default {
+                    data["default"] = default.into();
+                }
+                data
+            }
+            Schema::Integer(integer_schema) => {
+                let mut data = json!({
+                    "type": "integer",
+                    "description": integer_schema.description,
+                });
+                if let Some(default) = integer_schema.default {
+                    data["default"] = default.into();
+                }
+                if let Some(minimum) = integer_schema.minimum {
+                    data["minimum"] = minimum.into();
+                }
+                if let Some(maximum) = integer_schema.maximum {
+                    data["maximum"] = maximum.into();
+                }
+                data
+            }
+            Schema::Number(number_schema) => {
+                let mut data = json!({
+                    "type": "number",
+                    "description": number_schema.description,
+                });
+                if let Some(default) = number_schema.default {
+                    data["default"] = default.into();
+                }
+                if let Some(minimum) = number_schema.minimum {
+                    data["minimum"] = minimum.into();
+                }
+                if let Some(maximum) = number_schema.maximum {
+                    data["maximum"] = maximum.into();
+                }
+                data
+            }
+            Schema::String(string_schema) => {
+                let mut data = json!({
+                    "type": "string",
+                    "description": string_schema.description,
+                });
+                if let Some(default) = string_schema.default {
+                    data["default"] = default.into();
+                }
+                if let Some(min_length) = string_schema.min_length {
+                    data["minLength"] = min_length.into();
+                }
+                if let Some(max_length) = string_schema.max_length {
+                    data["maxLength"] = max_length.into();
+                }
+                match string_schema.format {
+                    None | Some(ApiStringFormat::VerifyFn(_)) => (),
+                    Some(ApiStringFormat::Pattern(const_regex)) => {
+                        data["pattern"] = const_regex.regex_string.into();
+                    }
+                    Some(ApiStringFormat::Enum(variants)) => {
+                        let variants: Vec<Value> = variants
+                            .iter()
+                            .map(|variant| Value::from(variant.value))
+                            .collect();
+                        data["enum"] = variants.into();
+                    }
+                    Some(ApiStringFormat::PropertyString(_)) => {
+                        data["format"] = "property-string".into();
+                    }
+                }
+                data
+            }
+            Schema::Array(array_schema) => {
+                let mut data = json!({
+                    "type": "array",
+                    "description": array_schema.description,
+                    "items": self.schema(array_schema.items, context),
+                });
+                if let Some(min_length) = array_schema.min_length {
+                    data["minItems"] = min_length.into();
+                }
+                if let Some(max_length) = array_schema.max_length {
+                    data["maxItems"] = max_length.into();
+                }
+                data
+            }
+            Schema::Object(object_schema) => {
+                self.object_schema(object_schema, object_schema.description)
+            }
+            Schema::AllOf(all_of_schema) => {
+                self.object_schema(all_of_schema, all_of_schema.description)
+            }
+        }
+    }
+
+    fn operation(
+        &mut self,
+        method: &str,
+        api_method: &ApiMethod,
+        path: &str,
+        path_params: &[&str],
+        tag: &str,
+    ) -> Value {
+        let description = api_method.parameters.description();
+        let summary = description.lines().next().unwrap_or("");
+
+        let mut parameters = Vec::new();
+        for name in path_params {
+            let schema = match api_method.parameters.lookup(name) {
+                Some((_optional, schema)) => self.schema(schema, name),
+                None => json!({ "type": "string" }),
+            };
+            parameters.push(json!({
+                "name": name,
+                "in": "path",
+                "required": true,
+                "schema": schema,
+            }));
+        }
+
+        let mut body_properties = Map::new();
+        let mut body_required = Vec::new();
+
+        for (name, optional, schema) in api_method.parameters.properties() {
+            if path_params.contains(name) {
+                continue;
+            }
+            let schema = self.schema(schema, name);
+            if method == "GET" || method == "DELETE" {
+                parameters.push(json!({
+                    "name": name,
+                    "in": "query",
+                    "required": !*optional,
+                    "schema": schema,
+                }));
+            } else {
+                body_properties.insert(name.to_string(), schema);
+                if !*optional {
+                    body_required.push(Value::from(*name));
+                }
+            }
+        }
+
+        let response = match api_method.handler {
+            ApiHandler::AsyncHttp(_) => json!({ "description": "Success" }),
+            _ => {
+                let mut data = json!({ "type": "object" });
+                if !matches!(api_method.returns.schema, Schema::Null) {
+                    data["properties"] = json!({
+                        "data": self.schema(api_method.returns.schema, path),
+                    });
+                }
+                json!({
+                    "description": "Success",
+                    "content": { "application/json": { "schema": data } },
+                })
+            }
+        };
+
+        let operation_id = format!("{}{}", method.to_lowercase(), camel_case(path));
+
+        let mut operation = json!({
+            "operationId": operation_id,
+            "summary": summary,
+            "description": description,
+            "tags": [tag],
+            "parameters": parameters,
+            "responses": { "200": response },
+        });
+
+        if !body_properties.is_empty() {
+            let mut schema = json!({
+                "type": "object",
+                "properties": body_properties,
+            });
+            let required = !body_required.is_empty();
+            if required {
+                schema["required"] = body_required.into();
+            }
+            operation["requestBody"] = json!({
+                "required": required,
+                "content": { "application/json": { "schema": schema } },
+            });
+        }
+
+        if let Permission::World = api_method.access.permission {
+            operation["security"] = json!([]);
+        }
+
+        operation
+    }
+
+    fn add_router(&mut self, router: &Router, path: &str, path_params: &[&str]) {
+        let tag = path
+            .trim_start_matches('/')
+            .split('/')
+            .next()
+            .filter(|segment| !segment.is_empty())
+            .unwrap_or("root")
+            .to_string();
+
+        let mut item = Map::new();
+        let methods = [
+            ("GET", router.get),
+            ("POST", router.post),
+            ("PUT", router.put),
+            ("DELETE", router.delete),
+        ];
+        for (method, api_method) in methods.iter() {
+            if let Some(api_method) = api_method {
+                let operation = self.operation(method, api_method, path, path_params, &tag);
+                item.insert(method.to_lowercase(), operation);
+            }
+        }
+
+        if !item.is_empty() {
+            let key = if path.is_empty() { "/".to_string() } else { path.to_string() };
+            self.paths.insert(key, Value::Object(item));
+            self.tags.insert(tag);
+        }
+
+        match &router.subroute {
+            None => (),
+            Some(SubRoute::MatchAll { router, param_name }) => {
+                let mut params = path_params.to_vec();
+                params.push(*param_name);
+                self.add_router(router, &format!("{}/{{{}}}", path, param_name), &params);
+            }
+            Some(SubRoute::Map(dirmap)) => {
+                for (key, sub_router) in dirmap.iter() {
+                    self.add_router(sub_router, &format!("{}/{}", path, key), path_params);
+                }
+            }
+        }
+    }
+
+    fn document(self) -> Value {
+        let tags: Vec<Value> = self.tags.iter().map(|tag| json!({ "name": tag })).collect();
+
+        json!({
+            "openapi": "3.0.3",
+            "info": {
+                "title": "Proxmox Backup Server API",
+                "version": PROXMOX_PKG_VERSION,
+            },
+            "servers": [{ "url": "/api2/json" }],
+            "tags": tags,
+            "paths": self.paths,
+            "components": {
+                "schemas": self.components,
+                "securitySchemes": {
+                    "ticket": {
+                        "type": "apiKey",
+                        "in": "cookie",
+                        "name": "PBSAuthCookie",
+                    },
+                    "apiToken": {
+                        "type": "apiKey",
+                        "in": "header",
+                        "name": "Authorization",
+                        "description": "PBSAPIToken=<tokenid>:<secret>",
+                    },
+                },
+            },
+            "security": [{ "ticket": [] }, { "apiToken": [] }],
+        })
+    }
+}
+
+fn get_schema(
+    _param: Value,
+    _info: &ApiMethod,
+    _rpcenv: &mut dyn RpcEnvironment,
+) -> Result<Value, Error> {
+    Ok(super::ROUTER.to_openapi_schema())
+}
+
+pub const ROUTER: Router = Router::new()
+    .get(
+        &ApiMethod::new(
+            &ApiHandler::Sync(&get_schema),
+            &ObjectSchema::new("OpenAPI 3.0 specification of this API.", &[])
+        ).access(None, &Permission::Anybody)
+    );
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_openapi_schema() -> Result<(), Error> {
+        let data = crate::api2::ROUTER.to_openapi_schema();
+
+        let _document: openapiv3::OpenAPI = serde_json::from_value(data.clone())?;
+
+        assert_eq!(data["info"]["version"], PROXMOX_PKG_VERSION);
+
+        let path = &data["paths"]["/admin/datastore/{store}/notes"];
+        assert!(path["get"].is_object());
+        assert!(path["put"]["requestBody"].is_object());
+        assert_eq!(path["get"]["tags"][0], "admin");
+        assert_eq!(path["get"]["parameters"][0]["in"], "path");
+
+        // world accessible methods need no authentication
+        assert_eq!(data["paths"]["/access/ticket"]["post"]["security"], json!([]));
+
+        // every reference points to an existing schema
+        fn check_refs(value: &Value, components: &Value) {
+            match value {
+                Value::Object(map) => {
+                    if let Some(Value::String(reference)) = map.get("$ref") {
+                        let name = reference.trim_start_matches("#/components/schemas/");
+                        assert!(components[name].is_object(), "missing schema {}", name);
+                    }
+                    map.values().for_each(|value| check_refs(value, components));
+                }
+                Value::Array(list) => list.iter().for_each(|value| check_refs(value, components)),
+                _ => (),
+            }
+        }
+        check_refs(&data, &data["components"]["schemas"]);
+
+        Ok(())
+    }
+}
@@ -68,7 +68,11 @@ use crate::{
             TapeLockError,
             set_tape_device_state,
         },
-        changer::update_changer_online_status,
+        changer::{
+            update_changer_online_status,
+            ElementStatus,
+            MtxStatus,
+        },
     },
 };
 
@@ -422,6 +426,8 @@ fn backup_worker(
     let status_path = Path::new(TAPE_STATUS_DIR);
     let start = std::time::Instant::now();
 
+    check_changer_health(worker, &setup.drive, &setup.pool)?;
+
     task_log!(worker, "update media online status");
     let changer_name = update_media_online_status(&setup.drive)?;
 
@@ -538,6 +544,9 @@ fn backup_worker(
         }
     }
 
+    // needs the drive, so check before eject/export
+    pool_writer.warn_if_drive_needs_cleaning(worker);
+
     if setup.export_media_set.unwrap_or(false) {
         pool_writer.export_media_set(worker)?;
     } else if setup.eject_media.unwrap_or(false) {
@@ -553,6 +562,100 @@ fn backup_worker(
     Ok(())
 }
 
+// Abort early if the changer reports faults for the drive or slots used by the
+// job, instead of failing somewhere in the middle of the backup
+//
+// This uses the cached changer status, so it does not move the robot. Faults
+// of other drives and slots are only logged as warnings.
+fn check_changer_health(worker: &WorkerTask, drive: &str, pool: &str) -> Result<(), Error> {
+
+    let (config, _digest) = config::drive::config()?;
+
+    if let Some((mut changer, changer_name)) = media_changer(&config, drive)? {
+        task_log!(worker, "check health of changer '{}'", changer_name);
+
+        let status = changer.cached_status()?;
+
+        let inventory = Inventory::load(Path::new(TAPE_STATUS_DIR))?;
+        // media of the pool, or unassigned media which the pool may allocate
+        let usable_media = |label_text: &str| {
+            match inventory.find_media_by_label_text(label_text) {
+                Some(media_id) => match media_id.media_set_label {
+                    Some(ref set) => set.pool == pool,
+                    None => true,
+                },
+                None => false, // not labeled, so the pool cannot use it
+            }
+        };
+
+        let (errors, warnings) = job_changer_faults(&status, changer.drive_number(), usable_media);
+
+        for warning in warnings {
+            task_warn!(worker, "changer '{}': {}", changer_name, warning);
+        }
+
+        if !errors.is_empty() {
+            bail!(
+                "changer '{}' reports faults:\n{}\nplease check the library before \
+                 starting the backup (rescan with 'POST /tape/changer/{}/health')",
+                changer_name,
+                errors.join("\n"),
+                changer_name,
+            );
+        }
+    }
+
+    Ok(())
+}
+
+// Split the faults reported in 'status' into errors (drive 'drivenum', the
+// slot its media was loaded from, and slots holding media accepted by
+// 'usable_media') and warnings (all other drives and slots)
+fn job_changer_faults<F: Fn(&str) -> bool>(
+    status: &MtxStatus,
+    drivenum: u64,
+    usable_media: F,
+) -> (Vec<String>, Vec<String>) {
+    let mut errors = Vec::new();
+    let mut warnings = Vec::new();
+
+    let mut loaded_slot = None;
+
+    for (i, drive) in status.drives.iter().enumerate() {
+        if i as u64 == drivenum {
+            loaded_slot = drive.loaded_slot;
+        }
+        if let Some(ref fault) = drive.fault {
+            let text = format!("drive {}: {}", i, fault);
+            if i as u64 == drivenum {
+                errors.push(text);
+            } else {
+                warnings.push(text);
+            }
+        }
+    }
+
+    for (i, slot) in status.slots.iter().enumerate() {
+        let slot_num = i as u64 + 1;
+        let fault = match slot.fault {
+            Some(ref fault) => fault,
+            None => continue,
+        };
+        let text = format!("slot {}: {}", slot_num, fault);
+        let used = loaded_slot == Some(slot_num) || match slot.status {
+            ElementStatus::VolumeTag(ref label_text) => !slot.import_export && usable_media(label_text),
+            _ => false,
+        };
+        if used {
+            errors.push(text);
+        } else {
+            warnings.push(text);
+        }
+    }
+
+    (errors, warnings)
+}
+
 // Try to update the the media online status
 fn update_media_online_status(drive: &str) -> Result<Option<String>, Error> {
 
@@ -658,3 +761,63 @@ pub fn backup_snapshot(
 
     Ok(true)
 }
+
+#[cfg(test)]
+mod test {
+    use super::job_changer_faults;
+    use crate::tape::changer::{DriveStatus, ElementStatus, MtxStatus, StorageElementStatus};
+
+    fn slot(label_text: Option<&str>, fault: Option<&str>) -> StorageElementStatus {
+        StorageElementStatus {
+            import_export: false,
+            status: match label_text {
+                Some(label_text) => ElementStatus::VolumeTag(label_text.to_string()),
+                None => ElementStatus::Empty,
+            },
+            element_address: 0,
+            fault: fault.map(String::from),
+        }
+    }
+
+    fn drive(loaded_slot: Option<u64>, fault: Option<&str>) -> DriveStatus {
+        DriveStatus {
+            loaded_slot,
+            status: ElementStatus::Empty,
+            drive_serial_number: None,
+            vendor: None,
+            model: None,
+            element_address: 0,
+            fault: fault.map(String::from),
+        }
+    }
+
+    #[test]
+    fn test_job_changer_faults() {
+        let status = MtxStatus {
+            drives: vec![drive(None, Some("drive fault")), drive(Some(4), None)],
+            slots: vec![
+                slot(Some("pool1"), Some("slot fault")),
+                slot(Some("other"), Some("slot fault")),
+                slot(None, Some("slot fault")),
+                slot(None, Some("slot fault")),
+                slot(Some("pool2"), None),
+            ],
+            transports: Vec::new(),
+        };
+
+        let usable_media = |label_text: &str| label_text.starts_with("pool");
+
+        // drive 1: its own drive is fine, slot 4 is where its media came from
+        let (errors, warnings) = job_changer_faults(&status, 1, usable_media);
+        assert_eq!(errors, vec!["slot 1: slot fault", "slot 4: slot fault"]);
+        assert_eq!(warnings, vec![
+            "drive 0: drive fault",
+            "slot 2: slot fault",
+            "slot 3: slot fault",
+        ]);
+
+        let (errors, warnings) = job_changer_faults(&status, 0, usable_media);
+        assert_eq!(errors, vec!["drive 0: drive fault", "slot 1: slot fault"]);
+        assert_eq!(warnings.len(), 3);
+    }
+}
@@ -1,41 +1,56 @@
 use std::collections::HashMap;
 use std::path::Path;
 
-use anyhow::Error;
+use anyhow::{format_err, Error};
 use serde_json::Value;
 
-use proxmox::api::{api, Router, SubdirMap, RpcEnvironment, Permission};
+use proxmox::api::{api, Router, SubdirMap, RpcEnvironment, RpcEnvironmentType, Permission};
 use proxmox::list_subdirs_api_method;
 
 use crate::{
+    task_log,
+    task_warn,
     config::{
         self,
         cached_user_info::CachedUserInfo,
         acl::{
             PRIV_TAPE_AUDIT,
+            PRIV_TAPE_MODIFY,
             PRIV_TAPE_READ,
         },
     },
+    server::WorkerTask,
     api2::types::{
         Authid,
         CHANGER_NAME_SCHEMA,
+        ChangerElementKind,
+        ChangerElementStatus,
+        ChangerHealthReport,
         ChangerListEntry,
+        ChangerMoveEvent,
         LtoTapeDrive,
         MtxEntryKind,
         MtxStatusEntry,
         ScsiTapeChanger,
+        UPID_SCHEMA,
     },
     tape::{
         TAPE_STATUS_DIR,
         Inventory,
+        MediaStateDatabase,
         linux_tape_changer_list,
         changer::{
             OnlineStatusMap,
             ElementStatus,
             ScsiMediaChange,
             mtx_status_to_online_set,
+            record_changer_move,
+        },
+        drive::{
+            get_tape_device_state,
+            lock_tape_device,
+            set_tape_device_state,
         },
-        drive::get_tape_device_state,
         lookup_device_identification,
     },
 };
@@ -144,6 +159,216 @@ pub async fn get_status(
     Ok(list)
 }
 
+fn element_volume_tag(status: &ElementStatus) -> (bool, Option<String>) {
+    match status {
+        ElementStatus::Empty => (false, None),
+        ElementStatus::Full => (true, None),
+        ElementStatus::VolumeTag(tag) => (true, Some(tag.to_string())),
+    }
+}
+
+#[api(
+    input: {
+        properties: {
+            name: {
+                schema: CHANGER_NAME_SCHEMA,
+            },
+            cache: {
+                description: "Use cached value.",
+                optional: true,
+                default: true,
+            },
+        },
+    },
+    returns: {
+        description: "A status entry for each changer element.",
+        type: Array,
+        items: {
+            type: ChangerElementStatus,
+        },
+    },
+    access: {
+        permission: &Permission::Privilege(&["tape", "device", "{name}"], PRIV_TAPE_AUDIT, false),
+    },
+)]
+/// Get the physical element status of a tape changer
+///
+/// Unlike the changer status, this does not update or use the media
+/// inventory, and also lists transport elements.
+pub async fn get_element_status(
+    name: String,
+    cache: bool,
+) -> Result<Vec<ChangerElementStatus>, Error> {
+
+    let (config, _digest) = config::drive::config()?;
+
+    let mut changer_config: ScsiTapeChanger = config.lookup("changer", &name)?;
+
+    let status = tokio::task::spawn_blocking(move || {
+        changer_config.status(cache)
+    }).await??;
+
+    let mut list = Vec::new();
+
+    for (id, drive_status) in status.drives.iter().enumerate() {
+        let (full, volume_tag) = element_volume_tag(&drive_status.status);
+        list.push(ChangerElementStatus {
+            element_kind: ChangerElementKind::Drive,
+            element_address: drive_status.element_address as u64,
+            entry_id: id as u64,
+            full,
+            volume_tag,
+            loaded_slot: drive_status.loaded_slot,
+        });
+    }
+
+    for (id, slot_info) in status.slots.iter().enumerate() {
+        let (full, volume_tag) = element_volume_tag(&slot_info.status);
+        list.push(ChangerElementStatus {
+            element_kind: if slot_info.import_export {
+                ChangerElementKind::ImportExport
+            } else {
+                ChangerElementKind::Slot
+            },
+            element_address: slot_info.element_address as u64,
+            entry_id: id as u64 + 1,
+            full,
+            volume_tag,
+            loaded_slot: None,
+        });
+    }
+
+    for (id, transport) in status.transports.iter().enumerate() {
+        let (full, volume_tag) = element_volume_tag(&transport.status);
+        list.push(ChangerElementStatus {
+            element_kind: ChangerElementKind::Transport,
+            element_address: transport.element_address as u64,
+            entry_id: id as u64,
+            full,
+            volume_tag,
+            loaded_slot: None,
+        });
+    }
+
+    Ok(list)
+}
+
+#[api(
+    input: {
+        properties: {
+            name: {
+                schema: CHANGER_NAME_SCHEMA,
+            },
+        },
+    },
+    returns: {
+        type: ChangerHealthReport,
+    },
+    access: {
+        permission: &Permission::Privilege(&["tape", "device", "{name}"], PRIV_TAPE_AUDIT, false),
+    },
+)]
+/// Get the faults reported for the changer drives and slots
+///
+/// This uses the cached changer status, so it does not move the robot.
+/// Use the POST method to rescan all elements first.
+pub async fn get_health(name: String) -> Result<ChangerHealthReport, Error> {
+
+    let (config, _digest) = config::drive::config()?;
+
+    let mut changer_config: ScsiTapeChanger = config.lookup("changer", &name)?;
+
+    let status = tokio::task::spawn_blocking(move || {
+        changer_config.status(true)
+    }).await??;
+
+    Ok(status.health_report())
+}
+
+#[api(
+    input: {
+        properties: {
+            name: {
+                schema: CHANGER_NAME_SCHEMA,
+            },
+        },
+    },
+    returns: {
+        schema: UPID_SCHEMA,
+    },
+    access: {
+        permission: &Permission::Privilege(&["tape", "device", "{name}"], PRIV_TAPE_MODIFY, false),
+    },
+)]
+/// Rescan the changer drives and slots and check them for faults
+///
+/// This runs INITIALIZE ELEMENT STATUS, which moves the robot and can
+/// take several minutes, so all drives of the changer are locked.
+pub fn health_check(
+    name: String,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<String, Error> {
+
+    let (config, _digest) = config::drive::config()?;
+
+    let mut changer_config: ScsiTapeChanger = config.lookup("changer", &name)?;
+
+    // early check/lock before starting worker
+    let drive_list: Vec<LtoTapeDrive> = config.convert_to_typed_array("lto")?;
+    let mut drives = Vec::new();
+    let mut lock_guards = Vec::new();
+    for drive in drive_list {
+        if drive.changer.as_deref() != Some(&name) {
+            continue;
+        }
+        lock_guards.push(lock_tape_device(&config, &drive.name)?);
+        drives.push(drive.name);
+    }
+
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+    let to_stdout = rpcenv.env_type() == RpcEnvironmentType::CLI;
+
+    let upid_str = WorkerTask::new_thread(
+        "changer-health",
+        Some(name.clone()),
+        auth_id,
+        to_stdout,
+        move |worker| {
+            let _lock_guards = lock_guards;
+            for drive in drives.iter() {
+                set_tape_device_state(drive, &worker.upid().to_string())
+                    .map_err(|err| format_err!("could not set tape device state: {}", err))?;
+            }
+
+            task_log!(worker, "rescan elements of changer '{}'", name);
+            let result = changer_config.health_check();
+
+            for drive in drives.iter() {
+                set_tape_device_state(drive, "")
+                    .map_err(|err| format_err!("could not unset tape device state: {}", err))?;
+            }
+
+            let report = result?;
+            task_log!(
+                worker,
+                "{} drives and {} slots ok",
+                report.drives_ok.len(),
+                report.slots_ok.len(),
+            );
+            for fault in report.drives_fault.iter() {
+                task_warn!(worker, "drive {}: {}", fault.entry_id, fault.fault);
+            }
+            for fault in report.slots_fault.iter() {
+                task_warn!(worker, "slot {}: {}", fault.entry_id, fault.fault);
+            }
+
+            Ok(())
+        },
+    )?;
+
+    Ok(upid_str)
+}
+
 #[api(
     input: {
         properties: {
@@ -169,18 +394,68 @@ pub async fn transfer(
     name: String,
     from: u64,
     to: u64,
+    rpcenv: &mut dyn RpcEnvironment,
 ) -> Result<(), Error> {
 
+    let auth_id: Authid = rpcenv.get_auth_id().unwrap().parse()?;
+
     let (config, _digest) = config::drive::config()?;
 
     let mut changer_config: ScsiTapeChanger = config.lookup("changer", &name)?;
 
     tokio::task::spawn_blocking(move || {
-        changer_config.transfer(from, to)?;
+        let status = changer_config.transfer(from, to)?;
+        record_changer_move(
+            &name, status.slot_label_text(to), Some(from), Some(to), None, Some(&auth_id));
         Ok(())
     }).await?
 }
 
+#[api(
+    input: {
+        properties: {
+            name: {
+                schema: CHANGER_NAME_SCHEMA,
+            },
+            since: {
+                description: "Only list events since this time (epoch).",
+                type: i64,
+                optional: true,
+            },
+            limit: {
+                description: "Maximum number of returned events.",
+                type: u64,
+                optional: true,
+                minimum: 1,
+            },
+        },
+    },
+    returns: {
+        description: "Media move events, newest first.",
+        type: Array,
+        items: {
+            type: ChangerMoveEvent,
+        },
+    },
+    access: {
+        permission: &Permission::Privilege(&["tape", "device", "{name}"], PRIV_TAPE_AUDIT, false),
+    },
+)]
+/// Get the media move history of a tape changer
+pub fn get_history(
+    name: String,
+    since: Option<i64>,
+    limit: Option<u64>,
+) -> Result<Vec<ChangerMoveEvent>, Error> {
+
+    let (config, _digest) = config::drive::config()?;
+    let _changer_config: ScsiTapeChanger = config.lookup("changer", &name)?;
+
+    let db = MediaStateDatabase::new(Path::new(TAPE_STATUS_DIR));
+
+    db.list_moves(Some(&name), since, limit.map(|limit| limit as usize))
+}
+
 #[api(
     input: {
         properties: {},
@@ -227,6 +502,22 @@ pub fn list_changers(
 }
 
 const SUBDIRS: SubdirMap = &[
+    (
+        "element-status",
+        &Router::new()
+            .get(&API_METHOD_GET_ELEMENT_STATUS)
+    ),
+    (
+        "health",
+        &Router::new()
+            .get(&API_METHOD_GET_HEALTH)
+            .post(&API_METHOD_HEALTH_CHECK)
+    ),
+    (
+        "history",
+        &Router::new()
+            .get(&API_METHOD_GET_HISTORY)
+    ),
     (
         "status",
         &Router::new()
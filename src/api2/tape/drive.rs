@@ -24,6 +24,7 @@ use proxmox::{
 
 use crate::{
     task_log,
+    task_warn,
     config::{
         self,
         cached_user_info::CachedUserInfo,
@@ -72,6 +73,7 @@ use crate::{
         },
         drive::{
             TapeDriver,
+            MediaEncryption,
             LtoTapeHandle,
             open_lto_tape_device,
             media_changer,
@@ -86,6 +88,9 @@ use crate::{
     },
 };
 
+// how long the eject-media API waits for the drive to report no media
+const EJECT_CONFIRM_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60);
+
 fn run_drive_worker<F>(
     rpcenv: &dyn RpcEnvironment,
     drive: String,
@@ -463,12 +468,29 @@ pub fn eject_media(
         drive.clone(),
         "eject-media",
         Some(drive.clone()),
-        move |_worker, config| {
+        move |worker, config| {
+            let eject_and_wait = |handle: &mut Box<dyn TapeDriver>| -> Result<(), Error> {
+                if !handle.eject_and_wait(EJECT_CONFIRM_TIMEOUT)? {
+                    task_warn!(
+                        worker,
+                        "drive '{}' still reports media {:?} after eject",
+                        drive,
+                        EJECT_CONFIRM_TIMEOUT,
+                    );
+                }
+                Ok(())
+            };
+
             if let Some((mut changer, _)) = media_changer(&config, &drive)? {
+                // make sure the tape is out before the changer moves it
+                // (opening the drive fails if there is no media)
+                if let Ok(mut handle) = open_drive(&config, &drive) {
+                    eject_and_wait(&mut handle)?;
+                }
                 changer.unload_media(None)?;
             } else {
-                let mut drive = open_drive(&config, &drive)?;
-                drive.eject_media()?;
+                let mut handle = open_drive(&config, &drive)?;
+                eject_and_wait(&mut handle)?;
             }
             Ok(())
         },
@@ -697,36 +719,21 @@ pub async fn read_label(
         move |config| {
             let mut drive = open_drive(&config, &drive)?;
 
-            let (media_id, _key_config) = drive.read_label()?;
+            let (media_id, encryption) = drive.read_label_with_encryption()?;
 
             let media_id = match media_id {
                 Some(media_id) => {
-                    let mut flat = MediaIdFlat {
-                        uuid: media_id.label.uuid.clone(),
-                        label_text: media_id.label.label_text.clone(),
-                        ctime: media_id.label.ctime,
-                        media_set_ctime: None,
-                        media_set_uuid: None,
-                        encryption_key_fingerprint: None,
-                        pool: None,
-                        seq_nr: None,
-                    };
-                    if let Some(ref set) = media_id.media_set_label {
-                        flat.pool = Some(set.pool.clone());
-                        flat.seq_nr = Some(set.seq_nr);
-                        flat.media_set_uuid = Some(set.uuid.clone());
-                        flat.media_set_ctime = Some(set.ctime);
-                        flat.encryption_key_fingerprint = set
-                            .encryption_key_fingerprint
-                            .as_ref()
-                            .map(|fp| crate::tools::format::as_fingerprint(fp.bytes()));
-
+                    let mut flat = media_id_flat(&media_id);
+                    if let Err(err) = encryption.check_key_available() {
+                        flat.encryption_key_error = Some(err.to_string());
+                    } else if let Some(ref set) = media_id.media_set_label {
                         let encrypt_fingerprint = set.encryption_key_fingerprint.clone()
                             .map(|fp| (fp, set.uuid.clone()));
 
+                        // try, but do not fail - reading the label works without the key
                         if let Err(err) = drive.set_encryption(encrypt_fingerprint) {
-                            // try, but ignore errors. just log to stderr
-                            eprintln!("unable to load encryption key: {}", err);
+                            flat.encryption_key_error =
+                                Some(format!("unable to load encryption key: {}", err));
                         }
                     }
 
@@ -759,6 +766,86 @@ pub async fn read_label(
     .await
 }
 
+fn media_id_flat(media_id: &MediaId) -> MediaIdFlat {
+    let mut flat = MediaIdFlat {
+        uuid: media_id.label.uuid.clone(),
+        label_text: media_id.label.label_text.clone(),
+        ctime: media_id.label.ctime,
+        media_set_ctime: None,
+        media_set_uuid: None,
+        encryption_key_fingerprint: None,
+        encryption_key_error: None,
+        pool: None,
+        seq_nr: None,
+    };
+    if let Some(ref set) = media_id.media_set_label {
+        flat.pool = Some(set.pool.clone());
+        flat.seq_nr = Some(set.seq_nr);
+        flat.media_set_uuid = Some(set.uuid.clone());
+        flat.media_set_ctime = Some(set.ctime);
+        flat.encryption_key_fingerprint = set
+            .encryption_key_fingerprint
+            .as_ref()
+            .map(|fp| crate::tools::format::as_fingerprint(fp.bytes()));
+    }
+    flat
+}
+
+#[api(
+    input: {
+        properties: {
+            drive: {
+                schema: DRIVE_NAME_SCHEMA,
+            },
+            "source-slot": {
+                description: "Source slot number.",
+                minimum: 1,
+            },
+        },
+    },
+    returns: {
+        type: MediaIdFlat,
+    },
+    access: {
+        permission: &Permission::Privilege(&["tape", "device", "{drive}"], PRIV_TAPE_READ, false),
+    },
+)]
+/// Load media from the specified slot and read its labels
+///
+/// Does the same as 'load-slot' followed by 'read-label', but keeps the
+/// drive locked in between.
+pub async fn load_and_read_label(drive: String, source_slot: u64) -> Result<MediaIdFlat, Error> {
+    run_drive_blocking_task(
+        drive.clone(),
+        format!("load from slot {} and read label", source_slot),
+        move |config| {
+            let (mut changer, _) = required_media_changer(&config, &drive)?;
+            changer.load_media_from_slot(source_slot)?;
+
+            // opening the drive waits until it is ready
+            let mut handle = open_drive(&config, &drive)?;
+
+            match handle.read_label() {
+                Ok((Some(media_id), key_config)) => {
+                    let mut flat = media_id_flat(&media_id);
+                    let encryption = MediaEncryption::from_media_id(&media_id, key_config.as_ref())?;
+                    if let Err(err) = encryption.check_key_available() {
+                        flat.encryption_key_error = Some(err.to_string());
+                    }
+                    Ok(flat)
+                }
+                Ok((None, _)) => bail!("media from slot {} is empty (no label)", source_slot),
+                Err(err) => bail!(
+                    "unable to read label of media from slot {} (unrelated data?) - {}",
+                    source_slot,
+                    err,
+                ),
+            }
+        },
+    )
+    .await
+}
+
 #[api(
     input: {
         properties: {
@@ -822,6 +909,77 @@ pub fn clean_drive(
     Ok(upid_str.into())
 }
 
+#[api(
+    input: {
+        properties: {
+            drive: {
+                schema: DRIVE_NAME_SCHEMA,
+            },
+        },
+    },
+    returns: {
+        schema: UPID_SCHEMA,
+    },
+    access: {
+        permission: &Permission::Privilege(&["tape", "device", "{drive}"], PRIV_TAPE_WRITE, false),
+    },
+)]
+/// Calibrate the locate offset of the drive
+///
+/// This temporarily writes some filemarks after the end of data of
+/// the loaded media, so it is only allowed on blank media or scratch
+/// media (labeled, but not assigned to a media set). The result is
+/// cached per drive and media.
+pub fn calibrate_drive(
+    drive: String,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<Value, Error> {
+    let upid_str = run_drive_worker(
+        rpcenv,
+        drive.clone(),
+        "calibrate-drive",
+        Some(drive.clone()),
+        move |worker, config| {
+            let drive_config: LtoTapeDrive = config.lookup("lto", &drive)?;
+            let mut handle = drive_config.open()?;
+
+            let media_uuid = match handle.read_label()? {
+                (Some(media_id), _) => {
+                    if let Some(ref set) = media_id.media_set_label {
+                        if set.uuid.as_ref() != [0u8;16] { // media is not empty
+                            bail!(
+                                "refusing to calibrate on media '{}' - media is part of media set {} \
+                                 (use blank or scratch media)",
+                                media_id.label.label_text,
+                                set.uuid,
+                            );
+                        }
+                    }
+                    task_log!(
+                        worker,
+                        "calibrating locate offset for media '{}' ({})",
+                        media_id.label.label_text,
+                        media_id.label.uuid,
+                    );
+                    Some(media_id.label.uuid)
+                }
+                (None, _) => {
+                    task_log!(worker, "calibrating locate offset on blank media");
+                    None
+                }
+            };
+
+            let offset = handle.calibrate_locate_offset(media_uuid.as_ref())?;
+
+            task_log!(worker, "locate offset: {}", offset);
+
+            Ok(())
+        },
+    )?;
+
+    Ok(upid_str.into())
+}
+
 #[api(
     input: {
         properties: {
@@ -1183,6 +1341,12 @@ pub async fn volume_statistics(drive: String) -> Result<Lp17VolumeStatistics, Er
             drive: {
                 schema: DRIVE_NAME_SCHEMA,
             },
+            temperature: {
+                description: "Also read the drive temperature (needs an additional SCSI command).",
+                type: bool,
+                optional: true,
+                default: false,
+            },
         },
     },
     returns: {
@@ -1193,7 +1357,9 @@ pub async fn volume_statistics(drive: String) -> Result<Lp17VolumeStatistics, Er
     },
 )]
 /// Get drive/media status
-pub async fn status(drive: String) -> Result<LtoDriveAndMediaStatus, Error> {
+pub async fn status(drive: String, temperature: Option<bool>) -> Result<LtoDriveAndMediaStatus, Error> {
+    let temperature = temperature.unwrap_or(false);
+
     run_drive_blocking_task(
         drive.clone(),
         "reading drive status".to_string(),
@@ -1205,7 +1371,7 @@ pub async fn status(drive: String) -> Result<LtoDriveAndMediaStatus, Error> {
 
             let mut handle = LtoTapeHandle::new(file)?;
 
-            handle.get_drive_and_media_status()
+            handle.get_drive_and_media_status(temperature)
         }
     )
     .await
@@ -1275,6 +1441,8 @@ pub fn catalog_media(
                             serde_json::to_string_pretty(&serde_json::to_value(&key_config)?)?
                         ));
                     }
+                    MediaEncryption::from_media_id(&media_id, key_config.as_ref())?
+                        .check_key_available()?;
                     media_id
                 },
                 (None, _) => bail!("media is empty (no media label found)"),
@@ -1409,6 +1577,11 @@ pub const SUBDIRS: SubdirMap = &sorted!([
         &Router::new()
             .post(&API_METHOD_BARCODE_LABEL_MEDIA)
     ),
+    (
+        "calibrate",
+        &Router::new()
+            .post(&API_METHOD_CALIBRATE_DRIVE)
+    ),
     (
         "catalog",
         &Router::new()
@@ -1445,6 +1618,11 @@ pub const SUBDIRS: SubdirMap = &sorted!([
         &Router::new()
             .post(&API_METHOD_LABEL_MEDIA)
     ),
+    (
+        "load-and-read-label",
+        &Router::new()
+            .post(&API_METHOD_LOAD_AND_READ_LABEL)
+    ),
     (
         "load-media",
         &Router::new()
@@ -4,6 +4,7 @@ use std::collections::{HashMap, HashSet, BTreeMap};
 use std::convert::TryFrom;
 use std::io::{Seek, SeekFrom};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use anyhow::{bail, format_err, Error};
 use serde_json::Value;
@@ -95,6 +96,7 @@ use crate::{
         },
         drive::{
             TapeDriver,
+            MediaEncryption,
             request_and_load_media,
             lock_tape_device,
             set_tape_device_state,
@@ -552,6 +554,7 @@ fn restore_list_worker(
                 &drive_config,
                 &drive_name,
                 &media_id.label,
+                media_id.media_set_label.as_ref(),
                 &email,
             )?;
             file_list.sort_unstable();
@@ -604,6 +607,7 @@ fn restore_list_worker(
                 &drive_config,
                 &drive_name,
                 &media_id.label,
+                media_id.media_set_label.as_ref(),
                 &email,
             )?;
             restore_file_chunk_map(worker.clone(), &mut drive, &store_map, file_chunk_map)?;
@@ -711,6 +715,8 @@ fn restore_snapshots_to_tmpdir(
                     media_set_uuid
                 );
             }
+            MediaEncryption::from_media_id(&media_id, None)?.check_key_available()?;
+
             let encrypt_fingerprint = set.encryption_key_fingerprint.clone().map(|fp| {
                 task_log!(worker, "Encryption key fingerprint: {}", fp);
                 (fp, set.uuid.clone())
@@ -929,7 +935,14 @@ pub fn request_and_restore_media(
         Some(ref set) => &set.uuid,
     };
 
-    let (mut drive, info) = request_and_load_media(&worker, &drive_config, &drive_name, &media_id.label, email)?;
+    let (mut drive, info) = request_and_load_media(
+        &worker,
+        &drive_config,
+        &drive_name,
+        &media_id.label,
+        media_id.media_set_label.as_ref(),
+        email,
+    )?;
 
     match info.media_set_label {
         None => {
@@ -942,6 +955,8 @@ pub fn request_and_restore_media(
                       media_id.label.label_text, media_id.label.uuid,
                       media_set_uuid);
             }
+            MediaEncryption::from_media_id(&media_id, None)?.check_key_available()?;
+
             let encrypt_fingerprint = set.encryption_key_fingerprint.clone()
                 .map(|fp| (fp, set.uuid.clone()));
 
@@ -959,6 +974,9 @@ pub fn request_and_restore_media(
     )
 }
 
+// how often restore_media logs the estimated tape position
+const PROGRESS_LOG_INTERVAL: Duration = Duration::from_secs(10);
+
 /// Restore complete media content and catalog
 ///
 /// Only create the catalog if target is None.
@@ -974,7 +992,16 @@ pub fn restore_media(
     let status_path = Path::new(TAPE_STATUS_DIR);
     let mut catalog = MediaCatalog::create_temporary_database(status_path, media_id, false)?;
 
+    let mut last_progress = Instant::now();
+
     loop {
+        if last_progress.elapsed() >= PROGRESS_LOG_INTERVAL {
+            if let Ok(Some(percent)) = drive.estimated_position_percent() {
+                task_log!(worker, "estimated tape position: {:.1}%", percent);
+            }
+            last_progress = Instant::now();
+        }
+
         let current_file_number = drive.current_file_number()?;
         let reader = match drive.read_next_file() {
             Err(BlockReadError::EndOfFile) => {
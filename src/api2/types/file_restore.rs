@@ -13,3 +13,14 @@ pub struct RestoreDaemonStatus {
     pub timeout: i64,
 }
 
+
+#[api()]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// An alternate data stream (NTFS) of a file
+pub struct AdsEntry {
+    /// stream name
+    pub name: String,
+    /// stream size in bytes
+    pub size: u64,
+}
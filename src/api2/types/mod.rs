@@ -445,6 +445,12 @@ pub const SYNC_SCHEDULE_SCHEMA: Schema = StringSchema::new(
     .type_text("<calendar-event>")
     .schema();
 
+pub const CHUNK_DIR_FAN_OUT_SCHEMA: Schema = StringSchema::new(
+    "Chunk directory fan-out, number of digest bits used for each directory level (default '16').")
+    .format(&ApiStringFormat::VerifyFn(crate::backup::verify_chunk_dir_fan_out))
+    .type_text("<bits>[:<bits>[:<bits>]]")
+    .schema();
+
 pub const GC_SCHEDULE_SCHEMA: Schema = StringSchema::new(
     "Run garbage collection job at specified schedule.")
     .format(&ApiStringFormat::VerifyFn(crate::tools::systemd::time::verify_calendar_event))
@@ -480,6 +486,27 @@ pub const REMOVE_VANISHED_BACKUPS_SCHEMA: Schema = BooleanSchema::new(
     .default(true)
     .schema();
 
+pub const PULL_CHECK_CONCURRENCY_SCHEMA: Schema = IntegerSchema::new(
+    "Number of concurrent local chunk existence checks during sync.")
+    .minimum(1)
+    .maximum(256)
+    .default(32)
+    .schema();
+
+pub const PULL_DOWNLOAD_CONCURRENCY_SCHEMA: Schema = IntegerSchema::new(
+    "Number of concurrent chunk downloads during sync.")
+    .minimum(1)
+    .maximum(256)
+    .default(20)
+    .schema();
+
+pub const PULL_GROUP_CONCURRENCY_SCHEMA: Schema = IntegerSchema::new(
+    "Number of backup groups synced concurrently.")
+    .minimum(1)
+    .maximum(32)
+    .default(1)
+    .schema();
+
 pub const IGNORE_VERIFIED_BACKUPS_SCHEMA: Schema = BooleanSchema::new(
     "Do not verify backups that are already verified if their verification is not outdated.")
     .default(true)
@@ -750,12 +777,29 @@ pub struct BackupContent {
     pub size: Option<u64>,
 }
 
+#[api()]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// Garbage collection phase.
+pub enum GarbageCollectionPhase {
+    /// Phase 1, mark chunks referenced by index files.
+    Marking,
+    /// Phase 2, remove unused chunks.
+    Sweeping,
+    /// Garbage collection finished.
+    Done,
+}
+
 #[api(
     properties: {
         "upid": {
             optional: true,
             schema: UPID_SCHEMA,
         },
+        "phase": {
+            type: GarbageCollectionPhase,
+            optional: true,
+        },
     },
 )]
 #[derive(Clone, Serialize, Deserialize)]
@@ -763,6 +807,9 @@ pub struct BackupContent {
 /// Garbage collection status.
 pub struct GarbageCollectionStatus {
     pub upid: Option<String>,
+    /// Phase of the garbage collection (not recorded by older versions).
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub phase: Option<GarbageCollectionPhase>,
     /// Number of processed index files.
     pub index_file_count: usize,
     /// Sum of bytes referred by index files.
@@ -789,6 +836,7 @@ impl Default for GarbageCollectionStatus {
     fn default() -> Self {
         GarbageCollectionStatus {
             upid: None,
+            phase: None,
             index_file_count: 0,
             index_data_bytes: 0,
             disk_bytes: 0,
@@ -815,6 +863,35 @@ pub struct StorageStatus {
     pub avail: u64,
 }
 
+#[api()]
+#[derive(Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// Storage space and inode usage information.
+pub struct StorageStatusFull {
+    /// Total space (bytes).
+    pub total: u64,
+    /// Used space (bytes).
+    pub used: u64,
+    /// Available space (bytes).
+    pub avail: u64,
+    /// Total number of inodes (0 if the file system does not report them).
+    pub inodes_total: u64,
+    /// Used inodes.
+    pub inodes_used: u64,
+    /// Free inodes.
+    pub inodes_free: u64,
+}
+
+impl From<StorageStatusFull> for StorageStatus {
+    fn from(status: StorageStatusFull) -> Self {
+        Self {
+            total: status.total,
+            used: status.used,
+            avail: status.avail,
+        }
+    }
+}
+
 #[api()]
 #[derive(Serialize, Deserialize, Default)]
 /// Backup Type group/snapshot counts.
@@ -886,6 +963,9 @@ pub struct DataStoreStatus {
     /// Group/Snapshot counts
     #[serde(skip_serializing_if="Option::is_none")]
     pub counts: Option<Counts>,
+    /// Failed chunk insert verifications since the datastore was opened (only with verify-on-insert)
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub insert_verify_mismatches: Option<u64>,
 }
 
 #[api(
@@ -1594,6 +1674,19 @@ pub struct NodeSwapCounters {
     pub free: u64,
 }
 
+#[api]
+#[derive(Serialize, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+/// Node (root file system) inode usage counters
+pub struct NodeInodeCounters {
+    /// Total inodes
+    pub total: u64,
+    /// Used inodes
+    pub used: u64,
+    /// Free inodes
+    pub free: u64,
+}
+
 #[api]
 #[derive(Serialize,Deserialize,Default)]
 #[serde(rename_all = "kebab-case")]
@@ -1624,6 +1717,9 @@ pub struct NodeCpuInformation {
         root: {
             type: StorageStatus,
         },
+        "root-inodes": {
+            type: NodeInodeCounters,
+        },
         swap: {
             type: NodeSwapCounters,
         },
@@ -1648,6 +1744,7 @@ pub struct NodeCpuInformation {
 pub struct NodeStatus {
     pub memory: NodeMemoryCounters,
     pub root: StorageStatus,
+    pub root_inodes: NodeInodeCounters,
     pub swap: NodeSwapCounters,
     /// The current uptime of the server.
     pub uptime: u64,
@@ -1673,3 +1770,66 @@ pub const HTTP_PROXY_SCHEMA: Schema = StringSchema::new(
     .max_length(128)
     .type_text("[http://]<host>[:port]")
     .schema();
+
+#[api(
+    default: "On",
+)]
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// The ZFS compression algorithm to use.
+pub enum ZfsCompressionType {
+    /// Gnu Zip
+    Gzip,
+    /// LZ4
+    Lz4,
+    /// LZJB
+    Lzjb,
+    /// ZLE
+    Zle,
+    /// Zstandard
+    Zstd,
+    /// Enable compression using the default algorithm.
+    On,
+    /// Disable compression.
+    Off,
+}
+
+/// ZFS properties recommended for datastores
+///
+/// Backups never need the access time, and storing xattrs in the inode
+/// avoids extra IO for the ACLs/xattrs of the chunk store directories.
+pub const ZFS_DATASTORE_PROPERTIES: &[(&str, &str)] = &[("atime", "off"), ("xattr", "sa")];
+
+#[api(
+    properties: {
+        compression: {
+            type: ZfsCompressionType,
+        },
+    },
+)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// Options for a ZFS dataset used as datastore.
+pub struct ZfsDatasetOptions {
+    /// Quota (bytes)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quota: Option<u64>,
+    /// Reservation (bytes)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reservation: Option<u64>,
+    pub compression: ZfsCompressionType,
+    /// Mountpoint (defaults to '/mnt/datastore/<dataset name>')
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mountpoint: Option<String>,
+}
+
+impl Default for ZfsDatasetOptions {
+    fn default() -> Self {
+        Self {
+            quota: None,
+            reservation: None,
+            compression: ZfsCompressionType::Zstd,
+            mountpoint: None,
+        }
+    }
+}
@@ -14,6 +14,7 @@ use proxmox::api::{
 };
 
 use crate::api2::types::{
+    Authid,
     PROXMOX_SAFE_ID_FORMAT,
     OptionalDeviceIdentification,
 };
@@ -133,3 +134,154 @@ pub struct MtxStatusEntry {
     #[serde(skip_serializing_if="Option::is_none")]
     pub state: Option<String>,
 }
+
+#[api()]
+#[derive(Serialize,Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// Changer Element Kind
+pub enum ChangerElementKind {
+    /// Data transfer element (drive)
+    Drive,
+    /// Storage element (slot)
+    Slot,
+    /// Import/Export element
+    ImportExport,
+    /// Medium transport element (robot arm)
+    Transport,
+}
+
+#[api(
+    properties: {
+        "element-kind": {
+            type: ChangerElementKind,
+        },
+        "volume-tag": {
+            schema: MEDIA_LABEL_SCHEMA,
+            optional: true,
+        },
+    },
+)]
+#[derive(Serialize,Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// Changer Element Status
+///
+/// The raw element status as reported by the changer, without
+/// looking at the media inventory or the drives.
+pub struct ChangerElementStatus {
+    pub element_kind: ChangerElementKind,
+    /// The SCSI element address
+    pub element_address: u64,
+    /// The drive number, slot number or transport index
+    pub entry_id: u64,
+    /// The element contains a media
+    pub full: bool,
+    /// The volume tag (barcode), if the changer reports one
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub volume_tag: Option<String>,
+    /// The slot the drive was loaded from
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub loaded_slot: Option<u64>,
+}
+
+#[api()]
+#[derive(Serialize,Deserialize,Clone,Debug,PartialEq)]
+#[serde(rename_all = "kebab-case")]
+/// A changer element reporting a fault
+pub struct ChangerElementFault {
+    /// The drive or slot number
+    pub entry_id: u64,
+    /// Fault description (from the additional sense code)
+    pub fault: String,
+}
+
+#[api(
+    properties: {
+        "drives-ok": {
+            type: Array,
+            items: {
+                type: Integer,
+                description: "Drive number.",
+            },
+        },
+        "drives-fault": {
+            type: Array,
+            items: {
+                type: ChangerElementFault,
+            },
+        },
+        "slots-ok": {
+            type: Array,
+            items: {
+                type: Integer,
+                description: "Slot number.",
+            },
+        },
+        "slots-fault": {
+            type: Array,
+            items: {
+                type: ChangerElementFault,
+            },
+        },
+    },
+)]
+#[derive(Serialize,Deserialize,Default,Debug)]
+#[serde(rename_all = "kebab-case")]
+/// Changer health check result
+pub struct ChangerHealthReport {
+    pub drives_ok: Vec<u64>,
+    pub drives_fault: Vec<ChangerElementFault>,
+    pub slots_ok: Vec<u64>,
+    pub slots_fault: Vec<ChangerElementFault>,
+}
+
+impl ChangerHealthReport {
+
+    /// Returns true if any drive or slot reports a fault
+    pub fn has_faults(&self) -> bool {
+        !self.drives_fault.is_empty() || !self.slots_fault.is_empty()
+    }
+
+    /// List all faults as text (one element per line)
+    pub fn fault_text(&self) -> String {
+        let mut list = Vec::new();
+        for item in self.drives_fault.iter() {
+            list.push(format!("drive {}: {}", item.entry_id, item.fault));
+        }
+        for item in self.slots_fault.iter() {
+            list.push(format!("slot {}: {}", item.entry_id, item.fault));
+        }
+        list.join("\n")
+    }
+}
+
+#[api(
+    properties: {
+        "operator-auth-id": {
+            type: Authid,
+            optional: true,
+        },
+    },
+)]
+#[derive(Serialize,Deserialize,Clone,Debug,PartialEq)]
+#[serde(rename_all = "kebab-case")]
+/// Media move event (changer history)
+pub struct ChangerMoveEvent {
+    /// Time of the move (epoch)
+    pub timestamp: i64,
+    /// Changer name
+    pub changer: String,
+    /// Media label text (barcode), empty if unknown
+    pub media_label: String,
+    /// Source slot (None if moved out of a drive)
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub from_slot: Option<u64>,
+    /// Target slot (None if loaded into a drive)
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub to_slot: Option<u64>,
+    /// Drive name (for load and unload)
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub drive: Option<String>,
+    /// User or token that requested the move (if known)
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub operator_auth_id: Option<Authid>,
+}
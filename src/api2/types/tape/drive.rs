@@ -197,12 +197,27 @@ pub struct LtoDriveAndMediaStatus {
     /// Tape Alert Flags
     #[serde(skip_serializing_if="Option::is_none")]
     pub alert_flags: Option<String>,
+    /// Drive requests a cleaning cycle
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub needs_cleaning: Option<bool>,
     /// Current file number
     #[serde(skip_serializing_if="Option::is_none")]
     pub file_number: Option<u64>,
     /// Current block number
     #[serde(skip_serializing_if="Option::is_none")]
     pub block_number: Option<u64>,
+    /// Estimated position in percent of the written data
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub position_percent: Option<f64>,
+    /// Remaining capacity of the current partition (bytes, from Cartridge Memory)
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub remaining_capacity: Option<u64>,
+    /// Used part of the current partition (0.0 to 1.0)
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub fill_ratio: Option<f64>,
+    /// Drive temperature (degrees Celsius)
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub temperature: Option<u8>,
     /// Medium Manufacture Date (epoch)
     #[serde(skip_serializing_if="Option::is_none")]
     pub manufactured: Option<i64>,
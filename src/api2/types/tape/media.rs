@@ -116,6 +116,9 @@ pub struct MediaIdFlat {
     /// Encryption key fingerprint
     #[serde(skip_serializing_if="Option::is_none")]
     pub encryption_key_fingerprint: Option<String>,
+    /// Why the encryption key cannot be used (e.g. because it is not configured)
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub encryption_key_error: Option<String>,
 }
 
 #[api(
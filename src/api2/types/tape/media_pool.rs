@@ -4,7 +4,7 @@
 //! so we cannot use them directly for the API. Instead, we represent
 //! them as String.
 
-use anyhow::Error;
+use anyhow::{bail, Error};
 use std::str::FromStr;
 use serde::{Deserialize, Serialize};
 
@@ -80,7 +80,7 @@ pub const MEDIA_RETENTION_POLICY_FORMAT: ApiStringFormat =
     ApiStringFormat::VerifyFn(|s| { RetentionPolicy::from_str(s)?; Ok(()) });
 
 pub const MEDIA_RETENTION_POLICY_SCHEMA: Schema = StringSchema::new(
-    "Media retention policy ('overwrite', 'keep', or time span).")
+    "Media retention policy ('overwrite', 'keep', 'sets:<count>', or time span).")
     .format(&MEDIA_RETENTION_POLICY_FORMAT)
     .schema();
 
@@ -92,6 +92,8 @@ pub enum RetentionPolicy {
     ProtectFor(TimeSpan),
     /// Never overwrite data
     KeepForever,
+    /// Keep the specified number of media sets (the newest ones)
+    KeepMediaSets(u32),
 }
 
 impl std::str::FromStr for RetentionPolicy {
@@ -104,6 +106,13 @@ impl std::str::FromStr for RetentionPolicy {
         if s == "keep" {
             return Ok(RetentionPolicy::KeepForever);
         }
+        if let Some(count) = s.strip_prefix("sets:") {
+            let count: u32 = match count.parse() {
+                Ok(count) if count > 0 => count,
+                _ => bail!("invalid media set count '{}' (expected a number greater than 0)", count),
+            };
+            return Ok(RetentionPolicy::KeepMediaSets(count));
+        }
 
         let time_span = parse_time_span(s)?;
 
@@ -384,13 +384,10 @@ fn list_backup_files<P: ?Sized + nix::NixPath>(
 ) -> Result<Vec<String>, Error> {
     let mut files = vec![];
 
-    tools::scandir(dirfd, path, &BACKUP_FILE_REGEX, |_, filename, file_type| {
-        if file_type != nix::dir::Type::File {
-            return Ok(());
-        }
-        files.push(filename.to_owned());
-        Ok(())
-    })?;
+    for entry in tools::fs::scan_subdir_typed(dirfd, path, &BACKUP_FILE_REGEX, nix::dir::Type::File)? {
+        let entry = entry?;
+        files.push(unsafe { entry.file_name_utf8_unchecked() }.to_owned());
+    }
 
     Ok(files)
 }
@@ -3,13 +3,18 @@ use std::ffi::{CStr, CString, OsStr};
 use std::fmt;
 use std::io::{Read, Write, Seek, SeekFrom};
 use std::os::unix::ffi::OsStrExt;
+use std::path::{Component, Path};
 
 use anyhow::{bail, format_err, Error};
 
 use pathpatterns::{MatchList, MatchType};
 use proxmox::tools::io::ReadExt;
 
-use crate::backup::file_formats::PROXMOX_CATALOG_FILE_MAGIC_1_0;
+use crate::backup::file_formats::{
+    PROXMOX_CATALOG_FILE_MAGIC_1_0,
+    PROXMOX_CATALOG_FILE_MAGIC_1_1,
+    PROXMOX_CATALOG_FILE_MAGIC_1_2,
+};
 use crate::pxar::catalog::BackupCatalogWriter;
 
 #[repr(u8)]
@@ -78,7 +83,7 @@ pub struct DirEntry {
 #[derive(Clone, Debug, PartialEq)]
 pub enum DirEntryAttribute {
     Directory { start: u64 },
-    File { size: u64, mtime: i64 },
+    File { size: u64, mtime: i64, csum: Option<[u8; 32]> },
     Symlink,
     Hardlink,
     BlockDevice,
@@ -89,13 +94,20 @@ pub enum DirEntryAttribute {
 
 impl DirEntry {
 
-    fn new(etype: CatalogEntryType, name: Vec<u8>, start: u64, size: u64, mtime: i64) -> Self {
+    fn new(
+        etype: CatalogEntryType,
+        name: Vec<u8>,
+        start: u64,
+        size: u64,
+        mtime: i64,
+        csum: Option<[u8; 32]>,
+    ) -> Self {
         match etype {
             CatalogEntryType::Directory => {
                 DirEntry { name, attr: DirEntryAttribute::Directory { start } }
             }
             CatalogEntryType::File => {
-                DirEntry { name, attr: DirEntryAttribute::File { size, mtime } }
+                DirEntry { name, attr: DirEntryAttribute::File { size, mtime, csum } }
             }
             CatalogEntryType::Symlink => {
                 DirEntry { name, attr: DirEntryAttribute::Symlink }
@@ -146,6 +158,40 @@ impl DirEntry {
     }
 }
 
+// Catalog format features, derived from the magic number
+#[derive(Copy, Clone)]
+struct CatalogFormat {
+    // v1.1: directory blocks are followed by a lookup table
+    lookup_table: bool,
+    // v1.2: file entries may include a sha256 checksum of the content
+    file_checksums: bool,
+}
+
+impl CatalogFormat {
+
+    fn from_magic(magic: &[u8; 8]) -> Result<Self, Error> {
+        if magic == &PROXMOX_CATALOG_FILE_MAGIC_1_2 {
+            Ok(Self { lookup_table: true, file_checksums: true })
+        } else if magic == &PROXMOX_CATALOG_FILE_MAGIC_1_1 {
+            Ok(Self { lookup_table: true, file_checksums: false })
+        } else if magic == &PROXMOX_CATALOG_FILE_MAGIC_1_0 {
+            Ok(Self { lookup_table: false, file_checksums: false })
+        } else {
+            bail!("got unexpected magic number for catalog");
+        }
+    }
+
+    fn magic(&self) -> &'static [u8; 8] {
+        if self.file_checksums {
+            &PROXMOX_CATALOG_FILE_MAGIC_1_2
+        } else if self.lookup_table {
+            &PROXMOX_CATALOG_FILE_MAGIC_1_1
+        } else {
+            &PROXMOX_CATALOG_FILE_MAGIC_1_0
+        }
+    }
+}
+
 struct DirInfo {
     name: CString,
     entries: Vec<DirEntry>,
@@ -165,6 +211,7 @@ impl DirInfo {
         writer: &mut W,
         entry: &DirEntry,
         pos: u64,
+        format: CatalogFormat,
     ) -> Result<(), Error> {
         match entry {
             DirEntry { name, attr: DirEntryAttribute::Directory { start } } => {
@@ -173,12 +220,21 @@ impl DirInfo {
                 writer.write_all(name)?;
                 catalog_encode_u64(writer, pos - start)?;
             }
-            DirEntry { name, attr: DirEntryAttribute::File { size, mtime } } => {
+            DirEntry { name, attr: DirEntryAttribute::File { size, mtime, csum } } => {
                 writer.write_all(&[CatalogEntryType::File as u8])?;
                 catalog_encode_u64(writer, name.len() as u64)?;
                 writer.write_all(name)?;
                 catalog_encode_u64(writer, *size)?;
                 catalog_encode_i64(writer, *mtime)?;
+                if format.file_checksums {
+                    match csum {
+                        Some(csum) => {
+                            writer.write_all(&[1u8])?;
+                            writer.write_all(csum)?;
+                        }
+                        None => writer.write_all(&[0u8])?,
+                    }
+                }
             }
             DirEntry { name, attr: DirEntryAttribute::Symlink } => {
                 writer.write_all(&[CatalogEntryType::Symlink as u8])?;
@@ -214,22 +270,80 @@ impl DirInfo {
         Ok(())
     }
 
-    fn encode(self, start: u64) -> Result<(CString, Vec<u8>), Error> {
+    fn encode(self, start: u64, format: CatalogFormat) -> Result<(CString, Vec<u8>), Error> {
         let mut table = Vec::new();
+        let mut hash_list = Vec::new();
         catalog_encode_u64(&mut table, self.entries.len() as u64)?;
         for entry in self.entries {
-            Self::encode_entry(&mut table, &entry, start)?;
+            if format.lookup_table {
+                hash_list.push((pxar::format::hash_filename(&entry.name), table.len() as u64));
+            }
+            Self::encode_entry(&mut table, &entry, start, format)?;
         }
 
         let mut data = Vec::new();
         catalog_encode_u64(&mut data, table.len() as u64)?;
         data.extend_from_slice(&table);
 
+        if format.lookup_table {
+            let tree = build_lookup_tree(hash_list);
+            catalog_encode_u64(&mut data, tree.len() as u64)?;
+            for (hash, offset) in tree {
+                data.extend_from_slice(&hash.to_le_bytes());
+                data.extend_from_slice(&offset.to_le_bytes());
+            }
+        }
+
         Ok((self.name, data))
     }
 
-    fn parse<C: FnMut(CatalogEntryType, &[u8], u64, u64, i64) -> Result<bool, Error>>(
+    #[allow(clippy::type_complexity)]
+    fn read_entry<'a, R: Read>(
+        reader: &mut R,
+        name_buf: &'a mut [u8],
+        format: CatalogFormat,
+    ) -> Result<(CatalogEntryType, &'a [u8], u64, u64, i64, Option<[u8; 32]>), Error> {
+
+        let mut buf = [ 0u8 ];
+        reader.read_exact(&mut buf)?;
+        let etype = CatalogEntryType::try_from(buf[0])?;
+
+        let name_len = catalog_decode_u64(reader)? as usize;
+        if name_len >= name_buf.len() {
+            bail!("directory entry name too long ({} >= {})", name_len, name_buf.len());
+        }
+        let name = &mut name_buf[0..name_len];
+        reader.read_exact(name)?;
+
+        let (offset, size, mtime, csum) = match etype {
+            CatalogEntryType::Directory => (catalog_decode_u64(reader)?, 0, 0, None),
+            CatalogEntryType::File => {
+                let size = catalog_decode_u64(reader)?;
+                let mtime = catalog_decode_i64(reader)?;
+                let mut csum = None;
+                if format.file_checksums {
+                    reader.read_exact(&mut buf)?;
+                    match buf[0] {
+                        0 => (),
+                        1 => {
+                            let mut data = [0u8; 32];
+                            reader.read_exact(&mut data)?;
+                            csum = Some(data);
+                        }
+                        flag => bail!("invalid file checksum flag {}", flag),
+                    }
+                }
+                (0, size, mtime, csum)
+            }
+            _ => (0, 0, 0, None),
+        };
+
+        Ok((etype, name, offset, size, mtime, csum))
+    }
+
+    fn parse<C: FnMut(CatalogEntryType, &[u8], u64, u64, i64, Option<[u8; 32]>) -> Result<bool, Error>>(
         data: &[u8],
+        format: CatalogFormat,
         mut callback: C,
     ) -> Result<(), Error> {
 
@@ -241,32 +355,10 @@ impl DirInfo {
 
         for _ in 0..entries {
 
-            let mut buf = [ 0u8 ];
-            cursor.read_exact(&mut buf)?;
-            let etype = CatalogEntryType::try_from(buf[0])?;
-
-            let name_len = catalog_decode_u64(&mut cursor)? as usize;
-            if name_len >= name_buf.len() {
-                bail!("directory entry name too long ({} >= {})", name_len, name_buf.len());
-            }
-            let name = &mut name_buf[0..name_len];
-            cursor.read_exact(name)?;
+            let (etype, name, offset, size, mtime, csum) =
+                Self::read_entry(&mut cursor, &mut name_buf, format)?;
 
-            let cont = match etype {
-                CatalogEntryType::Directory => {
-                    let offset = catalog_decode_u64(&mut cursor)?;
-                    callback(etype, name, offset, 0, 0)?
-                }
-                CatalogEntryType::File => {
-                    let size = catalog_decode_u64(&mut cursor)?;
-                    let mtime = catalog_decode_i64(&mut cursor)?;
-                    callback(etype, name, 0, size, mtime)?
-                }
-                _ => {
-                    callback(etype, name, 0, 0, 0)?
-                }
-            };
-            if !cont {
+            if !callback(etype, name, offset, size, mtime, csum)? {
                 return Ok(());
             }
         }
@@ -279,24 +371,79 @@ impl DirInfo {
     }
 }
 
+// Build a binary search tree (in Eytzinger layout, children of node k
+// are 2k+1 and 2k+2) from a list of (filename hash, entry offset)
+fn build_lookup_tree(mut list: Vec<(u64, u64)>) -> Vec<(u64, u64)> {
+
+    fn fill_tree(
+        tree: &mut Vec<(u64, u64)>,
+        k: usize,
+        iter: &mut impl Iterator<Item = (u64, u64)>,
+    ) {
+        if k >= tree.len() {
+            return;
+        }
+        fill_tree(tree, 2 * k + 1, iter);
+        tree[k] = iter.next().unwrap();
+        fill_tree(tree, 2 * k + 2, iter);
+    }
+
+    list.sort_unstable_by_key(|(hash, _)| *hash);
+
+    let mut tree = vec![(0, 0); list.len()];
+    fill_tree(&mut tree, 0, &mut list.into_iter());
+
+    tree
+}
+
 /// Write small catalog files
 ///
 /// A Catalogs simply contains list of files and directories
 /// (directory tree). They are use to find content without having to
 /// search the real archive (which may be large). For files, they
 /// include the last modification time and file size.
+///
+/// Format version 1.1 (opt-in, see `with_lookup_table`) follows each
+/// directory block with a binary search tree over the filename hashes
+/// (same hash as used by the pxar goodbye table), so that lookups do not
+/// need to parse the whole directory.
+///
+/// Format version 1.2 (opt-in, see `with_file_checksums`) additionally
+/// stores an optional sha256 checksum of the file content.
 pub struct CatalogWriter<W> {
     writer: W,
     dirstack: Vec<DirInfo>,
     pos: u64,
+    format: CatalogFormat,
 }
 
 impl <W: Write> CatalogWriter<W> {
 
     /// Create a new  CatalogWriter instance
+    ///
+    /// This writes format version 1.0, readable by all clients.
     pub fn new(writer: W) -> Result<Self, Error> {
-        let mut me = Self { writer, dirstack: vec![ DirInfo::new_rootdir() ], pos: 0 };
-        me.write_all(&PROXMOX_CATALOG_FILE_MAGIC_1_0)?;
+        Self::with_format(writer, CatalogFormat { lookup_table: false, file_checksums: false })
+    }
+
+    /// Create a new CatalogWriter instance which adds directory lookup tables
+    ///
+    /// This writes format version 1.1, which older clients cannot read.
+    pub fn with_lookup_table(writer: W) -> Result<Self, Error> {
+        Self::with_format(writer, CatalogFormat { lookup_table: true, file_checksums: false })
+    }
+
+    /// Create a new CatalogWriter instance which also records file checksums
+    ///
+    /// This writes format version 1.2 (including lookup tables), which older
+    /// clients cannot read.
+    pub fn with_file_checksums(writer: W) -> Result<Self, Error> {
+        Self::with_format(writer, CatalogFormat { lookup_table: true, file_checksums: true })
+    }
+
+    fn with_format(writer: W, format: CatalogFormat) -> Result<Self, Error> {
+        let mut me = Self { writer, dirstack: vec![ DirInfo::new_rootdir() ], pos: 0, format };
+        me.write_all(format.magic())?;
         Ok(me)
     }
 
@@ -317,7 +464,7 @@ impl <W: Write> CatalogWriter<W> {
         let dir = self.dirstack.pop().unwrap();
 
         let start = self.pos;
-        let (_, data) = dir.encode(start)?;
+        let (_, data) = dir.encode(start, self.format)?;
         self.write_all(&data)?;
 
         self.write_all(&start.to_le_bytes())?;
@@ -340,7 +487,7 @@ impl <W: Write> BackupCatalogWriter for CatalogWriter<W> {
         let (start, name) = match self.dirstack.pop() {
             Some(dir) => {
                 let start = self.pos;
-                let (name, data) = dir.encode(start)?;
+                let (name, data) = dir.encode(start, self.format)?;
                 self.write_all(&data)?;
                 (start, name)
             }
@@ -359,10 +506,27 @@ impl <W: Write> BackupCatalogWriter for CatalogWriter<W> {
     fn add_file(&mut self, name: &CStr, size: u64, mtime: i64) -> Result<(), Error> {
         let dir = self.dirstack.last_mut().ok_or_else(|| format_err!("outside root"))?;
         let name = name.to_bytes().to_vec();
-        dir.entries.push(DirEntry { name, attr: DirEntryAttribute::File { size, mtime } });
+        dir.entries.push(DirEntry { name, attr: DirEntryAttribute::File { size, mtime, csum: None } });
         Ok(())
     }
 
+    fn file_checksums(&self) -> bool {
+        self.format.file_checksums
+    }
+
+    fn set_file_checksum(&mut self, name: &CStr, checksum: &[u8; 32]) -> Result<(), Error> {
+        let dir = self.dirstack.last_mut().ok_or_else(|| format_err!("outside root"))?;
+        match dir.entries.last_mut() {
+            Some(DirEntry { name: entry_name, attr: DirEntryAttribute::File { csum, .. } })
+                if entry_name.as_slice() == name.to_bytes() =>
+            {
+                *csum = Some(*checksum);
+                Ok(())
+            }
+            _ => bail!("unable to set checksum for {:?} - not the last added file", name),
+        }
+    }
+
     fn add_symlink(&mut self, name: &CStr) -> Result<(), Error> {
         let dir = self.dirstack.last_mut().ok_or_else(|| format_err!("outside root"))?;
         let name = name.to_bytes().to_vec();
@@ -409,13 +573,35 @@ impl <W: Write> BackupCatalogWriter for CatalogWriter<W> {
 /// Read Catalog files
 pub struct CatalogReader<R> {
     reader: R,
+    format: Option<CatalogFormat>,
 }
 
 impl <R: Read + Seek> CatalogReader<R> {
 
     /// Create a new CatalogReader instance
     pub fn new(reader: R) -> Self {
-        Self { reader }
+        Self { reader, format: None }
+    }
+
+    // check the format version (v1.1 has lookup tables, v1.2 file checksums)
+    fn format(&mut self) -> Result<CatalogFormat, Error> {
+        if let Some(format) = self.format {
+            return Ok(format);
+        }
+
+        self.reader.seek(SeekFrom::Start(0))?;
+        let mut magic = [ 0u8; 8];
+        self.reader.read_exact(&mut magic)?;
+
+        let format = CatalogFormat::from_magic(&magic)?;
+        self.format = Some(format);
+
+        Ok(format)
+    }
+
+    /// Returns true if the catalog may contain file checksums
+    pub fn has_file_checksums(&mut self) -> Result<bool, Error> {
+        Ok(self.format()?.file_checksums)
     }
 
     /// Print whole catalog to stdout
@@ -433,12 +619,7 @@ impl <R: Read + Seek> CatalogReader<R> {
     /// Get the root DirEntry
     pub fn root(&mut self) ->  Result<DirEntry, Error>  {
         // Root dir is special
-        self.reader.seek(SeekFrom::Start(0))?;
-        let mut magic = [ 0u8; 8];
-        self.reader.read_exact(&mut magic)?;
-        if magic != PROXMOX_CATALOG_FILE_MAGIC_1_0 {
-            bail!("got unexpected magic number for catalog");
-        }
+        self.format()?;
         self.reader.seek(SeekFrom::End(-8))?;
         let start = unsafe { self.reader.read_le_value::<u64>()? };
         Ok(DirEntry { name: b"".to_vec(), attr: DirEntryAttribute::Directory { start } })
@@ -455,12 +636,13 @@ impl <R: Read + Seek> CatalogReader<R> {
             _ => bail!("parent is not a directory - internal error"),
         };
 
+        let format = self.format()?;
         let data = self.read_raw_dirinfo_block(start)?;
 
         let mut entry_list = Vec::new();
 
-        DirInfo::parse(&data, |etype, name, offset, size, mtime| {
-            let entry = DirEntry::new(etype, name.to_vec(), start - offset, size, mtime);
+        DirInfo::parse(&data, format, |etype, name, offset, size, mtime, csum| {
+            let entry = DirEntry::new(etype, name.to_vec(), start - offset, size, mtime, csum);
             entry_list.push(entry);
             Ok(true)
         })?;
@@ -494,6 +676,32 @@ impl <R: Read + Seek> CatalogReader<R> {
         Ok(current)
     }
 
+    /// Lookup a DirEntry from a path (relative to the root directory)
+    ///
+    /// Returns `None` if the path does not exist.
+    pub fn lookup_path(&mut self, path: &Path) -> Result<Option<DirEntry>, Error> {
+        let mut current = self.root()?;
+
+        for component in path.components() {
+            let name = match component {
+                Component::RootDir | Component::CurDir => continue,
+                Component::Normal(name) => name,
+                _ => bail!("unsupported path component in {:?}", path),
+            };
+
+            if !current.is_directory() {
+                return Ok(None);
+            }
+
+            match self.lookup(&current, name.as_bytes())? {
+                Some(entry) => current = entry,
+                None => return Ok(None),
+            }
+        }
+
+        Ok(Some(current))
+    }
+
     /// Lockup a DirEntry inside a parent directory
     pub fn lookup(
         &mut self,
@@ -506,15 +714,72 @@ impl <R: Read + Seek> CatalogReader<R> {
             _ => bail!("parent is not a directory - internal error"),
         };
 
+        let format = self.format()?;
+        if format.lookup_table {
+            self.lookup_hashed(start, filename, format)
+        } else {
+            self.lookup_linear(start, filename, format)
+        }
+    }
+
+    // Search the lookup table (binary search tree) of directory block 'start'
+    fn lookup_hashed(
+        &mut self,
+        start: u64,
+        filename: &[u8],
+        format: CatalogFormat,
+    ) -> Result<Option<DirEntry>, Error> {
+        self.reader.seek(SeekFrom::Start(start))?;
+        let table_size = catalog_decode_u64(&mut self.reader)?;
+        let table_start = self.reader.seek(SeekFrom::Current(0))?;
+
+        self.reader.seek(SeekFrom::Start(table_start + table_size))?;
+        let count = catalog_decode_u64(&mut self.reader)?;
+        let tree_start = self.reader.seek(SeekFrom::Current(0))?;
+
+        let hash = pxar::format::hash_filename(filename);
+
+        let mut k = 0;
+        while k < count {
+            self.reader.seek(SeekFrom::Start(tree_start + k * 16))?;
+            let node_hash = unsafe { self.reader.read_le_value::<u64>()? };
+            let entry_offset = unsafe { self.reader.read_le_value::<u64>()? };
+
+            if node_hash == hash {
+                self.reader.seek(SeekFrom::Start(table_start + entry_offset))?;
+                let mut name_buf = vec![0u8; 4096];
+                let (etype, name, offset, size, mtime, csum) =
+                    DirInfo::read_entry(&mut self.reader, &mut name_buf, format)?;
+                if name == filename {
+                    let entry = DirEntry::new(etype, name.to_vec(), start - offset, size, mtime, csum);
+                    return Ok(Some(entry));
+                }
+                // hash collision (very unlikely), simply scan the whole directory
+                return self.lookup_linear(start, filename, format);
+            }
+
+            k = if hash < node_hash { 2 * k + 1 } else { 2 * k + 2 };
+        }
+
+        Ok(None)
+    }
+
+    fn lookup_linear(
+        &mut self,
+        start: u64,
+        filename: &[u8],
+        format: CatalogFormat,
+    ) -> Result<Option<DirEntry>, Error> {
+
         let data = self.read_raw_dirinfo_block(start)?;
 
         let mut item = None;
-        DirInfo::parse(&data, |etype, name, offset, size, mtime| {
+        DirInfo::parse(&data, format, |etype, name, offset, size, mtime, csum| {
             if name != filename {
                 return Ok(true);
             }
 
-            let entry = DirEntry::new(etype, name.to_vec(), start - offset, size, mtime);
+            let entry = DirEntry::new(etype, name.to_vec(), start - offset, size, mtime, csum);
             item = Some(entry);
             Ok(false) // stop parsing
         })?;
@@ -534,9 +799,10 @@ impl <R: Read + Seek> CatalogReader<R> {
     /// Print the content of a directory to stdout
     pub fn dump_dir(&mut self, prefix: &std::path::Path, start: u64) -> Result<(), Error> {
 
+        let format = self.format()?;
         let data = self.read_raw_dirinfo_block(start)?;
 
-        DirInfo::parse(&data, |etype, name, offset, size, mtime| {
+        DirInfo::parse(&data, format, |etype, name, offset, size, mtime, csum| {
 
             let mut path = std::path::PathBuf::from(prefix);
             let name: &OsStr = OsStrExt::from_bytes(name);
@@ -557,13 +823,23 @@ impl <R: Read + Seek> CatalogReader<R> {
                         mtime_string = s;
                     }
 
-                    println!(
-                        "{} {:?} {} {}",
-                        etype,
-                        path,
-                        size,
-                        mtime_string,
-                    );
+                    match csum {
+                        Some(csum) => println!(
+                            "{} {:?} {} {} {}",
+                            etype,
+                            path,
+                            size,
+                            mtime_string,
+                            proxmox::tools::digest_to_hex(&csum),
+                        ),
+                        None => println!(
+                            "{} {:?} {} {}",
+                            etype,
+                            path,
+                            size,
+                            mtime_string,
+                        ),
+                    }
                 }
                 _ => {
                     println!("{} {:?}", etype, path);
@@ -793,3 +1069,95 @@ fn test_catalog_i64_compatibility() {
     test_encode_decode((1<<50)-1);
     test_encode_decode(u64::MAX);
 }
+
+#[test]
+fn test_catalog_lookup_table() -> Result<(), Error> {
+
+    fn create_catalog(lookup_table: bool) -> Result<Vec<u8>, Error> {
+        let mut writer = if lookup_table {
+            CatalogWriter::with_lookup_table(Vec::new())?
+        } else {
+            CatalogWriter::new(Vec::new())?
+        };
+        for i in 0..10 {
+            writer.start_directory(&CString::new(format!("dir{}", i))?)?;
+            for j in 0..1000 {
+                writer.add_file(&CString::new(format!("file{}", j))?, j, i as i64)?;
+            }
+            writer.add_symlink(&CString::new("link")?)?;
+            writer.end_directory()?;
+        }
+        writer.add_file(&CString::new("file")?, 42, 0)?;
+        writer.finish()?;
+        Ok(writer.writer)
+    }
+
+    for lookup_table in [true, false].iter() {
+        let data = create_catalog(*lookup_table)?;
+        let mut reader = CatalogReader::new(std::io::Cursor::new(data));
+
+        let entry = reader.lookup_path(Path::new("/dir7/file123"))?.unwrap();
+        assert_eq!(entry.name, b"file123");
+        assert!(matches!(entry.attr, DirEntryAttribute::File { size: 123, mtime: 7, csum: None }));
+
+        let entry = reader.lookup_path(Path::new("dir3"))?.unwrap();
+        assert!(entry.is_directory());
+        assert_eq!(reader.read_dir(&entry)?.len(), 1001);
+
+        assert!(reader.lookup_path(Path::new("dir9/link"))?.unwrap().is_symlink());
+        assert!(reader.lookup_path(Path::new("/"))?.unwrap().is_directory());
+
+        assert!(reader.lookup_path(Path::new("/dir1/file1000"))?.is_none());
+        assert!(reader.lookup_path(Path::new("/dir10"))?.is_none());
+        // files have no children
+        assert!(reader.lookup_path(Path::new("/file/file1"))?.is_none());
+
+        assert_eq!(reader.lookup_recursive(b"/dir2/file2")?.name, b"file2");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_catalog_file_checksums() -> Result<(), Error> {
+
+    let csum = openssl::sha::sha256(b"file content");
+
+    let mut writer = CatalogWriter::with_file_checksums(Vec::new())?;
+    assert!(writer.file_checksums());
+    writer.start_directory(&CString::new("dir")?)?;
+    writer.add_file(&CString::new("with-csum")?, 12, 1)?;
+    writer.set_file_checksum(&CString::new("with-csum")?, &csum)?;
+    writer.add_file(&CString::new("without-csum")?, 0, 2)?;
+    // only the last added file can get a checksum
+    assert!(writer.set_file_checksum(&CString::new("with-csum")?, &csum).is_err());
+    writer.add_symlink(&CString::new("link")?)?;
+    writer.end_directory()?;
+    writer.finish()?;
+
+    let mut reader = CatalogReader::new(std::io::Cursor::new(writer.writer));
+    assert!(reader.has_file_checksums()?);
+
+    let entry = reader.lookup_path(Path::new("/dir/with-csum"))?.unwrap();
+    assert_eq!(entry.attr, DirEntryAttribute::File { size: 12, mtime: 1, csum: Some(csum) });
+
+    let entry = reader.lookup_path(Path::new("/dir/without-csum"))?.unwrap();
+    assert_eq!(entry.attr, DirEntryAttribute::File { size: 0, mtime: 2, csum: None });
+
+    let dir = reader.lookup_path(Path::new("/dir"))?.unwrap();
+    assert_eq!(reader.read_dir(&dir)?.len(), 3);
+
+    // the default format (v1.0) does not store checksums
+    let mut writer = CatalogWriter::new(Vec::new())?;
+    assert!(!writer.file_checksums());
+    writer.add_file(&CString::new("file")?, 12, 1)?;
+    writer.set_file_checksum(&CString::new("file")?, &csum)?;
+    writer.finish()?;
+
+    let mut reader = CatalogReader::new(std::io::Cursor::new(writer.writer));
+    assert!(!reader.has_file_checksums()?);
+    let entry = reader.lookup_path(Path::new("/file"))?.unwrap();
+    assert_eq!(entry.attr, DirEntryAttribute::File { size: 12, mtime: 1, csum: None });
+
+    Ok(())
+}
@@ -39,6 +39,18 @@ impl <R: Read> ChecksumReader<R> {
             Ok((self.reader, crc, None))
         }
     }
+
+    /// Access the underlying reader
+    ///
+    /// Data read this way is not included in the checksum.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    /// Restart the CRC computation, e.g. after repositioning the underlying reader
+    pub fn reset_crc(&mut self) {
+        self.hasher = crc32fast::Hasher::new();
+    }
 }
 
 impl <R: Read> Read for ChecksumReader<R> {
@@ -2,25 +2,81 @@ use anyhow::{bail, format_err, Error};
 
 use std::path::{Path, PathBuf};
 use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
 use std::os::unix::io::AsRawFd;
 
-use proxmox::tools::fs::{CreateOptions, create_path, create_dir};
+use proxmox::tools::fs::{CreateOptions, create_path, create_dir, file_read_optional_string, replace_file};
 
 use crate::task_log;
 use crate::tools;
-use crate::api2::types::GarbageCollectionStatus;
+use crate::api2::types::{CryptMode, GarbageCollectionStatus};
 
-use super::DataBlob;
+use super::{DataBlob, ReadChunk};
 use crate::task::TaskState;
 
+// chunk inserts (all stores, since process start) which found the chunk already stored
+static CHUNK_INSERT_HITS: AtomicU64 = AtomicU64::new(0);
+// chunk inserts which had to write a new chunk file
+static CHUNK_INSERT_MISSES: AtomicU64 = AtomicU64::new(0);
+
+/// Returns the number of chunk inserts `(hits, misses)` in this process.
+///
+/// A hit is a chunk which was already stored (deduplicated), a miss a newly written chunk.
+pub fn chunk_insert_stats() -> (u64, u64) {
+    (
+        CHUNK_INSERT_HITS.load(Ordering::Relaxed),
+        CHUNK_INSERT_MISSES.load(Ordering::Relaxed),
+    )
+}
+
+/// Chunk insert errors callers may want to handle (use `downcast_ref`)
+#[derive(thiserror::Error, Debug)]
+pub enum InsertError {
+    /// The written chunk file does not contain the expected data
+    #[error("verification of chunk {digest} on store '{store}' failed - {reason}")]
+    VerificationFailed { store: String, digest: String, reason: String },
+}
+
+/// Modifies chunk data right before it is written, to simulate write errors in tests
+#[cfg(test)]
+type WriteInterceptor = fn(&mut Vec<u8>);
+
+/// Result of a chunk repair (see `ChunkStore::repair_chunks`)
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RepairReport {
+    /// Chunks replaced by a verified copy
+    pub repaired: u64,
+    /// Chunks where the copy was invalid (or could not be stored)
+    pub still_missing: u64,
+    /// Chunks the source could not provide
+    pub remote_also_missing: u64,
+}
+
+impl std::fmt::Display for RepairReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} repaired, {} still missing, {} also missing on remote",
+            self.repaired, self.still_missing, self.remote_also_missing,
+        )
+    }
+}
+
 /// File system based chunk store
 pub struct ChunkStore {
     name: String, // used for error reporting
     pub (crate) base: PathBuf,
     chunk_dir: PathBuf,
+    fan_out: ChunkDirFanOut,
+    fan_out_state: ChunkDirFanOutState,
+    fan_out_stamp: Option<(u64, i64, i64)>,
     mutex: Mutex<()>,
     locker: Arc<Mutex<tools::ProcessLocker>>,
+    verify_on_insert: bool,
+    mismatches_since_start: AtomicU64,
+    #[cfg(test)]
+    write_interceptor: Option<WriteInterceptor>,
 }
 
 // TODO: what about sysctl setting vm.vfs_cache_pressure (0 - 100) ?
@@ -35,21 +91,163 @@ pub fn verify_chunk_size(size: usize) -> Result<(), Error> {
     Ok(())
 }
 
-fn digest_to_prefix(digest: &[u8]) -> PathBuf {
+/// Number of digest bits used for each chunk directory level
+///
+/// Every level uses a multiple of 4 bits (one hex digit per 4 bits), with
+/// at most 16 bits per level and 24 bits in total. The default is a single
+/// level using the first 16 bits (`.chunks/XXXX/<digest>`), which is the
+/// layout of all chunk stores created before this was configurable.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChunkDirFanOut {
+    OneLevel(u8),
+    TwoLevel(u8, u8),
+    ThreeLevel(u8, u8, u8),
+}
+
+impl Default for ChunkDirFanOut {
+    fn default() -> Self {
+        ChunkDirFanOut::OneLevel(16)
+    }
+}
+
+impl ChunkDirFanOut {
+
+    fn levels(&self) -> Vec<u8> {
+        match *self {
+            ChunkDirFanOut::OneLevel(a) => vec![a],
+            ChunkDirFanOut::TwoLevel(a, b) => vec![a, b],
+            ChunkDirFanOut::ThreeLevel(a, b, c) => vec![a, b, c],
+        }
+    }
+
+    /// Total number of digest bits used for the directory prefix.
+    pub fn total_bits(&self) -> u32 {
+        self.levels().iter().map(|bits| *bits as u32).sum()
+    }
 
-    let mut buf = Vec::<u8>::with_capacity(2+1+2+1);
+    /// Number of leaf directories (the ones containing chunk files).
+    pub fn leaf_dir_count(&self) -> usize {
+        1 << self.total_bits()
+    }
 
-    const HEX_CHARS: &[u8; 16] = b"0123456789abcdef";
+    pub fn verify(&self) -> Result<(), Error> {
+        for bits in self.levels() {
+            if bits == 0 || bits > 16 || bits % 4 != 0 {
+                bail!("invalid chunk directory level size '{}' (expected 4, 8, 12 or 16 bits)", bits);
+            }
+        }
+        if self.total_bits() > 24 {
+            bail!("chunk directory fan-out uses more than 24 bits");
+        }
+        Ok(())
+    }
 
-    buf.push(HEX_CHARS[(digest[0] as usize) >> 4]);
-    buf.push(HEX_CHARS[(digest[0] as usize) &0xf]);
-    buf.push(HEX_CHARS[(digest[1] as usize) >> 4]);
-    buf.push(HEX_CHARS[(digest[1] as usize) & 0xf]);
-    buf.push(b'/');
+    /// Relative path of the leaf directory with the given index.
+    pub fn leaf_dir(&self, index: usize) -> String {
+        let mut shift = self.total_bits();
+        let mut path = String::with_capacity(16);
+        for bits in self.levels() {
+            shift -= bits as u32;
+            let value = (index >> shift) & ((1 << bits) - 1);
+            if !path.is_empty() {
+                path.push('/');
+            }
+            path.push_str(&format!("{:0width$x}", value, width = (bits / 4) as usize));
+        }
+        path
+    }
 
-    let path = unsafe { String::from_utf8_unchecked(buf)};
+    /// Relative path of the leaf directory a chunk is stored in.
+    pub fn digest_to_prefix(&self, digest: &[u8]) -> String {
+        let value = u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]]);
+        self.leaf_dir((value >> (32 - self.total_bits())) as usize)
+    }
 
-    path.into()
+    /// Returns true if `path` (relative to the chunk directory) is a leaf directory.
+    pub fn is_leaf_dir(&self, path: &str) -> bool {
+        let levels = self.levels();
+        let components: Vec<&str> = path.split('/').collect();
+        components.len() == levels.len() &&
+            components.iter().zip(levels.iter()).all(|(component, bits)| {
+                component.len() == (*bits / 4) as usize &&
+                    component.bytes().all(|b| b.is_ascii_hexdigit())
+            })
+    }
+}
+
+impl std::str::FromStr for ChunkDirFanOut {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let levels = s.trim().split(':')
+            .map(|bits| bits.parse::<u8>()
+                .map_err(|err| format_err!("unable to parse chunk directory fan-out '{}' - {}", s, err)))
+            .collect::<Result<Vec<u8>, Error>>()?;
+
+        let fan_out = match levels[..] {
+            [a] => ChunkDirFanOut::OneLevel(a),
+            [a, b] => ChunkDirFanOut::TwoLevel(a, b),
+            [a, b, c] => ChunkDirFanOut::ThreeLevel(a, b, c),
+            _ => bail!("chunk directory fan-out '{}' has more than 3 levels", s),
+        };
+
+        fan_out.verify()?;
+
+        Ok(fan_out)
+    }
+}
+
+impl std::fmt::Display for ChunkDirFanOut {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let levels: Vec<String> = self.levels().iter().map(|bits| bits.to_string()).collect();
+        write!(f, "{}", levels.join(":"))
+    }
+}
+
+/// On-disk state of the chunk directory layout (stored in `.chunk-fan-out`)
+///
+/// A migration records both layouts before the first chunk gets moved, so an
+/// interrupted migration can be detected and resumed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChunkDirFanOutState {
+    Active(ChunkDirFanOut),
+    Migrating { from: ChunkDirFanOut, to: ChunkDirFanOut },
+}
+
+impl std::str::FromStr for ChunkDirFanOutState {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        match s.strip_prefix("migrating ") {
+            Some(layouts) => match layouts.split_whitespace().collect::<Vec<&str>>()[..] {
+                [from, to] => Ok(ChunkDirFanOutState::Migrating { from: from.parse()?, to: to.parse()? }),
+                _ => bail!("unable to parse chunk directory fan-out state '{}'", s),
+            },
+            None => Ok(ChunkDirFanOutState::Active(s.parse()?)),
+        }
+    }
+}
+
+impl std::fmt::Display for ChunkDirFanOutState {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ChunkDirFanOutState::Active(fan_out) => write!(f, "{}", fan_out),
+            ChunkDirFanOutState::Migrating { from, to } => write!(f, "migrating {} {}", from, to),
+        }
+    }
+}
+
+/// Verify function for the chunk directory fan-out property string.
+pub fn verify_chunk_dir_fan_out(value: &str) -> Result<(), Error> {
+    value.parse::<ChunkDirFanOut>().map(|_| ())
+}
+
+fn is_chunk_file_name(bytes: &[u8]) -> bool {
+    if bytes.len() != 64 && bytes.len() != 64 + ".0.bad".len() {
+        return false;
+    }
+    bytes.iter().take(64).all(u8::is_ascii_hexdigit)
 }
 
 impl ChunkStore {
@@ -62,13 +260,22 @@ impl ChunkStore {
         chunk_dir
     }
 
-    pub fn create<P>(name: &str, path: P, uid: nix::unistd::Uid, gid: nix::unistd::Gid, worker: Option<&dyn TaskState>) -> Result<Self, Error>
+    pub fn create<P>(
+        name: &str,
+        path: P,
+        uid: nix::unistd::Uid,
+        gid: nix::unistd::Gid,
+        fan_out: ChunkDirFanOut,
+        worker: Option<&dyn TaskState>,
+    ) -> Result<Self, Error>
     where
         P: Into<PathBuf>,
     {
 
         let base: PathBuf = path.into();
 
+        fan_out.verify()?;
+
         if !base.is_absolute() {
             bail!("expected absolute path - got {:?}", base);
         }
@@ -94,16 +301,19 @@ impl ChunkStore {
         let lockfile_path = Self::lockfile_path(&base);
         proxmox::tools::fs::replace_file(lockfile_path, b"", options.clone())?;
 
-        // create 64*1024 subdirs
+        Self::write_fan_out(&base, fan_out, options.clone())?;
+
+        // create all leaf subdirs (64*1024 with the default fan-out)
         let mut last_percentage = 0;
+        let leaf_dir_count = fan_out.leaf_dir_count();
 
-        for i in 0..64*1024 {
-            let mut l1path = chunk_dir.clone();
-            l1path.push(format!("{:04x}", i));
-            if let Err(err) = create_dir(&l1path, options.clone()) {
-                bail!("unable to create chunk store '{}' subdir {:?} - {}", name, l1path, err);
+        for i in 0..leaf_dir_count {
+            let mut leaf_path = chunk_dir.clone();
+            leaf_path.push(fan_out.leaf_dir(i));
+            if let Err(err) = create_path(&leaf_path, Some(options.clone()), Some(options.clone())) {
+                bail!("unable to create chunk store '{}' subdir {:?} - {}", name, leaf_path, err);
             }
-            let percentage = (i*100)/(64*1024);
+            let percentage = (i*100)/leaf_dir_count;
             if percentage != last_percentage {
                 if let Some(worker) = worker {
                     task_log!(worker, "Chunkstore create: {}%", percentage)
@@ -123,7 +333,53 @@ impl ChunkStore {
         lockfile_path
     }
 
+    fn fan_out_path<P: Into<PathBuf>>(base: P) -> PathBuf {
+        let mut fan_out_path: PathBuf = base.into();
+
+        fan_out_path.push(".chunk-fan-out");
+
+        fan_out_path
+    }
+
+    /// Read the chunk directory fan-out state from disk.
+    ///
+    /// Chunk stores without fan-out file use the default layout.
+    pub fn read_fan_out_state<P: Into<PathBuf>>(base: P) -> Result<ChunkDirFanOutState, Error> {
+        match file_read_optional_string(Self::fan_out_path(base))? {
+            Some(content) => content.parse(),
+            None => Ok(ChunkDirFanOutState::Active(ChunkDirFanOut::default())),
+        }
+    }
+
+    fn write_fan_out_state(base: &Path, state: ChunkDirFanOutState, options: CreateOptions) -> Result<(), Error> {
+        let content = format!("{}\n", state);
+        replace_file(Self::fan_out_path(base), content.as_bytes(), options)
+    }
+
+    fn write_fan_out(base: &Path, fan_out: ChunkDirFanOut, options: CreateOptions) -> Result<(), Error> {
+        Self::write_fan_out_state(base, ChunkDirFanOutState::Active(fan_out), options)
+    }
+
+    // identifies the fan-out file version, it gets replaced on every state change
+    fn read_fan_out_stamp(base: &Path) -> Result<Option<(u64, i64, i64)>, Error> {
+        match nix::sys::stat::stat(&Self::fan_out_path(base)) {
+            Ok(stat) => Ok(Some((stat.st_ino, stat.st_mtime, stat.st_mtime_nsec))),
+            Err(nix::Error::Sys(nix::errno::Errno::ENOENT)) => Ok(None),
+            Err(err) => bail!("unable to stat chunk directory fan-out file - {}", err),
+        }
+    }
+
+    /// Open the chunk store, fails while a fan-out migration is unfinished.
     pub fn open<P: Into<PathBuf>>(name: &str, base: P) -> Result<Self, Error> {
+        let chunk_store = Self::open_for_migration(name, base)?;
+        chunk_store.check_not_migrating()?;
+        Ok(chunk_store)
+    }
+
+    /// Open the chunk store, even if a fan-out migration is unfinished.
+    ///
+    /// Only use this to (re)start the migration, see `migrate_fan_out`.
+    pub fn open_for_migration<P: Into<PathBuf>>(name: &str, base: P) -> Result<Self, Error> {
 
         let base: PathBuf = base.into();
 
@@ -141,15 +397,65 @@ impl ChunkStore {
 
         let locker = tools::ProcessLocker::new(&lockfile_path)?;
 
+        let fan_out_stamp = Self::read_fan_out_stamp(&base)?;
+        let fan_out_state = Self::read_fan_out_state(&base)?;
+        let fan_out = match fan_out_state {
+            ChunkDirFanOutState::Active(fan_out) => fan_out,
+            ChunkDirFanOutState::Migrating { from, .. } => from,
+        };
+
         Ok(ChunkStore {
             name: name.to_owned(),
             base,
             chunk_dir,
+            fan_out,
+            fan_out_state,
+            fan_out_stamp,
             locker,
-            mutex: Mutex::new(())
+            mutex: Mutex::new(()),
+            verify_on_insert: false,
+            mismatches_since_start: AtomicU64::new(0),
+            #[cfg(test)]
+            write_interceptor: None,
         })
     }
 
+    /// Fails if the chunk store was opened during an unfinished fan-out migration.
+    pub fn check_not_migrating(&self) -> Result<(), Error> {
+        if let ChunkDirFanOutState::Migrating { from, to } = self.fan_out_state {
+            bail!(
+                "chunk store '{}' is being migrated from fan-out {} to {} - wait for the \
+                 migration to finish, or restart it with 'migrate-chunk-fanout'",
+                self.name, from, to,
+            );
+        }
+        Ok(())
+    }
+
+    /// Returns true if the fan-out file changed since the chunk store was opened.
+    ///
+    /// This only needs a `stat`, so it is cheap enough to check on every lookup.
+    pub fn fan_out_changed(&self) -> Result<bool, Error> {
+        Ok(Self::read_fan_out_stamp(&self.base)? != self.fan_out_stamp)
+    }
+
+    /// Read back and check newly written chunk files
+    ///
+    /// Chunks are synced to disk and dropped from the page cache before
+    /// reading them back. A mismatch fails the insert with `InsertError::VerificationFailed`.
+    pub fn set_verify_on_insert(&mut self, verify: bool) {
+        self.verify_on_insert = verify;
+    }
+
+    pub fn verify_on_insert(&self) -> bool {
+        self.verify_on_insert
+    }
+
+    /// Number of failed insert verifications since the store was opened
+    pub fn mismatches_since_start(&self) -> u64 {
+        self.mismatches_since_start.load(Ordering::Relaxed)
+    }
+
     pub fn touch_chunk(&self, digest: &[u8; 32]) -> Result<(), Error> {
         self.cond_touch_chunk(digest, true)?;
         Ok(())
@@ -207,6 +513,9 @@ impl ChunkStore {
                 )
             })?;
 
+        let fan_out = self.fan_out;
+        let leaf_dir_count = fan_out.leaf_dir_count();
+
         let mut done = false;
         let mut inner: Option<tools::fs::ReadDir> = None;
         let mut at = 0;
@@ -222,10 +531,7 @@ impl ChunkStore {
                         Some(Ok(entry)) => {
                             // skip files if they're not a hash
                             let bytes = entry.file_name().to_bytes();
-                            if bytes.len() != 64 && bytes.len() != 64 + ".0.bad".len() {
-                                continue;
-                            }
-                            if !bytes.iter().take(64).all(u8::is_ascii_hexdigit) {
+                            if !is_chunk_file_name(bytes) {
                                 continue;
                             }
 
@@ -244,13 +550,13 @@ impl ChunkStore {
 
                 inner = None;
 
-                if at == 0x10000 {
+                if at == leaf_dir_count {
                     done = true;
                     return None;
                 }
 
-                let subdir: &str = &format!("{:04x}", at);
-                percentage = (at * 100) / 0x10000;
+                let subdir: &str = &fan_out.leaf_dir(at);
+                percentage = (at * 100) / leaf_dir_count;
                 at += 1;
                 match tools::fs::read_subdir(base_handle.as_raw_fd(), subdir) {
                     Ok(dir) => {
@@ -277,12 +583,16 @@ impl ChunkStore {
         tools::ProcessLocker::oldest_shared_lock(self.locker.clone())
     }
 
+    /// Remove chunks not touched since the start of phase 1 (minus a safety gap)
+    ///
+    /// `progress` is called with the current status whenever the percentage changes.
     pub fn sweep_unused_chunks(
         &self,
         oldest_writer: i64,
         phase1_start_time: i64,
         status: &mut GarbageCollectionStatus,
         worker: &dyn TaskState,
+        progress: &dyn Fn(&GarbageCollectionStatus),
     ) -> Result<(), Error> {
         use nix::sys::stat::fstatat;
         use nix::unistd::{unlinkat, UnlinkatFlags};
@@ -303,10 +613,12 @@ impl ChunkStore {
                 last_percentage = percentage;
                 crate::task_log!(
                     worker,
-                    "processed {}% ({} chunks)",
+                    "processed {}% ({} chunks, {} removed)",
                     percentage,
                     chunk_count,
+                    status.removed_chunks,
                 );
+                progress(status);
             }
 
             worker.check_abort()?;
@@ -372,6 +684,34 @@ impl ChunkStore {
         Ok(())
     }
 
+    /// Remove a single chunk, unless it was accessed after `min_atime`
+    ///
+    /// Returns the size of the removed chunk, or `None` if the chunk was
+    /// kept or does not exist. The caller needs to hold the exclusive lock
+    /// (see `try_exclusive_lock`), else the atime cutoff is not safe.
+    pub fn remove_unused_chunk(&self, digest: &[u8; 32], min_atime: i64) -> Result<Option<u64>, Error> {
+        let (chunk_path, digest_str) = self.chunk_path(digest);
+
+        let _lock = self.mutex.lock();
+
+        let metadata = match std::fs::symlink_metadata(&chunk_path) {
+            Ok(metadata) => metadata,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => bail!("stat chunk {} on store '{}' failed - {}", digest_str, self.name, err),
+        };
+
+        use std::os::unix::fs::MetadataExt;
+        if metadata.atime() >= min_atime {
+            return Ok(None);
+        }
+
+        std::fs::remove_file(&chunk_path).map_err(|err| {
+            format_err!("unlinking chunk {} failed on store '{}' - {}", digest_str, self.name, err)
+        })?;
+
+        Ok(Some(metadata.len()))
+    }
+
     pub fn insert_chunk(
         &self,
         chunk: &DataBlob,
@@ -387,13 +727,38 @@ impl ChunkStore {
         if let Ok(metadata) = std::fs::metadata(&chunk_path) {
             if metadata.is_file() {
                 self.touch_chunk(digest)?;
+                CHUNK_INSERT_HITS.fetch_add(1, Ordering::Relaxed);
                 return Ok((true, metadata.len()));
             } else {
                 bail!("Got unexpected file type on store '{}' for chunk {}", self.name, digest_str);
             }
         }
 
-        let mut tmp_path = chunk_path.clone();
+        let encoded_size = self.write_chunk_file(chunk, &chunk_path, &digest_str)?;
+
+        drop(lock);
+
+        CHUNK_INSERT_MISSES.fetch_add(1, Ordering::Relaxed);
+
+        Ok((false, encoded_size))
+    }
+
+    /// Replace a (damaged) chunk file, using an atomic rename
+    pub fn replace_chunk(&self, chunk: &DataBlob, digest: &[u8; 32]) -> Result<u64, Error> {
+        let (chunk_path, digest_str) = self.chunk_path(digest);
+
+        let _lock = self.mutex.lock();
+
+        self.write_chunk_file(chunk, &chunk_path, &digest_str)
+    }
+
+    fn write_chunk_file(
+        &self,
+        chunk: &DataBlob,
+        chunk_path: &Path,
+        digest_str: &str,
+    ) -> Result<u64, Error> {
+        let mut tmp_path = chunk_path.to_owned();
         tmp_path.set_extension("tmp");
 
         let mut file = std::fs::File::create(&tmp_path)?;
@@ -401,8 +766,35 @@ impl ChunkStore {
         let raw_data = chunk.raw_data();
         let encoded_size = raw_data.len() as u64;
 
+        #[cfg(test)]
+        let intercepted = self.write_interceptor.map(|intercept| {
+            let mut data = raw_data.to_vec();
+            intercept(&mut data);
+            data
+        });
+        #[cfg(test)]
+        let raw_data = intercepted.as_deref().unwrap_or(raw_data);
+
         file.write_all(raw_data)?;
 
+        if self.verify_on_insert {
+            // the page cache can only be dropped for clean pages
+            file.sync_all()?;
+        }
+        drop(file);
+
+        if self.verify_on_insert {
+            if let Err(err) = verify_chunk_file(&tmp_path, chunk.raw_data()) {
+                if std::fs::remove_file(&tmp_path).is_err()  { /* ignore */ }
+                self.mismatches_since_start.fetch_add(1, Ordering::Relaxed);
+                return Err(InsertError::VerificationFailed {
+                    store: self.name.clone(),
+                    digest: digest_str.to_string(),
+                    reason: err.to_string(),
+                }.into());
+            }
+        }
+
         if let Err(err) = std::fs::rename(&tmp_path, &chunk_path) {
             if std::fs::remove_file(&tmp_path).is_err()  { /* ignore */ }
             bail!(
@@ -413,14 +805,61 @@ impl ChunkStore {
             );
         }
 
-        drop(lock);
+        Ok(encoded_size)
+    }
 
-        Ok((false, encoded_size))
+    /// Replace damaged (or missing) chunks with copies read from `source`
+    ///
+    /// Each downloaded chunk is checked (CRC, and the digest for
+    /// unencrypted chunks) before it replaces the local file.
+    pub fn repair_chunks(
+        &self,
+        source: &dyn ReadChunk,
+        damaged_chunks: &[[u8; 32]],
+        worker: &dyn TaskState,
+    ) -> Result<RepairReport, Error> {
+        let mut report = RepairReport::default();
+
+        for digest in damaged_chunks {
+            worker.check_abort()?;
+
+            let digest_str = proxmox::tools::digest_to_hex(digest);
+
+            let chunk = match source.read_raw_chunk(digest) {
+                Ok(chunk) => chunk,
+                Err(err) => {
+                    task_log!(worker, "repair chunk {} - unable to get copy: {}", digest_str, err);
+                    report.remote_also_missing += 1;
+                    continue;
+                }
+            };
+
+            let result = proxmox::try_block!({
+                chunk.verify_crc()?;
+                if chunk.crypt_mode()? != CryptMode::Encrypt {
+                    chunk.decode(None, Some(digest))?; // verifies digest
+                }
+                self.replace_chunk(&chunk, digest)
+            });
+
+            match result {
+                Ok(_) => {
+                    task_log!(worker, "repaired chunk {}", digest_str);
+                    report.repaired += 1;
+                }
+                Err(err) => {
+                    task_log!(worker, "repair chunk {} failed - {}", digest_str, err);
+                    report.still_missing += 1;
+                }
+            }
+        }
+
+        Ok(report)
     }
 
     pub fn chunk_path(&self, digest:&[u8; 32]) -> (PathBuf, String) {
         let mut chunk_path = self.chunk_dir.clone();
-        let prefix = digest_to_prefix(digest);
+        let prefix = self.fan_out.digest_to_prefix(digest);
         chunk_path.push(&prefix);
         let digest_str = proxmox::tools::digest_to_hex(digest);
         chunk_path.push(&digest_str);
@@ -438,6 +877,139 @@ impl ChunkStore {
         &self.name
     }
 
+    /// The chunk directory fan-out this chunk store was opened with.
+    pub fn fan_out(&self) -> ChunkDirFanOut {
+        self.fan_out
+    }
+
+    /// Move all chunks to the directory layout described by `fan_out`.
+    ///
+    /// The caller needs to hold the exclusive process lock, and has to
+    /// re-open the chunk store afterwards. The migration is recorded in the
+    /// fan-out file before any chunk gets moved, and the chunk store cannot
+    /// be opened (see `open`) until it finished. Moving chunks is idempotent,
+    /// so an interrupted migration is resumed by running it again.
+    pub fn migrate_fan_out(&self, fan_out: ChunkDirFanOut, worker: &dyn TaskState) -> Result<(), Error> {
+        use nix::sys::stat::stat;
+        use nix::unistd::{Gid, Uid};
+
+        fan_out.verify()?;
+
+        // the state on disk is authoritative, another process may have changed it
+        let old_fan_out = match Self::read_fan_out_state(&self.base)? {
+            ChunkDirFanOutState::Active(old_fan_out) => {
+                if old_fan_out == fan_out {
+                    task_log!(worker, "chunk store '{}' already uses fan-out {}", self.name, fan_out);
+                    return Ok(());
+                }
+                task_log!(worker, "migrate chunk store '{}' from fan-out {} to {}", self.name, old_fan_out, fan_out);
+                old_fan_out
+            }
+            ChunkDirFanOutState::Migrating { from, to } => {
+                if to != fan_out {
+                    bail!(
+                        "unfinished migration of chunk store '{}' to fan-out {} - configure that \
+                         fan-out again to finish it first",
+                        self.name, to,
+                    );
+                }
+                task_log!(worker, "resume migration of chunk store '{}' from fan-out {} to {}", self.name, from, to);
+                from
+            }
+        };
+
+        // new directories get the same owner as the chunk directory
+        let dir_stat = stat(&self.chunk_dir)?;
+        let options = CreateOptions::new()
+            .owner(Uid::from_raw(dir_stat.st_uid))
+            .group(Gid::from_raw(dir_stat.st_gid));
+
+        let state = ChunkDirFanOutState::Migrating { from: old_fan_out, to: fan_out };
+        Self::write_fan_out_state(&self.base, state, options.clone())?;
+
+        let leaf_dir_count = fan_out.leaf_dir_count();
+        for i in 0..leaf_dir_count {
+            let mut leaf_path = self.chunk_dir.clone();
+            leaf_path.push(fan_out.leaf_dir(i));
+            create_path(&leaf_path, Some(options.clone()), Some(options.clone()))
+                .map_err(|err| format_err!("unable to create subdir {:?} - {}", leaf_path, err))?;
+        }
+
+        let old_leaf_dir_count = old_fan_out.leaf_dir_count();
+        let mut last_percentage = 0;
+        let mut moved_chunks = 0;
+
+        for i in 0..old_leaf_dir_count {
+            worker.check_abort()?;
+
+            let old_dir = old_fan_out.leaf_dir(i);
+            let mut old_path = self.chunk_dir.clone();
+            old_path.push(&old_dir);
+
+            let entries = match std::fs::read_dir(&old_path) {
+                Ok(entries) => entries,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(err) => bail!("unable to read subdir {:?} - {}", old_path, err),
+            };
+
+            for entry in entries {
+                let file_name = entry?.file_name();
+                let file_name = match file_name.to_str() {
+                    Some(file_name) if is_chunk_file_name(file_name.as_bytes()) => file_name,
+                    _ => continue,
+                };
+
+                let digest = proxmox::tools::hex_to_digest(&file_name[..64])?;
+
+                let mut new_path = self.chunk_dir.clone();
+                new_path.push(fan_out.digest_to_prefix(&digest));
+                new_path.push(file_name);
+
+                let mut chunk_path = old_path.clone();
+                chunk_path.push(file_name);
+
+                if chunk_path == new_path {
+                    continue;
+                }
+
+                std::fs::rename(&chunk_path, &new_path)
+                    .map_err(|err| format_err!("unable to move chunk {:?} - {}", chunk_path, err))?;
+                moved_chunks += 1;
+            }
+
+            let percentage = (i*100)/old_leaf_dir_count;
+            if percentage != last_percentage {
+                task_log!(worker, "migration: {}% ({} chunks moved)", percentage, moved_chunks);
+                last_percentage = percentage;
+            }
+        }
+
+        // remove old directories, directories still containing new ones are kept
+        for i in 0..old_leaf_dir_count {
+            let mut dir = old_fan_out.leaf_dir(i);
+            loop {
+                if fan_out.is_leaf_dir(&dir) {
+                    break;
+                }
+                let mut path = self.chunk_dir.clone();
+                path.push(&dir);
+                if std::fs::remove_dir(&path).is_err() {
+                    break;
+                }
+                match dir.rfind('/') {
+                    Some(pos) => dir.truncate(pos),
+                    None => break,
+                }
+            }
+        }
+
+        Self::write_fan_out(&self.base, fan_out, options)?;
+
+        task_log!(worker, "migration finished, moved {} chunks", moved_chunks);
+
+        Ok(())
+    }
+
     pub fn base_path(&self) -> PathBuf {
         self.base.clone()
     }
@@ -451,6 +1023,27 @@ impl ChunkStore {
     }
 }
 
+// Read back a chunk file from disk and compare with the expected data
+//
+// The file needs to be synced already - only clean pages get dropped from the
+// page cache, so that the read below actually hits the disk.
+fn verify_chunk_file(path: &Path, expected: &[u8]) -> Result<(), Error> {
+    use std::io::Read;
+    use nix::fcntl::{posix_fadvise, PosixFadviseAdvice};
+
+    let mut file = std::fs::File::open(path)?;
+    posix_fadvise(file.as_raw_fd(), 0, 0, PosixFadviseAdvice::POSIX_FADV_DONTNEED)
+        .map_err(|err| format_err!("dropping cached pages failed - {}", err))?;
+
+    let mut data = Vec::with_capacity(expected.len());
+    file.read_to_end(&mut data)?;
+    let blob = DataBlob::from_raw(data)?;
+    blob.verify_crc()?;
+    if blob.raw_data() != expected {
+        bail!("read back data does not match written data");
+    }
+    Ok(())
+}
 
 #[test]
 fn test_chunk_store1() {
@@ -464,7 +1057,7 @@ fn test_chunk_store1() {
     assert!(chunk_store.is_err());
 
     let user = nix::unistd::User::from_uid(nix::unistd::Uid::current()).unwrap().unwrap();
-    let chunk_store = ChunkStore::create("test", &path, user.uid, user.gid, None).unwrap();
+    let chunk_store = ChunkStore::create("test", &path, user.uid, user.gid, ChunkDirFanOut::default(), None).unwrap();
 
     let (chunk, digest) = super::DataChunkBuilder::new(&[0u8, 1u8]).build().unwrap();
 
@@ -475,8 +1068,197 @@ fn test_chunk_store1() {
     assert!(exists);
 
 
-    let chunk_store = ChunkStore::create("test", &path, user.uid, user.gid, None);
+    let chunk_store = ChunkStore::create("test", &path, user.uid, user.gid, ChunkDirFanOut::default(), None);
     assert!(chunk_store.is_err());
 
     if let Err(_e) = std::fs::remove_dir_all(".testdir") { /* ignore */ }
 }
+
+#[test]
+fn test_chunk_store_repair() {
+
+    struct TestTask;
+
+    impl TaskState for TestTask {
+        fn check_abort(&self) -> Result<(), Error> { Ok(()) }
+        fn log(&self, _level: log::Level, _message: &std::fmt::Arguments) {}
+    }
+
+    // hands out a fixed set of blobs, regardless of their content
+    struct TestSource(std::collections::HashMap<[u8; 32], DataBlob>);
+
+    impl ReadChunk for TestSource {
+        fn read_raw_chunk(&self, digest: &[u8; 32]) -> Result<DataBlob, Error> {
+            match self.0.get(digest) {
+                Some(blob) => Ok(DataBlob::from_raw(blob.raw_data().to_vec())?),
+                None => bail!("no such chunk"),
+            }
+        }
+
+        fn read_chunk(&self, digest: &[u8; 32]) -> Result<Vec<u8>, Error> {
+            self.read_raw_chunk(digest)?.decode(None, Some(digest))
+        }
+    }
+
+    let mut path = std::fs::canonicalize(".").unwrap(); // we need absolute path
+    path.push(".testdir-repair");
+
+    if let Err(_e) = std::fs::remove_dir_all(&path) { /* ignore */ }
+
+    let user = nix::unistd::User::from_uid(nix::unistd::Uid::current()).unwrap().unwrap();
+    let chunk_store = ChunkStore::create("test", &path, user.uid, user.gid, ChunkDirFanOut::default(), None).unwrap();
+
+    let (good, good_digest) = super::DataChunkBuilder::new(b"good").build().unwrap();
+    let (_, missing_digest) = super::DataChunkBuilder::new(b"missing").build().unwrap();
+    let (_, bad_digest) = super::DataChunkBuilder::new(b"bad").build().unwrap();
+    let (wrong, _) = super::DataChunkBuilder::new(b"wrong").build().unwrap();
+
+    // simulate a damaged local copy
+    let (corrupt, _) = super::DataChunkBuilder::new(b"corrupt").build().unwrap();
+    chunk_store.insert_chunk(&corrupt, &good_digest).unwrap();
+
+    let mut blobs = std::collections::HashMap::new();
+    blobs.insert(good_digest, good);
+    blobs.insert(bad_digest, wrong);
+    let source = TestSource(blobs);
+
+    let report = chunk_store
+        .repair_chunks(&source, &[good_digest, missing_digest, bad_digest], &TestTask)
+        .unwrap();
+
+    assert_eq!(report, RepairReport { repaired: 1, still_missing: 1, remote_also_missing: 1 });
+
+    let (chunk_path, _) = chunk_store.chunk_path(&good_digest);
+    let blob = DataBlob::load_from_reader(&mut std::fs::File::open(chunk_path).unwrap()).unwrap();
+    assert_eq!(blob.decode(None, Some(&good_digest)).unwrap(), b"good");
+
+    let (chunk_path, _) = chunk_store.chunk_path(&bad_digest);
+    assert!(!chunk_path.exists());
+
+    if let Err(_e) = std::fs::remove_dir_all(&path) { /* ignore */ }
+}
+
+#[test]
+fn test_chunk_dir_fan_out() {
+
+    let digest = proxmox::tools::hex_to_digest(
+        "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef").unwrap();
+
+    let fan_out = ChunkDirFanOut::default();
+    assert_eq!(fan_out.digest_to_prefix(&digest), "0123");
+    assert_eq!(fan_out.leaf_dir_count(), 64*1024);
+    assert_eq!(fan_out.leaf_dir(0xffff), "ffff");
+
+    let fan_out: ChunkDirFanOut = "8:8".parse().unwrap();
+    assert_eq!(fan_out, ChunkDirFanOut::TwoLevel(8, 8));
+    assert_eq!(fan_out.digest_to_prefix(&digest), "01/23");
+    assert!(fan_out.is_leaf_dir("01/23"));
+    assert!(!fan_out.is_leaf_dir("0123"));
+
+    let fan_out: ChunkDirFanOut = "4:4:12".parse().unwrap();
+    assert_eq!(fan_out.to_string(), "4:4:12");
+    assert_eq!(fan_out.digest_to_prefix(&digest), "0/1/234");
+
+    assert!("3".parse::<ChunkDirFanOut>().is_err());
+    assert!("20".parse::<ChunkDirFanOut>().is_err());
+    assert!("16:16".parse::<ChunkDirFanOut>().is_err());
+    assert!("4:4:4:4".parse::<ChunkDirFanOut>().is_err());
+
+    let state: ChunkDirFanOutState = "8:8\n".parse().unwrap();
+    assert_eq!(state, ChunkDirFanOutState::Active(ChunkDirFanOut::TwoLevel(8, 8)));
+
+    let state: ChunkDirFanOutState = "migrating 16 8:8\n".parse().unwrap();
+    assert_eq!(state, ChunkDirFanOutState::Migrating {
+        from: ChunkDirFanOut::OneLevel(16),
+        to: ChunkDirFanOut::TwoLevel(8, 8),
+    });
+    assert_eq!(state.to_string(), "migrating 16 8:8");
+
+    assert!("migrating 16".parse::<ChunkDirFanOutState>().is_err());
+}
+
+#[test]
+fn test_chunk_store_fan_out_migration_resume() {
+    use crate::task::TaskState;
+
+    struct TestTask;
+    impl TaskState for TestTask {
+        fn check_abort(&self) -> Result<(), Error> { Ok(()) }
+        fn log(&self, _level: log::Level, _message: &std::fmt::Arguments) {}
+    }
+
+    let mut path = std::fs::canonicalize(".").unwrap(); // we need absolute path
+    path.push(".testdir-fan-out-migration");
+
+    if let Err(_e) = std::fs::remove_dir_all(".testdir-fan-out-migration") { /* ignore */ }
+
+    let user = nix::unistd::User::from_uid(nix::unistd::Uid::current()).unwrap().unwrap();
+    let fan_out: ChunkDirFanOut = "4".parse().unwrap();
+    let chunk_store = ChunkStore::create("test", &path, user.uid, user.gid, fan_out, None).unwrap();
+
+    let (chunk, digest) = super::DataChunkBuilder::new(&[0u8, 1u8]).build().unwrap();
+    chunk_store.insert_chunk(&chunk, &digest).unwrap();
+
+    // simulate a crash right after the migration was recorded
+    let new_fan_out: ChunkDirFanOut = "4:4".parse().unwrap();
+    let state = ChunkDirFanOutState::Migrating { from: fan_out, to: new_fan_out };
+    ChunkStore::write_fan_out_state(&path, state, CreateOptions::new()).unwrap();
+
+    assert!(chunk_store.fan_out_changed().unwrap());
+    assert!(ChunkStore::open("test", &path).is_err());
+
+    // only the target of the unfinished migration is accepted
+    let chunk_store = ChunkStore::open_for_migration("test", &path).unwrap();
+    assert!(chunk_store.migrate_fan_out("8".parse().unwrap(), &TestTask).is_err());
+    chunk_store.migrate_fan_out(new_fan_out, &TestTask).unwrap();
+
+    let chunk_store = ChunkStore::open("test", &path).unwrap();
+    assert_eq!(chunk_store.fan_out(), new_fan_out);
+    assert!(chunk_store.chunk_path(&digest).0.exists());
+
+    if let Err(_e) = std::fs::remove_dir_all(&path) { /* ignore */ }
+}
+
+#[test]
+fn test_chunk_store_verify_on_insert() {
+
+    let mut path = std::fs::canonicalize(".").unwrap(); // we need absolute path
+    path.push(".testdir-verify-insert");
+
+    if let Err(_e) = std::fs::remove_dir_all(&path) { /* ignore */ }
+
+    let user = nix::unistd::User::from_uid(nix::unistd::Uid::current()).unwrap().unwrap();
+    let mut chunk_store = ChunkStore::create("test", &path, user.uid, user.gid, ChunkDirFanOut::default(), None).unwrap();
+
+    // flip a bit in the payload, so that the CRC does not match
+    fn corrupt(data: &mut Vec<u8>) {
+        let last = data.len() - 1;
+        data[last] ^= 1;
+    }
+
+    let (chunk, digest) = super::DataChunkBuilder::new(b"verify on insert").build().unwrap();
+    let (chunk_path, _) = chunk_store.chunk_path(&digest);
+
+    // not detected without verification
+    chunk_store.write_interceptor = Some(corrupt);
+    let (exists, _) = chunk_store.insert_chunk(&chunk, &digest).unwrap();
+    assert!(!exists);
+    std::fs::remove_file(&chunk_path).unwrap();
+
+    chunk_store.set_verify_on_insert(true);
+    let err = chunk_store.insert_chunk(&chunk, &digest).unwrap_err();
+    match err.downcast_ref::<InsertError>() {
+        Some(InsertError::VerificationFailed { .. }) => (),
+        None => panic!("expected verification error, got: {}", err),
+    }
+    assert!(!chunk_path.exists());
+    assert_eq!(chunk_store.mismatches_since_start(), 1);
+
+    chunk_store.write_interceptor = None;
+    let (exists, _) = chunk_store.insert_chunk(&chunk, &digest).unwrap();
+    assert!(!exists);
+    assert!(chunk_path.exists());
+    assert_eq!(chunk_store.mismatches_since_start(), 1);
+
+    if let Err(_e) = std::fs::remove_dir_all(&path) { /* ignore */ }
+}
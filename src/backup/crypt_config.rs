@@ -9,7 +9,7 @@
 
 use std::fmt;
 use std::fmt::Display;
-use std::io::Write;
+use std::io::{self, Write};
 
 use anyhow::{Error};
 use openssl::hash::MessageDigest;
@@ -77,6 +77,29 @@ impl std::str::FromStr for Fingerprint {
     }
 }
 
+// Collects decompressed data, fails instead of growing beyond max_size
+struct SizeLimitedWriter {
+    data: Vec<u8>,
+    max_size: usize,
+}
+
+impl Write for SizeLimitedWriter {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, io::Error> {
+        if self.data.len() + buf.len() > self.max_size {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("decompressed size exceeds limit ({} bytes)", self.max_size),
+            ));
+        }
+        self.data.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), io::Error> {
+        Ok(())
+    }
+}
+
 /// Encryption Configuration with secret key
 ///
 /// This structure stores the secret key and provides helpers for
@@ -203,14 +226,17 @@ impl CryptConfig {
     }
 
     /// Decompress and decrypt data, verify MAC.
+    ///
+    /// Fails if the decompressed data exceeds `max_size`.
     pub fn decode_compressed_chunk(
         &self,
         data: &[u8],
         iv: &[u8; 16],
         tag: &[u8; 16],
+        max_size: usize,
     ) -> Result<Vec<u8>, Error> {
 
-        let dec = Vec::with_capacity(1024*1024);
+        let dec = SizeLimitedWriter { data: Vec::with_capacity(1024*1024), max_size };
 
         let mut decompressor = zstd::stream::write::Decoder::new(dec)?;
 
@@ -240,7 +266,7 @@ impl CryptConfig {
 
         decompressor.flush()?;
 
-        Ok(decompressor.into_inner())
+        Ok(decompressor.into_inner().data)
     }
 
     /// Decrypt data, verify tag.
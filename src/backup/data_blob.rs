@@ -1,12 +1,16 @@
 use anyhow::{bail, Error};
 use std::convert::TryInto;
+use std::io::Read;
 
 use proxmox::tools::io::{ReadExt, WriteExt};
 
 use super::file_formats::*;
 use super::{CryptConfig, CryptMode};
 
-const MAX_BLOB_SIZE: usize = 128*1024*1024;
+/// Maximum size of blob data
+///
+/// Also used as default limit for the decompressed data (see `DataBlob::decode_with_limit`).
+pub const MAX_BLOB_SIZE: usize = 128*1024*1024;
 
 /// Encoded data chunk with digest and positional information
 pub struct ChunkInfo {
@@ -16,6 +20,65 @@ pub struct ChunkInfo {
     pub offset: u64,
 }
 
+/// Result information of `DataBlob::encode_with_info`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EncodeInfo {
+    /// Whether the data was stored compressed (only if that was smaller)
+    pub compressed: bool,
+    /// Size of the input data
+    pub input_len: u64,
+    /// Size of the encoded blob, including the header
+    pub output_len: u64,
+}
+
+impl EncodeInfo {
+    /// Ratio of output to input size (smaller is better)
+    pub fn ratio(&self) -> f64 {
+        if self.input_len == 0 {
+            return 1.0;
+        }
+        self.output_len as f64 / self.input_len as f64
+    }
+}
+
+/// Blob format, identified by the magic number
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlobType {
+    Uncompressed,
+    Compressed,
+    Encrypted,
+    EncryptedCompressed,
+}
+
+impl BlobType {
+    /// The magic number of this blob type
+    pub fn magic(&self) -> &'static [u8; 8] {
+        match self {
+            BlobType::Uncompressed => &UNCOMPRESSED_BLOB_MAGIC_1_0,
+            BlobType::Compressed => &COMPRESSED_BLOB_MAGIC_1_0,
+            BlobType::Encrypted => &ENCRYPTED_BLOB_MAGIC_1_0,
+            BlobType::EncryptedCompressed => &ENCR_COMPR_BLOB_MAGIC_1_0,
+        }
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        matches!(self, BlobType::Encrypted | BlobType::EncryptedCompressed)
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        matches!(self, BlobType::Compressed | BlobType::EncryptedCompressed)
+    }
+
+    /// Size of the blob header, including magic and CRC
+    pub fn header_size(&self) -> usize {
+        if self.is_encrypted() {
+            std::mem::size_of::<EncryptedDataBlobHeader>()
+        } else {
+            std::mem::size_of::<DataBlobHeader>()
+        }
+    }
+}
+
 /// Data blob binary storage format
 ///
 /// Data blobs store arbitrary binary data (< 128MB), and can be
@@ -51,6 +114,27 @@ impl DataBlob {
         self.raw_data[0..8].try_into().unwrap()
     }
 
+    /// Identify the blob type of a magic number
+    ///
+    /// Returns `None` for unknown magic numbers, so that callers (e.g.
+    /// tools scanning a datastore) can skip blobs written by a newer
+    /// format version.
+    pub fn try_identify(magic: &[u8; 8]) -> Option<BlobType> {
+        match *magic {
+            UNCOMPRESSED_BLOB_MAGIC_1_0 => Some(BlobType::Uncompressed),
+            COMPRESSED_BLOB_MAGIC_1_0 => Some(BlobType::Compressed),
+            ENCRYPTED_BLOB_MAGIC_1_0 => Some(BlobType::Encrypted),
+            ENCR_COMPR_BLOB_MAGIC_1_0 => Some(BlobType::EncryptedCompressed),
+            _ => None,
+        }
+    }
+
+    /// The blob type of this blob
+    pub fn blob_type(&self) -> BlobType {
+        // the magic number is checked when creating the blob
+        Self::try_identify(self.magic()).unwrap()
+    }
+
     /// accessor to crc32 checksum
     pub fn crc(&self) -> u32 {
         let crc_o = proxmox::offsetof!(DataBlobHeader, crc);
@@ -66,7 +150,7 @@ impl DataBlob {
     /// compute the CRC32 checksum
     pub fn compute_crc(&self) -> u32 {
         let mut hasher = crc32fast::Hasher::new();
-        let start = header_size(self.magic()); // start after HEAD
+        let start = self.blob_type().header_size(); // start after HEAD
         hasher.update(&self.raw_data[start..]);
         hasher.finalize()
     }
@@ -171,6 +255,53 @@ impl DataBlob {
         Ok(blob)
     }
 
+    /// Like `encode`, but also returns information about the achieved compression.
+    pub fn encode_with_info(
+        data: &[u8],
+        config: Option<&CryptConfig>,
+        compress: bool,
+    ) -> Result<(Self, EncodeInfo), Error> {
+        let blob = Self::encode(data, config, compress)?;
+
+        let magic = blob.magic();
+        let info = EncodeInfo {
+            compressed: magic == &COMPRESSED_BLOB_MAGIC_1_0 || magic == &ENCR_COMPR_BLOB_MAGIC_1_0,
+            input_len: data.len() as u64,
+            output_len: blob.raw_size(),
+        };
+
+        Ok((blob, info))
+    }
+
+    /// Create an uncompressed, unencrypted DataBlob using a precomputed CRC32 of ``data``.
+    ///
+    /// The CRC of such blobs covers the plain data, so callers which already hashed the data
+    /// can avoid a second pass over it. The CRC is trusted, only debug builds recompute and
+    /// check it.
+    pub fn encode_prehashed(data: &[u8], crc: u32) -> Result<Self, Error> {
+
+        if data.len() > MAX_BLOB_SIZE {
+            bail!("data blob too large ({} bytes).", data.len());
+        }
+
+        let mut raw_data = Vec::with_capacity(data.len() + std::mem::size_of::<DataBlobHeader>());
+
+        let head =  DataBlobHeader {
+            magic: UNCOMPRESSED_BLOB_MAGIC_1_0,
+            crc: crc.to_le_bytes(),
+        };
+        unsafe {
+            raw_data.write_le_value(head)?;
+        }
+        raw_data.extend_from_slice(data);
+
+        let blob = DataBlob { raw_data };
+
+        debug_assert_eq!(blob.compute_crc(), crc, "wrong precomputed data blob CRC");
+
+        Ok(blob)
+    }
+
     /// Get the encryption mode for this blob.
     pub fn crypt_mode(&self) -> Result<CryptMode, Error> {
         let magic = self.magic();
@@ -185,7 +316,22 @@ impl DataBlob {
     }
 
     /// Decode blob data
+    ///
+    /// Decompressed data is limited to `MAX_BLOB_SIZE`.
     pub fn decode(&self, config: Option<&CryptConfig>, digest: Option<&[u8; 32]>) -> Result<Vec<u8>, Error> {
+        self.decode_with_limit(config, digest, MAX_BLOB_SIZE)
+    }
+
+    /// Decode blob data, fail if the decompressed data exceeds `max_size`
+    ///
+    /// This avoids unbounded allocations for blobs from untrusted sources
+    /// (compression bombs).
+    pub fn decode_with_limit(
+        &self,
+        config: Option<&CryptConfig>,
+        digest: Option<&[u8; 32]>,
+        max_size: usize,
+    ) -> Result<Vec<u8>, Error> {
 
         let magic = self.magic();
 
@@ -198,10 +344,15 @@ impl DataBlob {
             Ok(data)
         } else if magic == &COMPRESSED_BLOB_MAGIC_1_0 {
             let data_start = std::mem::size_of::<DataBlobHeader>();
-            let mut reader = &self.raw_data[data_start..];
-            let data = zstd::stream::decode_all(&mut reader)?;
+            let reader = &self.raw_data[data_start..];
+            let mut data = Vec::new();
             // zstd::block::decompress is abou 10% slower
-            // let data = zstd::block::decompress(&self.raw_data[data_start..], MAX_BLOB_SIZE)?;
+            zstd::stream::read::Decoder::new(reader)?
+                .take(max_size as u64 + 1)
+                .read_to_end(&mut data)?;
+            if data.len() > max_size {
+                bail!("decompressed size exceeds limit ({} bytes)", max_size);
+            }
             if let Some(digest) = digest {
                 Self::verify_digest(&data, None, digest)?;
             }
@@ -214,7 +365,7 @@ impl DataBlob {
 
             if let Some(config) = config  {
                 let data = if magic == &ENCR_COMPR_BLOB_MAGIC_1_0 {
-                    config.decode_compressed_chunk(&self.raw_data[header_len..], &head.iv, &head.tag)?
+                    config.decode_compressed_chunk(&self.raw_data[header_len..], &head.iv, &head.tag, max_size)?
                 } else {
                     config.decode_uncompressed_chunk(&self.raw_data[header_len..], &head.iv, &head.tag)?
                 };
@@ -250,25 +401,18 @@ impl DataBlob {
             bail!("blob too small ({} bytes).", data.len());
         }
 
-        let magic = &data[0..8];
-
-        if magic == ENCR_COMPR_BLOB_MAGIC_1_0 || magic == ENCRYPTED_BLOB_MAGIC_1_0 {
+        let magic: &[u8; 8] = data[0..8].try_into().unwrap();
 
-            if data.len() < std::mem::size_of::<EncryptedDataBlobHeader>() {
-                bail!("encrypted blob too small ({} bytes).", data.len());
-            }
-
-            let blob = DataBlob { raw_data: data };
-
-            Ok(blob)
-        } else if magic == COMPRESSED_BLOB_MAGIC_1_0 || magic == UNCOMPRESSED_BLOB_MAGIC_1_0 {
-
-            let blob = DataBlob { raw_data: data };
+        let blob_type = match Self::try_identify(magic) {
+            Some(blob_type) => blob_type,
+            None => bail!("unable to parse raw blob - unknown magic {:?}", magic),
+        };
 
-            Ok(blob)
-        } else {
-            bail!("unable to parse raw blob - wrong magic");
+        if blob_type.is_encrypted() && data.len() < blob_type.header_size() {
+            bail!("encrypted blob too small ({} bytes).", data.len());
         }
+
+        Ok(DataBlob { raw_data: data })
     }
 
     /// Returns if chunk is encrypted
@@ -333,6 +477,7 @@ pub struct DataChunkBuilder<'a, 'b> {
     orig_data: &'a [u8],
     digest_computed: bool,
     digest: [u8; 32],
+    crc: Option<u32>,
     compress: bool,
 }
 
@@ -345,6 +490,7 @@ impl <'a, 'b> DataChunkBuilder<'a, 'b> {
             config: None,
             digest_computed: false,
             digest: [0u8; 32],
+            crc: None,
             compress: true,
         }
     }
@@ -368,6 +514,19 @@ impl <'a, 'b> DataChunkBuilder<'a, 'b> {
         self
     }
 
+    /// Use a precomputed digest and CRC32 of the data.
+    ///
+    /// Both are trusted, only debug builds recompute and check them. The CRC is only used for
+    /// uncompressed, unencrypted chunks, where it covers the plain data.
+    ///
+    /// Note: For encrypted chunks, the digest must be computed with the ``CryptConfig``.
+    pub fn prehashed(mut self, digest: [u8; 32], crc: u32) -> Self {
+        self.digest = digest;
+        self.digest_computed = true;
+        self.crc = Some(crc);
+        self
+    }
+
     fn compute_digest(&mut self) {
         if !self.digest_computed {
             if let Some(ref config) = self.config {
@@ -398,7 +557,20 @@ impl <'a, 'b> DataChunkBuilder<'a, 'b> {
             self.compute_digest();
         }
 
-        let chunk = DataBlob::encode(self.orig_data, self.config, self.compress)?;
+        if cfg!(debug_assertions) && self.crc.is_some() {
+            let digest = match self.config {
+                Some(config) => config.compute_digest(self.orig_data),
+                None => openssl::sha::sha256(self.orig_data),
+            };
+            assert_eq!(digest, self.digest, "wrong precomputed chunk digest");
+        }
+
+        let chunk = match self.crc {
+            Some(crc) if self.config.is_none() && !self.compress => {
+                DataBlob::encode_prehashed(self.orig_data, crc)?
+            }
+            _ => DataBlob::encode(self.orig_data, self.config, self.compress)?,
+        };
         Ok((chunk, self.digest))
     }
 
@@ -418,3 +590,101 @@ impl <'a, 'b> DataChunkBuilder<'a, 'b> {
     }
 
 }
+
+#[test]
+fn test_encode_prehashed() -> Result<(), Error> {
+    let data = b"some chunk data";
+    let crc = crc32fast::hash(data);
+
+    let blob = DataBlob::encode_prehashed(data, crc)?;
+    blob.verify_crc()?;
+    assert_eq!(blob.raw_data(), DataBlob::encode(data, None, false)?.raw_data());
+    assert_eq!(blob.decode(None, Some(&openssl::sha::sha256(data)))?, data);
+
+    let digest = openssl::sha::sha256(data);
+    let (chunk, chunk_digest) = DataChunkBuilder::new(data)
+        .compress(false)
+        .prehashed(digest, crc)
+        .build()?;
+    assert_eq!(chunk_digest, digest);
+    assert_eq!(chunk.raw_data(), blob.raw_data());
+
+    // compression changes the payload, so the CRC gets computed
+    let (chunk, _) = DataChunkBuilder::new(&[0u8; 4096])
+        .prehashed(openssl::sha::sha256(&[0u8; 4096]), crc32fast::hash(&[0u8; 4096]))
+        .build()?;
+    chunk.verify_crc()?;
+
+    Ok(())
+}
+
+#[test]
+fn test_encode_with_info() -> Result<(), Error> {
+    let data = vec![0u8; 64*1024];
+    let (blob, info) = DataBlob::encode_with_info(&data, None, true)?;
+    assert!(info.compressed);
+    assert_eq!(info.input_len, data.len() as u64);
+    assert_eq!(info.output_len, blob.raw_size());
+    assert!(info.ratio() < 0.1);
+
+    // random data does not compress, so encode falls back to uncompressed
+    let mut data = vec![0u8; 4096];
+    proxmox::sys::linux::fill_with_random_data(&mut data)?;
+    let (blob, info) = DataBlob::encode_with_info(&data, None, true)?;
+    assert!(!info.compressed);
+    assert_eq!(blob.magic(), &UNCOMPRESSED_BLOB_MAGIC_1_0);
+    assert!(info.ratio() > 1.0);
+
+    let (_blob, info) = DataBlob::encode_with_info(b"abc", None, false)?;
+    assert!(!info.compressed);
+
+    Ok(())
+}
+
+#[test]
+fn test_decode_size_limit() -> Result<(), Error> {
+    let data = vec![0u8; 1024*1024];
+    let crypt_config = std::sync::Arc::new(CryptConfig::new([1u8; 32])?);
+
+    for config in vec![None, Some(crypt_config)] {
+        let blob = DataBlob::encode(&data, config.as_deref(), true)?;
+        assert_eq!(blob.decode_with_limit(config.as_deref(), None, data.len())?, data);
+
+        let err = blob.decode_with_limit(config.as_deref(), None, data.len() - 1).unwrap_err();
+        assert!(err.to_string().contains("decompressed size exceeds limit"));
+
+        let mut reader = super::DataBlobReader::new(blob.raw_data(), config)?
+            .with_size_limit(4096);
+        let err = reader.read_to_end(&mut Vec::new()).unwrap_err();
+        assert!(err.to_string().contains("decompressed size exceeds limit"));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_blob_type() -> Result<(), Error> {
+    for blob_type in vec![
+        BlobType::Uncompressed,
+        BlobType::Compressed,
+        BlobType::Encrypted,
+        BlobType::EncryptedCompressed,
+    ] {
+        assert_eq!(DataBlob::try_identify(blob_type.magic()), Some(blob_type));
+        assert_eq!(header_size(blob_type.magic())?, blob_type.header_size());
+    }
+
+    let blob = DataBlob::encode(b"data", None, false)?;
+    assert_eq!(blob.blob_type(), BlobType::Uncompressed);
+
+    // e.g. a blob written by a newer version
+    let magic = [1u8; 8];
+    assert_eq!(DataBlob::try_identify(&magic), None);
+    assert!(header_size(&magic).is_err());
+
+    let mut raw_data = magic.to_vec();
+    raw_data.extend_from_slice(&[0u8; 32]);
+    assert!(DataBlob::from_raw(raw_data).is_err());
+
+    Ok(())
+}
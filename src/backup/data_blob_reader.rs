@@ -1,12 +1,14 @@
 use anyhow::{bail, format_err, Error};
 use std::sync::Arc;
-use std::io::{Read, BufReader};
+use std::io::{Read, BufReader, Seek, SeekFrom};
 use proxmox::tools::io::ReadExt;
 
 use super::*;
 
 enum BlobReaderState<R: Read> {
-    Uncompressed { expected_crc: u32, csum_reader: ChecksumReader<R> },
+    // 'offset' is the current position inside the blob data, 'crc_valid' is cleared if the
+    // data was not read sequentially from the start (see Seek implementation)
+    Uncompressed { expected_crc: u32, csum_reader: ChecksumReader<R>, offset: u64, crc_valid: bool },
     Compressed { expected_crc: u32, decompr: zstd::stream::read::Decoder<BufReader<ChecksumReader<R>>> },
     Encrypted { expected_crc: u32, decrypt_reader: CryptReader<BufReader<ChecksumReader<R>>> },
     EncryptedCompressed { expected_crc: u32, decompr: zstd::stream::read::Decoder<BufReader<CryptReader<BufReader<ChecksumReader<R>>>>> },
@@ -15,6 +17,8 @@ enum BlobReaderState<R: Read> {
 /// Read data blobs
 pub struct DataBlobReader<R: Read> {
     state: BlobReaderState<R>,
+    max_size: Option<usize>,
+    read_size: usize,
 }
 
 // zstd_safe::DCtx is not sync but we are, since
@@ -26,18 +30,18 @@ impl <R: Read> DataBlobReader<R> {
     pub fn new(mut reader: R, config: Option<Arc<CryptConfig>>) -> Result<Self, Error> {
 
         let head: DataBlobHeader = unsafe { reader.read_le_value()? };
-        match head.magic {
+        let state = match head.magic {
             UNCOMPRESSED_BLOB_MAGIC_1_0 => {
                 let expected_crc = u32::from_le_bytes(head.crc);
                 let csum_reader =  ChecksumReader::new(reader, None);
-                Ok(Self { state: BlobReaderState::Uncompressed { expected_crc, csum_reader }})
+                BlobReaderState::Uncompressed { expected_crc, csum_reader, offset: 0, crc_valid: true }
             }
             COMPRESSED_BLOB_MAGIC_1_0 => {
                 let expected_crc = u32::from_le_bytes(head.crc);
                 let csum_reader =  ChecksumReader::new(reader, None);
 
                 let decompr = zstd::stream::read::Decoder::new(csum_reader)?;
-                Ok(Self { state: BlobReaderState::Compressed { expected_crc, decompr }})
+                BlobReaderState::Compressed { expected_crc, decompr }
             }
             ENCRYPTED_BLOB_MAGIC_1_0 => {
                 let config = config.ok_or_else(|| format_err!("unable to read encrypted blob without key"))?;
@@ -48,7 +52,7 @@ impl <R: Read> DataBlobReader<R> {
                 reader.read_exact(&mut expected_tag)?;
                 let csum_reader = ChecksumReader::new(reader, None);
                 let decrypt_reader = CryptReader::new(BufReader::with_capacity(64*1024, csum_reader), iv, expected_tag, config)?;
-                Ok(Self { state: BlobReaderState::Encrypted { expected_crc, decrypt_reader }})
+                BlobReaderState::Encrypted { expected_crc, decrypt_reader }
             }
             ENCR_COMPR_BLOB_MAGIC_1_0 => {
                 let config = config.ok_or_else(|| format_err!("unable to read encrypted blob without key"))?;
@@ -60,17 +64,28 @@ impl <R: Read> DataBlobReader<R> {
                 let csum_reader = ChecksumReader::new(reader, None);
                 let decrypt_reader = CryptReader::new(BufReader::with_capacity(64*1024, csum_reader), iv, expected_tag, config)?;
                 let decompr = zstd::stream::read::Decoder::new(decrypt_reader)?;
-                Ok(Self { state: BlobReaderState::EncryptedCompressed { expected_crc, decompr }})
+                BlobReaderState::EncryptedCompressed { expected_crc, decompr }
             }
             _ => bail!("got wrong magic number {:?}", head.magic)
-        }
+        };
+
+        Ok(Self { state, max_size: None, read_size: 0 })
+    }
+
+    /// Fail if the decoded data exceeds `max_size`
+    ///
+    /// This avoids unbounded reads for blobs from untrusted sources
+    /// (compression bombs).
+    pub fn with_size_limit(mut self, max_size: usize) -> Self {
+        self.max_size = Some(max_size);
+        self
     }
 
     pub fn finish(self) -> Result<R, Error> {
         match self.state {
-            BlobReaderState::Uncompressed { csum_reader, expected_crc } => {
+            BlobReaderState::Uncompressed { csum_reader, expected_crc, crc_valid, .. } => {
                 let (reader, crc, _) = csum_reader.finish()?;
-                if crc != expected_crc {
+                if crc_valid && crc != expected_crc {
                     bail!("blob crc check failed");
                 }
                 Ok(reader)
@@ -107,9 +122,11 @@ impl <R: Read> DataBlobReader<R> {
 impl <R: Read> Read for DataBlobReader<R> {
 
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, std::io::Error> {
-        match &mut self.state {
-            BlobReaderState::Uncompressed { csum_reader, .. } => {
-                csum_reader.read(buf)
+        let count = match &mut self.state {
+            BlobReaderState::Uncompressed { csum_reader, offset, .. } => {
+                let count = csum_reader.read(buf)?;
+                *offset += count as u64;
+                Ok(count)
             }
             BlobReaderState::Compressed { decompr, .. } => {
                 decompr.read(buf)
@@ -120,6 +137,74 @@ impl <R: Read> Read for DataBlobReader<R> {
             BlobReaderState::EncryptedCompressed { decompr, .. } => {
                 decompr.read(buf)
             }
+        }?;
+
+        self.read_size += count;
+        if let Some(max_size) = self.max_size {
+            if self.read_size > max_size {
+                proxmox::io_bail!("decompressed size exceeds limit ({} bytes)", max_size);
+            }
+        }
+
+        Ok(count)
+    }
+}
+
+/// Seeking is only supported for uncompressed, unencrypted blobs.
+///
+/// The CRC can only be computed while the data is read sequentially from the
+/// start, so `finish` does not check it after seeking to a non-zero offset.
+/// Seeking back to the start restarts the CRC computation.
+impl <R: Read + Seek> Seek for DataBlobReader<R> {
+
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, std::io::Error> {
+        let (csum_reader, offset, crc_valid) = match &mut self.state {
+            BlobReaderState::Uncompressed { csum_reader, offset, crc_valid, .. } => {
+                (csum_reader, offset, crc_valid)
+            }
+            _ => {
+                return Err(std::io::Error::from_raw_os_error(libc::EOPNOTSUPP));
+            }
+        };
+
+        let reader = csum_reader.get_mut();
+
+        // the blob does not necessarily start at the beginning of the reader
+        let data_start = reader.seek(SeekFrom::Current(0))? - *offset;
+
+        let new_offset = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::Current(delta) => checked_add_signed(*offset, delta),
+            SeekFrom::End(delta) => {
+                let data_end = reader.seek(SeekFrom::End(0))?;
+                checked_add_signed(data_end - data_start, delta)
+            }
+        };
+
+        let new_offset = match new_offset {
+            Some(new_offset) => new_offset,
+            None => proxmox::io_bail!("invalid seek to a negative or overflowing position"),
+        };
+
+        reader.seek(SeekFrom::Start(data_start + new_offset))?;
+
+        if new_offset == 0 {
+            csum_reader.reset_crc();
+            *crc_valid = true;
+        } else if new_offset != *offset {
+            *crc_valid = false;
         }
+        *offset = new_offset;
+        self.read_size = new_offset as usize;
+
+        Ok(new_offset)
+    }
+}
+
+fn checked_add_signed(value: u64, delta: i64) -> Option<u64> {
+    if delta < 0 {
+        value.checked_sub(delta.wrapping_neg() as u64) // also handles i64::MIN
+    } else {
+        value.checked_add(delta as u64)
     }
 }
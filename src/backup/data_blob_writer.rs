@@ -1,10 +1,52 @@
-use anyhow::{Error};
+use anyhow::{bail, format_err, Error};
 use std::sync::Arc;
-use std::io::{Write, Seek, SeekFrom};
-use proxmox::tools::io::WriteExt;
+use std::io::{Cursor, Read, Write, Seek, SeekFrom};
+use std::os::unix::io::AsRawFd;
+use proxmox::tools::io::{ReadExt, WriteExt};
 
 use super::*;
 
+/// Options for [DataBlobWriter::finish]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FinishOptions {
+    /// Sync the target to stable storage (`fsync` for files)
+    pub fsync: bool,
+    /// Read back the header and check magic and CRC
+    pub verify_written: bool,
+}
+
+impl Default for FinishOptions {
+    fn default() -> Self {
+        Self { fsync: true, verify_written: false }
+    }
+}
+
+/// Target for [DataBlobWriter]
+///
+/// The header is written last, so the target needs to be seekable, and
+/// readable to verify the written header.
+pub trait BlobWriterTarget: Read + Write + Seek {
+    /// Sync written data to stable storage. Does nothing by default.
+    fn sync_data(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl BlobWriterTarget for std::fs::File {
+    fn sync_data(&mut self) -> Result<(), Error> {
+        nix::unistd::fsync(self.as_raw_fd())?;
+        Ok(())
+    }
+}
+
+impl BlobWriterTarget for Cursor<Vec<u8>> {}
+
+impl <T: BlobWriterTarget + ?Sized> BlobWriterTarget for &mut T {
+    fn sync_data(&mut self) -> Result<(), Error> {
+        (**self).sync_data()
+    }
+}
+
 enum BlobWriterState<W: Write> {
     Uncompressed { csum_writer: ChecksumWriter<W> },
     Compressed { compr: zstd::stream::write::Encoder<ChecksumWriter<W>> },
@@ -73,8 +115,14 @@ impl <W: Write + Seek> DataBlobWriter<W> {
         Ok(Self { state: BlobWriterState::EncryptedCompressed { compr }})
     }
 
-    pub fn finish(self) -> Result<W, Error> {
-        match self.state {
+}
+
+impl <W: BlobWriterTarget> DataBlobWriter<W> {
+
+    /// Finish the blob: write the final header, flush and (optionally)
+    /// sync the target, and check that the header was persisted.
+    pub fn finish(self, options: FinishOptions) -> Result<W, Error> {
+        let (mut writer, magic, crc) = match self.state {
             BlobWriterState::Uncompressed { csum_writer } => {
                 // write CRC
                 let (mut writer, crc, _) = csum_writer.finish()?;
@@ -85,7 +133,7 @@ impl <W: Write + Seek> DataBlobWriter<W> {
                     writer.write_le_value(head)?;
                 }
 
-                Ok(writer)
+                (writer, UNCOMPRESSED_BLOB_MAGIC_1_0, crc)
             }
             BlobWriterState::Compressed { compr } => {
                 let csum_writer = compr.finish()?;
@@ -98,7 +146,7 @@ impl <W: Write + Seek> DataBlobWriter<W> {
                     writer.write_le_value(head)?;
                 }
 
-                Ok(writer)
+                (writer, COMPRESSED_BLOB_MAGIC_1_0, crc)
             }
             BlobWriterState::Encrypted { crypt_writer } => {
                 let (csum_writer, iv, tag) = crypt_writer.finish()?;
@@ -112,7 +160,8 @@ impl <W: Write + Seek> DataBlobWriter<W> {
                 unsafe {
                     writer.write_le_value(head)?;
                 }
-                Ok(writer)
+
+                (writer, ENCRYPTED_BLOB_MAGIC_1_0, crc)
             }
             BlobWriterState::EncryptedCompressed { compr } => {
                 let crypt_writer = compr.finish()?;
@@ -127,9 +176,33 @@ impl <W: Write + Seek> DataBlobWriter<W> {
                 unsafe {
                     writer.write_le_value(head)?;
                 }
-                Ok(writer)
+
+                (writer, ENCR_COMPR_BLOB_MAGIC_1_0, crc)
+            }
+        };
+
+        writer.flush()?;
+
+        if options.fsync {
+            writer.sync_data()
+                .map_err(|err| format_err!("unable to sync blob data - {}", err))?;
+        }
+
+        if options.verify_written {
+            let pos = writer.seek(SeekFrom::Current(0))?;
+            writer.seek(SeekFrom::Start(0))?;
+            let head: DataBlobHeader = unsafe { writer.read_le_value()? };
+            writer.seek(SeekFrom::Start(pos))?;
+
+            if head.magic != magic {
+                bail!("verify written blob failed - wrong magic number");
+            }
+            if u32::from_le_bytes(head.crc) != crc {
+                bail!("verify written blob failed - wrong CRC checksum");
             }
         }
+
+        Ok(writer)
     }
 }
 
@@ -169,3 +242,62 @@ impl <W: Write + Seek> Write for DataBlobWriter<W> {
         }
     }
 }
+
+/// Data blob writer for non-seekable targets (pipes, sockets)
+///
+/// `DataBlobWriter` needs `Seek` to update the header (CRC, IV and tag)
+/// after all data is written. This variant writes the blob into an
+/// in-memory buffer instead, and copies the complete blob to the target
+/// writer on `finish()`.
+///
+/// Memory usage: the whole (compressed/encrypted) blob is kept in memory
+/// until `finish()`, whereas the seekable writer only needs small, constant
+/// buffers. This is fine for typical blob contents (manifests, indexes,
+/// logs), but use `DataBlobWriter` if the target supports `Seek`.
+pub struct BufferedDataBlobWriter<W: Write> {
+    inner: DataBlobWriter<Cursor<Vec<u8>>>,
+    writer: W,
+}
+
+impl <W: Write> BufferedDataBlobWriter<W> {
+
+    pub fn new_uncompressed(writer: W) -> Result<Self, Error> {
+        let inner = DataBlobWriter::new_uncompressed(Cursor::new(Vec::new()))?;
+        Ok(Self { inner, writer })
+    }
+
+    pub fn new_compressed(writer: W) -> Result<Self, Error> {
+        let inner = DataBlobWriter::new_compressed(Cursor::new(Vec::new()))?;
+        Ok(Self { inner, writer })
+    }
+
+    pub fn new_encrypted(writer: W, config: Arc<CryptConfig>) -> Result<Self, Error> {
+        let inner = DataBlobWriter::new_encrypted(Cursor::new(Vec::new()), config)?;
+        Ok(Self { inner, writer })
+    }
+
+    pub fn new_encrypted_compressed(writer: W, config: Arc<CryptConfig>) -> Result<Self, Error> {
+        let inner = DataBlobWriter::new_encrypted_compressed(Cursor::new(Vec::new()), config)?;
+        Ok(Self { inner, writer })
+    }
+
+    /// Finish the blob and write it (header first) to the target writer
+    pub fn finish(self) -> Result<W, Error> {
+        let data = self.inner.finish(FinishOptions { fsync: false, verify_written: false })?.into_inner();
+        let mut writer = self.writer;
+        writer.write_all(&data)?;
+        writer.flush()?;
+        Ok(writer)
+    }
+}
+
+impl <W: Write> Write for BufferedDataBlobWriter<W> {
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize, std::io::Error> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> Result<(), std::io::Error> {
+        self.inner.flush()
+    }
+}
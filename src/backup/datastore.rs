@@ -2,6 +2,7 @@ use std::collections::{HashSet, HashMap};
 use std::io::{self, Write};
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::convert::TryFrom;
 use std::str::FromStr;
 use std::time::Duration;
@@ -13,18 +14,25 @@ use lazy_static::lazy_static;
 use proxmox::tools::fs::{replace_file, file_read_optional_string, CreateOptions, open_file_locked};
 
 use super::backup_info::{BackupGroup, BackupDir};
-use super::chunk_store::ChunkStore;
+use super::chunk_store::{ChunkDirFanOut, ChunkStore, RepairReport};
 use super::dynamic_index::{DynamicIndexReader, DynamicIndexWriter};
 use super::fixed_index::{FixedIndexReader, FixedIndexWriter};
 use super::manifest::{MANIFEST_BLOB_NAME, MANIFEST_LOCK_NAME, CLIENT_LOG_BLOB_NAME, BackupManifest};
+use super::prune::{compute_prune_info, PruneOptions};
 use super::index::*;
-use super::{DataBlob, ArchiveType, archive_type};
+use super::{DataBlob, ArchiveType, ReadChunk, archive_type};
 use crate::config::datastore::{self, DataStoreConfig};
 use crate::task::TaskState;
 use crate::tools;
 use crate::tools::format::HumanByte;
-use crate::tools::fs::{lock_dir_noblock, DirLockGuard};
-use crate::api2::types::{Authid, GarbageCollectionStatus};
+use crate::tools::fs::{lock_dir_noblock, lock_dir_noblock_shared, DirLockGuard};
+use crate::api2::types::{
+    Authid,
+    GarbageCollectionPhase,
+    GarbageCollectionStatus,
+    ZfsDatasetOptions,
+    ZFS_DATASTORE_PROPERTIES,
+};
 use crate::server::UPID;
 
 lazy_static! {
@@ -39,12 +47,43 @@ pub struct DataStore {
     chunk_store: Arc<ChunkStore>,
     gc_mutex: Mutex<()>,
     last_gc_status: Mutex<GarbageCollectionStatus>,
+    gc_progress: Mutex<Option<GarbageCollectionStatus>>,
     verify_new: bool,
+    readonly: AtomicBool,
+}
+
+/// Result of `DataStore::prune_and_collect`
+#[derive(Default, Debug)]
+pub struct PruneGcResult {
+    /// Snapshots of the group and whether they were kept (oldest first)
+    pub prune_info: Vec<(BackupDir, bool)>,
+    pub snapshots_deleted: u64,
+    pub chunks_freed: u64,
+    pub bytes_freed: u64,
+}
+
+/// Sentinel file marking a datastore as read-only (see `DataStore::set_readonly`)
+const READONLY_SENTINEL_NAME: &str = ".readonly";
+
+fn readonly_sentinel_path(base: &Path) -> PathBuf {
+    base.join(READONLY_SENTINEL_NAME)
 }
 
 impl DataStore {
 
     pub fn lookup_datastore(name: &str) -> Result<Arc<DataStore>, Error> {
+        let datastore = Self::lookup_datastore_impl(name, false)?;
+        datastore.chunk_store.check_not_migrating()?;
+        Ok(datastore)
+    }
+
+    /// Like `lookup_datastore`, but also returns a datastore with an
+    /// unfinished chunk fan-out migration, so that it can be resumed.
+    pub fn lookup_datastore_for_migration(name: &str) -> Result<Arc<DataStore>, Error> {
+        Self::lookup_datastore_impl(name, true)
+    }
+
+    fn lookup_datastore_impl(name: &str, for_migration: bool) -> Result<Arc<DataStore>, Error> {
 
         let (config, _digest) = datastore::config()?;
         let config: datastore::DataStoreConfig = config.lookup("datastore", name)?;
@@ -54,15 +93,26 @@ impl DataStore {
 
         if let Some(datastore) = map.get(name) {
             // Compare Config - if changed, create new Datastore object!
+            // The fan-out file gets replaced when a chunk fan-out migration
+            // starts or finishes, so a stat is enough to detect that.
             if datastore.chunk_store.base == path &&
-                datastore.verify_new == config.verify_new.unwrap_or(false)
+                datastore.verify_new == config.verify_new.unwrap_or(false) &&
+                datastore.chunk_store.verify_on_insert() == config.verify_on_insert.unwrap_or(false) &&
+                (for_migration || !datastore.chunk_store.fan_out_changed()?)
             {
+                // may have been changed by another process
+                datastore.readonly.store(readonly_sentinel_path(&path).exists(), Ordering::SeqCst);
                 return Ok(datastore.clone());
             }
         }
 
         let datastore = DataStore::open_with_path(name, &path, config)?;
 
+        // keep the cached instance, a running migration holds its locks
+        if !for_migration {
+            datastore.chunk_store.check_not_migrating()?;
+        }
+
         let datastore = Arc::new(datastore);
         map.insert(name.to_string(), datastore.clone());
 
@@ -81,8 +131,47 @@ impl DataStore {
         Ok(())
     }
 
+    /// Create a ZFS dataset for a datastore on an existing pool
+    ///
+    /// The dataset gets the properties recommended for datastores (see
+    /// `ZFS_DATASTORE_PROPERTIES`). Returns the mountpoint of the new dataset.
+    pub fn create_on_zfs_pool(
+        pool: &str,
+        dataset_name: &str,
+        options: ZfsDatasetOptions,
+    ) -> Result<PathBuf, Error> {
+        crate::tools::disks::zpool_list(Some(pool.to_string()), false)
+            .map_err(|err| format_err!("no such zpool '{}' - {}", pool, err))?;
+
+        let mountpoint = match options.mountpoint {
+            Some(ref mountpoint) => PathBuf::from(mountpoint),
+            None => PathBuf::from(format!("/mnt/datastore/{}", dataset_name)),
+        };
+
+        if mountpoint.exists() {
+            bail!("path {:?} already exists", mountpoint);
+        }
+
+        let mut command = std::process::Command::new("zfs");
+        command.args(zfs_create_dataset_args(pool, dataset_name, &mountpoint, &options)?);
+        tools::run_command(command, None)?;
+
+        Ok(mountpoint)
+    }
+
+    /// Destroy a dataset created by `create_on_zfs_pool`
+    pub fn destroy_zfs_dataset(pool: &str, dataset_name: &str) -> Result<(), Error> {
+        let mut command = std::process::Command::new("zfs");
+        command.args(&["destroy", &format!("{}/{}", pool, dataset_name)]);
+        tools::run_command(command, None)?;
+
+        Ok(())
+    }
+
     fn open_with_path(store_name: &str, path: &Path, config: DataStoreConfig) -> Result<Self, Error> {
-        let chunk_store = ChunkStore::open(store_name, path)?;
+        // lookup_datastore refuses access during a chunk fan-out migration
+        let mut chunk_store = ChunkStore::open_for_migration(store_name, path)?;
+        chunk_store.set_verify_on_insert(config.verify_on_insert.unwrap_or(false));
 
         let mut gc_status_path = chunk_store.base_path();
         gc_status_path.push(".gc-status");
@@ -99,14 +188,52 @@ impl DataStore {
             GarbageCollectionStatus::default()
         };
 
+        let readonly = readonly_sentinel_path(path).exists();
+
         Ok(Self {
             chunk_store: Arc::new(chunk_store),
             gc_mutex: Mutex::new(()),
             last_gc_status: Mutex::new(gc_status),
+            gc_progress: Mutex::new(None),
             verify_new: config.verify_new.unwrap_or(false),
+            readonly: AtomicBool::new(readonly),
         })
     }
 
+    /// Returns true if the datastore is in read-only mode
+    pub fn is_readonly(&self) -> bool {
+        self.readonly.load(Ordering::SeqCst)
+    }
+
+    /// Enable or disable read-only mode
+    ///
+    /// This is persisted with a `.readonly` sentinel file in the datastore
+    /// directory. In read-only mode, everything which modifies the datastore
+    /// (new backups, chunk inserts, removing snapshots, garbage collection)
+    /// fails, while reading and verification still work.
+    pub fn set_readonly(&self, enable: bool) -> Result<(), Error> {
+        let path = readonly_sentinel_path(&self.base_path());
+
+        if enable {
+            replace_file(&path, b"", CreateOptions::new())?;
+        } else if let Err(err) = std::fs::remove_file(&path) {
+            if err.kind() != io::ErrorKind::NotFound {
+                bail!("unable to remove {:?} - {}", path, err);
+            }
+        }
+
+        self.readonly.store(enable, Ordering::SeqCst);
+
+        Ok(())
+    }
+
+    fn check_writable(&self) -> Result<(), Error> {
+        if self.is_readonly() {
+            bail!("datastore '{}' is in read-only mode", self.name());
+        }
+        Ok(())
+    }
+
     pub fn get_chunk_iterator(
         &self,
     ) -> Result<
@@ -118,6 +245,8 @@ impl DataStore {
 
     pub fn create_fixed_writer<P: AsRef<Path>>(&self, filename: P, size: usize, chunk_size: usize) -> Result<FixedIndexWriter, Error> {
 
+        self.check_writable()?;
+
         let index = FixedIndexWriter::create(self.chunk_store.clone(), filename.as_ref(), size, chunk_size)?;
 
         Ok(index)
@@ -136,6 +265,8 @@ impl DataStore {
         &self, filename: P,
     ) -> Result<DynamicIndexWriter, Error> {
 
+        self.check_writable()?;
+
         let index = DynamicIndexWriter::create(
             self.chunk_store.clone(), filename.as_ref())?;
 
@@ -252,6 +383,8 @@ impl DataStore {
     /// Remove a complete backup group including all snapshots
     pub fn remove_backup_group(&self, backup_group: &BackupGroup) ->  Result<(), Error> {
 
+        self.check_writable()?;
+
         let full_path = self.group_path(backup_group);
 
         let _guard = tools::fs::lock_dir_noblock(&full_path, "backup group", "possible running backup")?;
@@ -279,6 +412,8 @@ impl DataStore {
     /// Remove a backup directory including all content
     pub fn remove_backup_dir(&self, backup_dir: &BackupDir, force: bool) ->  Result<(), Error> {
 
+        self.check_writable()?;
+
         let full_path = self.snapshot_path(backup_dir);
 
         let (_guard, _manifest_guard);
@@ -373,6 +508,8 @@ impl DataStore {
         backup_group: &BackupGroup,
         auth_id: &Authid,
     ) -> Result<(Authid, DirLockGuard), Error> {
+        self.check_writable()?;
+
         // create intermediate path first:
         let mut full_path = self.base_path();
         full_path.push(backup_group.backup_type());
@@ -403,6 +540,8 @@ impl DataStore {
     pub fn create_locked_backup_dir(&self, backup_dir: &BackupDir)
         -> Result<(PathBuf, bool, DirLockGuard), Error>
     {
+        self.check_writable()?;
+
         let relative_path = backup_dir.relative_path();
         let mut full_path = self.base_path();
         full_path.push(&relative_path);
@@ -417,6 +556,82 @@ impl DataStore {
         }
     }
 
+    /// Clone a backup snapshot into a new snapshot
+    ///
+    /// All index and blob files are hard-linked (copied if that is not possible),
+    /// so no chunk data needs to be touched. The new snapshot gets a new manifest
+    /// with the updated backup time (without verification state). If the target
+    /// group does not exist, it gets created with the owner of the source group,
+    /// an existing target group needs to have the same owner.
+    ///
+    /// Snapshots with signed manifests cannot be cloned, because the signature
+    /// covers the backup type, id and time.
+    pub fn clone_snapshot(
+        &self,
+        src: &BackupDir,
+        dst_group: &BackupGroup,
+        dst_time: i64,
+    ) -> Result<BackupDir, Error> {
+        let dst = BackupDir::with_group(dst_group.clone(), dst_time)?;
+
+        let src_path = self.snapshot_path(src);
+        let _src_guard = lock_dir_noblock_shared(&src_path, "snapshot", "locked by another operation")?;
+
+        let (src_manifest, _) = self.load_manifest(src)?;
+        if src_manifest.signature.is_some() {
+            bail!("unable to clone snapshot {} - manifest is signed", src);
+        }
+
+        let owner = self.get_owner(src.group())?;
+        let (dst_owner, _group_guard) = self.create_locked_backup_group(dst_group, &owner)?;
+        if dst_owner != owner {
+            // do not add snapshots to a group owned by someone else
+            bail!("owner check failed ({} != {})", owner, dst_owner);
+        }
+
+        let (_, is_new, _dst_guard) = self.create_locked_backup_dir(&dst)?;
+        if !is_new {
+            bail!("snapshot {} already exists", dst);
+        }
+
+        let dst_path = self.snapshot_path(&dst);
+
+        let result = proxmox::try_block!({
+            for info in src_manifest.files() {
+                link_or_copy(&src_path.join(&info.filename), &dst_path.join(&info.filename))?;
+            }
+
+            let client_log_path = src_path.join(CLIENT_LOG_BLOB_NAME);
+            if client_log_path.exists() {
+                link_or_copy(&client_log_path, &dst_path.join(CLIENT_LOG_BLOB_NAME))?;
+            }
+
+            let mut manifest = BackupManifest::new(dst.clone());
+            for info in src_manifest.files() {
+                manifest.add_file(info.filename.clone(), info.size, info.csum, info.crypt_mode)?;
+            }
+            manifest.unprotected = src_manifest.unprotected.clone();
+            if let Some(unprotected) = manifest.unprotected.as_object_mut() {
+                unprotected.remove("verify_state");
+            }
+            manifest.unprotected["cloned-from"] = src.to_string().into();
+
+            let manifest = manifest.to_string(None)?;
+            let blob = DataBlob::encode(manifest.as_bytes(), None, true)?;
+            replace_file(&dst_path.join(MANIFEST_BLOB_NAME), blob.raw_data(), CreateOptions::new())?;
+
+            Ok(())
+        });
+
+        if let Err(err) = result {
+            // do not leave a half-cloned snapshot around
+            let _ = std::fs::remove_dir_all(&dst_path);
+            bail!("cloning snapshot {} to {} failed - {}", src, dst, err);
+        }
+
+        Ok(dst)
+    }
+
     pub fn list_images(&self) -> Result<Vec<PathBuf>, Error> {
         let base = self.base_path();
 
@@ -565,6 +780,7 @@ impl DataStore {
                     image_count,
                 );
                 last_percentage = percentage;
+                self.update_gc_progress(status, GarbageCollectionPhase::Marking);
             }
         }
 
@@ -580,16 +796,174 @@ impl DataStore {
         Ok(())
     }
 
+    // digests referenced by an index file, `None` if the file vanished
+    fn index_chunk_digests(&self, path: &Path) -> Result<Option<Vec<[u8; 32]>>, Error> {
+        let file = match std::fs::File::open(path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => bail!("can't open index {} - {}", path.to_string_lossy(), err),
+        };
+
+        let index: Box<dyn IndexFile> = match archive_type(path)? {
+            ArchiveType::FixedIndex => Box::new(FixedIndexReader::new(file)?),
+            ArchiveType::DynamicIndex => Box::new(DynamicIndexReader::new(file)?),
+            _ => bail!("cannot open index file of unknown type: {:?}", path),
+        };
+
+        let digests = (0..index.index_count())
+            .map(|pos| *index.index_digest(pos).unwrap())
+            .collect();
+
+        Ok(Some(digests))
+    }
+
+    /// Prune a backup group and remove the chunks only used by the removed snapshots
+    ///
+    /// This avoids a full garbage collection run: only chunks referenced by the removed
+    /// snapshots are candidates for removal, so the chunk store is not scanned. All remaining
+    /// index files are still read to find candidates which are in use elsewhere. Like garbage
+    /// collection, this needs the exclusive chunk store lock (backup writers of an old daemon
+    /// process are not visible as oldest writer), so it fails while backups are running.
+    /// Chunks accessed after the start of this operation are kept.
+    pub fn prune_and_collect(
+        &self,
+        group: &BackupGroup,
+        prune_options: &PruneOptions,
+        worker: &dyn TaskState,
+    ) -> Result<PruneGcResult, Error> {
+
+        self.check_writable()?;
+
+        let _gc_mutex = self.gc_mutex.try_lock()
+            .map_err(|_| format_err!("garbage collection running, cannot prune and collect"))?;
+
+        let _exclusive_lock = self.chunk_store.try_exclusive_lock()
+            .map_err(|err| format_err!("unable to prune and collect (backup running?) - {}", err))?;
+
+        let start_time = proxmox::tools::time::epoch_i64();
+
+        let list = group.list_backups(&self.base_path())?;
+        let mut prune_info = compute_prune_info(list, prune_options)?;
+        prune_info.reverse(); // delete older snapshots first
+
+        let keep_all = !prune_options.keeps_something();
+
+        let mut result = PruneGcResult::default();
+        let mut candidates: HashSet<[u8; 32]> = HashSet::new();
+
+        for (info, keep) in prune_info {
+            let keep = keep || keep_all;
+            result.prune_info.push((info.backup_dir.clone(), keep));
+            if keep {
+                continue;
+            }
+
+            // read the index files first, they are gone after removal
+            let snapshot_path = self.snapshot_path(&info.backup_dir);
+            let mut digests = Vec::new();
+            for file in info.files.iter() {
+                let path = snapshot_path.join(file);
+                if !matches!(archive_type(&path), Ok(ArchiveType::FixedIndex) | Ok(ArchiveType::DynamicIndex)) {
+                    continue;
+                }
+                if let Some(list) = self.index_chunk_digests(&path)? {
+                    digests.extend(list);
+                }
+            }
+
+            match self.remove_backup_dir(&info.backup_dir, false) {
+                Ok(()) => {
+                    result.snapshots_deleted += 1;
+                    candidates.extend(digests);
+                }
+                Err(err) => crate::task_warn!(
+                    worker,
+                    "failed to remove dir {:?}: {}",
+                    info.backup_dir.relative_path(),
+                    err,
+                ),
+            }
+        }
+
+        if !candidates.is_empty() {
+            crate::task_log!(worker, "check {} chunks of removed snapshots", candidates.len());
+
+            for img in self.list_images()? {
+                worker.check_abort()?;
+                tools::fail_on_shutdown()?;
+
+                if let Some(digests) = self.index_chunk_digests(&img)? {
+                    for digest in digests.iter() {
+                        candidates.remove(digest);
+                    }
+                }
+                if candidates.is_empty() {
+                    break;
+                }
+            }
+        }
+
+        let min_atime = start_time - 300; // add 5 mins gap for safety
+
+        for digest in candidates.iter() {
+            worker.check_abort()?;
+            if let Some(size) = self.chunk_store.remove_unused_chunk(digest, min_atime)? {
+                result.chunks_freed += 1;
+                result.bytes_freed += size;
+            }
+        }
+
+        Ok(result)
+    }
+
     pub fn last_gc_status(&self) -> GarbageCollectionStatus {
         self.last_gc_status.lock().unwrap().clone()
     }
 
+    /// Status of the running garbage collection (if any)
+    ///
+    /// The counters are updated in steps of one percent.
+    pub fn gc_progress(&self) -> Option<GarbageCollectionStatus> {
+        self.gc_progress.lock().unwrap().clone()
+    }
+
+    fn update_gc_progress(&self, status: &GarbageCollectionStatus, phase: GarbageCollectionPhase) {
+        let mut progress = status.clone();
+        progress.phase = Some(phase);
+        *self.gc_progress.lock().unwrap() = Some(progress);
+    }
+
+    /// Move all chunks to the chunk directory fan-out configured for this datastore.
+    ///
+    /// Needs the exclusive chunk store lock, so this fails while backups or
+    /// garbage collection are running. `lookup_datastore` fails until the
+    /// migration finished, use `lookup_datastore_for_migration` to resume it.
+    pub fn migrate_chunk_fanout(&self, worker: &dyn TaskState) -> Result<(), Error> {
+
+        let (config, _digest) = datastore::config()?;
+        let config: DataStoreConfig = config.lookup("datastore", self.name())?;
+
+        let fan_out = match config.chunk_dir_fan_out {
+            Some(ref fan_out) => fan_out.parse()?,
+            None => ChunkDirFanOut::default(),
+        };
+
+        let _gc_mutex = self.gc_mutex.try_lock()
+            .map_err(|_| format_err!("garbage collection running, cannot migrate chunk store"))?;
+
+        let _exclusive_lock = self.chunk_store.try_exclusive_lock()?;
+
+        self.chunk_store.migrate_fan_out(fan_out, worker)
+    }
+
     pub fn garbage_collection_running(&self) -> bool {
         !matches!(self.gc_mutex.try_lock(), Ok(_))
     }
 
     pub fn garbage_collection(&self, worker: &dyn TaskState, upid: &UPID) -> Result<(), Error> {
 
+        self.check_writable()?;
+
         if let Ok(ref mut _mutex) = self.gc_mutex.try_lock() {
 
             // avoids that we run GC if an old daemon process has still a
@@ -603,17 +977,43 @@ impl DataStore {
             let mut gc_status = GarbageCollectionStatus::default();
             gc_status.upid = Some(upid.to_string());
 
-            crate::task_log!(worker, "Start GC phase1 (mark used chunks)");
+            // The marker only exists while a GC runs, so finding one means that the
+            // previous run crashed. Marking is atime based and the sweep only removes
+            // chunks older than the cutoff, so it is safe to simply start over.
+            let marker_path = self.gc_marker_path();
+            if let Some(old_upid) = file_read_optional_string(&marker_path)? {
+                crate::task_warn!(
+                    worker,
+                    "previous garbage collection did not finish ({}), starting over",
+                    old_upid.trim(),
+                );
+            }
+            replace_file(&marker_path, upid.to_string().as_bytes(), CreateOptions::new())?;
+
+            let result = proxmox::try_block!({
+                crate::task_log!(worker, "Start GC phase1 (mark used chunks)");
+                self.update_gc_progress(&gc_status, GarbageCollectionPhase::Marking);
 
-            self.mark_used_chunks(&mut gc_status, worker)?;
+                self.mark_used_chunks(&mut gc_status, worker)?;
 
-            crate::task_log!(worker, "Start GC phase2 (sweep unused chunks)");
-            self.chunk_store.sweep_unused_chunks(
-                oldest_writer,
-                phase1_start_time,
-                &mut gc_status,
-                worker,
-            )?;
+                crate::task_log!(worker, "Start GC phase2 (sweep unused chunks)");
+                self.update_gc_progress(&gc_status, GarbageCollectionPhase::Sweeping);
+
+                self.chunk_store.sweep_unused_chunks(
+                    oldest_writer,
+                    phase1_start_time,
+                    &mut gc_status,
+                    worker,
+                    &|status| self.update_gc_progress(status, GarbageCollectionPhase::Sweeping),
+                )
+            });
+
+            // regular errors (abort, shutdown, ...) are no crash, so always clean up
+            let _ = std::fs::remove_file(&marker_path);
+            *self.gc_progress.lock().unwrap() = None;
+            result?;
+
+            gc_status.phase = Some(GarbageCollectionPhase::Done);
 
             crate::task_log!(
                 worker,
@@ -694,6 +1094,12 @@ impl DataStore {
         Ok(())
     }
 
+    fn gc_marker_path(&self) -> PathBuf {
+        let mut path = self.base_path();
+        path.push(".gc-in-progress");
+        path
+    }
+
     pub fn try_shared_chunk_store_lock(&self) -> Result<tools::ProcessLockSharedGuard, Error> {
         self.chunk_store.try_shared_lock()
     }
@@ -711,9 +1117,20 @@ impl DataStore {
         chunk: &DataBlob,
         digest: &[u8; 32],
     ) -> Result<(bool, u64), Error> {
+        self.check_writable()?;
         self.chunk_store.insert_chunk(chunk, digest)
     }
 
+    /// Replace damaged chunks with verified copies from `source`
+    pub fn repair_chunks(
+        &self,
+        source: &dyn ReadChunk,
+        damaged_chunks: &[[u8; 32]],
+        worker: &dyn TaskState,
+    ) -> Result<RepairReport, Error> {
+        self.chunk_store.repair_chunks(source, damaged_chunks, worker)
+    }
+
     pub fn load_blob(&self, backup_dir: &BackupDir, filename: &str) -> Result<DataBlob, Error> {
         let mut path = self.base_path();
         path.push(backup_dir.relative_path());
@@ -825,4 +1242,414 @@ impl DataStore {
     pub fn verify_new(&self) -> bool {
         self.verify_new
     }
+
+    /// Returns the number of failed chunk insert verifications, if `verify-on-insert` is enabled
+    pub fn insert_verify_mismatches(&self) -> Option<u64> {
+        if self.chunk_store.verify_on_insert() {
+            Some(self.chunk_store.mismatches_since_start())
+        } else {
+            None
+        }
+    }
+}
+
+// arguments for 'zfs create'
+fn zfs_create_dataset_args(
+    pool: &str,
+    dataset_name: &str,
+    mountpoint: &Path,
+    options: &ZfsDatasetOptions,
+) -> Result<Vec<String>, Error> {
+    let mut args = vec!["create".to_string()];
+
+    let mut set = |property: &str, value: String| {
+        args.push("-o".to_string());
+        args.push(format!("{}={}", property, value));
+    };
+
+    let compression = serde_json::to_value(options.compression)?;
+    set("compression", compression.as_str().unwrap_or("on").to_string());
+    for (property, value) in ZFS_DATASTORE_PROPERTIES {
+        set(property, value.to_string());
+    }
+    if let Some(quota) = options.quota {
+        set("quota", quota.to_string());
+    }
+    if let Some(reservation) = options.reservation {
+        set("reservation", reservation.to_string());
+    }
+    set("mountpoint", mountpoint.to_string_lossy().into_owned());
+
+    args.push(format!("{}/{}", pool, dataset_name));
+
+    Ok(args)
+}
+
+// Hard link a file, or copy it if the target is on another file system.
+fn link_or_copy(src: &Path, dst: &Path) -> Result<(), Error> {
+    match std::fs::hard_link(src, dst) {
+        Ok(()) => Ok(()),
+        Err(err) if err.raw_os_error() == Some(libc::EXDEV) => {
+            std::fs::copy(src, dst)
+                .map_err(|err| format_err!("unable to copy {:?} to {:?} - {}", src, dst, err))?;
+            Ok(())
+        }
+        Err(err) => bail!("unable to link {:?} to {:?} - {}", src, dst, err),
+    }
+}
+
+#[test]
+fn test_clone_snapshot() -> Result<(), Error> {
+
+    use crate::api2::types::CryptMode;
+
+    let mut path = std::fs::canonicalize(".")?; // we need absolute path
+    path.push(".testdir-clone");
+
+    if let Err(_e) = std::fs::remove_dir_all(&path) { /* ignore */ }
+
+    let user = nix::unistd::User::from_uid(nix::unistd::Uid::current())?.unwrap();
+    ChunkStore::create("test", &path, user.uid, user.gid, ChunkDirFanOut::default(), None)?;
+
+    let config: DataStoreConfig = serde_json::from_value(serde_json::json!({
+        "name": "test",
+        "path": path.to_str().unwrap(),
+    }))?;
+    let datastore = DataStore::open_with_path("test", &path, config)?;
+
+    let owner: Authid = "root@pam".parse()?;
+    let src = BackupDir::new("host", "test", 1_600_000_000)?;
+    datastore.create_locked_backup_group(src.group(), &owner)?;
+    datastore.create_locked_backup_dir(&src)?;
+    let src_path = datastore.snapshot_path(&src);
+
+    let mut manifest = BackupManifest::new(src.clone());
+
+    // one blob and one dynamic index referencing a single chunk
+    let blob = DataBlob::encode(b"some config", None, true)?;
+    replace_file(src_path.join("test.conf.blob"), blob.raw_data(), CreateOptions::new())?;
+    let csum = openssl::sha::sha256(blob.raw_data());
+    manifest.add_file("test.conf.blob".into(), blob.raw_size(), csum, CryptMode::None)?;
+
+    let (chunk, digest) = super::DataChunkBuilder::new(b"some data").build()?;
+    datastore.insert_chunk(&chunk, &digest)?;
+    let index_name = "test.pxar.didx";
+    let mut writer = datastore.create_dynamic_writer(src.relative_path().join(index_name))?;
+    writer.add_chunk(9, &digest)?;
+    writer.close()?;
+    let (csum, size) = datastore
+        .open_dynamic_reader(src.relative_path().join(index_name))?
+        .compute_csum();
+    manifest.add_file(index_name.into(), size, csum, CryptMode::None)?;
+
+    manifest.unprotected["verify_state"] = serde_json::json!({ "state": "ok" });
+    let manifest = DataBlob::encode(manifest.to_string(None)?.as_bytes(), None, true)?;
+    replace_file(src_path.join(MANIFEST_BLOB_NAME), manifest.raw_data(), CreateOptions::new())?;
+
+    let dst_group = BackupGroup::new("host", "clone");
+    let dst = datastore.clone_snapshot(&src, &dst_group, 1_600_000_100)?;
+    assert_eq!(dst, BackupDir::new("host", "clone", 1_600_000_100)?);
+    assert_eq!(datastore.get_owner(&dst_group)?, owner);
+
+    // cloning again must fail, the target exists
+    assert!(datastore.clone_snapshot(&src, &dst_group, 1_600_000_100).is_err());
+
+    // existing target groups with another owner must be refused
+    let foreign_group = BackupGroup::new("host", "foreign");
+    datastore.create_locked_backup_group(&foreign_group, &"someone@pbs".parse()?)?;
+    let err = datastore.clone_snapshot(&src, &foreign_group, 1_600_000_100).err().unwrap();
+    assert!(err.to_string().contains("owner check failed"));
+
+    // the clone must not depend on the source snapshot
+    datastore.remove_backup_dir(&src, true)?;
+
+    let (manifest, _) = datastore.load_manifest(&dst)?;
+    assert_eq!(manifest.files().len(), 2);
+    assert!(manifest.unprotected["verify_state"].is_null());
+    assert_eq!(manifest.unprotected["cloned-from"], "host/test/2020-09-13T12:26:40Z");
+
+    let blob = datastore.load_blob(&dst, "test.conf.blob")?;
+    manifest.verify_file("test.conf.blob", &openssl::sha::sha256(blob.raw_data()), blob.raw_size())?;
+    assert_eq!(blob.decode(None, None)?, b"some config");
+
+    let index = datastore.open_dynamic_reader(dst.relative_path().join(index_name))?;
+    let (csum, size) = index.compute_csum();
+    manifest.verify_file(index_name, &csum, size)?;
+    let info = index.chunk_info(0).unwrap();
+    assert_eq!(datastore.load_chunk(&info.digest)?.decode(None, Some(&info.digest))?, b"some data");
+
+    if let Err(_e) = std::fs::remove_dir_all(&path) { /* ignore */ }
+
+    Ok(())
+}
+
+#[test]
+fn test_readonly_datastore() -> Result<(), Error> {
+
+    let mut path = std::fs::canonicalize(".")?; // we need absolute path
+    path.push(".testdir-readonly");
+
+    if let Err(_e) = std::fs::remove_dir_all(&path) { /* ignore */ }
+
+    let user = nix::unistd::User::from_uid(nix::unistd::Uid::current())?.unwrap();
+    ChunkStore::create("test", &path, user.uid, user.gid, ChunkDirFanOut::default(), None)?;
+
+    let config = || -> Result<DataStoreConfig, Error> {
+        Ok(serde_json::from_value(serde_json::json!({
+            "name": "test",
+            "path": path.to_str().unwrap(),
+        }))?)
+    };
+    let datastore = DataStore::open_with_path("test", &path, config()?)?;
+    assert!(!datastore.is_readonly());
+
+    let owner: Authid = "root@pam".parse()?;
+    let snapshot = BackupDir::new("host", "test", 1_600_000_000)?;
+    datastore.create_locked_backup_group(snapshot.group(), &owner)?;
+    datastore.create_locked_backup_dir(&snapshot)?;
+
+    let (chunk, digest) = super::DataChunkBuilder::new(b"some data").build()?;
+    datastore.insert_chunk(&chunk, &digest)?;
+
+    datastore.set_readonly(true)?;
+
+    let err = datastore.insert_chunk(&chunk, &digest).unwrap_err();
+    assert!(err.to_string().contains("read-only"));
+    let other = BackupDir::new("host", "test", 1_600_000_100)?;
+    assert!(datastore.create_locked_backup_dir(&other).is_err());
+    assert!(datastore.create_locked_backup_group(&BackupGroup::new("host", "other"), &owner).is_err());
+    assert!(datastore.create_dynamic_writer(other.relative_path().join("test.didx")).is_err());
+    assert!(datastore.remove_backup_dir(&snapshot, true).is_err());
+    assert!(datastore.remove_backup_group(snapshot.group()).is_err());
+    assert!(datastore.snapshot_path(&snapshot).exists());
+
+    // reading still works
+    assert_eq!(datastore.load_chunk(&digest)?.decode(None, Some(&digest))?, b"some data");
+    assert_eq!(datastore.get_owner(snapshot.group())?, owner);
+
+    // persisted across re-opening
+    let datastore = DataStore::open_with_path("test", &path, config()?)?;
+    assert!(datastore.is_readonly());
+
+    datastore.set_readonly(false)?;
+    let datastore = DataStore::open_with_path("test", &path, config()?)?;
+    assert!(!datastore.is_readonly());
+    datastore.remove_backup_dir(&snapshot, true)?;
+
+    if let Err(_e) = std::fs::remove_dir_all(&path) { /* ignore */ }
+
+    Ok(())
+}
+
+#[test]
+fn test_prune_and_collect() -> Result<(), Error> {
+
+    use nix::sys::time::{TimeVal, TimeValLike};
+    use crate::api2::types::CryptMode;
+
+    struct TestTask;
+
+    impl TaskState for TestTask {
+        fn check_abort(&self) -> Result<(), Error> { Ok(()) }
+        fn log(&self, _level: log::Level, _message: &std::fmt::Arguments) {}
+    }
+
+    let mut path = std::fs::canonicalize(".")?; // we need absolute path
+    path.push(".testdir-prune-gc");
+
+    if let Err(_e) = std::fs::remove_dir_all(&path) { /* ignore */ }
+
+    let user = nix::unistd::User::from_uid(nix::unistd::Uid::current())?.unwrap();
+    ChunkStore::create("test", &path, user.uid, user.gid, ChunkDirFanOut::default(), None)?;
+
+    let config: DataStoreConfig = serde_json::from_value(serde_json::json!({
+        "name": "test",
+        "path": path.to_str().unwrap(),
+    }))?;
+    let datastore = DataStore::open_with_path("test", &path, config)?;
+
+    let mut digests = Vec::new();
+    for data in &["shared", "chunk-1", "chunk-2", "chunk-3", "unused"] {
+        let (chunk, digest) = super::DataChunkBuilder::new(data.as_bytes()).build()?;
+        datastore.insert_chunk(&chunk, &digest)?;
+        digests.push(digest);
+    }
+    let (shared, chunk1, chunk2, chunk3, unused) =
+        (digests[0], digests[1], digests[2], digests[3], digests[4]);
+
+    let owner: Authid = "root@pam".parse()?;
+    let group = BackupGroup::new("host", "test");
+    datastore.create_locked_backup_group(&group, &owner)?;
+
+    let create_snapshot = |backup_time: i64, chunks: &[[u8; 32]]| -> Result<(), Error> {
+        let snapshot = BackupDir::new("host", "test", backup_time)?;
+        datastore.create_locked_backup_dir(&snapshot)?;
+        let index_name = "test.pxar.didx";
+        let mut writer = datastore.create_dynamic_writer(snapshot.relative_path().join(index_name))?;
+        for (i, digest) in chunks.iter().enumerate() {
+            writer.add_chunk(((i + 1) * 6) as u64, digest)?;
+        }
+        writer.close()?;
+        let (csum, size) = datastore
+            .open_dynamic_reader(snapshot.relative_path().join(index_name))?
+            .compute_csum();
+        let mut manifest = BackupManifest::new(snapshot.clone());
+        manifest.add_file(index_name.into(), size, csum, CryptMode::None)?;
+        let manifest = DataBlob::encode(manifest.to_string(None)?.as_bytes(), None, true)?;
+        replace_file(
+            datastore.snapshot_path(&snapshot).join(MANIFEST_BLOB_NAME),
+            manifest.raw_data(),
+            CreateOptions::new(),
+        )?;
+        Ok(())
+    };
+
+    create_snapshot(1_600_000_000, &[shared, chunk1])?;
+    create_snapshot(1_600_000_100, &[chunk2])?;
+    create_snapshot(1_600_000_200, &[shared, chunk3])?;
+
+    // all chunks are old, except chunk2 (e.g. reused by a running backup)
+    let old = TimeVal::seconds(proxmox::tools::time::epoch_i64() - 3600);
+    for digest in &[shared, chunk1, chunk3, unused] {
+        nix::sys::stat::utimes(&datastore.chunk_path(digest).0, &old, &old)?;
+    }
+
+    let chunk1_size = datastore.chunk_path(&chunk1).0.metadata()?.len();
+
+    let result = datastore.prune_and_collect(&group, &PruneOptions::new().keep_last(Some(1)), &TestTask)?;
+
+    assert_eq!(result.prune_info.len(), 3);
+    assert_eq!(result.prune_info.iter().filter(|(_, keep)| *keep).count(), 1);
+    assert_eq!(result.snapshots_deleted, 2);
+    assert_eq!(result.chunks_freed, 1);
+    assert_eq!(result.bytes_freed, chunk1_size);
+
+    assert!(!datastore.chunk_path(&chunk1).0.exists());
+    for digest in &[shared, chunk2, chunk3, unused] {
+        assert!(datastore.chunk_path(digest).0.exists());
+    }
+    assert_eq!(group.list_backups(&datastore.base_path())?.len(), 1);
+
+    if let Err(_e) = std::fs::remove_dir_all(&path) { /* ignore */ }
+
+    Ok(())
+}
+
+#[test]
+fn test_garbage_collection() -> Result<(), Error> {
+
+    use nix::sys::time::{TimeVal, TimeValLike};
+
+    struct TestTask;
+
+    impl TaskState for TestTask {
+        fn check_abort(&self) -> Result<(), Error> { Ok(()) }
+        fn log(&self, _level: log::Level, _message: &std::fmt::Arguments) {}
+    }
+
+    let mut path = std::fs::canonicalize(".")?; // we need absolute path
+    path.push(".testdir-gc");
+
+    if let Err(_e) = std::fs::remove_dir_all(&path) { /* ignore */ }
+
+    let user = nix::unistd::User::from_uid(nix::unistd::Uid::current())?.unwrap();
+    ChunkStore::create("test", &path, user.uid, user.gid, ChunkDirFanOut::default(), None)?;
+
+    let config: DataStoreConfig = serde_json::from_value(serde_json::json!({
+        "name": "test",
+        "path": path.to_str().unwrap(),
+    }))?;
+    let datastore = DataStore::open_with_path("test", &path, config)?;
+
+    // every second chunk is referenced by one of several index files
+    let mut referenced = Vec::new();
+    let mut unreferenced = Vec::new();
+    for i in 0..20_000u32 {
+        let (chunk, digest) = super::DataChunkBuilder::new(&i.to_le_bytes()).build()?;
+        datastore.insert_chunk(&chunk, &digest)?;
+        if i % 2 == 0 {
+            referenced.push(digest);
+        } else {
+            unreferenced.push(digest);
+        }
+    }
+
+    let owner: Authid = "root@pam".parse()?;
+    datastore.create_locked_backup_group(&BackupGroup::new("host", "test"), &owner)?;
+
+    let mut index_count = 0;
+    for (n, chunks) in referenced.chunks(1000).enumerate() {
+        let snapshot = BackupDir::new("host", "test", 1_600_000_000 + (n as i64) * 100)?;
+        datastore.create_locked_backup_dir(&snapshot)?;
+        let index_name = snapshot.relative_path().join("test.pxar.didx");
+        let mut writer = datastore.create_dynamic_writer(index_name)?;
+        for (i, digest) in chunks.iter().enumerate() {
+            writer.add_chunk(((i + 1) * 4) as u64, digest)?;
+        }
+        writer.close()?;
+        index_count += 1;
+    }
+
+    // all chunks are older than the cutoff, only marking keeps them
+    let old = TimeVal::seconds(proxmox::tools::time::epoch_i64() - 2 * 24 * 3600);
+    for digest in referenced.iter().chain(unreferenced.iter()) {
+        nix::sys::stat::utimes(&datastore.chunk_path(digest).0, &old, &old)?;
+    }
+
+    let upid = UPID::new("garbage_collection", Some("test".to_string()), owner)?;
+    datastore.garbage_collection(&TestTask, &upid)?;
+
+    for digest in &referenced {
+        assert!(datastore.chunk_path(digest).0.exists());
+    }
+    for digest in &unreferenced {
+        assert!(!datastore.chunk_path(digest).0.exists());
+    }
+
+    let status = datastore.last_gc_status();
+    assert_eq!(status.phase, Some(GarbageCollectionPhase::Done));
+    assert_eq!(status.index_file_count, index_count);
+    assert_eq!(status.disk_chunks, referenced.len());
+    assert_eq!(status.removed_chunks, unreferenced.len());
+    assert!(datastore.gc_progress().is_none());
+    assert!(!datastore.gc_marker_path().exists());
+
+    if let Err(_e) = std::fs::remove_dir_all(&path) { /* ignore */ }
+
+    Ok(())
+}
+
+#[test]
+fn test_zfs_create_dataset_args() -> Result<(), Error> {
+    let mountpoint = Path::new("/mnt/datastore/store1");
+
+    let args = zfs_create_dataset_args("tank", "store1", mountpoint, &ZfsDatasetOptions::default())?;
+    assert_eq!(args, vec![
+        "create",
+        "-o", "compression=zstd",
+        "-o", "atime=off",
+        "-o", "xattr=sa",
+        "-o", "mountpoint=/mnt/datastore/store1",
+        "tank/store1",
+    ]);
+
+    let options = ZfsDatasetOptions {
+        quota: Some(1024 * 1024 * 1024),
+        reservation: Some(512 * 1024 * 1024),
+        compression: crate::api2::types::ZfsCompressionType::Lz4,
+        mountpoint: None,
+    };
+    let args = zfs_create_dataset_args("tank", "store1", mountpoint, &options)?;
+    assert_eq!(args, vec![
+        "create",
+        "-o", "compression=lz4",
+        "-o", "atime=off",
+        "-o", "xattr=sa",
+        "-o", "quota=1073741824",
+        "-o", "reservation=536870912",
+        "-o", "mountpoint=/mnt/datastore/store1",
+        "tank/store1",
+    ]);
+
+    Ok(())
 }
@@ -1,3 +1,4 @@
+use anyhow::{bail, Error};
 use endian_trait::Endian;
 
 // WARNING: PLEASE DO NOT MODIFY THOSE MAGIC VALUES
@@ -5,6 +6,12 @@ use endian_trait::Endian;
 // openssl::sha::sha256(b"Proxmox Backup Catalog file v1.0")[0..8]
 pub const PROXMOX_CATALOG_FILE_MAGIC_1_0: [u8; 8] = [145, 253, 96, 249, 196, 103, 88, 213];
 
+// openssl::sha::sha256(b"Proxmox Backup Catalog file v1.1")[0..8]
+pub const PROXMOX_CATALOG_FILE_MAGIC_1_1: [u8; 8] = [232, 152, 122, 234, 36, 72, 230, 145];
+
+// openssl::sha::sha256(b"Proxmox Backup Catalog file v1.2")[0..8]
+pub const PROXMOX_CATALOG_FILE_MAGIC_1_2: [u8; 8] = [241, 194, 228, 9, 121, 162, 117, 63];
+
 // openssl::sha::sha256(b"Proxmox Backup uncompressed blob v1.0")[0..8]
 pub const UNCOMPRESSED_BLOB_MAGIC_1_0: [u8; 8] = [66, 171, 56, 7, 190, 131, 112, 161];
 
@@ -61,13 +68,13 @@ pub struct EncryptedDataBlobHeader {
 
 /// Header size for different file types
 ///
-/// Panics on unknown magic numbers.
-pub fn header_size(magic: &[u8; 8]) -> usize {
-    match *magic {
+/// Fails on unknown magic numbers, e.g. from blobs written by a newer version.
+pub fn header_size(magic: &[u8; 8]) -> Result<usize, Error> {
+    Ok(match *magic {
         UNCOMPRESSED_BLOB_MAGIC_1_0 => std::mem::size_of::<DataBlobHeader>(),
         COMPRESSED_BLOB_MAGIC_1_0 => std::mem::size_of::<DataBlobHeader>(),
         ENCRYPTED_BLOB_MAGIC_1_0 => std::mem::size_of::<EncryptedDataBlobHeader>(),
         ENCR_COMPR_BLOB_MAGIC_1_0 => std::mem::size_of::<EncryptedDataBlobHeader>(),
-        _ => panic!("unknown blob magic"),
-    }
+        _ => bail!("unknown blob magic {:?}", magic),
+    })
 }
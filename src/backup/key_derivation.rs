@@ -64,6 +64,58 @@ impl KeyDerivationConfig {
     }
 }
 
+/// Master key wrapped for an additional recipient
+///
+/// The raw key is encrypted with the recipient's RSA public key
+/// (RSA-OAEP), so the matching private key can unwrap it without
+/// knowing the key config passphrase.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct EncryptedKeyRecipient {
+    /// SHA-256 fingerprint of the recipient certificate
+    pub recipient_cert_fingerprint: String,
+    #[serde(with = "proxmox::tools::serde::bytes_as_base64")]
+    pub wrapped_key: Vec<u8>,
+}
+
+impl EncryptedKeyRecipient {
+
+    /// Check that the entry was wrapped for the RSA public key of `cert`
+    ///
+    /// This cannot verify the wrapped key itself, it only checks the
+    /// certificate fingerprint and the size of the wrapped key.
+    pub fn check_cert(&self, cert: &openssl::x509::X509) -> Result<(), Error> {
+        let fingerprint = recipient_cert_fingerprint(cert)?;
+        if self.recipient_cert_fingerprint != fingerprint {
+            bail!(
+                "recipient certificate fingerprint {} does not match {}",
+                self.recipient_cert_fingerprint, fingerprint
+            );
+        }
+
+        let rsa = recipient_rsa_key(cert)?;
+        if self.wrapped_key.len() != rsa.size() as usize {
+            bail!(
+                "wrapped key has wrong size ({} != {})",
+                self.wrapped_key.len(), rsa.size()
+            );
+        }
+
+        Ok(())
+    }
+}
+
+fn recipient_cert_fingerprint(cert: &openssl::x509::X509) -> Result<String, Error> {
+    let digest = cert.digest(openssl::hash::MessageDigest::sha256())?;
+    Ok(crate::tools::format::as_fingerprint(&digest))
+}
+
+fn recipient_rsa_key(
+    cert: &openssl::x509::X509,
+) -> Result<openssl::rsa::Rsa<openssl::pkey::Public>, Error> {
+    cert.public_key()?.rsa()
+        .map_err(|err| format_err!("recipient certificate has no RSA public key - {}", err))
+}
+
 /// Encryption Key Configuration
 ///
 /// We use this struct to store secret keys. When used with a key
@@ -84,6 +136,10 @@ pub struct KeyConfig {
     /// Password hint
     #[serde(skip_serializing_if = "Option::is_none")]
     pub hint: Option<String>,
+    /// Additional recipients which can unwrap the key with their private key
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default)]
+    pub recipients: Vec<EncryptedKeyRecipient>,
 }
 
 impl From<&KeyConfig> for KeyInfo {
@@ -130,6 +186,7 @@ impl KeyConfig  {
             data: raw_key.to_vec(),
             fingerprint,
             hint: None,
+            recipients: Vec::new(),
         })
     }
 
@@ -196,6 +253,7 @@ impl KeyConfig  {
             data: enc_data,
             fingerprint,
             hint: None,
+            recipients: Vec::new(),
         })
     }
 
@@ -271,6 +329,92 @@ impl KeyConfig  {
         Ok((result, self.created, fingerprint))
     }
 
+    /// Wrap the raw master key for an additional recipient.
+    ///
+    /// The key is encrypted using the RSA public key contained in `cert`
+    /// (RSA-OAEP). An existing entry for the same certificate gets replaced.
+    pub fn add_recipient(
+        &mut self,
+        cert: &openssl::x509::X509,
+        master_key: &[u8],
+    ) -> Result<(), Error> {
+
+        if master_key.len() != 32 {
+            bail!("got strange key length ({} != 32)", master_key.len())
+        }
+
+        let mut raw_key = [0u8; 32];
+        raw_key.copy_from_slice(master_key);
+
+        let crypt_config = CryptConfig::new(raw_key)?;
+        let fingerprint = crypt_config.fingerprint();
+        if let Some(ref stored_fingerprint) = self.fingerprint {
+            if &fingerprint != stored_fingerprint {
+                bail!(
+                    "master key fingerprint {} does not match key config fingerprint {}",
+                    fingerprint, stored_fingerprint
+                );
+            }
+        }
+
+        let recipient_cert_fingerprint = recipient_cert_fingerprint(cert)?;
+        let rsa = recipient_rsa_key(cert)?;
+
+        let mut wrapped_key = vec![0u8; rsa.size() as usize];
+        let len = rsa.public_encrypt(&raw_key, &mut wrapped_key, openssl::rsa::Padding::PKCS1_OAEP)?;
+        wrapped_key.truncate(len);
+
+        self.recipients.retain(|r| r.recipient_cert_fingerprint != recipient_cert_fingerprint);
+        self.recipients.push(EncryptedKeyRecipient { recipient_cert_fingerprint, wrapped_key });
+        self.modified = proxmox::tools::time::epoch_i64();
+
+        Ok(())
+    }
+
+    /// Unwrap the raw master key using a recipient's private key.
+    pub fn decrypt_for_recipient(
+        &self,
+        private_key: &openssl::pkey::PKey<openssl::pkey::Private>,
+    ) -> Result<[u8; 32], Error> {
+
+        let rsa = private_key.rsa()
+            .map_err(|err| format_err!("recipient private key is not a RSA key - {}", err))?;
+
+        let mut buffer = vec![0u8; rsa.size() as usize];
+
+        for recipient in self.recipients.iter() {
+            let len = match rsa.private_decrypt(
+                &recipient.wrapped_key,
+                &mut buffer,
+                openssl::rsa::Padding::PKCS1_OAEP,
+            ) {
+                Ok(len) => len,
+                Err(_) => continue, // wrapped for another recipient
+            };
+
+            if len != 32 {
+                continue;
+            }
+
+            let mut result = [0u8; 32];
+            result.copy_from_slice(&buffer[..32]);
+
+            let fingerprint = CryptConfig::new(result.clone())?.fingerprint();
+            if let Some(ref stored_fingerprint) = self.fingerprint {
+                if &fingerprint != stored_fingerprint {
+                    bail!(
+                        "KeyConfig contains wrong fingerprint {}, wrapped key has fingerprint {}",
+                        stored_fingerprint, fingerprint
+                    );
+                }
+            }
+
+            return Ok(result);
+        }
+
+        bail!("Unable to decrypt key - no matching recipient found");
+    }
+
     /// Store a KeyConfig to path
     pub fn store<P: AsRef<Path>>(&self, path: P, replace: bool) -> Result<(), Error> {
 
@@ -378,6 +522,7 @@ fn encrypt_decrypt_test() -> Result<(), Error> {
             22, 131, 185, 101, 156, 10, 87, 174, 25, 144, 144, 21, 155,
         ])),
         hint: None,
+        recipients: Vec::new(),
     };
 
     let encrypted = rsa_encrypt_key_config(public, &key).expect("encryption failed");
@@ -401,6 +546,7 @@ fn fingerprint_checks() -> Result<(), Error> {
         data: (0u8..32u8).collect(),
         fingerprint: Some(Fingerprint::new([0u8; 32])), // wrong FP
         hint: None,
+        recipients: Vec::new(),
     };
 
     let expected_fingerprint = Fingerprint::new([
@@ -418,6 +564,7 @@ fn fingerprint_checks() -> Result<(), Error> {
         data: (0u8..32u8).collect(),
         fingerprint: None,
         hint: None,
+        recipients: Vec::new(),
     };
 
 
@@ -430,3 +577,61 @@ fn fingerprint_checks() -> Result<(), Error> {
 
     Ok(())
 }
+
+#[cfg(test)]
+fn create_recipient_cert() -> Result<(openssl::x509::X509, openssl::pkey::PKey<openssl::pkey::Private>), Error> {
+    use openssl::x509::{X509Builder, X509NameBuilder};
+
+    let rsa = openssl::rsa::Rsa::generate(2048)?;
+    let pkey = openssl::pkey::PKey::from_rsa(rsa)?;
+
+    let mut name = X509NameBuilder::new()?;
+    name.append_entry_by_text("CN", "recipient")?;
+    let name = name.build();
+
+    let mut builder = X509Builder::new()?;
+    builder.set_version(2)?;
+    builder.set_subject_name(&name)?;
+    builder.set_issuer_name(&name)?;
+    builder.set_pubkey(&pkey)?;
+    builder.set_not_before(&*openssl::asn1::Asn1Time::days_from_now(0)?)?;
+    builder.set_not_after(&*openssl::asn1::Asn1Time::days_from_now(1)?)?;
+    builder.sign(&pkey, openssl::hash::MessageDigest::sha256())?;
+
+    Ok((builder.build(), pkey))
+}
+
+#[test]
+fn multi_recipient_test() -> Result<(), Error> {
+    let raw_key: [u8; 32] = {
+        let mut key = [0u8; 32];
+        key.iter_mut().enumerate().for_each(|(i, b)| *b = i as u8);
+        key
+    };
+
+    let mut key = KeyConfig::without_password(raw_key)?;
+
+    let (cert1, private1) = create_recipient_cert()?;
+    let (cert2, private2) = create_recipient_cert()?;
+    let (_cert3, private3) = create_recipient_cert()?;
+
+    key.add_recipient(&cert1, &raw_key)?;
+    key.add_recipient(&cert2, &raw_key)?;
+    key.add_recipient(&cert2, &raw_key)?; // replaces existing entry
+    assert_eq!(key.recipients.len(), 2);
+
+    key.recipients[0].check_cert(&cert1)?;
+    key.recipients[0].check_cert(&cert2).expect_err("check with wrong certificate worked");
+
+    key.add_recipient(&cert1, &[0u8; 32]).expect_err("adding key with wrong fingerprint worked");
+
+    // recipients must survive a JSON round-trip
+    let data = serde_json::to_vec(&key)?;
+    let key: KeyConfig = serde_json::from_slice(&data)?;
+
+    assert_eq!(key.decrypt_for_recipient(&private1)?, raw_key);
+    assert_eq!(key.decrypt_for_recipient(&private2)?, raw_key);
+    key.decrypt_for_recipient(&private3).expect_err("decryption with unknown recipient worked");
+
+    Ok(())
+}
@@ -9,6 +9,10 @@ pub struct StoreProgress {
     pub done_snapshots: u64,
     /// Total snapshots in current group
     pub group_snapshots: u64,
+    /// Completed chunks of the index currently processed
+    pub done_chunks: u64,
+    /// Total chunks of the index currently processed
+    pub index_chunks: u64,
 }
 
 impl StoreProgress {
@@ -20,12 +24,19 @@ impl StoreProgress {
     }
 
     /// Calculates an interpolated relative progress based on current counters.
+    ///
+    /// The chunk counters are interpolated into the current snapshot.
     pub fn percentage(&self) -> f64 {
         let per_groups = (self.done_groups as f64) / (self.total_groups as f64);
         if self.group_snapshots == 0 {
             per_groups
         } else {
-            let per_snapshots = (self.done_snapshots as f64) / (self.group_snapshots as f64);
+            let per_chunks = if self.index_chunks == 0 {
+                0.0
+            } else {
+                (self.done_chunks as f64) / (self.index_chunks as f64)
+            };
+            let per_snapshots = (self.done_snapshots as f64 + per_chunks) / (self.group_snapshots as f64);
             per_groups + (1.0 / self.total_groups as f64) * per_snapshots
         }
     }
@@ -77,3 +88,16 @@ impl std::fmt::Display for StoreProgress {
         }
     }
 }
+
+#[test]
+fn test_store_progress_chunks() {
+    let mut progress = StoreProgress::new(2);
+    progress.done_groups = 1;
+    progress.group_snapshots = 4;
+    progress.done_snapshots = 2;
+    assert_eq!(progress.percentage(), 0.75);
+
+    progress.index_chunks = 100;
+    progress.done_chunks = 50;
+    assert_eq!(progress.percentage(), 0.8125);
+}
@@ -50,6 +50,11 @@ impl VerifyWorker {
             corrupt_chunks: Arc::new(Mutex::new(HashSet::with_capacity(64))),
         }
     }
+
+    /// Returns the chunks found to be corrupt (or missing) so far.
+    pub fn corrupt_chunks(&self) -> HashSet<[u8; 32]> {
+        self.corrupt_chunks.lock().unwrap().clone()
+    }
 }
 
 fn verify_blob(
@@ -658,6 +658,12 @@ fn scan(param: Value) -> Result<(), Error> {
                 schema: LTO_DRIVE_PATH_SCHEMA,
                 optional: true,
             },
+            temperature: {
+                description: "Also read the drive temperature.",
+                type: bool,
+                optional: true,
+                default: false,
+            },
             "output-format": {
                 schema: OUTPUT_FORMAT,
                 optional: true,
@@ -666,12 +672,12 @@ fn scan(param: Value) -> Result<(), Error> {
     },
 )]
 /// Drive Status
-fn status(param: Value) -> Result<(), Error> {
+fn status(temperature: bool, param: Value) -> Result<(), Error> {
 
     let output_format = get_output_format(&param);
 
     let mut handle = get_tape_handle(&param)?;
-    let result = handle.get_drive_and_media_status();
+    let result = handle.get_drive_and_media_status(temperature);
 
     if output_format == "json-pretty" {
         let result = result.map_err(|err: Error| err.to_string());
@@ -479,13 +479,23 @@ struct CatalogUploadResult {
 fn spawn_catalog_upload(
     client: Arc<BackupWriter>,
     encrypt: bool,
+    lookup_table: bool,
+    file_checksums: bool,
 ) -> Result<CatalogUploadResult, Error> {
     let (catalog_tx, catalog_rx) = std::sync::mpsc::sync_channel(10); // allow to buffer 10 writes
     let catalog_stream = crate::tools::StdChannelStream(catalog_rx);
     let catalog_chunk_size = 512*1024;
     let catalog_chunk_stream = ChunkStream::new(catalog_stream, Some(catalog_chunk_size));
 
-    let catalog_writer = Arc::new(Mutex::new(CatalogWriter::new(TokioWriterAdapter::new(StdChannelWriter::new(catalog_tx)))?));
+    let catalog_tx = TokioWriterAdapter::new(StdChannelWriter::new(catalog_tx));
+    let catalog_writer = if file_checksums {
+        CatalogWriter::with_file_checksums(catalog_tx)?
+    } else if lookup_table {
+        CatalogWriter::with_lookup_table(catalog_tx)?
+    } else {
+        CatalogWriter::new(catalog_tx)?
+    };
+    let catalog_writer = Arc::new(Mutex::new(catalog_writer));
 
     let (catalog_result_tx, catalog_result_rx) = tokio::sync::oneshot::channel();
 
@@ -563,6 +573,25 @@ fn spawn_catalog_upload(
                description: "Skip lost+found directory.",
                optional: true,
            },
+           "freeze-fs": {
+               type: Boolean,
+               description: "Freeze the file system of each archived directory while it is \
+                   archived (crash consistent backup, requires root). Not possible for the file \
+                   system holding the client itself, thawed after 30 minutes at the latest.",
+               optional: true,
+           },
+           "catalog-lookup-table": {
+               type: Boolean,
+               description: "Add a lookup table to each directory in the catalog, to speed up \
+                   file lookups (not readable by older clients).",
+               optional: true,
+           },
+           "catalog-checksums": {
+               type: Boolean,
+               description: "Store a checksum of each file's content in the catalog \
+                   (not readable by older clients).",
+               optional: true,
+           },
            "backup-type": {
                schema: BACKUP_TYPE_SCHEMA,
                optional: true,
@@ -588,6 +617,16 @@ fn spawn_catalog_upload(
                    description: "Path or match pattern.",
                 }
            },
+           "include": {
+               type: Array,
+               description: "Only back up files matching these paths or patterns (file archives \
+                   only). Excludes take precedence.",
+               optional: true,
+               items: {
+                   type: String,
+                   description: "Path or match pattern.",
+                }
+           },
            "entries-max": {
                type: Integer,
                description: "Max number of entries to hold in memory.",
@@ -617,6 +656,11 @@ async fn create_backup(
 
     let skip_lost_and_found = param["skip-lost-and-found"].as_bool().unwrap_or(false);
 
+    let freeze_fs = param["freeze-fs"].as_bool().unwrap_or(false);
+
+    let catalog_lookup_table = param["catalog-lookup-table"].as_bool().unwrap_or(false);
+    let catalog_checksums = param["catalog-checksums"].as_bool().unwrap_or(false);
+
     let verbose = param["verbose"].as_bool().unwrap_or(false);
 
     let backup_time_opt = param["backup-time"].as_i64();
@@ -641,7 +685,18 @@ async fn create_backup(
     let empty = Vec::new();
     let exclude_args = param["exclude"].as_array().unwrap_or(&empty);
 
+    let include_args = param["include"].as_array();
+    let include_only = include_args.is_some();
+
     let mut pattern_list = Vec::with_capacity(exclude_args.len());
+    // the last matching pattern wins, so add includes first to let excludes override them
+    for entry in include_args.unwrap_or(&empty) {
+        let entry = entry.as_str().ok_or_else(|| format_err!("Invalid pattern string slice"))?;
+        pattern_list.push(
+            MatchEntry::parse_pattern(entry, PatternFlag::PATH_NAME, MatchType::Include)
+                .map_err(|err| format_err!("invalid include pattern entry: {}", err))?
+        );
+    }
     for entry in exclude_args {
         let entry = entry.as_str().ok_or_else(|| format_err!("Invalid pattern string slice"))?;
         pattern_list.push(
@@ -850,7 +905,12 @@ async fn create_backup(
             BackupSpecificationType::PXAR => {
                 // start catalog upload on first use
                 if catalog.is_none() {
-                    let catalog_upload_res = spawn_catalog_upload(client.clone(), crypto.mode == CryptMode::Encrypt)?;
+                    let catalog_upload_res = spawn_catalog_upload(
+                        client.clone(),
+                        crypto.mode == CryptMode::Encrypt,
+                        catalog_lookup_table,
+                        catalog_checksums,
+                    )?;
                     catalog = Some(catalog_upload_res.catalog_writer);
                     catalog_result_rx = Some(catalog_upload_res.result);
                 }
@@ -865,6 +925,11 @@ async fn create_backup(
                     entries_max: entries_max as usize,
                     skip_lost_and_found,
                     verbose,
+                    include_only,
+                    update_atime: false,
+                    read_rate_limit: None,
+                    read_threads: 0,
+                    freeze_fs,
                 };
 
                 let upload_options = UploadOptions {
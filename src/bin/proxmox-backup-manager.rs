@@ -226,6 +226,33 @@ fn task_mgmt_cli() -> CommandLineInterface {
                 schema: REMOVE_VANISHED_BACKUPS_SCHEMA,
                 optional: true,
             },
+            "verify-synced": {
+                description: "Verify newly synced snapshots.",
+                type: bool,
+                optional: true,
+            },
+            "omit-client-log": {
+                description: "Do not download client logs of synced snapshots.",
+                type: bool,
+                optional: true,
+            },
+            "check-concurrency": {
+                schema: PULL_CHECK_CONCURRENCY_SCHEMA,
+                optional: true,
+            },
+            "download-concurrency": {
+                schema: PULL_DOWNLOAD_CONCURRENCY_SCHEMA,
+                optional: true,
+            },
+            "group-concurrency": {
+                schema: PULL_GROUP_CONCURRENCY_SCHEMA,
+                optional: true,
+            },
+            resume: {
+                description: "Skip the groups already synced by a previous failed sync.",
+                type: bool,
+                optional: true,
+            },
             "output-format": {
                 schema: OUTPUT_FORMAT,
                 optional: true,
@@ -239,6 +266,12 @@ async fn pull_datastore(
     remote_store: String,
     local_store: String,
     remove_vanished: Option<bool>,
+    verify_synced: Option<bool>,
+    omit_client_log: Option<bool>,
+    check_concurrency: Option<u64>,
+    download_concurrency: Option<u64>,
+    group_concurrency: Option<u64>,
+    resume: Option<bool>,
     param: Value,
 ) -> Result<Value, Error> {
 
@@ -256,6 +289,30 @@ async fn pull_datastore(
         args["remove-vanished"] = Value::from(remove_vanished);
     }
 
+    if let Some(verify_synced) = verify_synced {
+        args["verify-synced"] = Value::from(verify_synced);
+    }
+
+    if let Some(omit_client_log) = omit_client_log {
+        args["omit-client-log"] = Value::from(omit_client_log);
+    }
+
+    if let Some(check_concurrency) = check_concurrency {
+        args["check-concurrency"] = Value::from(check_concurrency);
+    }
+
+    if let Some(download_concurrency) = download_concurrency {
+        args["download-concurrency"] = Value::from(download_concurrency);
+    }
+
+    if let Some(group_concurrency) = group_concurrency {
+        args["group-concurrency"] = Value::from(group_concurrency);
+    }
+
+    if let Some(resume) = resume {
+        args["resume"] = Value::from(resume);
+    }
+
     let result = client.post("api2/json/pull", Some(args)).await?;
 
     view_task_result(&mut client, result, &output_format).await?;
@@ -269,6 +326,14 @@ async fn pull_datastore(
             "store": {
                 schema: DATASTORE_SCHEMA,
             },
+            "repair-remote": {
+                schema: REMOTE_ID_SCHEMA,
+                optional: true,
+            },
+            "repair-remote-store": {
+                schema: DATASTORE_SCHEMA,
+                optional: true,
+            },
             "output-format": {
                 schema: OUTPUT_FORMAT,
                 optional: true,
@@ -279,6 +344,8 @@ async fn pull_datastore(
 /// Verify backups
 async fn verify(
     store: String,
+    repair_remote: Option<String>,
+    repair_remote_store: Option<String>,
     param: Value,
 ) -> Result<Value, Error> {
 
@@ -286,7 +353,13 @@ async fn verify(
 
     let mut client = connect_to_localhost()?;
 
-    let args = json!({});
+    let mut args = json!({});
+    if let Some(repair_remote) = repair_remote {
+        args["repair-remote"] = json!(repair_remote);
+    }
+    if let Some(repair_remote_store) = repair_remote_store {
+        args["repair-remote-store"] = json!(repair_remote_store);
+    }
 
     let path = format!("api2/json/admin/datastore/{}/verify", store);
 
@@ -348,6 +421,7 @@ fn main() {
 
     let cmd_def = CliCommandMap::new()
         .insert("acl", acl_commands())
+        .insert("config", config_commands())
         .insert("datastore", datastore_commands())
         .insert("disk", disk_commands())
         .insert("dns", dns_commands())
@@ -375,6 +449,7 @@ fn main() {
             CliCommand::new(&API_METHOD_VERIFY)
                 .arg_param(&["store"])
                 .completion_cb("store", config::datastore::complete_datastore_name)
+                .completion_cb("repair-remote", config::remote::complete_remote_name)
         )
         .insert("report",
             CliCommand::new(&API_METHOD_REPORT)
@@ -57,11 +57,32 @@ async fn do_update(
         }
     }
 
+    match remove_expired_tokens() {
+        Ok(()) => (),
+        Err(err) => {
+            eprintln!("error removing expired API tokens: {}", err);
+        }
+    }
+
     // TODO: cleanup tasks like in PVE?
 
     Ok(Value::Null)
 }
 
+fn remove_expired_tokens() -> Result<(), Error> {
+    let (config, _) = proxmox_backup::config::node::config()?;
+
+    if !config.remove_expired_tokens() {
+        return Ok(());
+    }
+
+    for tokenid in proxmox_backup::config::user::remove_expired_tokens()? {
+        println!("removed expired API token '{}'", tokenid);
+    }
+
+    Ok(())
+}
+
 async fn check_acme_certificates(rpcenv: &mut dyn RpcEnvironment) -> Result<(), Error> {
     let (config, _) = proxmox_backup::config::node::config()?;
 
@@ -12,11 +12,13 @@ use proxmox::api::{
         default_table_format_options, format_and_print_result_full, get_output_format,
         run_cli_command, CliCommand, CliCommandMap, CliEnvironment, ColumnConfig, OUTPUT_FORMAT,
     },
+    schema::{ArraySchema, Schema},
+    ReturnType,
 };
 use pxar::accessor::aio::Accessor;
 use pxar::decoder::aio::Decoder;
 
-use proxmox_backup::api2::{helpers, types::ArchiveEntry};
+use proxmox_backup::api2::{helpers, types::{AdsEntry, ArchiveEntry}};
 use proxmox_backup::backup::{
     decrypt_key, BackupDir, BufferedDynamicReader, CatalogReader, CryptConfig, CryptMode,
     DirEntryAttribute, IndexFile, LocalDynamicReadAt, CATALOG_NAME,
@@ -39,6 +41,11 @@ use proxmox_client_tools::{
 mod proxmox_file_restore;
 use proxmox_file_restore::*;
 
+const ADS_LIST_SCHEMA: Schema = ArraySchema::new(
+    "List of alternate data streams.",
+    &AdsEntry::API_SCHEMA,
+).schema();
+
 enum ExtractPath {
     ListArchives,
     Pxar(String, Vec<u8>),
@@ -126,6 +133,12 @@ fn keyfile_path(param: &Value) -> Option<String> {
                type: BlockDriverType,
                optional: true,
            },
+           "ads": {
+               type: Boolean,
+               description: "List the alternate data streams (NTFS) of the file instead (VM images only).",
+               optional: true,
+               default: false,
+           },
            "output-format": {
                schema: OUTPUT_FORMAT,
                optional: true,
@@ -145,6 +158,7 @@ async fn list(
     snapshot: String,
     path: String,
     base64: bool,
+    ads: bool,
     param: Value,
 ) -> Result<(), Error> {
     let repo = extract_repository_from_value(&param)?;
@@ -180,6 +194,10 @@ async fn list(
     let (manifest, _) = client.download_manifest().await?;
     manifest.check_fingerprint(crypt_config.as_ref().map(Arc::as_ref))?;
 
+    if ads && !matches!(path, ExtractPath::VM(..)) {
+        bail!("alternate data streams are only available for files in VM images");
+    }
+
     let result = match path {
         ExtractPath::ListArchives => {
             let mut entries = vec![];
@@ -230,6 +248,20 @@ async fn list(
                 Some(drv) => Some(serde_json::from_value(drv.clone())?),
                 None => None,
             };
+            if ads {
+                let list = data_list_ads(driver, details, file, path).await?;
+                let options = default_table_format_options()
+                    .sortby("name", false)
+                    .column(ColumnConfig::new("name"))
+                    .column(ColumnConfig::new("size"));
+                format_and_print_result_full(
+                    &mut json!(list),
+                    &ReturnType::new(false, &ADS_LIST_SCHEMA),
+                    &get_output_format(&param),
+                    &options,
+                );
+                return Ok(());
+            }
             data_list(driver, details, file, path).await
         }
     }?;
@@ -301,6 +333,13 @@ async fn list(
                type: BlockDriverType,
                optional: true,
            },
+           "ads": {
+               type: String,
+               description: "Extract this alternate data stream (NTFS) of the file instead \
+                   (VM images only). It is written to '<file name>:<stream name>' in the target \
+                   directory.",
+               optional: true,
+           },
        }
    }
 )]
@@ -311,6 +350,7 @@ async fn extract(
     base64: bool,
     target: Option<String>,
     verbose: bool,
+    ads: Option<String>,
     param: Value,
 ) -> Result<(), Error> {
     let repo = extract_repository_from_value(&param)?;
@@ -351,6 +391,10 @@ async fn extract(
     .await?;
     let (manifest, _) = client.download_manifest().await?;
 
+    if ads.is_some() && !matches!(path, ExtractPath::VM(..)) {
+        bail!("alternate data streams are only available for files in VM images");
+    }
+
     match path {
         ExtractPath::Pxar(archive_name, path) => {
             let file_info = manifest.lookup_file_info(&archive_name)?;
@@ -383,7 +427,22 @@ async fn extract(
                 None => None,
             };
 
-            if let Some(mut target) = target {
+            if let Some(ads) = ads {
+                let name = ads_file_name(&path, &ads)?;
+                let mut reader = data_extract_ads(driver, details, file, path, ads).await?;
+                match target {
+                    Some(mut target) => {
+                        target.push(OsStr::from_bytes(&name));
+                        let mut output = tokio::fs::File::create(&target).await.map_err(|err| {
+                            format_err!("unable to create {:?} - {}", target, err)
+                        })?;
+                        tokio::io::copy(&mut reader, &mut output).await?;
+                    }
+                    None => {
+                        tokio::io::copy(&mut reader, &mut tokio::io::stdout()).await?;
+                    }
+                }
+            } else if let Some(mut target) = target {
                 let reader = data_extract(driver, details, file, path.clone(), true).await?;
                 let decoder = Decoder::from_tokio(reader).await?;
                 extract_sub_dir_seq(&target, decoder, verbose).await?;
@@ -407,6 +466,20 @@ async fn extract(
     Ok(())
 }
 
+// file name for the extracted alternate data stream 'ads' of the file at 'path'
+fn ads_file_name(path: &[u8], ads: &str) -> Result<Vec<u8>, Error> {
+    if ads.is_empty() || ads.contains('/') {
+        bail!("invalid stream name '{}'", ads);
+    }
+    let mut name = match path.rsplit(|b| *b == b'/').next() {
+        Some(name) if !name.is_empty() => name.to_vec(),
+        _ => bail!("no file given to extract the stream '{}' from", ads),
+    };
+    name.push(b':');
+    name.extend(ads.as_bytes());
+    Ok(name)
+}
+
 async fn extract_to_target<T>(
     decoder: Accessor<T>,
     path: &[u8],
@@ -476,3 +549,16 @@ fn main() {
         Some(|future| proxmox_backup::tools::runtime::main(future)),
     );
 }
+
+#[test]
+fn test_ads_file_name() {
+    assert_eq!(
+        ads_file_name(b"/part/2/Users/doc.txt", "Zone.Identifier").unwrap(),
+        b"doc.txt:Zone.Identifier",
+    );
+    assert_eq!(ads_file_name(b"doc.txt", "info").unwrap(), b"doc.txt:info");
+
+    assert!(ads_file_name(b"/part/2/Users/", "info").is_err());
+    assert!(ads_file_name(b"/part/2/doc.txt", "").is_err());
+    assert!(ads_file_name(b"/part/2/doc.txt", "../info").is_err());
+}
@@ -420,6 +420,59 @@ async fn read_label(mut param: Value) -> Result<(), Error> {
         .column(ColumnConfig::new("media-set-uuid"))
         .column(ColumnConfig::new("media-set-ctime").renderer(render_epoch))
         .column(ColumnConfig::new("encryption-key-fingerprint"))
+        .column(ColumnConfig::new("encryption-key-error"))
+        ;
+
+    format_and_print_result_full(&mut data, &info.returns, &output_format, &options);
+
+    Ok(())
+}
+
+#[api(
+    input: {
+        properties: {
+            drive: {
+                schema: DRIVE_NAME_SCHEMA,
+                optional: true,
+            },
+            "source-slot": {
+                description: "Source slot number.",
+                type: u64,
+                minimum: 1,
+            },
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+             },
+        },
+    },
+)]
+/// Load media from the specified slot and read its label
+async fn load_and_read_label(mut param: Value) -> Result<(), Error> {
+
+    let output_format = extract_output_format(&mut param);
+
+    let (config, _digest) = config::drive::config()?;
+
+    let drive = extract_drive_name(&mut param, &config)?;
+
+    let mut client = connect_to_localhost()?;
+
+    let path = format!("api2/json/tape/drive/{}/load-and-read-label", drive);
+    let mut result = client.post(&path, Some(param)).await?;
+    let mut data = result["data"].take();
+
+    let info = &api2::tape::drive::API_METHOD_LOAD_AND_READ_LABEL;
+
+    let options = default_table_format_options()
+        .column(ColumnConfig::new("label-text"))
+        .column(ColumnConfig::new("uuid"))
+        .column(ColumnConfig::new("ctime").renderer(render_epoch))
+        .column(ColumnConfig::new("pool"))
+        .column(ColumnConfig::new("media-set-uuid"))
+        .column(ColumnConfig::new("media-set-ctime").renderer(render_epoch))
+        .column(ColumnConfig::new("encryption-key-fingerprint"))
+        .column(ColumnConfig::new("encryption-key-error"))
         ;
 
     format_and_print_result_full(&mut data, &info.returns, &output_format, &options);
@@ -723,6 +776,12 @@ async fn volume_statistics(mut param: Value) -> Result<(), Error> {
             drive: {
                 schema: DRIVE_NAME_SCHEMA,
                 optional: true,
+            },
+            temperature: {
+                description: "Also read the drive temperature.",
+                type: bool,
+                optional: true,
+                default: false,
             },
              "output-format": {
                 schema: OUTPUT_FORMAT,
@@ -770,6 +829,7 @@ async fn status(mut param: Value) -> Result<(), Error> {
         .column(ColumnConfig::new("medium-passes"))
         .column(ColumnConfig::new("medium-wearout").renderer(render_percentage))
         .column(ColumnConfig::new("volume-mounts"))
+        .column(ColumnConfig::new("temperature"))
         ;
 
     format_and_print_result_full(&mut data, &info.returns, &output_format, &options);
@@ -1078,6 +1138,12 @@ fn main() {
                 .completion_cb("drive", complete_drive_name)
                 .completion_cb("label-text", complete_media_label_text)
         )
+        .insert(
+            "load-and-read-label",
+            CliCommand::new(&API_METHOD_LOAD_AND_READ_LABEL)
+                .arg_param(&["source-slot"])
+                .completion_cb("drive", complete_drive_name)
+        )
         .insert(
             "load-media-from-slot",
             CliCommand::new(&API_METHOD_LOAD_MEDIA_FROM_SLOT)
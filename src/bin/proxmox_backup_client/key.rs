@@ -240,6 +240,47 @@ fn change_passphrase(
     Ok(())
 }
 
+#[api(
+    input: {
+        properties: {
+            cert: {
+                description: "Path to the PEM formatted recipient certificate (RSA public key).",
+            },
+            path: {
+                description: "Key file. Without this the default key will be updated.",
+                optional: true,
+            },
+        },
+    },
+)]
+/// Wrap the encryption key for an additional recipient.
+///
+/// The recipient can then decrypt the key with the private key belonging
+/// to the certificate, without knowing the key's password.
+fn add_recipient(cert: String, path: Option<String>) -> Result<(), Error> {
+    let path = match path {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let path = find_default_encryption_key()?.ok_or_else(|| {
+                format_err!("no encryption file provided and no default file found")
+            })?;
+            println!("updating default key at: {:?}", path);
+            path
+        }
+    };
+
+    let cert = openssl::x509::X509::from_pem(&file_get_contents(&cert)?)
+        .map_err(|err| format_err!("unable to parse recipient certificate - {}", err))?;
+
+    let mut key_config = KeyConfig::load(&path)?;
+    let (key, _created, _fingerprint) = key_config.decrypt(&get_encryption_key_password)?;
+
+    key_config.add_recipient(&cert, &key)?;
+    key_config.store(&path, true)?;
+
+    Ok(())
+}
+
 #[api(
     input: {
         properties: {
@@ -470,6 +511,11 @@ pub fn cli() -> CliCommandMap {
         .arg_param(&["path"])
         .completion_cb("path", tools::complete_file_name);
 
+    let key_add_recipient_cmd_def = CliCommand::new(&API_METHOD_ADD_RECIPIENT)
+        .arg_param(&["cert"])
+        .completion_cb("cert", tools::complete_file_name)
+        .completion_cb("path", tools::complete_file_name);
+
     let key_create_master_key_cmd_def = CliCommand::new(&API_METHOD_CREATE_MASTER_KEY);
     let key_import_master_pubkey_cmd_def = CliCommand::new(&API_METHOD_IMPORT_MASTER_PUBKEY)
         .arg_param(&["path"])
@@ -492,6 +538,7 @@ pub fn cli() -> CliCommandMap {
         .insert("create-master-key", key_create_master_key_cmd_def)
         .insert("import-master-pubkey", key_import_master_pubkey_cmd_def)
         .insert("change-passphrase", key_change_passphrase_cmd_def)
+        .insert("add-recipient", key_add_recipient_cmd_def)
         .insert("show", key_show_cmd_def)
         .insert("show-master-pubkey", key_show_master_pubkey_cmd_def)
         .insert("paperkey", paper_key_cmd_def)
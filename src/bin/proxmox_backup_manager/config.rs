@@ -0,0 +1,51 @@
+use anyhow::Error;
+
+use proxmox::api::{api, cli::*};
+
+use proxmox_backup::config;
+
+#[api]
+/// Encrypt configuration files (datastore.cfg, media-pool.cfg, remote.cfg).
+///
+/// Creates the master key (if it does not exist) and rewrites the files encrypted.
+fn migrate_to_encrypted() -> Result<(), Error> {
+
+    if config::encrypted::create_master_key()? {
+        println!("created master key {}", config::encrypted::MASTER_KEY_FILENAME);
+    }
+
+    {
+        let _lock = config::datastore::lock_config()?;
+        let (data, _digest) = config::datastore::config()?;
+        config::datastore::save_config(&data)?;
+        println!("encrypted {}", config::datastore::DATASTORE_CFG_FILENAME);
+    }
+
+    {
+        let _lock = config::media_pool::lock()?;
+        let (data, _digest) = config::media_pool::config()?;
+        config::media_pool::save_config(&data)?;
+        println!("encrypted {}", config::media_pool::MEDIA_POOL_CFG_FILENAME);
+    }
+
+    {
+        let _lock = proxmox::tools::fs::open_file_locked(
+            config::remote::REMOTE_CFG_LOCKFILE,
+            std::time::Duration::new(10, 0),
+            true,
+        )?;
+        let (data, _digest) = config::remote::config()?;
+        config::remote::save_config(&data)?;
+        println!("encrypted {}", config::remote::REMOTE_CFG_FILENAME);
+    }
+
+    Ok(())
+}
+
+pub fn config_commands() -> CommandLineInterface {
+
+    let cmd_def = CliCommandMap::new()
+        .insert("migrate-to-encrypted", CliCommand::new(&API_METHOD_MIGRATE_TO_ENCRYPTED));
+
+    cmd_def.into()
+}
@@ -4,6 +4,7 @@ use serde_json::Value;
 use proxmox::api::{api, cli::*, RpcEnvironment, ApiHandler};
 
 use proxmox_backup::config;
+use proxmox_backup::tools;
 use proxmox_backup::api2::{self, types::* };
 use proxmox_backup::client::{
     connect_to_localhost,
@@ -126,6 +127,10 @@ fn show_datastore(param: Value, rpcenv: &mut dyn RpcEnvironment) -> Result<Value
                 optional: true,
                 schema: PRUNE_SCHEMA_KEEP_YEARLY,
             },
+            "chunk-dir-fan-out": {
+                optional: true,
+                schema: CHUNK_DIR_FAN_OUT_SCHEMA,
+            },
             "output-format": {
                 schema: OUTPUT_FORMAT,
                 optional: true,
@@ -147,6 +152,38 @@ async fn create_datastore(mut param: Value) -> Result<Value, Error> {
     Ok(Value::Null)
 }
 
+#[api(
+    protected: true,
+    input: {
+        properties: {
+            store: {
+                schema: DATASTORE_SCHEMA,
+            },
+            "output-format": {
+                schema: OUTPUT_FORMAT,
+                optional: true,
+            },
+        },
+    },
+)]
+/// Move all chunks to the configured chunk directory fan-out.
+async fn migrate_chunk_fanout(mut param: Value) -> Result<Value, Error> {
+
+    let output_format = extract_output_format(&mut param);
+
+    let store = tools::required_string_param(&param, "store")?;
+
+    let mut client = connect_to_localhost()?;
+
+    let path = format!("api2/json/admin/datastore/{}/migrate-chunk-fanout", store);
+
+    let result = client.post(&path, None).await?;
+
+    view_task_result(&mut client, result, &output_format).await?;
+
+    Ok(Value::Null)
+}
+
 pub fn datastore_commands() -> CommandLineInterface {
 
     let cmd_def = CliCommandMap::new()
@@ -171,6 +208,11 @@ pub fn datastore_commands() -> CommandLineInterface {
                 CliCommand::new(&api2::config::datastore::API_METHOD_DELETE_DATASTORE)
                 .arg_param(&["name"])
                 .completion_cb("name", config::datastore::complete_datastore_name)
+        )
+        .insert("migrate-chunk-fanout",
+                CliCommand::new(&API_METHOD_MIGRATE_CHUNK_FANOUT)
+                .arg_param(&["store"])
+                .completion_cb("store", config::datastore::complete_datastore_name)
         );
 
     cmd_def.into()
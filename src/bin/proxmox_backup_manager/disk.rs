@@ -13,7 +13,6 @@ use proxmox_backup::api2::node::disks::{
     zfs::DISK_LIST_SCHEMA,
     zfs::ZFS_ASHIFT_SCHEMA,
     zfs::ZfsRaidLevel,
-    zfs::ZfsCompressionType,
 };
 
 use proxmox_backup::api2::{self, types::* };
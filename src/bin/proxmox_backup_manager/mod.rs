@@ -4,6 +4,8 @@ mod acme;
 pub use acme::*;
 mod cert;
 pub use cert::*;
+mod config;
+pub use config::*;
 mod datastore;
 pub use datastore::*;
 mod dns;
@@ -9,7 +9,7 @@ use std::hash::BuildHasher;
 use std::pin::Pin;
 
 use proxmox_backup::backup::{BackupDir, BackupManifest};
-use proxmox_backup::api2::types::ArchiveEntry;
+use proxmox_backup::api2::types::{AdsEntry, ArchiveEntry};
 use proxmox_backup::client::BackupRepository;
 
 use proxmox::api::{api, cli::*};
@@ -55,6 +55,23 @@ pub trait BlockRestoreDriver {
         pxar: bool,
     ) -> Async<Result<Box<dyn tokio::io::AsyncRead + Unpin + Send>, Error>>;
 
+    /// List the alternate data streams (NTFS) of the file at the given path
+    fn data_list_ads(
+        &self,
+        details: SnapRestoreDetails,
+        img_file: String,
+        path: Vec<u8>,
+    ) -> Async<Result<Vec<AdsEntry>, Error>>;
+
+    /// Read the alternate data stream 'name' of the file at the given path
+    fn data_extract_ads(
+        &self,
+        details: SnapRestoreDetails,
+        img_file: String,
+        path: Vec<u8>,
+        name: String,
+    ) -> Async<Result<Box<dyn tokio::io::AsyncRead + Unpin + Send>, Error>>;
+
     /// Return status of all running/mapped images, result value is (id, extra data), where id must
     /// match with the ones returned from list()
     fn status(&self) -> Async<Result<Vec<DriverStatus>, Error>>;
@@ -104,6 +121,27 @@ pub async fn data_extract(
     driver.data_extract(details, img_file, path, pxar).await
 }
 
+pub async fn data_list_ads(
+    driver: Option<BlockDriverType>,
+    details: SnapRestoreDetails,
+    img_file: String,
+    path: Vec<u8>,
+) -> Result<Vec<AdsEntry>, Error> {
+    let driver = driver.unwrap_or(DEFAULT_DRIVER).resolve();
+    driver.data_list_ads(details, img_file, path).await
+}
+
+pub async fn data_extract_ads(
+    driver: Option<BlockDriverType>,
+    details: SnapRestoreDetails,
+    img_file: String,
+    path: Vec<u8>,
+    name: String,
+) -> Result<Box<dyn tokio::io::AsyncRead + Send + Unpin>, Error> {
+    let driver = driver.unwrap_or(DEFAULT_DRIVER).resolve();
+    driver.data_extract_ads(details, img_file, path, name).await
+}
+
 #[api(
    input: {
        properties: {
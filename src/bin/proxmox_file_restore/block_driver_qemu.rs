@@ -9,7 +9,7 @@ use std::fs::{File, OpenOptions};
 use std::io::{prelude::*, SeekFrom};
 
 use proxmox::tools::fs::lock_file;
-use proxmox_backup::api2::types::ArchiveEntry;
+use proxmox_backup::api2::types::{AdsEntry, ArchiveEntry};
 use proxmox_backup::backup::BackupDir;
 use proxmox_backup::client::*;
 use proxmox_backup::tools;
@@ -185,19 +185,43 @@ async fn start_vm(cid_request: i32, details: &SnapRestoreDetails) -> Result<VMSt
     Ok(VMState { pid, cid, ticket })
 }
 
+// base64 encoded path of a file inside 'img_file', as used by the restore daemon API
+fn encode_vm_path(img_file: &str, mut path: Vec<u8>) -> String {
+    if !path.is_empty() && path[0] != b'/' {
+        path.insert(0, b'/');
+    }
+    base64::encode(img_file.bytes().chain(path).collect::<Vec<u8>>())
+}
+
+// stream the result of the restore daemon's 'extract' call with 'param'
+fn download_extract_stream(
+    client: VsockClient,
+    param: serde_json::Value,
+) -> Box<dyn tokio::io::AsyncRead + Unpin + Send> {
+    let (mut tx, rx) = tokio::io::duplex(1024 * 4096);
+    tokio::spawn(async move {
+        if let Err(err) = client
+            .download("api2/json/extract", Some(param), &mut tx)
+            .await
+        {
+            eprintln!("reading file extraction stream failed - {}", err);
+            std::process::exit(1);
+        }
+    });
+
+    Box::new(rx)
+}
+
 impl BlockRestoreDriver for QemuBlockDriver {
     fn data_list(
         &self,
         details: SnapRestoreDetails,
         img_file: String,
-        mut path: Vec<u8>,
+        path: Vec<u8>,
     ) -> Async<Result<Vec<ArchiveEntry>, Error>> {
         async move {
             let client = ensure_running(&details).await?;
-            if !path.is_empty() && path[0] != b'/' {
-                path.insert(0, b'/');
-            }
-            let path = base64::encode(img_file.bytes().chain(path).collect::<Vec<u8>>());
+            let path = encode_vm_path(&img_file, path);
             let mut result = client
                 .get("api2/json/list", Some(json!({ "path": path })))
                 .await?;
@@ -210,31 +234,45 @@ impl BlockRestoreDriver for QemuBlockDriver {
         &self,
         details: SnapRestoreDetails,
         img_file: String,
-        mut path: Vec<u8>,
+        path: Vec<u8>,
         pxar: bool,
     ) -> Async<Result<Box<dyn tokio::io::AsyncRead + Unpin + Send>, Error>> {
         async move {
             let client = ensure_running(&details).await?;
-            if !path.is_empty() && path[0] != b'/' {
-                path.insert(0, b'/');
-            }
-            let path = base64::encode(img_file.bytes().chain(path).collect::<Vec<u8>>());
-            let (mut tx, rx) = tokio::io::duplex(1024 * 4096);
-            tokio::spawn(async move {
-                if let Err(err) = client
-                    .download(
-                        "api2/json/extract",
-                        Some(json!({ "path": path, "pxar": pxar })),
-                        &mut tx,
-                    )
-                    .await
-                {
-                    eprintln!("reading file extraction stream failed - {}", err);
-                    std::process::exit(1);
-                }
-            });
+            let path = encode_vm_path(&img_file, path);
+            Ok(download_extract_stream(client, json!({ "path": path, "pxar": pxar })))
+        }
+        .boxed()
+    }
 
-            Ok(Box::new(rx) as Box<dyn tokio::io::AsyncRead + Unpin + Send>)
+    fn data_list_ads(
+        &self,
+        details: SnapRestoreDetails,
+        img_file: String,
+        path: Vec<u8>,
+    ) -> Async<Result<Vec<AdsEntry>, Error>> {
+        async move {
+            let client = ensure_running(&details).await?;
+            let path = encode_vm_path(&img_file, path);
+            let mut result = client
+                .get("api2/json/ads", Some(json!({ "path": path })))
+                .await?;
+            serde_json::from_value(result["data"].take()).map_err(|err| err.into())
+        }
+        .boxed()
+    }
+
+    fn data_extract_ads(
+        &self,
+        details: SnapRestoreDetails,
+        img_file: String,
+        path: Vec<u8>,
+        name: String,
+    ) -> Async<Result<Box<dyn tokio::io::AsyncRead + Unpin + Send>, Error>> {
+        async move {
+            let client = ensure_running(&details).await?;
+            let path = encode_vm_path(&img_file, path);
+            Ok(download_extract_stream(client, json!({ "path": path, "ads": name })))
         }
         .boxed()
     }
@@ -32,6 +32,7 @@ use super::{disk::ResolveResult, watchdog_remaining, watchdog_inhibit, watchdog_
 // not exist within the restore VM. Safety is guaranteed by checking a ticket via a custom ApiAuth.
 
 const SUBDIRS: SubdirMap = &[
+    ("ads", &Router::new().get(&API_METHOD_LIST_ADS)),
     ("extract", &Router::new().get(&API_METHOD_EXTRACT)),
     ("list", &Router::new().get(&API_METHOD_LIST)),
     ("status", &Router::new().get(&API_METHOD_STATUS)),
@@ -103,6 +104,7 @@ fn get_dir_entry(path: &Path) -> Result<DirEntryAttribute, Error> {
         libc::S_IFREG => DirEntryAttribute::File {
             size: stat.st_size as u64,
             mtime: stat.st_mtime,
+            csum: None,
         },
         libc::S_IFDIR => DirEntryAttribute::Directory { start: 0 },
         _ => bail!("unsupported file type: {}", stat.st_mode),
@@ -217,6 +219,43 @@ fn list(
     Ok(res)
 }
 
+#[api(
+    input: {
+        properties: {
+            "path": {
+                type: String,
+                description: "base64-encoded path of the file",
+            },
+        },
+    },
+    returns: {
+        description: "List of alternate data streams.",
+        type: Array,
+        items: { type: AdsEntry },
+    },
+    access: {
+        description: "Permissions are handled outside restore VM.",
+        permission: &Permission::Superuser,
+    },
+)]
+/// List the alternate data streams (NTFS) of a file.
+fn list_ads(
+    path: String,
+    _info: &ApiMethod,
+    _rpcenv: &mut dyn RpcEnvironment,
+) -> Result<Vec<AdsEntry>, Error> {
+    watchdog_ping();
+
+    let mut path = base64::decode(path)?;
+    if let Some(b'/') = path.last() {
+        path.pop();
+    }
+    let path = Path::new(OsStr::from_bytes(&path[..]));
+
+    let mut disk_state = crate::DISK_STATE.lock().unwrap();
+    disk_state.list_ads(&path)
+}
+
 #[sortable]
 pub const API_METHOD_EXTRACT: ApiMethod = ApiMethod::new(
     &ApiHandler::AsyncHttp(&extract),
@@ -238,6 +277,15 @@ pub const API_METHOD_EXTRACT: ApiMethod = ApiMethod::new(
                 ))
                 .default(true)
                 .schema()
+            ),
+            (
+                "ads",
+                true,
+                &StringSchema::new(concat!(
+                    "return the content of this alternate data stream of the file ",
+                    "instead of the file content (implies pxar=false)"
+                ))
+                .schema()
             )
         ]),
     ),
@@ -268,6 +316,18 @@ fn extract(
         }
         let path = Path::new(OsStr::from_bytes(&path[..]));
 
+        if let Some(ads) = param["ads"].as_str() {
+            let data = {
+                let mut disk_state = crate::DISK_STATE.lock().unwrap();
+                disk_state.read_ads(&path, ads)?
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::OK)
+                .header(header::CONTENT_TYPE, "application/octet-stream")
+                .body(Body::from(data))
+                .unwrap());
+        }
+
         let pxar = param["pxar"].as_bool().unwrap_or(true);
 
         let query_result = {
@@ -336,6 +396,11 @@ fn extract(
                         patterns,
                         verbose: false,
                         skip_lost_and_found: false,
+                        include_only: false,
+                        update_atime: false,
+                        read_rate_limit: None,
+                        read_threads: 0,
+                        freeze_fs: false,
                     };
 
                     let pxar_writer = TokioWriter::new(writer);
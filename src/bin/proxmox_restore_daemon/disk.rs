@@ -2,15 +2,19 @@
 use anyhow::{bail, format_err, Error};
 use lazy_static::lazy_static;
 use log::{info, warn};
+use serde::Deserialize;
+use serde_json::Value;
 
 use std::collections::HashMap;
 use std::fs::{create_dir_all, File};
 use std::io::{BufRead, BufReader};
 use std::path::{Component, Path, PathBuf};
+use std::process::Command;
 
 use proxmox::const_regex;
 use proxmox::tools::fs;
-use proxmox_backup::api2::types::BLOCKDEVICE_NAME_REGEX;
+use proxmox_backup::api2::types::{AdsEntry, BLOCKDEVICE_NAME_REGEX};
+use proxmox_backup::tools::xattr;
 
 const_regex! {
     VIRTIO_PART_REGEX = r"^vd[a-z]+(\d+)$";
@@ -37,6 +41,13 @@ lazy_static! {
     };
 }
 
+// Alternate data streams are exposed as 'user.<stream name>' xattrs by NTFS mounted with
+// 'streams_interface=xattr' (ntfs-3g), see ads_via_xattr()
+const ADS_XATTR_PREFIX: &[u8] = b"user.";
+
+// there is no udev in the restore VM, so LVM has to create the device nodes itself
+const LVM_NO_UDEV_CONFIG: &str = "activation { udev_sync = 0 udev_rules = 0 }";
+
 pub enum ResolveResult {
     Path(PathBuf),
     BucketTypes(Vec<&'static str>),
@@ -48,6 +59,16 @@ struct PartitionBucketData {
     number: i32,
     mountpoint: Option<PathBuf>,
     size: u64,
+    ads_xattr: bool,
+}
+
+struct LvmThinData {
+    vg_name: String,
+    thin_pool: String,
+    lv_name: String,
+    mountpoint: Option<PathBuf>,
+    size: u64,
+    ads_xattr: bool,
 }
 
 /// A "Bucket" represents a mapping found on a disk, e.g. a partition, a zfs dataset or an LV. A
@@ -60,9 +81,13 @@ struct PartitionBucketData {
 ///   path: relative path of the file on the filesystem indicated by the other parts, may contain
 ///         more subdirectories
 /// e.g.: "/drive-scsi0/part/0/etc/passwd"
+///
+/// Thin LVs use three components, the volume group, the thin pool and the LV itself, e.g.:
+/// "/drive-scsi0/lvm-thin/pve/data/vm-100-disk-0/etc/passwd"
 enum Bucket {
     Partition(PartitionBucketData),
     RawFs(PartitionBucketData),
+    LvmThinVolume(LvmThinData),
 }
 
 impl Bucket {
@@ -81,6 +106,13 @@ impl Bucket {
                 }
             }
             Bucket::RawFs(_) => ty == "raw",
+            Bucket::LvmThinVolume(data) => {
+                ty == "lvm-thin"
+                    && comp.len() == 3
+                    && comp[0].as_ref() == data.vg_name
+                    && comp[1].as_ref() == data.thin_pool
+                    && comp[2].as_ref() == data.lv_name
+            }
         })
     }
 
@@ -88,6 +120,7 @@ impl Bucket {
         match self {
             Bucket::Partition(_) => "part",
             Bucket::RawFs(_) => "raw",
+            Bucket::LvmThinVolume(_) => "lvm-thin",
         }
     }
 
@@ -104,6 +137,11 @@ impl Bucket {
         Ok(match self {
             Bucket::Partition(data) => data.number.to_string(),
             Bucket::RawFs(_) => "raw".to_owned(),
+            Bucket::LvmThinVolume(data) => match idx {
+                0 => data.vg_name.clone(),
+                1 => data.thin_pool.clone(),
+                _ => data.lv_name.clone(),
+            },
         })
     }
 
@@ -111,6 +149,7 @@ impl Bucket {
         Ok(match type_string {
             "part" => 1,
             "raw" => 0,
+            "lvm-thin" => 3,
             _ => bail!("invalid bucket type for component depth: {}", type_string),
         })
     }
@@ -118,10 +157,28 @@ impl Bucket {
     fn size(&self) -> u64 {
         match self {
             Bucket::Partition(data) | Bucket::RawFs(data) => data.size,
+            Bucket::LvmThinVolume(data) => data.size,
+        }
+    }
+
+    // mountpoint and whether the file system exposes alternate data streams as xattrs
+    fn mount_state(&self) -> (Option<&PathBuf>, bool) {
+        match self {
+            Bucket::Partition(data) | Bucket::RawFs(data) => (data.mountpoint.as_ref(), data.ads_xattr),
+            Bucket::LvmThinVolume(data) => (data.mountpoint.as_ref(), data.ads_xattr),
         }
     }
 }
 
+// Only NTFS mounted with 'streams_interface=xattr' maps alternate data streams to 'user.*'
+// xattrs, on other file systems those are just regular xattrs
+fn ads_via_xattr(fs_type: &str, options: Option<&str>) -> bool {
+    fs_type.starts_with("ntfs")
+        && options
+            .map(|opts| opts.split(',').any(|opt| opt == "streams_interface=xattr"))
+            .unwrap_or(false)
+}
+
 /// Functions related to the local filesystem. This mostly exists so we can use 'supported_fs' in
 /// try_mount while a Bucket is still mutably borrowed from DiskState.
 struct Filesystems {
@@ -157,7 +214,19 @@ impl Filesystems {
                 }
 
                 let mp = format!("/mnt{}/", data.dev_node);
-                self.try_mount(&data.dev_node, &mp)?;
+                data.ads_xattr = self.try_mount(&data.dev_node, &mp)?;
+                let mp = PathBuf::from(mp);
+                data.mountpoint = Some(mp.clone());
+                Ok(mp)
+            }
+            Bucket::LvmThinVolume(data) => {
+                if let Some(mp) = &data.mountpoint {
+                    return Ok(mp.clone());
+                }
+
+                let dev_node = activate_lv(&data.vg_name, &data.lv_name)?;
+                let mp = format!("/mnt/lvm-thin/{}/{}/", data.vg_name, data.lv_name);
+                data.ads_xattr = self.try_mount(&dev_node, &mp)?;
                 let mp = PathBuf::from(mp);
                 data.mountpoint = Some(mp.clone());
                 Ok(mp)
@@ -165,7 +234,8 @@ impl Filesystems {
         }
     }
 
-    fn try_mount(&self, source: &str, target: &str) -> Result<(), Error> {
+    // returns whether the mounted file system exposes alternate data streams as xattrs
+    fn try_mount(&self, source: &str, target: &str) -> Result<bool, Error> {
         use nix::mount::*;
 
         create_dir_all(target)?;
@@ -181,9 +251,16 @@ impl Filesystems {
             match mount(Some(source), target, Some(fs), flags, opts) {
                 Ok(()) => {
                     info!("mounting '{}' succeeded, fstype: '{}'", source, fs);
-                    return Ok(());
+                    return Ok(ads_via_xattr(fs, opts));
                 }
                 Err(nix::Error::Sys(nix::errno::Errno::EINVAL)) => {}
+                Err(nix::Error::Sys(nix::errno::Errno::ENOSPC)) => {
+                    // a thin LV's blocks need not all be provisioned, trying other fs won't help
+                    bail!(
+                        "mounting '{}' failed - no space left on device (thin pool exhausted?)",
+                        source
+                    );
+                }
                 Err(err) => {
                     warn!("mount error on '{}' ({}) - {}", source, fs, err);
                 }
@@ -194,6 +271,93 @@ impl Filesystems {
     }
 }
 
+#[derive(Deserialize)]
+struct LvsEntry {
+    vg_name: String,
+    lv_name: String,
+    lv_size: String,
+    pool_lv: String,
+    origin: String,
+    segtype: String,
+}
+
+impl LvsEntry {
+    fn is_thin_volume(&self) -> bool {
+        self.segtype == "thin" && !self.pool_lv.is_empty()
+    }
+}
+
+fn parse_lvm_report<T: serde::de::DeserializeOwned>(
+    output: &str,
+    kind: &str,
+) -> Result<Vec<T>, Error> {
+    let mut report: Value = serde_json::from_str(output)?;
+    let mut list = Vec::new();
+    if let Some(reports) = report["report"].as_array_mut() {
+        for report in reports {
+            if report[kind].is_null() {
+                continue;
+            }
+            let entries: Vec<T> = serde_json::from_value(report[kind].take())?;
+            list.extend(entries);
+        }
+    }
+    Ok(list)
+}
+
+fn lvs_report(vg: Option<&str>) -> Result<Vec<LvsEntry>, Error> {
+    let mut command = Command::new("lvs");
+    command.args(&["--readonly", "--reportformat", "json", "--units", "b", "--nosuffix"]);
+    command.args(&["-o", "vg_name,lv_name,lv_size,pool_lv,origin,segtype"]);
+    if let Some(vg) = vg {
+        command.arg(vg);
+    }
+    let output = proxmox_backup::tools::run_command(command, None)?;
+    parse_lvm_report(&output, "lv")
+}
+
+// LVM config for activating LVs of volume group 'vg' in the restore VM:
+// * there is no udev, so LVM has to create the device nodes itself
+// * the drives are attached read-only, so the whole VG must be activated read-only too, which
+//   also makes the kernel load the thin pool in read-only mode
+// * the pool metadata of a running guest's backup is only crash consistent and may well fail
+//   thin_check (and repairing it would need write access anyway), so skip the check - a
+//   read-only pool cannot be damaged by us, at worst mounting the LV fails
+fn lvm_activation_config(vg: &str) -> String {
+    format!(
+        "activation {{ udev_sync = 0 udev_rules = 0 read_only_volume_list = [ \"{}\" ] }} \
+         global {{ thin_check_executable = \"\" }}",
+        vg
+    )
+}
+
+fn lvchange_activate_args(vg: &str, lv: &str) -> Vec<String> {
+    // thin snapshots are flagged to skip activation by default, '-K' ignores that
+    vec![
+        "--config".to_string(),
+        lvm_activation_config(vg),
+        "-ay".to_string(),
+        "-K".to_string(),
+        format!("{}/{}", vg, lv),
+    ]
+}
+
+// returns the device node of the activated LV
+fn activate_lv(vg: &str, lv: &str) -> Result<String, Error> {
+    let lv_path = format!("{}/{}", vg, lv);
+
+    let mut command = Command::new("lvchange");
+    command.args(lvchange_activate_args(vg, lv));
+    proxmox_backup::tools::run_command(command, None)
+        .map_err(|err| format_err!("activating LV '{}' failed - {}", lv_path, err))?;
+
+    let mut command = Command::new("vgmknodes");
+    command.args(&["--config", LVM_NO_UDEV_CONFIG, &lv_path]);
+    proxmox_backup::tools::run_command(command, None)?;
+
+    Ok(format!("/dev/{}", lv_path))
+}
+
 pub struct DiskState {
     filesystems: Filesystems,
     disk_map: HashMap<String, Vec<Bucket>>,
@@ -207,6 +371,8 @@ impl DiskState {
         // create mapping for virtio drives and .fidx files (via serial description)
         // note: disks::DiskManager relies on udev, which we don't have
         let mut disk_map = HashMap::new();
+        // device node => fidx, to find the disk LVM physical volumes are on
+        let mut dev_map = HashMap::new();
         for entry in proxmox_backup::tools::fs::scan_subdir(
             libc::AT_FDCWD,
             "/sys/block",
@@ -233,11 +399,13 @@ impl DiskState {
             // attempt to mount device directly
             let dev_node = format!("/dev/{}", name);
             let size = Self::make_dev_node(&dev_node, &sys_path)?;
+            dev_map.insert(dev_node.clone(), fidx.clone());
             let mut dfs_bucket = Bucket::RawFs(PartitionBucketData {
                 dev_node: dev_node.clone(),
                 number: 0,
                 mountpoint: None,
                 size,
+                ads_xattr: false,
             });
             if let Ok(_) = filesystems.ensure_mounted(&mut dfs_bucket) {
                 // mount succeeded, add bucket and skip any other checks for the disk
@@ -263,6 +431,7 @@ impl DiskState {
 
                 // create partition device node for further use
                 let size = Self::make_dev_node(&dev_node, &part_path)?;
+                dev_map.insert(dev_node.clone(), fidx.clone());
 
                 let number = fs::file_read_firstline(&format!("{}/partition", part_path))?
                     .trim()
@@ -278,6 +447,7 @@ impl DiskState {
                     mountpoint: None,
                     number,
                     size,
+                    ads_xattr: false,
                 });
 
                 parts.push(bucket);
@@ -286,12 +456,96 @@ impl DiskState {
             disk_map.insert(fidx, parts);
         }
 
+        if let Err(err) = Self::scan_lvm_thin(&dev_map, &mut disk_map) {
+            warn!("scanning for LVM thin volumes failed - {}", err);
+        }
+
         Ok(Self {
             filesystems,
             disk_map,
         })
     }
 
+    fn scan_lvm_thin(
+        dev_map: &HashMap<String, String>,
+        disk_map: &mut HashMap<String, Vec<Bucket>>,
+    ) -> Result<(), Error> {
+        #[derive(Deserialize)]
+        struct PvsEntry {
+            pv_name: String,
+            vg_name: String,
+        }
+
+        let mut command = Command::new("pvs");
+        command.args(&["--readonly", "--reportformat", "json", "-o", "pv_name,vg_name"]);
+        let output = proxmox_backup::tools::run_command(command, None)?;
+
+        let mut vg_map = HashMap::new();
+        for pv in parse_lvm_report::<PvsEntry>(&output, "pv")? {
+            if let Some(fidx) = dev_map.get(&pv.pv_name) {
+                vg_map.insert(pv.vg_name, fidx.clone());
+            }
+        }
+
+        let mut lvs = lvs_report(None)?;
+        lvs.sort_unstable_by(|a, b| {
+            (&a.vg_name, &a.pool_lv, &a.lv_name).cmp(&(&b.vg_name, &b.pool_lv, &b.lv_name))
+        });
+
+        let mut pools = Vec::new();
+        for lv in lvs.iter() {
+            if lv.is_thin_volume() && !pools.contains(&(&lv.vg_name, &lv.pool_lv)) {
+                pools.push((&lv.vg_name, &lv.pool_lv));
+            }
+        }
+
+        for (vg_name, thin_pool) in pools {
+            let fidx = match vg_map.get(vg_name) {
+                Some(fidx) => fidx,
+                None => {
+                    warn!("no disk found for volume group '{}'", vg_name);
+                    continue;
+                }
+            };
+
+            let snapshots = Self::list_thin_snapshots(vg_name, thin_pool)?;
+
+            let volumes = lvs.iter().filter(|lv| {
+                lv.is_thin_volume()
+                    && &lv.vg_name == vg_name
+                    && &lv.pool_lv == thin_pool
+                    && (lv.origin.is_empty() || snapshots.contains(&lv.lv_name))
+            });
+
+            let buckets = disk_map.entry(fidx.clone()).or_insert_with(Vec::new);
+            for lv in volumes {
+                info!(
+                    "drive '{}': found thin LV '{}/{}' in pool '{}' ({}B)",
+                    fidx, vg_name, lv.lv_name, thin_pool, lv.lv_size
+                );
+                buckets.push(Bucket::LvmThinVolume(LvmThinData {
+                    vg_name: vg_name.clone(),
+                    thin_pool: thin_pool.clone(),
+                    lv_name: lv.lv_name.clone(),
+                    mountpoint: None,
+                    size: lv.lv_size.parse()?,
+                    ads_xattr: false,
+                }));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// List the names of all thin snapshots in the thin pool 'pool' of volume group 'vg'.
+    pub fn list_thin_snapshots(vg: &str, pool: &str) -> Result<Vec<String>, Error> {
+        Ok(lvs_report(Some(vg))?
+            .into_iter()
+            .filter(|lv| lv.is_thin_volume() && lv.pool_lv == pool && !lv.origin.is_empty())
+            .map(|lv| lv.lv_name)
+            .collect())
+    }
+
     /// Given a path like "/drive-scsi0.img.fidx/part/0/etc/passwd", this will mount the first
     /// partition of 'drive-scsi0' on-demand (i.e. if not already mounted) and return a path
     /// pointing to the requested file locally, e.g. "/mnt/vda1/etc/passwd", which can be used to
@@ -345,19 +599,25 @@ impl DiskState {
                 Some(Component::Normal(x)) => x.to_string_lossy(),
                 Some(c) => bail!("invalid bucket component in path: {:?}", c),
                 None => {
-                    // list bucket components available at this level
-                    let comps = buckets
-                        .iter()
-                        .filter_map(|b| {
-                            if b.type_string() != bucket_type {
-                                return None;
-                            }
-                            match b.component_string(components.len()) {
-                                Ok(cs) => Some((cs.to_owned(), b.size())),
-                                Err(_) => None,
-                            }
-                        })
-                        .collect();
+                    // list bucket components available at this level, below the already
+                    // given ones - for intermediate levels sum up the sizes
+                    let mut comps: Vec<(String, u64)> = Vec::new();
+                    let matching = buckets.iter().filter(|b| {
+                        b.type_string() == bucket_type
+                            && components.iter().enumerate().all(|(idx, comp)| {
+                                matches!(b.component_string(idx), Ok(cs) if cs == *comp)
+                            })
+                    });
+                    for b in matching {
+                        let cs = match b.component_string(components.len()) {
+                            Ok(cs) => cs,
+                            Err(_) => continue,
+                        };
+                        match comps.iter_mut().find(|(name, _)| *name == cs) {
+                            Some((_, size)) => *size += b.size(),
+                            None => comps.push((cs, b.size())),
+                        }
+                    }
                     return Ok(ResolveResult::BucketComponents(comps));
                 }
             };
@@ -398,6 +658,76 @@ impl DiskState {
         Ok(ResolveResult::Path(local_path))
     }
 
+    // opens the file at 'path' and returns whether its file system exposes alternate data
+    // streams as xattrs
+    fn resolve_ads_file(&mut self, path: &Path) -> Result<(File, bool), Error> {
+        let vm_path = match self.resolve(path)? {
+            ResolveResult::Path(vm_path) => vm_path,
+            _ => bail!("invalid path, not a file: {:?}", path),
+        };
+
+        let ads_xattr = self.disk_map.values().flatten().any(|bucket| {
+            match bucket.mount_state() {
+                (Some(mp), ads_xattr) => ads_xattr && vm_path.starts_with(mp),
+                (None, _) => false,
+            }
+        });
+
+        let file = File::open(&vm_path)
+            .map_err(|err| format_err!("unable to open {:?} - {}", path, err))?;
+
+        Ok((file, ads_xattr))
+    }
+
+    /// List the alternate data streams of a file, see `read_ads`.
+    ///
+    /// Returns an empty list if the file system does not expose alternate data streams.
+    pub fn list_ads(&mut self, path: &Path) -> Result<Vec<AdsEntry>, Error> {
+        use std::os::unix::io::AsRawFd;
+
+        let (file, ads_xattr) = self.resolve_ads_file(path)?;
+        if !ads_xattr {
+            return Ok(Vec::new());
+        }
+
+        let xattrs = match xattr::flistxattr(file.as_raw_fd()) {
+            Ok(xattrs) => xattrs,
+            Err(nix::errno::Errno::EOPNOTSUPP) => return Ok(Vec::new()),
+            Err(err) => bail!("unable to list xattrs of {:?} - {}", path, err),
+        };
+
+        let mut list = Vec::new();
+        for xattr_name in &xattrs {
+            let name = match xattr_name.to_bytes().strip_prefix(ADS_XATTR_PREFIX) {
+                Some(name) => String::from_utf8_lossy(name).into_owned(),
+                None => continue,
+            };
+            let size = xattr::fgetxattr(file.as_raw_fd(), xattr_name)
+                .map_err(|err| format_err!("unable to read stream '{}' of {:?} - {}", name, path, err))?
+                .len() as u64;
+            list.push(AdsEntry { name, size });
+        }
+
+        Ok(list)
+    }
+
+    /// Read the content of an alternate data stream.
+    pub fn read_ads(&mut self, path: &Path, name: &str) -> Result<Vec<u8>, Error> {
+        use std::os::unix::io::AsRawFd;
+
+        let (file, ads_xattr) = self.resolve_ads_file(path)?;
+        if !ads_xattr {
+            bail!("file system of {:?} does not expose alternate data streams", path);
+        }
+
+        let mut xattr_name = ADS_XATTR_PREFIX.to_vec();
+        xattr_name.extend(name.as_bytes());
+        let xattr_name = std::ffi::CString::new(xattr_name)?;
+
+        xattr::fgetxattr(file.as_raw_fd(), &xattr_name)
+            .map_err(|err| format_err!("unable to read stream '{}' of {:?} - {}", name, path, err))
+    }
+
     fn make_dev_node(devnode: &str, sys_path: &str) -> Result<u64, Error> {
         let dev_num_str = fs::file_read_firstline(&format!("{}/dev", sys_path))?;
         let (major, minor) = dev_num_str.split_at(dev_num_str.find(':').unwrap());
@@ -420,3 +750,63 @@ impl DiskState {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{ads_via_xattr, lvchange_activate_args, parse_lvm_report, LvsEntry};
+
+    #[test]
+    fn test_parse_lvs_report() {
+        let output = r#"{
+            "report": [
+                {
+                    "lv": [
+                        {"vg_name":"pve", "lv_name":"data", "lv_size":"8589934592", "pool_lv":"", "origin":"", "segtype":"thin-pool"},
+                        {"vg_name":"pve", "lv_name":"root", "lv_size":"4294967296", "pool_lv":"", "origin":"", "segtype":"linear"},
+                        {"vg_name":"pve", "lv_name":"vm-100-disk-0", "lv_size":"4294967296", "pool_lv":"data", "origin":"", "segtype":"thin"},
+                        {"vg_name":"pve", "lv_name":"snap_vm-100-disk-0_test", "lv_size":"4294967296", "pool_lv":"data", "origin":"vm-100-disk-0", "segtype":"thin"}
+                    ]
+                }
+            ]
+        }"#;
+
+        let list: Vec<LvsEntry> = parse_lvm_report(output, "lv").unwrap();
+        assert_eq!(list.len(), 4);
+
+        let thin: Vec<&str> = list
+            .iter()
+            .filter(|lv| lv.is_thin_volume())
+            .map(|lv| lv.lv_name.as_str())
+            .collect();
+        assert_eq!(thin, vec!["vm-100-disk-0", "snap_vm-100-disk-0_test"]);
+        assert_eq!(list[3].origin, "vm-100-disk-0");
+        assert_eq!(list[2].lv_size.parse::<u64>().unwrap(), 4294967296);
+
+        let list: Vec<LvsEntry> = parse_lvm_report(r#"{"report": [{}]}"#, "lv").unwrap();
+        assert!(list.is_empty());
+    }
+
+    #[test]
+    fn test_lvchange_activate_args() {
+        let args = lvchange_activate_args("pve", "snap_vm-100-disk-0_test");
+        assert_eq!(args[0], "--config");
+        assert_eq!(&args[2..], &["-ay", "-K", "pve/snap_vm-100-disk-0_test"]);
+
+        let config = &args[1];
+        assert!(config.contains("read_only_volume_list = [ \"pve\" ]"));
+        assert!(config.contains("thin_check_executable = \"\""));
+        assert!(config.contains("udev_sync = 0"));
+    }
+
+    #[test]
+    fn test_ads_via_xattr() {
+        assert!(ads_via_xattr("ntfs", Some("utf8,streams_interface=xattr")));
+        assert!(ads_via_xattr("ntfs3", Some("streams_interface=xattr")));
+
+        // the kernel driver default, and regular 'user.*' xattrs on other file systems
+        assert!(!ads_via_xattr("ntfs", Some("utf8")));
+        assert!(!ads_via_xattr("ntfs", None));
+        assert!(!ads_via_xattr("ext4", Some("streams_interface=xattr")));
+        assert!(!ads_via_xattr("ntfs", Some("streams_interface=xattrs")));
+    }
+}
@@ -108,6 +108,11 @@ fn extract_archive_from_reader<R: std::io::Read>(
                 optional: true,
                 default: false,
             },
+            "oci-whiteouts": {
+                description: "Apply OCI whiteout files (overlay semantics) instead of extracting them.",
+                optional: true,
+                default: false,
+            },
         },
     },
 )]
@@ -127,6 +132,7 @@ fn extract_archive(
     no_fifos: bool,
     no_sockets: bool,
     strict: bool,
+    oci_whiteouts: bool,
 ) -> Result<(), Error> {
     let mut feature_flags = Flags::DEFAULT;
     if no_xattrs {
@@ -147,6 +153,9 @@ fn extract_archive(
     if no_sockets {
         feature_flags.remove(Flags::WITH_SOCKETS);
     }
+    if oci_whiteouts {
+        feature_flags.insert(Flags::WITH_OCI_WHITEOUTS);
+    }
 
     let pattern = pattern.unwrap_or_else(Vec::new);
     let target = target.as_ref().map_or_else(|| ".", String::as_str);
@@ -283,6 +292,15 @@ fn extract_archive(
                     type: String,
                 },
             },
+            include: {
+                description: "Only archive files matching these paths or patterns. Excludes take precedence.",
+                optional: true,
+                type: Array,
+                items: {
+                    description: "Path or pattern matching files to archive",
+                    type: String,
+                },
+            },
             "entries-max": {
                 description: "Max number of entries loaded at once into memory",
                 optional: true,
@@ -290,6 +308,28 @@ fn extract_archive(
                 minimum: 0,
                 maximum: std::isize::MAX,
             },
+            "oci-whiteouts": {
+                description: "Store OCI whiteout files as empty whiteout markers.",
+                optional: true,
+                default: false,
+            },
+            "read-rate-limit": {
+                description: "Limit file content reads to this many bytes per second.",
+                optional: true,
+                minimum: 1,
+            },
+            "read-threads": {
+                description: "Number of threads reading file contents ahead of the encoder.",
+                optional: true,
+                default: 0,
+                minimum: 0,
+                maximum: 64,
+            },
+            "update-atime": {
+                description: "Do not open files with O_NOATIME, let reading them update their access time.",
+                optional: true,
+                default: false,
+            },
         },
     },
 )]
@@ -307,11 +347,25 @@ async fn create_archive(
     no_fifos: bool,
     no_sockets: bool,
     exclude: Option<Vec<String>>,
+    include: Option<Vec<String>>,
     entries_max: isize,
+    oci_whiteouts: bool,
+    read_rate_limit: Option<u64>,
+    read_threads: usize,
+    update_atime: bool,
 ) -> Result<(), Error> {
+    let include_only = include.is_some();
     let patterns = {
         let input = exclude.unwrap_or_else(Vec::new);
-        let mut patterns = Vec::with_capacity(input.len());
+        let include = include.unwrap_or_else(Vec::new);
+        let mut patterns = Vec::with_capacity(input.len() + include.len());
+        // the last matching pattern wins, so add includes first to let excludes override them
+        for entry in include {
+            patterns.push(
+                MatchEntry::parse_pattern(entry, PatternFlag::PATH_NAME, MatchType::Include)
+                    .map_err(|err| format_err!("error in include pattern: {}", err))?,
+            );
+        }
         for entry in input {
             patterns.push(
                 MatchEntry::parse_pattern(entry, PatternFlag::PATH_NAME, MatchType::Exclude)
@@ -333,6 +387,11 @@ async fn create_archive(
         patterns,
         verbose,
         skip_lost_and_found: false,
+        include_only,
+        update_atime,
+        read_rate_limit,
+        read_threads,
+        freeze_fs: false,
     };
 
 
@@ -370,6 +429,9 @@ async fn create_archive(
     if no_sockets {
         feature_flags.remove(Flags::WITH_SOCKETS);
     }
+    if oci_whiteouts {
+        feature_flags.insert(Flags::WITH_OCI_WHITEOUTS);
+    }
 
     let writer = pxar::encoder::sync::StandardWriter::new(writer);
     proxmox_backup::pxar::create_archive(
@@ -34,6 +34,9 @@ pub use backup_writer::*;
 mod remote_chunk_reader;
 pub use remote_chunk_reader::*;
 
+mod prefetching_chunk_reader;
+pub use prefetching_chunk_reader::*;
+
 mod pxar_backup_stream;
 pub use pxar_backup_stream::*;
 
@@ -0,0 +1,199 @@
+use std::collections::VecDeque;
+
+use anyhow::{format_err, Error};
+use tokio::task::JoinHandle;
+
+use crate::backup::{AsyncReadChunk, DataBlob};
+
+/// Read chunks in index order, with a number of downloads already running ahead.
+///
+/// Index files are read sequentially, so the digests of the next chunks are known in advance.
+/// This starts up to `prefetch_depth` downloads for the upcoming digests as tokio tasks, and
+/// `read_raw_chunk` awaits the matching task instead of making a new request.
+///
+/// Usually wraps a ``RemoteChunkReader``.
+pub struct PrefetchingChunkReader<R> {
+    reader: R,
+    pending: VecDeque<[u8; 32]>,
+    in_flight: VecDeque<([u8; 32], JoinHandle<Result<DataBlob, Error>>)>,
+    prefetch_depth: usize,
+}
+
+impl<R: AsyncReadChunk + Clone + 'static> PrefetchingChunkReader<R> {
+    /// Number of downloads running ahead by default
+    pub const DEFAULT_PREFETCH_DEPTH: usize = 8;
+
+    /// Create a new instance, `digests` are the chunks in the order they will be requested.
+    pub fn new<I: IntoIterator<Item = [u8; 32]>>(reader: R, digests: I) -> Self {
+        Self {
+            reader,
+            pending: digests.into_iter().collect(),
+            in_flight: VecDeque::new(),
+            prefetch_depth: Self::DEFAULT_PREFETCH_DEPTH,
+        }
+    }
+
+    /// Set the number of downloads running ahead (at least 1).
+    pub fn prefetch_depth(mut self, prefetch_depth: usize) -> Self {
+        self.prefetch_depth = prefetch_depth.max(1);
+        self
+    }
+
+    fn fill(&mut self) {
+        while self.in_flight.len() < self.prefetch_depth {
+            let digest = match self.pending.pop_front() {
+                Some(digest) => digest,
+                None => break,
+            };
+            let reader = self.reader.clone();
+            let handle = tokio::spawn(async move { reader.read_raw_chunk(&digest).await });
+            self.in_flight.push_back((digest, handle));
+        }
+    }
+
+    /// Returns the raw chunk for `digest`.
+    ///
+    /// Chunks requested out of order (or not listed at all) are downloaded directly.
+    pub async fn read_raw_chunk(&mut self, digest: &[u8; 32]) -> Result<DataBlob, Error> {
+        self.fill();
+
+        let pos = self.in_flight.iter().position(|(d, _)| d == digest);
+        let handle = match pos.and_then(|pos| self.in_flight.remove(pos)) {
+            Some((_, handle)) => handle,
+            None => {
+                if let Some(pos) = self.pending.iter().position(|d| d == digest) {
+                    self.pending.remove(pos);
+                }
+                return self.reader.read_raw_chunk(digest).await;
+            }
+        };
+
+        self.fill();
+
+        handle
+            .await
+            .map_err(|err| format_err!("chunk prefetch task failed - {}", err))?
+    }
+
+    /// Returns the next chunk in index order, or `None` if all chunks were read.
+    pub async fn next_chunk(&mut self) -> Option<Result<([u8; 32], DataBlob), Error>> {
+        self.fill();
+
+        let (digest, handle) = self.in_flight.pop_front()?;
+
+        self.fill();
+
+        let result = match handle.await {
+            Ok(result) => result,
+            Err(err) => Err(format_err!("chunk prefetch task failed - {}", err)),
+        };
+
+        Some(result.map(|chunk| (digest, chunk)))
+    }
+}
+
+impl<R> Drop for PrefetchingChunkReader<R> {
+    fn drop(&mut self) {
+        for (_, handle) in self.in_flight.iter() {
+            handle.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::sync::{Arc, Mutex};
+
+    use anyhow::{bail, Error};
+
+    use super::PrefetchingChunkReader;
+    use crate::backup::{AsyncReadChunk, DataBlob};
+
+    // serves the first digest byte as chunk data and records each request
+    #[derive(Clone, Default)]
+    struct TestReader {
+        requests: Arc<Mutex<Vec<u8>>>,
+    }
+
+    impl AsyncReadChunk for TestReader {
+        fn read_raw_chunk<'a>(
+            &'a self,
+            digest: &'a [u8; 32],
+        ) -> Pin<Box<dyn Future<Output = Result<DataBlob, Error>> + Send + 'a>> {
+            Box::pin(async move {
+                self.requests.lock().unwrap().push(digest[0]);
+                if digest[0] == 255 {
+                    bail!("no such chunk");
+                }
+                DataBlob::encode(&digest[..1], None, false)
+            })
+        }
+
+        fn read_chunk<'a>(
+            &'a self,
+            digest: &'a [u8; 32],
+        ) -> Pin<Box<dyn Future<Output = Result<Vec<u8>, Error>> + Send + 'a>> {
+            Box::pin(async move { self.read_raw_chunk(digest).await?.decode(None, None) })
+        }
+    }
+
+    fn digest(nr: u8) -> [u8; 32] {
+        [nr; 32]
+    }
+
+    #[test]
+    fn prefetch_in_order() -> Result<(), Error> {
+        let rt = tokio::runtime::Runtime::new()?;
+        rt.block_on(async {
+            let reader = TestReader::default();
+            let requests = Arc::clone(&reader.requests);
+
+            let mut prefetcher = PrefetchingChunkReader::new(reader, (0..20).map(digest))
+                .prefetch_depth(4);
+
+            for nr in 0..10 {
+                let chunk = prefetcher.read_raw_chunk(&digest(nr)).await?;
+                assert_eq!(chunk.decode(None, None)?, vec![nr]);
+            }
+
+            // not in the list, so this is a direct request
+            let chunk = prefetcher.read_raw_chunk(&digest(100)).await?;
+            assert_eq!(chunk.decode(None, None)?, vec![100]);
+
+            let mut nr = 10;
+            while let Some(result) = prefetcher.next_chunk().await {
+                let (chunk_digest, chunk) = result?;
+                assert_eq!(chunk_digest, digest(nr));
+                assert_eq!(chunk.decode(None, None)?, vec![nr]);
+                nr += 1;
+            }
+            assert_eq!(nr, 20);
+
+            // every chunk was downloaded exactly once
+            let mut requests = requests.lock().unwrap().clone();
+            requests.sort_unstable();
+            let mut expected: Vec<u8> = (0..20).collect();
+            expected.push(100);
+            assert_eq!(requests, expected);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn prefetch_error() -> Result<(), Error> {
+        let rt = tokio::runtime::Runtime::new()?;
+        rt.block_on(async {
+            let mut prefetcher =
+                PrefetchingChunkReader::new(TestReader::default(), vec![digest(1), digest(255)]);
+
+            prefetcher.read_raw_chunk(&digest(1)).await?;
+            assert!(prefetcher.read_raw_chunk(&digest(255)).await.is_err());
+            assert!(prefetcher.next_chunk().await.is_none());
+
+            Ok(())
+        })
+    }
+}
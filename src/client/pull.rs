@@ -1,13 +1,16 @@
 //! Sync datastore from remote server
 
 use anyhow::{bail, format_err, Error};
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
 use std::io::{Seek, SeekFrom};
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::future::Future;
 use std::sync::{Arc, Mutex};
-use std::time::SystemTime;
+use std::time::{Duration, Instant, SystemTime};
 
 use crate::{
     api2::types::*,
@@ -15,7 +18,7 @@ use crate::{
     client::*,
     server::WorkerTask,
     task_log,
-    tools::{compute_file_csum, ParallelHandler},
+    tools::{self, compute_file_csum, ParallelHandler, format::HumanByte},
 };
 use proxmox::api::error::{HttpError, StatusCode};
 
@@ -23,31 +26,234 @@ use proxmox::api::error::{HttpError, StatusCode};
 // fixme: delete vanished groups
 // Todo: correctly lock backup groups
 
+/// Transfer statistics for a single pulled archive
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PullArchiveStats {
+    pub filename: String,
+    /// Number of chunks referenced by the index
+    pub chunks_total: u64,
+    /// Chunks downloaded from the remote
+    pub chunks_downloaded: u64,
+    /// Chunks already available locally (or downloaded for another archive)
+    pub chunks_cached: u64,
+    /// Downloaded chunk (or blob) data, raw (compressed/encrypted) size
+    pub bytes_downloaded: u64,
+    pub duration_ms: u64,
+}
+
+impl PullArchiveStats {
+    pub fn new(filename: &str) -> Self {
+        Self {
+            filename: filename.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Account a chunk that was downloaded with `raw_size` bytes.
+    pub fn add_downloaded_chunk(&mut self, raw_size: u64) {
+        self.chunks_total += 1;
+        self.chunks_downloaded += 1;
+        self.bytes_downloaded += raw_size;
+    }
+
+    /// Account a chunk that did not need to be downloaded.
+    pub fn add_cached_chunk(&mut self) {
+        self.chunks_total += 1;
+        self.chunks_cached += 1;
+    }
+}
+
+impl std::fmt::Display for PullArchiveStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {} chunks, {} downloaded, {} cached, {}, {:.1}s",
+            self.filename,
+            self.chunks_total,
+            self.chunks_downloaded,
+            self.chunks_cached,
+            HumanByte::from(self.bytes_downloaded),
+            (self.duration_ms as f64) / 1000.0,
+        )
+    }
+}
+
+/// Returns the archive with the most downloaded data, and the total
+/// downloaded size of all archives.
+///
+/// Returns `None` if nothing was downloaded.
+pub fn largest_transfer(archive_stats: &[PullArchiveStats]) -> Option<(&PullArchiveStats, u64)> {
+    let total: u64 = archive_stats.iter().map(|stats| stats.bytes_downloaded).sum();
+    if total == 0 {
+        return None;
+    }
+    archive_stats
+        .iter()
+        .max_by_key(|stats| stats.bytes_downloaded)
+        .map(|stats| (stats, total))
+}
+
+/// Default number of concurrent local chunk existence checks
+pub const PULL_CHECK_CONCURRENCY_DEFAULT: usize = 32;
+/// Default number of concurrent chunk downloads
+pub const PULL_DOWNLOAD_CONCURRENCY_DEFAULT: usize = 20;
+
+/// Concurrency levels used when pulling chunks
+///
+/// Checking whether a chunk already exists in the local store (`check`)
+/// runs as separate stage in front of the downloader (`download`), so
+/// upcoming digests get checked while earlier chunks are still in flight.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PullChunkConcurrency {
+    pub check: usize,
+    pub download: usize,
+}
+
+impl Default for PullChunkConcurrency {
+    fn default() -> Self {
+        Self {
+            check: PULL_CHECK_CONCURRENCY_DEFAULT,
+            download: PULL_DOWNLOAD_CONCURRENCY_DEFAULT,
+        }
+    }
+}
+
+impl PullChunkConcurrency {
+    /// Create a new instance, using the defaults for unset values.
+    pub fn new(check: Option<usize>, download: Option<usize>) -> Self {
+        Self {
+            check: check.unwrap_or(PULL_CHECK_CONCURRENCY_DEFAULT).max(1),
+            download: download.unwrap_or(PULL_DOWNLOAD_CONCURRENCY_DEFAULT).max(1),
+        }
+    }
+}
+
+/// Parameters of a pull operation (see `pull_store`)
+pub struct PullParameters {
+    /// Source datastore on the remote
+    pub src_repo: BackupRepository,
+    /// Local target datastore
+    pub tgt_store: Arc<DataStore>,
+    /// Owner of the synced groups, groups with another owner are not synced
+    pub owner: Authid,
+    /// Remove local groups and snapshots which vanished on the remote
+    pub delete: bool,
+    /// Verify newly synced snapshots
+    pub verify_synced: bool,
+    pub concurrency: PullChunkConcurrency,
+    /// Do not download client logs
+    pub omit_client_log: bool,
+    /// Skip the groups synced by a previous failed run
+    pub resume: bool,
+    /// Number of groups synced at the same time
+    pub group_concurrency: usize,
+}
+
+impl PullParameters {
+    /// Create new parameters, which remove vanished backups and use the
+    /// defaults for everything else.
+    pub fn new(src_repo: BackupRepository, tgt_store: Arc<DataStore>, owner: Authid) -> Self {
+        Self {
+            src_repo,
+            tgt_store,
+            owner,
+            delete: true,
+            verify_synced: false,
+            concurrency: PullChunkConcurrency::default(),
+            omit_client_log: false,
+            resume: false,
+            group_concurrency: 1,
+        }
+    }
+}
+
+// Two stage chunk pipeline: `check` returns the item if it still needs to
+// be fetched, those are passed on to `download`.
+async fn run_chunk_pipeline<T, C, CF, D, DF>(
+    items: impl Iterator<Item = T>,
+    concurrency: PullChunkConcurrency,
+    check: C,
+    download: D,
+) -> Result<(), Error>
+where
+    C: FnMut(T) -> CF,
+    CF: Future<Output = Result<Option<T>, Error>>,
+    D: FnMut(T) -> DF,
+    DF: Future<Output = Result<(), Error>>,
+{
+    use futures::stream::{self, StreamExt, TryStreamExt};
+
+    stream::iter(items)
+        .map(check)
+        .buffered(concurrency.check)
+        .try_filter_map(futures::future::ok)
+        .map_ok(download)
+        .try_buffer_unordered(concurrency.download)
+        .try_for_each(|_res| futures::future::ok(()))
+        .await
+}
+
+/// Chunk level progress of the snapshot currently pulled
+///
+/// The total is the sum of the entries of all indexes pulled for the
+/// snapshot, set before the first chunk gets downloaded, so the progress
+/// never goes backwards. Updated by `pull_index_chunks`, so that the
+/// progress of a long running snapshot download can be sampled. Chunks
+/// which are already available (locally or downloaded for another index)
+/// are done right away.
+#[derive(Debug, Default)]
+pub struct PullChunkProgress {
+    done: AtomicU64,
+    total: AtomicU64,
+}
+
+impl PullChunkProgress {
+    fn start_snapshot(&self, total: u64) {
+        self.done.store(0, Ordering::SeqCst);
+        self.total.store(total, Ordering::SeqCst);
+    }
+
+    fn chunk_done(&self) {
+        self.done.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Returns `(done_chunks, total_chunks)`
+    pub fn get(&self) -> (u64, u64) {
+        (self.done.load(Ordering::SeqCst), self.total.load(Ordering::SeqCst))
+    }
+}
+
+// interval for updating the task progress during a snapshot sync
+const PROGRESS_SAMPLE_INTERVAL: Duration = Duration::from_secs(5);
+
 async fn pull_index_chunks<I: IndexFile>(
     worker: &WorkerTask,
     chunk_reader: RemoteChunkReader,
-    target: Arc<DataStore>,
     index: I,
+    stats: Arc<Mutex<PullArchiveStats>>,
+    params: &PullParameters,
     downloaded_chunks: Arc<Mutex<HashSet<[u8; 32]>>>,
+    chunk_progress: Arc<PullChunkProgress>,
 ) -> Result<(), Error> {
-    use futures::stream::{self, StreamExt, TryStreamExt};
-
     let start_time = SystemTime::now();
 
-    let stream = stream::iter(
-        (0..index.index_count())
-            .map(|pos| index.chunk_info(pos).unwrap())
-            .filter(|info| {
-                let mut guard = downloaded_chunks.lock().unwrap();
-                let done = guard.contains(&info.digest);
-                if !done {
-                    // Note: We mark a chunk as downloaded before its actually downloaded
-                    // to avoid duplicate downloads.
-                    guard.insert(info.digest);
-                }
-                !done
-            }),
-    );
+    let target = params.tgt_store.clone();
+
+    let chunk_list = (0..index.index_count())
+        .map(|pos| index.chunk_info(pos).unwrap())
+        .filter(|info| {
+            let mut guard = downloaded_chunks.lock().unwrap();
+            let done = guard.contains(&info.digest);
+            if !done {
+                // Note: We mark a chunk as downloaded before its actually downloaded
+                // to avoid duplicate downloads.
+                guard.insert(info.digest);
+            } else {
+                stats.lock().unwrap().add_cached_chunk();
+                chunk_progress.chunk_done();
+            }
+            !done
+        });
 
     let target2 = target.clone();
     let verify_pool = ParallelHandler::new(
@@ -65,38 +271,54 @@ async fn pull_index_chunks<I: IndexFile>(
 
     let bytes = Arc::new(AtomicUsize::new(0));
 
-    stream
-        .map(|info| {
-            let target = Arc::clone(&target);
-            let chunk_reader = chunk_reader.clone();
-            let bytes = Arc::clone(&bytes);
-            let verify_and_write_channel = verify_and_write_channel.clone();
-
-            Ok::<_, Error>(async move {
-                let chunk_exists = crate::tools::runtime::block_in_place(|| {
-                    target.cond_touch_chunk(&info.digest, false)
-                })?;
-                if chunk_exists {
-                    //worker.log(format!("chunk {} exists {}", pos, proxmox::tools::digest_to_hex(digest)));
-                    return Ok::<_, Error>(());
-                }
-                //worker.log(format!("sync {} chunk {}", pos, proxmox::tools::digest_to_hex(digest)));
-                let chunk = chunk_reader.read_raw_chunk(&info.digest).await?;
-                let raw_size = chunk.raw_size() as usize;
+    let check = |info: ChunkInfo| {
+        let target = Arc::clone(&target);
+        let stats = Arc::clone(&stats);
+        let chunk_progress = Arc::clone(&chunk_progress);
 
-                // decode, verify and write in a separate threads to maximize throughput
-                crate::tools::runtime::block_in_place(|| {
-                    verify_and_write_channel.send((chunk, info.digest, info.size()))
-                })?;
+        async move {
+            let digest = info.digest;
+            // stat in a blocking thread, so that multiple checks can run concurrently
+            let chunk_exists = tokio::task::spawn_blocking(move || {
+                target.cond_touch_chunk(&digest, false)
+            })
+            .await??;
+            if chunk_exists {
+                //worker.log(format!("chunk {} exists {}", pos, proxmox::tools::digest_to_hex(digest)));
+                stats.lock().unwrap().add_cached_chunk();
+                chunk_progress.chunk_done();
+                return Ok::<_, Error>(None);
+            }
+            Ok(Some(info))
+        }
+    };
 
-                bytes.fetch_add(raw_size, Ordering::SeqCst);
+    let download = |info: ChunkInfo| {
+        let chunk_reader = chunk_reader.clone();
+        let bytes = Arc::clone(&bytes);
+        let stats = Arc::clone(&stats);
+        let chunk_progress = Arc::clone(&chunk_progress);
+        let verify_and_write_channel = verify_and_write_channel.clone();
+
+        async move {
+            //worker.log(format!("sync {} chunk {}", pos, proxmox::tools::digest_to_hex(digest)));
+            let chunk = chunk_reader.read_raw_chunk(&info.digest).await?;
+            let raw_size = chunk.raw_size() as usize;
+
+            // decode, verify and write in a separate threads to maximize throughput
+            crate::tools::runtime::block_in_place(|| {
+                verify_and_write_channel.send((chunk, info.digest, info.size()))
+            })?;
 
-                Ok(())
-            })
-        })
-        .try_buffer_unordered(20)
-        .try_for_each(|_res| futures::future::ok(()))
-        .await?;
+            bytes.fetch_add(raw_size, Ordering::SeqCst);
+            stats.lock().unwrap().add_downloaded_chunk(raw_size as u64);
+            chunk_progress.chunk_done();
+
+            Ok::<_, Error>(())
+        }
+    };
+
+    run_chunk_pipeline(chunk_list, params.concurrency, check, download).await?;
 
     drop(verify_and_write_channel);
 
@@ -152,17 +374,65 @@ fn verify_archive(info: &FileInfo, csum: &[u8; 32], size: u64) -> Result<(), Err
     Ok(())
 }
 
-async fn pull_single_archive(
+// Like compute_file_csum(), but also validates the DataBlob structure
+// (magic, CRC) and returns the blob crypt mode.
+fn compute_blob_csum(file: &mut std::fs::File) -> Result<([u8; 32], u64, CryptMode), Error> {
+    use std::io::Read;
+
+    file.seek(SeekFrom::Start(0))?;
+
+    let mut data = Vec::new();
+    file.read_to_end(&mut data)?;
+
+    let csum = openssl::sha::sha256(&data);
+    let size = data.len() as u64;
+
+    let blob = DataBlob::from_raw(data)?;
+    blob.verify_crc()?;
+
+    Ok((csum, size, blob.crypt_mode()?))
+}
+
+enum PulledArchiveData {
+    DynamicIndex(DynamicIndexReader),
+    FixedIndex(FixedIndexReader),
+    Blob,
+}
+
+// An archive downloaded to its temporary path, see download_archive()
+struct PulledArchive {
+    chunk_crypt_mode: CryptMode,
+    path: std::path::PathBuf,
+    tmp_path: std::path::PathBuf,
+    data: PulledArchiveData,
+    stats: Arc<Mutex<PullArchiveStats>>,
+    download_time: Duration,
+}
+
+impl PulledArchive {
+    // number of index entries (chunks) to pull for this archive
+    fn chunk_count(&self) -> u64 {
+        match self.data {
+            PulledArchiveData::DynamicIndex(ref index) => index.index_count() as u64,
+            PulledArchiveData::FixedIndex(ref index) => index.index_count() as u64,
+            PulledArchiveData::Blob => 0,
+        }
+    }
+}
+
+// Download and verify an index or blob file, without pulling any chunks
+async fn download_archive(
     worker: &WorkerTask,
-    reader: &BackupReader,
-    chunk_reader: &mut RemoteChunkReader,
-    tgt_store: Arc<DataStore>,
+    reader: &Arc<BackupReader>,
     snapshot: &BackupDir,
     archive_info: &FileInfo,
-    downloaded_chunks: Arc<Mutex<HashSet<[u8; 32]>>>,
-) -> Result<(), Error> {
+    params: &PullParameters,
+) -> Result<PulledArchive, Error> {
+    let start_time = Instant::now();
+    let stats = Arc::new(Mutex::new(PullArchiveStats::new(&archive_info.filename)));
+
     let archive_name = &archive_info.filename;
-    let mut path = tgt_store.base_path();
+    let mut path = params.tgt_store.base_path();
     path.push(snapshot.relative_path());
     path.push(archive_name);
 
@@ -178,7 +448,7 @@ async fn pull_single_archive(
 
     reader.download(archive_name, &mut tmpfile).await?;
 
-    match archive_type(archive_name)? {
+    let data = match archive_type(archive_name)? {
         ArchiveType::DynamicIndex => {
             let index = DynamicIndexReader::new(tmpfile).map_err(|err| {
                 format_err!("unable to read dynamic index {:?} - {}", tmp_path, err)
@@ -186,14 +456,7 @@ async fn pull_single_archive(
             let (csum, size) = index.compute_csum();
             verify_archive(archive_info, &csum, size)?;
 
-            pull_index_chunks(
-                worker,
-                chunk_reader.clone(),
-                tgt_store.clone(),
-                index,
-                downloaded_chunks,
-            )
-            .await?;
+            PulledArchiveData::DynamicIndex(index)
         }
         ArchiveType::FixedIndex => {
             let index = FixedIndexReader::new(tmpfile).map_err(|err| {
@@ -202,24 +465,94 @@ async fn pull_single_archive(
             let (csum, size) = index.compute_csum();
             verify_archive(archive_info, &csum, size)?;
 
+            PulledArchiveData::FixedIndex(index)
+        }
+        ArchiveType::Blob => {
+            let (csum, size, crypt_mode) = compute_blob_csum(&mut tmpfile)
+                .map_err(|err| format_err!("unable to read blob {:?} - {}", tmp_path, err))?;
+            verify_archive(archive_info, &csum, size)?;
+
+            stats.lock().unwrap().bytes_downloaded += size;
+
+            // blobs only know about encrypted or not, sign-only blobs are plain
+            let expected_encrypted = archive_info.crypt_mode == CryptMode::Encrypt;
+            if (crypt_mode == CryptMode::Encrypt) != expected_encrypted {
+                bail!(
+                    "wrong crypt mode for file '{}' ({:?} != {:?})",
+                    archive_info.filename,
+                    archive_info.crypt_mode,
+                    crypt_mode,
+                );
+            }
+
+            PulledArchiveData::Blob
+        }
+    };
+
+    Ok(PulledArchive {
+        chunk_crypt_mode: archive_info.chunk_crypt_mode(),
+        path,
+        tmp_path,
+        data,
+        stats,
+        download_time: start_time.elapsed(),
+    })
+}
+
+// Pull the chunks of a downloaded archive and move it into place
+async fn pull_archive_chunks(
+    worker: &WorkerTask,
+    reader: &Arc<BackupReader>,
+    archive: PulledArchive,
+    params: &PullParameters,
+    downloaded_chunks: Arc<Mutex<HashSet<[u8; 32]>>>,
+    chunk_progress: Arc<PullChunkProgress>,
+) -> Result<PullArchiveStats, Error> {
+    let start_time = Instant::now();
+
+    let chunk_reader = RemoteChunkReader::new(
+        reader.clone(),
+        None,
+        archive.chunk_crypt_mode,
+        HashMap::new(),
+    );
+
+    match archive.data {
+        PulledArchiveData::DynamicIndex(index) => {
             pull_index_chunks(
                 worker,
-                chunk_reader.clone(),
-                tgt_store.clone(),
+                chunk_reader,
                 index,
+                archive.stats.clone(),
+                params,
                 downloaded_chunks,
+                chunk_progress,
             )
             .await?;
         }
-        ArchiveType::Blob => {
-            let (csum, size) = compute_file_csum(&mut tmpfile)?;
-            verify_archive(archive_info, &csum, size)?;
+        PulledArchiveData::FixedIndex(index) => {
+            pull_index_chunks(
+                worker,
+                chunk_reader,
+                index,
+                archive.stats.clone(),
+                params,
+                downloaded_chunks,
+                chunk_progress,
+            )
+            .await?;
         }
+        PulledArchiveData::Blob => {}
     }
-    if let Err(err) = std::fs::rename(&tmp_path, &path) {
-        bail!("Atomic rename file {:?} failed - {}", path, err);
+
+    if let Err(err) = std::fs::rename(&archive.tmp_path, &archive.path) {
+        bail!("Atomic rename file {:?} failed - {}", archive.path, err);
     }
-    Ok(())
+
+    let mut stats = archive.stats.lock().unwrap().clone();
+    stats.duration_ms = (archive.download_time + start_time.elapsed()).as_millis() as u64;
+
+    Ok(stats)
 }
 
 // Note: The client.log.blob is uploaded after the backup, so it is
@@ -252,10 +585,16 @@ async fn try_client_log_download(
 async fn pull_snapshot(
     worker: &WorkerTask,
     reader: Arc<BackupReader>,
-    tgt_store: Arc<DataStore>,
     snapshot: &BackupDir,
+    params: &PullParameters,
     downloaded_chunks: Arc<Mutex<HashSet<[u8; 32]>>>,
-) -> Result<(), Error> {
+    chunk_progress: Arc<PullChunkProgress>,
+) -> Result<Vec<PullArchiveStats>, Error> {
+    let mut archive_stats = Vec::new();
+
+    let tgt_store = &params.tgt_store;
+    let omit_client_log = params.omit_client_log;
+
     let mut manifest_name = tgt_store.base_path();
     manifest_name.push(snapshot.relative_path());
     manifest_name.push(MANIFEST_BLOB_NAME);
@@ -278,7 +617,7 @@ async fn pull_snapshot(
                             "skipping snapshot {} - vanished since start of sync",
                             snapshot
                         ));
-                        return Ok(());
+                        return Ok(archive_stats);
                     }
                     _ => {
                         bail!("HTTP error {} - {}", code, message);
@@ -290,7 +629,10 @@ async fn pull_snapshot(
             };
         }
     };
-    let tmp_manifest_blob = DataBlob::load_from_reader(&mut tmp_manifest_file)?;
+    // Note: load_from_reader verifies the CRC, so a truncated or corrupt
+    // download fails here, before comparing or parsing the manifest
+    let tmp_manifest_blob = DataBlob::load_from_reader(&mut tmp_manifest_file)
+        .map_err(|err| format_err!("unable to load downloaded manifest - {}", err))?;
 
     if manifest_name.exists() {
         let manifest_blob = proxmox::try_block!({
@@ -314,17 +656,19 @@ async fn pull_snapshot(
         })?;
 
         if manifest_blob.raw_data() == tmp_manifest_blob.raw_data() {
-            if !client_log_name.exists() {
+            if !omit_client_log && !client_log_name.exists() {
                 try_client_log_download(worker, reader, &client_log_name).await?;
             }
             worker.log("no data changes");
             let _ = std::fs::remove_file(&tmp_manifest_name);
-            return Ok(()); // nothing changed
+            return Ok(archive_stats); // nothing changed
         }
     }
 
     let manifest = BackupManifest::try_from(tmp_manifest_blob)?;
 
+    let mut archives = Vec::new();
+
     for item in manifest.files() {
         let mut path = tgt_store.base_path();
         path.push(snapshot.relative_path());
@@ -365,83 +709,188 @@ async fn pull_snapshot(
             }
         }
 
-        let mut chunk_reader = RemoteChunkReader::new(
-            reader.clone(),
-            None,
-            item.chunk_crypt_mode(),
-            HashMap::new(),
-        );
+        archives.push(download_archive(worker, &reader, snapshot, &item, params).await?);
+    }
+
+    // download all indexes first, so that the chunk progress covers the whole snapshot
+    chunk_progress.start_snapshot(archives.iter().map(PulledArchive::chunk_count).sum());
 
-        pull_single_archive(
+    for archive in archives {
+        let stats = pull_archive_chunks(
             worker,
             &reader,
-            &mut chunk_reader,
-            tgt_store.clone(),
-            snapshot,
-            &item,
+            archive,
+            params,
             downloaded_chunks.clone(),
+            chunk_progress.clone(),
         )
         .await?;
+        archive_stats.push(stats);
     }
 
     if let Err(err) = std::fs::rename(&tmp_manifest_name, &manifest_name) {
         bail!("Atomic rename file {:?} failed - {}", manifest_name, err);
     }
 
-    if !client_log_name.exists() {
+    if !omit_client_log && !client_log_name.exists() {
         try_client_log_download(worker, reader, &client_log_name).await?;
     }
 
     // cleanup - remove stale files
     tgt_store.cleanup_backup_dir(snapshot, &manifest)?;
 
-    Ok(())
+    if !archive_stats.is_empty() {
+        task_log!(worker, "transfer statistics:");
+        for stats in archive_stats.iter() {
+            task_log!(worker, "  {}", stats);
+        }
+        if archive_stats.len() > 1 {
+            if let Some((stats, total)) = largest_transfer(&archive_stats) {
+                task_log!(
+                    worker,
+                    "most data transferred by {} ({} of {}, {:.0}%)",
+                    stats.filename,
+                    HumanByte::from(stats.bytes_downloaded),
+                    HumanByte::from(total),
+                    (stats.bytes_downloaded as f64) * 100.0 / (total as f64),
+                );
+            }
+        }
+    }
+
+    Ok(archive_stats)
 }
 
+/// Runs a cleanup function on drop, unless disarmed
+///
+/// Aborting a sync task drops the pull future, so error handling placed
+/// after an `.await` never runs for an aborted task. Data written by the
+/// interrupted operation is therefore removed on drop.
+struct PullCleanupGuard<F: FnOnce()> {
+    cleanup: Option<F>,
+}
+
+impl<F: FnOnce()> PullCleanupGuard<F> {
+    fn new(cleanup: F) -> Self {
+        Self { cleanup: Some(cleanup) }
+    }
+
+    fn disarm(mut self) {
+        self.cleanup = None;
+    }
+}
+
+impl<F: FnOnce()> Drop for PullCleanupGuard<F> {
+    fn drop(&mut self) {
+        if let Some(cleanup) = self.cleanup.take() {
+            cleanup();
+        }
+    }
+}
+
+// remove temporary files (archives and manifest) left by an interrupted download
+fn remove_tmp_files(dir: &Path) -> Result<usize, Error> {
+    let mut count = 0;
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().map(|ext| ext == "tmp").unwrap_or(false) && path.is_file() {
+            std::fs::remove_file(&path)?;
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+/// Pull a single snapshot
+///
+/// A newly created snapshot is removed again if the pull fails or the task
+/// is aborted, so no half-written snapshot directory remains. Re-syncing an
+/// existing snapshot replaces files atomically, if the task gets aborted
+/// with cleanup requested (see `WorkerTask::cleanup_requested`), left over
+/// temporary files get removed as well.
 pub async fn pull_snapshot_from(
     worker: &WorkerTask,
     reader: Arc<BackupReader>,
-    tgt_store: Arc<DataStore>,
     snapshot: &BackupDir,
+    params: &PullParameters,
+    verify_worker: Option<&VerifyWorker>,
     downloaded_chunks: Arc<Mutex<HashSet<[u8; 32]>>>,
-) -> Result<(), Error> {
-    let (_path, is_new, _snap_lock) = tgt_store.create_locked_backup_dir(&snapshot)?;
+    chunk_progress: Arc<PullChunkProgress>,
+) -> Result<Vec<PullArchiveStats>, Error> {
+    let tgt_store = &params.tgt_store;
+
+    let (_path, is_new, snap_lock) = tgt_store.create_locked_backup_dir(&snapshot)?;
+
+    let archive_stats;
 
     if is_new {
         worker.log(format!("sync snapshot {:?}", snapshot.relative_path()));
 
-        if let Err(err) = pull_snapshot(
+        // dropped before the snapshot lock
+        let cleanup_guard = PullCleanupGuard::new(|| {
+            if let Err(cleanup_err) = tgt_store.remove_backup_dir(&snapshot, true) {
+                worker.log(format!("cleanup error - {}", cleanup_err));
+            }
+        });
+
+        archive_stats = pull_snapshot(
             worker,
             reader,
-            tgt_store.clone(),
             &snapshot,
+            params,
             downloaded_chunks,
+            chunk_progress,
         )
-        .await
-        {
-            if let Err(cleanup_err) = tgt_store.remove_backup_dir(&snapshot, true) {
-                worker.log(format!("cleanup error - {}", cleanup_err));
+        .await?;
+
+        cleanup_guard.disarm();
+
+        worker.log(format!("sync snapshot {:?} done", snapshot.relative_path()));
+
+        if let Some(verify_worker) = verify_worker {
+            let upid = worker.upid().clone();
+            // we still hold the exclusive snapshot lock, so pass it on
+            let verified = tools::runtime::block_in_place(|| {
+                verify_backup_dir_with_lock(verify_worker, snapshot, upid, None, snap_lock)
+            })?;
+            if !verified {
+                bail!("verification of synced snapshot {:?} failed", snapshot.relative_path());
             }
-            return Err(err);
         }
-        worker.log(format!("sync snapshot {:?} done", snapshot.relative_path()));
     } else {
         worker.log(format!("re-sync snapshot {:?}", snapshot.relative_path()));
-        pull_snapshot(
+
+        let cleanup_guard = PullCleanupGuard::new(|| {
+            if !worker.cleanup_requested() {
+                return;
+            }
+            let path = tgt_store.snapshot_path(&snapshot);
+            match remove_tmp_files(&path) {
+                Ok(0) => {},
+                Ok(count) => worker.log(format!("removed {} temporary files from {:?}", count, path)),
+                Err(err) => worker.log(format!("cleanup error - {}", err)),
+            }
+        });
+
+        archive_stats = pull_snapshot(
             worker,
             reader,
-            tgt_store.clone(),
             &snapshot,
+            params,
             downloaded_chunks,
+            chunk_progress,
         )
         .await?;
+
+        cleanup_guard.disarm();
+
         worker.log(format!(
             "re-sync snapshot {:?} done",
             snapshot.relative_path()
         ));
     }
 
-    Ok(())
+    Ok(archive_stats)
 }
 
 struct SkipInfo {
@@ -489,15 +938,172 @@ impl std::fmt::Display for SkipInfo {
     }
 }
 
-pub async fn pull_group(
+// Chunk source for repair_from_remote(), only checks the CRC - the chunk
+// store verifies the digest before replacing anything.
+struct RepairChunkSource {
+    reader: Arc<BackupReader>,
+}
+
+impl ReadChunk for RepairChunkSource {
+    fn read_raw_chunk(&self, digest: &[u8; 32]) -> Result<DataBlob, Error> {
+        let mut chunk_data = Vec::with_capacity(4 * 1024 * 1024);
+        tools::runtime::block_on(self.reader.download_chunk(digest, &mut chunk_data))?;
+        DataBlob::load_from_reader(&mut &chunk_data[..])
+    }
+
+    fn read_chunk(&self, digest: &[u8; 32]) -> Result<Vec<u8>, Error> {
+        self.read_raw_chunk(digest)?.decode(None, Some(digest))
+    }
+}
+
+/// Replace corrupt chunks of a local snapshot with copies from the same
+/// snapshot on a remote.
+///
+/// Only chunks referenced by the snapshot are requested, since the remote
+/// reader refuses to hand out chunks not listed in one of its indexes.
+pub async fn repair_from_remote(
     worker: &WorkerTask,
     client: &HttpClient,
     src_repo: &BackupRepository,
     tgt_store: Arc<DataStore>,
+    snapshot: &BackupDir,
+    corrupt_chunks: &HashSet<[u8; 32]>,
+) -> Result<RepairReport, Error> {
+    let (manifest, _) = tgt_store.load_manifest(snapshot)?;
+
+    let mut damaged = Vec::new();
+    let mut index_files = Vec::new();
+    for item in manifest.files() {
+        match archive_type(&item.filename)? {
+            ArchiveType::FixedIndex | ArchiveType::DynamicIndex => {}
+            ArchiveType::Blob => continue,
+        }
+
+        let mut path = snapshot.relative_path();
+        path.push(&item.filename);
+        let index = tgt_store.open_index(&path)?;
+        for pos in 0..index.index_count() {
+            let digest = index.index_digest(pos).unwrap();
+            if corrupt_chunks.contains(digest) && !damaged.contains(digest) {
+                damaged.push(*digest);
+            }
+        }
+        index_files.push(item.filename.clone());
+    }
+
+    if damaged.is_empty() {
+        return Ok(RepairReport::default());
+    }
+
+    task_log!(worker, "repair {} chunks of snapshot {}", damaged.len(), snapshot);
+
+    // get updated auth_info (new tickets)
+    let auth_info = client.login().await?;
+    let options = HttpClientOptions::new_non_interactive(
+        auth_info.ticket.clone(),
+        client.fingerprint(),
+    );
+    let new_client = HttpClient::new(
+        src_repo.host(),
+        src_repo.port(),
+        src_repo.auth_id(),
+        options,
+    )?;
+
+    let reader = BackupReader::start(
+        new_client,
+        None,
+        src_repo.store(),
+        snapshot.group().backup_type(),
+        snapshot.group().backup_id(),
+        snapshot.backup_time(),
+        false,
+    )
+    .await?;
+
+    // downloading the indexes registers their chunks with the reader session
+    for filename in index_files {
+        reader
+            .download(&filename, std::io::sink())
+            .await
+            .map_err(|err| format_err!("unable to download index '{}' - {}", filename, err))?;
+    }
+
+    let source = RepairChunkSource { reader };
+
+    tools::runtime::block_in_place(|| tgt_store.repair_chunks(&source, &damaged, worker))
+}
+
+// Task progress of the running groups, the task progress is the sum over
+// the finished and running groups. Concurrently pulled groups thus do not
+// overwrite each others progress.
+#[derive(Debug, Default)]
+struct PullProgressState {
+    total_groups: u64,
+    finished_groups: u64,
+    running_groups: HashMap<usize, f64>,
+}
+
+impl PullProgressState {
+    fn percentage(&self) -> f64 {
+        if self.total_groups == 0 {
+            return 1.0;
+        }
+        let running: f64 = self.running_groups.values().sum();
+        ((self.finished_groups as f64 + running) / self.total_groups as f64).min(1.0)
+    }
+}
+
+/// Task progress of a sync, see `pull_store`
+pub struct PullProgress<'a> {
+    worker: &'a WorkerTask,
+    state: Mutex<PullProgressState>,
+}
+
+impl<'a> PullProgress<'a> {
+    fn new(worker: &'a WorkerTask, total_groups: u64, finished_groups: u64) -> Self {
+        let state = PullProgressState {
+            total_groups,
+            finished_groups,
+            ..Default::default()
+        };
+        Self { worker, state: Mutex::new(state) }
+    }
+
+    /// Update the progress of group `index`, returns the task progress.
+    fn update_group(&self, index: usize, group_progress: &StoreProgress) -> f64 {
+        let mut state = self.state.lock().unwrap();
+        state.running_groups.insert(index, group_progress.percentage());
+        let percentage = state.percentage();
+        self.worker.progress(percentage);
+        percentage
+    }
+
+    fn finish_group(&self, index: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.running_groups.remove(&index);
+        state.finished_groups += 1;
+        self.worker.progress(state.percentage());
+    }
+}
+
+/// Pull all snapshots of a group
+///
+/// `progress` gets the progress of this group, as group number `group_index`.
+pub async fn pull_group(
+    worker: &WorkerTask,
+    client: &HttpClient,
+    params: &PullParameters,
     group: &BackupGroup,
-    delete: bool,
-    progress: &mut StoreProgress,
+    verify_worker: Option<&VerifyWorker>,
+    progress: (&PullProgress<'_>, usize),
 ) -> Result<(), Error> {
+    let src_repo = &params.src_repo;
+    let tgt_store = &params.tgt_store;
+    let (store_progress, group_index) = progress;
+    // progress within this group
+    let mut progress = StoreProgress::new(1);
+
     let path = format!("api2/json/admin/datastore/{}/snapshots", src_repo.store());
 
     let args = json!({
@@ -575,22 +1181,48 @@ pub async fn pull_group(
         )
         .await?;
 
-        let result = pull_snapshot_from(
+        let chunk_progress = Arc::new(PullChunkProgress::default());
+
+        let pull_future = pull_snapshot_from(
             worker,
             reader,
-            tgt_store.clone(),
             &snapshot,
+            params,
+            verify_worker,
             downloaded_chunks.clone(),
-        )
-        .await;
+            chunk_progress.clone(),
+        );
+        futures::pin_mut!(pull_future);
+
+        // sample the chunk progress, a single snapshot can take hours
+        let result = loop {
+            tokio::select! {
+                result = &mut pull_future => break result,
+                _ = tokio::time::sleep(PROGRESS_SAMPLE_INTERVAL) => {
+                    let (done_chunks, index_chunks) = chunk_progress.get();
+                    progress.done_chunks = done_chunks;
+                    progress.index_chunks = index_chunks;
+                    store_progress.update_group(group_index, &progress);
+                }
+            }
+        };
 
+        progress.done_chunks = 0;
+        progress.index_chunks = 0;
         progress.done_snapshots = pos as u64 + 1;
-        worker.log(format!("percentage done: {}", progress));
+        let percentage = store_progress.update_group(group_index, &progress);
+        worker.log(format!(
+            "percentage done: {:.2}% ({}/{} snapshots of group {})",
+            percentage * 100.0,
+            progress.done_snapshots,
+            progress.group_snapshots,
+            group,
+        ));
 
         result?; // stop on error
     }
 
-    if delete {
+    if params.delete {
         let local_list = group.list_backups(&tgt_store.base_path())?;
         for info in local_list {
             let backup_time = info.backup_dir.backup_time();
@@ -612,14 +1244,97 @@ pub async fn pull_group(
     Ok(())
 }
 
+/// Groups completed by a sync run with errors, so that the next run can resume
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct PullResumeState {
+    /// Number of groups (in sync order) which were all synced successfully
+    completed: usize,
+    /// Digest over the names of these groups, to detect changes of the remote group list
+    digest: String,
+}
+
+fn group_list_digest(list: &[GroupListItem]) -> String {
+    let mut hasher = openssl::sha::Sha256::new();
+    for item in list {
+        hasher.update(item.backup_type.as_bytes());
+        hasher.update(b"/");
+        hasher.update(item.backup_id.as_bytes());
+        hasher.update(b"\n");
+    }
+    proxmox::tools::digest_to_hex(&hasher.finish())
+}
+
+// state file inside the target datastore, one per source repository
+fn resume_state_path(tgt_store: &DataStore, src_repo: &BackupRepository) -> PathBuf {
+    let digest = openssl::sha::sha256(src_repo.to_string().as_bytes());
+    let mut path = tgt_store.base_path();
+    path.push(format!(".sync-resume-{}", proxmox::tools::digest_to_hex(&digest)));
+    path
+}
+
+fn load_resume_state(path: &Path) -> Option<PullResumeState> {
+    let data = proxmox::tools::fs::file_read_optional_string(path).ok()??;
+    serde_json::from_str(&data).ok()
+}
+
+fn save_resume_state(path: &Path, state: &PullResumeState) -> Result<(), Error> {
+    let backup_user = crate::backup::backup_user()?;
+    let mode = nix::sys::stat::Mode::from_bits_truncate(0o0644);
+    let options = proxmox::tools::fs::CreateOptions::new()
+        .perm(mode)
+        .owner(backup_user.uid)
+        .group(backup_user.gid);
+
+    let data = serde_json::to_string(state)?;
+    proxmox::tools::fs::replace_file(path, data.as_bytes(), options)
+}
+
+// Number of groups to skip when resuming, None if the state does not match the
+// (sorted) remote group list anymore.
+fn resume_position(state: &PullResumeState, list: &[GroupListItem]) -> Option<usize> {
+    if state.completed > list.len() || group_list_digest(&list[..state.completed]) != state.digest {
+        return None;
+    }
+    Some(state.completed)
+}
+
+// Number of leading groups which were all synced successfully, starting the
+// search at `completed`.
+fn synced_prefix(synced: &[Option<bool>], completed: usize) -> usize {
+    completed + synced[completed..]
+        .iter()
+        .take_while(|state| **state == Some(true))
+        .count()
+}
+
+/// Pull all backup groups from a remote datastore
+///
+/// If `params.verify_synced` is set, each newly synced snapshot is verified
+/// locally. A verification error fails the group, but the sync
+/// continues with the remaining groups.
+///
+/// With `params.omit_client_log` the client log of a snapshot is not downloaded,
+/// already existing local copies are kept.
+///
+/// If a run fails, the groups synced successfully before the first error
+/// are recorded in a state file in the target datastore. With `resume`,
+/// the next run skips these groups, as long as the remote group list did
+/// not change up to there. The state is removed after a run without errors.
+///
+/// Up to `params.group_concurrency` groups are synced at the same time. Each
+/// group uses its own set of already downloaded chunks, a failing group does
+/// not abort the others. The task progress covers all running groups.
 pub async fn pull_store(
-    worker: &WorkerTask,
+    worker: &Arc<WorkerTask>,
     client: &HttpClient,
-    src_repo: &BackupRepository,
-    tgt_store: Arc<DataStore>,
-    delete: bool,
-    auth_id: Authid,
+    params: &PullParameters,
 ) -> Result<(), Error> {
+    use futures::stream::StreamExt;
+
+    let src_repo = &params.src_repo;
+    let tgt_store = &params.tgt_store;
+
     // explicit create shared lock to prevent GC on newly created chunks
     let _shared_store_lock = tgt_store.try_shared_chunk_store_lock()?;
 
@@ -650,55 +1365,88 @@ pub async fn pull_store(
         new_groups.insert(BackupGroup::new(&item.backup_type, &item.backup_id));
     }
 
-    let mut progress = StoreProgress::new(list.len() as u64);
+    let resume_path = resume_state_path(tgt_store, src_repo);
 
-    for (done, item) in list.into_iter().enumerate() {
-        progress.done_groups = done as u64;
-        progress.done_snapshots = 0;
-        progress.group_snapshots = 0;
+    let mut skip = 0;
+    if params.resume {
+        match load_resume_state(&resume_path) {
+            Some(state) => match resume_position(&state, &list) {
+                Some(completed) => {
+                    worker.log(format!("resume sync, skipping {} already synced groups", completed));
+                    skip = completed;
+                }
+                None => worker.log("remote group list changed, unable to resume - sync all groups"),
+            },
+            None => worker.log("no state of a previous failed sync found - sync all groups"),
+        }
+    }
 
-        let group = BackupGroup::new(&item.backup_type, &item.backup_id);
+    let verify_worker = if params.verify_synced {
+        Some(VerifyWorker::new(worker.clone(), tgt_store.clone()))
+    } else {
+        None
+    };
 
-        let (owner, _lock_guard) = match tgt_store.create_locked_backup_group(&group, &auth_id) {
-            Ok(result) => result,
-            Err(err) => {
-                worker.log(format!(
-                    "sync group {}/{} failed - group lock failed: {}",
-                    item.backup_type, item.backup_id, err
-                ));
-                errors = true; // do not stop here, instead continue
-                continue;
-            }
-        };
+    let progress = PullProgress::new(worker, list.len() as u64, skip as u64);
+
+    let group_futures = list.iter().enumerate().skip(skip).map(|(done, item)| {
+        let verify_worker = verify_worker.as_ref();
+        let progress = &progress;
+        async move {
+            let auth_id = &params.owner;
+            let group = BackupGroup::new(&item.backup_type, &item.backup_id);
+
+            let result = match tgt_store.create_locked_backup_group(&group, auth_id) {
+                Err(err) => Err(format_err!("group lock failed: {}", err)),
+                Ok((owner, _lock_guard)) => {
+                    // permission check
+                    if *auth_id != owner {
+                        // only the owner is allowed to create additional snapshots
+                        Err(format_err!("owner check failed ({} != {})", auth_id, owner))
+                    } else {
+                        pull_group(worker, client, params, &group, verify_worker, (progress, done)).await
+                    }
+                }
+            };
 
-        // permission check
-        if auth_id != owner {
-            // only the owner is allowed to create additional snapshots
-            worker.log(format!(
-                "sync group {}/{} failed - owner check failed ({} != {})",
-                item.backup_type, item.backup_id, auth_id, owner
-            ));
-            errors = true; // do not stop here, instead continue
-        } else if let Err(err) = pull_group(
-            worker,
-            client,
-            src_repo,
-            tgt_store.clone(),
-            &group,
-            delete,
-            &mut progress,
-        )
-        .await
-        {
+            progress.finish_group(done);
+
+            (done, result)
+        }
+    });
+
+    let mut group_results = futures::stream::iter(group_futures)
+        .buffer_unordered(params.group_concurrency.max(1));
+
+    let mut synced: Vec<Option<bool>> = vec![None; list.len()];
+    let mut completed = skip;
+    for state in synced.iter_mut().take(skip) {
+        *state = Some(true);
+    }
+
+    while let Some((done, result)) = group_results.next().await {
+        let item = &list[done];
+        if let Err(err) = &result {
             worker.log(format!(
                 "sync group {}/{} failed - {}",
                 item.backup_type, item.backup_id, err,
             ));
             errors = true; // do not stop here, instead continue
         }
+        synced[done] = Some(result.is_ok());
+
+        // groups may finish out of order, only record the synced prefix
+        let new_completed = synced_prefix(&synced, completed);
+        if new_completed > completed {
+            completed = new_completed;
+            let state = PullResumeState { completed, digest: group_list_digest(&list[..completed]) };
+            if let Err(err) = save_resume_state(&resume_path, &state) {
+                worker.warn(format!("unable to save sync state - {}", err));
+            }
+        }
     }
 
-    if delete {
+    if params.delete {
         let result: Result<(), Error> = proxmox::try_block!({
             let local_groups = BackupInfo::list_backup_groups(&tgt_store.base_path())?;
             for local_group in local_groups {
@@ -727,5 +1475,290 @@ pub async fn pull_store(
         bail!("sync failed with some errors.");
     }
 
+    if let Err(err) = std::fs::remove_file(&resume_path) {
+        if err.kind() != std::io::ErrorKind::NotFound {
+            worker.warn(format!("unable to remove sync state {:?} - {}", resume_path, err));
+        }
+    }
+
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use anyhow::Error;
+
+    use super::{
+        group_list_digest, largest_transfer, remove_tmp_files, resume_position,
+        run_chunk_pipeline, synced_prefix, PullArchiveStats, PullChunkConcurrency,
+        PullCleanupGuard, PullProgressState, PullResumeState,
+    };
+    use crate::api2::types::GroupListItem;
+
+    #[test]
+    fn test_pull_archive_stats() {
+        let mut stats = PullArchiveStats::new("archive.pxar.didx");
+
+        for _ in 0..512 {
+            stats.add_downloaded_chunk(4 * 1024 * 1024);
+        }
+        for _ in 0..512 {
+            stats.add_cached_chunk();
+        }
+        stats.duration_ms = 15_300;
+
+        assert_eq!(stats.chunks_total, 1024);
+        assert_eq!(stats.chunks_downloaded, 512);
+        assert_eq!(stats.chunks_cached, 512);
+        assert_eq!(stats.bytes_downloaded, 2 * 1024 * 1024 * 1024);
+
+        assert_eq!(
+            stats.to_string(),
+            "archive.pxar.didx: 1024 chunks, 512 downloaded, 512 cached, 2.00 GiB, 15.3s",
+        );
+    }
+
+    #[test]
+    fn test_largest_transfer() {
+        let mut image = PullArchiveStats::new("drive-scsi0.img.fidx");
+        image.add_downloaded_chunk(4 * 1024 * 1024);
+        image.add_downloaded_chunk(4 * 1024 * 1024);
+        image.add_cached_chunk();
+
+        let mut config = PullArchiveStats::new("qemu-server.conf.blob");
+        config.bytes_downloaded = 512;
+
+        let list = vec![config.clone(), image];
+        let (stats, total) = largest_transfer(&list).unwrap();
+        assert_eq!(stats.filename, "drive-scsi0.img.fidx");
+        assert_eq!(total, 8 * 1024 * 1024 + 512);
+
+        config.bytes_downloaded = 0;
+        assert_eq!(largest_transfer(&[config]), None);
+        assert_eq!(largest_transfer(&[]), None);
+    }
+
+    // every other chunk exists locally, returns the maximum number of
+    // checks and downloads in flight and the number of downloads
+    fn run_counting_pipeline(check: usize) -> (usize, usize, usize) {
+        let concurrency = PullChunkConcurrency { check, download: 4 };
+
+        let checks = InFlight::default();
+        let downloads = InFlight::default();
+        let downloaded = AtomicUsize::new(0);
+
+        crate::tools::runtime::main(run_chunk_pipeline(
+            0..64u32,
+            concurrency,
+            |item| {
+                let checks = &checks;
+                async move {
+                    checks.enter();
+                    // let the pipeline start other checks before this one finishes
+                    tokio::task::yield_now().await;
+                    checks.leave();
+                    Ok::<_, Error>(if item % 2 == 0 { None } else { Some(item) })
+                }
+            },
+            |item| {
+                assert!(item % 2 == 1);
+                downloaded.fetch_add(1, Ordering::SeqCst);
+                let downloads = &downloads;
+                async move {
+                    downloads.enter();
+                    tokio::task::yield_now().await;
+                    downloads.leave();
+                    Ok::<_, Error>(())
+                }
+            },
+        ))
+        .unwrap();
+
+        (checks.max(), downloads.max(), downloaded.load(Ordering::SeqCst))
+    }
+
+    #[derive(Default)]
+    struct InFlight {
+        current: AtomicUsize,
+        max: AtomicUsize,
+    }
+
+    impl InFlight {
+        fn enter(&self) {
+            let current = self.current.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max.fetch_max(current, Ordering::SeqCst);
+        }
+
+        fn leave(&self) {
+            self.current.fetch_sub(1, Ordering::SeqCst);
+        }
+
+        fn max(&self) -> usize {
+            self.max.load(Ordering::SeqCst)
+        }
+    }
+
+    #[test]
+    fn test_chunk_pipeline_check_concurrency() {
+        let (checks, downloads, count) = run_counting_pipeline(1);
+        assert_eq!(checks, 1);
+        assert!(downloads <= 4);
+        assert_eq!(count, 32);
+
+        let (checks, downloads, count) = run_counting_pipeline(16);
+        assert_eq!(checks, 16);
+        assert!(downloads <= 4);
+        assert_eq!(count, 32);
+    }
+
+    fn group_list(ids: &[&str]) -> Vec<GroupListItem> {
+        ids.iter()
+            .map(|id| GroupListItem {
+                backup_type: "vm".to_string(),
+                backup_id: id.to_string(),
+                last_backup: 0,
+                backup_count: 1,
+                files: Vec::new(),
+                owner: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_pull_resume_position() {
+        let list = group_list(&["100", "101", "102", "103"]);
+        let state = PullResumeState { completed: 2, digest: group_list_digest(&list[..2]) };
+        assert_eq!(resume_position(&state, &list), Some(2));
+
+        // groups added after the completed ones do not matter
+        let list = group_list(&["100", "101", "102", "103", "104"]);
+        assert_eq!(resume_position(&state, &list), Some(2));
+
+        // but changes before do
+        let list = group_list(&["099", "100", "101", "102", "103"]);
+        assert_eq!(resume_position(&state, &list), None);
+        let list = group_list(&["100", "102", "103"]);
+        assert_eq!(resume_position(&state, &list), None);
+        let list = group_list(&["100"]);
+        assert_eq!(resume_position(&state, &list), None);
+    }
+
+    #[test]
+    fn test_pull_progress_concurrent_groups() {
+        let mut state = PullProgressState { total_groups: 4, ..Default::default() };
+
+        // two groups running at the same time, both count
+        state.running_groups.insert(0, 0.5);
+        state.running_groups.insert(1, 0.25);
+        assert_eq!(state.percentage(), 0.75 / 4.0);
+
+        // a finished group replaces its running progress
+        state.running_groups.insert(1, 1.0);
+        state.running_groups.remove(&1);
+        state.finished_groups += 1;
+        assert_eq!(state.percentage(), 1.5 / 4.0);
+
+        state.running_groups.clear();
+        state.finished_groups = 4;
+        assert_eq!(state.percentage(), 1.0);
+
+        assert_eq!(PullProgressState::default().percentage(), 1.0);
+    }
+
+    #[test]
+    fn test_pull_synced_prefix() {
+        // groups finishing out of order
+        let mut synced = vec![None; 4];
+        synced[1] = Some(true);
+        assert_eq!(synced_prefix(&synced, 0), 0);
+        synced[0] = Some(true);
+        assert_eq!(synced_prefix(&synced, 0), 2);
+
+        // a failed group stops the prefix, even if later ones succeed
+        synced[2] = Some(false);
+        synced[3] = Some(true);
+        assert_eq!(synced_prefix(&synced, 2), 2);
+
+        let synced = vec![Some(true); 3];
+        assert_eq!(synced_prefix(&synced, 1), 3);
+    }
+
+    // an aborted sync drops the pull future, the guard has to remove the
+    // half-written snapshot directory
+    #[test]
+    fn test_pull_cleanup_on_abort() -> Result<(), Error> {
+        use futures::future::{self, Either, FutureExt};
+
+        let dir = std::fs::canonicalize(".")?.join(".testdir-pull-cleanup");
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let snapshot_dir = dir.join("vm/100/2021-01-01T00:00:00Z");
+
+        let pull = async {
+            std::fs::create_dir_all(&snapshot_dir)?;
+            let guard = PullCleanupGuard::new(|| {
+                let _ = std::fs::remove_dir_all(&snapshot_dir);
+            });
+            std::fs::write(snapshot_dir.join("root.pxar.didx.tmp"), b"partial")?;
+            future::pending::<()>().await; // download never finishes
+            guard.disarm();
+            Ok::<_, Error>(())
+        };
+
+        let abort = future::ready(());
+
+        let mut pull = Box::pin(pull);
+        let result = futures::executor::block_on(async {
+            match future::select(&mut pull, abort.boxed()).await {
+                Either::Left((result, _)) => Some(result),
+                Either::Right(_) => None,
+            }
+        });
+        assert!(result.is_none());
+        // the snapshot directory was created, but the pull did not finish
+        assert!(snapshot_dir.exists());
+
+        drop(pull); // the worker task drops the aborted future
+        assert!(!snapshot_dir.exists());
+
+        // a finished pull keeps the snapshot
+        let keep_dir = dir.join("vm/100/2021-01-02T00:00:00Z");
+        std::fs::create_dir_all(&keep_dir)?;
+        PullCleanupGuard::new(|| {
+            let _ = std::fs::remove_dir_all(&keep_dir);
+        })
+        .disarm();
+        assert!(keep_dir.exists());
+
+        let _ = std::fs::remove_dir_all(&dir);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove_tmp_files() -> Result<(), Error> {
+        let dir = std::fs::canonicalize(".")?.join(".testdir-pull-tmp-files");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir)?;
+
+        for name in &["index.json.blob", "index.json.tmp", "root.pxar.didx", "root.pxar.tmp"] {
+            std::fs::write(dir.join(name), b"")?;
+        }
+
+        let count = remove_tmp_files(&dir)?;
+
+        let mut list: Vec<String> = std::fs::read_dir(&dir)?
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        list.sort();
+
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert_eq!(count, 2);
+        assert_eq!(list, vec!["index.json.blob", "root.pxar.didx"]);
+
+        Ok(())
+    }
+}
@@ -19,6 +19,7 @@ pub mod acl;
 pub mod acme;
 pub mod cached_user_info;
 pub mod datastore;
+pub mod encrypted;
 pub mod network;
 pub mod node;
 pub mod remote;
@@ -182,3 +182,37 @@ impl UserInformation for CachedUserInfo {
         }
     }
 }
+
+#[test]
+fn expired_token_test() -> Result<(), Error> {
+    let (user_cfg, _) = crate::config::user::test_cfg_from_str(r###"
+user: user@pbs
+
+token: user@pbs!active
+
+token: user@pbs!future
+	expire 4102444800
+
+token: user@pbs!expired
+	expire 1
+
+token: user@pbs!never
+	expire 0
+
+"###).expect("test user.cfg is not parsable");
+
+    let expired = crate::config::user::expired_tokens(&user_cfg, now())?;
+    assert_eq!(expired, vec!["user@pbs!expired".parse::<Authid>()?]);
+
+    let acl_tree = super::acl::AclTree::from_raw("").expect("test acl.cfg is not parsable");
+    let user_info = CachedUserInfo::test_new(user_cfg, acl_tree);
+
+    assert!(user_info.is_active_auth_id(&"user@pbs".parse()?));
+    assert!(user_info.is_active_auth_id(&"user@pbs!active".parse()?));
+    assert!(user_info.is_active_auth_id(&"user@pbs!future".parse()?));
+    assert!(user_info.is_active_auth_id(&"user@pbs!never".parse()?));
+    assert!(!user_info.is_active_auth_id(&"user@pbs!expired".parse()?));
+    assert!(!user_info.is_active_auth_id(&"user@pbs!missing".parse()?));
+
+    Ok(())
+}
@@ -15,7 +15,6 @@ use proxmox::api::{
 
 use proxmox::tools::fs::{
     open_file_locked,
-    replace_file,
     CreateOptions,
 };
 
@@ -84,6 +83,14 @@ pub const DIR_NAME_SCHEMA: Schema = StringSchema::new("Directory name").schema()
             optional: true,
             type: bool,
         },
+        "verify-on-insert": {
+            optional: true,
+            type: bool,
+        },
+        "chunk-dir-fan-out": {
+            optional: true,
+            schema: CHUNK_DIR_FAN_OUT_SCHEMA,
+        },
     }
 )]
 #[derive(Serialize,Deserialize)]
@@ -113,12 +120,18 @@ pub struct DataStoreConfig {
     /// If enabled, all backups will be verified right after completion.
     #[serde(skip_serializing_if="Option::is_none")]
     pub verify_new: Option<bool>,
+    /// If enabled, newly written chunks are read back and checked.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub verify_on_insert: Option<bool>,
     /// Send job email notification to this user
     #[serde(skip_serializing_if="Option::is_none")]
     pub notify_user: Option<Userid>,
     /// Send notification only for job errors
     #[serde(skip_serializing_if="Option::is_none")]
     pub notify: Option<String>,
+    /// Chunk directory layout, applied on creation or by a fan-out migration.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub chunk_dir_fan_out: Option<String>,
 }
 
 fn init() -> SectionConfig {
@@ -144,7 +157,7 @@ pub fn lock_config() -> Result<std::fs::File, Error> {
 
 pub fn config() -> Result<(SectionConfigData, [u8;32]), Error> {
 
-    let content = proxmox::tools::fs::file_read_optional_string(DATASTORE_CFG_FILENAME)?
+    let content = crate::config::encrypted::read_config_file(DATASTORE_CFG_FILENAME)?
         .unwrap_or_else(|| "".to_string());
 
     let digest = openssl::sha::sha256(content.as_bytes());
@@ -164,7 +177,7 @@ pub fn save_config(config: &SectionConfigData) -> Result<(), Error> {
         .owner(nix::unistd::ROOT)
         .group(backup_user.gid);
 
-    replace_file(DATASTORE_CFG_FILENAME, raw.as_bytes(), options)?;
+    crate::config::encrypted::write_config_file(DATASTORE_CFG_FILENAME, &raw, options)?;
 
     Ok(())
 }
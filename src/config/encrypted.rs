@@ -0,0 +1,275 @@
+//! Encryption of configuration files at rest
+//!
+//! Configuration files (datastore.cfg, media-pool.cfg and remote.cfg, which contains the
+//! remote credentials) can be stored encrypted with a per-installation master key. Encryption
+//! is enabled by creating that key, see `proxmox-backup-manager config migrate-to-encrypted`.
+//! After that, the files are encrypted on every write. Reading accepts both formats, so plain
+//! files are migrated on their next write. Encrypted files without the master key are an
+//! error.
+//!
+//! The key is stored outside of the configuration directory
+//! (`/var/lib/proxmox-backup/config-master.key`), so copies of `/etc/proxmox-backup` (config
+//! backups, system reports, ...) cannot be decrypted. It is owned by root, readable by the
+//! backup group (mode 0640), because the proxy needs to read the configuration.
+//!
+//! Data is encrypted with AES-256-GCM (see `CryptConfig`). The file format is a magic line,
+//! followed by the base64 encoded IV, tag and cipher text, one per line.
+//!
+//! Note: Hardware backed key storage (e.g. TPM2) is not supported.
+
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, format_err, Error};
+
+use lazy_static::lazy_static;
+
+use proxmox::tools::fs::{fchown, file_read_optional_string, replace_file, CreateOptions};
+
+use crate::backup::CryptConfig;
+
+pub const MASTER_KEY_FILENAME: &str = "/var/lib/proxmox-backup/config-master.key";
+
+const ENCRYPTED_CONFIG_MAGIC: &str = "PBS-ENCRYPTED-CONFIG-1.0";
+
+/// Encrypts and decrypts configuration file contents
+pub struct EncryptedConfig {
+    crypt_config: CryptConfig,
+}
+
+impl EncryptedConfig {
+    pub fn new(key: [u8; 32]) -> Result<Self, Error> {
+        Ok(Self { crypt_config: CryptConfig::new(key)? })
+    }
+
+    /// Encrypt the raw configuration data
+    pub fn encrypt(&self, raw: &str) -> Result<String, Error> {
+        let mut data = Vec::with_capacity(raw.len());
+        let (iv, tag) = self.crypt_config.encrypt_to(raw.as_bytes(), &mut data)?;
+
+        Ok(format!(
+            "{}\n{}\n{}\n{}\n",
+            ENCRYPTED_CONFIG_MAGIC,
+            base64::encode(&iv),
+            base64::encode(&tag),
+            base64::encode(&data),
+        ))
+    }
+
+    /// Decrypt data returned by `encrypt`
+    pub fn decrypt(&self, content: &str) -> Result<String, Error> {
+        let mut lines = content.lines();
+
+        if lines.next() != Some(ENCRYPTED_CONFIG_MAGIC) {
+            bail!("not an encrypted configuration file");
+        }
+
+        let mut next_field = |name: &str| -> Result<Vec<u8>, Error> {
+            let line = lines.next().ok_or_else(|| format_err!("missing {}", name))?;
+            base64::decode(line).map_err(|err| format_err!("unable to decode {} - {}", name, err))
+        };
+
+        let iv: [u8; 16] = next_field("iv")?
+            .as_slice()
+            .try_into()
+            .map_err(|_| format_err!("wrong iv length"))?;
+        let tag: [u8; 16] = next_field("tag")?
+            .as_slice()
+            .try_into()
+            .map_err(|_| format_err!("wrong tag length"))?;
+        let data = next_field("data")?;
+
+        let raw = self.crypt_config.decode_uncompressed_chunk(&data, &iv, &tag)?;
+
+        Ok(String::from_utf8(raw)?)
+    }
+}
+
+/// Check if the file content is encrypted
+pub fn is_encrypted(content: &str) -> bool {
+    content.starts_with(ENCRYPTED_CONFIG_MAGIC)
+}
+
+fn load_master_key(path: &Path) -> Result<Option<EncryptedConfig>, Error> {
+    let content = match file_read_optional_string(path)? {
+        Some(content) => content,
+        None => return Ok(None),
+    };
+
+    let key = proxmox::tools::hex_to_digest(content.trim())
+        .map_err(|err| format_err!("unable to parse master key {:?} - {}", path, err))?;
+
+    Ok(Some(EncryptedConfig::new(key)?))
+}
+
+lazy_static! {
+    // the master key never changes once it exists
+    static ref MASTER_KEY: Mutex<Option<Arc<EncryptedConfig>>> = Mutex::new(None);
+
+    // decrypted content by path, with the digest of the encrypted content
+    static ref DECRYPTED_CACHE: Mutex<HashMap<String, ([u8; 32], String)>> =
+        Mutex::new(HashMap::new());
+}
+
+/// Load the master key, returns `None` if config encryption is not enabled
+pub fn master_key() -> Result<Option<Arc<EncryptedConfig>>, Error> {
+    let mut cached = MASTER_KEY.lock().unwrap();
+    if cached.is_none() {
+        *cached = load_master_key(Path::new(MASTER_KEY_FILENAME))?.map(Arc::new);
+    }
+    Ok(cached.clone())
+}
+
+fn create_key_file(
+    path: &Path,
+    key: &[u8; 32],
+    group: Option<nix::unistd::Gid>,
+) -> Result<bool, Error> {
+    // O_EXCL, so that concurrent callers cannot overwrite an existing key
+    let mut file = match std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o0640)
+        .open(path)
+    {
+        Ok(file) => file,
+        Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => return Ok(false),
+        Err(err) => bail!("unable to create master key {:?} - {}", path, err),
+    };
+
+    // owner(rw) = root, group(r)= backup
+    if let Some(group) = group {
+        fchown(file.as_raw_fd(), Some(nix::unistd::ROOT), Some(group))?;
+    }
+
+    let content = format!("{}\n", proxmox::tools::digest_to_hex(key));
+    let result = file
+        .write_all(content.as_bytes())
+        .and_then(|()| file.sync_all());
+
+    if let Err(err) = result {
+        let _ = std::fs::remove_file(path);
+        bail!("unable to write master key {:?} - {}", path, err);
+    }
+
+    Ok(true)
+}
+
+/// Create a new master key, this enables config encryption
+///
+/// Returns false if the key already exists.
+pub fn create_master_key() -> Result<bool, Error> {
+    let mut key = [0u8; 32];
+    proxmox::sys::linux::fill_with_random_data(&mut key)?;
+
+    let backup_user = crate::backup::backup_user()?;
+
+    create_key_file(Path::new(MASTER_KEY_FILENAME), &key, Some(backup_user.gid))
+}
+
+fn decrypt_content(
+    path: &str,
+    content: String,
+    key: Option<&EncryptedConfig>,
+) -> Result<String, Error> {
+    if !is_encrypted(&content) {
+        return Ok(content);
+    }
+
+    match key {
+        Some(key) => key
+            .decrypt(&content)
+            .map_err(|err| format_err!("unable to decrypt {} - {}", path, err)),
+        None => bail!(
+            "configuration file {} is encrypted, but the master key {} is missing",
+            path,
+            MASTER_KEY_FILENAME,
+        ),
+    }
+}
+
+/// Read a configuration file, decrypting it if required
+///
+/// Decrypted content is cached as long as the file does not change, so frequent
+/// readers (e.g. `DataStore::lookup_datastore`) do not decrypt on every call.
+pub fn read_config_file(path: &str) -> Result<Option<String>, Error> {
+    let content = match file_read_optional_string(path)? {
+        Some(content) => content,
+        None => return Ok(None),
+    };
+
+    if !is_encrypted(&content) {
+        return Ok(Some(content));
+    }
+
+    let digest = openssl::sha::sha256(content.as_bytes());
+    if let Some((cached_digest, raw)) = DECRYPTED_CACHE.lock().unwrap().get(path) {
+        if *cached_digest == digest {
+            return Ok(Some(raw.clone()));
+        }
+    }
+
+    let raw = decrypt_content(path, content, master_key()?.as_deref())?;
+    DECRYPTED_CACHE.lock().unwrap().insert(path.to_string(), (digest, raw.clone()));
+
+    Ok(Some(raw))
+}
+
+/// Write a configuration file, encrypted if a master key exists
+pub fn write_config_file(path: &str, raw: &str, options: CreateOptions) -> Result<(), Error> {
+    match master_key()? {
+        Some(key) => replace_file(path, key.encrypt(raw)?.as_bytes(), options),
+        None => replace_file(path, raw.as_bytes(), options),
+    }
+}
+
+#[test]
+fn test_encrypted_config_roundtrip() -> Result<(), Error> {
+    let raw = "datastore: store1\n\tpath /mnt/datastore/store1\n\n";
+
+    let dir = std::fs::canonicalize(".")?.join(".testdir-encrypted-config");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir)?;
+
+    let key_path = dir.join("master.key");
+    assert!(load_master_key(&key_path)?.is_none());
+
+    let key = [7u8; 32];
+    assert!(create_key_file(&key_path, &key, None)?);
+    // an existing key is never overwritten
+    assert!(!create_key_file(&key_path, &[9u8; 32], None)?);
+    let config = load_master_key(&key_path)?.unwrap();
+
+    let encrypted = config.encrypt(raw)?;
+    assert!(is_encrypted(&encrypted));
+    assert!(!encrypted.contains("store1"));
+    assert_eq!(config.decrypt(&encrypted)?, raw);
+
+    // random IV
+    assert_ne!(config.encrypt(raw)?, encrypted);
+
+    // plain files are read as they are
+    assert_eq!(decrypt_content("test.cfg", raw.to_string(), Some(&config))?, raw);
+    assert_eq!(decrypt_content("test.cfg", raw.to_string(), None)?, raw);
+    assert_eq!(decrypt_content("test.cfg", encrypted.clone(), Some(&config))?, raw);
+
+    // missing or wrong key
+    let err = decrypt_content("test.cfg", encrypted.clone(), None).unwrap_err();
+    assert!(err.to_string().contains("master key"));
+    let wrong_key = EncryptedConfig::new([8u8; 32])?;
+    assert!(wrong_key.decrypt(&encrypted).is_err());
+
+    // tampered data
+    let mut lines: Vec<String> = encrypted.lines().map(String::from).collect();
+    lines[3] = base64::encode(&vec![0u8; raw.len()]);
+    assert!(config.decrypt(&lines.join("\n")).is_err());
+
+    let _ = std::fs::remove_dir_all(&dir);
+
+    Ok(())
+}
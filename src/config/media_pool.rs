@@ -22,7 +22,6 @@ use proxmox::{
     },
     tools::fs::{
         open_file_locked,
-        replace_file,
         CreateOptions,
     },
 };
@@ -66,7 +65,7 @@ pub fn lock() -> Result<std::fs::File, Error> {
 /// Read and parse the configuration file
 pub fn config() -> Result<(SectionConfigData, [u8;32]), Error> {
 
-    let content = proxmox::tools::fs::file_read_optional_string(MEDIA_POOL_CFG_FILENAME)?
+    let content = crate::config::encrypted::read_config_file(MEDIA_POOL_CFG_FILENAME)?
         .unwrap_or_else(|| "".to_string());
 
     let digest = openssl::sha::sha256(content.as_bytes());
@@ -87,7 +86,7 @@ pub fn save_config(config: &SectionConfigData) -> Result<(), Error> {
         .owner(nix::unistd::ROOT)
         .group(backup_user.gid);
 
-    replace_file(MEDIA_POOL_CFG_FILENAME, raw.as_bytes(), options)?;
+    crate::config::encrypted::write_config_file(MEDIA_POOL_CFG_FILENAME, &raw, options)?;
 
     Ok(())
 }
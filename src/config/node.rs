@@ -96,6 +96,12 @@ pub struct AcmeConfig {
             schema: HTTP_PROXY_SCHEMA,
             optional: true,
         },
+        "remove-expired-tokens": {
+            description: "Remove expired API tokens during the daily update.",
+            type: bool,
+            optional: true,
+            default: false,
+        },
     },
 )]
 #[derive(Deserialize, Serialize, Updater)]
@@ -123,6 +129,9 @@ pub struct NodeConfig {
 
     #[serde(skip_serializing_if = "Updater::is_empty")]
     http_proxy: Option<String>,
+
+    #[serde(skip_serializing_if = "Updater::is_empty")]
+    remove_expired_tokens: Option<bool>,
 }
 
 impl NodeConfig {
@@ -165,6 +174,11 @@ impl NodeConfig {
         self.http_proxy = http_proxy;
     }
 
+    /// Returns if expired API tokens should be removed automatically
+    pub fn remove_expired_tokens(&self) -> bool {
+        self.remove_expired_tokens.unwrap_or(false)
+    }
+
     /// Validate the configuration.
     pub fn validate(&self) -> Result<(), Error> {
         let mut domains = HashSet::new();
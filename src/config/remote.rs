@@ -13,7 +13,7 @@ use proxmox::api::{
     }
 };
 
-use proxmox::tools::{fs::replace_file, fs::CreateOptions};
+use proxmox::tools::fs::CreateOptions;
 
 use crate::api2::types::*;
 
@@ -92,7 +92,7 @@ pub const REMOTE_CFG_LOCKFILE: &str = "/etc/proxmox-backup/.remote.lck";
 
 pub fn config() -> Result<(SectionConfigData, [u8;32]), Error> {
 
-    let content = proxmox::tools::fs::file_read_optional_string(REMOTE_CFG_FILENAME)?
+    let content = crate::config::encrypted::read_config_file(REMOTE_CFG_FILENAME)?
         .unwrap_or_else(|| "".to_string());
 
     let digest = openssl::sha::sha256(content.as_bytes());
@@ -112,7 +112,7 @@ pub fn save_config(config: &SectionConfigData) -> Result<(), Error> {
         .owner(nix::unistd::ROOT)
         .group(backup_user.gid);
 
-    replace_file(REMOTE_CFG_FILENAME, raw.as_bytes(), options)?;
+    crate::config::encrypted::write_config_file(REMOTE_CFG_FILENAME, &raw, options)?;
 
     Ok(())
 }
@@ -241,6 +241,43 @@ pub fn save_config(config: &SectionConfigData) -> Result<(), Error> {
     Ok(())
 }
 
+/// Returns the IDs of all tokens expired at `now` (epoch).
+pub fn expired_tokens(config: &SectionConfigData, now: i64) -> Result<Vec<Authid>, Error> {
+    let list: Vec<ApiToken> = config.convert_to_typed_array("token")?;
+
+    Ok(list
+        .into_iter()
+        .filter(|token| matches!(token.expire, Some(expire) if expire > 0 && expire <= now))
+        .map(|token| token.tokenid)
+        .collect())
+}
+
+/// Remove all expired API tokens (including their secrets).
+///
+/// Returns the IDs of the removed tokens.
+pub fn remove_expired_tokens() -> Result<Vec<Authid>, Error> {
+    let _lock = proxmox::tools::fs::open_file_locked(USER_CFG_LOCKFILE, std::time::Duration::new(10, 0), true)?;
+
+    let (mut config, _digest) = config()?;
+
+    let expired = expired_tokens(&config, proxmox::tools::time::epoch_i64())?;
+    if expired.is_empty() {
+        return Ok(expired);
+    }
+
+    for tokenid in expired.iter() {
+        config.sections.remove(&tokenid.to_string());
+    }
+
+    save_config(&config)?;
+
+    for tokenid in expired.iter() {
+        super::token_shadow::delete_secret(tokenid)?;
+    }
+
+    Ok(expired)
+}
+
 #[cfg(test)]
 pub(crate) fn test_cfg_from_str(raw: &str) -> Result<(SectionConfigData, [u8;32]), Error> {
     let cfg = init();
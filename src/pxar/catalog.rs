@@ -16,4 +16,14 @@ pub trait BackupCatalogWriter {
     fn add_char_device(&mut self, name: &CStr) -> Result<(), Error>;
     fn add_fifo(&mut self, name: &CStr) -> Result<(), Error>;
     fn add_socket(&mut self, name: &CStr) -> Result<(), Error>;
+
+    /// Returns true if the catalog wants file content checksums.
+    fn file_checksums(&self) -> bool {
+        false
+    }
+
+    /// Set the content checksum (sha256) of the file added last.
+    fn set_file_checksum(&mut self, _name: &CStr, _csum: &[u8; 32]) -> Result<(), Error> {
+        Ok(())
+    }
 }
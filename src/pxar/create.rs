@@ -6,6 +6,7 @@ use std::os::unix::ffi::OsStrExt;
 use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, RawFd};
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
 use anyhow::{bail, format_err, Error};
 use nix::dir::Dir;
@@ -13,6 +14,7 @@ use nix::errno::Errno;
 use nix::fcntl::OFlag;
 use nix::sys::stat::{FileStat, Mode};
 use futures::future::BoxFuture;
+use openssl::sha::Sha256;
 use futures::FutureExt;
 
 use pathpatterns::{MatchEntry, MatchFlag, MatchList, MatchType, PatternFlag};
@@ -25,10 +27,12 @@ use proxmox::tools::fd::RawFdNum;
 use proxmox::tools::vec;
 
 use crate::pxar::catalog::BackupCatalogWriter;
+use crate::pxar::freeze::{is_temporary_file_system, FsFreezeGuard, FREEZE_TIMEOUT};
 use crate::pxar::metadata::errno_is_unsupported;
+use crate::pxar::prefetch::{FilePrefetcher, PrefetchedData};
 use crate::pxar::Flags;
-use crate::pxar::tools::assert_single_path_component;
-use crate::tools::{acl, fs, xattr, Fd};
+use crate::pxar::tools::{assert_single_path_component, OciWhiteout};
+use crate::tools::{acl, fs, xattr, Fd, RateLimiter};
 
 /// Pxar options for creating a pxar archive/stream
 #[derive(Default, Clone)]
@@ -37,12 +41,44 @@ pub struct PxarCreateOptions {
     pub device_set: Option<HashSet<u64>>,
     /// Exclusion patterns
     pub patterns: Vec<MatchEntry>,
+    /// Only archive entries matching an include pattern
+    ///
+    /// Patterns are evaluated as usual, the last matching pattern decides. Entries matched by
+    /// no pattern at all inherit the decision of their parent directory, and the top level
+    /// default is "exclude" in this mode. Directories which are not included themselves are
+    /// only archived (without their non-matching contents) if an included entry exists below.
+    /// An exclude pattern always wins over an include pattern which appears before it in the
+    /// list.
+    pub include_only: bool,
     /// Maximum number of entries to hold in memory
+    ///
+    /// This counts the entries of all directories currently being archived (the current
+    /// directory and its parents), because their sorted file lists are kept in memory. A
+    /// warning is printed once per directory when 75% of the limit are reached. Raising the
+    /// limit allows huge flat directories, at the cost of more memory usage.
     pub entries_max: usize,
     /// Skip lost+found directory
     pub skip_lost_and_found: bool,
     /// Verbose output
     pub verbose: bool,
+    /// Open files without `O_NOATIME`, so reading them updates their access time
+    ///
+    /// By default `O_NOATIME` is used whenever we are permitted to (file owner or
+    /// `CAP_FOWNER`), so the backup does not modify the source metadata.
+    pub update_atime: bool,
+    /// Limit file content reads to this many bytes per second (on average)
+    ///
+    /// Only regular file payload is throttled, metadata and directory traversal are not.
+    pub read_rate_limit: Option<u64>,
+    /// Number of threads used to read file contents ahead of the encoder (0 disables it)
+    ///
+    /// The archive structure is still written by a single task, so the result is identical to
+    /// an archive created without read threads (see `pxar::prefetch`).
+    pub read_threads: usize,
+    /// Freeze the source file system while creating the archive (see `pxar::freeze`)
+    ///
+    /// Ignored for memory backed file systems (tmpfs, ramfs).
+    pub freeze_fs: bool,
 }
 
 
@@ -139,6 +175,8 @@ struct Archiver {
     patterns: Vec<MatchEntry>,
     callback: Box<dyn FnMut(&Path) -> Result<(), Error> + Send>,
     catalog: Option<Arc<Mutex<dyn BackupCatalogWriter + Send>>>,
+    /// Whether the catalog records file content checksums
+    file_checksums: bool,
     path: PathBuf,
     entry_counter: usize,
     entry_limit: usize,
@@ -148,6 +186,13 @@ struct Archiver {
     errors: ErrorReporter,
     logger: Logger,
     file_copy_buffer: Vec<u8>,
+    /// Whether entries matching no pattern are included, see `PxarCreateOptions::include_only`
+    current_match: bool,
+    update_atime: bool,
+    read_limiter: Option<RateLimiter>,
+    prefetcher: Option<FilePrefetcher>,
+    /// Prefetch job for the entry currently being added
+    current_prefetch: Option<u64>,
 }
 
 type Encoder<'a, T> = pxar::encoder::aio::Encoder<'a, T>;
@@ -169,6 +214,13 @@ where
         bail!("refusing to backup a virtual file system");
     }
 
+    // thaws the file system when dropped, also on errors
+    let _freeze_guard = if options.freeze_fs && !is_temporary_file_system(fs_magic) {
+        Some(FsFreezeGuard::freeze(source_dir.as_raw_fd(), FREEZE_TIMEOUT)?)
+    } else {
+        None
+    };
+
     let mut fs_feature_flags = Flags::from_magic(fs_magic);
 
     let stat = nix::sys::stat::fstat(source_dir.as_raw_fd())?;
@@ -198,6 +250,11 @@ where
         )?);
     }
 
+    let file_checksums = match catalog {
+        Some(ref catalog) => catalog.lock().unwrap().file_checksums(),
+        None => false,
+    };
+
     let mut archiver = Archiver {
         feature_flags,
         fs_feature_flags,
@@ -205,6 +262,7 @@ where
         callback: Box::new(callback),
         patterns,
         catalog,
+        file_checksums,
         path: PathBuf::new(),
         entry_counter: 0,
         entry_limit: options.entries_max,
@@ -214,17 +272,75 @@ where
         errors: ErrorReporter,
         logger: Logger,
         file_copy_buffer: vec::undefined(4 * 1024 * 1024),
+        current_match: !options.include_only,
+        update_atime: options.update_atime,
+        // allow bursts of up to one second, so that small files are not delayed
+        read_limiter: options.read_rate_limit.map(|rate| RateLimiter::new(rate, rate)),
+        prefetcher: if options.read_threads > 0 {
+            Some(FilePrefetcher::new(options.read_threads, options.update_atime))
+        } else {
+            None
+        },
+        current_prefetch: None,
     };
 
     archiver.archive_dir_contents(&mut encoder, source_dir, true).await?;
     encoder.finish().await?;
+    if let Some(prefetcher) = archiver.prefetcher.take() {
+        prefetcher.finish()?;
+    }
     Ok(())
 }
 
+/// Add a regular file to an archive, reading its contents from an async reader.
+///
+/// This allows streaming data which is not available as file on disk (for example data
+/// generated on the fly) into an archive without staging it to a temporary file. The file
+/// metadata is taken from `stat` (mode, owner and mtime), and exactly `size` bytes are read
+/// from `reader`. Fails if the reader ends early, or provides more than `size` bytes.
+pub async fn encode_async_file<T, R>(
+    encoder: &mut pxar::encoder::aio::Encoder<'_, T>,
+    reader: &mut R,
+    file_name: &Path,
+    size: u64,
+    stat: &FileStat,
+) -> Result<LinkOffset, Error>
+where
+    T: SeqWrite + Send,
+    R: tokio::io::AsyncRead + Unpin,
+{
+    use tokio::io::AsyncReadExt;
+
+    let metadata = stat_metadata(stat);
+    let mut buffer = vec::undefined(4 * 1024 * 1024);
+
+    let mut remaining = size;
+    let mut out = encoder.create_file(&metadata, file_name, size).await?;
+    while remaining != 0 {
+        let max = remaining.min(buffer.len() as u64) as usize;
+        let got = match reader.read(&mut buffer[..max]).await {
+            Ok(0) => bail!("{:?}: unexpected end of data ({} bytes missing)", file_name, remaining),
+            Ok(got) => got,
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(err) => bail!(err),
+        };
+        out.write_all(&buffer[..got]).await?;
+        remaining -= got as u64;
+    }
+
+    if reader.read(&mut buffer[..1]).await? != 0 {
+        bail!("{:?}: got more data than expected ({} bytes)", file_name, size);
+    }
+
+    Ok(out.file_offset())
+}
+
 struct FileListEntry {
     name: CString,
     path: PathBuf,
     stat: FileStat,
+    /// false for directories which are only traversed in `include_only` mode
+    included: bool,
 }
 
 impl Archiver {
@@ -261,6 +377,7 @@ impl Archiver {
                     name: CString::new(".pxarexclude-cli").unwrap(),
                     path: PathBuf::new(),
                     stat: unsafe { std::mem::zeroed() },
+                    included: true,
                 });
             }
 
@@ -268,7 +385,14 @@ impl Archiver {
 
             let old_path = std::mem::take(&mut self.path);
 
-            for file_entry in file_list {
+            let shared_dir = match self.prefetcher {
+                Some(_) => Some(FilePrefetcher::share_dir(dir_fd)?),
+                None => None,
+            };
+            let mut prefetch_ids = vec![None; file_list.len()];
+            let mut prefetch_pos = 0;
+
+            for (pos, file_entry) in file_list.iter().enumerate() {
                 let file_name = file_entry.name.to_bytes();
 
                 if is_root && file_name == b".pxarexclude-cli" {
@@ -276,10 +400,25 @@ impl Archiver {
                     continue;
                 }
 
+                if let Some(ref shared_dir) = shared_dir {
+                    prefetch_pos = std::cmp::max(prefetch_pos, pos);
+                    self.prefetch_ahead(shared_dir, &file_list, &mut prefetch_pos, &mut prefetch_ids)?;
+                    self.current_prefetch = prefetch_ids[pos].take();
+                }
+
                 (self.callback)(&file_entry.path)?;
-                self.path = file_entry.path;
-                self.add_entry(encoder, dir_fd, &file_entry.name, &file_entry.stat).await
-                    .map_err(|err| self.wrap_err(err))?;
+                self.path = file_entry.path.clone();
+                let old_match = std::mem::replace(&mut self.current_match, file_entry.included);
+                let result = self.add_entry(encoder, dir_fd, &file_entry.name, &file_entry.stat).await
+                    .map_err(|err| self.wrap_err(err));
+                self.current_match = old_match;
+
+                // the entry did not use the prefetched data (excluded, hardlink, ...)
+                if let Some(id) = self.current_prefetch.take() {
+                    let _ = self.prefetcher.as_mut().unwrap().take(id);
+                }
+
+                result?;
             }
             self.path = old_path;
             self.entry_counter = entry_counter;
@@ -289,8 +428,47 @@ impl Archiver {
         }.boxed()
     }
 
+    /// Queue regular files following `pos` for prefetching, as long as the prefetch window has
+    /// room. `pos` is advanced past all checked entries.
+    fn prefetch_ahead(
+        &mut self,
+        shared_dir: &Arc<Fd>,
+        file_list: &[FileListEntry],
+        pos: &mut usize,
+        ids: &mut [Option<u64>],
+    ) -> Result<(), Error> {
+        let prefetcher = match self.prefetcher {
+            Some(ref mut prefetcher) => prefetcher,
+            None => return Ok(()),
+        };
+
+        while *pos < file_list.len() {
+            let entry = &file_list[*pos];
+            let size = entry.stat.st_size as u64;
+
+            let wanted = entry.included
+                && (entry.stat.st_mode & libc::S_IFMT) == libc::S_IFREG
+                && entry.stat.st_nlink == 1
+                && FilePrefetcher::wants(size);
+
+            if wanted {
+                if !prefetcher.has_room(size) {
+                    break;
+                }
+                ids[*pos] = Some(prefetcher.submit(shared_dir, &entry.name, size)?);
+            }
+
+            *pos += 1;
+        }
+
+        Ok(())
+    }
+
     /// openat() wrapper which allows but logs `EACCES` and turns `ENOENT` into `None`.
     ///
+    /// Unless `update_atime` is set, files are opened with `O_NOATIME`, falling back to a
+    /// normal open on `EPERM` (we are neither the owner nor have `CAP_FOWNER`).
+    ///
     /// The `existed` flag is set when iterating through a directory to note that we know the file
     /// is supposed to exist and we should warn if it doesnt'.
     fn open_file(
@@ -303,7 +481,11 @@ impl Archiver {
         // common flags we always want to use:
         let oflags = oflags | OFlag::O_CLOEXEC | OFlag::O_NOCTTY;
 
-        let mut noatime = OFlag::O_NOATIME;
+        let mut noatime = if self.update_atime {
+            OFlag::empty()
+        } else {
+            OFlag::O_NOATIME
+        };
         loop {
             return match Fd::openat(
                 &unsafe { RawFdNum::from_raw_fd(parent) },
@@ -430,6 +612,10 @@ impl Archiver {
 
         let mut file_list = Vec::new();
 
+        let warn_limit = self.entry_limit - self.entry_limit / 4;
+        // only warn in the directory crossing the threshold, not in all its subdirectories
+        let mut warned = self.entry_counter > warn_limit;
+
         for file in dir.iter() {
             let file = file?;
 
@@ -458,23 +644,45 @@ impl Archiver {
             };
 
             let match_path = PathBuf::from("/").join(full_path.clone());
-            if self
+            let included = match self
                 .patterns
                 .matches(match_path.as_os_str().as_bytes(), Some(stat.st_mode as u32))
-                == Some(MatchType::Exclude)
             {
-                continue;
+                Some(MatchType::Exclude) => continue,
+                Some(MatchType::Include) => true,
+                None => self.current_match,
+            };
+
+            // not included, but descendants might be
+            if !included {
+                if (stat.st_mode & libc::S_IFMT) != libc::S_IFDIR {
+                    continue;
+                }
+                if !self.subtree_has_includes(dir_fd, &file_name, &match_path, &stat)? {
+                    continue;
+                }
             }
 
             self.entry_counter += 1;
             if self.entry_counter > self.entry_limit {
                 bail!("exceeded allowed number of file entries (> {})",self.entry_limit);
             }
+            if !warned && self.entry_counter > warn_limit {
+                warned = true;
+                writeln!(
+                    self.errors,
+                    "warning: {:?}: number of file entries in memory reached 75% of the limit ({}), \
+                     consider raising entries-max",
+                    self.path,
+                    self.entry_limit,
+                )?;
+            }
 
             file_list.push(FileListEntry {
                 name: file_name,
                 path: full_path,
-                stat
+                stat,
+                included,
             });
         }
 
@@ -483,6 +691,78 @@ impl Archiver {
         Ok(file_list)
     }
 
+    /// Check whether anything below the directory `dir_name` is matched by an include pattern.
+    ///
+    /// Used in `include_only` mode, so that directories without any included descendants are
+    /// not archived as empty skeletons. Only the patterns known at this level are checked,
+    /// `.pxarexclude` files further down are not read, so in rare cases an empty directory is
+    /// still archived. Mount points which would be skipped are not searched.
+    fn subtree_has_includes(
+        &mut self,
+        parent: RawFd,
+        dir_name: &CStr,
+        match_path: &Path,
+        stat: &FileStat,
+    ) -> Result<bool, Error> {
+        if stat.st_dev != self.current_st_dev {
+            if let Some(set) = &self.device_set {
+                if !set.contains(&stat.st_dev) {
+                    return Ok(false);
+                }
+            }
+        }
+
+        let mut dir = match Dir::openat(
+            parent,
+            dir_name,
+            OFlag::O_RDONLY | OFlag::O_DIRECTORY | OFlag::O_NOFOLLOW | OFlag::O_CLOEXEC,
+            Mode::empty(),
+        ) {
+            Ok(dir) => dir,
+            // reported when the directory itself gets archived, if at all
+            Err(nix::Error::Sys(Errno::ENOENT)) | Err(nix::Error::Sys(Errno::EACCES)) => {
+                return Ok(false)
+            }
+            Err(err) => bail!("failed to open directory {:?}: {}", match_path, err),
+        };
+        let dir_fd = dir.as_raw_fd();
+
+        for file in dir.iter() {
+            let file = file?;
+
+            let file_name = file.file_name();
+            let file_name_bytes = file_name.to_bytes();
+            if file_name_bytes == b"." || file_name_bytes == b".." {
+                continue;
+            }
+
+            let path = match_path.join(OsStr::from_bytes(file_name_bytes));
+
+            let stat = match nix::sys::stat::fstatat(
+                dir_fd,
+                file_name,
+                nix::fcntl::AtFlags::AT_SYMLINK_NOFOLLOW,
+            ) {
+                Ok(stat) => stat,
+                Err(ref err) if err.not_found() => continue,
+                Err(err) => bail!("stat failed on {:?}: {}", path, err),
+            };
+            match self.patterns.matches(path.as_os_str().as_bytes(), Some(stat.st_mode as u32)) {
+                Some(MatchType::Exclude) => continue,
+                Some(MatchType::Include) => return Ok(true),
+                None => (),
+            }
+
+            if (stat.st_mode & libc::S_IFMT) == libc::S_IFDIR
+                && self.subtree_has_includes(dir_fd, file_name, &path, &stat)?
+            {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
     fn report_vanished_file(&mut self) -> Result<(), Error> {
         writeln!(self.errors, "warning: file vanished while reading: {:?}", self.path)?;
         Ok(())
@@ -564,14 +844,29 @@ impl Archiver {
                     }
                 }
 
+                if self.feature_flags.contains(Flags::WITH_OCI_WHITEOUTS)
+                    && OciWhiteout::from_file_name(c_file_name.to_bytes()).is_some()
+                {
+                    // whiteouts are markers only, their content is irrelevant
+                    if let Some(ref catalog) = self.catalog {
+                        catalog.lock().unwrap().add_file(c_file_name, 0, stat.st_mtime)?;
+                    }
+                    encoder.create_file(&metadata, file_name, 0).await?;
+                    return Ok(());
+                }
+
                 let file_size = stat.st_size as u64;
                 if let Some(ref catalog) = self.catalog {
                     catalog.lock().unwrap().add_file(c_file_name, file_size, stat.st_mtime)?;
                 }
 
-                let offset: LinkOffset =
+                let (offset, csum) =
                     self.add_regular_file(encoder, fd, file_name, &metadata, file_size).await?;
 
+                if let (Some(ref catalog), Some(csum)) = (&self.catalog, csum) {
+                    catalog.lock().unwrap().set_file_checksum(c_file_name, &csum)?;
+                }
+
                 if stat.st_nlink > 1 {
                     self.hardlinks.insert(link_info, (self.path.clone(), offset));
                 }
@@ -684,12 +979,74 @@ impl Archiver {
         file_name: &Path,
         metadata: &Metadata,
         file_size: u64,
-    ) -> Result<LinkOffset, Error> {
+    ) -> Result<(LinkOffset, Option<[u8; 32]>), Error> {
+        if let Some(id) = self.current_prefetch.take() {
+            // on errors, fall back to reading the file ourselves, so that they are reported as
+            // usual (vanished, access denied...)
+            if let Ok(prefetched) = self.prefetcher.as_mut().unwrap().take(id) {
+                // the worker opened the file by name, it may have been replaced since we opened it
+                if prefetched.is_same_file(&nix::sys::stat::fstat(fd.as_raw_fd())?) {
+                    return self
+                        .add_prefetched_file(encoder, prefetched, file_name, metadata, file_size)
+                        .await;
+                }
+            }
+        }
+
         let mut file = unsafe { std::fs::File::from_raw_fd(fd.into_raw_fd()) };
         let mut remaining = file_size;
         let mut out = encoder.create_file(metadata, file_name, file_size).await?;
+
+        // computed over the archived content (including zeroes written for holes)
+        let mut hasher = if self.file_checksums { Some(Sha256::new()) } else { None };
+
+        // The archive format has no notion of holes, the payload always contains the full file
+        // contents. But for sparse files we avoid reading the holes and write zeroes instead.
+        // (The extractor creates holes for zero blocks again.) End of the current data region,
+        // u64::MAX means "read everything".
+        let mut offset = 0u64;
+        let mut data_end = if is_sparse(file.as_raw_fd(), file_size) { 0 } else { u64::MAX };
+
         while remaining != 0 {
-            let mut got = match file.read(&mut self.file_copy_buffer[..]) {
+            if offset >= data_end {
+                let hole_end = match next_data_region(file.as_raw_fd(), offset) {
+                    Ok(Some((start, end))) if end > start => {
+                        data_end = end;
+                        start
+                    }
+                    Ok(None) => {
+                        // no more data, the rest (up to the current file size) is a hole
+                        data_end = u64::MAX;
+                        nix::sys::stat::fstat(file.as_raw_fd())?.st_size as u64
+                    }
+                    _ => {
+                        // no (usable) hole detection on this file system, fall back to reading
+                        data_end = u64::MAX;
+                        offset
+                    }
+                };
+                let hole = hole_end.saturating_sub(offset).min(remaining);
+                if hole > 0 {
+                    let to_zero = hole.min(self.file_copy_buffer.len() as u64) as usize;
+                    vec::clear(&mut self.file_copy_buffer[..to_zero]);
+                    let mut count = hole;
+                    while count != 0 {
+                        let fill = count.min(to_zero as u64) as usize;
+                        if let Some(ref mut hasher) = hasher {
+                            hasher.update(&self.file_copy_buffer[..fill]);
+                        }
+                        out.write_all(&self.file_copy_buffer[..fill]).await?;
+                        count -= fill as u64;
+                    }
+                    remaining -= hole;
+                    offset += hole;
+                }
+                nix::unistd::lseek(file.as_raw_fd(), offset as i64, nix::unistd::Whence::SeekSet)?;
+                continue;
+            }
+
+            let max = (data_end - offset).min(self.file_copy_buffer.len() as u64) as usize;
+            let mut got = match file.read(&mut self.file_copy_buffer[..max]) {
                 Ok(0) => break,
                 Ok(got) => got,
                 Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
@@ -699,8 +1056,17 @@ impl Archiver {
                 self.report_file_grew_while_reading()?;
                 got = remaining as usize;
             }
+            if let Some(ref mut limiter) = self.read_limiter {
+                if let Some(delay) = limiter.register_traffic(Instant::now(), got as u64) {
+                    tokio::time::sleep(delay).await;
+                }
+            }
+            if let Some(ref mut hasher) = hasher {
+                hasher.update(&self.file_copy_buffer[..got]);
+            }
             out.write_all(&self.file_copy_buffer[..got]).await?;
             remaining -= got as u64;
+            offset += got as u64;
         }
         if remaining > 0 {
             self.report_file_shrunk_while_reading()?;
@@ -708,12 +1074,47 @@ impl Archiver {
             vec::clear(&mut self.file_copy_buffer[..to_zero]);
             while remaining != 0 {
                 let fill = remaining.min(self.file_copy_buffer.len() as u64) as usize;
+                if let Some(ref mut hasher) = hasher {
+                    hasher.update(&self.file_copy_buffer[..fill]);
+                }
                 out.write_all(&self.file_copy_buffer[..fill]).await?;
                 remaining -= fill as u64;
             }
         }
 
-        Ok(out.file_offset())
+        Ok((out.file_offset(), hasher.map(Sha256::finish)))
+    }
+
+    async fn add_prefetched_file<T: SeqWrite + Send>(
+        &mut self,
+        encoder: &mut Encoder<'_, T>,
+        prefetched: PrefetchedData,
+        file_name: &Path,
+        metadata: &Metadata,
+        file_size: u64,
+    ) -> Result<(LinkOffset, Option<[u8; 32]>), Error> {
+        let mut data = prefetched.data;
+
+        if prefetched.grew {
+            self.report_file_grew_while_reading()?;
+        }
+        if (data.len() as u64) < file_size {
+            self.report_file_shrunk_while_reading()?;
+            data.resize(file_size as usize, 0);
+        }
+
+        if let Some(ref mut limiter) = self.read_limiter {
+            if let Some(delay) = limiter.register_traffic(Instant::now(), data.len() as u64) {
+                tokio::time::sleep(delay).await;
+            }
+        }
+
+        let csum = if self.file_checksums { Some(openssl::sha::sha256(&data)) } else { None };
+
+        let mut out = encoder.create_file(metadata, file_name, file_size).await?;
+        out.write_all(&data).await?;
+
+        Ok((out.file_offset(), csum))
     }
 
     async fn add_symlink<T: SeqWrite + Send>(
@@ -747,7 +1148,17 @@ fn get_metadata(fd: RawFd, stat: &FileStat, flags: Flags, fs_magic: i64, fs_feat
     // required for some of these
     let proc_path = Path::new("/proc/self/fd/").join(fd.to_string());
 
-    let mut meta = Metadata {
+    let mut meta = stat_metadata(stat);
+
+    get_xattr_fcaps_acl(&mut meta, fd, &proc_path, flags, fs_feature_flags)?;
+    get_chattr(&mut meta, fd)?;
+    get_fat_attr(&mut meta, fd, fs_magic)?;
+    get_quota_project_id(&mut meta, fd, flags, fs_magic)?;
+    Ok(meta)
+}
+
+fn stat_metadata(stat: &FileStat) -> Metadata {
+    Metadata {
         stat: pxar::Stat {
             mode: u64::from(stat.st_mode),
             flags: 0,
@@ -756,13 +1167,7 @@ fn get_metadata(fd: RawFd, stat: &FileStat, flags: Flags, fs_magic: i64, fs_feat
             mtime: pxar::format::StatxTimestamp::new(stat.st_mtime, stat.st_mtime_nsec as u32),
         },
         ..Default::default()
-    };
-
-    get_xattr_fcaps_acl(&mut meta, fd, &proc_path, flags, fs_feature_flags)?;
-    get_chattr(&mut meta, fd)?;
-    get_fat_attr(&mut meta, fd, fs_magic)?;
-    get_quota_project_id(&mut meta, fd, flags, fs_magic)?;
-    Ok(meta)
+    }
 }
 
 fn get_fcaps(meta: &mut Metadata, fd: RawFd, flags: Flags, fs_feature_flags: &mut Flags) -> Result<(), Error> {
@@ -785,6 +1190,30 @@ fn get_fcaps(meta: &mut Metadata, fd: RawFd, flags: Flags, fs_feature_flags: &mu
     }
 }
 
+// Check if a file has holes: fewer blocks allocated than needed for its size.
+fn is_sparse(fd: RawFd, file_size: u64) -> bool {
+    match nix::sys::stat::fstat(fd) {
+        Ok(stat) => (stat.st_blocks as u64) * 512 < file_size,
+        Err(_) => false,
+    }
+}
+
+// Returns the next data region (start, end) at or after 'offset' using
+// SEEK_DATA/SEEK_HOLE, or None if there is no more data. File systems without
+// hole detection return EINVAL (or treat the whole file as data).
+fn next_data_region(fd: RawFd, offset: u64) -> Result<Option<(u64, u64)>, nix::Error> {
+    use nix::unistd::{lseek, Whence};
+
+    let start = match lseek(fd, offset as i64, Whence::SeekData) {
+        Ok(start) => start as u64,
+        Err(nix::Error::Sys(Errno::ENXIO)) => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    let end = lseek(fd, start as i64, Whence::SeekHole)? as u64;
+
+    Ok(Some((start, end)))
+}
+
 fn get_xattr_fcaps_acl(
     meta: &mut Metadata,
     fd: RawFd,
@@ -817,19 +1246,32 @@ fn get_xattr_fcaps_acl(
             continue;
         }
 
-        if !xattr::is_valid_xattr_name(&attr) {
+        if xattr::is_selinux_context(attr.to_bytes()) {
+            if flags.contains(Flags::WITH_SELINUX) {
+                get_xattr(meta, fd, attr)?;
+            }
             continue;
         }
 
-        match xattr::fgetxattr(fd, attr) {
-            Ok(data) => meta
-                .xattrs
-                .push(pxar::format::XAttr::new(attr.to_bytes(), data)),
-            Err(Errno::ENODATA) => (), // it got removed while we were iterating...
-            Err(Errno::EOPNOTSUPP) => (), // shouldn't be possible so just ignore this
-            Err(Errno::EBADF) => (),   // symlinks, shouldn't be able to reach this either
-            Err(err) => bail!("error reading extended attribute {:?}: {}", attr, err),
+        if !xattr::is_valid_xattr_name(&attr) {
+            continue;
         }
+
+        get_xattr(meta, fd, attr)?;
+    }
+
+    Ok(())
+}
+
+fn get_xattr(meta: &mut Metadata, fd: RawFd, attr: &CStr) -> Result<(), Error> {
+    match xattr::fgetxattr(fd, attr) {
+        Ok(data) => meta
+            .xattrs
+            .push(pxar::format::XAttr::new(attr.to_bytes(), data)),
+        Err(Errno::ENODATA) => (), // it got removed while we were iterating...
+        Err(Errno::EOPNOTSUPP) => (), // shouldn't be possible so just ignore this
+        Err(Errno::EBADF) => (),   // symlinks, shouldn't be able to reach this either
+        Err(err) => bail!("error reading extended attribute {:?}: {}", attr, err),
     }
 
     Ok(())
@@ -1,5 +1,6 @@
 //! Code for extraction of pxar contents onto the file system.
 
+use std::collections::HashSet;
 use std::convert::TryFrom;
 use std::ffi::{CStr, CString, OsStr, OsString};
 use std::io;
@@ -30,6 +31,7 @@ use proxmox::tools::{
 use crate::pxar::dir_stack::PxarDirStack;
 use crate::pxar::metadata;
 use crate::pxar::Flags;
+use crate::pxar::tools::OciWhiteout;
 
 use crate::tools::zip::{ZipEncoder, ZipEntry};
 
@@ -120,6 +122,23 @@ where
             Some(MatchType::Exclude) => false,
             None => current_match,
         };
+
+        if did_match && extractor.contains_flags(Flags::WITH_OCI_WHITEOUTS) {
+            if let EntryKind::File { .. } = entry.kind() {
+                if let Some((whiteout, target)) = OciWhiteout::from_file_name(file_name_os.as_bytes()) {
+                    callback(entry.path());
+                    extractor
+                        .apply_oci_whiteout(whiteout, target)
+                        .map_err(|err| format_err!("error at entry {:?}: {}", file_name_os, err))?;
+                    continue;
+                }
+            }
+
+            if !matches!(entry.kind(), EntryKind::GoodbyeTable) {
+                extractor.note_extracted(file_name_os);
+            }
+        }
+
         match (did_match, entry.kind()) {
             (_, EntryKind::Directory) => {
                 callback(entry.path());
@@ -220,6 +239,10 @@ pub(crate) struct Extractor {
     allow_existing_dirs: bool,
     dir_stack: PxarDirStack,
 
+    /// Names extracted into each directory of `dir_stack`, only tracked with
+    /// `Flags::WITH_OCI_WHITEOUTS` so opaque whiteouts keep entries of the current layer.
+    extracted_names: Vec<HashSet<OsString>>,
+
     /// For better error output we need to track the current path in the Extractor state.
     current_path: Arc<Mutex<OsString>>,
 
@@ -238,6 +261,7 @@ impl Extractor {
     ) -> Self {
         Self {
             dir_stack: PxarDirStack::new(root_dir, metadata),
+            extracted_names: vec![HashSet::new()],
             allow_existing_dirs,
             feature_flags,
             current_path: Arc::new(Mutex::new(OsString::new())),
@@ -275,6 +299,10 @@ impl Extractor {
     ) -> Result<(), Error> {
         self.dir_stack.push(file_name, metadata)?;
 
+        if self.contains_flags(Flags::WITH_OCI_WHITEOUTS) {
+            self.extracted_names.push(HashSet::new());
+        }
+
         if create {
             self.dir_stack.create_last_dir(self.allow_existing_dirs)?;
         }
@@ -292,6 +320,10 @@ impl Extractor {
             .map_err(|err| format_err!("unexpected end of directory entry: {}", err))?
             .ok_or_else(|| format_err!("broken pxar archive (directory stack underrun)"))?;
 
+        if self.contains_flags(Flags::WITH_OCI_WHITEOUTS) {
+            self.extracted_names.pop();
+        }
+
         if let Some(fd) = dir.try_as_borrowed_fd() {
             metadata::apply(
                 self.feature_flags,
@@ -317,6 +349,64 @@ impl Extractor {
             .map_err(|err| format_err!("failed to get parent directory file descriptor: {}", err))
     }
 
+    /// Remember that an entry of the current directory comes from the archive.
+    fn note_extracted(&mut self, file_name: &OsStr) {
+        if let Some(names) = self.extracted_names.last_mut() {
+            names.insert(file_name.to_owned());
+        }
+    }
+
+    /// Apply an OCI whiteout found in the archive to the target directory.
+    ///
+    /// Explicit whiteouts remove the named entry, opaque whiteouts remove every existing entry
+    /// of the current directory which was not extracted from this archive.
+    pub fn apply_oci_whiteout(
+        &mut self,
+        whiteout: OciWhiteout,
+        target: Option<&[u8]>,
+    ) -> Result<(), Error> {
+        let parent = self.parent_fd()?;
+
+        let names = self.extracted_names.last();
+        let is_extracted = |name: &[u8]| {
+            names.map(|names| names.contains(OsStr::from_bytes(name))).unwrap_or(false)
+        };
+
+        match whiteout {
+            OciWhiteout::Explicit => {
+                let target = target
+                    .ok_or_else(|| format_err!("explicit whiteout without target name"))?;
+                if !is_extracted(target) {
+                    remove_entry_at(parent, &CString::new(target)?)?;
+                }
+            }
+            OciWhiteout::Opaque => {
+                let mut dir = Dir::openat(
+                    parent,
+                    ".",
+                    OFlag::O_DIRECTORY | OFlag::O_RDONLY | OFlag::O_CLOEXEC,
+                    Mode::empty(),
+                )?;
+
+                let mut remove = Vec::new();
+                for item in dir.iter() {
+                    let item = item?;
+                    let name = item.file_name().to_bytes();
+                    if name == b"." || name == b".." || is_extracted(name) {
+                        continue;
+                    }
+                    remove.push(item.file_name().to_owned());
+                }
+
+                for name in remove {
+                    remove_entry_at(parent, &name)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn extract_symlink(
         &mut self,
         file_name: &CStr,
@@ -503,6 +593,46 @@ impl Extractor {
     }
 }
 
+/// Remove a file or a whole directory tree relative to `parent`. A missing entry is not an error.
+fn remove_entry_at(parent: RawFd, name: &CStr) -> Result<(), Error> {
+    use nix::errno::Errno;
+    use nix::unistd::{unlinkat, UnlinkatFlags};
+
+    match unlinkat(Some(parent), name, UnlinkatFlags::NoRemoveDir) {
+        Ok(()) => return Ok(()),
+        Err(nix::Error::Sys(Errno::ENOENT)) => return Ok(()),
+        Err(nix::Error::Sys(Errno::EISDIR)) => (),
+        Err(err) => bail!("failed to remove {:?}: {}", name, err),
+    }
+
+    let mut dir = Dir::openat(
+        parent,
+        name,
+        OFlag::O_DIRECTORY | OFlag::O_RDONLY | OFlag::O_NOFOLLOW | OFlag::O_CLOEXEC,
+        Mode::empty(),
+    )
+    .map_err(|err| format_err!("failed to open directory {:?}: {}", name, err))?;
+    let dir_fd = dir.as_raw_fd();
+
+    let mut children = Vec::new();
+    for item in dir.iter() {
+        let item = item?;
+        let child = item.file_name();
+        if child.to_bytes() != b"." && child.to_bytes() != b".." {
+            children.push(child.to_owned());
+        }
+    }
+
+    for child in children {
+        remove_entry_at(dir_fd, &child)?;
+    }
+
+    unlinkat(Some(parent), name, UnlinkatFlags::RemoveDir)
+        .map_err(|err| format_err!("failed to remove directory {:?}: {}", name, err))?;
+
+    Ok(())
+}
+
 pub async fn create_zip<T, W, P>(
     output: W,
     decoder: Accessor<T>,
@@ -63,7 +63,10 @@ bitflags! {
         const WITH_XATTRS                      = 0x1000_0000;
         /// Preserve Access Control List metadata
         const WITH_ACL                         = 0x2000_0000;
-        /// Preserve SELinux security context
+        /// Preserve SELinux security context ("security.selinux" xattr)
+        ///
+        /// The context is stored in the extended attribute block, so this only has an effect
+        /// together with `WITH_XATTRS`.
         const WITH_SELINUX                     = 0x4000_0000;
         /// Preserve "security.capability" xattr
         const WITH_FCAPS                       = 0x8000_0000;
@@ -71,6 +74,9 @@ bitflags! {
         /// Preserve XFS/ext4/ZFS project quota ID
         const WITH_QUOTA_PROJID                = 0x0001_0000_0000;
 
+        /// Handle OCI container layer whiteouts (`.wh.<name>` and `.wh..wh..opq`)
+        const WITH_OCI_WHITEOUTS               = 0x0002_0000_0000;
+
         /// Support ".pxarexclude" files
         const EXCLUDE_FILE                     = 0x1000_0000_0000_0000;
         /// Exclude submounts
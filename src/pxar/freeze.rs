@@ -0,0 +1,215 @@
+//! Freeze the source file system while creating an archive
+//!
+//! A frozen file system blocks all modifications (writers sleep until it is thawed), so the
+//! archive represents a crash consistent state. This requires `CAP_SYS_ADMIN`, and the archive
+//! must not be written to the frozen file system itself.
+//!
+//! The kernel does not thaw a file system when the freezing process dies, so a watchdog child
+//! process thaws it when the client exits (or gets killed), or after a hard timeout.
+
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{bail, Error};
+use nix::errno::Errno;
+use nix::unistd::{ForkResult, Pid};
+
+use crate::tools::Fd;
+
+// From /usr/include/linux/fs.h
+// #define FIFREEZE _IOWR('X', 119, int)
+// #define FITHAW _IOWR('X', 120, int)
+nix::ioctl_readwrite!(fs_ioc_freeze, b'X', 119, libc::c_int);
+nix::ioctl_readwrite!(fs_ioc_thaw, b'X', 120, libc::c_int);
+
+/// Maximum time a file system stays frozen, the watchdog thaws it after that
+pub const FREEZE_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// Returns true for memory backed file systems, freezing them makes no sense
+pub fn is_temporary_file_system(magic: i64) -> bool {
+    magic == libc::TMPFS_MAGIC as i64 || magic == libc::RAMFS_MAGIC as i64
+}
+
+// Files the client itself needs while running: its binary (and libraries), temporary and
+// runtime files, and stdout/stderr if they are redirected to a (log) file. Any write to a
+// frozen file system blocks, so freezing one of these would deadlock the client.
+fn client_file_systems() -> Vec<(String, u64)> {
+    let mut paths: Vec<PathBuf> = vec![PathBuf::from("/proc/self/exe"), std::env::temp_dir()];
+    if let Some(runtime_dir) = std::env::var_os("XDG_RUNTIME_DIR") {
+        paths.push(runtime_dir.into());
+    }
+
+    let mut list = Vec::new();
+    for path in paths {
+        if let Ok(stat) = nix::sys::stat::stat(&path) {
+            list.push((path.to_string_lossy().into_owned(), stat.st_dev));
+        }
+    }
+
+    for (name, fd) in [("stdout", libc::STDOUT_FILENO), ("stderr", libc::STDERR_FILENO)].iter() {
+        if let Ok(stat) = nix::sys::stat::fstat(*fd) {
+            if stat.st_mode & libc::S_IFMT == libc::S_IFREG {
+                list.push((name.to_string(), stat.st_dev));
+            }
+        }
+    }
+
+    list
+}
+
+fn thaw(fd: RawFd) -> Result<(), nix::Error> {
+    let mut arg: libc::c_int = 0;
+    unsafe { fs_ioc_thaw(fd, &mut arg) }.map(drop)
+}
+
+// messages from the guard to the watchdog
+const WATCHDOG_FROZEN: u8 = b'F';
+const WATCHDOG_THAWED: u8 = b'T';
+
+// Watchdog child: once the guard reports the freeze, it thaws the file system if the pipe gets
+// closed without the guard reporting the thaw (parent died), or after the timeout. Only uses
+// async-signal-safe calls after fork.
+fn run_watchdog(fd: RawFd, pipe_read: RawFd, timeout: Duration) -> ! {
+    let mut pollfd = libc::pollfd { fd: pipe_read, events: libc::POLLIN, revents: 0 };
+    let timeout_ms = timeout.as_millis().min(libc::c_int::MAX as u128) as libc::c_int;
+    let mut frozen = false;
+    loop {
+        let res = unsafe { libc::poll(&mut pollfd, 1, if frozen { timeout_ms } else { -1 }) };
+        if res == 0 {
+            let _ = thaw(fd); // timeout
+            break;
+        } else if res < 0 {
+            if Errno::last() == Errno::EINTR {
+                continue;
+            }
+            if frozen {
+                let _ = thaw(fd);
+            }
+            break;
+        }
+
+        let mut msg = 0u8;
+        match unsafe { libc::read(pipe_read, &mut msg as *mut u8 as *mut libc::c_void, 1) } {
+            1 if msg == WATCHDOG_FROZEN => frozen = true,
+            1 if msg == WATCHDOG_THAWED => break,
+            n if n < 0 && Errno::last() == Errno::EINTR => continue,
+            _ => {
+                // EOF, the guard was not dropped normally
+                if frozen {
+                    let _ = thaw(fd);
+                }
+                break;
+            }
+        }
+    }
+    unsafe { libc::_exit(0) }
+}
+
+fn notify_watchdog(pipe_write: &Fd, msg: u8) -> Result<(), nix::Error> {
+    nix::unistd::write(pipe_write.as_raw_fd(), &[msg]).map(drop)
+}
+
+/// Freezes a file system, thaws it when dropped
+pub struct FsFreezeGuard {
+    fd: Fd,
+    frozen: bool,
+    watchdog: Pid,
+    pipe_write: Option<Fd>,
+}
+
+impl FsFreezeGuard {
+    /// Freeze the file system containing `fd`, for at most `timeout`
+    ///
+    /// Refuses to freeze the file system holding the files of the client itself.
+    pub fn freeze(fd: RawFd, timeout: Duration) -> Result<Self, Error> {
+        let stat = nix::sys::stat::fstat(fd)?;
+        for (name, dev) in client_file_systems() {
+            if dev == stat.st_dev {
+                bail!("refusing to freeze the file system containing {}", name);
+            }
+        }
+
+        let fd = Fd(nix::unistd::dup(fd)?);
+
+        let (pipe_read, pipe_write) = nix::unistd::pipe2(nix::fcntl::OFlag::O_CLOEXEC)?;
+        let (pipe_read, pipe_write) = (Fd(pipe_read), Fd(pipe_write));
+
+        // start the watchdog before freezing, so that there is no window without it
+        let watchdog = match unsafe { nix::unistd::fork() }? {
+            ForkResult::Child => {
+                drop(pipe_write);
+                run_watchdog(fd.as_raw_fd(), pipe_read.as_raw_fd(), timeout);
+            }
+            ForkResult::Parent { child } => child,
+        };
+        drop(pipe_read);
+
+        // from here on, dropping the guard thaws the file system and stops the watchdog
+        let mut guard = Self { fd, frozen: false, watchdog, pipe_write: Some(pipe_write) };
+
+        let mut arg: libc::c_int = 0;
+        match unsafe { fs_ioc_freeze(guard.fd.as_raw_fd(), &mut arg) } {
+            Ok(_) => {
+                guard.frozen = true;
+                if let Some(pipe_write) = &guard.pipe_write {
+                    notify_watchdog(pipe_write, WATCHDOG_FROZEN)?;
+                }
+                Ok(guard)
+            }
+            Err(nix::Error::Sys(Errno::EOPNOTSUPP)) => {
+                bail!("unable to freeze file system - not supported by this file system")
+            }
+            Err(nix::Error::Sys(Errno::EPERM)) => {
+                bail!("unable to freeze file system - permission denied (requires root)")
+            }
+            Err(nix::Error::Sys(Errno::EBUSY)) => {
+                bail!("unable to freeze file system - already frozen")
+            }
+            Err(err) => bail!("unable to freeze file system - {}", err),
+        }
+    }
+}
+
+impl Drop for FsFreezeGuard {
+    fn drop(&mut self) {
+        if self.frozen {
+            match thaw(self.fd.as_raw_fd()) {
+                Ok(()) => {
+                    if let Some(pipe_write) = &self.pipe_write {
+                        let _ = notify_watchdog(pipe_write, WATCHDOG_THAWED);
+                    }
+                }
+                // not frozen anymore, the watchdog thawed it after the timeout
+                Err(nix::Error::Sys(Errno::EINVAL)) => {
+                    log::warn!("file system was thawed by the watchdog (freeze timeout reached)");
+                }
+                Err(err) => log::error!("unable to thaw file system - {}", err),
+            }
+        }
+
+        // closing the pipe stops the watchdog (after thawing, if not reported above)
+        drop(self.pipe_write.take());
+        if let Err(err) = nix::sys::wait::waitpid(self.watchdog, None) {
+            log::error!("waiting for freeze watchdog failed - {}", err);
+        }
+    }
+}
+
+#[test]
+fn test_refuse_freezing_client_file_system() -> Result<(), Error> {
+    use nix::fcntl::OFlag;
+    use nix::sys::stat::Mode;
+
+    // the file system with the client binary must never be frozen
+    let exe_dir = std::env::current_exe()?.parent().unwrap().to_owned();
+
+    let dir = nix::dir::Dir::open(&exe_dir, OFlag::O_DIRECTORY | OFlag::O_RDONLY, Mode::empty())?;
+    let err = match FsFreezeGuard::freeze(dir.as_raw_fd(), FREEZE_TIMEOUT) {
+        Ok(_) => panic!("froze the file system of the test binary"),
+        Err(err) => err,
+    };
+    assert!(err.to_string().contains("refusing to freeze"), "unexpected error: {}", err);
+
+    Ok(())
+}
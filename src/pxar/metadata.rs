@@ -118,6 +118,7 @@ pub fn apply(
     apply_xattrs(flags, c_proc_path.as_ptr(), metadata, &mut skip_xattrs)
         .or_else(&mut *on_error)?;
     add_fcaps(flags, c_proc_path.as_ptr(), metadata, &mut skip_xattrs).or_else(&mut *on_error)?;
+    apply_selinux_context(flags, c_proc_path.as_ptr(), metadata).or_else(&mut *on_error)?;
     apply_acls(flags, &c_proc_path, metadata, path_info)
         .map_err(|err| format_err!("failed to apply acls: {}", err))
         .or_else(&mut *on_error)?;
@@ -207,6 +208,10 @@ fn apply_xattrs(
             return Ok(());
         }
 
+        if xattr::is_selinux_context(xattr.name().to_bytes()) {
+            continue; // see apply_selinux_context
+        }
+
         if !xattr::is_valid_xattr_name(xattr.name()) {
             eprintln!("skipping invalid xattr named {:?}", xattr.name());
             continue;
@@ -229,6 +234,43 @@ fn apply_xattrs(
     Ok(())
 }
 
+// The SELinux context is stored in the xattr block, so archives created
+// without WITH_XATTRS never contain one. It is restored on its own, because
+// an unsupported or rejected context must not skip the remaining xattrs.
+fn apply_selinux_context(
+    flags: Flags,
+    c_proc_path: *const libc::c_char,
+    metadata: &Metadata,
+) -> Result<(), Error> {
+    if !flags.contains(Flags::WITH_SELINUX) {
+        return Ok(());
+    }
+
+    let context = match metadata
+        .xattrs
+        .iter()
+        .find(|xattr| xattr::is_selinux_context(xattr.name().to_bytes()))
+    {
+        Some(xattr) => xattr,
+        None => return Ok(()),
+    };
+
+    c_result!(unsafe {
+        libc::setxattr(
+            c_proc_path,
+            xattr::xattr_name_selinux().as_ptr(),
+            context.value().as_ptr() as *const libc::c_void,
+            context.value().len(),
+            0,
+        )
+    })
+    .map(drop)
+    .or_else(allow_notsupp)
+    .map_err(|err| format_err!("failed to apply SELinux security context: {}", err))?;
+
+    Ok(())
+}
+
 fn apply_acls(
     flags: Flags,
     c_proc_path: &CStr,
@@ -51,14 +51,16 @@ pub mod catalog;
 pub(crate) mod create;
 pub(crate) mod dir_stack;
 pub(crate) mod extract;
+pub(crate) mod freeze;
 pub(crate) mod metadata;
+pub(crate) mod prefetch;
 pub mod fuse;
 pub(crate) mod tools;
 
 mod flags;
 pub use flags::Flags;
 
-pub use create::{create_archive, PxarCreateOptions};
+pub use create::{create_archive, encode_async_file, PxarCreateOptions};
 pub use extract::{
     create_zip, extract_archive, extract_sub_dir, extract_sub_dir_seq, ErrorHandler,
     PxarExtractOptions,
@@ -66,7 +68,7 @@ pub use extract::{
 
 /// The format requires to build sorted directory lookup tables in
 /// memory, so we restrict the number of allowed entries to limit
-/// maximum memory usage.
+/// maximum memory usage. This is the default, see `PxarCreateOptions::entries_max`.
 pub const ENCODER_MAX_ENTRIES: usize = 1024 * 1024;
 
 pub use tools::{format_multi_line_entry, format_single_line_entry};
@@ -0,0 +1,236 @@
+//! Parallel file content prefetching for the archive encoder.
+//!
+//! The pxar encoder itself is strictly sequential: every entry is written at the current
+//! archive offset, and directory goodbye tables reference the offsets of their entries. So the
+//! archiver task stays the only one talking to the encoder, and it still visits entries in the
+//! (sorted) directory file list order. Worker threads never see the encoder - they only open
+//! and read the contents of regular files which are coming up next in the current directory, and
+//! hand the data back keyed by a job id.
+//!
+//! When the archiver reaches such a file, it waits for the prefetched data and writes it like it
+//! would have written the data read by itself. Because of that the produced archive (including
+//! all offsets and goodbye tables) is byte-for-byte identical to a sequential run.
+//!
+//! Only small and medium sized files (`MAX_FILE_SIZE`) are prefetched, and the amount of data in
+//! flight is bounded by `WINDOW_SIZE`. Files failing to prefetch (e.g. vanished, access denied)
+//! are simply read again by the archiver, so error reporting is the same as without prefetching.
+//!
+//! Workers open files by name, which may refer to another inode than the file the archiver
+//! opened itself (if the file got replaced in between). The data carries the device and inode
+//! of the file actually read, and the archiver only uses it if they match (see `is_same_file`).
+
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+use std::io::Read;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::sync::{Arc, Condvar, Mutex};
+
+use anyhow::{bail, Error};
+use nix::errno::Errno;
+use nix::fcntl::OFlag;
+use nix::sys::stat::Mode;
+
+use crate::tools::{Fd, ParallelHandler};
+
+/// File contents read by a worker thread
+pub(crate) struct PrefetchedData {
+    /// The file contents, at most the expected size (shorter if the file shrunk)
+    pub data: Vec<u8>,
+    /// Set if the file contains more data than expected
+    pub grew: bool,
+    dev: u64,
+    ino: u64,
+}
+
+impl PrefetchedData {
+    /// Check if the data was read from the file with this stat
+    pub fn is_same_file(&self, stat: &nix::sys::stat::FileStat) -> bool {
+        self.dev == stat.st_dev && self.ino == stat.st_ino
+    }
+}
+
+struct PrefetchJob {
+    id: u64,
+    dir: Arc<Fd>,
+    name: CString,
+    size: u64,
+    update_atime: bool,
+}
+
+#[derive(Default)]
+struct PrefetchState {
+    results: Mutex<HashMap<u64, Result<PrefetchedData, Error>>>,
+    cond: Condvar,
+}
+
+pub(crate) struct FilePrefetcher {
+    pool: ParallelHandler<PrefetchJob>,
+    state: Arc<PrefetchState>,
+    submitted: HashMap<u64, u64>,
+    pending_bytes: u64,
+    next_id: u64,
+    update_atime: bool,
+}
+
+impl FilePrefetcher {
+    /// Larger files are always read by the archiver itself
+    pub const MAX_FILE_SIZE: u64 = 8 * 1024 * 1024;
+
+    /// Maximum amount of prefetched, but not yet written data
+    pub const WINDOW_SIZE: u64 = 64 * 1024 * 1024;
+
+    pub fn new(threads: usize, update_atime: bool) -> Self {
+        let state = Arc::new(PrefetchState::default());
+
+        let worker_state = Arc::clone(&state);
+        let pool = ParallelHandler::new("pxar prefetch", threads, move |job: PrefetchJob| {
+            let result = read_file(&job);
+            worker_state.results.lock().unwrap().insert(job.id, result);
+            worker_state.cond.notify_all();
+            Ok(())
+        });
+
+        Self {
+            pool,
+            state,
+            submitted: HashMap::new(),
+            pending_bytes: 0,
+            next_id: 0,
+            update_atime,
+        }
+    }
+
+    /// Duplicate a directory file descriptor, so that it can be used by the worker threads.
+    pub fn share_dir(dir_fd: RawFd) -> Result<Arc<Fd>, Error> {
+        let fd = nix::fcntl::fcntl(dir_fd, nix::fcntl::FcntlArg::F_DUPFD_CLOEXEC(0))?;
+        Ok(Arc::new(Fd(fd)))
+    }
+
+    /// Check if a file of this size is prefetched at all
+    pub fn wants(size: u64) -> bool {
+        size > 0 && size <= Self::MAX_FILE_SIZE
+    }
+
+    /// Check if there is room for another file of this size.
+    pub fn has_room(&self, size: u64) -> bool {
+        self.pending_bytes + size <= Self::WINDOW_SIZE
+    }
+
+    /// Queue a file for prefetching, returns the job id.
+    ///
+    /// This blocks if all worker threads are busy.
+    pub fn submit(&mut self, dir: &Arc<Fd>, name: &CStr, size: u64) -> Result<u64, Error> {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let job = PrefetchJob {
+            id,
+            dir: Arc::clone(dir),
+            name: name.to_owned(),
+            size,
+            update_atime: self.update_atime,
+        };
+
+        crate::tools::runtime::block_in_place(|| self.pool.send(job))?;
+
+        self.submitted.insert(id, size);
+        self.pending_bytes += size;
+
+        Ok(id)
+    }
+
+    /// Wait for the result of a job.
+    pub fn take(&mut self, id: u64) -> Result<PrefetchedData, Error> {
+        let size = match self.submitted.remove(&id) {
+            Some(size) => size,
+            None => bail!("unknown prefetch job {}", id),
+        };
+
+        let state = &self.state;
+        let result = crate::tools::runtime::block_in_place(|| {
+            let mut results = state.results.lock().unwrap();
+            loop {
+                if let Some(result) = results.remove(&id) {
+                    return result;
+                }
+                results = state.cond.wait(results).unwrap();
+            }
+        });
+
+        self.pending_bytes -= size;
+
+        result
+    }
+
+    /// Stop the worker threads.
+    pub fn finish(self) -> Result<(), Error> {
+        self.pool.complete()
+    }
+}
+
+fn read_file(job: &PrefetchJob) -> Result<PrefetchedData, Error> {
+    let oflags = OFlag::O_RDONLY | OFlag::O_NOFOLLOW | OFlag::O_CLOEXEC | OFlag::O_NOCTTY;
+
+    let fd = if job.update_atime {
+        nix::fcntl::openat(job.dir.as_raw_fd(), job.name.as_c_str(), oflags, Mode::empty())?
+    } else {
+        match nix::fcntl::openat(
+            job.dir.as_raw_fd(),
+            job.name.as_c_str(),
+            oflags | OFlag::O_NOATIME,
+            Mode::empty(),
+        ) {
+            // not the owner and no CAP_FOWNER
+            Err(nix::Error::Sys(Errno::EPERM)) => {
+                nix::fcntl::openat(job.dir.as_raw_fd(), job.name.as_c_str(), oflags, Mode::empty())?
+            }
+            result => result?,
+        }
+    };
+
+    let file = unsafe { std::fs::File::from_raw_fd(fd) };
+
+    let stat = nix::sys::stat::fstat(file.as_raw_fd())?;
+
+    // read one more byte to detect files which grew
+    let mut data = Vec::with_capacity(job.size as usize + 1);
+    file.take(job.size + 1).read_to_end(&mut data)?;
+
+    let grew = data.len() as u64 > job.size;
+    data.truncate(job.size as usize);
+
+    Ok(PrefetchedData { data, grew, dev: stat.st_dev, ino: stat.st_ino })
+}
+
+#[test]
+fn test_prefetch_replaced_file() -> Result<(), Error> {
+    let dir = std::fs::canonicalize(".")?.join(".testdir-pxar-prefetch");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir)?;
+
+    std::fs::write(dir.join("file"), b"original")?;
+    std::fs::write(dir.join("file.new"), b"replaced")?;
+
+    let dir_fd = nix::dir::Dir::open(&dir, OFlag::O_DIRECTORY | OFlag::O_RDONLY, Mode::empty())?;
+    let shared_dir = FilePrefetcher::share_dir(dir_fd.as_raw_fd())?;
+
+    // the archiver opened the original file, which gets replaced before the worker opens it
+    let original = std::fs::File::open(dir.join("file"))?;
+    std::fs::rename(dir.join("file.new"), dir.join("file"))?;
+
+    let mut prefetcher = FilePrefetcher::new(1, false);
+    let name = CString::new("file")?;
+    let id = prefetcher.submit(&shared_dir, &name, 8)?;
+    let prefetched = prefetcher.take(id)?;
+    prefetcher.finish()?;
+
+    assert_eq!(prefetched.data, b"replaced");
+    assert!(!prefetched.is_same_file(&nix::sys::stat::fstat(original.as_raw_fd())?));
+
+    let current = std::fs::File::open(dir.join("file"))?;
+    assert!(prefetched.is_same_file(&nix::sys::stat::fstat(current.as_raw_fd())?));
+
+    let _ = std::fs::remove_dir_all(&dir);
+
+    Ok(())
+}
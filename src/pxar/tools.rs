@@ -10,6 +10,44 @@ use nix::sys::stat::Mode;
 
 use pxar::{mode, Entry, EntryKind, Metadata, format::StatxTimestamp};
 
+/// Prefix of OCI whiteout file names.
+pub const OCI_WHITEOUT_PREFIX: &[u8] = b".wh.";
+
+/// File name of the OCI opaque directory marker.
+pub const OCI_WHITEOUT_OPAQUE: &[u8] = b".wh..wh..opq";
+
+/// Whiteout markers used by OCI container image layers.
+///
+/// The pxar format has no dedicated entry type for these, so with `Flags::WITH_OCI_WHITEOUTS`
+/// the encoder stores them as empty regular files and the extractor recognizes them by name.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum OciWhiteout {
+    /// `.wh.<name>`: remove `<name>` from the lower layers.
+    Explicit = 1,
+    /// `.wh..wh..opq`: hide all lower layer entries of the directory.
+    Opaque = 2,
+}
+
+impl OciWhiteout {
+    /// Check whether a file name is a whiteout marker.
+    ///
+    /// For explicit whiteouts this also returns the name of the entry to remove. Other names
+    /// starting with `.wh..wh.` are reserved by the OCI spec and are not treated as whiteouts.
+    pub fn from_file_name(file_name: &[u8]) -> Option<(Self, Option<&[u8]>)> {
+        if file_name == OCI_WHITEOUT_OPAQUE {
+            return Some((OciWhiteout::Opaque, None));
+        }
+
+        let name = file_name.strip_prefix(OCI_WHITEOUT_PREFIX)?;
+        if name.is_empty() || name == b"." || name == b".." || name.starts_with(OCI_WHITEOUT_PREFIX) {
+            return None;
+        }
+
+        Some((OciWhiteout::Explicit, Some(name)))
+    }
+}
+
 /// Get the file permissions as `nix::Mode`
 pub fn perms_from_metadata(meta: &Metadata) -> Result<Mode, Error> {
     let mode = meta.stat.get_permission_bits();
@@ -86,6 +86,9 @@ pub use gc_job::*;
 mod email_notifications;
 pub use email_notifications::*;
 
+mod webhook_notifications;
+pub use webhook_notifications::*;
+
 mod report;
 pub use report::*;
 
@@ -93,6 +96,8 @@ pub mod ticket;
 
 pub mod auth;
 
+pub mod metrics;
+
 pub(crate) async fn reload_proxy_certificate() -> Result<(), Error> {
     let proxy_pid = crate::server::read_pid(buildcfg::PROXMOX_BACKUP_PROXY_PID_FN)?;
     let sock = crate::server::ctrl_sock_from_pid(proxy_pid);
@@ -0,0 +1,374 @@
+//! Prometheus metrics export
+//!
+//! The `/metrics` endpoint runs all collectors on each scrape request and returns the result
+//! in the Prometheus text exposition format. There is no background polling, so values like
+//! the task gauges are computed from the task list at scrape time.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::path::Path;
+
+use anyhow::Error;
+
+use crate::backup::{chunk_insert_stats, DataStore};
+use crate::config;
+use crate::server::{TaskListInfoIterator, TaskState};
+use crate::tape::{MediaStateDatabase, TAPE_STATUS_DIR};
+
+/// Prometheus metric type
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MetricType {
+    Counter,
+    Gauge,
+}
+
+impl MetricType {
+    fn as_str(&self) -> &'static str {
+        match self {
+            MetricType::Counter => "counter",
+            MetricType::Gauge => "gauge",
+        }
+    }
+}
+
+/// A single value of a metric, identified by its labels
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sample {
+    pub labels: Vec<(String, String)>,
+    pub value: f64,
+}
+
+/// All samples of a metric, with its help text and type
+#[derive(Clone, Debug, PartialEq)]
+pub struct MetricFamily {
+    pub name: String,
+    pub help: String,
+    pub metric_type: MetricType,
+    pub samples: Vec<Sample>,
+}
+
+impl MetricFamily {
+    pub fn new(name: &str, help: &str, metric_type: MetricType) -> Self {
+        Self {
+            name: name.to_string(),
+            help: help.to_string(),
+            metric_type,
+            samples: Vec::new(),
+        }
+    }
+
+    /// Add a sample, `labels` are `(name, value)` pairs.
+    pub fn sample(mut self, labels: &[(&str, &str)], value: f64) -> Self {
+        let labels = labels
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        self.samples.push(Sample { labels, value });
+        self
+    }
+}
+
+/// Something which provides metrics
+pub trait MetricsCollector {
+    fn collect(&self) -> Vec<MetricFamily>;
+}
+
+// label values need `\`, `"` and newlines escaped
+fn escape_label_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// help texts only need `\` and newlines escaped
+fn escape_help(help: &str) -> String {
+    help.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn format_value(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "+Inf" } else { "-Inf" }.to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+/// Merge families with the same name, keeping the order of first appearance.
+///
+/// Each name may only appear once in the output, but multiple collectors (e.g. one per
+/// datastore) provide samples for the same metric.
+pub fn merge_metric_families(families: Vec<MetricFamily>) -> Vec<MetricFamily> {
+    let mut merged: Vec<MetricFamily> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+
+    for family in families {
+        match index.get(&family.name) {
+            Some(pos) => merged[*pos].samples.extend(family.samples),
+            None => {
+                index.insert(family.name.clone(), merged.len());
+                merged.push(family);
+            }
+        }
+    }
+
+    merged
+}
+
+/// Format metric families in the Prometheus text exposition format
+pub fn format_metrics(families: &[MetricFamily]) -> String {
+    let mut output = String::new();
+
+    for family in families {
+        // writing to a String cannot fail
+        let _ = writeln!(output, "# HELP {} {}", family.name, escape_help(&family.help));
+        let _ = writeln!(output, "# TYPE {} {}", family.name, family.metric_type.as_str());
+
+        for sample in family.samples.iter() {
+            output.push_str(&family.name);
+            if !sample.labels.is_empty() {
+                let labels: Vec<String> = sample
+                    .labels
+                    .iter()
+                    .map(|(name, value)| format!("{}=\"{}\"", name, escape_label_value(value)))
+                    .collect();
+                let _ = write!(output, "{{{}}}", labels.join(","));
+            }
+            let _ = writeln!(output, " {}", format_value(sample.value));
+        }
+    }
+
+    output
+}
+
+impl MetricsCollector for DataStore {
+    fn collect(&self) -> Vec<MetricFamily> {
+        let status = match crate::tools::disks::disk_usage(&self.base_path()) {
+            Ok(status) => status,
+            Err(err) => {
+                log::error!("metrics: unable to get usage of datastore '{}' - {}", self.name(), err);
+                return Vec::new();
+            }
+        };
+
+        let labels = [("store", self.name())];
+
+        vec![
+            MetricFamily::new(
+                "proxmox_backup_datastore_total_bytes",
+                "Total size of the datastore file system in bytes.",
+                MetricType::Gauge,
+            )
+            .sample(&labels, status.total as f64),
+            MetricFamily::new(
+                "proxmox_backup_datastore_used_bytes",
+                "Used space of the datastore file system in bytes.",
+                MetricType::Gauge,
+            )
+            .sample(&labels, status.used as f64),
+        ]
+    }
+}
+
+/// Chunk insert statistics of this process (see `chunk_insert_stats`)
+pub struct ChunkMetrics;
+
+impl MetricsCollector for ChunkMetrics {
+    fn collect(&self) -> Vec<MetricFamily> {
+        let (hits, misses) = chunk_insert_stats();
+
+        vec![
+            MetricFamily::new(
+                "proxmox_backup_chunk_hits_total",
+                "Number of uploaded chunks which were already stored.",
+                MetricType::Counter,
+            )
+            .sample(&[], hits as f64),
+            MetricFamily::new(
+                "proxmox_backup_chunk_miss_total",
+                "Number of uploaded chunks which had to be written.",
+                MetricType::Counter,
+            )
+            .sample(&[], misses as f64),
+        ]
+    }
+}
+
+fn task_status(state: &Option<TaskState>) -> &'static str {
+    match state {
+        None => "running",
+        Some(TaskState::OK { .. }) => "ok",
+        Some(TaskState::Warning { .. }) => "warning",
+        Some(TaskState::Error { .. }) => "error",
+        Some(TaskState::Unknown { .. }) => "unknown",
+    }
+}
+
+/// Worker task gauges, computed from the (archived) task list
+///
+/// The task archive gets rotated, so these are no counters.
+pub struct TaskMetrics;
+
+impl MetricsCollector for TaskMetrics {
+    fn collect(&self) -> Vec<MetricFamily> {
+        let mut counts: BTreeMap<(String, &'static str), u64> = BTreeMap::new();
+
+        let list = match TaskListInfoIterator::new(false) {
+            Ok(list) => list,
+            Err(err) => {
+                log::error!("metrics: unable to read task list - {}", err);
+                return Vec::new();
+            }
+        };
+
+        for info in list {
+            let info = match info {
+                Ok(info) => info,
+                Err(err) => {
+                    log::error!("metrics: unable to read task list - {}", err);
+                    return Vec::new();
+                }
+            };
+            let key = (info.upid.worker_type.clone(), task_status(&info.state));
+            *counts.entry(key).or_insert(0) += 1;
+        }
+
+        let mut family = MetricFamily::new(
+            "proxmox_backup_tasks",
+            "Number of worker tasks in the task list by type and status.",
+            MetricType::Gauge,
+        );
+        for ((worker_type, status), count) in counts {
+            family = family.sample(&[("type", worker_type.as_str()), ("status", status)], count as f64);
+        }
+
+        vec![family]
+    }
+}
+
+/// Tape drive counters
+///
+/// The load count includes all media loads done via a changer (e.g. 'load-media',
+/// 'load-slot' and loads by tape backup jobs), see `MediaStateDatabase::drive_load_counts`.
+pub struct TapeDriveMetrics;
+
+impl MetricsCollector for TapeDriveMetrics {
+    fn collect(&self) -> Vec<MetricFamily> {
+        let mut loads: BTreeMap<String, u64> = BTreeMap::new();
+
+        match config::drive::config() {
+            Ok((config, _digest)) => {
+                for drive in config.sections.keys() {
+                    loads.insert(drive.clone(), 0);
+                }
+            }
+            Err(err) => {
+                log::error!("metrics: unable to read drive config - {}", err);
+                return Vec::new();
+            }
+        }
+
+        if loads.is_empty() {
+            return Vec::new();
+        }
+
+        let db = MediaStateDatabase::new(Path::new(TAPE_STATUS_DIR));
+        match db.drive_load_counts() {
+            Ok(counts) => {
+                for (drive, count) in counts {
+                    if let Some(loads) = loads.get_mut(&drive) {
+                        *loads = count;
+                    }
+                }
+            }
+            Err(err) => {
+                log::error!("metrics: unable to read drive load counts - {}", err);
+                return Vec::new();
+            }
+        }
+
+        let mut family = MetricFamily::new(
+            "proxmox_backup_tape_loads_total",
+            "Number of media loads per tape drive.",
+            MetricType::Counter,
+        );
+        for (drive, count) in loads {
+            family = family.sample(&[("drive", drive.as_str())], count as f64);
+        }
+
+        vec![family]
+    }
+}
+
+/// Run all collectors and format the result
+pub fn collect_metrics() -> Result<String, Error> {
+    let mut families = Vec::new();
+
+    let (config, _digest) = config::datastore::config()?;
+    for store in config.sections.keys() {
+        match DataStore::lookup_datastore(store) {
+            Ok(datastore) => families.extend(datastore.collect()),
+            Err(err) => log::error!("metrics: unable to open datastore '{}' - {}", store, err),
+        }
+    }
+
+    let collectors: [&dyn MetricsCollector; 3] = [&ChunkMetrics, &TaskMetrics, &TapeDriveMetrics];
+    for collector in collectors.iter() {
+        families.extend(collector.collect());
+    }
+
+    Ok(format_metrics(&merge_metric_families(families)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_format_metrics() {
+        let families = merge_metric_families(vec![
+            MetricFamily::new("test_bytes", "Size in bytes.", MetricType::Gauge)
+                .sample(&[("store", "store1")], 1024.0),
+            MetricFamily::new("test_total", "A counter.", MetricType::Counter)
+                .sample(&[], 3.0),
+            MetricFamily::new("test_bytes", "Size in bytes.", MetricType::Gauge)
+                .sample(&[("store", "store2")], 1.5),
+        ]);
+
+        assert_eq!(
+            format_metrics(&families),
+            "# HELP test_bytes Size in bytes.\n\
+             # TYPE test_bytes gauge\n\
+             test_bytes{store=\"store1\"} 1024\n\
+             test_bytes{store=\"store2\"} 1.5\n\
+             # HELP test_total A counter.\n\
+             # TYPE test_total counter\n\
+             test_total 3\n"
+        );
+    }
+
+    #[test]
+    fn test_label_formatting() {
+        let families = vec![
+            MetricFamily::new("test_total", "Help with \\ and\nnewline.", MetricType::Counter)
+                .sample(&[("type", "a\"b"), ("status", "c\\d\ne")], f64::INFINITY)
+                .sample(&[("type", "x"), ("status", "ok")], f64::NAN),
+        ];
+
+        assert_eq!(
+            format_metrics(&families),
+            "# HELP test_total Help with \\\\ and\\nnewline.\n\
+             # TYPE test_total counter\n\
+             test_total{type=\"a\\\"b\",status=\"c\\\\d\\ne\"} +Inf\n\
+             test_total{type=\"x\",status=\"ok\"} NaN\n"
+        );
+    }
+}
@@ -56,6 +56,9 @@ pub fn generate_report() -> String {
         .iter()
         .map(|file_name| {
             let content = match file_read_optional_string(Path::new(file_name)) {
+                Ok(Some(content)) if crate::config::encrypted::is_encrypted(&content) => {
+                    String::from("# encrypted configuration file, content omitted")
+                }
                 Ok(Some(content)) => content,
                 Ok(None) => String::from("# file does not exist"),
                 Err(err) => err.to_string(),
@@ -8,7 +8,7 @@ use std::task::{Context, Poll};
 
 use anyhow::{bail, format_err, Error};
 use futures::future::{self, FutureExt, TryFutureExt};
-use futures::stream::TryStreamExt;
+use futures::stream::{Stream, TryStreamExt};
 use hyper::body::HttpBody;
 use hyper::header::{self, HeaderMap};
 use hyper::http::request::Parts;
@@ -30,13 +30,14 @@ use proxmox::api::{
 };
 use proxmox::http_err;
 
-use super::auth::AuthError;
+use super::auth::{ApiAuth, AuthError};
 use super::environment::RestEnvironment;
 use super::formatter::*;
 use super::ApiConfig;
 
 use crate::api2::types::{Authid, Userid};
 use crate::auth_helpers::*;
+use crate::config::acl::PRIV_SYS_AUDIT;
 use crate::config::cached_user_info::CachedUserInfo;
 use crate::tools;
 use crate::tools::compression::{CompressionMethod, DeflateEncoder, Level};
@@ -53,6 +54,8 @@ pub struct RestServer {
 
 const MAX_URI_QUERY_LENGTH: usize = 3072;
 const CHUNK_SIZE_LIMIT: u64 = 32 * 1024;
+/// Maximum body size for requests with JSON or form encoded parameters
+const MAX_PARAMETER_BODY_SIZE: usize = 64 * 1024;
 
 impl RestServer {
     pub fn new(api_config: ApiConfig) -> Self {
@@ -304,6 +307,7 @@ async fn get_request_parameters<S: 'static + BuildHasher + Send>(
     parts: Parts,
     req_body: Body,
     uri_param: HashMap<String, String, S>,
+    max_body_size: usize,
 ) -> Result<Value, Error> {
     let mut is_json = false;
 
@@ -319,19 +323,12 @@ async fn get_request_parameters<S: 'static + BuildHasher + Send>(
         }
     }
 
-    let body = TryStreamExt::map_err(req_body, |err| {
-        http_err!(BAD_REQUEST, "Problems reading request body: {}", err)
-    })
-    .try_fold(Vec::new(), |mut acc, chunk| async move {
-        // FIXME: max request body size?
-        if acc.len() + chunk.len() < 64 * 1024 {
+    let body = SizeLimitedBody::new(req_body, max_body_size)
+        .try_fold(Vec::new(), |mut acc, chunk| async move {
             acc.extend_from_slice(&*chunk);
             Ok(acc)
-        } else {
-            Err(http_err!(BAD_REQUEST, "Request body too large"))
-        }
-    })
-    .await?;
+        })
+        .await?;
 
     let utf8_data =
         std::str::from_utf8(&body).map_err(|err| format_err!("Request body not uft8: {}", err))?;
@@ -350,6 +347,79 @@ async fn get_request_parameters<S: 'static + BuildHasher + Send>(
     }
 }
 
+/// Request body stream which fails once more than `limit` bytes are received
+///
+/// Use [check_content_length] to reject requests announcing a larger body
+/// early, this catches bodies without (or with a wrong) `Content-Length`.
+pub struct SizeLimitedBody {
+    body: Body,
+    limit: usize,
+    received: usize,
+}
+
+impl SizeLimitedBody {
+    pub fn new(body: Body, limit: usize) -> Self {
+        Self { body, limit, received: 0 }
+    }
+}
+
+impl Stream for SizeLimitedBody {
+    type Item = Result<hyper::body::Bytes, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        match Pin::new(&mut this.body).poll_next(cx) {
+            Poll::Ready(Some(Ok(chunk))) => {
+                this.received += chunk.len();
+                if this.received > this.limit {
+                    return Poll::Ready(Some(Err(http_err!(
+                        PAYLOAD_TOO_LARGE,
+                        "Request body too large (limit is {} bytes)",
+                        this.limit
+                    ))));
+                }
+                Poll::Ready(Some(Ok(chunk)))
+            }
+            Poll::Ready(Some(Err(err))) => Poll::Ready(Some(Err(http_err!(
+                BAD_REQUEST,
+                "Problems reading request body: {}",
+                err
+            )))),
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// Reject requests with a `Content-Length` above `limit`, without reading the body.
+pub fn check_content_length(headers: &HeaderMap, limit: usize) -> Result<(), Error> {
+    if let Some(value) = headers.get(header::CONTENT_LENGTH) {
+        let length: u64 = value
+            .to_str()
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .ok_or_else(|| http_err!(BAD_REQUEST, "invalid content length"))?;
+        if length > limit as u64 {
+            return Err(http_err!(
+                PAYLOAD_TOO_LARGE,
+                "Request body too large ({} > {} bytes)",
+                length,
+                limit
+            ));
+        }
+    }
+    Ok(())
+}
+
+// The body of AsyncHttp methods (uploads, protocol upgrades) is passed on
+// to the handler, which is responsible for limiting it.
+fn max_request_body_size(info: &ApiMethod) -> Option<usize> {
+    match info.handler {
+        ApiHandler::AsyncHttp(_) => None,
+        _ => Some(MAX_PARAMETER_BODY_SIZE),
+    }
+}
+
 struct NoLogExtension();
 
 async fn proxy_protected_request(
@@ -403,6 +473,12 @@ pub async fn handle_api_request<Env: RpcEnvironment, S: 'static + BuildHasher +
     let delay_unauth_time = std::time::Instant::now() + std::time::Duration::from_millis(3000);
     let compression = extract_compression_method(&parts.headers);
 
+    let max_body_size = max_request_body_size(info);
+    if let Some(limit) = max_body_size {
+        check_content_length(&parts.headers, limit)?;
+    }
+    let max_body_size = max_body_size.unwrap_or(usize::MAX);
+
     let result = match info.handler {
         ApiHandler::AsyncHttp(handler) => {
             let params = parse_query_parameters(info.parameters, "", &parts, &uri_param)?;
@@ -410,12 +486,14 @@ pub async fn handle_api_request<Env: RpcEnvironment, S: 'static + BuildHasher +
         }
         ApiHandler::Sync(handler) => {
             let params =
-                get_request_parameters(info.parameters, parts, req_body, uri_param).await?;
+                get_request_parameters(info.parameters, parts, req_body, uri_param, max_body_size)
+                    .await?;
             (handler)(params, info, &mut rpcenv).map(|data| (formatter.format_data)(data, &rpcenv))
         }
         ApiHandler::Async(handler) => {
             let params =
-                get_request_parameters(info.parameters, parts, req_body, uri_param).await?;
+                get_request_parameters(info.parameters, parts, req_body, uri_param, max_body_size)
+                    .await?;
             (handler)(params, info, &mut rpcenv)
                 .await
                 .map(|data| (formatter.format_data)(data, &rpcenv))
@@ -655,6 +733,78 @@ fn extract_compression_method(headers: &http::HeaderMap) -> Option<CompressionMe
     None
 }
 
+// HTTP basic authentication with an API token ("<tokenid>:<secret>"), converted to the
+// 'PBSAPIToken' authorization header. Only accepted for the (read-only) metrics endpoint.
+fn metrics_basic_auth_headers(headers: &HeaderMap) -> Option<HeaderMap> {
+    let credentials = headers
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Basic ")?;
+    let credentials = String::from_utf8(base64::decode(credentials.trim()).ok()?).ok()?;
+
+    let mut headers = headers.clone();
+    headers.insert(
+        header::AUTHORIZATION,
+        format!("PBSAPIToken={}", credentials).parse().ok()?,
+    );
+    Some(headers)
+}
+
+// Prometheus metrics, see `server::metrics`
+//
+// Needs `Sys.Audit` on `/system/status`. Scrapers usually authenticate with an API token,
+// either with the `PBSAPIToken` or with a HTTP basic authorization header.
+async fn handle_metrics_request(
+    headers: &HeaderMap,
+    method: &hyper::Method,
+    auth: &(dyn ApiAuth + Send + Sync),
+    user_info: &CachedUserInfo,
+    peer: &std::net::SocketAddr,
+    delay_unauth_time: std::time::Instant,
+    access_forbidden_time: std::time::Instant,
+) -> Result<Response<Body>, Error> {
+    if method != hyper::Method::GET {
+        return Err(http_err!(METHOD_NOT_ALLOWED, "metrics are only available with GET"));
+    }
+
+    let basic_auth_headers = metrics_basic_auth_headers(headers);
+    let headers = basic_auth_headers.as_ref().unwrap_or(headers);
+
+    let auth_id = match auth.check_auth(headers, method, user_info) {
+        Ok(auth_id) => auth_id,
+        Err(auth_err) => {
+            if let AuthError::Generic(err) = auth_err {
+                auth_logger()?.log(format!(
+                    "authentication failure; rhost={} msg={}",
+                    peer.ip(),
+                    err
+                ));
+            }
+            tokio::time::sleep_until(Instant::from_std(delay_unauth_time)).await;
+            return Ok(Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header(header::WWW_AUTHENTICATE, "Basic realm=\"Proxmox Backup Server\"")
+                .body("authentication failed\n".into())?);
+        }
+    };
+
+    let privs = user_info.lookup_privs(&auth_id, &["system", "status"]);
+    if privs & PRIV_SYS_AUDIT == 0 {
+        tokio::time::sleep_until(Instant::from_std(access_forbidden_time)).await;
+        return Ok(Response::builder()
+            .status(StatusCode::FORBIDDEN)
+            .body("permission check failed\n".into())?);
+    }
+
+    let metrics = tokio::task::spawn_blocking(super::metrics::collect_metrics).await??;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "text/plain; version=0.0.4")
+        .body(metrics.into())?)
+}
+
 async fn handle_request(
     api: Arc<ApiConfig>,
     req: Request<Body>,
@@ -775,6 +925,19 @@ async fn handle_request(
             bail!("Unsupported HTTP method {}", method);
         }
 
+        if comp_len == 1 && components[0] == "metrics" {
+            return handle_metrics_request(
+                &parts.headers,
+                &method,
+                auth.as_ref(),
+                &user_info,
+                peer,
+                delay_unauth_time,
+                access_forbidden_time,
+            )
+            .await;
+        }
+
         if comp_len == 0 {
             let language = extract_lang_header(&parts.headers);
             match auth.check_auth(&parts.headers, &method, &user_info) {
@@ -806,3 +969,45 @@ async fn handle_request(
 
     Err(http_err!(NOT_FOUND, "Path '{}' not found.", path))
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn body_size(body: Body, limit: usize) -> Result<usize, Error> {
+        crate::tools::runtime::main(
+            SizeLimitedBody::new(body, limit).try_fold(0, |acc, chunk| async move {
+                Ok(acc + chunk.len())
+            }),
+        )
+    }
+
+    fn http_error_code(err: &Error) -> Option<StatusCode> {
+        err.downcast_ref::<HttpError>().map(|err| err.code)
+    }
+
+    #[test]
+    fn test_request_body_size_limit() {
+        let mut headers = HeaderMap::new();
+        assert!(check_content_length(&headers, 1024).is_ok());
+
+        headers.insert(header::CONTENT_LENGTH, "1024".parse().unwrap());
+        assert!(check_content_length(&headers, 1024).is_ok());
+
+        headers.insert(header::CONTENT_LENGTH, "4294967296".parse().unwrap());
+        let err = check_content_length(&headers, 1024).unwrap_err();
+        assert_eq!(http_error_code(&err), Some(StatusCode::PAYLOAD_TOO_LARGE));
+
+        headers.insert(header::CONTENT_LENGTH, "-1".parse().unwrap());
+        let err = check_content_length(&headers, 1024).unwrap_err();
+        assert_eq!(http_error_code(&err), Some(StatusCode::BAD_REQUEST));
+
+        assert_eq!(body_size(Body::from(vec![0u8; 1024]), 1024).unwrap(), 1024);
+
+        // streamed body without content length, limit exceeded in the third chunk
+        let chunks: Vec<Result<_, std::io::Error>> = (0..4).map(|_| Ok(vec![0u8; 512])).collect();
+        let body = Body::wrap_stream(futures::stream::iter(chunks));
+        let err = body_size(body, 1024).unwrap_err();
+        assert_eq!(http_error_code(&err), Some(StatusCode::PAYLOAD_TOO_LARGE));
+    }
+}
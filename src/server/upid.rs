@@ -99,6 +99,13 @@ impl UPID {
         path.push(self.to_string());
         path
     }
+
+    /// Path of the JSON file holding the task metadata (next to the log file)
+    pub fn meta_path(&self) -> std::path::PathBuf {
+        let mut path = self.log_path();
+        path.set_file_name(format!("{}.meta.json", self));
+        path
+    }
 }
 
 
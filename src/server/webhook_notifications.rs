@@ -0,0 +1,55 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::{bail, format_err, Error};
+use serde::{Deserialize, Serialize};
+
+use crate::tools::{http_post_request, pbs_simple_http};
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Webhook notification target
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct WebhookConfig {
+    /// The URL the notification is posted to
+    pub url: String,
+    /// Shared secret used to sign the payload (`X-Proxmox-Signature` header)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    /// Additional HTTP headers, e.g. for authentication
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub extra_headers: HashMap<String, String>,
+}
+
+/// Post a JSON `payload` to a webhook
+///
+/// Like the mail notifications, this blocks until the request is done, so it
+/// can be used from tape backup, verification jobs and other workers.
+pub fn send_webhook_notification(config: &WebhookConfig, payload: &str) -> Result<(), Error> {
+    let proxy_config = crate::config::node::config()
+        .ok()
+        .and_then(|(node_config, _digest)| node_config.http_proxy());
+
+    let mut client = pbs_simple_http(proxy_config);
+
+    let request = http_post_request(
+        &config.url,
+        payload,
+        config.secret.as_deref(),
+        &config.extra_headers,
+    )?;
+
+    let response = crate::tools::runtime::block_on(async {
+        tokio::time::timeout(WEBHOOK_TIMEOUT, client.request(request)).await
+    })
+    .map_err(|_| format_err!("webhook '{}' timed out after {:?}", config.url, WEBHOOK_TIMEOUT))?
+    .map_err(|err| format_err!("webhook '{}' failed - {}", config.url, err))?;
+
+    let status = response.status();
+    if !status.is_success() {
+        bail!("webhook '{}' failed - got status {}", config.url, status);
+    }
+
+    Ok(())
+}
@@ -2,8 +2,9 @@ use std::collections::{HashMap, VecDeque};
 use std::fs::File;
 use std::io::{Read, Write, BufRead, BufReader};
 use std::panic::UnwindSafe;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
 use anyhow::{bail, format_err, Error};
 use futures::*;
@@ -21,7 +22,7 @@ use super::UPID;
 use crate::buildcfg;
 use crate::server;
 use crate::tools::logrotate::{LogRotate, LogRotateFiles};
-use crate::tools::{FileLogger, FileLogOptions};
+use crate::tools::{FileLogger, FileLogOptions, RateLimiter};
 use crate::api2::types::{Authid, TaskStateType};
 
 macro_rules! taskdir {
@@ -33,6 +34,12 @@ pub const PROXMOX_BACKUP_ACTIVE_TASK_FN: &str = taskdir!("/active");
 pub const PROXMOX_BACKUP_INDEX_TASK_FN: &str = taskdir!("/index");
 pub const PROXMOX_BACKUP_ARCHIVE_TASK_FN: &str = taskdir!("/archive");
 
+/// Default maximum size of a task log file (100 MiB)
+pub const DEFAULT_MAX_TASK_LOG_SIZE: u64 = 100*1024*1024;
+
+// Space reserved at the end of the task log for the final status messages
+const TASK_LOG_SIZE_RESERVE: u64 = 4096;
+
 lazy_static! {
     static ref WORKER_TASK_LIST: Mutex<HashMap<usize, Arc<WorkerTask>>> = Mutex::new(HashMap::new());
 }
@@ -100,8 +107,16 @@ pub fn register_task_control_commands(
     commando_sock.register_command("worker-task-abort".into(), move |args| {
         let upid = get_upid(args)?;
 
+        let cleanup = args
+            .and_then(|args| args["cleanup"].as_bool())
+            .unwrap_or(false);
+
         if let Some(ref worker) = WORKER_TASK_LIST.lock().unwrap().get(&upid.task_id) {
-            worker.request_abort();
+            if cleanup {
+                worker.request_abort_with_cleanup();
+            } else {
+                worker.request_abort();
+            }
         }
         Ok(Value::Null)
     })?;
@@ -116,21 +131,26 @@ pub fn register_task_control_commands(
     Ok(())
 }
 
-pub fn abort_worker_async(upid: UPID) {
+pub fn abort_worker_async(upid: UPID, cleanup: bool) {
     tokio::spawn(async move {
-        if let Err(err) = abort_worker(upid).await {
+        if let Err(err) = abort_worker(upid, cleanup).await {
             eprintln!("abort worker failed - {}", err);
         }
     });
 }
 
-pub async fn abort_worker(upid: UPID) -> Result<(), Error> {
+/// Request abort of a worker task
+///
+/// If `cleanup` is set, the worker is asked to also remove data written by
+/// the interrupted operation (see `WorkerTask::cleanup_requested`).
+pub async fn abort_worker(upid: UPID, cleanup: bool) -> Result<(), Error> {
 
     let sock = server::ctrl_sock_from_pid(upid.pid);
     let cmd = json!({
         "command": "worker-task-abort",
         "args": {
             "upid": upid.to_string(),
+            "cleanup": cleanup,
         },
     });
     super::send_command(sock, &cmd).map_ok(|_| ()).await
@@ -220,6 +240,209 @@ pub fn upid_read_status(upid: &UPID) -> Result<TaskState, Error> {
     Ok(status)
 }
 
+/// Timezone used to format task log timestamps
+///
+/// Parsed from `UTC`/`Z`, a fixed offset `+HH:MM`/`-HH:MM`, or a zone
+/// name like `Europe/Vienna` (which also handles DST changes).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TaskLogTimezone {
+    zone: TaskLogZone,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TaskLogZone {
+    Fixed(i64), // seconds east of UTC
+    Named(chrono_tz::Tz),
+}
+
+impl TaskLogTimezone {
+
+    pub fn utc() -> Self {
+        Self { zone: TaskLogZone::Fixed(0) }
+    }
+
+    // offset (seconds east of UTC) at the given time
+    fn offset_at(&self, epoch: i64) -> i64 {
+        use chrono::{Offset, TimeZone};
+
+        match self.zone {
+            TaskLogZone::Fixed(offset) => offset,
+            TaskLogZone::Named(tz) => {
+                let time = chrono::NaiveDateTime::from_timestamp(epoch, 0);
+                tz.offset_from_utc_datetime(&time).fix().local_minus_utc() as i64
+            }
+        }
+    }
+
+    /// Format `epoch` as RFC3339 timestamp with the offset at that time
+    pub fn format_epoch(&self, epoch: i64) -> Result<String, Error> {
+        let offset = self.offset_at(epoch);
+        let time = proxmox::tools::time::strftime_utc("%Y-%m-%dT%H:%M:%S", epoch + offset)?;
+        if self.zone == TaskLogZone::Fixed(0) {
+            return Ok(format!("{}Z", time));
+        }
+        let sign = if offset < 0 { '-' } else { '+' };
+        let minutes = offset.abs() / 60;
+        Ok(format!("{}{}{:02}:{:02}", time, sign, minutes / 60, minutes % 60))
+    }
+}
+
+impl std::str::FromStr for TaskLogTimezone {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        if s == "UTC" || s == "Z" {
+            return Ok(Self::utc());
+        }
+
+        let (sign, rest) = match (s.strip_prefix('+'), s.strip_prefix('-')) {
+            (Some(rest), _) => (1, rest),
+            (_, Some(rest)) => (-1, rest),
+            _ => {
+                let tz: chrono_tz::Tz = s.parse().map_err(|_| format_err!(
+                    "unable to parse timezone '{}' - expected 'UTC', '+HH:MM' or a zone name", s))?;
+                return Ok(Self { zone: TaskLogZone::Named(tz) });
+            }
+        };
+
+        let mut parts = rest.splitn(2, ':');
+        let (hours, minutes) = match (parts.next(), parts.next()) {
+            (Some(hours), Some(minutes)) if hours.len() == 2 && minutes.len() == 2 => {
+                (hours.parse::<i64>()?, minutes.parse::<i64>()?)
+            }
+            _ => bail!("unable to parse timezone '{}' - expected 'UTC' or '+HH:MM'", s),
+        };
+
+        if hours > 14 || minutes > 59 {
+            bail!("timezone offset '{}' out of range", s);
+        }
+
+        Ok(Self { zone: TaskLogZone::Fixed(sign * (hours * 3600 + minutes * 60)) })
+    }
+}
+
+/// A task log line, split into timestamp and message
+#[derive(Debug, PartialEq)]
+pub struct TaskLogEntry {
+    /// Line timestamp (epoch), `None` for lines without a valid timestamp
+    pub time: Option<i64>,
+    pub message: String,
+}
+
+impl TaskLogEntry {
+
+    /// Parse a task log line (`<rfc3339>: <message>`)
+    pub fn parse(line: &str) -> Self {
+        let mut iter = line.splitn(2, ": ");
+        if let (Some(time_str), Some(message)) = (iter.next(), iter.next()) {
+            if let Ok(time) = proxmox::tools::time::parse_rfc3339(time_str) {
+                return Self { time: Some(time), message: message.to_string() };
+            }
+        }
+        Self { time: None, message: line.to_string() }
+    }
+
+    /// Format the timestamp using the given timezone
+    pub fn timestamp_formatted(&self, tz: &TaskLogTimezone) -> Option<String> {
+        self.time.and_then(|time| tz.format_epoch(time).ok())
+    }
+
+    /// Format the whole line using the given timezone
+    pub fn format(&self, tz: &TaskLogTimezone) -> String {
+        match self.timestamp_formatted(tz) {
+            Some(time) => format!("{}: {}", time, self.message),
+            None => self.message.clone(),
+        }
+    }
+}
+
+/// Task metadata, stored as JSON next to the task log file
+///
+/// Written when the task starts, and updated with the result when it
+/// finishes. Tasks created by older versions have no metadata file, so
+/// readers need to fall back to the UPID and the task log.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct TaskMeta {
+    pub upid: String,
+    #[serde(rename = "type")]
+    pub worker_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub auth_id: String,
+    pub starttime: i64,
+    pub node: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub endtime: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exitstatus: Option<String>,
+    /// Size of the task log file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bytes_written: Option<u64>,
+}
+
+impl TaskMeta {
+
+    pub fn new(upid: &UPID) -> Self {
+        Self {
+            upid: upid.to_string(),
+            worker_type: upid.worker_type.clone(),
+            id: upid.worker_id.clone(),
+            auth_id: upid.auth_id.to_string(),
+            starttime: upid.starttime,
+            node: upid.node.clone(),
+            endtime: None,
+            exitstatus: None,
+            bytes_written: None,
+        }
+    }
+
+    /// Returns the final task state, or `None` if the task has not finished.
+    pub fn state(&self) -> Option<TaskState> {
+        match (self.endtime, &self.exitstatus) {
+            (Some(endtime), Some(exitstatus)) => {
+                TaskState::from_endtime_and_message(endtime, exitstatus).ok()
+            }
+            _ => None,
+        }
+    }
+
+    fn write(&self, upid: &UPID) -> Result<(), Error> {
+        let backup_user = crate::backup::backup_user()?;
+        let data = serde_json::to_string(self)?;
+        replace_file(
+            upid.meta_path(),
+            data.as_bytes(),
+            CreateOptions::new()
+                .owner(backup_user.uid)
+                .group(backup_user.gid),
+        )
+    }
+}
+
+/// Read the metadata file of a task, returns `None` if there is none
+pub fn read_task_meta(upid: &UPID) -> Result<Option<TaskMeta>, Error> {
+    let path = upid.meta_path();
+    match proxmox::tools::fs::file_read_optional_string(&path)? {
+        Some(data) => {
+            let meta = serde_json::from_str(&data)
+                .map_err(|err| format_err!("unable to parse task metadata {:?} - {}", path, err))?;
+            Ok(Some(meta))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Read the final state of a task
+///
+/// Prefers the metadata file, and falls back to parsing the task log for
+/// tasks without (complete) metadata.
+pub fn read_task_state(upid: &UPID) -> Result<TaskState, Error> {
+    if let Ok(Some(state)) = read_task_meta(upid).map(|meta| meta.and_then(|meta| meta.state())) {
+        return Ok(state);
+    }
+    upid_read_status(upid)
+}
+
 /// Task State
 #[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum TaskState {
@@ -359,7 +582,7 @@ fn update_active_workers(new_upid: Option<&UPID>) -> Result<(), Error> {
             if !worker_is_active_local(&info.upid) {
                 // println!("Detected stopped task '{}'", &info.upid_str);
                 let now = proxmox::tools::time::epoch_i64();
-                let status = upid_read_status(&info.upid).unwrap_or(TaskState::Unknown { endtime: now });
+                let status = read_task_state(&info.upid).unwrap_or(TaskState::Unknown { endtime: now });
                 finish_list.push(TaskListInfo {
                     upid: info.upid,
                     upid_str: info.upid_str,
@@ -566,6 +789,9 @@ pub struct WorkerTask {
     upid: UPID,
     data: Mutex<WorkerTaskData>,
     abort_requested: AtomicBool,
+    abort_cleanup: AtomicBool,
+    log_size_exceeded: AtomicBool,
+    log_lines_written: AtomicU64,
 }
 
 impl std::fmt::Display for WorkerTask {
@@ -581,6 +807,9 @@ struct WorkerTaskData {
     progress: f64, // 0..1
     warn_count: u64,
     pub abort_listeners: Vec<oneshot::Sender<()>>,
+    log_limiter: Option<RateLimiter>,
+    dropped_log_lines: u64,
+    max_log_size: u64,
 }
 
 impl WorkerTask {
@@ -609,16 +838,9 @@ impl WorkerTask {
         let logger = FileLogger::new(&path, logger_options)?;
         nix::unistd::chown(&path, Some(backup_user.uid), Some(backup_user.gid))?;
 
-        let worker = Arc::new(Self {
-            upid: upid.clone(),
-            abort_requested: AtomicBool::new(false),
-            data: Mutex::new(WorkerTaskData {
-                logger,
-                progress: 0.0,
-                warn_count: 0,
-                abort_listeners: vec![],
-            }),
-        });
+        TaskMeta::new(&upid).write(&upid)?;
+
+        let worker = Arc::new(Self::with_logger(upid.clone(), logger));
 
         // scope to drop the lock again after inserting
         {
@@ -632,6 +854,25 @@ impl WorkerTask {
         Ok(worker)
     }
 
+    fn with_logger(upid: UPID, logger: FileLogger) -> Self {
+        Self {
+            upid,
+            abort_requested: AtomicBool::new(false),
+            abort_cleanup: AtomicBool::new(false),
+            log_size_exceeded: AtomicBool::new(false),
+            log_lines_written: AtomicU64::new(0),
+            data: Mutex::new(WorkerTaskData {
+                logger,
+                progress: 0.0,
+                warn_count: 0,
+                abort_listeners: vec![],
+                log_limiter: None,
+                dropped_log_lines: 0,
+                max_log_size: DEFAULT_MAX_TASK_LOG_SIZE,
+            }),
+        }
+    }
+
     /// Spawn a new tokio task/future.
     pub fn spawn<F, T>(
         worker_type: &str,
@@ -697,6 +938,8 @@ impl WorkerTask {
 
         if let Err(err) = result {
             TaskState::Error { message: err.to_string(), endtime }
+        } else if self.log_size_exceeded() {
+            TaskState::Error { message: "task log size limit exceeded".to_string(), endtime }
         } else if warn_count > 0 {
             TaskState::Warning { count: warn_count, endtime }
         } else {
@@ -707,23 +950,105 @@ impl WorkerTask {
     /// Log task result, remove task from running list
     pub fn log_result(&self, result: &Result<(), Error>) {
         let state = self.create_state(result);
-        self.log(state.result_text());
+        {
+            // always write the result, it is parsed from the last line of the log
+            let mut data = self.data.lock().unwrap();
+            self.log_dropped_lines_summary(&mut data);
+            data.logger.log(state.result_text());
+        }
+
+        let mut meta = TaskMeta::new(&self.upid);
+        meta.endtime = Some(state.endtime());
+        meta.exitstatus = Some(state.to_string());
+        meta.bytes_written = std::fs::metadata(self.upid.log_path()).ok().map(|m| m.len());
+        let _ = meta.write(&self.upid); // ignore errors, the log still contains the result
 
         WORKER_TASK_LIST.lock().unwrap().remove(&self.upid.task_id);
         let _ = update_active_workers(None);
         super::set_worker_count(WORKER_TASK_LIST.lock().unwrap().len());
     }
 
+    /// Limit the number of log lines per second
+    ///
+    /// Lines exceeding the limit are dropped, and replaced by a summary
+    /// message as soon as logging is allowed again.
+    pub fn with_log_rate_limit(self: Arc<Self>, max_lines_per_second: u32) -> Arc<Self> {
+        {
+            let mut data = self.data.lock().unwrap();
+            let rate = u64::from(max_lines_per_second);
+            data.log_limiter = Some(RateLimiter::new(rate, rate));
+        }
+        self
+    }
+
+    /// Set the maximum task log size (default is [DEFAULT_MAX_TASK_LOG_SIZE])
+    ///
+    /// Logging stops when the limit is reached, and the task fails. Lines
+    /// dropped by the rate limit are not written, so they do not count.
+    pub fn with_max_log_size(self: Arc<Self>, max_log_size: u64) -> Arc<Self> {
+        self.data.lock().unwrap().max_log_size = max_log_size;
+        self
+    }
+
+    /// Number of lines written to the task log
+    pub fn log_lines_written(&self) -> u64 {
+        self.log_lines_written.load(Ordering::SeqCst)
+    }
+
+    /// Test if the task log size limit was exceeded.
+    pub fn log_size_exceeded(&self) -> bool {
+        self.log_size_exceeded.load(Ordering::SeqCst)
+    }
+
+    fn log_dropped_lines_summary(&self, data: &mut WorkerTaskData) {
+        if data.dropped_log_lines > 0 {
+            data.logger.log(format!("[{} log lines dropped due to rate limit]", data.dropped_log_lines));
+            data.dropped_log_lines = 0;
+            self.log_lines_written.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    // write a line to the task log, respecting rate and size limits
+    fn log_line(&self, data: &mut WorkerTaskData, msg: &str) {
+        if self.log_size_exceeded() {
+            return;
+        }
+
+        if let Some(ref mut limiter) = data.log_limiter {
+            if !limiter.try_consume(Instant::now(), 1) {
+                data.dropped_log_lines += 1;
+                return;
+            }
+        }
+
+        self.log_dropped_lines_summary(data);
+
+        let size_limit = data.max_log_size.saturating_sub(TASK_LOG_SIZE_RESERVE);
+        // Note: line length is approximated (time prefix is not included)
+        if data.logger.bytes_written() + (msg.len() as u64) >= size_limit {
+            self.log_size_exceeded.store(true, Ordering::SeqCst);
+            data.logger.log(format!("task log size limit ({} bytes) exceeded - stop logging", data.max_log_size));
+            // notify listeners, so that the task stops
+            for ch in data.abort_listeners.drain(..) {
+                let _ = ch.send(()); // ignore errors here
+            }
+            return;
+        }
+
+        data.logger.log(msg);
+        self.log_lines_written.fetch_add(1, Ordering::SeqCst);
+    }
+
     /// Log a message.
     pub fn log<S: AsRef<str>>(&self, msg: S) {
         let mut data = self.data.lock().unwrap();
-        data.logger.log(msg);
+        self.log_line(&mut data, msg.as_ref());
     }
 
     /// Log a message as warning.
     pub fn warn<S: AsRef<str>>(&self, msg: S) {
         let mut data = self.data.lock().unwrap();
-        data.logger.log(format!("WARN: {}", msg.as_ref()));
+        self.log_line(&mut data, &format!("WARN: {}", msg.as_ref()));
         data.warn_count += 1;
     }
 
@@ -757,6 +1082,19 @@ impl WorkerTask {
         }
     }
 
+    /// Request abort, and ask the worker to remove partially written data
+    ///
+    /// Currently only sync tasks check this flag.
+    pub fn request_abort_with_cleanup(&self) {
+        self.abort_cleanup.store(true, Ordering::SeqCst);
+        self.request_abort();
+    }
+
+    /// Test if abort with cleanup was requested.
+    pub fn cleanup_requested(&self) -> bool {
+        self.abort_cleanup.load(Ordering::SeqCst)
+    }
+
     /// Test if abort was requested.
     pub fn abort_requested(&self) -> bool {
         self.abort_requested.load(Ordering::SeqCst)
@@ -767,6 +1105,9 @@ impl WorkerTask {
         if self.abort_requested() {
             bail!("abort requested - aborting task");
         }
+        if self.log_size_exceeded() {
+            bail!("task log size limit exceeded - aborting task");
+        }
         Ok(())
     }
 
@@ -775,7 +1116,7 @@ impl WorkerTask {
         let (tx, rx) = oneshot::channel::<()>();
 
         let mut data = self.data.lock().unwrap();
-        if self.abort_requested() {
+        if self.abort_requested() || self.log_size_exceeded() {
             let _ = tx.send(());
         } else {
             data.abort_listeners.push(tx);
@@ -803,3 +1144,145 @@ impl crate::task::TaskState for WorkerTask {
         }
     }
 }
+
+#[test]
+fn test_task_meta_serialization() -> Result<(), Error> {
+    let upid: UPID = "UPID:elsa:00004F37:0039E469:00000000:5CA78B83:garbage_collection:store1:root@pam:".parse()?;
+
+    let mut meta = TaskMeta::new(&upid);
+    assert_eq!(meta.state(), None);
+
+    let value = serde_json::to_value(&meta)?;
+    assert_eq!(value, json!({
+        "upid": upid.to_string(),
+        "type": "garbage_collection",
+        "id": "store1",
+        "auth_id": "root@pam",
+        "starttime": 0x5CA78B83,
+        "node": "elsa",
+    }));
+    assert_eq!(serde_json::from_value::<TaskMeta>(value)?, meta);
+
+    meta.endtime = Some(0x5CA78C00);
+    meta.exitstatus = Some("WARNINGS: 2".to_string());
+    meta.bytes_written = Some(1234);
+
+    let data = serde_json::to_string(&meta)?;
+    let parsed: TaskMeta = serde_json::from_str(&data)?;
+    assert_eq!(parsed, meta);
+    assert_eq!(parsed.state(), Some(TaskState::Warning { count: 2, endtime: 0x5CA78C00 }));
+
+    Ok(())
+}
+
+#[test]
+fn test_task_meta_state() {
+    let upid: UPID = "UPID:elsa:00004F37:0039E469:00000000:5CA78B83:sync::root@pam:".parse().unwrap();
+    let mut meta = TaskMeta::new(&upid);
+
+    meta.endtime = Some(10);
+    assert_eq!(meta.state(), None);
+
+    meta.exitstatus = Some("OK".to_string());
+    assert_eq!(meta.state(), Some(TaskState::OK { endtime: 10 }));
+
+    meta.exitstatus = Some("some error".to_string());
+    assert_eq!(meta.state(), Some(TaskState::Error { message: "some error".to_string(), endtime: 10 }));
+
+    meta.exitstatus = Some("".to_string());
+    assert_eq!(meta.state(), None);
+}
+
+#[test]
+fn test_task_log_timezone() -> Result<(), Error> {
+    let epoch = 1705328625; // 2024-01-15T14:23:45Z
+
+    let tz: TaskLogTimezone = "+01:00".parse()?;
+    let formatted = tz.format_epoch(epoch)?;
+    assert_eq!(formatted, "2024-01-15T15:23:45+01:00");
+    assert_eq!(proxmox::tools::time::parse_rfc3339(&formatted)?, epoch);
+
+    let tz: TaskLogTimezone = "-05:30".parse()?;
+    let formatted = tz.format_epoch(epoch)?;
+    assert_eq!(formatted, "2024-01-15T08:53:45-05:30");
+    assert_eq!(proxmox::tools::time::parse_rfc3339(&formatted)?, epoch);
+
+    let tz: TaskLogTimezone = "UTC".parse()?;
+    assert_eq!(tz, TaskLogTimezone::utc());
+    assert_eq!(tz.format_epoch(epoch)?, "2024-01-15T14:23:45Z");
+
+    // named zones use the offset valid at that time (DST)
+    let tz: TaskLogTimezone = "Europe/Vienna".parse()?;
+    assert_eq!(tz.format_epoch(epoch)?, "2024-01-15T15:23:45+01:00");
+    let summer = 1721053425; // 2024-07-15T14:23:45Z
+    let formatted = tz.format_epoch(summer)?;
+    assert_eq!(formatted, "2024-07-15T16:23:45+02:00");
+    assert_eq!(proxmox::tools::time::parse_rfc3339(&formatted)?, summer);
+
+    let tz: TaskLogTimezone = "Europe/London".parse()?;
+    assert_eq!(tz.format_epoch(epoch)?, "2024-01-15T14:23:45+00:00");
+
+    assert!("Europe/Nowhere".parse::<TaskLogTimezone>().is_err());
+    assert!("+1:00".parse::<TaskLogTimezone>().is_err());
+    assert!("+15:00".parse::<TaskLogTimezone>().is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_task_log_entry() -> Result<(), Error> {
+    let entry = TaskLogEntry::parse("2024-01-15T15:23:45+01:00: TASK OK");
+    assert_eq!(entry, TaskLogEntry { time: Some(1705328625), message: "TASK OK".to_string() });
+    assert_eq!(entry.format(&"+02:00".parse()?), "2024-01-15T16:23:45+02:00: TASK OK");
+
+    let entry = TaskLogEntry::parse("no timestamp: here");
+    assert_eq!(entry.time, None);
+    assert_eq!(entry.format(&TaskLogTimezone::utc()), "no timestamp: here");
+
+    Ok(())
+}
+
+#[test]
+fn test_task_log_limits() -> Result<(), Error> {
+    const MAX_LOG_SIZE: u64 = 64*1024;
+
+    let path = std::path::PathBuf::from(".testdir-task-log-limit");
+
+    let new_worker = || -> Result<Arc<WorkerTask>, Error> {
+        let _ = std::fs::remove_file(&path);
+        let upid: UPID = "UPID:elsa:00004F37:0039E469:00000000:5CA78B83:log_limit_test::root@pam:".parse()?;
+        let logger = FileLogger::new(&path, FileLogOptions { exclusive: true, ..Default::default() })?;
+        Ok(Arc::new(WorkerTask::with_logger(upid, logger)))
+    };
+
+    let log_lines = |worker: &WorkerTask, count: usize| {
+        for i in 0..count {
+            worker.log(format!("log line {} with some additional text to fill the log", i));
+        }
+    };
+
+    // default size limit, not reached by a few thousand lines
+    let worker = new_worker()?;
+    assert_eq!(worker.data.lock().unwrap().max_log_size, DEFAULT_MAX_TASK_LOG_SIZE);
+    log_lines(&worker, 10_000);
+    assert!(!worker.log_size_exceeded());
+    assert_eq!(worker.log_lines_written(), 10_000);
+
+    let worker = new_worker()?.with_max_log_size(MAX_LOG_SIZE);
+    log_lines(&worker, 10_000);
+    assert!(worker.fail_on_abort().is_err());
+    assert!(matches!(worker.create_state(&Ok(())), TaskState::Error { .. }));
+    let lines = worker.log_lines_written();
+    assert!(lines > 0 && lines < 10_000, "unexpected number of written log lines ({})", lines);
+    assert!(std::fs::metadata(&path)?.len() <= MAX_LOG_SIZE);
+
+    // lines dropped by the rate limit do not count toward the size limit
+    let worker = new_worker()?.with_log_rate_limit(100).with_max_log_size(MAX_LOG_SIZE);
+    log_lines(&worker, 100_000);
+    assert!(!worker.log_size_exceeded());
+    assert!(worker.log_lines_written() < 1000);
+
+    let _ = std::fs::remove_file(&path);
+
+    Ok(())
+}
@@ -8,7 +8,7 @@ mod online_status_map;
 pub use online_status_map::*;
 
 use std::collections::HashSet;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use anyhow::{bail, Error};
 use serde::{Serialize, Deserialize};
@@ -25,8 +25,12 @@ use proxmox::{
 
 use crate::api2::types::{
     SLOT_ARRAY_SCHEMA,
+    Authid,
+    ChangerMoveEvent,
     ScsiTapeChanger,
     LtoTapeDrive,
+    ChangerElementFault,
+    ChangerHealthReport,
 };
 
 /// Changer element status.
@@ -55,6 +59,9 @@ pub struct DriveStatus {
     pub model: Option<String>,
     /// Element Address
     pub element_address: u16,
+    /// Fault reported by the changer (Except bit set)
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub fault: Option<String>,
 }
 
 /// Storage element status.
@@ -66,6 +73,9 @@ pub struct StorageElementStatus {
     pub status: ElementStatus,
     /// Element Address
     pub element_address: u16,
+    /// Fault reported by the changer (Except bit set)
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub fault: Option<String>,
 }
 
 /// Transport element status.
@@ -122,6 +132,25 @@ impl MtxStatus {
         .unwrap_or(0u16)
     }
 
+    /// Label text of the media inside a slot (if known)
+    pub fn slot_label_text(&self, slot: u64) -> Option<&str> {
+        if slot == 0 {
+            return None;
+        }
+        match self.slots.get(slot as usize - 1)?.status {
+            ElementStatus::VolumeTag(ref tag) => Some(tag),
+            _ => None,
+        }
+    }
+
+    /// Label text of the media inside a drive (if known)
+    pub fn drive_label_text(&self, drivenum: u64) -> Option<&str> {
+        match self.drives.get(drivenum as usize)?.status {
+            ElementStatus::VolumeTag(ref tag) => Some(tag),
+            _ => None,
+        }
+    }
+
     pub fn find_free_slot(&self, import_export: bool) -> Option<u64> {
         let mut free_slot = None;
         for (i, slot_info) in self.slots.iter().enumerate() {
@@ -136,6 +165,34 @@ impl MtxStatus {
         free_slot
     }
 
+    /// Summarize the faults reported for drives and slots
+    pub fn health_report(&self) -> ChangerHealthReport {
+        let mut report = ChangerHealthReport::default();
+
+        for (drivenum, drive) in self.drives.iter().enumerate() {
+            match drive.fault {
+                Some(ref fault) => report.drives_fault.push(ChangerElementFault {
+                    entry_id: drivenum as u64,
+                    fault: fault.clone(),
+                }),
+                None => report.drives_ok.push(drivenum as u64),
+            }
+        }
+
+        for (i, slot) in self.slots.iter().enumerate() {
+            let slot_num = i as u64 + 1;
+            match slot.fault {
+                Some(ref fault) => report.slots_fault.push(ChangerElementFault {
+                    entry_id: slot_num,
+                    fault: fault.clone(),
+                }),
+                None => report.slots_ok.push(slot_num),
+            }
+        }
+
+        report
+    }
+
     pub fn mark_import_export_slots(&mut self, config: &ScsiTapeChanger) -> Result<(), Error>{
         let mut export_slots: HashSet<u64> = HashSet::new();
 
@@ -170,6 +227,9 @@ pub trait ScsiMediaChange {
     fn unload(&mut self, to_slot: u64, drivenum: u64) -> Result<MtxStatus, Error>;
 
     fn transfer(&mut self, from_slot: u64, to_slot: u64) -> Result<MtxStatus, Error>;
+
+    /// Rescan all elements (INITIALIZE ELEMENT STATUS) and report faults
+    fn health_check(&mut self) -> Result<ChangerHealthReport, Error>;
 }
 
 /// Interface to the media changer device for a single drive
@@ -396,6 +456,14 @@ pub trait MediaChange {
             bail!("drive '{}' unload failure - no free slot", self.drive_name());
         }
     }
+
+    /// Returns the changer status, using the cached state if available
+    ///
+    /// This avoids talking to the changer at all, so the status may be
+    /// outdated. The default implementation queries the current status.
+    fn cached_status(&mut self) -> Result<MtxStatus, Error> {
+        self.status()
+    }
 }
 
 const USE_MTX: bool = false;
@@ -471,6 +539,21 @@ impl ScsiMediaChange for ScsiTapeChanger {
 
         Ok(status)
     }
+
+    fn health_check(&mut self) -> Result<ChangerHealthReport, Error> {
+        let result = if USE_MTX {
+            mtx::mtx_inventory(&self.path)
+        } else {
+            let mut file = sg_pt_changer::open(&self.path)?;
+            sg_pt_changer::initialize_element_status(&mut file)
+        };
+
+        let status = self.status(false)?; // always update status
+
+        result?; // check inventory result
+
+        Ok(status.health_report())
+    }
 }
 
 fn save_changer_state_cache(
@@ -514,6 +597,33 @@ fn load_changer_state_cache(changer: &str) -> Result<Option<MtxStatus>, Error> {
     Ok(Some(state))
 }
 
+/// Record a media move in the changer history
+///
+/// Errors are only logged, because the move itself already happened.
+pub fn record_changer_move(
+    changer: &str,
+    label_text: Option<&str>,
+    from_slot: Option<u64>,
+    to_slot: Option<u64>,
+    drive: Option<&str>,
+    operator: Option<&Authid>,
+) {
+    let event = ChangerMoveEvent {
+        timestamp: proxmox::tools::time::epoch_i64(),
+        changer: changer.to_string(),
+        media_label: label_text.unwrap_or("").to_string(),
+        from_slot,
+        to_slot,
+        drive: drive.map(String::from),
+        operator_auth_id: operator.cloned(),
+    };
+
+    let db = crate::tape::MediaStateDatabase::new(Path::new(crate::tape::TAPE_STATUS_DIR));
+    if let Err(err) = db.record_move(event) {
+        log::warn!("unable to record move in changer history of '{}' - {}", changer, err);
+    }
+}
+
 /// Implements MediaChange using 'mtx' linux cli tool
 pub struct MtxMediaChanger {
     drive_name: String, // used for error messages
@@ -552,17 +662,42 @@ impl MediaChange for MtxMediaChanger {
         self.config.status(false)
     }
 
+    fn cached_status(&mut self) -> Result<MtxStatus, Error> {
+        self.config.status(true)
+    }
+
     fn transfer_media(&mut self, from: u64, to: u64) -> Result<MtxStatus, Error> {
-        self.config.transfer(from, to)
+        let status = self.config.transfer(from, to)?;
+        record_changer_move(
+            &self.config.name, status.slot_label_text(to), Some(from), Some(to), None, None);
+        Ok(status)
     }
 
     fn load_media_from_slot(&mut self, slot: u64) -> Result<MtxStatus, Error> {
-        self.config.load_slot(slot, self.drive_number)
+        let status = self.config.load_slot(slot, self.drive_number)?;
+        record_changer_move(
+            &self.config.name,
+            status.drive_label_text(self.drive_number),
+            Some(slot),
+            None,
+            Some(&self.drive_name),
+            None,
+        );
+        Ok(status)
     }
 
     fn unload_media(&mut self, target_slot: Option<u64>) -> Result<MtxStatus, Error> {
         if let Some(target_slot) = target_slot {
-            self.config.unload(target_slot, self.drive_number)
+            let status = self.config.unload(target_slot, self.drive_number)?;
+            record_changer_move(
+                &self.config.name,
+                status.slot_label_text(target_slot),
+                None,
+                Some(target_slot),
+                Some(&self.drive_name),
+                None,
+            );
+            Ok(status)
         } else {
             let status = self.status()?;
             self.unload_to_free_slot(status)
@@ -1,4 +1,4 @@
-use anyhow::Error;
+use anyhow::{format_err, Error};
 
 use crate::{
     tools::run_command,
@@ -6,6 +6,7 @@ use crate::{
     tape::changer::{
         MtxStatus,
         mtx::parse_mtx_status,
+        sg_pt_changer,
     },
 };
 
@@ -20,11 +21,38 @@ pub fn mtx_status(config: &ScsiTapeChanger) -> Result<MtxStatus, Error> {
 
     let mut status = parse_mtx_status(&output)?;
 
+    // 'mtx status' does not show the Except bit, so read the faults
+    // from the element status page ourselves
+    let mut file = sg_pt_changer::open(path)
+        .map_err(|err| format_err!("error opening '{}': {}", path, err))?;
+    let faults = sg_pt_changer::read_element_status(&mut file)
+        .map_err(|err| format_err!("error reading element status: {}", err))?;
+    copy_element_faults(&mut status, &faults);
+
     status.mark_import_export_slots(&config)?;
 
     Ok(status)
 }
 
+// Copy the fault info of drives and slots from 'source' (same element order)
+fn copy_element_faults(status: &mut MtxStatus, source: &MtxStatus) {
+    for (drive, source) in status.drives.iter_mut().zip(source.drives.iter()) {
+        drive.fault = source.fault.clone();
+    }
+    for (slot, source) in status.slots.iter_mut().zip(source.slots.iter()) {
+        slot.fault = source.fault.clone();
+    }
+}
+
+/// Run 'mtx inventory'
+pub fn mtx_inventory(path: &str) -> Result<(), Error> {
+    let mut command = std::process::Command::new("mtx");
+    command.args(&["-f", path, "inventory"]);
+    run_command(command, None)?;
+
+    Ok(())
+}
+
 /// Run 'mtx load'
 pub fn mtx_load(
     path: &str,
@@ -49,6 +49,7 @@ fn parse_drive_status(i: &str, id: u64) -> IResult<&str, DriveStatus> {
             vendor: None,
             model: None,
             element_address: id as u16,
+            fault: None,
         };
         return Ok((empty, status));
     }
@@ -76,6 +77,7 @@ fn parse_drive_status(i: &str, id: u64) -> IResult<&str, DriveStatus> {
             vendor: None,
             model: None,
             element_address: id as u16,
+            fault: None,
         };
         return Ok((i, status));
     }
@@ -89,6 +91,7 @@ fn parse_drive_status(i: &str, id: u64) -> IResult<&str, DriveStatus> {
         vendor: None,
         model: None,
         element_address: id as u16,
+        fault: None,
     };
     Ok((i, status))
 }
@@ -162,6 +165,7 @@ fn parse_status(i: &str) ->  IResult<&str, MtxStatus> {
             import_export,
             status: element_status,
             element_address: id as u16,
+            fault: None,
         };
         slots.push(status);
     }
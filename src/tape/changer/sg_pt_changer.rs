@@ -31,6 +31,7 @@ use crate::{
         ScsiError,
         scsi_ascii_to_string,
         scsi_inquiry,
+        get_asc_ascq_string,
     },
     api2::types::ScsiTapeChanger,
 };
@@ -529,6 +530,16 @@ struct DecodedStatusPage {
     import_export_slots: Vec<StorageElementStatus>,
 }
 
+// Returns the fault description if the element reports an
+// exception (Except bit), using the additional sense code
+fn element_fault(flags1: u8, asc: u8, ascq: u8) -> Option<String> {
+    if (flags1 & 4) != 0 {
+        Some(get_asc_ascq_string(asc, ascq))
+    } else {
+        None
+    }
+}
+
 fn create_element_status(full: bool, volume_tag: Option<String>) -> ElementStatus {
     if full {
         if let Some(volume_tag) = volume_tag {
@@ -619,6 +630,11 @@ fn decode_element_status_page(
                         let desc: StorageDescriptor = unsafe { reader.read_be_value()? };
 
                         let full = (desc.flags1 & 1) != 0;
+                        let fault = element_fault(
+                            desc.flags1,
+                            desc.additional_sense_code,
+                            desc.additional_sense_code_qualifier,
+                        );
                         let volume_tag = subhead.parse_optional_volume_tag(&mut reader, full)?;
 
                         subhead.skip_alternate_volume_tag(&mut reader)?;
@@ -633,6 +649,7 @@ fn decode_element_status_page(
                                 import_export: true,
                                 status: create_element_status(full, volume_tag),
                                 element_address: desc.element_address,
+                                fault,
                             };
                             result.import_export_slots.push(status);
                         } else {
@@ -640,6 +657,7 @@ fn decode_element_status_page(
                                 import_export: false,
                                 status: create_element_status(full, volume_tag),
                                 element_address: desc.element_address,
+                                fault,
                             };
                             result.storage_slots.push(status);
                         }
@@ -654,6 +672,11 @@ fn decode_element_status_page(
                         };
 
                         let full = (desc.flags1 & 1) != 0;
+                        let fault = element_fault(
+                            desc.flags1,
+                            desc.additional_sense_code,
+                            desc.additional_sense_code_qualifier,
+                        );
                         let volume_tag = subhead.parse_optional_volume_tag(&mut reader, full)?;
 
                         subhead.skip_alternate_volume_tag(&mut reader)?;
@@ -690,6 +713,7 @@ fn decode_element_status_page(
                             vendor,
                             model,
                             element_address: desc.element_address,
+                            fault,
                         };
                         result.drives.push(drive);
                     }
@@ -45,7 +45,10 @@ use crate::{
         TapeWrite,
         BlockReadError,
         drive::{
+            AppendPosition,
             TapeDriver,
+            TapePosition,
+            check_last_file,
         },
         file_formats::{
             PROXMOX_BACKUP_MEDIA_SET_LABEL_MAGIC_1_0,
@@ -129,13 +132,18 @@ impl LtoTapeHandle {
     }
 
     /// Get Tape and Media status
-    pub fn get_drive_and_media_status(&mut self) -> Result<LtoDriveAndMediaStatus, Error>  {
+    ///
+    /// The drive temperature is only queried if `read_temperature` is set.
+    pub fn get_drive_and_media_status(
+        &mut self,
+        read_temperature: bool,
+    ) -> Result<LtoDriveAndMediaStatus, Error>  {
 
-        let drive_status = self.sg_tape.read_drive_status()?;
+        let drive_status = self.sg_tape.read_drive_status(read_temperature)?;
 
-        let alert_flags = self.tape_alert_flags()
-            .map(|flags| format!("{:?}", flags))
-            .ok();
+        let flags = self.tape_alert_flags().ok();
+        let alert_flags = flags.map(|flags| format!("{:?}", flags));
+        let needs_cleaning = flags.map(tape_alert_flags_cleaning_request);
 
         let mut status = LtoDriveAndMediaStatus {
             vendor: self.sg_tape.info().vendor.clone(),
@@ -146,9 +154,14 @@ impl LtoTapeHandle {
             buffer_mode: drive_status.buffer_mode,
             density: drive_status.density_code.try_into()?,
             alert_flags,
+            needs_cleaning,
             write_protect: None,
             file_number: None,
             block_number: None,
+            position_percent: None,
+            remaining_capacity: None,
+            fill_ratio: None,
+            temperature: drive_status.temperature,
             manufactured: None,
             bytes_read: None,
             bytes_written: None,
@@ -172,6 +185,16 @@ impl LtoTapeHandle {
 
                 let usage = mam_extract_media_usage(&mam)?;
 
+                status.position_percent = estimate_position_percent(
+                    position.logical_object_number,
+                    mam_estimate_logical_objects(&mam)?,
+                );
+
+                if let Some(capacity) = mam_extract_capacity(&mam)? {
+                    status.remaining_capacity = Some(capacity.remaining);
+                    status.fill_ratio = capacity.fill_ratio();
+                }
+
                 status.manufactured = Some(usage.manufactured);
                 status.bytes_read = Some(usage.bytes_read);
                 status.bytes_written = Some(usage.bytes_written);
@@ -219,6 +242,22 @@ impl LtoTapeHandle {
         self.sg_tape.locate_file(position)
     }
 
+    /// Calibrate the locate offset and store the result in the cache
+    ///
+    /// Only use this on blank or scratch media (see `SgTape::calibrate_locate_offset`).
+    pub fn calibrate_locate_offset(&mut self, media_uuid: Option<&Uuid>) -> Result<i64, Error> {
+        let serial = match self.sg_tape.drive_serial() {
+            Some(serial) => serial,
+            None => bail!("unable to cache locate offset - drive does not report a serial number"),
+        };
+
+        let offset = self.sg_tape.calibrate_locate_offset()?;
+
+        save_locate_offset(&serial, media_uuid, offset)?;
+
+        Ok(offset)
+    }
+
     pub fn erase_media(&mut self, fast: bool) -> Result<(), Error> {
         self.sg_tape.erase_media(fast)
     }
@@ -290,6 +329,25 @@ impl TapeDriver for LtoTapeHandle {
         self.locate_file(file)
     }
 
+    fn find_append_position(&mut self) -> Result<AppendPosition, Error> {
+
+        self.move_to_eom(false)?;
+
+        if !self.sg_tape.check_filemark()? {
+            // write was interrupted before the filemark - the last file is partial,
+            // so close it first (else the next file would get appended to it)
+            let partial_file = self.current_file_number()?;
+            self.sg_tape.write_filemarks(1, false)?;
+            let file_number = self.current_file_number()?;
+            return Ok(AppendPosition {
+                file_number,
+                partial_file: Some((partial_file, String::from("missing filemark"))),
+            });
+        }
+
+        check_last_file(self)
+    }
+
     fn rewind(&mut self) -> Result<(), Error> {
         self.sg_tape.rewind()
     }
@@ -298,6 +356,14 @@ impl TapeDriver for LtoTapeHandle {
         self.sg_tape.current_file_number()
     }
 
+    fn current_position(&mut self) -> Result<TapePosition, Error> {
+        let position = self.sg_tape.position()?;
+        Ok(TapePosition {
+            file_number: position.logical_file_id,
+            block_number: position.logical_object_number,
+        })
+    }
+
     fn format_media(&mut self, fast: bool) -> Result<(), Error> {
         self.sg_tape.format_media(fast)
     }
@@ -309,7 +375,8 @@ impl TapeDriver for LtoTapeHandle {
     }
 
     fn write_file<'a>(&'a mut self) -> Result<Box<dyn TapeWrite + 'a>, std::io::Error> {
-        let handle = self.sg_tape.open_writer();
+        let handle = self.sg_tape.open_writer()
+            .map_err(|err| proxmox::io_format_err!("{}", err))?;
         Ok(Box::new(handle))
     }
 
@@ -364,11 +431,19 @@ impl TapeDriver for LtoTapeHandle {
         self.sg_tape.eject()
     }
 
+    fn eject_and_wait(&mut self, timeout: std::time::Duration) -> Result<bool, Error> {
+        self.sg_tape.eject_and_wait(timeout)
+    }
+
     /// Read Tape Alert Flags
     fn tape_alert_flags(&mut self) -> Result<TapeAlertFlags, Error> {
         self.sg_tape.tape_alert_flags()
     }
 
+    fn estimated_position_percent(&mut self) -> Result<Option<f64>, Error> {
+        self.sg_tape.estimated_position_percent()
+    }
+
     /// Set or clear encryption key
     ///
     /// Note: Only 'root' can read secret encryption keys, so we need
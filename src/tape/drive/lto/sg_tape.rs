@@ -24,6 +24,12 @@ pub use mam::*;
 mod report_density;
 pub use report_density::*;
 
+mod locate_offset;
+pub use locate_offset::*;
+
+mod temperature;
+pub use temperature::*;
+
 use proxmox::{
     sys::error::SysResult,
     tools::io::{ReadExt, WriteExt},
@@ -52,6 +58,7 @@ use crate::{
         ModeBlockDescriptor,
         alloc_page_aligned_buffer,
         scsi_inquiry,
+        scsi_unit_serial_number,
         scsi_mode_sense,
         scsi_request_sense,
     },
@@ -119,13 +126,19 @@ pub struct LtoTapeStatus {
     pub buffer_mode: u8,
     pub write_protect: bool,
     pub compression: bool,
+    /// Drive temperature (degrees Celsius), only read on request
+    pub temperature: Option<u8>,
 }
 
 pub struct SgTape {
     file: File,
     locate_offset: Option<i64>,
+    // the locate offset cache is only read on first locate, not on open
+    locate_offset_cache_checked: bool,
     info: InquiryInfo,
     encryption_key_loaded: bool,
+    // file number at end of data, reset by anything which modifies the tape
+    eod_file_number: Option<u64>,
 }
 
 impl SgTape {
@@ -147,7 +160,9 @@ impl SgTape {
             file,
             info,
             encryption_key_loaded: false,
+            eod_file_number: None,
             locate_offset: None,
+            locate_offset_cache_checked: false,
         })
     }
 
@@ -160,6 +175,21 @@ impl SgTape {
         &self.info
     }
 
+    /// Read the drive serial number (if the drive reports one)
+    pub fn drive_serial(&mut self) -> Option<String> {
+        scsi_unit_serial_number(&mut self.file).ok()
+    }
+
+    // use a previously calibrated locate offset (if any)
+    fn load_cached_locate_offset(&mut self) {
+        if self.locate_offset.is_some() || self.locate_offset_cache_checked {
+            return;
+        }
+        self.locate_offset_cache_checked = true;
+        self.locate_offset = self.drive_serial()
+            .and_then(|serial| lookup_locate_offset(&serial, None));
+    }
+
     /// Return the maximum supported density code
     ///
     /// This can be used to detect the drive generation.
@@ -199,6 +229,8 @@ impl SgTape {
     /// drive is positioned immediately before End Of Data (not End Of
     /// Tape).
     pub fn erase_media(&mut self, fast: bool) -> Result<(), Error> {
+        self.eod_file_number = None;
+
         let mut sg_raw = SgRaw::new(&mut self.file, 16)?;
         sg_raw.set_timeout(Self::SCSI_TAPE_DEFAULT_TIMEOUT);
         let mut cmd = Vec::new();
@@ -285,6 +317,18 @@ impl SgTape {
         Ok(())
     }
 
+    /// Lock the drive door (PREVENT MEDIUM REMOVAL)
+    pub fn lock_media(&mut self) -> Result<(), Error> {
+        self.set_medium_removal(false)
+            .map_err(|err| format_err!("lock media failed - {}", err))
+    }
+
+    /// Unlock the drive door (ALLOW MEDIUM REMOVAL)
+    pub fn unlock_media(&mut self) -> Result<(), Error> {
+        self.set_medium_removal(true)
+            .map_err(|err| format_err!("unlock media failed - {}", err))
+    }
+
     pub fn rewind(&mut self) -> Result<(), Error> {
 
         let mut sg_raw = SgRaw::new(&mut self.file, 16)?;
@@ -298,6 +342,27 @@ impl SgTape {
         Ok(())
     }
 
+    /// Returns the file number at end of data
+    ///
+    /// The value is cached, so we only need to move to the end of
+    /// data once. This changes the tape position.
+    pub fn end_of_data_file_number(&mut self) -> Result<u64, Error> {
+        if let Some(file_number) = self.eod_file_number {
+            return Ok(file_number);
+        }
+
+        self.move_to_eom(false)?;
+        let file_number = self.current_file_number()?;
+        self.eod_file_number = Some(file_number);
+
+        Ok(file_number)
+    }
+
+    /// Locate file 'position'
+    ///
+    /// Checks the reached position after the LOCATE. Fails if
+    /// 'position' is beyond end of data, because the drive position
+    /// is undefined after such a LOCATE.
     pub fn locate_file(&mut self, position: u64) ->  Result<(), Error> {
         if position == 0 {
             return self.rewind();
@@ -310,11 +375,75 @@ impl SgTape {
             self.rewind()?;
             let mut sg_raw = SgRaw::new(&mut self.file, 16)?;
             sg_raw.set_timeout(Self::SCSI_TAPE_DEFAULT_TIMEOUT);
-            sg_raw.do_command(SPACE_ONE_FILEMARK)
-                .map_err(|err| format_err!("locate file {} (space) failed - {}", position, err))?;
+            if let Err(err) = sg_raw.do_command(SPACE_ONE_FILEMARK) {
+                let err = format_err!("locate file {} (space) failed - {}", position, err);
+                return Err(self.locate_failure(position, err));
+            }
             return Ok(());
         }
 
+        self.load_cached_locate_offset();
+
+        if let Err(err) = self.locate_file_with_offset(position, self.locate_offset.unwrap_or(0)) {
+            return Err(self.locate_failure(position, err));
+        }
+
+        // check if we landed at correct position
+        let current_file = self.current_file_number()?;
+        if current_file == position {
+            if self.locate_offset.is_none() {
+                self.locate_offset = Some(0);
+            }
+            return Ok(());
+        }
+
+        let err = format_err!("locate file {} failed - reached file {}", position, current_file);
+
+        if self.locate_offset.is_some() {
+            return Err(self.locate_failure(position, err));
+        }
+
+        // a target beyond end of data also ends at the wrong file, so
+        // make sure of that before we compute the offset from it
+        if let Ok(max_file_number) = self.end_of_data_file_number() {
+            if position > max_file_number {
+                return Err(locate_error(position, err, Some(max_file_number)));
+            }
+        }
+
+        let offset: i64 =
+            i64::try_from((position as i128) - (current_file as i128)).map_err(|err| {
+                format_err!(
+                    "locate_file: offset between {} and {} invalid: {}",
+                    position,
+                    current_file,
+                    err
+                )
+            })?;
+        self.locate_offset = Some(offset);
+        self.locate_file_with_offset(position, offset)?;
+        let current_file = self.current_file_number()?;
+        if current_file != position {
+            bail!("locate_file: compensating offset did not work, aborting...");
+        }
+
+        Ok(())
+    }
+
+    // Explain a failed locate. Moving to end of data for that is best
+    // effort, so a failure there does not hide the original error.
+    fn locate_failure(&mut self, position: u64, err: Error) -> Error {
+        let end_of_data = self.end_of_data_file_number().ok();
+        locate_error(position, err, end_of_data)
+    }
+
+    // Issue LOCATE(16) for file 'position', corrected by 'locate_offset'
+    //
+    // Position must be greater than 1.
+    fn locate_file_with_offset(&mut self, position: u64, locate_offset: i64) -> Result<(), Error> {
+
+        const SPACE_ONE_FILEMARK: &[u8] = &[0x11, 0x01, 0, 0, 1, 0];
+
         let mut sg_raw = SgRaw::new(&mut self.file, 16)?;
         sg_raw.set_timeout(Self::SCSI_TAPE_DEFAULT_TIMEOUT);
 
@@ -324,14 +453,10 @@ impl SgTape {
         // e.g. for IBM drives, LOCATE 1 moves to File #2, but
         // for HP drives, LOCATE 1 move to File #1
 
-        let fixed_position = if let Some(locate_offset) = self.locate_offset {
-            if locate_offset < 0 {
-                position.saturating_sub((-locate_offset) as u64)
-            } else {
-                position.saturating_add(locate_offset as u64)
-            }
+        let fixed_position = if locate_offset < 0 {
+            position.saturating_sub((-locate_offset) as u64)
         } else {
-            position
+            position.saturating_add(locate_offset as u64)
         };
         // always sub(1), so that it works for IBM drives without locate_offset
         let fixed_position = fixed_position.saturating_sub(1);
@@ -349,31 +474,70 @@ impl SgTape {
         sg_raw.do_command(SPACE_ONE_FILEMARK)
             .map_err(|err| format_err!("locate file {} (space) failed - {}", position, err))?;
 
-        if self.locate_offset.is_none() {
-            // check if we landed at correct position
-            let current_file = self.current_file_number()?;
-            if current_file != position {
-                let offset: i64 =
-                    i64::try_from((position as i128) - (current_file as i128)).map_err(|err| {
-                        format_err!(
-                            "locate_file: offset between {} and {} invalid: {}",
-                            position,
-                            current_file,
-                            err
-                        )
-                    })?;
-                self.locate_offset = Some(offset);
-                self.locate_file(position)?;
+        Ok(())
+    }
+
+    /// Calibrate the locate offset
+    ///
+    /// Writes a few filemarks after the end of data, locates to
+    /// them without offset correction and compares the reached
+    /// position. The scratch filemarks are removed afterwards (EOD
+    /// is rewritten at its original position).
+    ///
+    /// Only use this on blank or scratch media: if this fails (or
+    /// gets interrupted), the scratch filemarks may stay on the
+    /// tape. They are overwritten when the media gets (re)labeled or
+    /// a media set is started on it.
+    ///
+    /// The computed offset is used for all further locate commands.
+    pub fn calibrate_locate_offset(&mut self) -> Result<i64, Error> {
+
+        const CALIBRATION_FILEMARKS: u64 = 4;
+
+        self.move_to_eom(false)?;
+        let start = self.current_file_number()?;
+
+        self.write_filemarks(CALIBRATION_FILEMARKS as usize, false)?;
+
+        // Note: a wrong offset may move us one file too far or too
+        // short, so we only test positions surrounded by filemarks.
+        let result = proxmox::try_block!({
+            let mut samples = Vec::new();
+            for position in (start + 2)..(start + CALIBRATION_FILEMARKS) {
+                self.locate_file_with_offset(position, 0)?;
+                samples.push((position, self.current_file_number()?));
+            }
+            compute_locate_offset(&samples)
+        });
+
+        if let Ok(offset) = result {
+            self.locate_offset = Some(offset);
+        }
+
+        // remove the scratch filemarks
+        let cleanup = proxmox::try_block!({
+            let located = match start {
+                0 | 1 => self.locate_file(start),
+                _ => self.locate_file_with_offset(start, *result.as_ref().unwrap_or(&0)),
+            };
+            if located.is_err() || self.current_file_number()? != start {
+                // does not depend on the (possibly wrong) locate offset
+                self.rewind()?;
+                if start > 0 {
+                    self.space_filemarks(isize::try_from(start)?)?;
+                }
                 let current_file = self.current_file_number()?;
-                if current_file != position {
-                    bail!("locate_file: compensating offset did not work, aborting...");
+                if current_file != start {
+                    bail!("unable to restore end of data position ({} != {})", current_file, start);
                 }
-            } else {
-                self.locate_offset = Some(0);
             }
-        }
+            self.erase_media(true)
+        });
 
-        Ok(())
+        let offset = result.map_err(|err| format_err!("locate offset calibration failed - {}", err))?;
+        cleanup.map_err(|err| format_err!("locate offset calibration cleanup failed - {}", err))?;
+
+        Ok(offset)
     }
 
     pub fn position(&mut self) -> Result<ReadPositionLongPage, Error> {
@@ -431,7 +595,7 @@ impl SgTape {
                     .map_err(|err| format_err!("check_filemark failed (space forward) - {}", err))?;
                 Ok(false)
             }
-            Err(ScsiError::Sense(SenseInfo { sense_key: 0, asc: 0, ascq: 1 })) => {
+            Err(ScsiError::Sense(SenseInfo { sense_key: 0, asc: 0, ascq: 1, .. })) => {
                 // Filemark detected - good
                 self.space(1, false) // move to EOT side of filemark
                     .map_err(|err| format_err!("check_filemark failed (move to EOT side of filemark) - {}", err))?;
@@ -507,6 +671,8 @@ impl SgTape {
     }
 
     pub fn eject(&mut self) ->  Result<(), Error> {
+        self.eod_file_number = None;
+
         let mut sg_raw = SgRaw::new(&mut self.file, 16)?;
         sg_raw.set_timeout(Self::SCSI_TAPE_DEFAULT_TIMEOUT);
         let mut cmd = Vec::new();
@@ -519,6 +685,8 @@ impl SgTape {
     }
 
     pub fn load(&mut self) ->  Result<(), Error> {
+        self.eod_file_number = None;
+
         let mut sg_raw = SgRaw::new(&mut self.file, 16)?;
         sg_raw.set_timeout(Self::SCSI_TAPE_DEFAULT_TIMEOUT);
         let mut cmd = Vec::new();
@@ -540,6 +708,10 @@ impl SgTape {
             proxmox::io_bail!("write_filemarks failed: got strange count '{}'", count);
         }
 
+        if count > 0 {
+            self.eod_file_number = None;
+        }
+
         let mut sg_raw = SgRaw::new(&mut self.file, 16)
             .map_err(|err| proxmox::io_format_err!("write_filemarks failed (alloc) - {}", err))?;
 
@@ -556,7 +728,7 @@ impl SgTape {
 
         match sg_raw.do_command(&cmd) {
             Ok(_) => { /* OK */ }
-            Err(ScsiError::Sense(SenseInfo { sense_key: 0, asc: 0, ascq: 2 })) => {
+            Err(ScsiError::Sense(SenseInfo { sense_key: 0, asc: 0, ascq: 2, .. })) => {
                 /* LEOM - ignore */
             }
             Err(err) => {
@@ -588,6 +760,45 @@ impl SgTape {
         }
     }
 
+    /// Test if there is a media in the drive
+    ///
+    /// Uses TEST UNIT READY, only 'NOT READY, MEDIUM NOT PRESENT' means
+    /// there is no media. Other 'NOT READY' conditions (e.g. while loading
+    /// or unloading) are reported as present.
+    pub fn media_present(&mut self) -> Result<bool, Error> {
+
+        let mut sg_raw = SgRaw::new(&mut self.file, 16)?;
+        sg_raw.set_timeout(30); // use short timeout
+        let mut cmd = Vec::new();
+        cmd.extend(&[0x00, 0, 0, 0, 0, 0]); // TEST UNIT READY
+
+        match sg_raw.do_command(&cmd) {
+            Ok(_) => Ok(true),
+            Err(ScsiError::Sense(SenseInfo { sense_key: 2, asc: 0x3a, .. })) => Ok(false),
+            Err(ScsiError::Sense(SenseInfo { sense_key: 2, .. })) => Ok(true),
+            Err(err) => bail!("test_unit_ready failed - {}", err),
+        }
+    }
+
+    /// Eject media and wait until the drive reports no media
+    ///
+    /// Returns `false` if the media is still reported after `timeout`.
+    pub fn eject_and_wait(&mut self, timeout: std::time::Duration) -> Result<bool, Error> {
+        self.eject()?;
+
+        let start = SystemTime::now();
+
+        loop {
+            if !self.media_present()? {
+                return Ok(true);
+            }
+            if start.elapsed()? > timeout {
+                return Ok(false);
+            }
+            std::thread::sleep(std::time::Duration::new(1, 0));
+        }
+    }
+
     pub fn wait_until_ready(&mut self) -> Result<(), Error> {
 
         let start = SystemTime::now();
@@ -621,6 +832,41 @@ impl SgTape {
         return read_volume_statistics(&mut self.file);
     }
 
+    /// Read the current drive temperature (degrees Celsius)
+    pub fn temperature(&mut self) -> Result<Option<u8>, Error> {
+        read_temperature(&mut self.file)
+    }
+
+    /// Estimate the current tape position in percent of the written data
+    ///
+    /// Returns `None` if the drive does not report the capacity
+    /// attributes in Cartridge Memory (see `mam_estimate_logical_objects`).
+    pub fn estimated_position_percent(&mut self) -> Result<Option<f64>, Error> {
+        let position = self.position()?;
+
+        let total_objects = match self.cartridge_memory() {
+            Ok(mam) => mam_estimate_logical_objects(&mam)?,
+            Err(_) => None,
+        };
+
+        Ok(estimate_position_percent(position.logical_object_number, total_objects))
+    }
+
+    /// Remaining capacity of the current partition (bytes)
+    ///
+    /// Returns `None` if the drive does not report the capacity
+    /// attributes in Cartridge Memory.
+    pub fn remaining_capacity_bytes(&mut self) -> Result<Option<u64>, Error> {
+        let mam = self.cartridge_memory()?;
+        Ok(mam_extract_capacity(&mam)?.map(|capacity| capacity.remaining))
+    }
+
+    /// Used part of the current partition, 0.0 (empty) to 1.0 (full)
+    pub fn fill_ratio(&mut self) -> Result<Option<f64>, Error> {
+        let mam = self.cartridge_memory()?;
+        Ok(mam_extract_capacity(&mam)?.and_then(|capacity| capacity.fill_ratio()))
+    }
+
     pub fn set_encryption(
         &mut self,
         key: Option<[u8; 32]>,
@@ -636,6 +882,8 @@ impl SgTape {
     // Returns true if the drive reached the Logical End Of Media (early warning)
     fn write_block(&mut self, data: &[u8]) -> Result<bool, std::io::Error> {
 
+        self.eod_file_number = None;
+
         let transfer_len = data.len();
 
         if transfer_len > 0x800000 {
@@ -659,7 +907,7 @@ impl SgTape {
 
         match sg_raw.do_out_command(&cmd, data) {
             Ok(()) => { return Ok(false) }
-            Err(ScsiError::Sense(SenseInfo { sense_key: 0, asc: 0, ascq: 2 })) => {
+            Err(ScsiError::Sense(SenseInfo { sense_key: 0, asc: 0, ascq: 2, .. })) => {
                 return Ok(true); // LEOM
             }
             Err(err) => {
@@ -692,10 +940,10 @@ impl SgTape {
 
         let data = match sg_raw.do_in_command(&cmd, buffer) {
             Ok(data) => data,
-            Err(ScsiError::Sense(SenseInfo { sense_key: 0, asc: 0, ascq: 1 })) => {
+            Err(ScsiError::Sense(SenseInfo { sense_key: 0, asc: 0, ascq: 1, .. })) => {
                 return Err(BlockReadError::EndOfFile);
             }
-            Err(ScsiError::Sense(SenseInfo { sense_key: 8, asc: 0, ascq: 5 })) => {
+            Err(ScsiError::Sense(SenseInfo { sense_key: 8, asc: 0, ascq: 5, .. })) => {
                 return Err(BlockReadError::EndOfStream);
             }
             Err(err) => {
@@ -714,9 +962,14 @@ impl SgTape {
         Ok(transfer_len)
     }
 
-    pub fn open_writer(&mut self) -> BlockedWriter<SgTapeWriter> {
-        let writer = SgTapeWriter::new(self);
-        BlockedWriter::new(writer)
+    /// Open a writer for the next tape file
+    ///
+    /// The drive door stays locked until the writer is dropped, so the
+    /// media cannot be ejected accidentally while writing.
+    pub fn open_writer(&mut self) -> Result<BlockedWriter<SgTapeWriter>, Error> {
+        let guard = MediaLockGuard::new(self)?;
+        let writer = SgTapeWriter::new(guard);
+        Ok(BlockedWriter::new(writer))
     }
 
     pub fn open_reader(&mut self) -> Result<BlockedReader<SgTapeReader>, BlockReadError> {
@@ -831,7 +1084,11 @@ impl SgTape {
     ///
     /// We read the drive compression page, including the
     /// block_descriptor. This is all information we need for now.
-    pub fn read_drive_status(&mut self) -> Result<LtoTapeStatus, Error> {
+    ///
+    /// Reading the temperature needs an additional LOG SENSE command,
+    /// so it is only done if `read_temperature` is set. Drives not
+    /// supporting the temperature log page report `None`.
+    pub fn read_drive_status(&mut self, read_temperature: bool) -> Result<LtoTapeStatus, Error> {
 
         // We do a Request Sense, but ignore the result.
         // This clears deferred error or media changed events.
@@ -839,12 +1096,19 @@ impl SgTape {
 
         let (head, block_descriptor, page) = self.read_compression_page()?;
 
+        let temperature = if read_temperature {
+            self.temperature().unwrap_or(None)
+        } else {
+            None
+        };
+
         Ok(LtoTapeStatus {
             block_length: block_descriptor.block_length(),
             write_protect: head.write_protect(),
             buffer_mode: head.buffer_mode(),
             compression: page.compression_enabled(),
             density_code: block_descriptor.density_code,
+            temperature,
         })
     }
 }
@@ -888,19 +1152,81 @@ impl <'a> BlockRead for SgTapeReader<'a> {
     }
 }
 
-pub struct SgTapeWriter<'a> {
-    sg_tape: &'a mut SgTape,
+/// Device operations used by `SgTapeWriter`
+///
+/// Implemented by `SgTape`, this allows testing the writer without a drive.
+pub trait TapeWriteDevice {
+    fn lock_media(&mut self) -> Result<(), Error>;
+    fn unlock_media(&mut self) -> Result<(), Error>;
+    fn write_block(&mut self, data: &[u8]) -> Result<bool, std::io::Error>;
+    fn write_filemarks(&mut self, count: usize, immediate: bool) -> Result<(), std::io::Error>;
+}
+
+impl TapeWriteDevice for SgTape {
+
+    fn lock_media(&mut self) -> Result<(), Error> {
+        SgTape::lock_media(self)
+    }
+
+    fn unlock_media(&mut self) -> Result<(), Error> {
+        SgTape::unlock_media(self)
+    }
+
+    fn write_block(&mut self, data: &[u8]) -> Result<bool, std::io::Error> {
+        SgTape::write_block(self, data)
+    }
+
+    fn write_filemarks(&mut self, count: usize, immediate: bool) -> Result<(), std::io::Error> {
+        SgTape::write_filemarks(self, count, immediate)
+    }
+}
+
+/// Locks the drive door, and unlocks it again on drop
+pub struct MediaLockGuard<'a, T: TapeWriteDevice = SgTape>(&'a mut T);
+
+impl <'a, T: TapeWriteDevice> MediaLockGuard<'a, T> {
+
+    pub fn new(sg_tape: &'a mut T) -> Result<Self, Error> {
+        sg_tape.lock_media()?;
+        Ok(Self(sg_tape))
+    }
+}
+
+impl <'a, T: TapeWriteDevice> std::ops::Deref for MediaLockGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &*self.0
+    }
+}
+
+impl <'a, T: TapeWriteDevice> std::ops::DerefMut for MediaLockGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut *self.0
+    }
+}
+
+impl <'a, T: TapeWriteDevice> Drop for MediaLockGuard<'a, T> {
+    fn drop(&mut self) {
+        if let Err(err) = self.0.unlock_media() {
+            log::error!("{}", err);
+        }
+    }
+}
+
+pub struct SgTapeWriter<'a, T: TapeWriteDevice = SgTape> {
+    sg_tape: MediaLockGuard<'a, T>,
     _leom_sent: bool,
 }
 
-impl <'a> SgTapeWriter<'a> {
+impl <'a, T: TapeWriteDevice> SgTapeWriter<'a, T> {
 
-    pub fn new(sg_tape: &'a mut SgTape) -> Self {
+    pub fn new(sg_tape: MediaLockGuard<'a, T>) -> Self {
         Self { sg_tape, _leom_sent: false }
     }
 }
 
-impl <'a> BlockWrite for SgTapeWriter<'a> {
+impl <'a, T: TapeWriteDevice> BlockWrite for SgTapeWriter<'a, T> {
 
     fn write_block(&mut self, buffer: &[u8]) -> Result<bool, std::io::Error> {
         self.sg_tape.write_block(buffer)
@@ -910,3 +1236,79 @@ impl <'a> BlockWrite for SgTapeWriter<'a> {
         self.sg_tape.write_filemarks(1, true)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::tape::{
+        TapeWrite,
+        file_formats::PROXMOX_TAPE_BLOCK_SIZE,
+    };
+
+    #[derive(Debug, PartialEq)]
+    enum Command {
+        Lock,
+        Unlock,
+        Write,
+        Filemark,
+    }
+
+    #[derive(Default)]
+    struct MockTape {
+        commands: Vec<Command>,
+        fail_lock: bool,
+    }
+
+    impl TapeWriteDevice for MockTape {
+        fn lock_media(&mut self) -> Result<(), Error> {
+            if self.fail_lock {
+                bail!("lock media failed - not supported");
+            }
+            self.commands.push(Command::Lock);
+            Ok(())
+        }
+
+        fn unlock_media(&mut self) -> Result<(), Error> {
+            self.commands.push(Command::Unlock);
+            Ok(())
+        }
+
+        fn write_block(&mut self, _data: &[u8]) -> Result<bool, std::io::Error> {
+            self.commands.push(Command::Write);
+            Ok(false)
+        }
+
+        fn write_filemarks(&mut self, _count: usize, _immediate: bool) -> Result<(), std::io::Error> {
+            self.commands.push(Command::Filemark);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_writer_locks_media() -> Result<(), Error> {
+        let mut tape = MockTape::default();
+
+        {
+            let guard = MediaLockGuard::new(&mut tape)?;
+            let mut writer = BlockedWriter::new(SgTapeWriter::new(guard));
+            writer.write_all(&vec![0u8; PROXMOX_TAPE_BLOCK_SIZE * 2])?;
+            writer.finish(false)?;
+        }
+
+        let (first, last) = (tape.commands.first(), tape.commands.last());
+        assert_eq!(first, Some(&Command::Lock));
+        assert_eq!(last, Some(&Command::Unlock));
+        assert!(tape.commands.contains(&Command::Write));
+        assert!(tape.commands.contains(&Command::Filemark));
+        assert_eq!(tape.commands.iter().filter(|cmd| **cmd == Command::Lock).count(), 1);
+        assert_eq!(tape.commands.iter().filter(|cmd| **cmd == Command::Unlock).count(), 1);
+
+        // nothing is written if the door cannot be locked
+        let mut tape = MockTape { fail_lock: true, ..Default::default() };
+        assert!(MediaLockGuard::new(&mut tape).is_err());
+        assert!(tape.commands.is_empty());
+
+        Ok(())
+    }
+}
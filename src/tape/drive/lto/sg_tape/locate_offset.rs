@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::path::PathBuf;
+
+use anyhow::{bail, format_err, Error};
+use serde::{Deserialize, Serialize};
+
+use proxmox::tools::{
+    Uuid,
+    fs::{create_path, file_read_optional_string, replace_file, CreateOptions},
+};
+
+/// Directory path where we cache tape drive calibration data
+pub const TAPE_CACHE_DIR: &str = concat!(PROXMOX_BACKUP_CACHE_DIR_M!(), "/tape");
+
+const LOCATE_OFFSET_CACHE_FILENAME: &str = "locate-offset.json";
+
+#[derive(Serialize, Deserialize, Default)]
+struct LocateOffsetCache {
+    /// Offsets indexed by drive serial, then by media uuid
+    drives: HashMap<String, HashMap<String, i64>>,
+}
+
+fn locate_offset_cache_path() -> PathBuf {
+    let mut path = PathBuf::from(TAPE_CACHE_DIR);
+    path.push(LOCATE_OFFSET_CACHE_FILENAME);
+    path
+}
+
+fn load_locate_offset_cache() -> Result<LocateOffsetCache, Error> {
+    let path = locate_offset_cache_path();
+    match file_read_optional_string(&path)? {
+        Some(raw) => serde_json::from_str(&raw)
+            .map_err(|err| format_err!("unable to parse {:?} - {}", path, err)),
+        None => Ok(LocateOffsetCache::default()),
+    }
+}
+
+/// Compute the LOCATE offset from calibration samples
+///
+/// Each sample is a `(requested, reached)` pair, where `requested`
+/// is the file number we tried to locate (without any offset
+/// correction), and `reached` is the file number reported by the
+/// drive afterwards. All samples must agree on the offset.
+pub fn compute_locate_offset(samples: &[(u64, u64)]) -> Result<i64, Error> {
+    let mut result = None;
+
+    for (requested, reached) in samples {
+        let offset = i64::try_from((*requested as i128) - (*reached as i128))
+            .map_err(|err| format_err!(
+                "offset between {} and {} invalid: {}", requested, reached, err))?;
+
+        match result {
+            None => result = Some(offset),
+            Some(expected) if expected != offset => {
+                bail!("inconsistent locate offsets ({} != {})", expected, offset);
+            }
+            Some(_) => { /* OK */ }
+        }
+    }
+
+    result.ok_or_else(|| format_err!("no calibration samples"))
+}
+
+/// Error for a failed LOCATE to file `position`
+///
+/// Reports targets beyond end of data (if that is known), else
+/// returns the original error.
+pub fn locate_error(position: u64, err: Error, end_of_data: Option<u64>) -> Error {
+    match end_of_data {
+        Some(max_file_number) if position > max_file_number => format_err!(
+            "locate file {} failed - file {} beyond end of data (max {})",
+            position,
+            position,
+            max_file_number,
+        ),
+        _ => err,
+    }
+}
+
+/// Lookup a cached LOCATE offset for a drive
+///
+/// If `media_uuid` is None, we only return a value if all cached
+/// entries for that drive agree on the same offset.
+pub fn lookup_locate_offset(drive_serial: &str, media_uuid: Option<&Uuid>) -> Option<i64> {
+    let cache = load_locate_offset_cache().ok()?;
+    let media_map = cache.drives.get(drive_serial)?;
+
+    if let Some(uuid) = media_uuid {
+        return media_map.get(&uuid.to_string()).copied();
+    }
+
+    let mut offsets = media_map.values();
+    let first = *offsets.next()?;
+    if offsets.all(|offset| *offset == first) {
+        Some(first)
+    } else {
+        None
+    }
+}
+
+// cache key for offsets calibrated on blank (unlabeled) media
+const BLANK_MEDIA_KEY: &str = "blank";
+
+/// Store the calibrated LOCATE offset for a (drive, media) pair
+///
+/// Offsets calibrated on blank media (`media_uuid` is None) are only
+/// used for lookups without media.
+pub fn save_locate_offset(drive_serial: &str, media_uuid: Option<&Uuid>, offset: i64) -> Result<(), Error> {
+    let backup_user = crate::backup::backup_user()?;
+    let mode = nix::sys::stat::Mode::from_bits_truncate(0o0750);
+    let dir_options = CreateOptions::new()
+        .perm(mode)
+        .owner(backup_user.uid)
+        .group(backup_user.gid);
+
+    create_path(TAPE_CACHE_DIR, None, Some(dir_options))
+        .map_err(|err: Error| format_err!("unable to create tape cache dir - {}", err))?;
+
+    let mut cache = load_locate_offset_cache()?;
+    cache.drives
+        .entry(drive_serial.to_string())
+        .or_insert_with(HashMap::new)
+        .insert(media_uuid.map(|uuid| uuid.to_string()).unwrap_or_else(|| BLANK_MEDIA_KEY.to_string()), offset);
+
+    let raw = serde_json::to_string_pretty(&cache)?;
+
+    let mode = nix::sys::stat::Mode::from_bits_truncate(0o0640);
+    let options = CreateOptions::new()
+        .perm(mode)
+        .owner(backup_user.uid)
+        .group(backup_user.gid);
+
+    replace_file(locate_offset_cache_path(), raw.as_bytes(), options)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::format_err;
+
+    use super::{compute_locate_offset, locate_error};
+
+    #[test]
+    fn test_compute_locate_offset() {
+        // IBM like drives - no correction needed
+        assert_eq!(compute_locate_offset(&[(5, 5), (6, 6)]).unwrap(), 0);
+        // drive moves one file too far
+        assert_eq!(compute_locate_offset(&[(5, 6), (6, 7)]).unwrap(), -1);
+        // drive stops one file too early
+        assert_eq!(compute_locate_offset(&[(5, 4), (6, 5)]).unwrap(), 1);
+        // samples must agree
+        assert!(compute_locate_offset(&[(5, 5), (6, 7)]).is_err());
+        // no samples
+        assert!(compute_locate_offset(&[]).is_err());
+    }
+
+    #[test]
+    fn test_locate_error() {
+        let locate_failed = || format_err!("locate file 7 failed - medium error");
+
+        let err = locate_error(7, locate_failed(), Some(5));
+        assert_eq!(err.to_string(), "locate file 7 failed - file 7 beyond end of data (max 5)");
+
+        // target within data, or end of data unknown (move to EOM failed)
+        let err = locate_error(7, locate_failed(), Some(10));
+        assert_eq!(err.to_string(), "locate file 7 failed - medium error");
+        let err = locate_error(7, locate_failed(), None);
+        assert_eq!(err.to_string(), "locate file 7 failed - medium error");
+    }
+}
@@ -12,6 +12,7 @@ use crate::{
     tools::sgutils2::SgRaw,
     tape::{
         drive::lto::TapeAlertFlags,
+        file_formats::PROXMOX_TAPE_BLOCK_SIZE,
     },
 };
 
@@ -225,14 +226,181 @@ pub fn mam_extract_media_usage(mam: &[MamAttribute]) -> Result<MediaUsageInfo, E
     };
 
     let bytes_written: u64 = match mam.iter().find(|v| v.id == 0x02_20).map(|v| v.value.clone()) {
-        Some(read_str) => read_str.parse::<u64>()? * 1024*1024,
+        Some(read_str) => mib_to_bytes(read_str.parse()?, "Total MBytes Written In Medium Life")?,
         None => bail!("unable to read MAM 'Total MBytes Written In Medium Life'"),
     };
 
     let bytes_read: u64 = match mam.iter().find(|v| v.id == 0x02_21).map(|v| v.value.clone()) {
-        Some(read_str) => read_str.parse::<u64>()? * 1024*1024,
+        Some(read_str) => mib_to_bytes(read_str.parse()?, "Total MBytes Read In Medium Life")?,
         None => bail!("unable to read MAM 'Total MBytes Read In Medium Life'"),
     };
 
     Ok(MediaUsageInfo { manufactured, bytes_written, bytes_read })
 }
+
+// MAM reports sizes in MiB, fail on bogus values instead of overflowing
+fn mib_to_bytes(value: u64, name: &str) -> Result<u64, Error> {
+    value.checked_mul(1024*1024)
+        .ok_or_else(|| format_err!("MAM '{}' out of range ({} MiB)", name, value))
+}
+
+/// Partition capacity from Cartridge Memory
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MamCapacity {
+    /// Remaining capacity in the current partition (bytes)
+    pub remaining: u64,
+    /// Maximum capacity of the current partition (bytes)
+    pub maximum: u64,
+}
+
+impl MamCapacity {
+    /// Used part of the partition, 0.0 (empty) to 1.0 (full)
+    pub fn fill_ratio(&self) -> Option<f64> {
+        if self.maximum == 0 {
+            return None;
+        }
+        let ratio = 1.0 - (self.remaining as f64) / (self.maximum as f64);
+        Some(ratio.max(0.0).min(1.0))
+    }
+}
+
+/// Extract the partition capacity from Cartridge Memory
+///
+/// Uses "Remaining Capacity In Partition" (0x0000) and "Maximum Capacity
+/// In Partition" (0x0001), both reported in MiB (after drive compression).
+///
+/// Returns `None` if the attributes are not available, and an error if
+/// the values are out of range.
+pub fn mam_extract_capacity(mam: &[MamAttribute]) -> Result<Option<MamCapacity>, Error> {
+
+    let lookup = |id: u16| -> Option<u64> {
+        mam.iter().find(|v| v.id == id).and_then(|v| v.value.parse().ok())
+    };
+
+    let (remaining, maximum) = match (lookup(0x00_00), lookup(0x00_01)) {
+        (Some(remaining), Some(maximum)) => (remaining, maximum),
+        _ => return Ok(None),
+    };
+
+    Ok(Some(MamCapacity {
+        remaining: mib_to_bytes(remaining, "Remaining Capacity In Partition")?,
+        maximum: mib_to_bytes(maximum, "Maximum Capacity In Partition")?,
+    }))
+}
+
+/// Estimate the number of logical objects (blocks) written to the tape
+///
+/// Uses the used capacity of the current partition (see
+/// `mam_extract_capacity`), assuming that all data was written as
+/// PROXMOX_TAPE_BLOCK_SIZE blocks. The capacity is counted after drive
+/// compression, so this is only a rough estimate.
+///
+/// Returns `None` if the attributes are not available.
+pub fn mam_estimate_logical_objects(mam: &[MamAttribute]) -> Result<Option<u64>, Error> {
+
+    let capacity = match mam_extract_capacity(mam)? {
+        Some(capacity) => capacity,
+        None => return Ok(None),
+    };
+
+    let used_bytes = capacity.maximum.saturating_sub(capacity.remaining);
+
+    Ok(Some(used_bytes / (PROXMOX_TAPE_BLOCK_SIZE as u64)))
+}
+
+/// Compute the position (in percent) from a logical object number.
+///
+/// The result is clamped to 100%, because the total is only an estimate.
+pub fn estimate_position_percent(logical_object_number: u64, total_objects: Option<u64>) -> Option<f64> {
+    match total_objects {
+        Some(total) if total > 0 => {
+            let percent = (logical_object_number as f64) * 100.0 / (total as f64);
+            Some(percent.min(100.0))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn mam_attribute(id: u16, value: &str) -> MamAttribute {
+        MamAttribute { id, name: String::new(), value: value.to_string() }
+    }
+
+    #[test]
+    fn test_estimate_position_percent() -> Result<(), Error> {
+        // LTO-8, 12TB native, 2000 GiB used
+        let mam = vec![
+            mam_attribute(0x00_00, "9999872"),
+            mam_attribute(0x00_01, "12047872"),
+        ];
+
+        let total = mam_estimate_logical_objects(&mam)?;
+        assert_eq!(total, Some(2048000 * 4));
+
+        assert_eq!(estimate_position_percent(0, total), Some(0.0));
+        assert_eq!(estimate_position_percent(2048000 * 2, total), Some(50.0));
+        // compression may lead to more blocks than estimated
+        assert_eq!(estimate_position_percent(2048000 * 5, total), Some(100.0));
+
+        // older drives without capacity attributes
+        let mam = vec![mam_attribute(0x00_01, "12047872")];
+        assert_eq!(mam_estimate_logical_objects(&mam)?, None);
+        assert_eq!(estimate_position_percent(100, None), None);
+
+        // empty tape
+        let mam = vec![
+            mam_attribute(0x00_00, "12047872"),
+            mam_attribute(0x00_01, "12047872"),
+        ];
+        assert_eq!(estimate_position_percent(0, mam_estimate_logical_objects(&mam)?), None);
+
+        Ok(())
+    }
+
+    // encode attributes like the drive does (READ ATTRIBUTE response)
+    fn mam_buffer(attributes: &[(u16, u64)]) -> Vec<u8> {
+        let mut data = Vec::new();
+        for (id, value) in attributes {
+            data.extend(&id.to_be_bytes());
+            data.push(0); // flags
+            data.extend(&8u16.to_be_bytes());
+            data.extend(&value.to_be_bytes());
+        }
+        let mut buffer = (data.len() as u32).to_be_bytes().to_vec();
+        buffer.extend(data);
+        buffer
+    }
+
+    #[test]
+    fn test_mam_capacity() -> Result<(), Error> {
+        // LTO-8, 12TB native, 3/4 used
+        let data = mam_buffer(&[(0x00_00, 3011968), (0x00_01, 12047872), (0x00_03, 12)]);
+        let mam = decode_mam_attributes(&data)?;
+
+        let capacity = mam_extract_capacity(&mam)?.unwrap();
+        assert_eq!(capacity.remaining, 3011968 * 1024*1024);
+        assert_eq!(capacity.maximum, 12047872 * 1024*1024);
+        assert_eq!(capacity.fill_ratio(), Some(0.75));
+
+        // new tape
+        let data = mam_buffer(&[(0x00_00, 12047872), (0x00_01, 12047872)]);
+        let capacity = mam_extract_capacity(&decode_mam_attributes(&data)?)?.unwrap();
+        assert_eq!(capacity.fill_ratio(), Some(0.0));
+
+        // missing maximum capacity
+        let data = mam_buffer(&[(0x00_00, 3011968)]);
+        assert_eq!(mam_extract_capacity(&decode_mam_attributes(&data)?)?, None);
+
+        // bogus values must not overflow
+        let data = mam_buffer(&[(0x00_00, u64::MAX / 1024), (0x00_01, u64::MAX / 1024)]);
+        assert!(mam_extract_capacity(&decode_mam_attributes(&data)?).is_err());
+
+        let capacity = MamCapacity { remaining: 0, maximum: 0 };
+        assert_eq!(capacity.fill_ratio(), None);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,129 @@
+use std::os::unix::io::AsRawFd;
+
+use anyhow::{bail, format_err, Error};
+use endian_trait::Endian;
+
+use proxmox::tools::io::ReadExt;
+
+use crate::tools::sgutils2::SgRaw;
+
+/// SCSI command to query the drive temperature
+///
+/// CDB: LOG SENSE / LP0Dh Temperature
+///
+/// Returns `None` if the drive does not report the current temperature.
+pub fn read_temperature<F: AsRawFd>(file: &mut F) -> Result<Option<u8>, Error> {
+
+    let data = sg_read_temperature(file)?;
+
+    decode_temperature(&data)
+}
+
+fn sg_read_temperature<F: AsRawFd>(file: &mut F) -> Result<Vec<u8>, Error> {
+
+    let alloc_len: u16 = 64;
+    let mut sg_raw = SgRaw::new(file, alloc_len as usize)?;
+
+    let mut cmd = Vec::new();
+    cmd.push(0x4D); // LOG SENSE
+    cmd.push(0);
+    cmd.push((1<<6) | 0x0D); // Temperature log page
+    cmd.push(0); // Subpage 0
+    cmd.push(0);
+    cmd.push(0);
+    cmd.push(0);
+    cmd.extend(&alloc_len.to_be_bytes()); // alloc len
+    cmd.push(0u8); // control byte
+
+    sg_raw.do_command(&cmd)
+        .map_err(|err| format_err!("read drive temperature failed - {}", err))
+        .map(|v| v.to_vec())
+}
+
+#[repr(C, packed)]
+#[derive(Endian)]
+struct LpParameterHeader {
+    parameter_code: u16,
+    control: u8,
+    parameter_len: u8,
+}
+
+fn decode_temperature(data: &[u8]) -> Result<Option<u8>, Error> {
+
+    proxmox::try_block!({
+        if data.len() < 4 || !((data[0] & 0x7f) == 0x0D && data[1] == 0) {
+            bail!("invalid response");
+        }
+
+        let mut reader = &data[2..];
+
+        let page_len: u16 = unsafe { reader.read_be_value()? };
+
+        let page_len = page_len as usize;
+
+        if (page_len + 4) > data.len() {
+            bail!("invalid page length");
+        } else {
+            reader = &data[4..page_len+4];
+        }
+
+        while !reader.is_empty() {
+            let head: LpParameterHeader = unsafe { reader.read_be_value()? };
+            let value = reader.read_exact_allocated(head.parameter_len as usize)?;
+
+            // parameter 0000h: current temperature (degrees Celsius)
+            if head.parameter_code == 0x0000 {
+                if value.len() != 2 {
+                    bail!("invalid temperature parameter length {}", value.len());
+                }
+                // FFh means the temperature is not available
+                return Ok(if value[1] == 0xff { None } else { Some(value[1]) });
+            }
+        }
+
+        Ok(None)
+    }).map_err(|err: Error| format_err!("decode temperature failed - {}", err))
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    fn build_page(params: &[(u16, &[u8])]) -> Vec<u8> {
+        let mut page = Vec::new();
+        for (code, value) in params {
+            page.extend(&code.to_be_bytes());
+            page.push(0x03); // control
+            page.push(value.len() as u8);
+            page.extend(*value);
+        }
+        let mut data = vec![0x0D, 0];
+        data.extend(&(page.len() as u16).to_be_bytes());
+        data.extend(page);
+        data
+    }
+
+    #[test]
+    fn test_decode_temperature() -> Result<(), Error> {
+
+        // current and reference temperature
+        let data = build_page(&[(0x0000, &[0, 38]), (0x0001, &[0, 60])]);
+        assert_eq!(decode_temperature(&data)?, Some(38));
+
+        // not available
+        let data = build_page(&[(0x0000, &[0, 0xff])]);
+        assert_eq!(decode_temperature(&data)?, None);
+
+        // only reference temperature
+        let data = build_page(&[(0x0001, &[0, 60])]);
+        assert_eq!(decode_temperature(&data)?, None);
+
+        // wrong page
+        let mut data = build_page(&[(0x0000, &[0, 38])]);
+        data[0] = 0x17;
+        assert!(decode_temperature(&data).is_err());
+
+        Ok(())
+    }
+}
@@ -1,10 +1,12 @@
 //! Tape drivers
 
 mod virtual_tape;
+pub use virtual_tape::{SimulatedError, VirtualTapeErrorConfig};
 
 mod lto;
 pub use lto::*;
 
+use std::fs::File;
 use std::os::unix::io::AsRawFd;
 use std::path::PathBuf;
 
@@ -46,13 +48,20 @@ use crate::{
         TapeRead,
         BlockReadError,
         MediaId,
-        drive::lto::TapeAlertFlags,
+        drive::lto::{
+            TapeAlertFlags,
+            tape_alert_flags_cleaning_request,
+        },
         file_formats::{
+            PROXMOX_BACKUP_CONTENT_HEADER_MAGIC_1_0,
             PROXMOX_BACKUP_MEDIA_LABEL_MAGIC_1_0,
             PROXMOX_BACKUP_MEDIA_SET_LABEL_MAGIC_1_0,
+            PROXMOX_BACKUP_CATALOG_ARCHIVE_MAGIC_1_0,
+            CatalogArchiveHeader,
             MediaLabel,
             MediaSetLabel,
             MediaContentHeader,
+            tape_write_catalog,
         },
         changer::{
             MediaChange,
@@ -61,6 +70,59 @@ use crate::{
     },
 };
 
+/// Logical tape position
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TapePosition {
+    /// Current file number (number of filemarks from BOT)
+    pub file_number: u64,
+    /// Current logical block number (blocks and filemarks from BOT)
+    pub block_number: u64,
+}
+
+/// Append position on a tape after an interrupted write session
+///
+/// See [TapeDriver::find_append_position].
+#[derive(Clone, Debug, PartialEq)]
+pub struct AppendPosition {
+    /// Current file number at end of data
+    pub file_number: u64,
+    /// Set if the last file on tape is partial/truncated (file number, reason)
+    pub partial_file: Option<(u64, String)>,
+}
+
+// Verify the last file on tape, expects the tape positioned at EOD (after a filemark)
+fn check_last_file<D: TapeDriver + ?Sized>(drive: &mut D) -> Result<AppendPosition, Error> {
+
+    let file_number = drive.current_file_number()?;
+    if file_number == 0 {
+        return Ok(AppendPosition { file_number, partial_file: None });
+    }
+
+    let last_file = file_number - 1;
+    drive.move_to_file(last_file)?;
+
+    let reason = match drive.read_next_file() {
+        Ok(mut reader) => match reader.skip_data() {
+            Ok(_) => match reader.has_end_marker() {
+                Ok(true) => None,
+                Ok(false) => Some(String::from("missing end marker")),
+                Err(err) => Some(err.to_string()),
+            },
+            Err(err) => Some(err.to_string()),
+        },
+        Err(BlockReadError::EndOfFile) => Some(String::from("file contains no data")),
+        Err(BlockReadError::EndOfStream) => bail!("got unexpected end of data in file {}", last_file),
+        Err(BlockReadError::Error(err)) => Some(err.to_string()),
+    };
+
+    drive.move_to_eom(false)?;
+
+    Ok(AppendPosition {
+        file_number,
+        partial_file: reason.map(|reason| (last_file, reason)),
+    })
+}
+
 /// Tape driver interface
 pub trait TapeDriver {
 
@@ -86,6 +148,9 @@ pub trait TapeDriver {
     /// Current file number
     fn current_file_number(&mut self) -> Result<u64, Error>;
 
+    /// Current position (file and logical block number)
+    fn current_position(&mut self) -> Result<TapePosition, Error>;
+
     /// Completely erase the media
     fn format_media(&mut self, fast: bool) -> Result<(), Error>;
 
@@ -207,9 +272,108 @@ pub trait TapeDriver {
         Ok((Some(media_id), key_config))
     }
 
+    /// Read the media label, and check if the data is readable
+    ///
+    /// Like `read_label`, but also returns the encryption state (see
+    /// `MediaEncryption`), so that callers can warn about missing keys
+    /// before reading any data.
+    fn read_label_with_encryption(&mut self) -> Result<(Option<MediaId>, MediaEncryption), Error> {
+        let (media_id, key_config) = self.read_label()?;
+        let encryption = match media_id {
+            Some(ref media_id) => MediaEncryption::from_media_id(media_id, key_config.as_ref())?,
+            None => MediaEncryption::Unencrypted,
+        };
+        Ok((media_id, encryption))
+    }
+
+    /// Write a media catalog archive (at the current position)
+    ///
+    /// The catalog lists all snapshots and chunk archives together
+    /// with their file numbers. It is written at the end of each
+    /// tape session, because appending is the only way to write
+    /// to a tape (anything after the written file gets lost).
+    ///
+    /// Returns `Ok(false)` if `LEOM` was detected before all data was
+    /// written (the archive is marked incomplete in that case).
+    fn write_catalog(
+        &mut self,
+        uuid: &Uuid,
+        media_set_uuid: &Uuid,
+        seq_nr: usize,
+        catalog: &mut File,
+    ) -> Result<bool, Error> {
+        let mut writer = self.write_file()?;
+        let done = tape_write_catalog(writer.as_mut(), uuid, media_set_uuid, seq_nr, catalog)?;
+        Ok(done.is_some())
+    }
+
+    /// Read the media catalog written by the last tape session
+    ///
+    /// Moves to the last file, and returns the catalog archive header
+    /// together with a reader for the catalog data. Returns `Ok(None)`
+    /// if the last file is not a catalog archive (e.g. the session was
+    /// interrupted). The catalog can then be used to locate snapshot
+    /// archives with `move_to_file`, without scanning the whole tape.
+    fn read_catalog<'a>(
+        &'a mut self,
+    ) -> Result<Option<(CatalogArchiveHeader, Box<dyn TapeRead + 'a>)>, Error> {
+
+        self.move_to_last_file()?;
+
+        let mut reader = match self.read_next_file() {
+            Ok(reader) => reader,
+            Err(BlockReadError::EndOfFile) | Err(BlockReadError::EndOfStream) => return Ok(None),
+            Err(BlockReadError::Error(err)) => return Err(err.into()),
+        };
+
+        let header: MediaContentHeader = unsafe { reader.read_le_value()? };
+        if header.magic != PROXMOX_BACKUP_CONTENT_HEADER_MAGIC_1_0 {
+            bail!("read_catalog: missing MediaContentHeader");
+        }
+
+        if header.content_magic != PROXMOX_BACKUP_CATALOG_ARCHIVE_MAGIC_1_0 {
+            return Ok(None);
+        }
+
+        let header_data = reader.read_exact_allocated(header.size as usize)?;
+
+        let archive_header: CatalogArchiveHeader = serde_json::from_slice(&header_data)
+            .map_err(|err| format_err!("unable to parse catalog archive header - {}", err))?;
+
+        Ok(Some((archive_header, reader)))
+    }
+
+    /// Find the append position after an interrupted write session
+    ///
+    /// Moves to end of data and reads the last file to verify it is
+    /// complete. The tape is left at end of data, and the returned
+    /// `file_number` is where the next file gets appended.
+    ///
+    /// If the last file is partial or truncated, it is reported in
+    /// `partial_file`, so that the caller can decide to overwrite it
+    /// (using `move_to_file`) instead of appending after it. A partial
+    /// file without its filemark gets closed first, so `file_number`
+    /// always points after it.
+    fn find_append_position(&mut self) -> Result<AppendPosition, Error> {
+        self.move_to_eom(false)?;
+        check_last_file(self)
+    }
+
     /// Eject media
     fn eject_media(&mut self) -> Result<(), Error>;
 
+    /// Eject media and wait until the drive confirms there is no media
+    ///
+    /// Some drives return from the eject command before the tape is
+    /// physically out, so a following changer unload may fail. Returns
+    /// `false` if the drive still reports media after `timeout`.
+    ///
+    /// Drives without such a check just eject (default).
+    fn eject_and_wait(&mut self, _timeout: std::time::Duration) -> Result<bool, Error> {
+        self.eject_media()?;
+        Ok(true)
+    }
+
     /// Read Tape Alert Flags
     ///
     /// This make only sense for real LTO drives. Virtual tape drives should
@@ -218,6 +382,21 @@ pub trait TapeDriver {
         Ok(TapeAlertFlags::empty())
     }
 
+    /// Estimate the current position in percent of the written data
+    ///
+    /// Returns `None` if the drive cannot provide an estimate (default).
+    fn estimated_position_percent(&mut self) -> Result<Option<f64>, Error> {
+        Ok(None)
+    }
+
+    /// Check if the drive requests a cleaning cycle
+    ///
+    /// Uses the CLEAN_NOW and CLEAN_PERIODIC tape alert flags.
+    fn drive_needs_cleaning(&mut self) -> Result<bool, Error> {
+        let flags = self.tape_alert_flags()?;
+        Ok(tape_alert_flags_cleaning_request(flags))
+    }
+
     /// Set or clear encryption key
     ///
     /// We use the media_set_uuid to XOR the secret key with the
@@ -234,6 +413,70 @@ pub trait TapeDriver {
     }
 }
 
+/// Encryption state of a media
+///
+/// Derived from the (unencrypted) media set label, which stores the
+/// fingerprint of the key used to encrypt the data.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MediaEncryption {
+    /// Data is not encrypted (or there is no media set label)
+    Unencrypted,
+    /// Data is encrypted, and the key is configured
+    KeyAvailable(Fingerprint),
+    /// Data is encrypted, but the key is not configured
+    KeyMissing {
+        fingerprint: Fingerprint,
+        /// The media contains the password protected key (see `restore_key`)
+        key_config_on_media: bool,
+    },
+}
+
+impl MediaEncryption {
+
+    /// Get the encryption state, checking the configured tape encryption keys
+    pub fn from_media_id(media_id: &MediaId, key_config: Option<&KeyConfig>) -> Result<Self, Error> {
+        if media_id.media_set_label.as_ref().and_then(|set| set.encryption_key_fingerprint.as_ref()).is_none() {
+            return Ok(MediaEncryption::Unencrypted);
+        }
+        let (key_configs, _digest) = crate::config::tape_encryption_keys::load_key_configs()?;
+        Ok(Self::with_known_keys(media_id, key_config, |fp| key_configs.contains_key(fp)))
+    }
+
+    fn with_known_keys<F: Fn(&Fingerprint) -> bool>(
+        media_id: &MediaId,
+        key_config: Option<&KeyConfig>,
+        key_is_configured: F,
+    ) -> Self {
+        let fingerprint = match media_id.media_set_label {
+            Some(MediaSetLabel { encryption_key_fingerprint: Some(ref fingerprint), .. }) => fingerprint.clone(),
+            _ => return MediaEncryption::Unencrypted,
+        };
+
+        if key_is_configured(&fingerprint) {
+            MediaEncryption::KeyAvailable(fingerprint)
+        } else {
+            MediaEncryption::KeyMissing { fingerprint, key_config_on_media: key_config.is_some() }
+        }
+    }
+
+    /// Fail with a descriptive message if the key is missing
+    pub fn check_key_available(&self) -> Result<(), Error> {
+        if let MediaEncryption::KeyMissing { fingerprint, key_config_on_media } = self {
+            let fingerprint = crate::tools::format::as_fingerprint(fingerprint.bytes());
+            if *key_config_on_media {
+                bail!(
+                    "this tape is encrypted; configure key {} before reading data \
+                     (the key can be restored from this tape with its password)",
+                    fingerprint,
+                );
+            } else {
+                bail!("this tape is encrypted; configure key {} before reading data", fingerprint);
+            }
+        }
+        Ok(())
+    }
+}
+
 /// Get the media changer (MediaChange + name) associated with a tape drive.
 ///
 /// Returns Ok(None) if the drive has no associated changer device.
@@ -327,6 +570,7 @@ enum TapeRequestError {
     EmptyTape,
     OpenFailed(String),
     WrongLabel(String),
+    WrongMediaSet(String, String),
     ReadFailed(String),
 }
 
@@ -342,6 +586,9 @@ impl std::fmt::Display for TapeRequestError {
             TapeRequestError::WrongLabel(label) => {
                 write!(f, "wrong media label {}", label)
             }
+            TapeRequestError::WrongMediaSet(label, reason) => {
+                write!(f, "wrong media label {} - {}", label, reason)
+            }
             TapeRequestError::EmptyTape => {
                 write!(f, "found empty media without label (please label all tapes first)")
             }
@@ -352,16 +599,52 @@ impl std::fmt::Display for TapeRequestError {
     }
 }
 
+// Compare the media set label of a wrong media with the expected one, to
+// generate a more helpful message for media from the same pool.
+fn wrong_media_error(found: &MediaId, expected_set: Option<&MediaSetLabel>) -> TapeRequestError {
+    let label_string = format!(
+        "{} ({})",
+        found.label.label_text,
+        found.label.uuid.to_string(),
+    );
+
+    if let (Some(expected), Some(set)) = (expected_set, &found.media_set_label) {
+        if set.uuid == expected.uuid && set.seq_nr != expected.seq_nr {
+            return TapeRequestError::WrongMediaSet(
+                label_string,
+                format!("expected media set sequence {}, found {}", expected.seq_nr, set.seq_nr),
+            );
+        }
+        if set.pool == expected.pool && set.uuid != expected.uuid {
+            return TapeRequestError::WrongMediaSet(
+                label_string,
+                format!(
+                    "media belongs to another media set of pool '{}' ({}, sequence {})",
+                    set.pool,
+                    set.uuid.to_string(),
+                    set.seq_nr,
+                ),
+            );
+        }
+    }
+
+    TapeRequestError::WrongLabel(label_string)
+}
+
 /// Requests a specific 'media' to be inserted into 'drive'. Within a
 /// loop, this then tries to read the media label and waits until it
 /// finds the requested media.
 ///
+/// If the media is part of a media set, `expected_set` is used to
+/// report media from the same set (or pool) with a better error message.
+///
 /// Returns a handle to the opened drive and the media labels.
 pub fn request_and_load_media(
     worker: &WorkerTask,
     config: &SectionConfigData,
     drive: &str,
     label: &MediaLabel,
+    expected_set: Option<&MediaSetLabel>,
     notify_email: &Option<String>,
 ) -> Result<(
     Box<dyn TapeDriver>,
@@ -380,6 +663,8 @@ pub fn request_and_load_media(
             if media_id.label.uuid == *uuid {
                 return Ok(media_id);
             }
+
+            bail!("{}", wrong_media_error(&media_id, expected_set));
         }
         bail!("read label failed (please label all tapes first)");
     };
@@ -484,12 +769,7 @@ pub fn request_and_load_media(
                                 return Ok((Box::new(handle), media_id));
                             }
                             Ok((Some(media_id), _)) => {
-                                let label_string = format!(
-                                    "{} ({})",
-                                    media_id.label.label_text,
-                                    media_id.label.uuid.to_string(),
-                                );
-                                TapeRequestError::WrongLabel(label_string)
+                                wrong_media_error(&media_id, expected_set)
                             }
                             Ok((None, _)) => {
                                 TapeRequestError::EmptyTape
@@ -654,3 +934,40 @@ fn test_device_path_lock(device_path: &str) -> Result<bool, Error> {
 
     Ok(false)
 }
+
+#[test]
+fn test_media_encryption_state() {
+    use crate::tools::format::as_fingerprint;
+
+    let fingerprint = Fingerprint::new([1u8; 32]);
+    let label = MediaLabel {
+        uuid: proxmox::tools::Uuid::generate(),
+        label_text: "tape1".to_string(),
+        ctime: 0,
+    };
+
+    let unencrypted = MediaId {
+        label: label.clone(),
+        media_set_label: Some(MediaSetLabel::with_data("pool1", proxmox::tools::Uuid::generate(), 0, 0, None)),
+    };
+    assert_eq!(MediaEncryption::with_known_keys(&unencrypted, None, |_| false), MediaEncryption::Unencrypted);
+
+    let unassigned = MediaId { label: label.clone(), media_set_label: None };
+    assert_eq!(MediaEncryption::with_known_keys(&unassigned, None, |_| false), MediaEncryption::Unencrypted);
+
+    let encrypted = MediaId {
+        label,
+        media_set_label: Some(MediaSetLabel::with_data(
+            "pool1", proxmox::tools::Uuid::generate(), 0, 0, Some(fingerprint.clone()),
+        )),
+    };
+
+    let state = MediaEncryption::with_known_keys(&encrypted, None, |fp| fp == &fingerprint);
+    assert_eq!(state, MediaEncryption::KeyAvailable(fingerprint.clone()));
+    assert!(state.check_key_available().is_ok());
+
+    let state = MediaEncryption::with_known_keys(&encrypted, None, |_| false);
+    assert_eq!(state, MediaEncryption::KeyMissing { fingerprint: fingerprint.clone(), key_config_on_media: false });
+    let err = state.check_key_available().unwrap_err().to_string();
+    assert!(err.contains(&as_fingerprint(fingerprint.bytes())));
+}
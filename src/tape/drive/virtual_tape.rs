@@ -2,12 +2,13 @@
 
 use std::fs::File;
 use std::io;
+use std::path::{Path, PathBuf};
 
 use anyhow::{bail, format_err, Error};
 use serde::{Serialize, Deserialize};
 
 use proxmox::tools::{
-    fs::{replace_file, CreateOptions},
+    fs::{file_read_optional_string, replace_file, CreateOptions},
 };
 
 use crate::{
@@ -26,11 +27,13 @@ use crate::{
         drive::{
             VirtualTapeDrive,
             TapeDriver,
+            TapePosition,
         },
         file_formats::{
             MediaSetLabel,
             MediaContentHeader,
             PROXMOX_BACKUP_MEDIA_SET_LABEL_MAGIC_1_0,
+            PROXMOX_TAPE_BLOCK_SIZE,
             BlockedReader,
             BlockedWriter,
         },
@@ -41,8 +44,85 @@ use crate::{
     },
 };
 
+/// Errors the virtual drive can simulate
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SimulatedError {
+    /// `read_next_file` fails
+    ReadError,
+    /// `write_file` fails
+    WriteError,
+    /// `move_to_file` and `move_to_eom` fail
+    LocateError,
+    /// Opening the drive fails
+    OpenFailed,
+}
+
+/// Error simulation for the virtual drive (to test error handling)
+///
+/// This is stored in the drive directory, so it affects all users of the
+/// drive, including code which opens it by name from the drive config. The
+/// file is read once when the drive is opened.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct VirtualTapeErrorConfig {
+    /// Probability (0.0 - 1.0) for each affected operation to fail
+    pub error_rate: f64,
+    /// Operations which fail
+    pub error_types: Vec<SimulatedError>,
+    /// Seed for the random number generator. Each open starts with this
+    /// seed, so error sequences are reproducible.
+    pub seed: u64,
+}
+
+fn error_config_path(path: &Path) -> PathBuf {
+    path.join("error-config.json")
+}
+
+// xorshift64*, good enough to simulate errors
+fn next_random(state: &mut u64) -> u64 {
+    if *state == 0 { *state = 0x9E37_79B9_7F4A_7C15; }
+    *state ^= *state >> 12;
+    *state ^= *state << 25;
+    *state ^= *state >> 27;
+    state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+}
+
+fn load_error_config(path: &Path) -> Result<Option<VirtualTapeErrorConfig>, Error> {
+    match file_read_optional_string(error_config_path(path))? {
+        Some(raw) => Ok(Some(serde_json::from_str(&raw)?)),
+        None => Ok(None),
+    }
+}
+
+// returns true if the operation should fail
+fn inject_error(config: &mut Option<VirtualTapeErrorConfig>, error: SimulatedError) -> bool {
+    let config = match config {
+        Some(config) if config.error_types.contains(&error) => config,
+        _ => return false,
+    };
+
+    let random = next_random(&mut config.seed);
+    (random as f64 / u64::MAX as f64) < config.error_rate
+}
+
 impl VirtualTapeDrive {
 
+    /// Simulate errors with the given configuration
+    pub fn with_error_config(self, config: VirtualTapeErrorConfig) -> Result<Self, Error> {
+        let raw = serde_json::to_string_pretty(&config)?;
+        replace_file(error_config_path(Path::new(&self.path)), raw.as_bytes(), CreateOptions::new())?;
+        Ok(self)
+    }
+
+    /// Stop simulating errors
+    pub fn clear_error_config(&self) -> Result<(), Error> {
+        match std::fs::remove_file(error_config_path(Path::new(&self.path))) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
     /// This needs to lock the drive
     pub fn open(&self) -> Result<VirtualTapeHandle, Error> {
         proxmox::try_block!({
@@ -52,11 +132,18 @@ impl VirtualTapeDrive {
             let timeout = std::time::Duration::new(10, 0);
             let lock = proxmox::tools::fs::open_file_locked(&lock_path, timeout, true)?;
 
+            let mut error_config = load_error_config(Path::new(&self.path))?;
+            if inject_error(&mut error_config, SimulatedError::OpenFailed) {
+                bail!("injected open error");
+            }
+
             Ok(VirtualTapeHandle {
                 _lock: lock,
                 drive_name: self.name.clone(),
                 max_size: self.max_size.unwrap_or(64*1024*1024),
                 path: std::path::PathBuf::from(&self.path),
+                inject_sync_error: false,
+                error_config,
             })
         }).map_err(|err: Error| format_err!("open drive '{}' ({}) failed - {}", self.name, self.path, err))
     }
@@ -82,11 +169,18 @@ pub struct VirtualTapeHandle {
     drive_name: String,
     path: std::path::PathBuf,
     max_size: usize,
+    inject_sync_error: bool,
+    error_config: Option<VirtualTapeErrorConfig>,
     _lock: File,
 }
 
 impl VirtualTapeHandle {
 
+    /// Let all following `sync()` calls fail (to test error handling)
+    pub fn set_inject_sync_error(&mut self, inject_sync_error: bool) {
+        self.inject_sync_error = inject_sync_error;
+    }
+
     fn status_file_path(&self) -> std::path::PathBuf {
         let mut path = self.path.clone();
         path.push("drive-status.json");
@@ -234,7 +328,25 @@ impl VirtualTapeHandle {
 impl TapeDriver for VirtualTapeHandle {
 
     fn sync(&mut self) -> Result<(), Error> {
-        Ok(()) // do nothing for now
+        if self.inject_sync_error {
+            bail!("sync failed - injected IO error");
+        }
+
+        let status = self.load_status()?;
+
+        if let Some(VirtualTapeStatus { ref name, .. }) = status.current_tape {
+            // simply sync all tape files, virtual tapes are small
+            let index = self.load_tape_index(name)?;
+            for pos in 0..index.files {
+                File::open(self.tape_file_path(name, pos))?.sync_all()?;
+            }
+            File::open(self.tape_index_path(name))?.sync_all()?;
+        }
+
+        // persist directory entries (index and status files are replaced)
+        File::open(&self.path)?.sync_all()?;
+
+        Ok(())
     }
 
     fn current_file_number(&mut self) -> Result<u64, Error> {
@@ -247,6 +359,25 @@ impl TapeDriver for VirtualTapeHandle {
         }
     }
 
+    fn current_position(&mut self) -> Result<TapePosition, Error> {
+        let status = self.load_status()
+            .map_err(|err| format_err!("current_position failed: {}", err.to_string()))?;
+
+        match status.current_tape {
+            Some(VirtualTapeStatus { ref name, pos }) => {
+                // we are always at a file boundary, so count the blocks
+                // (always full size) and filemarks of all previous files
+                let mut block_number = 0;
+                for file in 0..pos {
+                    let size = std::fs::metadata(self.tape_file_path(name, file))?.len();
+                    block_number += size / (PROXMOX_TAPE_BLOCK_SIZE as u64) + 1;
+                }
+                Ok(TapePosition { file_number: pos as u64, block_number })
+            }
+            None => bail!("current_position failed: drive is empty (no tape loaded)."),
+        }
+    }
+
     /// Move to last file
     fn move_to_last_file(&mut self) -> Result<(), Error> {
 
@@ -262,6 +393,10 @@ impl TapeDriver for VirtualTapeHandle {
     }
 
     fn move_to_file(&mut self, file: u64) -> Result<(), Error> {
+        if inject_error(&mut self.error_config, SimulatedError::LocateError) {
+            bail!("locate failed - injected IO error");
+        }
+
         let mut status = self.load_status()?;
         match status.current_tape {
             Some(VirtualTapeStatus { ref name, ref mut pos }) => {
@@ -285,6 +420,10 @@ impl TapeDriver for VirtualTapeHandle {
     }
 
     fn read_next_file(&mut self) -> Result<Box<dyn TapeRead>, BlockReadError> {
+        if inject_error(&mut self.error_config, SimulatedError::ReadError) {
+            return Err(BlockReadError::Error(proxmox::io_format_err!("read failed - injected IO error")));
+        }
+
         let mut status = self.load_status()
             .map_err(|err| BlockReadError::Error(io::Error::new(io::ErrorKind::Other, err.to_string())))?;
 
@@ -318,6 +457,10 @@ impl TapeDriver for VirtualTapeHandle {
     }
 
     fn write_file(&mut self) -> Result<Box<dyn TapeWrite>, io::Error> {
+        if inject_error(&mut self.error_config, SimulatedError::WriteError) {
+            proxmox::io_bail!("write failed - injected IO error");
+        }
+
         let mut status = self.load_status()
             .map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string()))?;
 
@@ -370,6 +513,10 @@ impl TapeDriver for VirtualTapeHandle {
     }
 
     fn move_to_eom(&mut self, _write_missing_eof: bool) -> Result<(), Error> {
+        if inject_error(&mut self.error_config, SimulatedError::LocateError) {
+            bail!("move to EOM failed - injected IO error");
+        }
+
         let mut status = self.load_status()?;
         match status.current_tape {
             Some(VirtualTapeStatus { ref name, ref mut pos }) => {
@@ -447,6 +594,8 @@ impl TapeDriver for VirtualTapeHandle {
                     writer.finish(false)?;
                 }
 
+                self.sync()?; // sync data to tape
+
                 Ok(())
             }
             None => bail!("drive is empty (no tape loaded)."),
@@ -485,6 +634,7 @@ impl MediaChange for VirtualTapeHandle {
                 vendor: None,
                 model: None,
                 element_address: 0,
+                fault: None,
            });
         }
 
@@ -505,6 +655,7 @@ impl MediaChange for VirtualTapeHandle {
                 import_export: false,
                 status,
                 element_address: (i + 1) as u16,
+                fault: None,
             });
         }
 
@@ -620,3 +771,190 @@ impl MediaChange for VirtualTapeDrive {
         handle.clean_drive()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::tape::drive::AppendPosition;
+    use crate::tape::file_formats::MediaLabel;
+    use proxmox::tools::Uuid;
+
+    #[test]
+    fn test_virtual_tape_sync_errors() -> Result<(), Error> {
+        let mut path = std::env::temp_dir();
+        path.push(format!("virtual-tape-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path)?;
+
+        let drive = VirtualTapeDrive {
+            name: "test".to_string(),
+            path: path.to_string_lossy().to_string(),
+            max_size: None,
+        };
+
+        let mut handle = drive.open()?;
+        handle.load_media("tape1")?;
+
+        let label = MediaLabel {
+            uuid: Uuid::generate(),
+            label_text: "tape1".to_string(),
+            ctime: 0,
+        };
+        let set_label = MediaSetLabel::with_data("pool", Uuid::generate(), 0, 0, None);
+
+        handle.label_tape(&label)?;
+        handle.write_media_set_label(&set_label, None)?;
+        handle.sync()?;
+
+        handle.set_inject_sync_error(true);
+        assert!(handle.sync().is_err());
+        assert!(handle.label_tape(&label).is_err());
+        assert!(handle.write_media_set_label(&set_label, None).is_err());
+
+        handle.set_inject_sync_error(false);
+        handle.label_tape(&label)?;
+        let (media_id, _) = handle.read_label()?;
+        assert_eq!(media_id.unwrap().label.label_text, "tape1");
+
+        drop(handle);
+        std::fs::remove_dir_all(&path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_virtual_tape_append_position() -> Result<(), Error> {
+        let mut path = std::env::temp_dir();
+        path.push(format!("virtual-tape-append-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path)?;
+
+        let drive = VirtualTapeDrive {
+            name: "test".to_string(),
+            path: path.to_string_lossy().to_string(),
+            max_size: None,
+        };
+
+        let mut handle = drive.open()?;
+        handle.load_media("tape1")?;
+
+        let label = MediaLabel {
+            uuid: Uuid::generate(),
+            label_text: "tape1".to_string(),
+            ctime: 0,
+        };
+        let set_label = MediaSetLabel::with_data("pool", Uuid::generate(), 0, 0, None);
+
+        handle.label_tape(&label)?;
+        handle.write_media_set_label(&set_label, None)?;
+
+        let position = handle.find_append_position()?;
+        assert_eq!(position, AppendPosition { file_number: 2, partial_file: None });
+
+        // simulate an interrupted write (no end marker)
+        {
+            let data = vec![0u8; PROXMOX_TAPE_BLOCK_SIZE * 2];
+            let mut writer = handle.write_file()?;
+            writer.write_all(&data)?;
+        }
+
+        let position = handle.find_append_position()?;
+        assert_eq!(position.file_number, 3);
+        assert_eq!(position.partial_file.map(|(file, _)| file), Some(2));
+
+        // the tape is left at end of data
+        assert_eq!(handle.current_file_number()?, 3);
+
+        // overwrite the partial file
+        handle.move_to_file(2)?;
+        {
+            let mut writer = handle.write_file()?;
+            writer.write_all(b"complete")?;
+            writer.finish(false)?;
+        }
+
+        let position = handle.find_append_position()?;
+        assert_eq!(position, AppendPosition { file_number: 3, partial_file: None });
+
+        drop(handle);
+        std::fs::remove_dir_all(&path)?;
+
+        Ok(())
+    }
+
+    fn error_config(error_rate: f64, error_types: Vec<SimulatedError>) -> VirtualTapeErrorConfig {
+        VirtualTapeErrorConfig { error_rate, error_types, seed: 42 }
+    }
+
+    #[test]
+    fn test_virtual_tape_simulated_errors() -> Result<(), Error> {
+        let mut path = std::env::temp_dir();
+        path.push(format!("virtual-tape-error-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path)?;
+
+        let drive = VirtualTapeDrive {
+            name: "test".to_string(),
+            path: path.to_string_lossy().to_string(),
+            max_size: None,
+        };
+
+        let label = MediaLabel {
+            uuid: Uuid::generate(),
+            label_text: "tape1".to_string(),
+            ctime: 0,
+        };
+
+        {
+            let mut handle = drive.open()?;
+            handle.load_media("tape1")?;
+            handle.label_tape(&label)?;
+        }
+
+        let drive = drive.with_error_config(error_config(1.0, vec![SimulatedError::OpenFailed]))?;
+        assert!(drive.open().is_err());
+
+        let drive = drive.with_error_config(error_config(1.0, vec![SimulatedError::ReadError]))?;
+        let mut handle = drive.open()?;
+        assert!(handle.read_label().is_err());
+        // not affected
+        handle.rewind()?;
+        handle.move_to_eom(false)?;
+        drop(handle);
+
+        let drive = drive.with_error_config(error_config(1.0, vec![SimulatedError::WriteError]))?;
+        let mut handle = drive.open()?;
+        assert!(handle.label_tape(&label).is_err());
+        drop(handle);
+
+        let drive = drive.with_error_config(error_config(1.0, vec![SimulatedError::LocateError]))?;
+        let mut handle = drive.open()?;
+        assert!(handle.move_to_file(1).is_err());
+        assert!(handle.move_to_eom(false).is_err());
+        assert!(handle.move_to_last_file().is_err());
+        drop(handle);
+
+        // the same seed gives the same error sequence
+        let mut sequences = Vec::new();
+        for _ in 0..2 {
+            let drive = drive.with_error_config(error_config(0.5, vec![SimulatedError::LocateError]))?;
+            let mut handle = drive.open()?;
+            let sequence: Vec<bool> = (0..32).map(|_| handle.move_to_file(0).is_err()).collect();
+            sequences.push(sequence);
+        }
+        assert_eq!(sequences[0], sequences[1]);
+        assert!(sequences[0].contains(&true));
+        assert!(sequences[0].contains(&false));
+
+        drive.clear_error_config()?;
+        let mut handle = drive.open()?;
+        let (media_id, _) = handle.read_label()?;
+        assert_eq!(media_id.unwrap().label.label_text, "tape1");
+
+        drop(handle);
+        std::fs::remove_dir_all(&path)?;
+
+        Ok(())
+    }
+}
@@ -6,6 +6,7 @@ use crate::tape::{
     BlockReadError,
     file_formats::{
         PROXMOX_TAPE_BLOCK_HEADER_MAGIC_1_0,
+        PROXMOX_TAPE_COMPRESSED_BLOCK_HEADER_MAGIC_1_0,
         BlockHeader,
         BlockHeaderFlags,
     },
@@ -20,6 +21,10 @@ use crate::tape::{
 /// - check block size
 /// - check block sequence numbers
 ///
+/// Compressed blocks (see `BlockedWriter::new_compressed`) are only
+/// accepted if opened with `new_compressed`, and must not decompress to
+/// more than the block size given there.
+///
 /// The reader consumes the EOF mark after the data stream (if read to
 /// the end of the stream).
 pub struct BlockedReader<R> {
@@ -31,6 +36,10 @@ pub struct BlockedReader<R> {
     got_eod: bool,
     read_error: bool,
     read_pos: usize,
+    // maximum decompressed block size, `None` if decompression is disabled
+    max_block_size: Option<usize>,
+    // decompressed payload of the current block
+    uncompressed: Option<Vec<u8>>,
 }
 
 impl <R: BlockRead> BlockedReader<R> {
@@ -39,13 +48,35 @@ impl <R: BlockRead> BlockedReader<R> {
     ///
     /// This tries to read the first block. Please inspect the error
     /// to detect EOF and EOT.
-    pub fn open(mut reader: R) -> Result<Self, BlockReadError> {
+    pub fn open(reader: R) -> Result<Self, BlockReadError> {
+        Self::open_with_options(reader, None)
+    }
+
+    /// Create a new BlockedReader instance, which also accepts
+    /// compressed blocks.
+    ///
+    /// `block_size` needs to match the one used by the writer (see
+    /// `BlockedWriter::new_compressed`), blocks which decompress to more
+    /// data are rejected. Like `open`, this tries to read the first block.
+    pub fn new_compressed(reader: R, block_size: usize) -> Result<Self, BlockReadError> {
+        Self::open_with_options(reader, Some(block_size.max(1)))
+    }
+
+    fn open_with_options(mut reader: R, max_block_size: Option<usize>) -> Result<Self, BlockReadError> {
 
         let mut buffer = BlockHeader::new();
 
         Self::read_block_frame(&mut buffer, &mut reader)?;
 
-        let (_size, found_end_marker) = Self::check_buffer(&buffer, 0)?;
+        let (size, found_end_marker, compressed) =
+            Self::check_buffer(&buffer, 0, max_block_size.is_some())?;
+
+        let uncompressed = match (compressed, max_block_size) {
+            (true, Some(max_block_size)) => {
+                Some(Self::decompress_payload(&buffer, size, max_block_size)?)
+            }
+            _ => None,
+        };
 
         let mut incomplete = false;
         let mut got_eod = false;
@@ -65,14 +96,27 @@ impl <R: BlockRead> BlockedReader<R> {
             seq_nr: 1,
             read_error: false,
             read_pos: 0,
+            max_block_size,
+            uncompressed,
         })
     }
 
-    fn check_buffer(buffer: &BlockHeader, seq_nr: u32) -> Result<(usize, bool), std::io::Error> {
-
-        if buffer.magic != PROXMOX_TAPE_BLOCK_HEADER_MAGIC_1_0 {
+    fn check_buffer(
+        buffer: &BlockHeader,
+        seq_nr: u32,
+        decompress: bool,
+    ) -> Result<(usize, bool, bool), std::io::Error> {
+
+        let compressed = if buffer.magic == PROXMOX_TAPE_BLOCK_HEADER_MAGIC_1_0 {
+            false
+        } else if buffer.magic == PROXMOX_TAPE_COMPRESSED_BLOCK_HEADER_MAGIC_1_0 {
+            if !decompress {
+                proxmox::io_bail!("detected compressed tape block, but decompression is not enabled");
+            }
+            true
+        } else {
             proxmox::io_bail!("detected tape block with wrong magic number - not written by proxmox tape");
-        }
+        };
 
         if seq_nr != buffer.seq_nr() {
             proxmox::io_bail!(
@@ -90,7 +134,40 @@ impl <R: BlockRead> BlockedReader<R> {
         }
 
 
-        Ok((size, found_end_marker))
+        Ok((size, found_end_marker, compressed))
+    }
+
+    fn decompress_payload(
+        buffer: &BlockHeader,
+        size: usize,
+        max_block_size: usize,
+    ) -> Result<Vec<u8>, std::io::Error> {
+        let decoder = zstd::stream::read::Decoder::new(&buffer.payload[..size])
+            .map_err(|err| proxmox::io_format_err!("unable to decompress tape block - {}", err))?;
+
+        // never decompress more than one byte above the limit
+        let mut data = Vec::new();
+        decoder
+            .take(max_block_size as u64 + 1)
+            .read_to_end(&mut data)
+            .map_err(|err| proxmox::io_format_err!("unable to decompress tape block - {}", err))?;
+
+        if data.len() > max_block_size {
+            proxmox::io_bail!(
+                "detected compressed tape block exceeding the block size ({} bytes)",
+                max_block_size,
+            );
+        }
+
+        Ok(data)
+    }
+
+    // the (decompressed) data of the current block
+    fn data(&self) -> &[u8] {
+        match &self.uncompressed {
+            Some(data) => &data[..],
+            None => &self.buffer.payload[..self.buffer.size()],
+        }
     }
 
     fn read_block_frame(buffer: &mut BlockHeader, reader: &mut R) -> Result<(), BlockReadError> {
@@ -135,7 +212,7 @@ impl <R: BlockRead> BlockedReader<R> {
             Ok(()) => { /* ok */ }
             Err(BlockReadError::EndOfFile) => {
                 self.got_eod = true;
-                self.read_pos = self.buffer.payload.len();
+                self.read_pos = self.data().len();
                 if !self.found_end_marker && check_end_marker {
                     proxmox::io_bail!("detected tape stream without end marker");
                 }
@@ -149,9 +226,17 @@ impl <R: BlockRead> BlockedReader<R> {
             }
         }
 
-        let (size, found_end_marker) = Self::check_buffer(&self.buffer, self.seq_nr)?;
+        let (size, found_end_marker, compressed) =
+            Self::check_buffer(&self.buffer, self.seq_nr, self.max_block_size.is_some())?;
         self.seq_nr += 1;
 
+        self.uncompressed = match (compressed, self.max_block_size) {
+            (true, Some(max_block_size)) => {
+                Some(Self::decompress_payload(&self.buffer, size, max_block_size)?)
+            }
+            _ => None,
+        };
+
         if found_end_marker { // consume EOF mark
             self.found_end_marker = true;
             self.incomplete = self.buffer.flags.contains(BlockHeaderFlags::INCOMPLETE);
@@ -161,7 +246,7 @@ impl <R: BlockRead> BlockedReader<R> {
 
         self.read_pos = 0;
 
-        Ok(size)
+        Ok(self.data().len())
     }
 }
 
@@ -190,7 +275,7 @@ impl <R: BlockRead> TapeRead for BlockedReader<R> {
     // stream has no end marker.
     fn skip_data(&mut self) -> Result<usize, std::io::Error> {
         let mut bytes = 0;
-        let buffer_size = self.buffer.size();
+        let buffer_size = self.data().len();
         let rest = (buffer_size as isize) - (self.read_pos as isize);
         if rest > 0 {
             bytes = rest as usize;
@@ -212,7 +297,7 @@ impl <R: BlockRead> Read for BlockedReader<R> {
             proxmox::io_bail!("detected read after error - internal error");
         }
 
-        let mut buffer_size = self.buffer.size();
+        let mut buffer_size = self.data().len();
         let mut rest = (buffer_size as isize) - (self.read_pos as isize);
 
         if rest <= 0 && !self.got_eod { // try to refill buffer
@@ -235,7 +320,7 @@ impl <R: BlockRead> Read for BlockedReader<R> {
                 rest as usize
             };
             buffer[..copy_len].copy_from_slice(
-                &self.buffer.payload[self.read_pos..(self.read_pos + copy_len)]);
+                &self.data()[self.read_pos..(self.read_pos + copy_len)]);
             self.read_pos += copy_len;
             Ok(copy_len)
         }
@@ -247,6 +332,7 @@ mod test {
     use std::io::Read;
     use anyhow::{bail, Error};
     use crate::tape::{
+        TapeRead,
         TapeWrite,
         BlockReadError,
         helpers::{EmulateTapeReader, EmulateTapeWriter},
@@ -370,4 +456,94 @@ mod test {
 
         Ok(())
     }
+
+    fn write_compressed(data: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut tape_data = Vec::new();
+
+        {
+            let writer = EmulateTapeWriter::new(&mut tape_data, 1024*1024*10);
+            let mut writer = BlockedWriter::new_compressed(writer, 4*PROXMOX_TAPE_BLOCK_SIZE, 1);
+
+            writer.write_all(data)?;
+
+            writer.finish(false)?;
+        }
+
+        Ok(tape_data)
+    }
+
+    fn read_compressed(tape_data: &[u8]) -> Result<Vec<u8>, Error> {
+        let reader = &mut &tape_data[..];
+        let reader = EmulateTapeReader::new(reader);
+        let mut reader = BlockedReader::new_compressed(reader, 4*PROXMOX_TAPE_BLOCK_SIZE)?;
+
+        let mut read_data = Vec::with_capacity(PROXMOX_TAPE_BLOCK_SIZE);
+        reader.read_to_end(&mut read_data)?;
+
+        assert!(!reader.is_incomplete()?);
+
+        Ok(read_data)
+    }
+
+    #[test]
+    fn compressed_roundtrip() -> Result<(), Error> {
+        for data in [&b""[..], b"ABC"].iter() {
+            let tape_data = write_compressed(data)?;
+            assert_eq!(tape_data.len(), PROXMOX_TAPE_BLOCK_SIZE);
+            assert_eq!(read_compressed(&tape_data)?, *data);
+        }
+
+        // well compressible data needs less blocks
+        let data: Vec<u8> = (0..1024*1024*5).map(|i| (i % 251) as u8).collect();
+        let tape_data = write_compressed(&data)?;
+        assert!(tape_data.len() < data.len() / 2);
+        assert_eq!(read_compressed(&tape_data)?, data);
+
+        Ok(())
+    }
+
+    #[test]
+    fn compressed_random_data() -> Result<(), Error> {
+        // not compressible, so this falls back to uncompressed blocks
+        let data = proxmox::sys::linux::random_data(1024*1024*5)?;
+        let tape_data = write_compressed(&data)?;
+        assert_eq!(
+            tape_data.len(),
+            ((data.len() + PROXMOX_TAPE_BLOCK_SIZE)/PROXMOX_TAPE_BLOCK_SIZE)
+                *PROXMOX_TAPE_BLOCK_SIZE
+        );
+        assert_eq!(read_compressed(&tape_data)?, data);
+
+        // mixed content
+        let mut data = proxmox::sys::linux::random_data(1024*1024)?;
+        data.extend(std::iter::repeat(0u8).take(1024*1024*3));
+        data.extend(proxmox::sys::linux::random_data(1024*1024)?);
+        let tape_data = write_compressed(&data)?;
+        assert_eq!(read_compressed(&tape_data)?, data);
+
+        Ok(())
+    }
+
+    #[test]
+    fn compressed_needs_decompression() -> Result<(), Error> {
+        let tape_data = write_compressed(&vec![0u8; 1024*1024])?;
+
+        let reader = &mut &tape_data[..];
+        let reader = EmulateTapeReader::new(reader);
+        assert!(BlockedReader::open(reader).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn compressed_block_size_limit() -> Result<(), Error> {
+        let tape_data = write_compressed(&vec![0u8; 1024*1024])?;
+
+        let reader = &mut &tape_data[..];
+        let reader = EmulateTapeReader::new(reader);
+        let err = BlockedReader::new_compressed(reader, 2*PROXMOX_TAPE_BLOCK_SIZE).err().unwrap();
+        assert!(err.to_string().contains("exceeding the block size"));
+
+        Ok(())
+    }
 }
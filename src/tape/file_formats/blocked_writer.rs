@@ -4,11 +4,22 @@ use crate::tape::{
     TapeWrite,
     BlockWrite,
     file_formats::{
+        PROXMOX_TAPE_BLOCK_HEADER_MAGIC_1_0,
+        PROXMOX_TAPE_COMPRESSED_BLOCK_HEADER_MAGIC_1_0,
         BlockHeader,
         BlockHeaderFlags,
     },
 };
 
+// Software compression state
+struct BlockCompression {
+    level: i32,
+    // uncompressed data per block
+    block_size: usize,
+    // pending uncompressed data
+    data: Vec<u8>,
+}
+
 /// Assemble and write blocks of data
 ///
 /// This type implement 'TapeWrite'. Data written is assembled to
@@ -22,6 +33,7 @@ pub struct BlockedWriter<W: BlockWrite> {
     logical_end_of_media: bool,
     bytes_written: usize,
     wrote_eof: bool,
+    compression: Option<BlockCompression>,
 }
 
 impl <W: BlockWrite> Drop for BlockedWriter<W> {
@@ -51,9 +63,31 @@ impl <W: BlockWrite> BlockedWriter<W> {
             logical_end_of_media: false,
             bytes_written: 0,
             wrote_eof: false,
+            compression: None,
         }
     }
 
+    /// Creates a new instance which compresses blocks (zstd).
+    ///
+    /// Up to `block_size` bytes of input data are compressed into a
+    /// single tape block, so this should be larger than the block
+    /// payload to gain capacity. Data which does not compress well
+    /// enough is written as uncompressed blocks. Compressed blocks use
+    /// a different block header magic, so they are only accepted by
+    /// `BlockedReader::new_compressed`.
+    ///
+    /// This is only useful if the drive does not compress itself
+    /// (e.g. virtual tapes).
+    pub fn new_compressed(writer: W, block_size: usize, level: i32) -> Self {
+        let mut this = Self::new(writer);
+        this.compression = Some(BlockCompression {
+            level,
+            block_size: block_size.max(1),
+            data: Vec::with_capacity(block_size),
+        });
+        this
+    }
+
     fn write_block(buffer: &BlockHeader, writer: &mut W) -> Result<bool, std::io::Error> {
 
         let data = unsafe {
@@ -78,6 +112,16 @@ impl <W: BlockWrite> BlockedWriter<W> {
 
         if data.is_empty() { return Ok(0); }
 
+        if let Some(compression) = &mut self.compression {
+            let rest = compression.block_size - compression.data.len();
+            let bytes = if data.len() < rest { data.len() } else { rest };
+            compression.data.extend_from_slice(&data[..bytes]);
+            if compression.data.len() >= compression.block_size {
+                self.write_compressed(false, false)?;
+            }
+            return Ok(bytes);
+        }
+
         let rest = self.buffer.payload.len() - self.buffer_pos;
         let bytes = if data.len() < rest { data.len() } else { rest };
         self.buffer.payload[self.buffer_pos..(self.buffer_pos+bytes)]
@@ -102,6 +146,58 @@ impl <W: BlockWrite> BlockedWriter<W> {
         Ok(bytes)
     }
 
+    // Write pending compression data. If there is not enough room for
+    // (compressed) data, the rest stays pending, unless this writes the
+    // last blocks of the stream.
+    fn write_compressed(&mut self, end_of_stream: bool, incomplete: bool) -> Result<(), std::io::Error> {
+
+        let compression = self.compression.as_mut().unwrap();
+
+        loop {
+            let payload_len = self.buffer.payload.len();
+
+            let compressed = if compression.data.is_empty() {
+                None
+            } else {
+                Some(zstd::block::compress(&compression.data, compression.level)?)
+            };
+
+            // Note: We only use compression if result is shorter
+            let (size, consumed) = match compressed {
+                Some(compressed) if compressed.len() < compression.data.len() && compressed.len() <= payload_len => {
+                    self.buffer.magic = PROXMOX_TAPE_COMPRESSED_BLOCK_HEADER_MAGIC_1_0;
+                    self.buffer.payload[..compressed.len()].copy_from_slice(&compressed);
+                    (compressed.len(), compression.data.len())
+                }
+                _ => {
+                    let size = compression.data.len().min(payload_len);
+                    self.buffer.magic = PROXMOX_TAPE_BLOCK_HEADER_MAGIC_1_0;
+                    self.buffer.payload[..size].copy_from_slice(&compression.data[..size]);
+                    (size, size)
+                }
+            };
+            compression.data.drain(..consumed);
+            vec::clear(&mut self.buffer.payload[size..]);
+
+            let last_block = end_of_stream && compression.data.is_empty();
+
+            self.buffer.flags = BlockHeaderFlags::empty();
+            if last_block {
+                self.buffer.flags |= BlockHeaderFlags::END_OF_STREAM;
+                if incomplete { self.buffer.flags |= BlockHeaderFlags::INCOMPLETE; }
+            }
+            self.buffer.set_size(size);
+            self.buffer.set_seq_nr(self.seq_nr);
+            self.seq_nr += 1;
+            let leom = Self::write_block(&self.buffer, &mut self.writer)?;
+            if leom { self.logical_end_of_media = true; }
+            self.bytes_written += BlockHeader::SIZE;
+
+            if !end_of_stream || last_block {
+                return Ok(());
+            }
+        }
+    }
 }
 
 impl <W: BlockWrite> TapeWrite for BlockedWriter<W> {
@@ -125,6 +221,11 @@ impl <W: BlockWrite> TapeWrite for BlockedWriter<W> {
     /// Note: This may write an empty block just including the
     /// END_OF_STREAM flag.
     fn finish(&mut self, incomplete: bool) -> Result<bool, std::io::Error> {
+        if self.compression.is_some() {
+            self.write_compressed(true, incomplete)?;
+            self.write_eof()?;
+            return Ok(self.logical_end_of_media);
+        }
         vec::clear(&mut self.buffer.payload[self.buffer_pos..]);
         self.buffer.flags = BlockHeaderFlags::END_OF_STREAM;
         if incomplete { self.buffer.flags |= BlockHeaderFlags::INCOMPLETE; }
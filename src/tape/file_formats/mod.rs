@@ -38,6 +38,8 @@ pub const PROXMOX_TAPE_BLOCK_SIZE: usize = 256*1024;
 
 // openssl::sha::sha256(b"Proxmox Tape Block Header v1.0")[0..8]
 pub const PROXMOX_TAPE_BLOCK_HEADER_MAGIC_1_0: [u8; 8] = [220, 189, 175, 202, 235, 160, 165, 40];
+// openssl::sha::sha256(b"Proxmox Tape Compressed Block Header v1.0")[0..8]
+pub const PROXMOX_TAPE_COMPRESSED_BLOCK_HEADER_MAGIC_1_0: [u8; 8] = [53, 220, 193, 133, 207, 191, 18, 205];
 
 // openssl::sha::sha256(b"Proxmox Backup Content Header v1.0")[0..8];
 pub const PROXMOX_BACKUP_CONTENT_HEADER_MAGIC_1_0: [u8; 8] = [99, 238, 20, 159, 205, 242, 155, 12];
@@ -98,7 +100,9 @@ pub fn proxmox_tape_magic_to_text(magic: &[u8; 8]) -> Option<String> {
 /// error checking.
 #[repr(C,packed)]
 pub struct BlockHeader {
-    /// fixed value `PROXMOX_TAPE_BLOCK_HEADER_MAGIC_1_0`
+    /// `PROXMOX_TAPE_BLOCK_HEADER_MAGIC_1_0`, or
+    /// `PROXMOX_TAPE_COMPRESSED_BLOCK_HEADER_MAGIC_1_0` if the payload is
+    /// a zstd frame (see `BlockedWriter::new_compressed`)
     pub magic: [u8; 8],
     pub flags: BlockHeaderFlags,
     /// size as 3 bytes unsigned, little endian
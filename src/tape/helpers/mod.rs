@@ -6,3 +6,6 @@ pub use emulate_tape_reader::*;
 
 mod snapshot_reader;
 pub use snapshot_reader::*;
+
+mod std_io_adapter;
+pub use std_io_adapter::*;
@@ -0,0 +1,199 @@
+use std::io::{self, Read, Write};
+
+use crate::tape::{
+    BlockRead,
+    BlockReadError,
+    TapeWrite,
+    file_formats::PROXMOX_TAPE_BLOCK_SIZE,
+};
+
+/// Implements `std::io::Write` on top of a `TapeWrite`
+///
+/// This allows to use code expecting a normal writer. The LEOM flag
+/// returned by `TapeWrite::write_all` is recorded and can be queried
+/// with `logical_end_of_media`. Please note that `flush` does
+/// nothing, because tapes only flush their buffers when writing a
+/// filemark (use `TapeWrite::finish` for that).
+pub struct TapeWriteAdapter<'a, W: TapeWrite + ?Sized> {
+    writer: &'a mut W,
+    leom: bool,
+}
+
+impl <'a, W: TapeWrite + ?Sized> TapeWriteAdapter<'a, W> {
+
+    pub fn new(writer: &'a mut W) -> Self {
+        Self { writer, leom: false }
+    }
+
+    /// Returns true if any write reached the Logical End Of Media
+    pub fn logical_end_of_media(&self) -> bool {
+        self.leom
+    }
+}
+
+impl <'a, W: TapeWrite + ?Sized> Write for TapeWriteAdapter<'a, W> {
+
+    fn write(&mut self, buffer: &[u8]) -> Result<usize, io::Error> {
+        if buffer.is_empty() {
+            return Ok(0);
+        }
+        if self.writer.write_all(buffer)? {
+            self.leom = true;
+        }
+        Ok(buffer.len())
+    }
+
+    fn flush(&mut self) -> Result<(), io::Error> {
+        Ok(())
+    }
+}
+
+/// Implements `std::io::Read` on top of a `BlockRead`
+///
+/// Blocks are read into an internal buffer of size
+/// PROXMOX_TAPE_BLOCK_SIZE. `BlockReadError::EndOfFile` is
+/// translated into a normal EOF (read returns 0), while
+/// `BlockReadError::EndOfStream` raises an `UnexpectedEof` error,
+/// because there is no more data on the tape.
+///
+/// Note: `TapeRead` already extends `Read`, so this is only required
+/// to read the raw blocks without `BlockedReader`.
+pub struct BlockReadAdapter<R: BlockRead> {
+    reader: R,
+    buffer: Vec<u8>,
+    read_pos: usize,
+    got_eof: bool,
+}
+
+impl <R: BlockRead> BlockReadAdapter<R> {
+
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buffer: Vec::with_capacity(PROXMOX_TAPE_BLOCK_SIZE),
+            read_pos: 0,
+            got_eof: false,
+        }
+    }
+
+    /// Returns the underlying block reader
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    fn fill_buffer(&mut self) -> Result<(), io::Error> {
+        self.buffer.resize(PROXMOX_TAPE_BLOCK_SIZE, 0u8);
+        self.read_pos = 0;
+
+        match self.reader.read_block(&mut self.buffer) {
+            Ok(len) => {
+                self.buffer.truncate(len);
+                Ok(())
+            }
+            Err(err) => {
+                self.buffer.clear();
+                match err {
+                    BlockReadError::EndOfFile => {
+                        self.got_eof = true;
+                        Ok(())
+                    }
+                    BlockReadError::EndOfStream => Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "got unexpected end of data stream",
+                    )),
+                    BlockReadError::Error(err) => Err(err),
+                }
+            }
+        }
+    }
+}
+
+impl <R: BlockRead> Read for BlockReadAdapter<R> {
+
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, io::Error> {
+        if buffer.is_empty() {
+            return Ok(0);
+        }
+
+        while self.read_pos >= self.buffer.len() {
+            if self.got_eof {
+                return Ok(0);
+            }
+            self.fill_buffer()?;
+        }
+
+        let data = &self.buffer[self.read_pos..];
+        let copy_len = data.len().min(buffer.len());
+        buffer[..copy_len].copy_from_slice(&data[..copy_len]);
+        self.read_pos += copy_len;
+
+        Ok(copy_len)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::Error;
+    use crate::tape::{
+        helpers::{EmulateTapeReader, EmulateTapeWriter},
+        file_formats::{BlockedReader, BlockedWriter},
+    };
+    use super::*;
+
+    #[test]
+    fn write_adapter() -> Result<(), Error> {
+        let data = proxmox::sys::linux::random_data(1024*1024)?;
+
+        let mut tape_data = Vec::new();
+        {
+            let writer = EmulateTapeWriter::new(&mut tape_data, 1024*1024*10);
+            let mut writer = BlockedWriter::new(writer);
+
+            let mut adapter = TapeWriteAdapter::new(&mut writer);
+            std::io::copy(&mut &data[..], &mut adapter)?;
+            adapter.flush()?;
+            assert!(!adapter.logical_end_of_media());
+
+            writer.finish(false)?;
+        }
+
+        let reader = EmulateTapeReader::new(&tape_data[..]);
+        let mut reader = BlockedReader::open(reader)?;
+        let mut read_data = Vec::new();
+        reader.read_to_end(&mut read_data)?;
+
+        assert_eq!(data, read_data);
+
+        Ok(())
+    }
+
+    #[test]
+    fn read_adapter() -> Result<(), Error> {
+        let data = proxmox::sys::linux::random_data(PROXMOX_TAPE_BLOCK_SIZE*3)?;
+
+        let mut reader = BlockReadAdapter::new(EmulateTapeReader::new(&data[..]));
+        let mut read_data = Vec::new();
+        reader.read_to_end(&mut read_data)?;
+        assert_eq!(data, read_data);
+
+        // stays at EOF
+        assert_eq!(reader.read(&mut [0u8; 16])?, 0);
+
+        Ok(())
+    }
+
+    struct EndOfStreamReader;
+
+    impl BlockRead for EndOfStreamReader {
+        fn read_block(&mut self, _buffer: &mut [u8]) -> Result<usize, BlockReadError> {
+            Err(BlockReadError::EndOfStream)
+        }
+    }
+
+    #[test]
+    fn read_adapter_end_of_stream() {
+        let mut reader = BlockReadAdapter::new(EndOfStreamReader);
+        let err = reader.read(&mut [0u8; 16]).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -502,6 +502,35 @@ impl Inventory {
         next_ctime
     }
 
+    // Start times of all newer media sets (in the same pool), sorted
+    fn newer_media_set_start_times(&self, media_set_uuid: &Uuid) -> Vec<i64> {
+
+        let (pool, start_time) = match self.map.values()
+            .filter_map(|entry| entry.id.media_set_label.as_ref())
+            .find(|set| &set.uuid == media_set_uuid)
+            .map(|set| (set.pool.clone(), self.media_set_start_time(&set.uuid).unwrap_or(set.ctime)))
+        {
+            Some(info) => info,
+            None => return Vec::new(),
+        };
+
+        let mut set_start_times: HashMap<Uuid, i64> = HashMap::new();
+
+        for set in self.map.values().filter_map(|entry| entry.id.media_set_label.as_ref()) {
+            if set.pool != pool || &set.uuid == media_set_uuid || set.uuid.as_ref() == [0u8;16] {
+                continue;
+            }
+            let set_start_time = self.media_set_start_time(&set.uuid).unwrap_or(set.ctime);
+            if set_start_time > start_time {
+                set_start_times.insert(set.uuid.clone(), set_start_time);
+            }
+        }
+
+        let mut list: Vec<i64> = set_start_times.into_iter().map(|(_, time)| time).collect();
+        list.sort_unstable();
+        list
+    }
+
     pub fn media_expire_time(
         &self,
         media: &MediaId,
@@ -518,6 +547,13 @@ impl Inventory {
             Some(ref set) => set,
         };
 
+        if let RetentionPolicy::KeepMediaSets(count) = retention_policy {
+            // expires when the set is no longer one of the newest 'count' sets
+            let newer = self.newer_media_set_start_times(&set.uuid);
+            let count = (*count as usize).max(1);
+            return newer.get(count - 1).copied().unwrap_or(i64::MAX);
+        }
+
         let set_start_time = match self.media_set_start_time(&set.uuid) {
             None => {
                 // missing information, use ctime from this
@@ -554,6 +590,7 @@ impl Inventory {
 
         match retention_policy {
             RetentionPolicy::KeepForever => i64::MAX,
+            RetentionPolicy::KeepMediaSets(_) => i64::MAX, // handled above
             RetentionPolicy::OverwriteAlways => max_use_time,
             RetentionPolicy::ProtectFor(time_span) => {
                 let seconds = f64::from(time_span.clone()) as i64;
@@ -347,6 +347,22 @@ impl MediaPool {
         current_time >= expire_time
     }
 
+    /// List all media with expired data (at the specified time)
+    ///
+    /// Media from the current media set is never considered expired.
+    /// Those media get reused (overwritten) by `alloc_writable_media`
+    /// when the pool has no empty media left.
+    pub fn list_expired_media(&self, current_time: i64) -> Vec<BackupMedia> {
+        self.list_media()
+            .into_iter()
+            .filter(|media| match media.media_set_label() {
+                Some(set) => &set.uuid != self.current_media_set.uuid(),
+                None => false,
+            })
+            .filter(|media| self.media_is_expired(media, current_time))
+            .collect()
+    }
+
     // check if a location is considered on site
     pub fn location_is_available(&self, location: &MediaLocation) -> bool {
         match location {
@@ -0,0 +1,191 @@
+//! Media state database - changer move history
+//!
+//! Media moves (slot to slot, load and unload) are appended to a JSON
+//! lines file inside the tape status directory, so that it is possible
+//! to track where a cartridge has been. Once the file grows beyond
+//! `MAX_HISTORY_SIZE`, it is rotated, keeping one old file.
+//!
+//! Additionally, the number of media loads per drive is counted in a
+//! separate file, which is never truncated (used for metrics).
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+use anyhow::{format_err, Error};
+
+use proxmox::tools::fs::{
+    file_read_optional_string, open_file_locked, replace_file, fchown, CreateOptions,
+};
+
+use crate::api2::types::ChangerMoveEvent;
+
+/// Changer move history, stored in the tape status directory
+pub struct MediaStateDatabase {
+    history_path: PathBuf,
+    rotated_history_path: PathBuf,
+    load_counts_path: PathBuf,
+    lockfile_path: PathBuf,
+}
+
+impl MediaStateDatabase {
+
+    pub const CHANGER_HISTORY_FILENAME: &'static str = "changer-history.json";
+    pub const CHANGER_HISTORY_ROTATED_FILENAME: &'static str = "changer-history.json.1";
+    pub const CHANGER_HISTORY_LOCKFILE: &'static str = ".changer-history.lck";
+    pub const DRIVE_LOAD_COUNTS_FILENAME: &'static str = "drive-load-counts.json";
+
+    /// History file size which triggers log rotation (about 5000 events)
+    pub const MAX_HISTORY_SIZE: u64 = 1024*1024;
+
+    pub fn new(base_path: &Path) -> Self {
+        let mut history_path = base_path.to_owned();
+        history_path.push(Self::CHANGER_HISTORY_FILENAME);
+
+        let mut rotated_history_path = base_path.to_owned();
+        rotated_history_path.push(Self::CHANGER_HISTORY_ROTATED_FILENAME);
+
+        let mut load_counts_path = base_path.to_owned();
+        load_counts_path.push(Self::DRIVE_LOAD_COUNTS_FILENAME);
+
+        let mut lockfile_path = base_path.to_owned();
+        lockfile_path.push(Self::CHANGER_HISTORY_LOCKFILE);
+
+        Self { history_path, rotated_history_path, load_counts_path, lockfile_path }
+    }
+
+    fn lock(&self) -> Result<std::fs::File, Error> {
+        let file = open_file_locked(&self.lockfile_path, std::time::Duration::new(10, 0), true)?;
+        if cfg!(test) {
+            // We cannot use chown inside test environment (no permissions)
+            return Ok(file);
+        }
+
+        let backup_user = crate::backup::backup_user()?;
+        fchown(file.as_raw_fd(), Some(backup_user.uid), Some(backup_user.gid))?;
+
+        Ok(file)
+    }
+
+    fn create_options() -> Result<CreateOptions, Error> {
+        let mode = nix::sys::stat::Mode::from_bits_truncate(0o0640);
+        let options = if cfg!(test) {
+            // We cannot use chown inside test environment (no permissions)
+            CreateOptions::new().perm(mode)
+        } else {
+            let backup_user = crate::backup::backup_user()?;
+            CreateOptions::new()
+                .perm(mode)
+                .owner(backup_user.uid)
+                .group(backup_user.gid)
+        };
+        Ok(options)
+    }
+
+    // oldest first, including the rotated file
+    fn load_history(&self) -> Result<Vec<ChangerMoveEvent>, Error> {
+        let mut list = Vec::new();
+        Self::load_history_file(&self.rotated_history_path, &mut list)?;
+        Self::load_history_file(&self.history_path, &mut list)?;
+        Ok(list)
+    }
+
+    fn load_history_file(path: &Path, list: &mut Vec<ChangerMoveEvent>) -> Result<(), Error> {
+        let file = match std::fs::File::open(path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+            match serde_json::from_str(&line) {
+                Ok(event) => list.push(event),
+                // skip damaged lines (e.g. partially written after a crash)
+                Err(err) => log::warn!("skip invalid changer history entry in {:?} - {}", path, err),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Number of media loads per drive
+    ///
+    /// The counters are never reset, so they can be exported as metric counters.
+    pub fn drive_load_counts(&self) -> Result<HashMap<String, u64>, Error> {
+        match file_read_optional_string(&self.load_counts_path)? {
+            Some(data) => Ok(serde_json::from_str(&data)?),
+            None => Ok(HashMap::new()),
+        }
+    }
+
+    fn increment_load_count_locked(&self, drive: &str) -> Result<(), Error> {
+        let mut counts = self.drive_load_counts()?;
+        *counts.entry(drive.to_string()).or_insert(0) += 1;
+        let data = serde_json::to_string(&counts)?;
+        replace_file(&self.load_counts_path, data.as_bytes(), Self::create_options()?)
+    }
+
+    /// Append a move event to the history
+    ///
+    /// Loads (slot to drive) are also counted, see `drive_load_counts`.
+    pub fn record_move(&self, event: ChangerMoveEvent) -> Result<(), Error> {
+        let _lock = self.lock()?;
+
+        if let (Some(drive), Some(_)) = (&event.drive, event.from_slot) {
+            self.increment_load_count_locked(drive)?;
+        }
+
+        let mut line = serde_json::to_string(&event)?;
+        line.push('\n');
+
+        if !self.history_path.exists() {
+            // create with correct owner/permissions
+            replace_file(&self.history_path, &[], Self::create_options()?)?;
+        }
+
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&self.history_path)
+            .map_err(|err| format_err!("unable to open {:?} - {}", self.history_path, err))?;
+
+        file.write_all(line.as_bytes())?;
+        let size = file.metadata()?.len();
+        drop(file);
+
+        if size > Self::MAX_HISTORY_SIZE {
+            // replaces the previously rotated file, keeps owner and permissions
+            std::fs::rename(&self.history_path, &self.rotated_history_path).map_err(|err| {
+                format_err!("unable to rotate {:?} - {}", self.history_path, err)
+            })?;
+        }
+
+        Ok(())
+    }
+
+    /// List recorded move events, newest first
+    ///
+    /// Optionally filtered by changer and start time (`since`, epoch).
+    pub fn list_moves(
+        &self,
+        changer: Option<&str>,
+        since: Option<i64>,
+        limit: Option<usize>,
+    ) -> Result<Vec<ChangerMoveEvent>, Error> {
+        let list = self.load_history()?;
+
+        let list = list
+            .into_iter()
+            .rev()
+            .filter(|event| changer.map(|changer| event.changer == changer).unwrap_or(true))
+            .filter(|event| since.map(|since| event.timestamp >= since).unwrap_or(true))
+            .take(limit.unwrap_or(usize::MAX))
+            .collect();
+
+        Ok(list)
+    }
+}
@@ -27,6 +27,9 @@ pub use media_set::*;
 mod inventory;
 pub use inventory::*;
 
+mod media_state_database;
+pub use media_state_database::*;
+
 mod linux_list_drives;
 pub use linux_list_drives::*;
 
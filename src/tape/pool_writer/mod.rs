@@ -15,6 +15,7 @@ use proxmox::tools::Uuid;
 
 use crate::{
     task_log,
+    task_warn,
     backup::{
         DataStore,
     },
@@ -125,6 +126,21 @@ impl PoolWriter {
         self.catalog_set.lock().unwrap().contains_snapshot(store, snapshot)
     }
 
+    /// Log a warning if the drive requests a cleaning cycle
+    ///
+    /// Errors reading the tape alert flags are ignored.
+    pub fn warn_if_drive_needs_cleaning(&mut self, worker: &WorkerTask) {
+        if let Some(PoolWriterState { ref mut drive, .. }) = self.status {
+            if let Ok(true) = drive.drive_needs_cleaning() {
+                task_warn!(
+                    worker,
+                    "WARNING: drive '{}' requests cleaning - please run a cleaning cycle",
+                    self.drive_name,
+                );
+            }
+        }
+    }
+
     /// Eject media and drop PoolWriterState (close drive)
     pub fn eject_media(&mut self, worker: &WorkerTask) -> Result<(), Error> {
         let mut status = match self.status.take() {
@@ -228,7 +244,7 @@ impl PoolWriter {
         let (drive_config, _digest) = crate::config::drive::config()?;
 
         let (mut drive, old_media_id) =
-            request_and_load_media(worker, &drive_config, &self.drive_name, media.label(), &self.notify_email)?;
+            request_and_load_media(worker, &drive_config, &self.drive_name, media.label(), None, &self.notify_email)?;
 
         // test for critical tape alert flags
         if let Ok(alert_flags) = drive.tape_alert_flags() {
@@ -352,17 +368,9 @@ impl PoolWriter {
 
         let seq_nr = media_list.len() - 1;
 
-        let mut writer: Box<dyn TapeWrite> = status.drive.write_file()?;
-
         let mut file = Self::open_catalog_file(uuid)?;
 
-        let done = tape_write_catalog(
-            writer.as_mut(),
-            uuid,
-            media_set.uuid(),
-            seq_nr,
-            &mut file,
-        )?.is_some();
+        let done = status.drive.write_catalog(uuid, media_set.uuid(), seq_nr, &mut file)?;
 
         Ok(done)
     }
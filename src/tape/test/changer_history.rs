@@ -0,0 +1,128 @@
+// Changer move history tests
+//
+// # cargo test --release tape::test::changer_history
+
+use std::path::PathBuf;
+use anyhow::Error;
+
+use crate::{
+    api2::types::{
+        Authid,
+        ChangerMoveEvent,
+    },
+    tape::MediaStateDatabase,
+};
+
+fn create_testdir(name: &str) -> Result<PathBuf, Error> {
+    let mut testdir: PathBuf = String::from("./target/testout").into();
+    testdir.push(std::module_path!());
+    testdir.push(name);
+
+    let _ = std::fs::remove_dir_all(&testdir);
+    let _ = std::fs::create_dir_all(&testdir);
+
+    Ok(testdir)
+}
+
+fn move_event(timestamp: i64, changer: &str, label: &str) -> ChangerMoveEvent {
+    ChangerMoveEvent {
+        timestamp,
+        changer: changer.to_string(),
+        media_label: label.to_string(),
+        from_slot: Some(1),
+        to_slot: None,
+        drive: Some(String::from("drive0")),
+        operator_auth_id: None,
+    }
+}
+
+#[test]
+fn test_changer_history_persistence() -> Result<(), Error> {
+
+    let testdir = create_testdir("test_changer_history_persistence")?;
+
+    let db = MediaStateDatabase::new(&testdir);
+    assert!(db.list_moves(None, None, None)?.is_empty());
+
+    let mut event = move_event(1000, "changer1", "tape1");
+    event.operator_auth_id = Some(Authid::root_auth_id().clone());
+    db.record_move(event.clone())?;
+    db.record_move(move_event(2000, "changer2", "tape2"))?;
+    db.record_move(move_event(3000, "changer1", "tape3"))?;
+    drop(db);
+
+    // simulate a restart
+    let db = MediaStateDatabase::new(&testdir);
+
+    let list = db.list_moves(None, None, None)?;
+    assert_eq!(list.len(), 3);
+    assert_eq!(list[0].media_label, "tape3"); // newest first
+    assert_eq!(list[2], event);
+
+    let list = db.list_moves(Some("changer1"), None, None)?;
+    assert_eq!(list.iter().map(|e| e.timestamp).collect::<Vec<_>>(), vec![3000, 1000]);
+
+    let list = db.list_moves(Some("changer1"), Some(2000), None)?;
+    assert_eq!(list.len(), 1);
+
+    let list = db.list_moves(None, None, Some(2))?;
+    assert_eq!(list.iter().map(|e| e.timestamp).collect::<Vec<_>>(), vec![3000, 2000]);
+
+    // all events above are loads into drive0, unloads are not counted
+    let mut unload = move_event(4000, "changer1", "tape3");
+    unload.from_slot = None;
+    unload.to_slot = Some(1);
+    db.record_move(unload)?;
+    assert_eq!(db.drive_load_counts()?.get("drive0"), Some(&3));
+
+    Ok(())
+}
+
+#[test]
+fn test_changer_history_rotation() -> Result<(), Error> {
+
+    let testdir = create_testdir("test_changer_history_rotation")?;
+
+    let history_path = testdir.join(MediaStateDatabase::CHANGER_HISTORY_FILENAME);
+    let rotated_path = testdir.join(MediaStateDatabase::CHANGER_HISTORY_ROTATED_FILENAME);
+
+    // pre-fill the history up to the size limit, starting at `start`
+    let fill_history = |start: usize| -> Result<usize, Error> {
+        let mut data = String::new();
+        let mut count = 0;
+        while data.len() as u64 <= MediaStateDatabase::MAX_HISTORY_SIZE {
+            data.push_str(&serde_json::to_string(&move_event((start + count) as i64, "changer1", "tape1"))?);
+            data.push('\n');
+            count += 1;
+        }
+        std::fs::write(&history_path, data)?;
+        Ok(count)
+    };
+
+    let count = fill_history(0)?;
+
+    let db = MediaStateDatabase::new(&testdir);
+    db.record_move(move_event(count as i64, "changer1", "tape1"))?;
+
+    // rotated, nothing lost
+    assert!(rotated_path.exists());
+    assert!(!history_path.exists());
+    let list = db.list_moves(None, None, None)?;
+    assert_eq!(list.len(), count + 1);
+    assert_eq!(list[0].timestamp, count as i64);
+
+    // appending goes to a new file
+    db.record_move(move_event(count as i64 + 1, "changer1", "tape1"))?;
+    assert_eq!(db.list_moves(None, None, Some(2))?[1].timestamp, count as i64);
+
+    // the second rotation drops the oldest events
+    let start = count + 2;
+    let count2 = fill_history(start)?;
+    db.record_move(move_event((start + count2) as i64, "changer1", "tape1"))?;
+
+    let list = db.list_moves(None, None, None)?;
+    assert_eq!(list.len(), count2 + 1);
+    assert_eq!(list.last().unwrap().timestamp, start as i64);
+
+    Ok(())
+}
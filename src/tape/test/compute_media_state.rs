@@ -2,6 +2,7 @@
 //
 // # cargo test --release tape::test::compute_media_state
 
+use std::collections::HashSet;
 use std::path::PathBuf;
 use anyhow::Error;
 
@@ -138,3 +139,112 @@ fn test_media_expire_time() -> Result<(), Error> {
 
     Ok(())
 }
+
+#[test]
+fn test_media_expire_keep_media_sets() -> Result<(), Error> {
+
+    let testdir = create_testdir("test_media_expire_keep_media_sets")?;
+
+    let mut inventory = Inventory::load(&testdir)?;
+
+    // tape0: single tape media set
+    let sl0 = MediaSetLabel::with_data("p1", Uuid::generate(), 0, 0, None);
+    let tape0_uuid = inventory.generate_used_tape("tape0", sl0, 0);
+
+    // tape1, tape2: two tape media set
+    let sl1 = MediaSetLabel::with_data("p1", Uuid::generate(), 0, 60, None);
+    let sl2 = MediaSetLabel::with_data("p1", sl1.uuid.clone(), 1, 70, None);
+    let tape1_uuid = inventory.generate_used_tape("tape1", sl1, 0);
+    let tape2_uuid = inventory.generate_used_tape("tape2", sl2, 0);
+
+    // tape3: other pool, does not count
+    let sl3 = MediaSetLabel::with_data("p2", Uuid::generate(), 0, 90, None);
+    inventory.generate_used_tape("tape3", sl3, 0);
+
+    // tape4: current media set
+    let sl4 = MediaSetLabel::with_data("p1", Uuid::generate(), 0, 120, None);
+    let tape4_uuid = inventory.generate_used_tape("tape4", sl4, 0);
+
+    let pool = MediaPool::new(
+        "p1",
+        &testdir,
+        MediaSetPolicy::AlwaysCreate,
+        "sets:2".parse()?,
+        None,
+        None,
+        false,
+    )?;
+
+    // tape0 expires when the third set gets started
+    assert_eq!(pool.media_is_expired(&pool.lookup_media(&tape0_uuid)?, 119), false);
+    assert_eq!(pool.media_is_expired(&pool.lookup_media(&tape0_uuid)?, 120), true);
+
+    // the second set (and all its tapes) is still kept
+    assert_eq!(pool.media_is_expired(&pool.lookup_media(&tape1_uuid)?, 1000), false);
+    assert_eq!(pool.media_is_expired(&pool.lookup_media(&tape2_uuid)?, 1000), false);
+
+    let expired: Vec<Uuid> = pool.list_expired_media(1000)
+        .iter()
+        .map(|media| media.uuid().clone())
+        .collect();
+    assert_eq!(expired, vec![tape0_uuid.clone()]);
+
+    // the current set is never expired
+    assert_eq!(pool.media_is_expired(&pool.lookup_media(&tape4_uuid)?, 1000), false);
+
+    assert!("sets:0".parse::<RetentionPolicy>().is_err());
+    assert!("sets:abc".parse::<RetentionPolicy>().is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_list_expired_media() -> Result<(), Error> {
+
+    let testdir = create_testdir("test_list_expired_media")?;
+
+    let mut inventory = Inventory::load(&testdir)?;
+
+    let sl0 = MediaSetLabel::with_data("p1", Uuid::generate(), 0, 0, None);
+    let tape0_uuid = inventory.generate_used_tape("tape0", sl0, 0);
+
+    let sl1 = MediaSetLabel::with_data("p1", Uuid::generate(), 0, 60, None);
+    let tape1_uuid = inventory.generate_used_tape("tape1", sl1, 0);
+
+    let sl2 = MediaSetLabel::with_data("p1", Uuid::generate(), 0, 120, None);
+    inventory.generate_used_tape("tape2", sl2, 0);
+
+    let list_expired = |retention: &str, current_time: i64| -> Result<HashSet<Uuid>, Error> {
+        let pool = MediaPool::new(
+            "p1",
+            &testdir,
+            MediaSetPolicy::AlwaysCreate,
+            retention.parse()?,
+            None,
+            None,
+            false,
+        )?;
+        Ok(pool.list_expired_media(current_time)
+            .iter()
+            .map(|media| media.uuid().clone())
+            .collect())
+    };
+
+    let none = HashSet::new();
+    let only_tape0: HashSet<Uuid> = vec![tape0_uuid.clone()].into_iter().collect();
+    let all: HashSet<Uuid> = vec![tape0_uuid, tape1_uuid].into_iter().collect();
+
+    assert_eq!(list_expired("keep", 100_000)?, none);
+
+    assert_eq!(list_expired("overwrite", 100_000)?, all);
+
+    assert_eq!(list_expired("1 min", 59)?, none);
+    assert_eq!(list_expired("1 min", 60)?, only_tape0);
+    assert_eq!(list_expired("1 min", 120)?, all);
+
+    assert_eq!(list_expired("sets:1", 100_000)?, all);
+    assert_eq!(list_expired("sets:2", 100_000)?, only_tape0);
+    assert_eq!(list_expired("sets:3", 100_000)?, none);
+
+    Ok(())
+}
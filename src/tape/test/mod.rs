@@ -3,3 +3,5 @@ mod inventory;
 mod current_set_usable;
 mod compute_media_state;
 mod alloc_writable_media;
+mod changer_history;
+mod virtual_tape_errors;
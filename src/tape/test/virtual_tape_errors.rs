@@ -0,0 +1,169 @@
+// Tape backup/restore with simulated drive errors
+//
+// # cargo test --release tape::test::virtual_tape_errors
+
+use std::path::PathBuf;
+use anyhow::{bail, Error};
+
+use proxmox::tools::{
+    Uuid,
+    io::ReadExt,
+};
+
+use crate::{
+    api2::types::VirtualTapeDrive,
+    backup::DataBlob,
+    tape::{
+        BlockReadError,
+        changer::MediaChange,
+        drive::{
+            AppendPosition,
+            SimulatedError,
+            TapeDriver,
+            VirtualTapeErrorConfig,
+        },
+        file_formats::{
+            ChunkArchiveDecoder,
+            ChunkArchiveWriter,
+            MediaContentHeader,
+            MediaLabel,
+            MediaSetLabel,
+            PROXMOX_BACKUP_CONTENT_HEADER_MAGIC_1_0,
+        },
+    },
+};
+
+fn create_testdir(name: &str) -> Result<PathBuf, Error> {
+    let mut testdir: PathBuf = String::from("./target/testout").into();
+    testdir.push(std::module_path!());
+    testdir.push(name);
+
+    let _ = std::fs::remove_dir_all(&testdir);
+    let _ = std::fs::create_dir_all(&testdir);
+
+    Ok(testdir)
+}
+
+fn error_config(error_type: SimulatedError) -> VirtualTapeErrorConfig {
+    VirtualTapeErrorConfig { error_rate: 1.0, error_types: vec![error_type], seed: 42 }
+}
+
+fn test_chunks() -> Result<Vec<([u8; 32], DataBlob)>, Error> {
+    let mut chunks = Vec::new();
+    for i in 0..4u8 {
+        let data = vec![i; 64*1024];
+        let digest = openssl::sha::sha256(&data);
+        chunks.push((digest, DataBlob::encode(&data, None, true)?));
+    }
+    Ok(chunks)
+}
+
+fn write_chunk_archive(
+    drive: &mut dyn TapeDriver,
+    chunks: &[([u8; 32], DataBlob)],
+) -> Result<(), Error> {
+    let writer = drive.write_file()?;
+    let (mut archive, _uuid) = ChunkArchiveWriter::new(writer, "store1", false)?;
+    for (digest, blob) in chunks {
+        if !archive.try_write_chunk(digest, blob)? {
+            bail!("unexpected end of media");
+        }
+    }
+    archive.finish()?;
+    Ok(())
+}
+
+fn read_chunk_archive(drive: &mut dyn TapeDriver) -> Result<Vec<[u8; 32]>, Error> {
+    let mut reader = drive.read_next_file()?;
+
+    let header: MediaContentHeader = unsafe { reader.read_le_value()? };
+    if header.magic != PROXMOX_BACKUP_CONTENT_HEADER_MAGIC_1_0
+        || header.content_magic != ChunkArchiveWriter::MAGIC
+    {
+        bail!("not a chunk archive");
+    }
+    reader.read_exact_allocated(header.size as usize)?;
+
+    let mut decoder = ChunkArchiveDecoder::new(reader);
+    let mut digests = Vec::new();
+    while let Some((digest, blob)) = decoder.next_chunk()? {
+        blob.decode(None, Some(&digest))?;
+        digests.push(digest);
+    }
+    Ok(digests)
+}
+
+#[test]
+fn test_virtual_tape_backup_restore_errors() -> Result<(), Error> {
+    let testdir = create_testdir("test_virtual_tape_backup_restore_errors")?;
+
+    let drive = VirtualTapeDrive {
+        name: "test".to_string(),
+        path: testdir.to_string_lossy().to_string(),
+        max_size: None,
+    };
+
+    let label = MediaLabel {
+        uuid: Uuid::generate(),
+        label_text: "tape1".to_string(),
+        ctime: 0,
+    };
+    let set_label = MediaSetLabel::with_data("pool", Uuid::generate(), 0, 0, None);
+
+    {
+        let mut handle = drive.open()?;
+        handle.load_media("tape1")?;
+        handle.label_tape(&label)?;
+        handle.write_media_set_label(&set_label, None)?;
+    }
+
+    let chunks = test_chunks()?;
+    let digests: Vec<[u8; 32]> = chunks.iter().map(|(digest, _)| *digest).collect();
+
+    // backup: a failed write must not leave anything on the media
+    let drive = drive.with_error_config(error_config(SimulatedError::WriteError))?;
+    let mut handle = drive.open()?;
+    handle.move_to_eom(false)?;
+    assert!(write_chunk_archive(&mut handle, &chunks).is_err());
+    drive.clear_error_config()?;
+    // the config is read on open, so the open handle still fails
+    assert!(write_chunk_archive(&mut handle, &chunks).is_err());
+    drop(handle);
+
+    let mut handle = drive.open()?;
+    assert_eq!(handle.find_append_position()?, AppendPosition { file_number: 2, partial_file: None });
+    write_chunk_archive(&mut handle, &chunks)?;
+    assert_eq!(handle.find_append_position()?, AppendPosition { file_number: 3, partial_file: None });
+    drop(handle);
+
+    // restore: read errors are reported as errors, not as end of data,
+    // and do not move the tape, so a retry reads the same archive
+    let drive = drive.with_error_config(error_config(SimulatedError::ReadError))?;
+    let mut handle = drive.open()?;
+    handle.move_to_file(2)?;
+    match handle.read_next_file() {
+        Err(BlockReadError::Error(_)) => {}
+        Err(err) => panic!("expected an IO error, got: {}", err),
+        Ok(_) => panic!("read did not fail"),
+    }
+    assert_eq!(handle.current_file_number()?, 2);
+    drop(handle);
+
+    let drive = drive.with_error_config(error_config(SimulatedError::LocateError))?;
+    let mut handle = drive.open()?;
+    handle.rewind()?;
+    assert!(handle.move_to_file(2).is_err());
+    assert_eq!(handle.current_file_number()?, 0);
+    drop(handle);
+
+    drive.clear_error_config()?;
+    let mut handle = drive.open()?;
+    handle.move_to_file(2)?;
+    assert_eq!(read_chunk_archive(&mut handle)?, digests);
+    match handle.read_next_file() {
+        Err(BlockReadError::EndOfStream) => {}
+        _ => panic!("expected end of data"),
+    }
+
+    Ok(())
+}
@@ -6,13 +6,16 @@ use std::borrow::Borrow;
 use std::collections::HashMap;
 use std::hash::BuildHasher;
 use std::fs::File;
-use std::io::{self, BufRead, Read, Seek, SeekFrom};
+use std::io::{self, BufRead, Read, Seek, SeekFrom, Write};
 use std::os::unix::io::RawFd;
 use std::path::Path;
+use std::time::Duration;
 
 use anyhow::{bail, format_err, Error};
 use serde_json::Value;
 use openssl::hash::{hash, DigestBytes, MessageDigest};
+use openssl::ssl::{SslConnector, SslMethod, SslVerifyMode};
+use openssl::x509::X509Ref;
 use percent_encoding::{utf8_percent_encode, AsciiSet};
 
 pub use proxmox::tools::fd::Fd;
@@ -79,6 +82,9 @@ pub use file_logger::{FileLogger, FileLogOptions};
 mod broadcast_future;
 pub use broadcast_future::{BroadcastData, BroadcastFuture};
 
+mod rate_limiter;
+pub use rate_limiter::RateLimiter;
+
 /// The `BufferedRead` trait provides a single function
 /// `buffered_read`. It returns a reference to an internal buffer. The
 /// purpose of this traid is to avoid unnecessary data copies.
@@ -251,10 +257,7 @@ where
 {
     for entry in self::fs::scan_subdir(dirfd, path, regex)? {
         let entry = entry?;
-        let file_type = match entry.file_type() {
-            Some(file_type) => file_type,
-            None => bail!("unable to detect file type"),
-        };
+        let file_type = entry.resolve_file_type()?;
 
         callback(
             entry.parent_fd(),
@@ -421,6 +424,121 @@ pub fn run_command(
     Ok(output)
 }
 
+/// Time between `SIGTERM` and `SIGKILL` for commands which timed out
+pub const COMMAND_KILL_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+/// Error returned by `run_command_with_timeout` if the command did not finish in time.
+///
+/// Use `Error::downcast_ref` to check for it.
+#[derive(Debug)]
+pub struct CommandTimeout {
+    pub command: String,
+    pub timeout: Duration,
+}
+
+impl std::fmt::Display for CommandTimeout {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "command {} timed out after {} seconds", self.command, self.timeout.as_secs_f64())
+    }
+}
+
+impl std::error::Error for CommandTimeout {}
+
+/// Like `run_command`, but terminates the command if it does not finish within `timeout`.
+///
+/// The command runs in its own process group, which gets a `SIGTERM` after `timeout`, and
+/// a `SIGKILL` if it is still running `COMMAND_KILL_GRACE_PERIOD` later. The error returned
+/// in that case is a `CommandTimeout`.
+pub fn run_command_with_timeout(
+    command: std::process::Command,
+    exit_code_check: Option<fn(i32) -> bool>,
+    timeout: Duration,
+) -> Result<String, Error> {
+    run_command_with_timeout_impl(command, None, exit_code_check, Some(timeout))
+}
+
+/// Like `run_command`, but writes `input` to the standard input of the command.
+///
+/// Commands which modify something should not get a `timeout` - killing them halfway
+/// through is usually worse than waiting.
+pub fn run_command_with_input(
+    command: std::process::Command,
+    input: Vec<u8>,
+    exit_code_check: Option<fn(i32) -> bool>,
+    timeout: Option<Duration>,
+) -> Result<String, Error> {
+    run_command_with_timeout_impl(command, Some(input), exit_code_check, timeout)
+}
+
+fn run_command_with_timeout_impl(
+    mut command: std::process::Command,
+    input: Option<Vec<u8>>,
+    exit_code_check: Option<fn(i32) -> bool>,
+    timeout: Option<Duration>,
+) -> Result<String, Error> {
+    use std::os::unix::process::CommandExt;
+    use std::process::Stdio;
+    use std::sync::mpsc::RecvTimeoutError;
+
+    use nix::sys::signal::{killpg, Signal};
+
+    command
+        .stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    // so that we can also terminate processes started by the command
+    unsafe {
+        command.pre_exec(|| {
+            if libc::setpgid(0, 0) == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+
+    let mut child = command.spawn()
+        .map_err(|err| format_err!("failed to execute {:?} - {}", command, err))?;
+
+    let pgid = nix::unistd::Pid::from_raw(child.id() as libc::pid_t);
+
+    let stdin = child.stdin.take();
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        if let (Some(mut stdin), Some(input)) = (stdin, input) {
+            // a command exiting early shows up in its exit status, so ignore EPIPE here
+            let _ = stdin.write_all(&input);
+        }
+        let _ = sender.send(child.wait_with_output());
+    });
+
+    let output = match timeout {
+        None => receiver.recv()
+            .map_err(|_| format_err!("command {:?} failed - wait thread died", command))?,
+        Some(timeout) => match receiver.recv_timeout(timeout) {
+            Ok(output) => output,
+            Err(RecvTimeoutError::Disconnected) => bail!("command {:?} failed - wait thread died", command),
+            Err(RecvTimeoutError::Timeout) => {
+                let _ = killpg(pgid, Signal::SIGTERM);
+                if receiver.recv_timeout(COMMAND_KILL_GRACE_PERIOD).is_err() {
+                    let _ = killpg(pgid, Signal::SIGKILL);
+                    let _ = receiver.recv();
+                }
+                return Err(CommandTimeout { command: format!("{:?}", command), timeout }.into());
+            }
+        },
+    };
+
+    let output = output
+        .map_err(|err| format_err!("failed to execute {:?} - {}", command, err))?;
+
+    let output = command_output_as_string(output, exit_code_check)
+        .map_err(|err| format_err!("command {:?} failed - {}", command, err))?;
+
+    Ok(output)
+}
+
 pub fn fd_change_cloexec(fd: RawFd, on: bool) -> Result<(), Error> {
     use nix::fcntl::{fcntl, FdFlag, F_GETFD, F_SETFD};
     let mut flags = FdFlag::from_bits(fcntl(fd, F_GETFD)?)
@@ -501,6 +619,451 @@ pub fn pbs_simple_http(proxy_config: Option<ProxyConfig>) -> SimpleHttp {
     SimpleHttp::with_options(options)
 }
 
+/// Returns a new instance of `SimpleHttp` which only accepts a server certificate with the
+/// given SHA-256 fingerprint (`xx:xx:...`).
+///
+/// This allows talking to services with self-signed certificates without disabling the
+/// verification. Only the leaf certificate is pinned, a trusted CA chain is not required.
+pub fn pbs_simple_http_pinned(
+    proxy_config: Option<ProxyConfig>,
+    fingerprint: &str,
+) -> Result<SimpleHttp, Error> {
+    let options = SimpleHttpOptions {
+        proxy_config,
+        user_agent: Some(DEFAULT_USER_AGENT_STRING.to_string()),
+        tcp_keepalive: Some(PROXMOX_BACKUP_TCP_KEEPALIVE_TIME),
+        ..Default::default()
+    };
+
+    let expected_fingerprint = fingerprint.to_lowercase();
+
+    let mut ssl_connector_builder = SslConnector::builder(SslMethod::tls())?;
+    ssl_connector_builder.set_verify_callback(SslVerifyMode::PEER, move |_valid, ctx| {
+        // only the leaf certificate is pinned, ignore the rest of the chain
+        if ctx.error_depth() != 0 {
+            return true;
+        }
+        let result = match ctx.current_cert() {
+            Some(cert) => check_pinned_fingerprint(cert, &expected_fingerprint),
+            None => Err(format_err!("context lacks current certificate.")),
+        };
+        match result {
+            Ok(()) => true,
+            Err(err) => {
+                eprintln!("certificate validation failed - {}", err);
+                false
+            }
+        }
+    });
+
+    Ok(SimpleHttp::with_ssl_connector(ssl_connector_builder.build(), options))
+}
+
+/// Options for `http_download_to_writer`
+#[derive(Default, Clone)]
+pub struct HttpDownloadOptions {
+    /// Fail if the response body is larger (in bytes).
+    pub max_size: Option<u64>,
+    /// Fail if the whole download (including the request) takes longer.
+    pub timeout: Option<Duration>,
+}
+
+/// Download `uri` and stream the response body to `writer`.
+///
+/// Unlike `SimpleHttp::get_string`, this does not buffer the whole response, so it is suitable
+/// for larger files. Non-success status codes are reported before anything is written. Returns
+/// the number of bytes written.
+pub async fn http_download_to_writer<W: tokio::io::AsyncWrite + Unpin>(
+    client: &mut SimpleHttp,
+    uri: &str,
+    writer: &mut W,
+    options: &HttpDownloadOptions,
+) -> Result<u64, Error> {
+    let download = async {
+        let request = http::Request::builder()
+            .method("GET")
+            .uri(uri)
+            .body(hyper::Body::empty())?;
+
+        let response = client.request(request).await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            bail!("download of '{}' failed - got status {}", uri, status);
+        }
+
+        if let (Some(max_size), Some(length)) = (
+            options.max_size,
+            response.headers().get(http::header::CONTENT_LENGTH),
+        ) {
+            if let Some(length) = length.to_str().ok().and_then(|l| l.parse::<u64>().ok()) {
+                if length > max_size {
+                    bail!("download of '{}' failed - size {} exceeds limit ({} bytes)", uri, length, max_size);
+                }
+            }
+        }
+
+        copy_body_to_writer(response.into_body(), writer, options.max_size)
+            .await
+            .map_err(|err| format_err!("download of '{}' failed - {}", uri, err))
+    };
+
+    match options.timeout {
+        Some(timeout) => tokio::time::timeout(timeout, download)
+            .await
+            .map_err(|_| format_err!("download of '{}' timed out after {:?}", uri, timeout))?,
+        None => download.await,
+    }
+}
+
+// the size limit is checked before each chunk is written, since the content length header is
+// optional (and not trustworthy)
+async fn copy_body_to_writer<W: tokio::io::AsyncWrite + Unpin>(
+    mut body: hyper::Body,
+    writer: &mut W,
+    max_size: Option<u64>,
+) -> Result<u64, Error> {
+    use futures::TryStreamExt;
+    use tokio::io::AsyncWriteExt;
+
+    let mut total = 0u64;
+
+    while let Some(chunk) = body.try_next().await? {
+        total += chunk.len() as u64;
+        if let Some(max_size) = max_size {
+            if total > max_size {
+                bail!("response body exceeds size limit ({} bytes)", max_size);
+            }
+        }
+        writer.write_all(&chunk).await?;
+    }
+
+    writer.flush().await?;
+
+    Ok(total)
+}
+
+/// Header carrying the request body signature (see `http_post_signed`).
+pub const HTTP_SIGNATURE_HEADER: &str = "X-Proxmox-Signature";
+
+/// Compute the signature header value (`sha256=<hex HMAC-SHA256(secret, body)>`).
+pub fn http_body_signature(secret: &str, body: &[u8]) -> Result<String, Error> {
+    let key = openssl::pkey::PKey::hmac(secret.as_bytes())
+        .map_err(|err| format_err!("error instantiating hmac key: {}", err))?;
+
+    let mut signer = openssl::sign::Signer::new(MessageDigest::sha256(), &key)
+        .map_err(|err| format_err!("error instantiating hmac signer: {}", err))?;
+
+    let hmac = signer
+        .sign_oneshot_to_vec(body)
+        .map_err(|err| format_err!("error calculating hmac: {}", err))?;
+
+    Ok(format!("sha256={}", proxmox::tools::digest_to_hex(&hmac)))
+}
+
+/// Build a JSON POST request, signed with `secret` if set.
+///
+/// `extra_headers` must not contain the signature header.
+pub fn http_post_request(
+    uri: &str,
+    body: &str,
+    secret: Option<&str>,
+    extra_headers: &HashMap<String, String>,
+) -> Result<http::Request<hyper::Body>, Error> {
+    let mut request = http::Request::builder()
+        .method("POST")
+        .uri(uri)
+        .header(http::header::CONTENT_TYPE, "application/json");
+
+    for (name, value) in extra_headers {
+        if name.eq_ignore_ascii_case(HTTP_SIGNATURE_HEADER) {
+            bail!("header '{}' cannot be overwritten", name);
+        }
+        request = request.header(name.as_str(), value.as_str());
+    }
+
+    if let Some(secret) = secret {
+        request = request.header(HTTP_SIGNATURE_HEADER, http_body_signature(secret, body.as_bytes())?);
+    }
+
+    Ok(request.body(hyper::Body::from(body.to_string()))?)
+}
+
+/// POST `body` (JSON) to `uri`, with a `X-Proxmox-Signature` header.
+///
+/// The header contains the HMAC-SHA256 of the body, so that the receiver can
+/// verify the message authenticity with the shared `secret`.
+pub async fn http_post_signed(
+    client: &mut SimpleHttp,
+    uri: &str,
+    body: &str,
+    secret: &str,
+) -> Result<http::Response<hyper::Body>, Error> {
+    let request = http_post_request(uri, body, Some(secret), &HashMap::new())?;
+    client.request(request).await
+}
+
+fn check_pinned_fingerprint(cert: &X509Ref, expected_fingerprint: &str) -> Result<(), Error> {
+    let fp = cert.digest(MessageDigest::sha256())?;
+    let fp_string = format::as_fingerprint(&fp);
+
+    if fp_string != expected_fingerprint {
+        bail!(
+            "certificate fingerprint mismatch (expected {}, got {})",
+            expected_fingerprint,
+            fp_string,
+        );
+    }
+
+    Ok(())
+}
+
+/// Returns the proxy configuration from the environment.
+///
+/// `ALL_PROXY` (or `all_proxy`) is the primary source. If it is not set, this falls back
+/// to `HTTPS_PROXY`/`https_proxy`, and then to `HTTP_PROXY`/`http_proxy` (for plain
+/// requests). Since `SimpleHttp` only holds a single proxy, the HTTPS variant wins when
+/// both scheme specific variables are set.
+///
+/// Returns `None` if `host` is matched by `NO_PROXY` (or `no_proxy`).
+pub fn proxy_config_from_env(host: &str) -> Result<Option<ProxyConfig>, Error> {
+    match proxy_url_from_env(|name| std::env::var(name).ok(), host) {
+        Some(url) => ProxyConfig::parse_proxy_url(&url)
+            .map(Some)
+            .map_err(|err| format_err!("unable to parse proxy url '{}' - {}", url, err)),
+        None => Ok(None),
+    }
+}
+
+/// Returns the system wide proxy configuration.
+///
+/// Uses the process environment first (see `proxy_config_from_env`), then the proxy
+/// variables from `/etc/environment` (which is where e.g. GNOME/NetworkManager based tools
+/// store system wide proxy settings), using the same precedence rules. The `NO_PROXY`
+/// setting of the respective source is honored for `host`.
+pub fn proxy_config_from_system(host: &str) -> Result<Option<ProxyConfig>, Error> {
+    if let Some(proxy_config) = proxy_config_from_env(host)? {
+        return Ok(Some(proxy_config));
+    }
+
+    let content = match proxmox::tools::fs::file_read_optional_string("/etc/environment")? {
+        Some(content) => content,
+        None => return Ok(None),
+    };
+
+    match proxy_url_from_environment_file(&content, host) {
+        Some(url) => ProxyConfig::parse_proxy_url(&url)
+            .map(Some)
+            .map_err(|err| format_err!("unable to parse proxy url '{}' in /etc/environment - {}", url, err)),
+        None => Ok(None),
+    }
+}
+
+// Parse pam_env style 'KEY=VALUE' lines (optionally prefixed by 'export', values may be quoted)
+fn proxy_url_from_environment_file(content: &str, host: &str) -> Option<String> {
+    let mut vars = HashMap::new();
+
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line).trim_start();
+        if let Some(pos) = line.find('=') {
+            let (key, value) = (&line[..pos], line[pos + 1..].trim());
+            let value = value
+                .strip_prefix('"').and_then(|v| v.strip_suffix('"'))
+                .or_else(|| value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')))
+                .unwrap_or(value);
+            vars.insert(key.trim().to_string(), value.to_string());
+        }
+    }
+
+    proxy_url_from_env(|name| vars.get(name).cloned(), host)
+}
+
+fn proxy_url_from_env<F: Fn(&str) -> Option<String>>(lookup: F, host: &str) -> Option<String> {
+    let no_proxy = ["NO_PROXY", "no_proxy"].iter().find_map(|name| lookup(name));
+    if let Some(no_proxy) = no_proxy {
+        if no_proxy_matches(&no_proxy, host) {
+            return None;
+        }
+    }
+
+    const PROXY_ENV_VARS: [&str; 6] = [
+        "ALL_PROXY", "all_proxy",
+        "HTTPS_PROXY", "https_proxy",
+        "HTTP_PROXY", "http_proxy",
+    ];
+
+    PROXY_ENV_VARS
+        .iter()
+        .filter_map(|name| lookup(name))
+        .find(|url| !url.is_empty())
+}
+
+// Check a comma separated `NO_PROXY` list. Entries match the host itself and all its
+// subdomains (a leading dot is optional), '*' matches every host.
+fn no_proxy_matches(no_proxy: &str, host: &str) -> bool {
+    let host = host.trim_end_matches('.').to_lowercase();
+
+    no_proxy
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .map(|entry| entry.trim().trim_start_matches('.').trim_end_matches('.').to_lowercase())
+        .filter(|entry| !entry.is_empty())
+        .any(|entry| {
+            entry == "*"
+                || host == entry
+                || (host.ends_with(&entry) && host[..host.len() - entry.len()].ends_with('.'))
+        })
+}
+
+#[test]
+fn test_copy_body_to_writer() -> Result<(), Error> {
+    let data = vec![0x42u8; 100_000];
+
+    let mut output = Vec::new();
+    let body = hyper::Body::from(data.clone());
+    assert_eq!(runtime::block_on(copy_body_to_writer(body, &mut output, None))?, 100_000);
+    assert_eq!(output, data);
+
+    let mut output = Vec::new();
+    let body = hyper::Body::from(data.clone());
+    assert_eq!(runtime::block_on(copy_body_to_writer(body, &mut output, Some(100_000)))?, 100_000);
+
+    let mut output = Vec::new();
+    let body = hyper::Body::from(data);
+    assert!(runtime::block_on(copy_body_to_writer(body, &mut output, Some(99_999))).is_err());
+    assert!(output.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_run_command_with_timeout() -> Result<(), Error> {
+    use std::process::Command;
+    use std::time::Instant;
+
+    let mut command = Command::new("echo");
+    command.arg("hello");
+    assert_eq!(run_command_with_timeout(command, None, Duration::from_secs(10))?, "hello\n");
+
+    let command = Command::new("false");
+    let err = run_command_with_timeout(command, None, Duration::from_secs(10)).unwrap_err();
+    assert!(err.downcast_ref::<CommandTimeout>().is_none());
+
+    let command = Command::new("cat");
+    let output = run_command_with_input(command, b"input\n".to_vec(), None, None)?;
+    assert_eq!(output, "input\n");
+
+    let start = Instant::now();
+    let mut command = Command::new("sleep");
+    command.arg("9999");
+    let err = run_command_with_timeout(command, None, Duration::from_millis(100)).unwrap_err();
+    assert!(err.downcast_ref::<CommandTimeout>().is_some());
+    assert!(start.elapsed() < COMMAND_KILL_GRACE_PERIOD);
+
+    // ignores SIGTERM, so this needs SIGKILL (for the whole process group)
+    let start = Instant::now();
+    let mut command = Command::new("sh");
+    command.args(&["-c", "trap '' TERM; sleep 9999"]);
+    let err = run_command_with_timeout(command, None, Duration::from_millis(100)).unwrap_err();
+    assert!(err.downcast_ref::<CommandTimeout>().is_some());
+    assert!(start.elapsed() >= COMMAND_KILL_GRACE_PERIOD);
+    assert!(start.elapsed() < Duration::from_secs(60));
+
+    Ok(())
+}
+
+#[test]
+fn test_check_pinned_fingerprint() -> Result<(), Error> {
+    use openssl::x509::{X509Builder, X509NameBuilder};
+
+    let rsa = openssl::rsa::Rsa::generate(2048)?;
+    let pkey = openssl::pkey::PKey::from_rsa(rsa)?;
+
+    let mut name = X509NameBuilder::new()?;
+    name.append_entry_by_text("CN", "localhost")?;
+    let name = name.build();
+
+    let mut builder = X509Builder::new()?;
+    builder.set_version(2)?;
+    builder.set_subject_name(&name)?;
+    builder.set_issuer_name(&name)?;
+    builder.set_pubkey(&pkey)?;
+    builder.set_not_before(&*openssl::asn1::Asn1Time::days_from_now(0)?)?;
+    builder.set_not_after(&*openssl::asn1::Asn1Time::days_from_now(1)?)?;
+    builder.sign(&pkey, MessageDigest::sha256())?;
+    let cert = builder.build();
+
+    let fingerprint = format::as_fingerprint(&cert.digest(MessageDigest::sha256())?);
+    check_pinned_fingerprint(&cert, &fingerprint)?;
+
+    let wrong = format::as_fingerprint(&[0u8; 32]);
+    let err = check_pinned_fingerprint(&cert, &wrong).unwrap_err().to_string();
+    assert!(err.contains("certificate fingerprint mismatch"));
+    assert!(err.contains(&fingerprint));
+
+    Ok(())
+}
+
+#[test]
+fn test_proxy_url_from_env() {
+    let lookup = |vars: &'static [(&'static str, &'static str)]| {
+        move |name: &str| {
+            vars.iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, value)| value.to_string())
+        }
+    };
+
+    assert_eq!(proxy_url_from_env(lookup(&[]), "example.org"), None);
+    assert_eq!(
+        proxy_url_from_env(lookup(&[("http_proxy", "http://plain:3128")]), "example.org").as_deref(),
+        Some("http://plain:3128"),
+    );
+    assert_eq!(
+        proxy_url_from_env(lookup(&[
+            ("HTTP_PROXY", "http://plain:3128"),
+            ("HTTPS_PROXY", "http://secure:3128"),
+        ]), "example.org").as_deref(),
+        Some("http://secure:3128"),
+    );
+    assert_eq!(
+        proxy_url_from_env(lookup(&[
+            ("HTTPS_PROXY", "http://secure:3128"),
+            ("ALL_PROXY", "http://all:3128"),
+        ]), "example.org").as_deref(),
+        Some("http://all:3128"),
+    );
+    // empty variables are treated as unset
+    assert_eq!(
+        proxy_url_from_env(
+            lookup(&[("ALL_PROXY", ""), ("https_proxy", "http://secure:3128")]),
+            "example.org",
+        ).as_deref(),
+        Some("http://secure:3128"),
+    );
+    assert_eq!(
+        proxy_url_from_env(
+            lookup(&[("ALL_PROXY", "http://all:3128"), ("no_proxy", "localhost,.example.org")]),
+            "www.example.org",
+        ),
+        None,
+    );
+}
+
+#[test]
+fn test_no_proxy_matches() {
+    assert!(no_proxy_matches("*", "example.org"));
+    assert!(no_proxy_matches("localhost, example.org", "example.org"));
+    assert!(no_proxy_matches("example.org", "www.Example.org."));
+    assert!(no_proxy_matches(".example.org", "www.example.org"));
+    assert!(!no_proxy_matches("example.org", "badexample.org"));
+    assert!(!no_proxy_matches("www.example.org", "example.org"));
+    assert!(!no_proxy_matches("", "example.org"));
+}
+
 /// This used to be: `SIMPLE_ENCODE_SET` plus space, `"`, `#`, `<`, `>`, backtick, `?`, `{`, `}`
 pub const DEFAULT_ENCODE_SET: &AsciiSet = &percent_encoding::CONTROLS // 0..1f and 7e
     // The SIMPLE_ENCODE_SET adds space and anything >= 0x7e (7e itself is already included above)
@@ -610,3 +1173,50 @@ pub enum ControlFlow<B, C = ()> {
 impl<B> ControlFlow<B> {
     pub const CONTINUE: ControlFlow<B, ()> = ControlFlow::Continue(());
 }
+
+#[test]
+fn test_proxy_url_from_environment_file() {
+    let content = r#"
+# system wide environment
+PATH="/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin"
+export http_proxy="http://proxy.example.com:3128"
+https_proxy='http://secure.example.com:3128'
+"#;
+    assert_eq!(
+        proxy_url_from_environment_file(content, "example.org").as_deref(),
+        Some("http://secure.example.com:3128"),
+    );
+
+    let content = "http_proxy=http://proxy.example.com:3128\n";
+    assert_eq!(
+        proxy_url_from_environment_file(content, "example.org").as_deref(),
+        Some("http://proxy.example.com:3128"),
+    );
+
+    assert_eq!(proxy_url_from_environment_file("PATH=/usr/bin\n", "example.org"), None);
+}
+
+#[test]
+fn test_http_body_signature() -> Result<(), Error> {
+    // RFC 4231, test case 2
+    assert_eq!(
+        http_body_signature("Jefe", b"what do ya want for nothing?")?,
+        "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+    );
+
+    let body = r#"{"text":"backup finished"}"#;
+    let request = http_post_request("http://localhost/hook", body, Some("Jefe"), &HashMap::new())?;
+    assert_eq!(
+        request.headers()[HTTP_SIGNATURE_HEADER],
+        http_body_signature("Jefe", body.as_bytes())?.as_str(),
+    );
+
+    let request = http_post_request("http://localhost/hook", body, None, &HashMap::new())?;
+    assert!(request.headers().get(HTTP_SIGNATURE_HEADER).is_none());
+
+    let mut extra_headers = HashMap::new();
+    extra_headers.insert("x-proxmox-signature".to_string(), "sha256=00".to_string());
+    assert!(http_post_request("http://localhost/hook", body, Some("Jefe"), &extra_headers).is_err());
+
+    Ok(())
+}
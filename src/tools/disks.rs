@@ -7,6 +7,7 @@ use std::os::unix::ffi::{OsStrExt, OsStringExt};
 use std::os::unix::fs::MetadataExt;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::{bail, format_err, Error};
 use libc::dev_t;
@@ -19,7 +20,7 @@ use proxmox::sys::linux::procfs::{MountInfo, mountinfo::Device};
 use proxmox::{io_bail, io_format_err};
 use proxmox::api::api;
 
-use crate::api2::types::{BLOCKDEVICE_NAME_REGEX, StorageStatus};
+use crate::api2::types::{BLOCKDEVICE_NAME_REGEX, StorageStatus, StorageStatusFull};
 
 mod zfs;
 pub use zfs::*;
@@ -29,6 +30,8 @@ mod zpool_list;
 pub use zpool_list::*;
 mod lvm;
 pub use lvm::*;
+mod ceph;
+pub use ceph::*;
 mod smart;
 pub use smart::*;
 
@@ -111,6 +114,27 @@ impl DiskManage {
         self.disk_by_sys_path(&syspath)
     }
 
+    /// List all disks in `/sys/block`.
+    ///
+    /// iSCSI devices are skipped, like `get_disks` does.
+    pub fn list_disks(self: Arc<Self>) -> Result<Vec<Disk>, Error> {
+        let mut list = Vec::new();
+
+        for item in crate::tools::fs::scan_subdir(libc::AT_FDCWD, "/sys/block", &BLOCKDEVICE_NAME_REGEX)? {
+            let item = item?;
+
+            let sys_path = format!("/sys/block/{}", item.file_name().to_string_lossy());
+
+            if is_iscsi_device(&sys_path) {
+                continue;
+            }
+
+            list.push(self.clone().disk_by_sys_path(&sys_path)?);
+        }
+
+        Ok(list)
+    }
+
     /// Gather information about mounted disks:
     fn mounted_devices(&self) -> Result<&HashSet<dev_t>, Error> {
         self.mounted_devices
@@ -168,6 +192,64 @@ impl DiskManage {
         Ok(None)
     }
 
+    /// Resolve the block devices a path is stored on.
+    ///
+    /// Starts with the device of the file system containing `path` and walks the `slaves`
+    /// directories in `/sys`, which covers device mapper (LVM, crypt) and md raid devices, down
+    /// to the physical disks. For ZFS, the member devices of the pool are queried with `zpool`.
+    ///
+    /// Returns `None` if the path is not on a (known) block device, e.g. on network file systems.
+    pub fn backing_devices(&self, path: &Path) -> Result<Option<BackingDevice>, Error> {
+        let (fs_type, device, source) = match self.find_mounted_device(path)? {
+            Some(info) => info,
+            None => return Ok(None),
+        };
+
+        let sys_block = Path::new("/sys/class/block");
+        let mut visited = HashSet::new();
+
+        if fs_type == "zfs" {
+            let source = match source {
+                Some(source) => source,
+                None => return Ok(None),
+            };
+            let pool = get_pool_from_dataset(&source).unwrap_or(&source).to_string_lossy();
+
+            let list = zpool_list(Some(pool.to_string()), true)
+                .map_err(|err| format_err!("unable to list devices of zpool '{}' - {}", pool, err))?;
+
+            let mut slaves = Vec::new();
+            for entry in list {
+                for device in entry.devices {
+                    let rdev = std::fs::metadata(&device)?.rdev();
+                    if let Some(name) = block_device_name(rdev) {
+                        if !visited.contains(&name) {
+                            slaves.push(sysfs_backing_device(sys_block, &name, &mut visited)?);
+                        }
+                    }
+                }
+            }
+
+            return Ok(Some(BackingDevice { name: pool.to_string(), slaves }));
+        }
+
+        // file systems like btrfs use an anonymous device number, so try the mount source
+        let name = match block_device_name(device.into_dev_t()) {
+            Some(name) => Some(name),
+            None => match source.as_ref().map(std::fs::metadata) {
+                Some(Ok(meta)) if (meta.mode() & libc::S_IFBLK) == libc::S_IFBLK => {
+                    block_device_name(meta.rdev())
+                }
+                _ => None,
+            },
+        };
+
+        match name {
+            Some(name) => Ok(Some(sysfs_backing_device(sys_block, &name, &mut visited)?)),
+            None => Ok(None),
+        }
+    }
+
     /// Check whether a specific device node is mounted.
     ///
     /// Note that this tries to `stat` the sources of all mount points without caching the result
@@ -175,6 +257,49 @@ impl DiskManage {
     pub fn is_devnum_mounted(&self, dev: dev_t) -> Result<bool, Error> {
         self.mounted_devices().map(|mounted| mounted.contains(&dev))
     }
+
+    /// Check whether a specific device node is mounted read-only.
+    ///
+    /// Uses the per mount point VFS options and the super block options of the cached mount
+    /// info. If the device is mounted multiple times, it only counts as read-only if all of
+    /// the mounts are read-only. Returns `None` if the device is not mounted.
+    pub fn is_devnum_mounted_readonly(&self, dev: dev_t) -> Result<Option<bool>, Error> {
+        let mut readonly = None;
+
+        for (_id, mp) in self.mount_info()? {
+            let source = match mp.mount_source.as_deref() {
+                Some(s) => s,
+                None => continue,
+            };
+
+            let path = Path::new(source);
+            if !path.is_absolute() {
+                continue;
+            }
+
+            let meta = match std::fs::metadata(path) {
+                Ok(meta) => meta,
+                Err(ref err) if err.kind() == io::ErrorKind::NotFound => continue,
+                Err(other) => return Err(Error::from(other)),
+            };
+
+            if (meta.mode() & libc::S_IFBLK) != libc::S_IFBLK || meta.rdev() != dev {
+                continue;
+            }
+
+            let mp_readonly = mount_options_readonly(&mp.mount_options)
+                || mount_options_readonly(&mp.super_options);
+
+            readonly = Some(readonly.unwrap_or(true) && mp_readonly);
+        }
+
+        Ok(readonly)
+    }
+}
+
+// check for the "ro" flag in a comma separated mount option list
+fn mount_options_readonly(options: &OsStr) -> bool {
+    options.as_bytes().split(|b| *b == b',').any(|opt| opt == b"ro")
 }
 
 /// Queries (and caches) various information about a specific disk.
@@ -467,6 +592,21 @@ impl Disk {
             .get_or_try_init(|| self.manager.is_devnum_mounted(self.devnum()?))?)
     }
 
+    /// Check if this disk is mounted read-only.
+    ///
+    /// Returns `None` if the disk is not mounted. Please note that a read-only mount is still a
+    /// mount, so this does not mean it is safe to modify the disk.
+    pub fn is_mounted_readonly(&self) -> Result<Option<bool>, Error> {
+        self.manager.is_devnum_mounted_readonly(self.devnum()?)
+    }
+
+    /// Get the id of the Ceph OSD using this disk, if any.
+    ///
+    /// This follows the device symlinks in the OSD directories (`/var/lib/ceph/osd/<cluster>-<id>`).
+    pub fn ceph_osd_id(&self) -> Option<u32> {
+        ceph_osd_id_for_devnum(self.devnum().ok()?).ok()?
+    }
+
     /// Read block device stats
     ///
     /// see https://www.kernel.org/doc/Documentation/block/stat.txt
@@ -498,15 +638,7 @@ impl Disk {
 
         let mut map = HashMap::new();
 
-        for item in crate::tools::fs::read_subdir(libc::AT_FDCWD, sys_path)? {
-            let item = item?;
-            let name = match item.file_name().to_str() {
-                Ok(name) => name,
-                Err(_) => continue, // skip non utf8 entries
-            };
-
-            if !name.starts_with(&device) { continue; }
-
+        for name in partition_dir_names(sys_path, &device)? {
             let mut part_path = sys_path.to_owned();
             part_path.push(name);
 
@@ -519,10 +651,162 @@ impl Disk {
 
         Ok(map)
     }
+
+    /// List device partitions, sorted by partition number
+    pub fn partitions_sorted(&self) -> Result<Vec<(u64, Disk)>, Error> {
+        let mut list: Vec<(u64, Disk)> = self.partitions()?.into_iter().collect();
+        list.sort_unstable_by_key(|(partition, _)| *partition);
+        Ok(list)
+    }
+}
+
+// Check if 'name' is a partition of 'device', following the kernel naming
+// convention: a 'p' separates the partition number if the device name
+// ends with a digit (nvme0n1p1, loop0p1), else it is appended (sda1).
+fn is_partition_name(device: &str, name: &str) -> bool {
+    let rest = match name.strip_prefix(device) {
+        Some(rest) => rest,
+        None => return false,
+    };
+
+    let number = if device.ends_with(|c: char| c.is_ascii_digit()) {
+        match rest.strip_prefix('p') {
+            Some(number) => number,
+            None => return false,
+        }
+    } else {
+        rest
+    };
+
+    !number.is_empty() && number.chars().all(|c| c.is_ascii_digit())
+}
+
+// names of the partition sub directories in the sysfs directory of a disk
+fn partition_dir_names(sys_path: &Path, device: &str) -> Result<Vec<String>, Error> {
+    let mut list = Vec::new();
+
+    for item in crate::tools::fs::read_subdir(libc::AT_FDCWD, sys_path)? {
+        let item = item?;
+        let name = match item.file_name().to_str() {
+            Ok(name) => name,
+            Err(_) => continue, // skip non utf8 entries
+        };
+
+        if is_partition_name(device, name) {
+            list.push(name.to_string());
+        }
+    }
+
+    Ok(list)
+}
+
+/// A block device and the devices it is built on.
+///
+/// This forms a tree, for example a LVM volume on a md raid:
+/// `dm-0 -> md0 -> (sda1 -> sda, sdb1 -> sdb)`. For ZFS, the top level entry is the pool.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackingDevice {
+    /// Kernel device name (or the pool name for ZFS)
+    pub name: String,
+    /// Underlying devices, empty for physical disks. The parent disk of a partition is listed
+    /// as its only entry.
+    pub slaves: Vec<BackingDevice>,
+}
+
+impl BackingDevice {
+    /// Names of the physical disks (the leafs of the tree)
+    pub fn physical_devices(&self) -> Vec<String> {
+        let mut list = Vec::new();
+        self.collect_physical_devices(&mut list);
+        list
+    }
+
+    fn collect_physical_devices(&self, list: &mut Vec<String>) {
+        if self.slaves.is_empty() {
+            list.push(self.name.clone());
+        }
+        for slave in self.slaves.iter() {
+            slave.collect_physical_devices(list);
+        }
+    }
+}
+
+impl std::fmt::Display for BackingDevice {
+    /// Shows single device chains, followed by the list of physical disks,
+    /// e.g. `dm-0 → md0 → sda, sdb`
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.name)?;
+        match self.slaves.len() {
+            0 => Ok(()),
+            1 => write!(f, " → {}", self.slaves[0]),
+            _ => write!(f, " → {}", self.physical_devices().join(", ")),
+        }
+    }
+}
+
+// kernel name of a block device number, if it exists in sysfs
+fn block_device_name(devnum: dev_t) -> Option<String> {
+    let path = format!(
+        "/sys/dev/block/{}:{}",
+        unsafe { libc::major(devnum) },
+        unsafe { libc::minor(devnum) },
+    );
+    let path = std::fs::canonicalize(path).ok()?;
+    Some(path.file_name()?.to_string_lossy().to_string())
+}
+
+// Walk the 'slaves' of a device in 'sys_block' (usually /sys/class/block).
+// Partitions link to their parent disk instead. Devices already seen are
+// skipped, so this terminates even if sysfs contains cycles.
+fn sysfs_backing_device(
+    sys_block: &Path,
+    name: &str,
+    visited: &mut HashSet<String>,
+) -> Result<BackingDevice, Error> {
+    visited.insert(name.to_string());
+
+    let dev_path = sys_block.join(name);
+
+    let mut names = Vec::new();
+    if dev_path.join("partition").exists() {
+        let real_path = std::fs::canonicalize(&dev_path)?;
+        if let Some(parent) = real_path.parent().and_then(Path::file_name) {
+            names.push(parent.to_string_lossy().to_string());
+        }
+    } else {
+        match std::fs::read_dir(dev_path.join("slaves")) {
+            Ok(list) => {
+                for entry in list {
+                    names.push(entry?.file_name().to_string_lossy().to_string());
+                }
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => (),
+            Err(err) => bail!("unable to read slaves of {:?} - {}", dev_path, err),
+        }
+        names.sort();
+    }
+
+    let mut slaves = Vec::new();
+    for name in names {
+        if visited.contains(&name) {
+            continue;
+        }
+        slaves.push(sysfs_backing_device(sys_block, &name, visited)?);
+    }
+
+    Ok(BackingDevice { name: name.to_string(), slaves })
 }
 
 /// Returns disk usage information (total, used, avail)
 pub fn disk_usage(path: &std::path::Path) -> Result<StorageStatus, Error> {
+    Ok(disk_usage_full(path)?.into())
+}
+
+/// Like `disk_usage`, but also reports inode usage
+///
+/// File systems with dynamic inode allocation (e.g. btrfs, ZFS) may
+/// report zero inodes.
+pub fn disk_usage_full(path: &std::path::Path) -> Result<StorageStatusFull, Error> {
 
     let mut stat: libc::statfs64 = unsafe { std::mem::zeroed() };
 
@@ -533,10 +817,13 @@ pub fn disk_usage(path: &std::path::Path) -> Result<StorageStatus, Error> {
 
     let bsize = stat.f_bsize as u64;
 
-    Ok(StorageStatus{
+    Ok(StorageStatusFull {
         total: stat.f_blocks*bsize,
         used: (stat.f_blocks-stat.f_bfree)*bsize,
         avail: stat.f_bavail*bsize,
+        inodes_total: stat.f_files,
+        inodes_used: stat.f_files.saturating_sub(stat.f_ffree),
+        inodes_free: stat.f_ffree,
     })
 }
 
@@ -574,7 +861,7 @@ pub fn get_lsblk_info() -> Result<Vec<LsblkInfo>, Error> {
     let mut command = std::process::Command::new("lsblk");
     command.args(&["--json", "-o", "path,parttype,fstype"]);
 
-    let output = crate::tools::run_command(command, None)?;
+    let output = crate::tools::run_command_with_timeout(command, None, Duration::from_secs(10))?;
 
     let mut output: serde_json::Value = output.parse()?;
 
@@ -612,6 +899,8 @@ pub enum DiskUsageType {
     LVM,
     /// Disk is used by ZFS
     ZFS,
+    /// Disk is used by a Ceph OSD
+    Ceph,
     /// Disk is used by device-mapper
     DeviceMapper,
     /// Disk has partitions
@@ -666,6 +955,7 @@ fn scan_partitions(
     disk_manager: Arc<DiskManage>,
     lvm_devices: &HashSet<u64>,
     zfs_devices: &HashSet<u64>,
+    ceph_devices: &HashSet<u64>,
     device: &str,
 ) -> Result<DiskUsageType, Error> {
 
@@ -676,6 +966,7 @@ fn scan_partitions(
 
     let mut found_lvm = false;
     let mut found_zfs = false;
+    let mut found_ceph = false;
     let mut found_mountpoints = false;
     let mut found_dm = false;
     let mut found_partitions = false;
@@ -712,10 +1003,16 @@ fn scan_partitions(
          if zfs_devices.contains(&devnum) {
             found_zfs = true;
          }
+
+        if ceph_devices.contains(&devnum) {
+            found_ceph = true;
+        }
     }
 
     if found_mountpoints {
         used = DiskUsageType::Mounted;
+    } else if found_ceph {
+        used = DiskUsageType::Ceph;
     } else if found_lvm {
         used = DiskUsageType::LVM;
     } else if found_zfs {
@@ -730,6 +1027,14 @@ fn scan_partitions(
 }
 
 
+// check if a '/sys/block/<name>' entry links to an iSCSI session
+fn is_iscsi_device(sys_path: &str) -> bool {
+    match std::fs::read_link(sys_path) {
+        Ok(target) => target.to_str().map(|t| ISCSI_PATH_REGEX.is_match(t)).unwrap_or(false),
+        Err(_) => false,
+    }
+}
+
 /// Get disk usage information for a single disk
 pub fn get_disk_usage_info(
     disk: &str,
@@ -766,7 +1071,10 @@ pub fn get_disks(
 
     let file_system_devices = get_file_system_devices(&lsblk_info)?;
 
-    // fixme: ceph journals/volumes
+    let ceph_devices = get_ceph_devices().or_else(|err| -> Result<HashSet<u64>, Error> {
+        eprintln!("error getting ceph devices: {}", err);
+        Ok(HashSet::new())
+    })?;
 
     let mut result = HashMap::new();
 
@@ -781,11 +1089,7 @@ pub fn get_disks(
 
         let sys_path = format!("/sys/block/{}", name);
 
-        if let Ok(target) = std::fs::read_link(&sys_path) {
-            if let Some(target) = target.to_str() {
-                if ISCSI_PATH_REGEX.is_match(target) { continue; } // skip iSCSI devices
-            }
-        }
+        if is_iscsi_device(&sys_path) { continue; }
 
         let disk = disk_manager.clone().disk_by_sys_path(&sys_path)?;
 
@@ -817,6 +1121,11 @@ pub fn get_disks(
             usage = DiskUsageType::ZFS;
         }
 
+        // ceph-volume creates LVM volumes, so this needs to override the LVM check
+        if ceph_devices.contains(&devnum) && usage != DiskUsageType::Mounted {
+            usage = DiskUsageType::Ceph;
+        }
+
         let vendor = disk.vendor().unwrap_or(None).
             map(|s| s.to_string_lossy().trim().to_string());
 
@@ -831,7 +1140,13 @@ pub fn get_disks(
         let wwn = disk.wwn().map(|s| s.to_string_lossy().into_owned());
 
         if usage != DiskUsageType::Mounted {
-            match scan_partitions(disk_manager.clone(), &lvm_devices, &zfs_devices, &name) {
+            match scan_partitions(
+                disk_manager.clone(),
+                &lvm_devices,
+                &zfs_devices,
+                &ceph_devices,
+                &name,
+            ) {
                 Ok(part_usage) => {
                     if part_usage != DiskUsageType::Unused {
                         usage = part_usage;
@@ -875,20 +1190,62 @@ pub fn get_disks(
 }
 
 /// Try to reload the partition table
-pub fn reread_partition_table(disk: &Disk) -> Result<(), Error> {
+///
+/// After `blockdev --rereadpt`, the partition list from `/sys` is scanned
+/// again to confirm that the kernel actually picked up the change. If
+/// `expected` is given, this returns an error if the partition numbers do
+/// not match after a short grace period (udev may need some time to create
+/// the device nodes). Without `expected`, the before/after set is logged.
+pub fn reread_partition_table(disk: &Disk, expected: Option<&[u64]>) -> Result<(), Error> {
 
     let disk_path = match disk.device_path() {
         Some(path) => path,
         None => bail!("disk {:?} has no node in /dev", disk.syspath()),
     };
 
+    let before = partition_numbers(disk)?;
+
     let mut command = std::process::Command::new("blockdev");
     command.arg("--rereadpt");
     command.arg(disk_path);
 
     crate::tools::run_command(command, None)?;
 
-    Ok(())
+    let expected = match expected {
+        Some(expected) => {
+            let mut expected = expected.to_vec();
+            expected.sort_unstable();
+            expected.dedup();
+            expected
+        }
+        None => {
+            let after = partition_numbers(disk)?;
+            log::info!(
+                "reread partition table of {:?}: partitions before {:?}, after {:?}",
+                disk_path, before, after,
+            );
+            return Ok(());
+        }
+    };
+
+    let mut after = Vec::new();
+    for _ in 0..10 {
+        after = partition_numbers(disk)?;
+        if after == expected {
+            return Ok(());
+        }
+        std::thread::sleep(std::time::Duration::from_millis(200));
+    }
+
+    bail!(
+        "reread partition table of {:?} did not take effect (expected partitions {:?}, before {:?}, after {:?})",
+        disk_path, expected, before, after,
+    );
+}
+
+// sorted list of partition numbers currently known to the kernel
+fn partition_numbers(disk: &Disk) -> Result<Vec<u64>, Error> {
+    Ok(disk.partitions_sorted()?.into_iter().map(|(partition, _)| partition).collect())
 }
 
 /// Initialize disk by writing a GPT partition table
@@ -910,8 +1267,87 @@ pub fn inititialize_gpt_disk(disk: &Disk, uuid: Option<&str>) -> Result<(), Erro
     Ok(())
 }
 
+// sfdisk ignores comment lines, so we can record the device size in the dump itself
+const PARTITION_TABLE_DUMP_SIZE_TAG: &str = "# device-size:";
+
+/// Dump the partition table of a disk (`sfdisk --dump`)
+///
+/// The dump also records the device size, so that `restore_partition_table`
+/// can refuse to apply it to a different sized disk.
+pub fn dump_partition_table(disk: &Disk) -> Result<String, Error> {
+
+    let disk_path = match disk.device_path() {
+        Some(path) => path,
+        None => bail!("disk {:?} has no node in /dev", disk.syspath()),
+    };
+
+    let mut command = std::process::Command::new("sfdisk");
+    command.arg("--dump");
+    command.arg(disk_path);
+
+    let output = crate::tools::run_command_with_timeout(command, None, Duration::from_secs(30))?;
+
+    Ok(format!("{} {}\n{}", PARTITION_TABLE_DUMP_SIZE_TAG, disk.size()?, output))
+}
+
+// device size recorded by dump_partition_table
+fn partition_table_dump_device_size(dump: &str) -> Result<u64, Error> {
+    for line in dump.lines() {
+        if let Some(size) = line.strip_prefix(PARTITION_TABLE_DUMP_SIZE_TAG) {
+            return size.trim().parse()
+                .map_err(|err| format_err!("invalid device size in partition table dump - {}", err));
+        }
+    }
+    bail!("partition table dump does not contain the device size");
+}
+
+/// Restore a partition table saved with `dump_partition_table`
+///
+/// Fails if the disk or one of its partitions is mounted or in use, or if
+/// the disk size differs from the one recorded in the dump.
+pub fn restore_partition_table(disk: &Disk, dump: &str) -> Result<(), Error> {
+
+    let disk_path = match disk.device_path() {
+        Some(path) => path,
+        None => bail!("disk {:?} has no node in /dev", disk.syspath()),
+    };
+
+    if disk.is_mounted()? || disk.has_holders()? {
+        bail!("disk {:?} is in use", disk_path);
+    }
+    for (_, partition) in disk.partitions()? {
+        if partition.is_mounted()? || partition.has_holders()? {
+            bail!("partition {:?} of disk {:?} is in use", partition.sysname(), disk_path);
+        }
+    }
+
+    let dump_size = partition_table_dump_device_size(dump)?;
+    let size = disk.size()?;
+    if dump_size != size {
+        bail!(
+            "partition table dump is for a disk with {} bytes, but {:?} has {} bytes",
+            dump_size, disk_path, size,
+        );
+    }
+
+    let mut command = std::process::Command::new("sfdisk");
+    command.arg(disk_path);
+
+    crate::tools::run_command_with_input(command, dump.as_bytes().to_vec(), None, None)?;
+
+    Ok(())
+}
+
 /// Create a single linux partition using the whole available space
-pub fn create_single_linux_partition(disk: &Disk) -> Result<Disk, Error> {
+///
+/// Optionally sets the GPT partition `name` and aligns the start of the
+/// partition to a multiple of `alignment` sectors (sgdisk defaults to 2048
+/// sectors, i.e. 1MiB on 512 byte sector disks).
+pub fn create_single_linux_partition(
+    disk: &Disk,
+    name: Option<&str>,
+    alignment: Option<u64>,
+) -> Result<Disk, Error> {
 
     let disk_path = match disk.device_path() {
         Some(path) => path,
@@ -919,7 +1355,19 @@ pub fn create_single_linux_partition(disk: &Disk) -> Result<Disk, Error> {
     };
 
     let mut command = std::process::Command::new("sgdisk");
+    if let Some(alignment) = alignment {
+        if alignment == 0 {
+            bail!("invalid partition alignment '0'");
+        }
+        command.arg(format!("-a{}", alignment));
+    }
     command.args(&["-n1", "-t1:8300"]);
+    if let Some(name) = name {
+        if name.is_empty() || name.chars().count() > 36 || name.contains(':') {
+            bail!("invalid partition name '{}'", name);
+        }
+        command.arg(format!("-c1:{}", name));
+    }
     command.arg(disk_path);
 
     crate::tools::run_command(command, None)?;
@@ -940,6 +1388,8 @@ pub enum FileSystemType {
     Ext4,
     /// XFS
     Xfs,
+    /// Btrfs
+    Btrfs,
 }
 
 impl std::fmt::Display for FileSystemType {
@@ -947,6 +1397,7 @@ impl std::fmt::Display for FileSystemType {
         let text = match self {
             FileSystemType::Ext4 => "ext4",
             FileSystemType::Xfs => "xfs",
+            FileSystemType::Btrfs => "btrfs",
         };
         write!(f, "{}", text)
     }
@@ -969,10 +1420,14 @@ pub fn create_file_system(disk: &Disk, fs_type: FileSystemType) -> Result<(), Er
         None => bail!("disk {:?} has no node in /dev", disk.syspath()),
     };
 
-    let fs_type = fs_type.to_string();
-
-    let mut command = std::process::Command::new("mkfs");
-    command.args(&["-t", &fs_type]);
+    let mut command = match fs_type {
+        FileSystemType::Btrfs => std::process::Command::new("mkfs.btrfs"),
+        _ => {
+            let mut command = std::process::Command::new("mkfs");
+            command.args(&["-t", &fs_type.to_string()]);
+            command
+        }
+    };
     command.arg(disk_path);
 
     crate::tools::run_command(command, None)?;
@@ -980,6 +1435,102 @@ pub fn create_file_system(disk: &Disk, fs_type: FileSystemType) -> Result<(), Er
     Ok(())
 }
 
+#[api()]
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all="lowercase")]
+/// Btrfs block group profile
+pub enum BtrfsRaid {
+    /// Single copy
+    Single,
+    /// Two copies on the same device
+    Dup,
+    /// Striping
+    Raid0,
+    /// Mirroring
+    Raid1,
+    /// Striping over mirrors
+    Raid10,
+}
+
+impl BtrfsRaid {
+    /// Minimum number of devices required for this profile
+    pub fn min_devices(&self) -> usize {
+        match self {
+            BtrfsRaid::Single | BtrfsRaid::Dup => 1,
+            BtrfsRaid::Raid0 | BtrfsRaid::Raid1 => 2,
+            BtrfsRaid::Raid10 => 4,
+        }
+    }
+}
+
+impl std::fmt::Display for BtrfsRaid {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let text = match self {
+            BtrfsRaid::Single => "single",
+            BtrfsRaid::Dup => "dup",
+            BtrfsRaid::Raid0 => "raid0",
+            BtrfsRaid::Raid1 => "raid1",
+            BtrfsRaid::Raid10 => "raid10",
+        };
+        write!(f, "{}", text)
+    }
+}
+
+#[api(
+    properties: {
+        "data-profile": {
+            type: BtrfsRaid,
+        },
+        "metadata-profile": {
+            type: BtrfsRaid,
+        },
+        label: {
+            optional: true,
+        },
+    },
+)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all="kebab-case")]
+/// Btrfs format options
+pub struct BtrfsFormatOptions {
+    /// Data block group profile
+    pub data_profile: BtrfsRaid,
+    /// Metadata block group profile
+    pub metadata_profile: BtrfsRaid,
+    /// File system label
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub label: Option<String>,
+}
+
+/// Create a (multi device) btrfs file system
+pub fn create_btrfs_file_system(disks: &[&Disk], opts: BtrfsFormatOptions) -> Result<(), Error> {
+
+    for profile in &[opts.data_profile, opts.metadata_profile] {
+        if disks.len() < profile.min_devices() {
+            bail!("btrfs profile '{}' needs at least {} disks", profile, profile.min_devices());
+        }
+    }
+
+    let mut command = std::process::Command::new("mkfs.btrfs");
+    command.args(&["-d", &opts.data_profile.to_string()]);
+    command.args(&["-m", &opts.metadata_profile.to_string()]);
+
+    if let Some(ref label) = opts.label {
+        command.args(&["-L", label]);
+    }
+
+    for disk in disks {
+        match disk.device_path() {
+            Some(path) => command.arg(path),
+            None => bail!("disk {:?} has no node in /dev", disk.syspath()),
+        };
+    }
+
+    crate::tools::run_command(command, None)?;
+
+    Ok(())
+}
+
 /// Block device name completion helper
 pub fn complete_disk_name(_arg: &str, _param: &HashMap<String, String>) -> Vec<String> {
     let mut list = Vec::new();
@@ -1013,7 +1564,7 @@ pub fn get_fs_uuid(disk: &Disk) -> Result<String, Error> {
     command.args(&["-o", "export"]);
     command.arg(disk_path);
 
-    let output = crate::tools::run_command(command, None)?;
+    let output = crate::tools::run_command_with_timeout(command, None, Duration::from_secs(10))?;
 
     for line in output.lines() {
         if let Some(uuid) = line.strip_prefix("UUID=") {
@@ -1023,3 +1574,113 @@ pub fn get_fs_uuid(disk: &Disk) -> Result<String, Error> {
 
     bail!("get_fs_uuid failed - missing UUID");
 }
+
+#[test]
+fn test_partition_dir_names() -> Result<(), Error> {
+
+    fn mock_sysfs(base: &Path, device: &str, entries: &[&str]) -> Result<PathBuf, Error> {
+        let path = base.join(device);
+        std::fs::create_dir_all(&path)?;
+        for entry in entries {
+            std::fs::create_dir(path.join(entry))?;
+        }
+        Ok(path)
+    }
+
+    fn sorted_names(path: &Path, device: &str) -> Result<Vec<String>, Error> {
+        let mut list = partition_dir_names(path, device)?;
+        list.sort();
+        Ok(list)
+    }
+
+    let base = std::fs::canonicalize(".")?.join(".testdir-partitions");
+    let _ = std::fs::remove_dir_all(&base);
+
+    let path = mock_sysfs(&base, "sda", &["sda1", "sda2", "sda10", "holders", "queue", "sdab"])?;
+    assert_eq!(sorted_names(&path, "sda")?, vec!["sda1", "sda10", "sda2"]);
+
+    let path = mock_sysfs(&base, "nvme0n1", &["nvme0n1p1", "nvme0n1p2", "nvme0n10", "mq", "queue"])?;
+    assert_eq!(sorted_names(&path, "nvme0n1")?, vec!["nvme0n1p1", "nvme0n1p2"]);
+
+    let _ = std::fs::remove_dir_all(&base);
+
+    assert!(is_partition_name("sda", "sda1"));
+    assert!(!is_partition_name("sda", "sda"));
+    assert!(!is_partition_name("sda", "sdb1"));
+    assert!(!is_partition_name("sda", "sdaa1"));
+    assert!(is_partition_name("nvme0n1", "nvme0n1p3"));
+    assert!(!is_partition_name("nvme0n1", "nvme0n12"));
+    assert!(!is_partition_name("nvme0n1", "nvme0n1p"));
+
+    Ok(())
+}
+
+#[test]
+fn test_mount_options_readonly() {
+    assert!(mount_options_readonly(OsStr::new("ro")));
+    assert!(mount_options_readonly(OsStr::new("rw,nosuid,ro")));
+    assert!(mount_options_readonly(OsStr::new("ro,relatime")));
+    assert!(!mount_options_readonly(OsStr::new("rw,relatime")));
+    assert!(!mount_options_readonly(OsStr::new("rw,errors=remount-ro")));
+    assert!(!mount_options_readonly(OsStr::new("")));
+}
+
+#[test]
+fn test_partition_table_dump_device_size() -> Result<(), Error> {
+
+    let dump = "# device-size: 1000204886016\nlabel: gpt\ndevice: /dev/sda\nunit: sectors\n\n/dev/sda1 : start=2048, size=1953504, type=0FC63DAF-8483-4772-8E79-3D69D8477DE4\n";
+    assert_eq!(partition_table_dump_device_size(dump)?, 1000204886016);
+
+    assert!(partition_table_dump_device_size("label: gpt\ndevice: /dev/sda\n").is_err());
+    assert!(partition_table_dump_device_size("# device-size: abc\nlabel: gpt\n").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_sysfs_backing_device() -> Result<(), Error> {
+
+    let base = std::fs::canonicalize(".")?.join(".testdir-backing-devices");
+    let _ = std::fs::remove_dir_all(&base);
+
+    let add_slaves = |device: &str, slaves: &[&str]| -> Result<(), Error> {
+        let path = base.join(device).join("slaves");
+        std::fs::create_dir_all(&path)?;
+        for slave in slaves {
+            std::fs::write(path.join(slave), b"")?;
+        }
+        Ok(())
+    };
+
+    let add_partition = |device: &str, partition: &str| -> Result<(), Error> {
+        let path = base.join(device).join(partition);
+        std::fs::create_dir_all(&path)?;
+        std::fs::write(path.join("partition"), b"1\n")?;
+        std::os::unix::fs::symlink(&path, base.join(partition))?;
+        Ok(())
+    };
+
+    // LVM volume on a md raid of two partitions
+    add_slaves("dm-0", &["md0"])?;
+    add_slaves("md0", &["sdb1", "sda1"])?;
+    add_partition("sda", "sda1")?;
+    add_partition("sdb", "sdb1")?;
+
+    // broken setup with a cycle
+    add_slaves("dm-1", &["dm-2"])?;
+    add_slaves("dm-2", &["dm-1"])?;
+
+    let device = sysfs_backing_device(&base, "dm-0", &mut HashSet::new())?;
+    assert_eq!(device.physical_devices(), vec!["sda", "sdb"]);
+    assert_eq!(device.to_string(), "dm-0 → md0 → sda, sdb");
+
+    let device = sysfs_backing_device(&base, "sda1", &mut HashSet::new())?;
+    assert_eq!(device.to_string(), "sda1 → sda");
+
+    let device = sysfs_backing_device(&base, "dm-1", &mut HashSet::new())?;
+    assert_eq!(device.to_string(), "dm-1 → dm-2");
+
+    let _ = std::fs::remove_dir_all(&base);
+
+    Ok(())
+}
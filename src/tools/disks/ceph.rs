@@ -0,0 +1,170 @@
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Error};
+
+use super::{block_device_name, sysfs_backing_device, BackingDevice};
+
+const CEPH_OSD_DIR: &str = "/var/lib/ceph/osd";
+
+/// Symlinks inside an OSD directory which point to the devices used by bluestore.
+const CEPH_OSD_DEVICE_LINKS: [&str; 3] = ["block", "block.db", "block.wal"];
+
+/// Parse the OSD id from an OSD directory name (`<cluster>-<id>`, e.g. `ceph-3`).
+fn parse_osd_id(name: &str) -> Option<u32> {
+    let pos = name.rfind('-')?;
+    if pos == 0 {
+        return None;
+    }
+    name[pos + 1..].parse().ok()
+}
+
+/// List the device symlinks of all OSD directories in `osd_dir`.
+///
+/// Directories which do not look like an OSD directory are ignored, as are
+/// missing links (e.g. an OSD without separate DB/WAL device).
+fn ceph_osd_device_links(osd_dir: &Path) -> Result<Vec<(u32, PathBuf)>, Error> {
+    let mut list = Vec::new();
+
+    let entries = match std::fs::read_dir(osd_dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(list),
+        Err(err) => bail!("unable to read ceph OSD directory {:?} - {}", osd_dir, err),
+    };
+
+    for entry in entries {
+        let entry = entry?;
+        let osd_id = match entry.file_name().to_str().and_then(parse_osd_id) {
+            Some(osd_id) => osd_id,
+            None => continue,
+        };
+
+        for link in CEPH_OSD_DEVICE_LINKS.iter() {
+            let path = entry.path().join(link);
+            match std::fs::symlink_metadata(&path) {
+                Ok(meta) if meta.file_type().is_symlink() => list.push((osd_id, path)),
+                _ => continue,
+            }
+        }
+    }
+
+    list.sort();
+
+    Ok(list)
+}
+
+// device numbers of a device and all devices it is built on (dm, md, partitions)
+fn collect_device_numbers(device: &BackingDevice, list: &mut Vec<u64>) {
+    let path = format!("/sys/class/block/{}/dev", device.name);
+    if let Ok(data) = std::fs::read_to_string(path) {
+        let mut parts = data.trim().splitn(2, ':');
+        if let (Some(Ok(major)), Some(Ok(minor))) = (
+            parts.next().map(str::parse::<u64>),
+            parts.next().map(str::parse::<u64>),
+        ) {
+            list.push(nix::sys::stat::makedev(major, minor));
+        }
+    }
+    for slave in device.slaves.iter() {
+        collect_device_numbers(slave, list);
+    }
+}
+
+/// Map device numbers to the id of the OSD using them.
+///
+/// Besides the devices the OSD symlinks point to, this includes the devices
+/// they are built on, so that e.g. the physical disk below a ceph-volume LVM
+/// volume is found as well.
+fn ceph_osd_devices() -> Result<HashMap<u64, u32>, Error> {
+    let sys_block = Path::new("/sys/class/block");
+
+    let mut map = HashMap::new();
+
+    for (osd_id, path) in ceph_osd_device_links(Path::new(CEPH_OSD_DIR))? {
+        let meta = match std::fs::metadata(&path) {
+            Ok(meta) => meta,
+            Err(_) => continue, // dangling link, the OSD is probably not active
+        };
+        if !meta.file_type().is_block_device() {
+            continue;
+        }
+
+        map.insert(meta.rdev(), osd_id);
+
+        if let Some(name) = block_device_name(meta.rdev()) {
+            let mut visited = HashSet::new();
+            let device = sysfs_backing_device(sys_block, &name, &mut visited)?;
+            let mut list = Vec::new();
+            collect_device_numbers(&device, &mut list);
+            for devnum in list {
+                map.entry(devnum).or_insert(osd_id);
+            }
+        }
+    }
+
+    Ok(map)
+}
+
+/// Get set of devices used by Ceph OSDs.
+///
+/// The set is indexed by using the unix raw device number (dev_t is u64)
+pub fn get_ceph_devices() -> Result<HashSet<u64>, Error> {
+    Ok(ceph_osd_devices()?.keys().copied().collect())
+}
+
+/// Get the id of the OSD using a device (see `get_ceph_devices`)
+pub fn ceph_osd_id_for_devnum(devnum: u64) -> Result<Option<u32>, Error> {
+    Ok(ceph_osd_devices()?.get(&devnum).copied())
+}
+
+#[test]
+fn test_parse_osd_id() {
+    assert_eq!(parse_osd_id("ceph-0"), Some(0));
+    assert_eq!(parse_osd_id("ceph-12"), Some(12));
+    assert_eq!(parse_osd_id("my-cluster-7"), Some(7));
+    assert_eq!(parse_osd_id("ceph-"), None);
+    assert_eq!(parse_osd_id("ceph-x"), None);
+    assert_eq!(parse_osd_id("-3"), None);
+    assert_eq!(parse_osd_id("ceph"), None);
+}
+
+#[test]
+fn test_ceph_osd_device_links() -> Result<(), Error> {
+    let base = std::fs::canonicalize(".")?.join(".testdir-ceph-osd");
+    let _ = std::fs::remove_dir_all(&base);
+
+    let add_osd = |name: &str, links: &[&str]| -> Result<PathBuf, Error> {
+        let path = base.join(name);
+        std::fs::create_dir_all(&path)?;
+        std::fs::write(path.join("type"), b"bluestore\n")?;
+        for link in links {
+            std::os::unix::fs::symlink("/dev/nonexistent", path.join(link))?;
+        }
+        Ok(path)
+    };
+
+    let osd0 = add_osd("ceph-0", &["block"])?;
+    let osd5 = add_osd("ceph-5", &["block", "block.db", "block.wal"])?;
+    add_osd("lost+found", &["block"])?;
+    // a regular file is not a device link
+    let osd7 = add_osd("ceph-7", &[])?;
+    std::fs::write(osd7.join("block"), b"")?;
+
+    let list = ceph_osd_device_links(&base)?;
+
+    let _ = std::fs::remove_dir_all(&base);
+
+    assert_eq!(list, vec![
+        (0, osd0.join("block")),
+        (5, osd5.join("block")),
+        (5, osd5.join("block.db")),
+        (5, osd5.join("block.wal")),
+    ]);
+
+    // no ceph installed
+    assert!(ceph_osd_device_links(&base)?.is_empty());
+
+    Ok(())
+}
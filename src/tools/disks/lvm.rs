@@ -1,5 +1,6 @@
 use std::collections::HashSet;
 use std::os::unix::fs::MetadataExt;
+use std::time::Duration;
 
 use anyhow::{Error};
 use serde_json::Value;
@@ -27,7 +28,7 @@ pub fn get_lvm_devices(
     let mut command = std::process::Command::new(PVS_BIN_PATH);
     command.args(&["--reportformat", "json", "--noheadings", "--readonly", "-o", "pv_name"]);
 
-    let output = crate::tools::run_command(command, None)?;
+    let output = crate::tools::run_command_with_timeout(command, None, Duration::from_secs(10))?;
 
     let mut device_set: HashSet<u64> = HashSet::new();
 
@@ -1,4 +1,5 @@
 use std::collections::{HashMap, HashSet};
+use std::time::Duration;
 
 use lazy_static::lazy_static;
 use anyhow::{bail, Error};
@@ -24,25 +25,25 @@ pub enum SmartStatus {
 /// SMART Attribute
 pub struct SmartAttribute {
     /// Attribute name
-    name: String,
+    pub name: String,
     /// Attribute raw value
-    value: String,
+    pub value: String,
     // the rest of the values is available for ATA type
     /// ATA Attribute ID
     #[serde(skip_serializing_if="Option::is_none")]
-    id: Option<u64>,
+    pub id: Option<u64>,
     /// ATA Flags
     #[serde(skip_serializing_if="Option::is_none")]
-    flags: Option<String>,
-    /// ATA normalized value (0..100)
+    pub flags: Option<String>,
+    /// ATA normalized value (0..100), NVMe: available spare
     #[serde(skip_serializing_if="Option::is_none")]
-    normalized: Option<f64>,
+    pub normalized: Option<f64>,
     /// ATA worst
     #[serde(skip_serializing_if="Option::is_none")]
-    worst: Option<f64>,
-    /// ATA threshold
+    pub worst: Option<f64>,
+    /// ATA threshold, NVMe: available spare threshold
     #[serde(skip_serializing_if="Option::is_none")]
-    threshold: Option<f64>,
+    pub threshold: Option<f64>,
 }
 
 
@@ -73,6 +74,44 @@ pub struct SmartData {
     pub attributes: Vec<SmartAttribute>,
 }
 
+impl SmartData {
+
+    /// Lookup an ATA attribute by ID (NVMe attributes have no ID).
+    pub fn attribute(&self, id: u64) -> Option<&SmartAttribute> {
+        self.attributes.iter().find(|attr| attr.id == Some(id))
+    }
+
+    /// Lookup an attribute by name, e.g. "Reallocated_Sector_Ct" (ATA) or
+    /// "media_errors" (NVMe).
+    pub fn attribute_by_name(&self, name: &str) -> Option<&SmartAttribute> {
+        self.attributes.iter().find(|attr| attr.name == name)
+    }
+}
+
+/// Read a single ATA SMART attribute by ID (e.g. 5 for reallocated sectors).
+pub fn get_smart_attribute(
+    disk: &super::Disk,
+    id: u64,
+) -> Result<Option<SmartAttribute>, Error> {
+    let mut data = get_smart_data(disk, false)?;
+    Ok(match data.attributes.iter().position(|attr| attr.id == Some(id)) {
+        Some(pos) => Some(data.attributes.swap_remove(pos)),
+        None => None,
+    })
+}
+
+/// Read a single SMART attribute by name, works for ATA and NVMe devices.
+pub fn get_smart_attribute_by_name(
+    disk: &super::Disk,
+    name: &str,
+) -> Result<Option<SmartAttribute>, Error> {
+    let mut data = get_smart_data(disk, false)?;
+    Ok(match data.attributes.iter().position(|attr| attr.name == name) {
+        Some(pos) => Some(data.attributes.swap_remove(pos)),
+        None => None,
+    })
+}
+
 /// Read smartctl data for a disk (/dev/XXX).
 pub fn get_smart_data(
     disk: &super::Disk,
@@ -91,10 +130,16 @@ pub fn get_smart_data(
     };
     command.arg(disk_path);
 
-    let output = crate::tools::run_command(command, None)?;
+    let output = crate::tools::run_command_with_timeout(command, None, Duration::from_secs(30))?;
 
     let output: serde_json::Value = output.parse()?;
 
+    Ok(parse_smart_output(&output))
+}
+
+// parse the 'smartctl -H -A -j' json output
+fn parse_smart_output(output: &serde_json::Value) -> SmartData {
+
     let mut wearout = None;
 
     let mut attributes = Vec::new();
@@ -165,6 +210,8 @@ pub fn get_smart_data(
 
     // NVME devices
     if let Some(list) = output["nvme_smart_health_information_log"].as_object() {
+        let spare_threshold = list.get("available_spare_threshold").and_then(|v| v.as_f64());
+
         for (name, value) in list {
             if name == "percentage_used" {
                 // extract wearout from nvme text, allow for decimal values
@@ -175,14 +222,20 @@ pub fn get_smart_data(
                 }
             }
             if let Some(value) = value.as_f64() {
+                // available spare is a normalized value with threshold, like ATA attributes
+                let (normalized, threshold) = if name == "available_spare" {
+                    (Some(value), spare_threshold)
+                } else {
+                    (None, None)
+                };
                 attributes.push(SmartAttribute {
                     name: name.to_string(),
                     value: value.to_string(),
                     id: None,
                     flags: None,
-                    normalized: None,
+                    normalized,
                     worst: None,
-                    threshold: None,
+                    threshold,
                 });
             }
         }
@@ -194,8 +247,7 @@ pub fn get_smart_data(
         Some(false) => SmartStatus::Failed,
     };
 
-
-    Ok(SmartData { status, wearout, attributes })
+    SmartData { status, wearout, attributes }
 }
 
 static WEAROUT_FIELD_ORDER: &[&'static str] = &[
@@ -219,3 +271,62 @@ lazy_static! {
         WEAROUT_FIELD_ORDER.iter().cloned().collect()
     };
 }
+
+#[test]
+fn test_parse_smart_output() {
+
+    let output = serde_json::json!({
+        "smart_status": { "passed": true },
+        "ata_smart_attributes": {
+            "table": [
+                {
+                    "id": 5, "name": "Reallocated_Sector_Ct",
+                    "value": 100, "worst": 100, "thresh": 10,
+                    "flags": { "string": "PO--CK " },
+                    "raw": { "value": 8, "string": "8" },
+                },
+                {
+                    "id": 194, "name": "Temperature_Celsius",
+                    "value": 70, "worst": 55, "thresh": 0,
+                    "flags": { "string": "-O---K " },
+                    "raw": { "value": 30, "string": "30 (Min/Max 18/45)" },
+                },
+            ],
+        },
+    });
+
+    let data = parse_smart_output(&output);
+    assert!(matches!(data.status, SmartStatus::Passed));
+
+    let attr = data.attribute(5).unwrap();
+    assert_eq!(attr.name, "Reallocated_Sector_Ct");
+    assert_eq!(attr.value, "8");
+    assert_eq!(attr.normalized, Some(100.0));
+    assert_eq!(attr.threshold, Some(10.0));
+
+    assert_eq!(data.attribute(194).unwrap().value, "30 (Min/Max 18/45)");
+    assert!(data.attribute(9).is_none());
+
+    let output = serde_json::json!({
+        "smart_status": { "passed": false },
+        "nvme_smart_health_information_log": {
+            "critical_warning": 0,
+            "temperature": 35,
+            "available_spare": 42,
+            "available_spare_threshold": 50,
+            "percentage_used": 3,
+            "media_errors": 7,
+        },
+    });
+
+    let data = parse_smart_output(&output);
+    assert!(matches!(data.status, SmartStatus::Failed));
+    assert_eq!(data.wearout, Some(97.0));
+
+    let spare = data.attribute_by_name("available_spare").unwrap();
+    assert_eq!(spare.normalized, Some(42.0));
+    assert_eq!(spare.threshold, Some(50.0));
+    assert!(spare.id.is_none());
+
+    assert_eq!(data.attribute_by_name("media_errors").unwrap().value, "7");
+}
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use anyhow::{bail, Error};
 
 use crate::tools::nom::{
@@ -147,7 +149,7 @@ pub fn zpool_list(pool: Option<String>, verbose: bool) -> Result<Vec<ZFSPoolInfo
 
     if let Some(pool) = pool { command.arg(pool); }
 
-    let output = crate::tools::run_command(command, None)?;
+    let output = crate::tools::run_command_with_timeout(command, None, Duration::from_secs(30))?;
 
     parse_zpool_list(&output)
 }
@@ -1,4 +1,5 @@
 use std::mem::replace;
+use std::time::Duration;
 
 use anyhow::{bail, Error};
 use serde::{Deserialize, Serialize};
@@ -364,7 +365,7 @@ pub fn zpool_status(pool: &str) -> Result<Vec<(String, String)>, Error> {
     let mut command = std::process::Command::new("zpool");
     command.args(&["status", "-p", "-P", pool]);
 
-    let output = crate::tools::run_command(command, None)?;
+    let output = crate::tools::run_command_with_timeout(command, None, Duration::from_secs(30))?;
 
     parse_zpool_status(&output)
 }
@@ -50,6 +50,7 @@ pub struct FileLogger {
     file: std::fs::File,
     file_name: std::path::PathBuf,
     options: FileLogOptions,
+    bytes_written: u64,
 }
 
 /// Log messages to [`FileLogger`](tools/struct.FileLogger.html)
@@ -69,7 +70,7 @@ impl FileLogger {
 
         let file_name: std::path::PathBuf = file_name.as_ref().to_path_buf();
 
-        Ok(Self { file, file_name, options })
+        Ok(Self { file, file_name, options, bytes_written: 0 })
     }
 
     pub fn reopen(&mut self) -> Result<&Self, Error> {
@@ -121,8 +122,15 @@ impl FileLogger {
             // avoid panicking, log methods should not do that
             // FIXME: or, return result???
             eprintln!("error writing to log file - {}", err);
+        } else {
+            self.bytes_written += line.len() as u64;
         }
     }
+
+    /// Number of bytes written by `log()` since this instance was created
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
 }
 
 impl std::io::Write for FileLogger {
@@ -78,6 +78,40 @@ impl ReadDirEntry {
     pub unsafe fn file_name_utf8_unchecked(&self) -> &str {
         std::str::from_utf8_unchecked(self.file_name().to_bytes())
     }
+
+    /// Get the file type, using `fstatat` if the file system does not provide it via `d_type`
+    /// (`DT_UNKNOWN`). Symbolic links are not followed.
+    pub fn resolve_file_type(&self) -> Result<dir::Type, Error> {
+        if let Some(ty) = self.file_type() {
+            return Ok(ty);
+        }
+
+        let stat = nix::sys::stat::fstatat(
+            self.parent_fd,
+            self.file_name(),
+            nix::fcntl::AtFlags::AT_SYMLINK_NOFOLLOW,
+        )?;
+
+        file_type_from_mode(stat.st_mode)
+            .ok_or_else(|| format_err!("unable to detect file type of {:?}", self.file_name()))
+    }
+}
+
+fn file_type_from_mode(mode: libc::mode_t) -> Option<dir::Type> {
+    use nix::sys::stat::SFlag;
+
+    let ty = match SFlag::from_bits_truncate(mode) & SFlag::S_IFMT {
+        SFlag::S_IFREG => dir::Type::File,
+        SFlag::S_IFDIR => dir::Type::Directory,
+        SFlag::S_IFLNK => dir::Type::Symlink,
+        SFlag::S_IFIFO => dir::Type::Fifo,
+        SFlag::S_IFSOCK => dir::Type::Socket,
+        SFlag::S_IFCHR => dir::Type::CharacterDevice,
+        SFlag::S_IFBLK => dir::Type::BlockDevice,
+        _ => return None,
+    };
+
+    Some(ty)
 }
 
 // Since Tied<T, U> implements Deref to U, a Tied<Dir, Iterator> already implements Iterator.
@@ -121,6 +155,29 @@ pub fn scan_subdir<'a, P: ?Sized + nix::NixPath>(
     Ok(read_subdir(dirfd, path)?.filter_file_name_regex(regex))
 }
 
+/// Like `scan_subdir`, but only returns entries of the given file type.
+///
+/// The type is taken from `d_type` where available, so no additional system calls are needed
+/// for most file systems. Entries reported as `DT_UNKNOWN` are checked with `fstatat`.
+pub fn scan_subdir_typed<'a, P: ?Sized + nix::NixPath>(
+    dirfd: RawFd,
+    path: &P,
+    regex: &'a regex::Regex,
+    file_type: dir::Type,
+) -> Result<impl Iterator<Item = Result<ReadDirEntry, Error>> + 'a, nix::Error> {
+    Ok(scan_subdir(dirfd, path, regex)?.filter_map(move |item| {
+        let entry = match item {
+            Ok(entry) => entry,
+            Err(err) => return Some(Err(err)),
+        };
+        match entry.resolve_file_type() {
+            Ok(ty) if ty == file_type => Some(Ok(entry)),
+            Ok(_) => None,
+            Err(err) => Some(Err(err)),
+        }
+    }))
+}
+
 /// Helper trait to provide a combinators for directory entry iterators.
 pub trait FileIterOps<T, E>
 where
@@ -312,3 +369,41 @@ fn do_lock_dir_noblock(
 
     Ok(handle)
 }
+
+#[test]
+fn test_scan_subdir_typed() -> Result<(), Error> {
+    let mut path = std::fs::canonicalize(".")?; // we need absolute path
+    path.push(".testdir-scan-subdir");
+
+    let _ = std::fs::remove_dir_all(&path);
+    std::fs::create_dir(&path)?;
+
+    for i in 0..10 {
+        std::fs::create_dir(path.join(format!("dir{}", i)))?;
+        std::fs::write(path.join(format!("file{}", i)), b"data")?;
+    }
+    std::os::unix::fs::symlink("file0", path.join("link0"))?;
+
+    let regex = Regex::new(r"^[a-z]+[0-9]$")?;
+
+    let count = |ty| -> Result<usize, Error> {
+        let mut count = 0;
+        for entry in scan_subdir_typed(libc::AT_FDCWD, &path, &regex, ty)? {
+            entry?;
+            count += 1;
+        }
+        Ok(count)
+    };
+
+    assert_eq!(count(dir::Type::Directory)?, 10);
+    assert_eq!(count(dir::Type::File)?, 10);
+    assert_eq!(count(dir::Type::Symlink)?, 1);
+
+    assert_eq!(file_type_from_mode(libc::S_IFDIR | 0o755), Some(dir::Type::Directory));
+    assert_eq!(file_type_from_mode(libc::S_IFREG | 0o644), Some(dir::Type::File));
+    assert_eq!(file_type_from_mode(0), None);
+
+    std::fs::remove_dir_all(&path)?;
+
+    Ok(())
+}
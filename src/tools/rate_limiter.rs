@@ -0,0 +1,115 @@
+use std::convert::TryFrom;
+use std::time::{Duration, Instant};
+
+/// Token bucket based rate limiter
+///
+/// Tokens are refilled at a constant `rate` (tokens per second), up to
+/// `bucket_size`. Traffic exceeding the available tokens is never
+/// rejected, instead the caller gets the time it should wait. So the
+/// rate is enforced as average, and short bursts (up to `bucket_size`)
+/// pass without delay.
+#[derive(Debug)]
+pub struct RateLimiter {
+    rate: u64,
+    bucket_size: u64,
+    consumed_tokens: u64,
+    last_update: Instant,
+}
+
+impl RateLimiter {
+
+    /// Create a new instance, using [Instant::now] as start time.
+    pub fn new(rate: u64, bucket_size: u64) -> Self {
+        Self::with_start_time(rate, bucket_size, Instant::now())
+    }
+
+    /// Create a new instance with specified `rate`, `bucket_size` and `start_time`.
+    pub fn with_start_time(rate: u64, bucket_size: u64, start_time: Instant) -> Self {
+        Self {
+            rate,
+            bucket_size,
+            consumed_tokens: 0,
+            last_update: start_time,
+        }
+    }
+
+    /// Returns the configured rate (tokens per second).
+    pub fn rate(&self) -> u64 {
+        self.rate
+    }
+
+    fn refill_bucket(&mut self, current_time: Instant) {
+        let time_diff = match current_time.checked_duration_since(self.last_update) {
+            Some(diff) => diff.as_nanos(),
+            None => return, // time went backwards, do nothing
+        };
+
+        let refill = (time_diff * (self.rate as u128)) / 1_000_000_000;
+        if refill == 0 {
+            return; // keep last_update, so that we do not lose fractional tokens
+        }
+
+        self.last_update = current_time;
+        self.consumed_tokens = self.consumed_tokens
+            .saturating_sub(u64::try_from(refill).unwrap_or(u64::MAX));
+    }
+
+    /// Register traffic, returning the delay the caller should wait (if any).
+    pub fn register_traffic(&mut self, current_time: Instant, data_len: u64) -> Option<Duration> {
+        self.refill_bucket(current_time);
+
+        self.consumed_tokens = self.consumed_tokens.saturating_add(data_len);
+
+        if self.consumed_tokens <= self.bucket_size || self.rate == 0 {
+            return None;
+        }
+
+        let overflow = (self.consumed_tokens - self.bucket_size) as u128;
+        let nanos = (overflow * 1_000_000_000) / (self.rate as u128);
+
+        Some(Duration::from_nanos(u64::try_from(nanos).unwrap_or(u64::MAX)))
+    }
+
+    /// Try to consume `tokens`, returns false (without consuming anything) if
+    /// there are not enough tokens available.
+    pub fn try_consume(&mut self, current_time: Instant, tokens: u64) -> bool {
+        self.refill_bucket(current_time);
+
+        match self.consumed_tokens.checked_add(tokens) {
+            Some(consumed) if consumed <= self.bucket_size => {
+                self.consumed_tokens = consumed;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter() {
+        let start = Instant::now();
+        let mut limiter = RateLimiter::with_start_time(1000, 1000, start);
+
+        // the initial burst is free
+        assert_eq!(limiter.register_traffic(start, 1000), None);
+
+        // bucket is empty now
+        assert_eq!(
+            limiter.register_traffic(start, 500),
+            Some(Duration::from_millis(500)),
+        );
+
+        // after two seconds, we are allowed to send again
+        let later = start + Duration::from_secs(2);
+        assert_eq!(limiter.register_traffic(later, 500), None);
+
+        let mut limiter = RateLimiter::with_start_time(10, 10, start);
+        assert!(limiter.try_consume(start, 10));
+        assert!(!limiter.try_consume(start, 1));
+        assert!(limiter.try_consume(start + Duration::from_millis(100), 1));
+    }
+}
@@ -22,11 +22,79 @@ pub struct SenseInfo {
     pub sense_key: u8,
     pub asc: u8,
     pub ascq: u8,
+    /// The original sense buffer (first 18 bytes, zero padded)
+    ///
+    /// Useful to diagnose drive specific errors using vendor unique fields.
+    pub raw: [u8; 18],
 }
 
-impl std::fmt::Display for SenseInfo {
+impl SenseInfo {
 
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    /// Decode the sense buffer returned by a failed command
+    pub fn decode(buffer: &[u8]) -> Result<Self, ScsiError> {
+
+        if buffer.is_empty() {
+            return Err(format_err!("scsi command failed, but got no sense data").into());
+        }
+
+        let mut raw = [0u8; 18];
+        let raw_len = buffer.len().min(raw.len());
+        raw[..raw_len].copy_from_slice(&buffer[..raw_len]);
+
+        let code = buffer[0] & 0x7f;
+
+        let mut reader = &buffer[..];
+
+        let sense = match code {
+            0x70 => {
+                let sense: RequestSenseFixed = unsafe { reader.read_be_value()? };
+                SenseInfo {
+                    sense_key: sense.flags2 & 0xf,
+                    asc: sense.additional_sense_code,
+                    ascq: sense.additional_sense_code_qualifier,
+                    raw,
+                }
+            }
+            0x72 => {
+                let sense: RequestSenseDescriptor = unsafe { reader.read_be_value()? };
+                SenseInfo {
+                    sense_key: sense.sense_key & 0xf,
+                    asc: sense.additional_sense_code,
+                    ascq: sense.additional_sense_code_qualifier,
+                    raw,
+                }
+            }
+            0x71 | 0x73 => {
+                return Err(format_err!("scsi command failed: received deferred Sense").into());
+            }
+            unknown => {
+                return Err(format_err!("scsi command failed: invalid Sense response code {:x}", unknown).into());
+            }
+        };
+
+        Ok(sense)
+    }
+
+    pub fn additional_sense_qualifier(&self) -> u8 {
+        self.ascq
+    }
+
+    /// Field replaceable unit code
+    ///
+    /// Only available with fixed format sense data (returns 0 for
+    /// descriptor format).
+    pub fn field_replaceable_unit(&self) -> u8 {
+        match self.raw[0] & 0x7f {
+            0x70 | 0x71 => self.raw[14],
+            _ => 0,
+        }
+    }
+
+    /// Sense key and ASC/ASCQ as text
+    ///
+    /// Uses `ASC_ASCQ_DESCRIPTIONS`, and falls back to libsgutils2
+    /// for unknown codes.
+    pub fn format_sense_description(&self) -> String {
 
         let sense_text = SENSE_KEY_DESCRIPTIONS
             .get(self.sense_key as usize)
@@ -34,11 +102,23 @@ impl std::fmt::Display for SenseInfo {
             .unwrap_or_else(|| format!("Invalid sense {:02X}", self.sense_key));
 
         if self.asc == 0 && self.ascq == 0 {
-            write!(f, "{}", sense_text)
-        } else {
-            let additional_sense_text = get_asc_ascq_string(self.asc, self.ascq);
-            write!(f, "{}, {}", sense_text, additional_sense_text)
+            return sense_text;
         }
+
+        let additional_sense_text = ASC_ASCQ_DESCRIPTIONS
+            .iter()
+            .find(|(asc, ascq, _)| *asc == self.asc && *ascq == self.ascq)
+            .map(|(_, _, text)| String::from(*text))
+            .unwrap_or_else(|| get_asc_ascq_string(self.asc, self.ascq));
+
+        format!("{}, {}", sense_text, additional_sense_text)
+    }
+}
+
+impl std::fmt::Display for SenseInfo {
+
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.format_sense_description())
     }
 }
 
@@ -161,6 +241,44 @@ pub const SENSE_KEY_DESCRIPTIONS: [&'static str; 16] = [
     "Completed",
 ];
 
+/// Additional Sense Code (ASC, ASCQ) Descriptions
+///
+/// Codes commonly returned by tape drives and changers (see SPC-5, Annex F).
+pub const ASC_ASCQ_DESCRIPTIONS: &[(u8, u8, &str)] = &[
+    (0x00, 0x01, "Filemark detected"),
+    (0x00, 0x02, "End-of-partition/medium detected"),
+    (0x00, 0x04, "Beginning-of-partition/medium detected"),
+    (0x00, 0x05, "End-of-data detected"),
+    (0x04, 0x00, "Logical unit not ready, cause not reportable"),
+    (0x04, 0x01, "Logical unit is in process of becoming ready"),
+    (0x04, 0x02, "Logical unit not ready, initializing command required"),
+    (0x04, 0x03, "Logical unit not ready, manual intervention required"),
+    (0x0C, 0x00, "Write error"),
+    (0x11, 0x00, "Unrecovered read error"),
+    (0x14, 0x00, "Recorded entity not found"),
+    (0x14, 0x03, "End-of-data not found"),
+    (0x20, 0x00, "Invalid command operation code"),
+    (0x24, 0x00, "Invalid field in CDB"),
+    (0x26, 0x00, "Invalid field in parameter list"),
+    (0x27, 0x00, "Write protected"),
+    (0x28, 0x00, "Not ready to ready change, medium may have changed"),
+    (0x29, 0x00, "Power on, reset, or bus device reset occurred"),
+    (0x30, 0x00, "Incompatible medium installed"),
+    (0x30, 0x03, "Cleaning cartridge installed"),
+    (0x31, 0x00, "Medium format corrupted"),
+    (0x3A, 0x00, "Medium not present"),
+    (0x3B, 0x00, "Sequential positioning error"),
+    (0x3B, 0x0D, "Medium destination element full"),
+    (0x3B, 0x0E, "Medium source element empty"),
+    (0x44, 0x00, "Internal target failure"),
+    (0x50, 0x00, "Write append error"),
+    (0x52, 0x00, "Cartridge fault"),
+    (0x53, 0x00, "Media load or eject failed"),
+    (0x53, 0x02, "Medium removal prevented"),
+    (0x5D, 0x00, "Failure prediction threshold exceeded"),
+    (0x74, 0x00, "Security error"),
+];
+
 #[repr(C, packed)]
 #[derive(Endian)]
 // Standard Inquiry page - 36 bytes
@@ -495,39 +613,8 @@ impl <'a, F: AsRawFd> SgRaw<'a, F> {
                 return Ok(());
             }
             SCSI_PT_RESULT_SENSE => {
-                if sense_len == 0 {
-                    return Err(format_err!("scsi command failed, but got no sense data").into());
-                }
-
-                let code = self.sense_buffer[0] & 0x7f;
-
-                let mut reader = &self.sense_buffer[..(sense_len as usize)];
-
-                let sense = match code {
-                    0x70 => {
-                        let sense: RequestSenseFixed = unsafe { reader.read_be_value()? };
-                        SenseInfo {
-                            sense_key: sense.flags2 & 0xf,
-                            asc: sense.additional_sense_code,
-                            ascq: sense.additional_sense_code_qualifier,
-                        }
-                    }
-                    0x72 => {
-                        let sense: RequestSenseDescriptor = unsafe { reader.read_be_value()? };
-                        SenseInfo {
-                            sense_key: sense.sense_key & 0xf,
-                            asc: sense.additional_sense_code,
-                            ascq: sense.additional_sense_code_qualifier,
-                        }
-                    }
-                    0x71 | 0x73 => {
-                        return Err(format_err!("scsi command failed: received deferred Sense").into());
-                    }
-                    unknown => {
-                        return Err(format_err!("scsi command failed: invalid Sense response code {:x}", unknown).into());
-                    }
-                };
-
+                let sense_len = (sense_len.max(0) as usize).min(self.sense_buffer.len());
+                let sense = SenseInfo::decode(&self.sense_buffer[..sense_len])?;
                 return Err(ScsiError::Sense(sense));
             }
             SCSI_PT_RESULT_TRANSPORT_ERR => return Err(format_err!("scsi command failed: transport error").into()),
@@ -645,6 +732,63 @@ impl <'a, F: AsRawFd> SgRaw<'a, F> {
 
         Ok(())
     }
+
+    /// Run the specified RAW SCSI command, retry on UNIT ATTENTION
+    ///
+    /// See [retry_on_unit_attention] for details.
+    pub fn do_command_with_retry(&mut self, cmd: &[u8], max_retries: u32) -> Result<Vec<u8>, ScsiError> {
+        retry_on_unit_attention(max_retries, || self.do_command(cmd).map(|data| data.to_vec()))
+    }
+
+    /// Run the specified RAW SCSI command with input buffer, retry on UNIT ATTENTION
+    ///
+    /// See [retry_on_unit_attention] for details.
+    pub fn do_in_command_with_retry<'b>(
+        &mut self,
+        cmd: &[u8],
+        data: &'b mut [u8],
+        max_retries: u32,
+    ) -> Result<&'b [u8], ScsiError> {
+        let data_len = retry_on_unit_attention(max_retries, || {
+            self.do_in_command(cmd, data).map(|result| result.len())
+        })?;
+        Ok(&data[..data_len])
+    }
+
+    /// Run dataout command, retry on UNIT ATTENTION
+    ///
+    /// See [retry_on_unit_attention] for details.
+    pub fn do_out_command_with_retry(&mut self, cmd: &[u8], data: &[u8], max_retries: u32) -> Result<(), ScsiError> {
+        retry_on_unit_attention(max_retries, || self.do_out_command(cmd, data))
+    }
+}
+
+/// Delay between retries in [retry_on_unit_attention]
+pub const UNIT_ATTENTION_RETRY_DELAY: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// Run `func`, and repeat it up to `max_retries` times if it fails with
+/// sense key UNIT ATTENTION
+///
+/// Devices report UNIT ATTENTION once after events like a power on or
+/// reset (ASC 0x29), or a media change (ASC 0x28). This is not an
+/// error, so simply repeating the command is the correct response.
+/// Any other error is returned immediately.
+pub fn retry_on_unit_attention<T, F>(max_retries: u32, mut func: F) -> Result<T, ScsiError>
+where
+    F: FnMut() -> Result<T, ScsiError>,
+{
+    let mut retries = 0;
+    loop {
+        match func() {
+            Err(ScsiError::Sense(ref sense))
+                if sense.sense_key == SENSE_KEY_UNIT_ATTENTION && retries < max_retries =>
+            {
+                retries += 1;
+                std::thread::sleep(UNIT_ATTENTION_RETRY_DELAY);
+            }
+            result => return result,
+        }
+    }
 }
 
 // Useful helpers
@@ -694,6 +838,32 @@ pub fn scsi_inquiry<F: AsRawFd>(
     }).map_err(|err: Error| format_err!("decode inquiry page failed - {}", err))
 }
 
+/// Read the unit serial number (Inquiry VPD page 0x80)
+pub fn scsi_unit_serial_number<F: AsRawFd>(
+    file: &mut F,
+) -> Result<String, Error> {
+
+    let allocation_len: u8 = 255;
+
+    let mut sg_raw = SgRaw::new(file, allocation_len as usize)?;
+    sg_raw.set_timeout(30); // use short timeout
+
+    let mut cmd = Vec::new();
+    cmd.extend(&[0x12, 1, 0x80, 0, allocation_len, 0]); // INQUIRY, EVPD=1
+
+    let data = sg_raw.do_command(&cmd)
+        .map_err(|err| format_err!("SCSI inquiry (unit serial number) failed - {}", err))?;
+
+    if data.len() < 4 || data[1] != 0x80 {
+        bail!("got unexpected unit serial number page");
+    }
+
+    let page_len = u16::from_be_bytes([data[2], data[3]]) as usize;
+    let end = data.len().min(4 + page_len);
+
+    Ok(scsi_ascii_to_string(&data[4..end]))
+}
+
 /// Run SCSI Mode Sense
 ///
 /// Warning: P needs to be repr(C, packed)]
@@ -797,3 +967,118 @@ pub fn scsi_request_sense<F: AsRawFd>(
 
     Ok(sense)
 }
+
+#[test]
+fn test_decode_sense() -> Result<(), Error> {
+
+    // fixed format, MEDIUM ERROR, "Unrecovered read error", FRU 0x42
+    let mut buffer = [0u8; 32];
+    buffer[0] = 0xf0; // valid, fixed format, current
+    buffer[2] = SENSE_KEY_MEDIUM_ERROR;
+    buffer[7] = 10; // additional sense length
+    buffer[12] = 0x11; // ASC
+    buffer[13] = 0x00; // ASCQ
+    buffer[14] = 0x42; // FRU
+    buffer[15] = 0x80; // sense key specific (vendor data)
+    buffer[20] = 0xff; // beyond the 18 byte raw data
+
+    let sense = match SenseInfo::decode(&buffer) {
+        Ok(sense) => sense,
+        Err(err) => bail!("decode failed - {}", err),
+    };
+
+    assert_eq!(sense.sense_key, SENSE_KEY_MEDIUM_ERROR);
+    assert_eq!(sense.asc, 0x11);
+    assert_eq!(sense.additional_sense_qualifier(), 0x00);
+    assert_eq!(sense.field_replaceable_unit(), 0x42);
+    assert_eq!(&sense.raw[..], &buffer[..18]);
+    assert_eq!(sense.format_sense_description(), "Medium Error, Unrecovered read error");
+
+    // descriptor format, BLANK CHECK, "End-of-data detected"
+    let mut buffer = [0u8; 16];
+    buffer[0] = 0x72;
+    buffer[1] = SENSE_KEY_BLANK_CHECK;
+    buffer[2] = 0x00;
+    buffer[3] = 0x05;
+
+    let sense = match SenseInfo::decode(&buffer) {
+        Ok(sense) => sense,
+        Err(err) => bail!("decode failed - {}", err),
+    };
+
+    assert_eq!((sense.sense_key, sense.asc, sense.ascq), (SENSE_KEY_BLANK_CHECK, 0, 5));
+    assert_eq!(sense.field_replaceable_unit(), 0);
+    assert_eq!(sense.to_string(), "Blank Check, End-of-data detected");
+
+    // deferred errors are not decoded
+    buffer[0] = 0x73;
+    assert!(SenseInfo::decode(&buffer).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_retry_on_unit_attention() {
+
+    fn sense_error(sense_key: u8, asc: u8) -> ScsiError {
+        ScsiError::Sense(SenseInfo { sense_key, asc, ascq: 0, raw: [0u8; 18] })
+    }
+
+    // fails 'failures' times with the given sense key, then succeeds
+    fn run(sense_key: u8, asc: u8, failures: u32, max_retries: u32) -> (Result<u32, ScsiError>, u32) {
+        let mut calls = 0;
+        let result = retry_on_unit_attention(max_retries, || {
+            calls += 1;
+            if calls <= failures {
+                Err(sense_error(sense_key, asc))
+            } else {
+                Ok(calls)
+            }
+        });
+        (result, calls)
+    }
+
+    // power on/reset and media changed are retried
+    let (result, calls) = run(SENSE_KEY_UNIT_ATTENTION, 0x29, 2, 3);
+    assert_eq!(result.unwrap(), 3);
+    assert_eq!(calls, 3);
+
+    let (result, calls) = run(SENSE_KEY_UNIT_ATTENTION, 0x28, 1, 3);
+    assert_eq!(result.unwrap(), 2);
+    assert_eq!(calls, 2);
+
+    // give up after max_retries
+    let (result, calls) = run(SENSE_KEY_UNIT_ATTENTION, 0x29, 10, 2);
+    match result {
+        Err(ScsiError::Sense(sense)) => assert_eq!(sense.sense_key, SENSE_KEY_UNIT_ATTENTION),
+        _ => panic!("expected UNIT ATTENTION error"),
+    }
+    assert_eq!(calls, 3);
+
+    // no retries at all
+    let (result, calls) = run(SENSE_KEY_UNIT_ATTENTION, 0x29, 1, 0);
+    assert!(result.is_err());
+    assert_eq!(calls, 1);
+
+    // all other sense keys are returned immediately
+    for sense_key in 0..16u8 {
+        if sense_key == SENSE_KEY_UNIT_ATTENTION {
+            continue;
+        }
+        let (result, calls) = run(sense_key, 0, 1, 3);
+        match result {
+            Err(ScsiError::Sense(sense)) => assert_eq!(sense.sense_key, sense_key),
+            _ => panic!("expected error for sense key {}", sense_key),
+        }
+        assert_eq!(calls, 1);
+    }
+
+    // as well as non-sense errors
+    let mut calls = 0;
+    let result: Result<(), ScsiError> = retry_on_unit_attention(3, || {
+        calls += 1;
+        Err(format_err!("do_scsi_pt failed - timeout").into())
+    });
+    assert!(matches!(result, Err(ScsiError::Error(_))));
+    assert_eq!(calls, 1);
+}
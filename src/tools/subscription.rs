@@ -10,6 +10,7 @@ use crate::config::node;
 use crate::tools::{
     self,
     pbs_simple_http,
+    proxy_config_from_system,
 };
 use proxmox::tools::fs::{replace_file, CreateOptions};
 use proxmox_http::client::SimpleHttp;
@@ -21,6 +22,7 @@ const MAX_KEY_CHECK_FAILURE_AGE: i64 = 5 * 24 * 3600;
 const SHARED_KEY_DATA: &str = "kjfdlskfhiuewhfk947368";
 const SUBSCRIPTION_FN: &str = "/etc/proxmox-backup/subscription";
 const APT_AUTH_FN: &str = "/etc/apt/auth.conf.d/pbs.conf";
+const SHOP_HOST: &str = "shop.maurer-it.com";
 
 #[api()]
 #[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
@@ -113,11 +115,20 @@ async fn register_subscription(
         None
     };
 
+    // fall back to the system wide proxy settings
+    let proxy_config = match proxy_config {
+        Some(proxy_config) => Some(proxy_config),
+        None => proxy_config_from_system(SHOP_HOST).unwrap_or_else(|err| {
+            log::warn!("ignoring system proxy settings - {}", err);
+            None
+        }),
+    };
+
     let mut client = pbs_simple_http(proxy_config);
 
-    let uri = "https://shop.maurer-it.com/modules/servers/licensing/verify.php";
+    let uri = format!("https://{}/modules/servers/licensing/verify.php", SHOP_HOST);
     let query = tools::json_object_to_query(params)?;
-    let response = client.post(uri, Some(query), Some("application/x-www-form-urlencoded")).await?;
+    let response = client.post(&uri, Some(query), Some("application/x-www-form-urlencoded")).await?;
     let body = SimpleHttp::response_body_string(response).await?;
 
     Ok((body, challenge))
@@ -16,6 +16,14 @@ pub fn xattr_name_fcaps() -> &'static CStr {
     c_str!("security.capability")
 }
 
+/// `"security.selinux"` as a CStr to avoid typos.
+///
+/// This cannot be `const` until `const_cstr_unchecked` is stable.
+#[inline]
+pub fn xattr_name_selinux() -> &'static CStr {
+    c_str!("security.selinux")
+}
+
 /// `"system.posix_acl_access"` as a CStr to avoid typos.
 ///
 /// This cannot be `const` until `const_cstr_unchecked` is stable.
@@ -155,6 +163,11 @@ pub fn is_security_capability(name: &CStr) -> bool {
     name.to_bytes() == xattr_name_fcaps().to_bytes()
 }
 
+/// Check if the xattr name is the SELinux security context (`security.selinux`).
+pub fn is_selinux_context(name: &[u8]) -> bool {
+    name == xattr_name_selinux().to_bytes()
+}
+
 pub fn is_acl(name: &CStr) -> bool {
     name.to_bytes() == xattr_acl_access().to_bytes()
     || name.to_bytes() == xattr_acl_default().to_bytes()
@@ -237,4 +250,15 @@ mod tests {
         assert!(is_valid_xattr_name(c_str!("trusted.attr")));
         assert!(is_valid_xattr_name(super::xattr_name_fcaps()));
     }
+
+    #[test]
+    fn test_is_selinux_context() {
+        assert!(is_selinux_context(b"security.selinux"));
+        assert!(is_selinux_context(xattr_name_selinux().to_bytes()));
+        assert!(!is_selinux_context(b"security.capability"));
+        assert!(!is_selinux_context(b"security.selinux2"));
+        assert!(!is_selinux_context(b"user.selinux"));
+        // handled separately, so not a generic xattr name
+        assert!(!is_valid_xattr_name(xattr_name_selinux()));
+    }
 }
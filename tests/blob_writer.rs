@@ -69,7 +69,7 @@ fn test_uncompressed_blob_writer() -> Result<(), Error> {
     let mut blob_writer = DataBlobWriter::new_uncompressed(tmp)?;
     blob_writer.write_all(&TEST_DATA)?;
 
-    verify_test_blob(blob_writer.finish()?, &*TEST_DIGEST_PLAIN)
+    verify_test_blob(blob_writer.finish(FinishOptions::default())?, &*TEST_DIGEST_PLAIN)
 }
 
 #[test]
@@ -78,7 +78,7 @@ fn test_compressed_blob_writer() -> Result<(), Error> {
     let mut blob_writer = DataBlobWriter::new_compressed(tmp)?;
     blob_writer.write_all(&TEST_DATA)?;
 
-    verify_test_blob(blob_writer.finish()?, &*TEST_DIGEST_PLAIN)
+    verify_test_blob(blob_writer.finish(FinishOptions::default())?, &*TEST_DIGEST_PLAIN)
 }
 
 #[test]
@@ -87,7 +87,7 @@ fn test_encrypted_blob_writer() -> Result<(), Error> {
     let mut blob_writer = DataBlobWriter::new_encrypted(tmp, CRYPT_CONFIG.clone())?;
     blob_writer.write_all(&TEST_DATA)?;
 
-    verify_test_blob(blob_writer.finish()?, &*TEST_DIGEST_ENC)
+    verify_test_blob(blob_writer.finish(FinishOptions::default())?, &*TEST_DIGEST_ENC)
 }
 
 #[test]
@@ -96,5 +96,142 @@ fn test_encrypted_compressed_blob_writer() -> Result<(), Error> {
     let mut blob_writer = DataBlobWriter::new_encrypted_compressed(tmp, CRYPT_CONFIG.clone())?;
     blob_writer.write_all(&TEST_DATA)?;
 
-    verify_test_blob(blob_writer.finish()?, &*TEST_DIGEST_ENC)
+    verify_test_blob(blob_writer.finish(FinishOptions::default())?, &*TEST_DIGEST_ENC)
+}
+
+#[test]
+fn test_buffered_blob_writer() -> Result<(), Error> {
+    // Vec<u8> does not implement Seek
+    let mut blob_writer = BufferedDataBlobWriter::new_compressed(Vec::<u8>::new())?;
+    blob_writer.write_all(&TEST_DATA)?;
+    verify_test_blob(Cursor::new(blob_writer.finish()?), &*TEST_DIGEST_PLAIN)?;
+
+    let mut blob_writer = BufferedDataBlobWriter::new_encrypted_compressed(Vec::<u8>::new(), CRYPT_CONFIG.clone())?;
+    blob_writer.write_all(&TEST_DATA)?;
+    verify_test_blob(Cursor::new(blob_writer.finish()?), &*TEST_DIGEST_ENC)
+}
+
+#[test]
+fn test_uncompressed_blob_reader_seek() -> Result<(), Error> {
+    let mut blob_writer = DataBlobWriter::new_uncompressed(Cursor::new(Vec::<u8>::new()))?;
+    blob_writer.write_all(&TEST_DATA)?;
+    let mut raw_data = blob_writer.finish(FinishOptions::default())?.into_inner();
+
+    // the blob does not need to start at offset 0 of the reader
+    raw_data.splice(0..0, vec![0xffu8; 100]);
+    let mut cursor = Cursor::new(raw_data);
+    cursor.seek(SeekFrom::Start(100))?;
+
+    let mut reader = DataBlobReader::new(&mut cursor, None)?;
+    let mut buf = vec![0u8; 1000];
+
+    assert_eq!(reader.seek(SeekFrom::Start(5000))?, 5000);
+    reader.read_exact(&mut buf)?;
+    assert_eq!(&buf[..], &TEST_DATA[5000..6000]);
+
+    assert_eq!(reader.seek(SeekFrom::Current(-2000))?, 4000);
+    reader.read_exact(&mut buf)?;
+    assert_eq!(&buf[..], &TEST_DATA[4000..5000]);
+
+    assert_eq!(reader.seek(SeekFrom::End(-1000))?, TEST_DATA.len() as u64 - 1000);
+    reader.read_exact(&mut buf)?;
+    assert_eq!(&buf[..], &TEST_DATA[TEST_DATA.len() - 1000..]);
+    assert_eq!(reader.read(&mut buf)?, 0);
+
+    assert!(reader.seek(SeekFrom::Current(-200_000)).is_err());
+
+    // seeking back to the start allows to verify the crc again
+    assert_eq!(reader.seek(SeekFrom::Start(0))?, 0);
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    assert_eq!(data, *TEST_DATA);
+    reader.finish()?;
+
+    Ok(())
+}
+
+#[test]
+fn test_blob_reader_seek_unsupported() -> Result<(), Error> {
+    let mut blob_writer = DataBlobWriter::new_compressed(Cursor::new(Vec::<u8>::new()))?;
+    blob_writer.write_all(&TEST_DATA)?;
+    let mut cursor = blob_writer.finish(FinishOptions::default())?;
+    cursor.seek(SeekFrom::Start(0))?;
+
+    let mut reader = DataBlobReader::new(&mut cursor, None)?;
+    let err = reader.seek(SeekFrom::Start(10)).unwrap_err();
+    assert_eq!(err.raw_os_error(), Some(libc::EOPNOTSUPP));
+
+    let mut blob_writer = DataBlobWriter::new_encrypted(Cursor::new(Vec::<u8>::new()), CRYPT_CONFIG.clone())?;
+    blob_writer.write_all(&TEST_DATA)?;
+    let mut cursor = blob_writer.finish(FinishOptions::default())?;
+    cursor.seek(SeekFrom::Start(0))?;
+
+    let mut reader = DataBlobReader::new(&mut cursor, Some(CRYPT_CONFIG.clone()))?;
+    assert!(reader.seek(SeekFrom::Start(10)).is_err());
+
+    Ok(())
+}
+
+// cursor based target with configurable sync behavior
+struct TestTarget {
+    cursor: Cursor<Vec<u8>>,
+    fail_sync: bool,
+    // simulate a lost header update (old header still on disk after sync)
+    lose_header: bool,
+}
+
+impl Read for TestTarget {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> { self.cursor.read(buf) }
+}
+
+impl Write for TestTarget {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> { self.cursor.write(buf) }
+    fn flush(&mut self) -> std::io::Result<()> { self.cursor.flush() }
+}
+
+impl Seek for TestTarget {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> { self.cursor.seek(pos) }
+}
+
+impl BlobWriterTarget for TestTarget {
+    fn sync_data(&mut self) -> Result<(), Error> {
+        if self.fail_sync {
+            bail!("simulated fsync failure");
+        }
+        if self.lose_header {
+            for byte in self.cursor.get_mut()[8..12].iter_mut() {
+                *byte = 0;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_test_target(fail_sync: bool, lose_header: bool, options: FinishOptions) -> Result<TestTarget, Error> {
+    let target = TestTarget { cursor: Cursor::new(Vec::new()), fail_sync, lose_header };
+    let mut blob_writer = DataBlobWriter::new_compressed(target)?;
+    blob_writer.write_all(&TEST_DATA)?;
+    blob_writer.finish(options)
+}
+
+#[test]
+fn test_blob_writer_finish_options() -> Result<(), Error> {
+    let sync_and_verify = FinishOptions { fsync: true, verify_written: true };
+
+    let target = write_test_target(false, false, sync_and_verify)?;
+    verify_test_blob(target.cursor, &*TEST_DIGEST_PLAIN)?;
+
+    // fsync errors are passed to the caller
+    assert!(write_test_target(true, false, sync_and_verify).is_err());
+    assert!(write_test_target(true, false, FinishOptions { fsync: true, verify_written: false }).is_err());
+
+    // no sync requested, so the failure does not matter
+    let target = write_test_target(true, false, FinishOptions { fsync: false, verify_written: true })?;
+    verify_test_blob(target.cursor, &*TEST_DIGEST_PLAIN)?;
+
+    // lost header update is detected when reading back
+    assert!(write_test_target(false, true, sync_and_verify).is_err());
+    assert!(write_test_target(false, true, FinishOptions::default()).is_ok());
+
+    Ok(())
 }
@@ -0,0 +1,80 @@
+use anyhow::{bail, Error};
+
+use std::path::PathBuf;
+use std::process::Command;
+
+use proxmox_backup::tools::disks::{
+    create_btrfs_file_system, get_fs_uuid, BtrfsFormatOptions, BtrfsRaid, DiskManage,
+};
+
+// tmpfs-backed loop device, detached on drop
+struct LoopDevice {
+    image: PathBuf,
+    device: String,
+}
+
+impl LoopDevice {
+    fn new(name: &str, size: u64) -> Result<Self, Error> {
+        let image = PathBuf::from(format!("/dev/shm/pbs-btrfs-test-{}-{}.img", std::process::id(), name));
+        let file = std::fs::File::create(&image)?;
+        file.set_len(size)?;
+
+        let output = Command::new("losetup")
+            .args(&["--find", "--show"])
+            .arg(&image)
+            .output()?;
+        if !output.status.success() {
+            let _ = std::fs::remove_file(&image);
+            bail!("losetup failed: {}", String::from_utf8_lossy(&output.stderr));
+        }
+        let device = String::from_utf8(output.stdout)?.trim().to_string();
+
+        Ok(Self { image, device })
+    }
+}
+
+impl Drop for LoopDevice {
+    fn drop(&mut self) {
+        let _ = Command::new("losetup").args(&["-d", &self.device]).status();
+        let _ = std::fs::remove_file(&self.image);
+    }
+}
+
+#[test] #[ignore]
+fn btrfs_raid1_on_loop_devices() -> Result<(), Error> {
+    // btrfs needs at least 109MiB per device with default settings
+    let loop1 = LoopDevice::new("1", 256*1024*1024)?;
+    let loop2 = LoopDevice::new("2", 256*1024*1024)?;
+
+    let manager = DiskManage::new();
+    let disk1 = manager.clone().disk_by_node(&loop1.device)?;
+    let disk2 = manager.disk_by_node(&loop2.device)?;
+
+    let opts = BtrfsFormatOptions {
+        data_profile: BtrfsRaid::Raid1,
+        metadata_profile: BtrfsRaid::Raid1,
+        label: Some("pbs-test".to_string()),
+    };
+
+    create_btrfs_file_system(&[&disk1, &disk2], opts)?;
+
+    // both devices belong to the same file system
+    let uuid1 = get_fs_uuid(&disk1)?;
+    let uuid2 = get_fs_uuid(&disk2)?;
+    assert_eq!(uuid1, uuid2);
+
+    let output = Command::new("blkid")
+        .args(&["-o", "value", "-s", "TYPE", &loop1.device])
+        .output()?;
+    assert_eq!(String::from_utf8(output.stdout)?.trim(), "btrfs");
+
+    // raid10 needs 4 devices
+    let opts = BtrfsFormatOptions {
+        data_profile: BtrfsRaid::Raid10,
+        metadata_profile: BtrfsRaid::Raid1,
+        label: None,
+    };
+    assert!(create_btrfs_file_system(&[&disk1, &disk2], opts).is_err());
+
+    Ok(())
+}
@@ -0,0 +1,79 @@
+// Helpers shared by the integration tests
+//
+// Each test crate only uses some of them.
+#![allow(dead_code)]
+
+use anyhow::Error;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use proxmox_backup::pxar::*;
+
+/// Create an empty, per process temporary directory `<prefix>-<pid>-<name>`
+pub fn test_dir(prefix: &str, name: &str) -> PathBuf {
+    let mut path = std::env::temp_dir();
+    path.push(format!("{}-{}-{}", prefix, std::process::id(), name));
+    let _ = fs::remove_dir_all(&path);
+    fs::create_dir_all(&path).unwrap();
+    path
+}
+
+/// Default archive creation options (without entry limit)
+pub fn create_options() -> PxarCreateOptions {
+    PxarCreateOptions {
+        entries_max: ENCODER_MAX_ENTRIES,
+        ..PxarCreateOptions::default()
+    }
+}
+
+/// Archive the `source` directory into `writer`
+pub fn create_archive_to<W: std::io::Write + Send>(
+    source: &Path,
+    writer: W,
+    flags: Flags,
+    options: PxarCreateOptions,
+) -> Result<(), Error> {
+    let writer = pxar::encoder::sync::StandardWriter::new(writer);
+
+    let dir = nix::dir::Dir::open(
+        source, nix::fcntl::OFlag::O_NOFOLLOW,
+        nix::sys::stat::Mode::empty())?;
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(create_archive(dir, writer, flags, |_| Ok(()), None, options))
+}
+
+/// Archive the `source` directory into the file `archive`
+pub fn create_archive_file(
+    source: &Path,
+    archive: &Path,
+    flags: Flags,
+    options: PxarCreateOptions,
+) -> Result<(), Error> {
+    let writer = fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(archive)?;
+
+    create_archive_to(source, writer, flags, options)
+}
+
+/// Extract the whole `archive` into the (existing) directory `target`
+pub fn extract_to(archive: &Path, target: &Path, flags: Flags) -> Result<(), Error> {
+    let options = PxarExtractOptions {
+        match_list: &[],
+        extract_match_default: true,
+        allow_existing_dirs: true,
+        on_error: None,
+    };
+
+    extract_archive(
+        pxar::decoder::Decoder::from_std(fs::File::open(archive)?)?,
+        target,
+        flags,
+        |_| {},
+        options,
+    )
+}
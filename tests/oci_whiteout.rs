@@ -0,0 +1,51 @@
+use anyhow::Error;
+
+use std::fs;
+
+use proxmox_backup::pxar::*;
+
+mod common;
+use common::{create_archive_file, create_options, extract_to, test_dir};
+
+#[test]
+fn oci_whiteout_overlay_semantics() -> Result<(), Error> {
+    let source = test_dir("pxar-oci-whiteout", "source");
+    let target = test_dir("pxar-oci-whiteout", "target");
+    let archive = test_dir("pxar-oci-whiteout", "archive").join("layer.pxar");
+
+    // upper layer
+    fs::write(source.join("added"), b"new")?;
+    fs::write(source.join(".wh.removed"), b"ignored content")?;
+    fs::write(source.join(".wh.removed-dir"), b"")?;
+    fs::create_dir(source.join("opaque"))?;
+    fs::write(source.join("opaque/.wh..wh..opq"), b"")?;
+    fs::write(source.join("opaque/new"), b"new")?;
+
+    // lower layer
+    fs::write(target.join("removed"), b"old")?;
+    fs::write(target.join("kept"), b"old")?;
+    fs::create_dir_all(target.join("removed-dir/sub"))?;
+    fs::write(target.join("removed-dir/sub/file"), b"old")?;
+    fs::create_dir(target.join("opaque"))?;
+    fs::write(target.join("opaque/old"), b"old")?;
+
+    let flags = Flags::DEFAULT | Flags::WITH_OCI_WHITEOUTS;
+    create_archive_file(&source, &archive, flags, create_options())?;
+
+    extract_to(&archive, &target, flags)?;
+
+    assert_eq!(fs::read(target.join("added"))?, b"new");
+    assert_eq!(fs::read(target.join("kept"))?, b"old");
+    assert!(!target.join("removed").exists());
+    assert!(!target.join("removed-dir").exists());
+    assert!(!target.join(".wh.removed").exists());
+    assert!(!target.join("opaque/old").exists());
+    assert!(!target.join("opaque/.wh..wh..opq").exists());
+    assert_eq!(fs::read(target.join("opaque/new"))?, b"new");
+
+    let _ = fs::remove_dir_all(&source);
+    let _ = fs::remove_dir_all(&target);
+    let _ = fs::remove_dir_all(archive.parent().unwrap());
+
+    Ok(())
+}
@@ -0,0 +1,60 @@
+use anyhow::Error;
+
+use std::fs;
+use std::path::Path;
+
+use proxmox_backup::pxar::*;
+
+mod common;
+use common::{extract_to, test_dir};
+
+// encode `content` as file "stream.raw", announcing `size` bytes
+fn create_stream_archive(archive: &Path, mut content: &[u8], size: u64) -> Result<(), Error> {
+    let writer = fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(archive)?;
+    let mut writer = pxar::encoder::sync::StandardWriter::new(writer);
+
+    let mut stat: nix::sys::stat::FileStat = unsafe { std::mem::zeroed() };
+    stat.st_mode = libc::S_IFREG | 0o640;
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async move {
+        let root = pxar::Metadata::dir_builder(0o755).build();
+        let mut encoder = pxar::encoder::aio::Encoder::new(&mut writer, &root).await?;
+
+        // any tokio AsyncRead works, use a plain byte slice here
+        encode_async_file(&mut encoder, &mut content, Path::new("stream.raw"), size, &stat)
+            .await?;
+
+        encoder.finish().await?;
+        Ok(())
+    })
+}
+
+#[test]
+fn encode_async_file_round_trip() -> Result<(), Error> {
+    let archive_dir = test_dir("pxar-async-file", "archive");
+    let target = test_dir("pxar-async-file", "target");
+    let archive = archive_dir.join("stream.pxar");
+
+    // larger than the copy buffer
+    let data: Vec<u8> = (0..(5 * 1024 * 1024)).map(|i| (i % 251) as u8).collect();
+
+    create_stream_archive(&archive, &data, data.len() as u64)?;
+
+    extract_to(&archive, &target, Flags::DEFAULT)?;
+
+    assert_eq!(fs::read(target.join("stream.raw"))?, data);
+
+    // size mismatch must be detected
+    assert!(create_stream_archive(&archive, &data[..10], 20).is_err());
+    assert!(create_stream_archive(&archive, &data[..20], 10).is_err());
+
+    let _ = fs::remove_dir_all(&archive_dir);
+    let _ = fs::remove_dir_all(&target);
+
+    Ok(())
+}
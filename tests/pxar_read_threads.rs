@@ -0,0 +1,46 @@
+use anyhow::Error;
+
+use std::fs;
+
+use proxmox_backup::pxar::*;
+
+mod common;
+use common::{create_archive_file, create_options, test_dir};
+
+#[test]
+fn pxar_read_threads_same_archive() -> Result<(), Error> {
+    let source = test_dir("pxar-read-threads", "source");
+    let output = test_dir("pxar-read-threads", "output");
+
+    // many small files, some larger than the prefetch limit, nested directories,
+    // empty files and hardlinks
+    for d in 0..5 {
+        let dir = source.join(format!("dir{}", d));
+        fs::create_dir_all(dir.join("sub"))?;
+        for i in 0..200 {
+            let data = vec![(i % 251) as u8; (i * 997) % 65536];
+            fs::write(dir.join(format!("file{:03}", i)), &data)?;
+        }
+        fs::write(dir.join("sub/large"), vec![d as u8; 9 * 1024 * 1024])?;
+        fs::write(dir.join("sub/empty"), b"")?;
+        fs::hard_link(dir.join("file001"), dir.join("sub/hardlink"))?;
+    }
+
+    let serial = output.join("serial.pxar");
+    let parallel = output.join("parallel.pxar");
+
+    create_archive_file(&source, &serial, Flags::DEFAULT, create_options())?;
+    create_archive_file(
+        &source,
+        &parallel,
+        Flags::DEFAULT,
+        PxarCreateOptions { read_threads: 4, ..create_options() },
+    )?;
+
+    assert!(fs::read(&serial)? == fs::read(&parallel)?, "archives differ");
+
+    let _ = fs::remove_dir_all(&source);
+    let _ = fs::remove_dir_all(&output);
+
+    Ok(())
+}
@@ -0,0 +1,66 @@
+use anyhow::Error;
+
+use std::fs;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+use proxmox_backup::pxar::*;
+use proxmox_backup::tools::xattr;
+
+mod common;
+use common::{create_archive_file, create_options, extract_to, test_dir};
+
+const CONTEXT: &[u8] = b"system_u:object_r:user_tmp_t:s0";
+
+fn archived_context(archive: &Path, file_name: &str) -> Result<Option<Vec<u8>>, Error> {
+    let decoder = pxar::decoder::Decoder::from_std(fs::File::open(archive)?)?;
+    for entry in decoder {
+        let entry = entry?;
+        if entry.path() != Path::new(file_name) {
+            continue;
+        }
+        let context = entry
+            .metadata()
+            .xattrs
+            .iter()
+            .find(|xattr| xattr::is_selinux_context(xattr.name().to_bytes()))
+            .map(|xattr| xattr.value().to_vec());
+        return Ok(context);
+    }
+    Ok(None)
+}
+
+#[test]
+fn selinux_context_roundtrip() -> Result<(), Error> {
+    let source = test_dir("pxar-selinux", "source");
+    let target = test_dir("pxar-selinux", "target");
+    let archive = test_dir("pxar-selinux", "archive").join("test.pxar");
+
+    fs::write(source.join("file"), b"data")?;
+    let file = fs::File::open(source.join("file"))?;
+
+    // needs privileges and a file system storing security xattrs
+    if let Err(err) = xattr::fsetxattr(file.as_raw_fd(), xattr::xattr_name_selinux(), CONTEXT) {
+        eprintln!("skipping test, unable to set SELinux context - {}", err);
+        return Ok(());
+    }
+    // the kernel may refuse or rewrite the context if SELinux is active
+    let context = xattr::fgetxattr(file.as_raw_fd(), xattr::xattr_name_selinux())?;
+
+    create_archive_file(&source, &archive, Flags::DEFAULT, create_options())?;
+    assert_eq!(archived_context(&archive, "/file")?, Some(context.clone()));
+
+    extract_to(&archive, &target, Flags::DEFAULT)?;
+
+    let file = fs::File::open(target.join("file"))?;
+    assert_eq!(xattr::fgetxattr(file.as_raw_fd(), xattr::xattr_name_selinux())?, context);
+
+    create_archive_file(&source, &archive, Flags::DEFAULT - Flags::WITH_SELINUX, create_options())?;
+    assert_eq!(archived_context(&archive, "/file")?, None);
+
+    let _ = fs::remove_dir_all(&source);
+    let _ = fs::remove_dir_all(&target);
+    let _ = fs::remove_dir_all(archive.parent().unwrap());
+
+    Ok(())
+}
@@ -0,0 +1,60 @@
+use anyhow::Error;
+
+use std::fs;
+use std::io::{Seek, SeekFrom, Write};
+use std::path::Path;
+
+use proxmox_backup::pxar::*;
+
+mod common;
+use common::{create_archive_file, create_options, extract_to, test_dir};
+
+const MB: u64 = 1024 * 1024;
+
+// write 'data' at the given offsets, the rest of the file stays a hole
+fn create_sparse_file(path: &Path, size: u64, data: &[(u64, &[u8])]) -> Result<Vec<u8>, Error> {
+    let mut file = fs::File::create(path)?;
+    file.set_len(size)?;
+
+    let mut expected = vec![0u8; size as usize];
+    for (offset, data) in data {
+        file.seek(SeekFrom::Start(*offset))?;
+        file.write_all(data)?;
+        expected[*offset as usize..(*offset as usize + data.len())].copy_from_slice(data);
+    }
+
+    Ok(expected)
+}
+
+#[test]
+fn sparse_file_roundtrip() -> Result<(), Error> {
+    let source = test_dir("pxar-sparse", "source");
+    let target = test_dir("pxar-sparse", "target");
+    let archive = test_dir("pxar-sparse", "archive").join("test.pxar");
+
+    let block = vec![0xaau8; 64 * 1024];
+    let files = vec![
+        ("leading-data", create_sparse_file(&source.join("leading-data"), 16 * MB, &[(0, &block)])?),
+        ("holes", create_sparse_file(
+            &source.join("holes"),
+            32 * MB,
+            &[(MB + 17, &block), (10 * MB, &block), (20 * MB - 3, b"end of data")],
+        )?),
+        ("trailing-data", create_sparse_file(&source.join("trailing-data"), 16 * MB, &[(16 * MB - 5, b"tail!")])?),
+        ("empty", create_sparse_file(&source.join("empty"), 12 * MB, &[])?),
+    ];
+
+    create_archive_file(&source, &archive, Flags::DEFAULT, create_options())?;
+
+    extract_to(&archive, &target, Flags::DEFAULT)?;
+
+    for (name, expected) in files {
+        assert!(fs::read(target.join(name))? == expected, "contents of {} differ", name);
+    }
+
+    let _ = fs::remove_dir_all(&source);
+    let _ = fs::remove_dir_all(&target);
+    let _ = fs::remove_dir_all(archive.parent().unwrap());
+
+    Ok(())
+}
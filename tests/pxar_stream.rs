@@ -0,0 +1,58 @@
+use anyhow::Error;
+
+use std::fs;
+use std::path::Path;
+
+use pathpatterns::{MatchEntry, MatchType, PatternFlag};
+use pxar::EntryKind;
+
+use proxmox_backup::pxar::*;
+
+mod common;
+use common::{create_archive_to, create_options, test_dir};
+
+// the archive is written to a plain `Vec<u8>`, which does not implement `Seek`
+fn create_archive_in_memory(source: &Path, patterns: Vec<MatchEntry>) -> Result<Vec<u8>, Error> {
+    let mut archive = Vec::new();
+    let options = PxarCreateOptions { patterns, ..create_options() };
+    create_archive_to(source, &mut archive, Flags::DEFAULT, options)?;
+    Ok(archive)
+}
+
+#[test]
+fn stream_archive_without_seek() -> Result<(), Error> {
+    let source = test_dir("pxar-stream", "source");
+
+    fs::write(source.join("data"), b"some file content")?;
+    fs::write(source.join("skip.tmp"), b"excluded")?;
+    fs::create_dir(source.join("subdir"))?;
+    fs::write(source.join("subdir/file"), vec![0x55u8; 128 * 1024])?;
+
+    let patterns = vec![
+        MatchEntry::parse_pattern("*.tmp", PatternFlag::PATH_NAME, MatchType::Exclude)?,
+    ];
+
+    let archive = create_archive_in_memory(&source, patterns)?;
+    let _ = fs::remove_dir_all(&source);
+
+    let mut files = Vec::new();
+    let decoder = pxar::decoder::Decoder::from_std(&archive[..])?;
+    for entry in decoder {
+        let entry = entry?;
+        if let EntryKind::File { size, .. } = entry.kind() {
+            files.push((entry.path().to_string_lossy().into_owned(), *size));
+        }
+    }
+    files.sort();
+
+    // the CLI exclude patterns are stored in '.pxarexclude-cli', whose size is known upfront
+    let exclude_cli = files.iter().find(|(path, _)| path == "/.pxarexclude-cli");
+    assert!(exclude_cli.is_some(), "missing .pxarexclude-cli in {:?}", files);
+    assert!(exclude_cli.unwrap().1 > 0);
+
+    assert!(files.contains(&("/data".to_string(), 17)));
+    assert!(files.contains(&("/subdir/file".to_string(), 128 * 1024)));
+    assert!(!files.iter().any(|(path, _)| path == "/skip.tmp"));
+
+    Ok(())
+}
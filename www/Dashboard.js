@@ -93,7 +93,13 @@ Ext.define('PBS.Dashboard', {
 	    memPanel.updateValue(mem.used / mem.total);
 
 	    var hdPanel = me.lookup('root');
-	    hdPanel.updateValue(root.used / root.total);
+	    let inodes = res['root-inodes'];
+	    if (inodes && inodes.total > 0 && inodes.used / inodes.total > 0.9) {
+		let text = Ext.String.format(gettext('{0} inodes used'), Ext.util.Format.percent(inodes.used / inodes.total, '0.0'));
+		hdPanel.updateValue(root.used / root.total, text);
+	    } else {
+		hdPanel.updateValue(root.used / root.total);
+	    }
 	},
 
 	showFingerPrint: function() {
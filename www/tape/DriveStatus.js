@@ -10,7 +10,7 @@ Ext.define('PBS.TapeManagement.DriveStatus', {
 	me.setTitle(`${gettext('Drive')}: ${me.drive}`);
 	let baseurl = `/api2/json/tape/drive/${me.drive}/`;
 	return {
-	    driveStatusUrl: `${baseurl}/status`,
+	    driveStatusUrl: `${baseurl}/status?temperature=1`,
 	    cartridgeMemoryUrl: `${baseurl}/cartridge-memory`,
 	};
     },
@@ -364,6 +364,37 @@ Ext.define('PBS.TapeManagement.DriveStatusGrid', {
 	'block-number': {
 	    visible: false,
 	},
+	'position-percent': {
+	    header: gettext('Estimated Position'),
+	    renderer: function(value) {
+		if (value !== undefined) {
+		    return value.toFixed(1) + "%";
+		}
+		return value;
+	    },
+	},
+	'remaining-capacity': {
+	    header: gettext('Remaining Capacity'),
+	    renderer: Proxmox.Utils.format_size,
+	},
+	'fill-ratio': {
+	    header: gettext('Tape Usage'),
+	    renderer: function(value) {
+		if (value !== undefined) {
+		    return (value*100).toFixed(1) + "%";
+		}
+		return value;
+	    },
+	},
+	'temperature': {
+	    header: gettext('Temperature'),
+	    renderer: function(value) {
+		if (value !== undefined) {
+		    return `${value} °C`;
+		}
+		return value;
+	    },
+	},
 	'manufactured': {
 	    header: gettext('Tape Manufacture Date'),
 	    renderer: function(value) {
@@ -396,6 +427,10 @@ Ext.define('PBS.TapeManagement.DriveStatusGrid', {
 	'alert-flags': {
 	    header: gettext('Alert Flags'),
 	},
+	'needs-cleaning': {
+	    header: gettext('Needs Cleaning'),
+	    renderer: Proxmox.Utils.format_boolean,
+	},
     },
 });
 